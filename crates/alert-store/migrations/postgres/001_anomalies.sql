@@ -0,0 +1,15 @@
+-- Anomalies table of the Postgres alert store, matching
+-- db/migrations/005_anomalies.sql of the SQLite database.
+CREATE TABLE IF NOT EXISTS anomalies (
+	id BIGSERIAL PRIMARY KEY,
+	reading_id BIGINT NOT NULL,
+	sensor_id BIGINT NOT NULL,
+	value DOUBLE PRECISION NOT NULL,
+	timestamp TIMESTAMP NOT NULL,
+	method TEXT NOT NULL,
+	score DOUBLE PRECISION NOT NULL,
+	severity TEXT NOT NULL,
+	detected_at TIMESTAMP(0) NOT NULL DEFAULT (now() AT TIME ZONE 'utc')
+);
+
+CREATE INDEX IF NOT EXISTS idx_anomalies_sensor_time ON anomalies(sensor_id, timestamp);
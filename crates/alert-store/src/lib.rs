@@ -0,0 +1,273 @@
+//! Where the anomaly detector keeps the anomalies it records.
+//!
+//! [`AlertStore`] is what the service needs of that storage; each backend
+//! implements it and applies its own migrations on connect:
+//!
+//! - [`MemoryAlertStore`]: a process-local list, for tests and deployments
+//!   that do not keep anomalies across restarts
+//! - [`SqliteAlertStore`]: the `anomalies` table of the SQLite database
+//!   shared with the Python API (`db/migrations/005_anomalies.sql`)
+//! - [`PostgresAlertStore`]: the same table in Postgres
+//!   (`migrations/postgres/`)
+//!
+//! [`connect`] picks the backend from a URL, so a deployment chooses its
+//! store with a setting. Timestamps and filter bounds are
+//! `YYYY-MM-DD HH:MM:SS[.ffffff]` in UTC, which every backend orders as
+//! time.
+
+pub mod memory;
+pub mod postgres;
+pub mod sqlite;
+
+use std::sync::Arc;
+
+use detection_core::Severity;
+use futures_util::future::BoxFuture;
+
+pub use memory::MemoryAlertStore;
+pub use postgres::PostgresAlertStore;
+pub use sqlite::SqliteAlertStore;
+
+pub type Error = sqlx::Error;
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct StoredAnomaly {
+    pub id: i64,
+    pub reading_id: i64,
+    pub sensor_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub method: String,
+    pub score: f64,
+    /// Shown with the installed labels; stored canonical.
+    #[sqlx(try_from = "String")]
+    #[serde(serialize_with = "detection_core::severity::labelled")]
+    pub severity: Severity,
+    pub detected_at: String,
+}
+
+/// Optional constraints on listed anomalies; bounds are normalized timestamps.
+#[derive(Clone, Debug, Default)]
+pub struct AnomalyFilter {
+    pub sensor_id: Option<i64>,
+    pub start: Option<String>,
+    pub end: Option<String>,
+    pub method: Option<String>,
+    pub min_severity: Option<Severity>,
+}
+
+impl AnomalyFilter {
+    /// The labels of `min_severity` and the severities above it.
+    fn severities(&self) -> Option<Vec<&'static str>> {
+        self.min_severity.map(|min| {
+            Severity::ALL
+                .iter()
+                .filter(|&&severity| severity >= min)
+                .map(Severity::as_str)
+                .collect()
+        })
+    }
+
+    fn matches(&self, anomaly: &StoredAnomaly) -> bool {
+        self.sensor_id.is_none_or(|id| anomaly.sensor_id == id)
+            && self
+                .start
+                .as_ref()
+                .is_none_or(|start| anomaly.timestamp >= *start)
+            && self.end.as_ref().is_none_or(|end| anomaly.timestamp < *end)
+            && self
+                .method
+                .as_ref()
+                .is_none_or(|method| anomaly.method == *method)
+            && self.min_severity.is_none_or(|min| anomaly.severity >= min)
+    }
+}
+
+pub struct NewAnomaly {
+    pub reading_id: i64,
+    pub sensor_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub method: &'static str,
+    pub score: f64,
+    pub severity: Severity,
+}
+
+/// Storage of recorded anomalies. Ids are assigned on insert and increase,
+/// so they double as a pagination cursor.
+pub trait AlertStore: Send + Sync {
+    /// Inserts anomalies, all or none, returning them as stored.
+    fn insert<'a>(
+        &'a self,
+        anomalies: &'a [NewAnomaly],
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>>;
+
+    /// Lists anomalies matching `filter` with an id greater than `after_id`,
+    /// ordered by id.
+    fn list<'a>(
+        &'a self,
+        filter: &'a AnomalyFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>>;
+
+    /// The anomalies of `ids` that exist, ordered by id.
+    fn by_id<'a>(&'a self, ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>>;
+
+    /// Deletes the anomalies of a sensor recorded by `method` in
+    /// `[start, end)`, returning how many there were.
+    fn delete_range<'a>(
+        &'a self,
+        sensor_id: i64,
+        start: &'a str,
+        end: &'a str,
+        method: &'a str,
+    ) -> BoxFuture<'a, Result<u64, Error>>;
+
+    /// Deletes up to `limit` anomalies with a timestamp before `cutoff`.
+    fn delete_before<'a>(
+        &'a self,
+        cutoff: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<u64, Error>>;
+}
+
+/// Opens the store at `url`: `memory`, `sqlite://<path>` or
+/// `postgres://...`.
+pub async fn connect(url: &str) -> Result<Arc<dyn AlertStore>, Error> {
+    let store: Arc<dyn AlertStore> = match url.split_once(':').map_or(url, |(scheme, _)| scheme) {
+        "memory" => Arc::new(MemoryAlertStore::default()),
+        "sqlite" => Arc::new(SqliteAlertStore::connect(url).await?),
+        "postgres" | "postgresql" => Arc::new(PostgresAlertStore::connect(url).await?),
+        scheme => {
+            return Err(Error::Configuration(
+                format!("unsupported alert store {:?}", scheme).into(),
+            ));
+        }
+    };
+    Ok(store)
+}
+
+/// Checks shared by the tests of every backend.
+#[cfg(test)]
+pub(crate) mod conformance {
+    use super::*;
+
+    fn new(sensor_id: i64, timestamp: &str, method: &'static str) -> NewAnomaly {
+        NewAnomaly {
+            reading_id: 1,
+            sensor_id,
+            value: 99.5,
+            timestamp: timestamp.to_string(),
+            method,
+            score: 4.2,
+            severity: Severity::High,
+        }
+    }
+
+    fn ids(anomalies: &[StoredAnomaly]) -> Vec<i64> {
+        anomalies.iter().map(|a| a.id).collect()
+    }
+
+    /// Runs every check against `store`, which must start empty.
+    pub async fn check(store: &dyn AlertStore) {
+        let inserted = store
+            .insert(&[
+                new(1, "2026-01-19 10:00:00", "zscore"),
+                new(1, "2026-01-19 11:00:00", "zscore"),
+                new(1, "2026-01-19 12:00:00", "mad"),
+                new(2, "2026-01-19 10:30:00", "zscore"),
+            ])
+            .await
+            .unwrap();
+        assert!(store.insert(&[]).await.unwrap().is_empty());
+
+        let all = store.list(&AnomalyFilter::default(), 0, 10).await.unwrap();
+        assert_eq!(all.len(), 4);
+        assert!(all.windows(2).all(|pair| pair[0].id < pair[1].id));
+        let first = &all[0];
+        assert_eq!(
+            (first.sensor_id, first.reading_id, first.value, first.score),
+            (1, 1, 99.5, 4.2)
+        );
+        assert_eq!(
+            (
+                first.timestamp.as_str(),
+                first.method.as_str(),
+                first.severity.as_str()
+            ),
+            ("2026-01-19 10:00:00", "zscore", "high")
+        );
+        assert_eq!(first.detected_at.len(), "2026-01-19 10:00:00".len());
+        let as_listed = |anomalies: &[StoredAnomaly]| -> Vec<(i64, String, String)> {
+            anomalies
+                .iter()
+                .map(|a| (a.id, a.timestamp.clone(), a.detected_at.clone()))
+                .collect()
+        };
+        assert_eq!(as_listed(&inserted), as_listed(&all));
+
+        let page = store
+            .list(&AnomalyFilter::default(), all[1].id, 2)
+            .await
+            .unwrap();
+        assert_eq!(ids(&page), ids(&all[2..4]));
+
+        let filter = AnomalyFilter {
+            sensor_id: Some(1),
+            start: Some("2026-01-19 10:00:00".to_string()),
+            end: Some("2026-01-19 12:00:00".to_string()),
+            method: Some("zscore".to_string()),
+            min_severity: None,
+        };
+        let matched = store.list(&filter, 0, 10).await.unwrap();
+        assert_eq!(ids(&matched), ids(&all[0..2]));
+        let at_least = |min_severity| AnomalyFilter {
+            min_severity: Some(min_severity),
+            ..AnomalyFilter::default()
+        };
+        let high = store.list(&at_least(Severity::High), 0, 10).await.unwrap();
+        assert_eq!(ids(&high), ids(&all));
+        let critical = store.list(&at_least(Severity::Critical), 0, 10).await;
+        assert!(critical.unwrap().is_empty());
+
+        let found = store.by_id(&[all[3].id, all[1].id, -1]).await.unwrap();
+        assert_eq!(ids(&found), [all[1].id, all[3].id]);
+        assert!(store.by_id(&[]).await.unwrap().is_empty());
+
+        let deleted = store
+            .delete_range(1, "2026-01-19 00:00:00", "2026-01-20 00:00:00", "zscore")
+            .await
+            .unwrap();
+        assert_eq!(deleted, 2);
+        let deleted = store
+            .delete_before("2026-01-19 12:00:00", 10)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+        let left = store.list(&AnomalyFilter::default(), 0, 10).await.unwrap();
+        assert_eq!(ids(&left), [all[2].id]);
+
+        store
+            .insert(&[new(3, "2026-01-19 13:00:00", "zscore")])
+            .await
+            .unwrap();
+        let latest = store.list(&AnomalyFilter::default(), 0, 10).await.unwrap();
+        assert!(latest[1].id > all[3].id, "ids are not reused");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connect_picks_the_backend_by_scheme() {
+        let store = connect("memory").await.unwrap();
+        conformance::check(store.as_ref()).await;
+        assert!(connect("sqlite::memory:").await.is_ok());
+
+        let error = connect("mysql://localhost/alerts").await.err().unwrap();
+        assert!(error.to_string().contains("\"mysql\""), "{}", error);
+    }
+}
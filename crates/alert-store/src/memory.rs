@@ -0,0 +1,147 @@
+//! Anomalies kept in process memory; they are lost on restart and not
+//! shared between replicas.
+
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use detection_core::calendar::civil_from_days;
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+
+use crate::{AlertStore, AnomalyFilter, Error, NewAnomaly, StoredAnomaly};
+
+#[derive(Default)]
+pub struct MemoryAlertStore {
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    /// In id order, as ids only grow.
+    anomalies: Vec<StoredAnomaly>,
+    last_id: i64,
+}
+
+impl AlertStore for MemoryAlertStore {
+    fn insert<'a>(
+        &'a self,
+        anomalies: &'a [NewAnomaly],
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        let detected_at = utc_now();
+        let mut inner = self.inner.lock().unwrap();
+        let mut inserted = Vec::with_capacity(anomalies.len());
+        for anomaly in anomalies {
+            inner.last_id += 1;
+            let stored = StoredAnomaly {
+                id: inner.last_id,
+                reading_id: anomaly.reading_id,
+                sensor_id: anomaly.sensor_id,
+                value: anomaly.value,
+                timestamp: anomaly.timestamp.clone(),
+                method: anomaly.method.to_string(),
+                score: anomaly.score,
+                severity: anomaly.severity,
+                detected_at: detected_at.clone(),
+            };
+            inserted.push(stored.clone());
+            inner.anomalies.push(stored);
+        }
+        async { Ok(inserted) }.boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        filter: &'a AnomalyFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        let inner = self.inner.lock().unwrap();
+        let page = inner
+            .anomalies
+            .iter()
+            .filter(|a| a.id > after_id && filter.matches(a))
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect();
+        async { Ok(page) }.boxed()
+    }
+
+    fn by_id<'a>(&'a self, ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        let inner = self.inner.lock().unwrap();
+        let found = inner
+            .anomalies
+            .iter()
+            .filter(|a| ids.contains(&a.id))
+            .cloned()
+            .collect();
+        async { Ok(found) }.boxed()
+    }
+
+    fn delete_range<'a>(
+        &'a self,
+        sensor_id: i64,
+        start: &'a str,
+        end: &'a str,
+        method: &'a str,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        let filter = AnomalyFilter {
+            sensor_id: Some(sensor_id),
+            start: Some(start.to_string()),
+            end: Some(end.to_string()),
+            method: Some(method.to_string()),
+            min_severity: None,
+        };
+        let mut inner = self.inner.lock().unwrap();
+        let before = inner.anomalies.len();
+        inner.anomalies.retain(|a| !filter.matches(a));
+        let deleted = (before - inner.anomalies.len()) as u64;
+        async move { Ok(deleted) }.boxed()
+    }
+
+    fn delete_before<'a>(
+        &'a self,
+        cutoff: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        let mut inner = self.inner.lock().unwrap();
+        let mut left = limit.max(0);
+        inner.anomalies.retain(|a| {
+            let expired = left > 0 && a.timestamp.as_str() < cutoff;
+            left -= i64::from(expired);
+            !expired
+        });
+        let deleted = (limit.max(0) - left) as u64;
+        async move { Ok(deleted) }.boxed()
+    }
+}
+
+/// The current time as `YYYY-MM-DD HH:MM:SS`, as SQLite's
+/// `CURRENT_TIMESTAMP` gives it.
+fn utc_now() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let (days, secs) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance;
+
+    #[tokio::test]
+    async fn test_memory_store() {
+        conformance::check(&MemoryAlertStore::default()).await;
+        assert!(utc_now().as_str() > "2026-01-01 00:00:00");
+    }
+}
@@ -0,0 +1,196 @@
+//! Anomalies in a Postgres `anomalies` table, for deployments whose replicas
+//! outgrow one SQLite file.
+
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+
+use crate::{AlertStore, AnomalyFilter, Error, NewAnomaly, StoredAnomaly};
+
+const MIGRATIONS: &[&str] = &[include_str!("../migrations/postgres/001_anomalies.sql")];
+
+/// Held while migrating, so replicas starting together do not race to
+/// create the same table.
+const MIGRATION_LOCK: i64 = 0x616c_6572_7473;
+
+#[derive(Clone)]
+pub struct PostgresAlertStore {
+    pool: PgPool,
+}
+
+impl PostgresAlertStore {
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let pool = PgPoolOptions::new().connect(url).await?;
+        Self::from_pool(pool).await
+    }
+
+    pub async fn from_pool(pool: PgPool) -> Result<Self, Error> {
+        let mut tx = pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(MIGRATION_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        for migration in MIGRATIONS {
+            sqlx::raw_sql(migration).execute(&mut *tx).await?;
+        }
+        tx.commit().await?;
+        Ok(Self { pool })
+    }
+}
+
+impl AlertStore for PostgresAlertStore {
+    fn insert<'a>(
+        &'a self,
+        anomalies: &'a [NewAnomaly],
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        async move {
+            let mut tx = self.pool.begin().await?;
+            let mut stored = Vec::with_capacity(anomalies.len());
+            for anomaly in anomalies {
+                let row: StoredAnomaly = sqlx::query_as(
+                    "INSERT INTO anomalies \
+                     (reading_id, sensor_id, value, timestamp, method, score, severity) \
+                     VALUES ($1, $2, $3, $4::timestamp, $5, $6, $7) \
+                     RETURNING id, reading_id, sensor_id, value, timestamp::text AS timestamp, \
+                               method, score, severity, detected_at::text AS detected_at",
+                )
+                .bind(anomaly.reading_id)
+                .bind(anomaly.sensor_id)
+                .bind(anomaly.value)
+                .bind(&anomaly.timestamp)
+                .bind(anomaly.method)
+                .bind(anomaly.score)
+                .bind(anomaly.severity.as_str())
+                .fetch_one(&mut *tx)
+                .await?;
+                stored.push(row);
+            }
+            tx.commit().await?;
+            Ok(stored)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        filter: &'a AnomalyFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        sqlx::query_as(
+            "SELECT id, reading_id, sensor_id, value, timestamp::text AS timestamp, \
+                    method, score, severity, detected_at::text AS detected_at \
+             FROM anomalies \
+             WHERE ($1::bigint IS NULL OR sensor_id = $1) \
+               AND ($2::timestamp IS NULL OR timestamp >= $2::timestamp) \
+               AND ($3::timestamp IS NULL OR timestamp < $3::timestamp) \
+               AND ($6::text IS NULL OR method = $6) \
+               AND ($7::text[] IS NULL OR severity = ANY($7)) \
+               AND id > $4 \
+             ORDER BY id LIMIT $5",
+        )
+        .bind(filter.sensor_id)
+        .bind(&filter.start)
+        .bind(&filter.end)
+        .bind(after_id)
+        .bind(limit)
+        .bind(&filter.method)
+        .bind(filter.severities())
+        .fetch_all(&self.pool)
+        .boxed()
+    }
+
+    fn by_id<'a>(&'a self, ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        sqlx::query_as(
+            "SELECT id, reading_id, sensor_id, value, timestamp::text AS timestamp, \
+                    method, score, severity, detected_at::text AS detected_at \
+             FROM anomalies WHERE id = ANY($1) \
+             ORDER BY id",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .boxed()
+    }
+
+    fn delete_range<'a>(
+        &'a self,
+        sensor_id: i64,
+        start: &'a str,
+        end: &'a str,
+        method: &'a str,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        async move {
+            let result = sqlx::query(
+                "DELETE FROM anomalies \
+                 WHERE sensor_id = $1 AND timestamp >= $2::timestamp \
+                   AND timestamp < $3::timestamp AND method = $4",
+            )
+            .bind(sensor_id)
+            .bind(start)
+            .bind(end)
+            .bind(method)
+            .execute(&self.pool)
+            .await?;
+            Ok(result.rows_affected())
+        }
+        .boxed()
+    }
+
+    fn delete_before<'a>(
+        &'a self,
+        cutoff: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        async move {
+            let result = sqlx::query(
+                "DELETE FROM anomalies WHERE id IN \
+                 (SELECT id FROM anomalies WHERE timestamp < $1::timestamp LIMIT $2)",
+            )
+            .bind(cutoff)
+            .bind(limit)
+            .execute(&self.pool)
+            .await?;
+            Ok(result.rows_affected())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use sqlx::postgres::PgConnectOptions;
+
+    use super::*;
+    use crate::conformance;
+
+    /// Runs against the database at `ALERT_STORE_POSTGRES_URL`, in a schema
+    /// of its own that is dropped afterwards; skipped when it is not set.
+    #[tokio::test]
+    async fn test_postgres_store() {
+        let Ok(url) = std::env::var("ALERT_STORE_POSTGRES_URL") else {
+            eprintln!("ALERT_STORE_POSTGRES_URL is not set; skipping");
+            return;
+        };
+        let schema = format!("alert_store_test_{}", std::process::id());
+        let admin = PgPool::connect(&url).await.unwrap();
+        sqlx::raw_sql(&format!("CREATE SCHEMA {}", schema))
+            .execute(&admin)
+            .await
+            .unwrap();
+
+        let options = PgConnectOptions::from_str(&url)
+            .unwrap()
+            .options([("search_path", schema.as_str())]);
+        let pool = PgPoolOptions::new().connect_with(options).await.unwrap();
+        let store = PostgresAlertStore::from_pool(pool.clone()).await.unwrap();
+        conformance::check(&store).await;
+        PostgresAlertStore::from_pool(pool).await.unwrap();
+
+        sqlx::raw_sql(&format!("DROP SCHEMA {} CASCADE", schema))
+            .execute(&admin)
+            .await
+            .unwrap();
+    }
+}
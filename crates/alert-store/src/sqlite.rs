@@ -0,0 +1,187 @@
+//! Anomalies in the `anomalies` table of a SQLite database, usually the one
+//! shared with the Python API.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use futures_util::future::BoxFuture;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePool, SqlitePoolOptions};
+
+use crate::{AlertStore, AnomalyFilter, Error, NewAnomaly, StoredAnomaly};
+
+const MIGRATIONS: &[&str] = &[include_str!("../../../db/migrations/005_anomalies.sql")];
+
+#[derive(Clone)]
+pub struct SqliteAlertStore {
+    pool: SqlitePool,
+}
+
+impl SqliteAlertStore {
+    pub async fn connect(url: &str) -> Result<Self, Error> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(5));
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        Self::from_pool(pool).await
+    }
+
+    /// Uses a pool the caller already holds, so the anomalies live in the
+    /// same database as its other tables.
+    pub async fn from_pool(pool: SqlitePool) -> Result<Self, Error> {
+        for migration in MIGRATIONS {
+            sqlx::raw_sql(migration).execute(&pool).await?;
+        }
+        Ok(Self { pool })
+    }
+}
+
+impl AlertStore for SqliteAlertStore {
+    fn insert<'a>(
+        &'a self,
+        anomalies: &'a [NewAnomaly],
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        async move {
+            let mut tx = self.pool.begin().await?;
+            let mut stored = Vec::with_capacity(anomalies.len());
+            for anomaly in anomalies {
+                let row: StoredAnomaly = sqlx::query_as(
+                    "INSERT INTO anomalies \
+                     (reading_id, sensor_id, value, timestamp, method, score, severity) \
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+                     RETURNING id, reading_id, sensor_id, value, \
+                               CAST(timestamp AS TEXT) AS timestamp, method, score, severity, \
+                               CAST(detected_at AS TEXT) AS detected_at",
+                )
+                .bind(anomaly.reading_id)
+                .bind(anomaly.sensor_id)
+                .bind(anomaly.value)
+                .bind(&anomaly.timestamp)
+                .bind(anomaly.method)
+                .bind(anomaly.score)
+                .bind(anomaly.severity.as_str())
+                .fetch_one(&mut *tx)
+                .await?;
+                stored.push(row);
+            }
+            tx.commit().await?;
+            Ok(stored)
+        }
+        .boxed()
+    }
+
+    fn list<'a>(
+        &'a self,
+        filter: &'a AnomalyFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        sqlx::query_as(
+            "SELECT id, reading_id, sensor_id, value, CAST(timestamp AS TEXT) AS timestamp, \
+                    method, score, severity, CAST(detected_at AS TEXT) AS detected_at \
+             FROM anomalies \
+             WHERE (?1 IS NULL OR sensor_id = ?1) \
+               AND (?2 IS NULL OR timestamp >= ?2) \
+               AND (?3 IS NULL OR timestamp < ?3) \
+               AND (?6 IS NULL OR method = ?6) \
+               AND (?7 IS NULL OR severity IN (SELECT value FROM json_each(?7))) \
+               AND id > ?4 \
+             ORDER BY id LIMIT ?5",
+        )
+        .bind(filter.sensor_id)
+        .bind(&filter.start)
+        .bind(&filter.end)
+        .bind(after_id)
+        .bind(limit)
+        .bind(&filter.method)
+        .bind(filter.severities().map(|labels| {
+            let quoted: Vec<String> = labels.iter().map(|l| format!("\"{}\"", l)).collect();
+            format!("[{}]", quoted.join(","))
+        }))
+        .fetch_all(&self.pool)
+        .boxed()
+    }
+
+    fn by_id<'a>(&'a self, ids: &'a [i64]) -> BoxFuture<'a, Result<Vec<StoredAnomaly>, Error>> {
+        let ids = ids.iter().map(i64::to_string).collect::<Vec<_>>().join(",");
+        async move {
+            sqlx::query_as(
+                "SELECT id, reading_id, sensor_id, value, CAST(timestamp AS TEXT) AS timestamp, \
+                        method, score, severity, CAST(detected_at AS TEXT) AS detected_at \
+                 FROM anomalies WHERE id IN (SELECT value FROM json_each(?1)) \
+                 ORDER BY id",
+            )
+            .bind(format!("[{}]", ids))
+            .fetch_all(&self.pool)
+            .await
+        }
+        .boxed()
+    }
+
+    fn delete_range<'a>(
+        &'a self,
+        sensor_id: i64,
+        start: &'a str,
+        end: &'a str,
+        method: &'a str,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        async move {
+            let result = sqlx::query(
+                "DELETE FROM anomalies \
+                 WHERE sensor_id = ?1 AND timestamp >= ?2 AND timestamp < ?3 AND method = ?4",
+            )
+            .bind(sensor_id)
+            .bind(start)
+            .bind(end)
+            .bind(method)
+            .execute(&self.pool)
+            .await?;
+            Ok(result.rows_affected())
+        }
+        .boxed()
+    }
+
+    fn delete_before<'a>(
+        &'a self,
+        cutoff: &'a str,
+        limit: i64,
+    ) -> BoxFuture<'a, Result<u64, Error>> {
+        async move {
+            let result = sqlx::query(
+                "DELETE FROM anomalies WHERE id IN \
+                 (SELECT id FROM anomalies WHERE timestamp < ?1 LIMIT ?2)",
+            )
+            .bind(cutoff)
+            .bind(limit)
+            .execute(&self.pool)
+            .await?;
+            Ok(result.rows_affected())
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance;
+
+    #[tokio::test]
+    async fn test_sqlite_store() {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap();
+        // A single connection keeps the in-memory database alive and shared.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .unwrap();
+        let store = SqliteAlertStore::from_pool(pool.clone()).await.unwrap();
+        conformance::check(&store).await;
+
+        // Migrations run again on every connect.
+        SqliteAlertStore::from_pool(pool).await.unwrap();
+    }
+}
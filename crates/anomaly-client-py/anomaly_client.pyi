@@ -0,0 +1,74 @@
+from collections.abc import Awaitable, Iterable, Mapping
+from typing import Any
+
+Readings = Iterable[Mapping[str, Any]] | Any
+"""Dicts with `id`, `value` and `timestamp`, or a pyarrow Table/RecordBatch."""
+
+class ApiError(Exception):
+    """args: (message, HTTP status or None)."""
+
+class Client:
+    def __init__(self, base_url: str, timeout: float = 30.0, max_attempts: int = 4) -> None: ...
+    def health(self) -> str: ...
+    def analyze(
+        self, readings: Readings, sensor_id: int | None = None, threshold: float | None = None
+    ) -> dict[str, Any]: ...
+    def analyze_batch(self, series: Iterable[Mapping[str, Any]]) -> dict[str, Any]: ...
+    def backfill(
+        self,
+        sensor_id: int,
+        start: str,
+        end: str,
+        threshold: float | None = None,
+        notify: bool = False,
+    ) -> dict[str, Any]: ...
+    def list_anomalies(
+        self,
+        sensor_id: int | None = None,
+        start: str | None = None,
+        end: str | None = None,
+        after_id: int | None = None,
+        limit: int | None = None,
+    ) -> dict[str, Any]: ...
+    def anomalies(
+        self,
+        sensor_id: int | None = None,
+        start: str | None = None,
+        end: str | None = None,
+        page_size: int | None = None,
+    ) -> list[dict[str, Any]]: ...
+    def baselines(self, hours: int | None = None) -> dict[str, Any]: ...
+    def acknowledge(self, sensor_id: int) -> None: ...
+
+class AsyncClient:
+    def __init__(self, base_url: str, timeout: float = 30.0, max_attempts: int = 4) -> None: ...
+    def health(self) -> Awaitable[str]: ...
+    def analyze(
+        self, readings: Readings, sensor_id: int | None = None, threshold: float | None = None
+    ) -> Awaitable[dict[str, Any]]: ...
+    def analyze_batch(self, series: Iterable[Mapping[str, Any]]) -> Awaitable[dict[str, Any]]: ...
+    def backfill(
+        self,
+        sensor_id: int,
+        start: str,
+        end: str,
+        threshold: float | None = None,
+        notify: bool = False,
+    ) -> Awaitable[dict[str, Any]]: ...
+    def list_anomalies(
+        self,
+        sensor_id: int | None = None,
+        start: str | None = None,
+        end: str | None = None,
+        after_id: int | None = None,
+        limit: int | None = None,
+    ) -> Awaitable[dict[str, Any]]: ...
+    def anomalies(
+        self,
+        sensor_id: int | None = None,
+        start: str | None = None,
+        end: str | None = None,
+        page_size: int | None = None,
+    ) -> Awaitable[list[dict[str, Any]]]: ...
+    def baselines(self, hours: int | None = None) -> Awaitable[dict[str, Any]]: ...
+    def acknowledge(self, sensor_id: int) -> Awaitable[None]: ...
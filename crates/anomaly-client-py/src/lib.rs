@@ -0,0 +1,371 @@
+//! Python client for the anomaly detector's HTTP API, over the
+//! `anomaly-client` crate: `Client` blocks, `AsyncClient` returns awaitables
+//! for asyncio. Both share the crate's connection pool, retry policy and
+//! response decompression.
+//!
+//! Readings are given as dicts with `id`, `value` and `timestamp` keys (a
+//! `datetime` is sent in ISO 8601), or as a `pyarrow.Table` or `RecordBatch`
+//! with those columns. Responses are returned as plain dicts and lists.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anomaly_client::{
+    AnalyzeRequest, AnomalyQuery, BackfillRequest, BatchRequest, BatchSeries, Error, Reading,
+    RetryPolicy,
+};
+use futures_util::TryStreamExt;
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::{PyDict, PyString};
+use pyo3_async_runtimes::tokio::{future_into_py, get_runtime};
+use pythonize::pythonize;
+use serde::Serialize;
+
+create_exception!(
+    anomaly_client,
+    ApiError,
+    PyException,
+    "A request failed after its retries; args are the message and the HTTP status, or None."
+);
+
+fn api_error(e: Error) -> PyErr {
+    ApiError::new_err((e.to_string(), e.status().map(|status| status.as_u16())))
+}
+
+fn connect(base_url: &str, timeout: f64, max_attempts: u32) -> PyResult<anomaly_client::Client> {
+    if !(timeout.is_finite() && timeout > 0.0) {
+        return Err(PyValueError::new_err("timeout must be a positive number"));
+    }
+    if max_attempts == 0 {
+        return Err(PyValueError::new_err("max_attempts must be at least 1"));
+    }
+    anomaly_client::Client::builder(base_url)
+        .timeout(Duration::from_secs_f64(timeout))
+        .retry(RetryPolicy {
+            max_attempts,
+            ..RetryPolicy::default()
+        })
+        .build()
+        .map_err(api_error)
+}
+
+/// Rows of a list of mappings, or of an Arrow table or record batch.
+fn readings(readings: &Bound<'_, PyAny>) -> PyResult<Vec<Reading>> {
+    let rows = if readings.hasattr("to_pylist")? {
+        readings.call_method0("to_pylist")?
+    } else {
+        readings.clone()
+    };
+    rows.try_iter()?
+        .map(|row| {
+            let row = row?;
+            let mut timestamp = row.get_item("timestamp")?;
+            if !timestamp.is_instance_of::<PyString>() && timestamp.hasattr("isoformat")? {
+                timestamp = timestamp.call_method0("isoformat")?;
+            }
+            Ok(Reading {
+                id: row.get_item("id")?.extract()?,
+                value: row.get_item("value")?.extract()?,
+                timestamp: timestamp.extract()?,
+            })
+        })
+        .collect()
+}
+
+/// Batch series given as dicts with `id`, `readings` and optionally
+/// `sensor_id` and `threshold`.
+fn batch(series: &Bound<'_, PyAny>) -> PyResult<BatchRequest> {
+    let series = series
+        .try_iter()?
+        .map(|entry| {
+            let entry = entry?;
+            let entry = entry.cast::<PyDict>()?;
+            let optional = |key: &str| -> PyResult<Option<Bound<'_, PyAny>>> {
+                Ok(entry.get_item(key)?.filter(|value| !value.is_none()))
+            };
+            let required = |key: &str| {
+                optional(key)?.ok_or_else(|| PyValueError::new_err(format!("series needs {}", key)))
+            };
+            Ok(BatchSeries {
+                id: required("id")?.extract()?,
+                request: AnalyzeRequest {
+                    sensor_id: optional("sensor_id")?.map(|v| v.extract()).transpose()?,
+                    readings: readings(&required("readings")?)?,
+                    threshold: optional("threshold")?.map(|v| v.extract()).transpose()?,
+                    export: None,
+                },
+            })
+        })
+        .collect::<PyResult<_>>()?;
+    Ok(BatchRequest { series })
+}
+
+fn anomaly_query(
+    sensor_id: Option<i64>,
+    start: Option<String>,
+    end: Option<String>,
+    after_id: Option<i64>,
+    limit: Option<i64>,
+) -> AnomalyQuery {
+    AnomalyQuery {
+        sensor_id,
+        start,
+        end,
+        after_id,
+        limit,
+        ..AnomalyQuery::default()
+    }
+}
+
+/// Runs `future` with the GIL released and converts its result.
+fn block_on<'py, T: Serialize + Send>(
+    py: Python<'py>,
+    future: impl Future<Output = Result<T, Error>> + Send,
+) -> PyResult<Bound<'py, PyAny>> {
+    let value = py
+        .detach(|| get_runtime().block_on(future))
+        .map_err(api_error)?;
+    Ok(pythonize(py, &value)?)
+}
+
+/// Wraps `future` in an asyncio awaitable yielding its converted result.
+fn awaitable<'py, T: Serialize + Send + 'static>(
+    py: Python<'py>,
+    future: impl Future<Output = Result<T, Error>> + Send + 'static,
+) -> PyResult<Bound<'py, PyAny>> {
+    future_into_py(py, async move {
+        let value = future.await.map_err(api_error)?;
+        Python::attach(|py| Ok(pythonize(py, &value)?.unbind()))
+    })
+}
+
+/// Blocking client; calls release the GIL while waiting.
+#[pyclass(frozen)]
+struct Client {
+    inner: anomaly_client::Client,
+}
+
+#[pymethods]
+impl Client {
+    #[new]
+    #[pyo3(signature = (base_url, timeout=30.0, max_attempts=4))]
+    fn new(base_url: &str, timeout: f64, max_attempts: u32) -> PyResult<Self> {
+        Ok(Self {
+            inner: connect(base_url, timeout, max_attempts)?,
+        })
+    }
+
+    fn health<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        block_on(py, self.inner.health())
+    }
+
+    #[pyo3(signature = (readings, sensor_id=None, threshold=None))]
+    fn analyze<'py>(
+        &self,
+        py: Python<'py>,
+        readings: &Bound<'py, PyAny>,
+        sensor_id: Option<i64>,
+        threshold: Option<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = AnalyzeRequest {
+            sensor_id,
+            readings: self::readings(readings)?,
+            threshold,
+            export: None,
+        };
+        block_on(py, self.inner.analyze(&request))
+    }
+
+    fn analyze_batch<'py>(
+        &self,
+        py: Python<'py>,
+        series: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = batch(series)?;
+        block_on(py, self.inner.analyze_batch(&request))
+    }
+
+    #[pyo3(signature = (sensor_id, start, end, threshold=None, notify=false))]
+    fn backfill<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: i64,
+        start: String,
+        end: String,
+        threshold: Option<f64>,
+        notify: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = BackfillRequest {
+            sensor_id,
+            start,
+            end,
+            threshold,
+            export: None,
+            notify,
+        };
+        block_on(py, self.inner.backfill(&request))
+    }
+
+    /// One page; pass its `next_after_id` as `after_id` for the next.
+    #[pyo3(signature = (sensor_id=None, start=None, end=None, after_id=None, limit=None))]
+    fn list_anomalies<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: Option<i64>,
+        start: Option<String>,
+        end: Option<String>,
+        after_id: Option<i64>,
+        limit: Option<i64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = anomaly_query(sensor_id, start, end, after_id, limit);
+        block_on(py, self.inner.list_anomalies(&query))
+    }
+
+    /// Every matching anomaly, fetching `page_size` at a time.
+    #[pyo3(signature = (sensor_id=None, start=None, end=None, page_size=None))]
+    fn anomalies<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: Option<i64>,
+        start: Option<String>,
+        end: Option<String>,
+        page_size: Option<i64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = anomaly_query(sensor_id, start, end, None, page_size);
+        block_on(py, self.inner.anomalies(query).try_collect::<Vec<_>>())
+    }
+
+    #[pyo3(signature = (hours=None))]
+    fn baselines<'py>(&self, py: Python<'py>, hours: Option<u32>) -> PyResult<Bound<'py, PyAny>> {
+        block_on(py, self.inner.baselines(hours))
+    }
+
+    fn acknowledge<'py>(&self, py: Python<'py>, sensor_id: i64) -> PyResult<Bound<'py, PyAny>> {
+        block_on(py, self.inner.acknowledge(sensor_id))
+    }
+}
+
+/// asyncio client; every method returns an awaitable.
+#[pyclass(frozen)]
+struct AsyncClient {
+    inner: anomaly_client::Client,
+}
+
+#[pymethods]
+impl AsyncClient {
+    #[new]
+    #[pyo3(signature = (base_url, timeout=30.0, max_attempts=4))]
+    fn new(base_url: &str, timeout: f64, max_attempts: u32) -> PyResult<Self> {
+        Ok(Self {
+            inner: connect(base_url, timeout, max_attempts)?,
+        })
+    }
+
+    fn health<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.inner.clone();
+        awaitable(py, async move { client.health().await })
+    }
+
+    #[pyo3(signature = (readings, sensor_id=None, threshold=None))]
+    fn analyze<'py>(
+        &self,
+        py: Python<'py>,
+        readings: &Bound<'py, PyAny>,
+        sensor_id: Option<i64>,
+        threshold: Option<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = AnalyzeRequest {
+            sensor_id,
+            readings: self::readings(readings)?,
+            threshold,
+            export: None,
+        };
+        let client = self.inner.clone();
+        awaitable(py, async move { client.analyze(&request).await })
+    }
+
+    fn analyze_batch<'py>(
+        &self,
+        py: Python<'py>,
+        series: &Bound<'py, PyAny>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = batch(series)?;
+        let client = self.inner.clone();
+        awaitable(py, async move { client.analyze_batch(&request).await })
+    }
+
+    #[pyo3(signature = (sensor_id, start, end, threshold=None, notify=false))]
+    fn backfill<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: i64,
+        start: String,
+        end: String,
+        threshold: Option<f64>,
+        notify: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = BackfillRequest {
+            sensor_id,
+            start,
+            end,
+            threshold,
+            export: None,
+            notify,
+        };
+        let client = self.inner.clone();
+        awaitable(py, async move { client.backfill(&request).await })
+    }
+
+    #[pyo3(signature = (sensor_id=None, start=None, end=None, after_id=None, limit=None))]
+    fn list_anomalies<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: Option<i64>,
+        start: Option<String>,
+        end: Option<String>,
+        after_id: Option<i64>,
+        limit: Option<i64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = anomaly_query(sensor_id, start, end, after_id, limit);
+        let client = self.inner.clone();
+        awaitable(py, async move { client.list_anomalies(&query).await })
+    }
+
+    #[pyo3(signature = (sensor_id=None, start=None, end=None, page_size=None))]
+    fn anomalies<'py>(
+        &self,
+        py: Python<'py>,
+        sensor_id: Option<i64>,
+        start: Option<String>,
+        end: Option<String>,
+        page_size: Option<i64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let query = anomaly_query(sensor_id, start, end, None, page_size);
+        let client = self.inner.clone();
+        awaitable(py, async move {
+            client.anomalies(query).try_collect::<Vec<_>>().await
+        })
+    }
+
+    #[pyo3(signature = (hours=None))]
+    fn baselines<'py>(&self, py: Python<'py>, hours: Option<u32>) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.inner.clone();
+        awaitable(py, async move { client.baselines(hours).await })
+    }
+
+    fn acknowledge<'py>(&self, py: Python<'py>, sensor_id: i64) -> PyResult<Bound<'py, PyAny>> {
+        let client = self.inner.clone();
+        awaitable(py, async move { client.acknowledge(sensor_id).await })
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "anomaly_client")]
+fn anomaly_client_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_class::<AsyncClient>()?;
+    m.add("ApiError", m.py().get_type::<ApiError>())?;
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+use reqwest::StatusCode;
+use tokio_tungstenite::tungstenite;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The base URL could not be parsed or cannot have paths joined to it.
+    InvalidUrl(String),
+    /// The request could not be sent or its response not read, after any
+    /// retries.
+    Http(reqwest::Error),
+    /// The service answered with an error status, after any retries.
+    /// `message` is the response body, which the service fills with a
+    /// description of the problem.
+    Status {
+        status: StatusCode,
+        message: String,
+    },
+    WebSocket(tungstenite::Error),
+    /// A WebSocket message was not a known event.
+    Decode(serde_json::Error),
+}
+
+impl Error {
+    /// The HTTP status the service answered with, if it got that far.
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status, .. } => Some(*status),
+            Error::Http(e) => e.status(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl(url) => write!(f, "invalid base URL: {}", url),
+            Error::Http(e) => write!(f, "request failed: {}", e),
+            Error::Status { status, message } if message.is_empty() => {
+                write!(f, "service answered {}", status)
+            }
+            Error::Status { status, message } => {
+                write!(f, "service answered {}: {}", status, message)
+            }
+            Error::WebSocket(e) => write!(f, "WebSocket error: {}", e),
+            Error::Decode(e) => write!(f, "invalid event: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Http(e) => Some(e),
+            Error::WebSocket(e) => Some(e),
+            Error::Decode(e) => Some(e),
+            Error::InvalidUrl(_) | Error::Status { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::Http(e)
+    }
+}
+
+impl From<tungstenite::Error> for Error {
+    fn from(e: tungstenite::Error) -> Self {
+        Error::WebSocket(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Decode(e)
+    }
+}
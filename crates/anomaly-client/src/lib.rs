@@ -0,0 +1,431 @@
+//! Typed client for the anomaly detector's HTTP API.
+//!
+//! A [`Client`] holds one connection pool and is cheap to clone, so a service
+//! should build it once and share it. Failed requests are retried according
+//! to its [`RetryPolicy`]; an error status the service answered with after
+//! the last attempt is returned as [`Error::Status`] with the service's
+//! message. Responses may come gzip-compressed, e.g. through a proxy.
+//!
+//! ```no_run
+//! use anomaly_client::{AnalyzeRequest, Client, Reading};
+//!
+//! # async fn run() -> Result<(), anomaly_client::Error> {
+//! let client = Client::new("http://localhost:3000")?;
+//! let response = client
+//!     .analyze(&AnalyzeRequest {
+//!         sensor_id: Some(7),
+//!         readings: vec![Reading {
+//!             id: 1,
+//!             value: 21.5,
+//!             timestamp: "2026-01-19T10:00:00".to_string(),
+//!         }],
+//!         ..AnalyzeRequest::default()
+//!     })
+//!     .await?;
+//! println!("{} anomalies", response.anomalies.len());
+//! # Ok(())
+//! # }
+//! ```
+
+mod error;
+mod retry;
+mod stream;
+mod types;
+
+use std::time::Duration;
+
+use futures_util::{Stream, TryStreamExt, stream as futures_stream};
+use reqwest::{RequestBuilder, Response, Url};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+
+pub use error::Error;
+pub use retry::RetryPolicy;
+pub use stream::Subscriber;
+pub use types::*;
+
+pub use detection_core::Severity;
+
+/// Configures a [`Client`]; start from [`Client::builder`].
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    base_url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    pool_idle_timeout: Duration,
+    pool_max_idle_per_host: usize,
+    retry: RetryPolicy,
+}
+
+impl ClientBuilder {
+    /// Limit on each attempt, from sending the request to reading the whole
+    /// response. Defaults to 30 seconds; long backfills may need more.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Defaults to 5 seconds.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// How long an idle pooled connection is kept. Defaults to 90 seconds.
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Idle connections kept open to the service. Defaults to 32.
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn build(self) -> Result<Client, Error> {
+        let mut base_url =
+            Url::parse(&self.base_url).map_err(|_| Error::InvalidUrl(self.base_url.clone()))?;
+        if base_url.cannot_be_a_base() || !matches!(base_url.scheme(), "http" | "https") {
+            return Err(Error::InvalidUrl(self.base_url));
+        }
+        // Endpoints are joined below any path prefix of the service.
+        if !base_url.path().ends_with('/') {
+            let path = format!("{}/", base_url.path());
+            base_url.set_path(&path);
+        }
+        let http = reqwest::Client::builder()
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .build()?;
+        Ok(Client {
+            http,
+            base_url,
+            retry: self.retry,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: Url,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// A client with the default timeouts, pool and retry policy.
+    pub fn new(base_url: &str) -> Result<Self, Error> {
+        Self::builder(base_url).build()
+    }
+
+    pub fn builder(base_url: &str) -> ClientBuilder {
+        ClientBuilder {
+            base_url: base_url.to_string(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(5),
+            pool_idle_timeout: Duration::from_secs(90),
+            pool_max_idle_per_host: 32,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    fn url(&self, path: &str) -> Url {
+        self.base_url
+            .join(path)
+            .expect("endpoint paths are valid relative URLs")
+    }
+
+    /// Sends the request, retrying per the policy, and returns the first
+    /// successful response.
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let mut attempts = 1;
+        loop {
+            let attempt = request
+                .try_clone()
+                .expect("request bodies are built in memory");
+            let wait = match attempt.send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response)
+                    if retry::is_retryable(response.status())
+                        && attempts < self.retry.max_attempts =>
+                {
+                    self.retry.backoff(attempts, Some(response.headers()))
+                }
+                Ok(response) => {
+                    let status = response.status();
+                    let message = response.text().await.unwrap_or_default();
+                    return Err(Error::Status { status, message });
+                }
+                Err(_) if attempts < self.retry.max_attempts => self.retry.backoff(attempts, None),
+                Err(e) => return Err(e.into()),
+            };
+            tokio::time::sleep(wait).await;
+            attempts += 1;
+        }
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T, Error> {
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// `GET /health`: the service's status line.
+    pub async fn health(&self) -> Result<String, Error> {
+        let request = self.http.get(self.url("health"));
+        Ok(self.send(request).await?.text().await?)
+    }
+
+    /// `POST /analyze`: detects anomalies in one series.
+    pub async fn analyze(&self, request: &AnalyzeRequest) -> Result<AnalyzeResponse, Error> {
+        self.json(self.http.post(self.url("analyze")).json(request))
+            .await
+    }
+
+    /// `POST /analyze/batch`: analyzes many series in one request; each
+    /// series succeeds or fails on its own.
+    pub async fn analyze_batch(&self, request: &BatchRequest) -> Result<BatchResponse, Error> {
+        self.json(self.http.post(self.url("analyze/batch")).json(request))
+            .await
+    }
+
+    /// `POST /backfill`: re-scores a sensor's stored readings over a range.
+    pub async fn backfill(&self, request: &BackfillRequest) -> Result<BackfillResponse, Error> {
+        self.json(self.http.post(self.url("backfill")).json(request))
+            .await
+    }
+
+    /// `GET /anomalies`: one page of stored anomalies.
+    pub async fn list_anomalies(&self, query: &AnomalyQuery) -> Result<AnomalyPage, Error> {
+        self.json(self.http.get(self.url("anomalies")).query(query))
+            .await
+    }
+
+    /// `GET /anomalies/{id}`: one stored anomaly.
+    pub async fn anomaly(&self, id: i64) -> Result<StoredAnomaly, Error> {
+        self.json(self.http.get(self.url(&format!("anomalies/{}", id))))
+            .await
+    }
+
+    /// Every stored anomaly matching `query`, fetched a page at a time as the
+    /// stream is read. `query.limit` sets the page size.
+    pub fn anomalies(
+        &self,
+        query: AnomalyQuery,
+    ) -> impl Stream<Item = Result<StoredAnomaly, Error>> + '_ {
+        futures_stream::try_unfold(Some(query), move |query| async move {
+            let Some(query) = query else {
+                return Ok::<_, Error>(None);
+            };
+            let page = self.list_anomalies(&query).await?;
+            let next = page.next_after_id.map(|after_id| AnomalyQuery {
+                after_id: Some(after_id),
+                ..query
+            });
+            let anomalies = futures_stream::iter(page.anomalies.into_iter().map(Ok));
+            Ok(Some((anomalies, next)))
+        })
+        .try_flatten()
+    }
+
+    /// `GET /baselines`: each sensor's statistics over the last `hours`,
+    /// 24 by default.
+    pub async fn baselines(&self, hours: Option<u32>) -> Result<BaselineResponse, Error> {
+        let mut request = self.http.get(self.url("baselines"));
+        if let Some(hours) = hours {
+            request = request.query(&[("hours", hours)]);
+        }
+        self.json(request).await
+    }
+
+    /// `POST /incidents/acknowledge`: stops the escalation of the sensor's
+    /// open incident. Fails with status 404 when it has none to acknowledge.
+    pub async fn acknowledge(&self, sensor_id: i64) -> Result<(), Error> {
+        let request = self
+            .http
+            .post(self.url("incidents/acknowledge"))
+            .json(&json!({ "sensor_id": sensor_id }));
+        self.send(request).await?;
+        Ok(())
+    }
+
+    /// Opens `/ws/anomalies` with `subscription`.
+    pub async fn subscribe(&self, subscription: &Subscription) -> Result<Subscriber, Error> {
+        Subscriber::connect(self.url("ws/anomalies"), subscription).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::extract::{Query, State};
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+    use axum::{Json, Router};
+    use serde_json::Value;
+
+    async fn serve(app: Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/detector", listener.local_addr().unwrap());
+        let app = Router::new().nest("/detector", app);
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_unavailable_responses() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/analyze",
+                post(
+                    |State(calls): State<Arc<AtomicUsize>>, Json(body): Json<Value>| async move {
+                        if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                            return Err((StatusCode::SERVICE_UNAVAILABLE, "busy".to_string()));
+                        }
+                        Ok(Json(serde_json::json!({
+                            "anomalies": [],
+                            "total_readings": body["readings"].as_array().unwrap().len(),
+                            "mean": 10.0,
+                            "std_dev": 0.0,
+                        })))
+                    },
+                ),
+            )
+            .with_state(calls.clone());
+        let base_url = serve(app).await;
+        let client = Client::builder(&base_url)
+            .retry(fast_retries())
+            .build()
+            .unwrap();
+        let request = AnalyzeRequest {
+            readings: vec![Reading {
+                id: 1,
+                value: 10.0,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+            }],
+            ..AnalyzeRequest::default()
+        };
+
+        let response = client.analyze(&request).await.unwrap();
+        assert_eq!(response.total_readings, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        calls.store(0, Ordering::SeqCst);
+        let once = Client::builder(&base_url)
+            .retry(RetryPolicy::none())
+            .build()
+            .unwrap();
+        let error = once.analyze(&request).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(
+            error.to_string(),
+            "service answered 503 Service Unavailable: busy"
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/incidents/acknowledge",
+                post(
+                    |State(calls): State<Arc<AtomicUsize>>, Json(body): Json<Value>| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        match body["sensor_id"].as_i64() {
+                            Some(7) => Ok(StatusCode::NO_CONTENT),
+                            _ => Err((StatusCode::NOT_FOUND, "no incident".to_string())),
+                        }
+                    },
+                ),
+            )
+            .with_state(calls.clone());
+        let client = Client::builder(&serve(app).await)
+            .retry(fast_retries())
+            .build()
+            .unwrap();
+
+        client.acknowledge(7).await.unwrap();
+        let error = client.acknowledge(8).await.unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_anomalies_follow_the_cursor() {
+        let app = Router::new().route(
+            "/anomalies",
+            get(|Query(query): Query<AnomalyQuery>| async move {
+                assert_eq!(query.sensor_id, Some(3));
+                let after_id = query.after_id.unwrap_or(0);
+                let ids: Vec<i64> = (after_id + 1..=5).take(2).collect();
+                let anomalies: Vec<Value> = ids
+                    .iter()
+                    .map(|id| {
+                        serde_json::json!({
+                            "id": id,
+                            "reading_id": id * 10,
+                            "sensor_id": 3,
+                            "value": 99.0,
+                            "timestamp": "2026-01-19 10:00:00",
+                            "method": "zscore",
+                            "score": 3.5,
+                            "severity": "high",
+                            "detected_at": "2026-01-19 10:00:01",
+                        })
+                    })
+                    .collect();
+                let next_after_id = ids.last().filter(|&&id| id < 5);
+                Json(serde_json::json!({
+                    "anomalies": anomalies,
+                    "next_after_id": next_after_id,
+                }))
+            }),
+        );
+        let client = Client::new(&serve(app).await).unwrap();
+
+        let query = AnomalyQuery {
+            sensor_id: Some(3),
+            ..AnomalyQuery::default()
+        };
+        let anomalies: Vec<StoredAnomaly> = client.anomalies(query).try_collect().await.unwrap();
+        let ids: Vec<i64> = anomalies.iter().map(|a| a.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
+        assert_eq!(anomalies[4].severity, Severity::High);
+    }
+
+    #[test]
+    fn test_rejects_invalid_base_urls() {
+        for url in ["localhost:3000", "ftp://example.com", "not a url"] {
+            assert!(
+                matches!(Client::new(url), Err(Error::InvalidUrl(_))),
+                "{}",
+                url
+            );
+        }
+        let client = Client::new("http://example.com/detector").unwrap();
+        assert_eq!(
+            client.url("analyze/batch").as_str(),
+            "http://example.com/detector/analyze/batch"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! When and how long to wait before sending a failed request again.
+//!
+//! A request is retried when it could not be sent or its response not read
+//! (connection refused or reset, timeouts), or when the service answered 408,
+//! 429 or a 5xx status. Waits double from `initial_backoff` up to
+//! `max_backoff`; a `Retry-After` header in seconds takes precedence, capped
+//! the same way.
+//!
+//! Every endpoint is retried, including the POSTs: analyzing or backfilling
+//! the same readings again gives the same result, and the service sends each
+//! anomaly's notifications once per dedup key. Live subscribers may see an
+//! event twice when an `/analyze` request for a `sensor_id` is retried after
+//! its response was lost.
+
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first; 1 disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sends every request once.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Wait before the attempt after `attempts` failed ones.
+    pub(crate) fn backoff(&self, attempts: u32, headers: Option<&HeaderMap>) -> Duration {
+        let requested = headers
+            .and_then(|headers| headers.get(RETRY_AFTER))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse().ok())
+            .map(Duration::from_secs);
+        requested
+            .unwrap_or_else(|| {
+                self.initial_backoff
+                    .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            })
+            .min(self.max_backoff)
+    }
+}
+
+pub(crate) fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error()
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status == StatusCode::REQUEST_TIMEOUT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+        };
+        let waits: Vec<u128> = (1..=6)
+            .map(|attempts| policy.backoff(attempts, None).as_millis())
+            .collect();
+        assert_eq!(waits, [100, 200, 400, 800, 1000, 1000]);
+        assert_eq!(policy.backoff(40, None), policy.max_backoff);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("0"));
+        assert_eq!(policy.backoff(3, Some(&headers)), Duration::ZERO);
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(policy.backoff(1, Some(&headers)), policy.max_backoff);
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2026 07:28:00 GMT"),
+        );
+        assert_eq!(policy.backoff(2, Some(&headers)).as_millis(), 200);
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        for status in [429, 408, 500, 502, 503] {
+            assert!(is_retryable(StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [400, 401, 404, 409, 422] {
+            assert!(!is_retryable(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Live anomalies from `/ws/anomalies`.
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, ready};
+
+use futures_util::{SinkExt, Stream, StreamExt};
+use reqwest::Url;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+use crate::{Error, Event, Subscription};
+
+/// A WebSocket subscription, yielding every [`Event`] the service sends
+/// until either side closes it.
+///
+/// The first event confirms the subscription. The connection is not
+/// reopened when it drops; subscribe again, and list what was missed with
+/// [`Client::anomalies`](crate::Client::anomalies).
+pub struct Subscriber {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Subscriber {
+    pub(crate) async fn connect(mut url: Url, subscription: &Subscription) -> Result<Self, Error> {
+        let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+        url.set_scheme(scheme)
+            .map_err(|()| Error::InvalidUrl(url.to_string()))?;
+        {
+            let mut query = url.query_pairs_mut();
+            if let Some(sensors) = &subscription.sensors {
+                let sensors: Vec<String> = sensors.iter().map(i64::to_string).collect();
+                query.append_pair("sensors", &sensors.join(","));
+            }
+            query.append_pair("min_severity", subscription.min_severity.as_str());
+        }
+        let (socket, _) = tokio_tungstenite::connect_async_tls_with_config(
+            url.as_str(),
+            None,
+            false,
+            Some(tls_connector()),
+        )
+        .await?;
+        Ok(Self { socket })
+    }
+
+    /// Replaces the subscription; the service confirms it with a
+    /// [`Event::Subscribed`] event.
+    pub async fn update(&mut self, subscription: &Subscription) -> Result<(), Error> {
+        let text = serde_json::to_string(subscription)?;
+        self.socket.send(Message::text(text)).await?;
+        Ok(())
+    }
+
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.socket.close(None).await?;
+        Ok(())
+    }
+}
+
+impl Stream for Subscriber {
+    type Item = Result<Event, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let message = match ready!(self.socket.poll_next_unpin(cx)) {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => return Poll::Ready(None),
+            };
+            match message {
+                Message::Text(text) => {
+                    return Poll::Ready(Some(serde_json::from_str(&text).map_err(Error::from)));
+                }
+                Message::Close(_) => return Poll::Ready(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// TLS for `wss://` with its own crypto provider, since the process-wide
+/// default is ambiguous when other dependencies enable a second one.
+fn tls_connector() -> Connector {
+    let roots = rustls::RootCertStore {
+        roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+    };
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+    let config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .expect("the aws-lc-rs provider supports the default protocol versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Connector::Rustls(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::extract::RawQuery;
+    use axum::extract::ws::{self, WebSocketUpgrade};
+    use axum::routing::get;
+    use detection_core::Severity;
+    use serde_json::json;
+
+    /// Echoes the query it was opened with, then one anomaly, then the text
+    /// of every message it receives.
+    async fn event_server() -> String {
+        let app = Router::new().route(
+            "/ws/anomalies",
+            get(
+                |RawQuery(query): RawQuery, ws: WebSocketUpgrade| async move {
+                    ws.on_upgrade(move |mut socket| async move {
+                        let echo = json!({
+                            "type": "error",
+                            "error": query.unwrap_or_default(),
+                        });
+                        let anomaly = json!({
+                            "type": "anomaly",
+                            "sensor_id": 2,
+                            "reading_id": 5,
+                            "value": 99.5,
+                            "timestamp": "2026-01-19 10:00:00",
+                            "method": "zscore",
+                            "score": 3.2,
+                            "severity": "critical",
+                        });
+                        for message in [echo, anomaly] {
+                            let text = message.to_string();
+                            socket.send(ws::Message::Text(text.into())).await.unwrap();
+                        }
+                        while let Some(Ok(ws::Message::Text(text))) = socket.recv().await {
+                            let update: Subscription = serde_json::from_str(&text).unwrap();
+                            let reply = json!({ "type": "subscribed", "subscription": update });
+                            let text = reply.to_string();
+                            socket.send(ws::Message::Text(text.into())).await.unwrap();
+                        }
+                    })
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/ws/anomalies", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_streams_events_and_updates() {
+        let url = Url::parse(&event_server().await).unwrap();
+        let subscription = Subscription {
+            sensors: Some(vec![1, 2]),
+            min_severity: Severity::High,
+        };
+        let mut subscriber = Subscriber::connect(url, &subscription).await.unwrap();
+
+        let query = subscriber.next().await.unwrap().unwrap();
+        assert_eq!(
+            query,
+            Event::Error {
+                error: "sensors=1%2C2&min_severity=high".to_string()
+            }
+        );
+        let Event::Anomaly(anomaly) = subscriber.next().await.unwrap().unwrap() else {
+            panic!("expected an anomaly");
+        };
+        assert_eq!(
+            (anomaly.sensor_id, anomaly.severity),
+            (2, Severity::Critical)
+        );
+
+        subscriber.update(&Subscription::default()).await.unwrap();
+        assert_eq!(
+            subscriber.next().await.unwrap().unwrap(),
+            Event::Subscribed {
+                subscription: Subscription::default()
+            }
+        );
+        subscriber.close().await.unwrap();
+    }
+}
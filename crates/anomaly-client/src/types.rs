@@ -0,0 +1,311 @@
+//! Request and response bodies of the service's endpoints.
+//!
+//! Field names and optionality follow the service exactly; optional request
+//! fields left as `None` are omitted so the service applies its defaults.
+
+use detection_core::Severity;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Reading {
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+/// Asks for a result set to be written to object storage instead of being
+/// returned in the response body.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    /// Object name below the service's configured prefix; generated when
+    /// absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ExportReceipt {
+    pub url: String,
+    pub format: ExportFormat,
+    pub rows: usize,
+}
+
+/// Body of `POST /analyze`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AnalyzeRequest {
+    /// Applies the sensor's registry entry, if it has one, and publishes the
+    /// anomalies found to live subscribers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id: Option<i64>,
+    pub readings: Vec<Reading>,
+    /// Defaults to the service's `default_threshold`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportRequest>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Anomaly {
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub z_score: f64,
+    pub severity: Severity,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AnalyzeResponse {
+    pub anomalies: Vec<Anomaly>,
+    pub total_readings: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    /// Where the anomalies were written when the request asked for an export;
+    /// `anomalies` is empty in that case.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportReceipt>,
+}
+
+/// One series of `POST /analyze/batch`; `export` is not supported here.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BatchSeries {
+    pub id: String,
+    #[serde(flatten)]
+    pub request: AnalyzeRequest,
+}
+
+/// Body of `POST /analyze/batch`, at most 1000 series.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct BatchRequest {
+    pub series: Vec<BatchSeries>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SeriesOutcome {
+    Ok { result: AnalyzeResponse },
+    Error { error: String },
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SeriesResult {
+    pub id: String,
+    #[serde(flatten)]
+    pub outcome: SeriesOutcome,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BatchResponse {
+    /// In the order of the request's series.
+    pub results: Vec<SeriesResult>,
+    pub succeeded: usize,
+    pub failed: usize,
+}
+
+/// Body of `POST /backfill`. Timestamps are anything the service accepts,
+/// e.g. `2026-01-01` or `2026-01-19T10:00:00`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct BackfillRequest {
+    pub sensor_id: i64,
+    pub start: String,
+    pub end: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportRequest>,
+    /// Sends the resulting anomalies to the configured notification channels.
+    #[serde(default)]
+    pub notify: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BackfillResponse {
+    pub sensor_id: i64,
+    pub method: String,
+    pub start: String,
+    pub end: String,
+    pub readings_scanned: u64,
+    pub anomalies_written: u64,
+    pub anomalies_replaced: u64,
+    /// Whether the most recent reading in the range was scored anomalous.
+    pub last_reading_anomalous: bool,
+    pub mean: f64,
+    pub std_dev: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub export: Option<ExportReceipt>,
+}
+
+/// Query of `GET /anomalies`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct AnomalyQuery {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<String>,
+    /// Only anomalies of this severity or above.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_severity: Option<Severity>,
+    /// Only anomalies with an id greater than this.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after_id: Option<i64>,
+    /// Page size.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StoredAnomaly {
+    pub id: i64,
+    pub reading_id: i64,
+    pub sensor_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub method: String,
+    pub score: f64,
+    pub severity: Severity,
+    pub detected_at: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AnomalyPage {
+    pub anomalies: Vec<StoredAnomaly>,
+    /// `after_id` of the next page; absent on the last page.
+    #[serde(default)]
+    pub next_after_id: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Baseline {
+    pub sensor_id: i64,
+    pub count: i64,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub last_value: f64,
+    pub last_timestamp: String,
+    /// Z-score of the latest reading; absent when the readings do not vary.
+    #[serde(default)]
+    pub last_score: Option<f64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct BaselineResponse {
+    pub since: String,
+    pub baselines: Vec<Baseline>,
+}
+
+/// Which live anomalies a [`Subscriber`](crate::Subscriber) receives.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct Subscription {
+    /// Sensors to receive events for; all of them when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensors: Option<Vec<i64>>,
+    #[serde(default)]
+    pub min_severity: Severity,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct AnomalyEvent {
+    pub sensor_id: i64,
+    pub reading_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub method: String,
+    pub score: f64,
+    pub severity: Severity,
+}
+
+/// A message from `/ws/anomalies`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// Confirms the subscription in effect, on connecting and after each
+    /// update.
+    Subscribed {
+        subscription: Subscription,
+    },
+    Anomaly(AnomalyEvent),
+    /// `missed` events were dropped because the client fell behind.
+    Lagged {
+        missed: u64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_optional_fields_are_omitted() {
+        let request = AnalyzeRequest {
+            readings: vec![Reading {
+                id: 1,
+                value: 10.0,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+            }],
+            ..AnalyzeRequest::default()
+        };
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(
+            value,
+            json!({
+                "readings": [{ "id": 1, "value": 10.0, "timestamp": "2026-01-19T10:00:00" }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_batch_outcomes_and_events_parse() {
+        let response: BatchResponse = serde_json::from_value(json!({
+            "results": [
+                {
+                    "id": "a",
+                    "status": "ok",
+                    "result": { "anomalies": [], "total_readings": 3, "mean": 1.0, "std_dev": 0.5 },
+                },
+                { "id": "b", "status": "error", "error": "series has no readings" },
+            ],
+            "succeeded": 1,
+            "failed": 1,
+        }))
+        .unwrap();
+        assert!(matches!(
+            &response.results[0].outcome,
+            SeriesOutcome::Ok { result } if result.total_readings == 3
+        ));
+        assert_eq!(
+            response.results[1].outcome,
+            SeriesOutcome::Error {
+                error: "series has no readings".to_string()
+            }
+        );
+
+        let event: Event = serde_json::from_value(json!({
+            "type": "subscribed",
+            "subscription": { "min_severity": "high" },
+        }))
+        .unwrap();
+        assert_eq!(
+            event,
+            Event::Subscribed {
+                subscription: Subscription {
+                    sensors: None,
+                    min_severity: Severity::High,
+                },
+            }
+        );
+    }
+}
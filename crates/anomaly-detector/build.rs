@@ -0,0 +1,8 @@
+// Compiles the gRPC service's proto/anomaly.proto with protox, so building
+// needs no protoc.
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto");
+    let descriptors = protox::compile(["proto/anomaly.proto"], ["proto"])?;
+    tonic_prost_build::configure().compile_fds(descriptors)?;
+    Ok(())
+}
@@ -0,0 +1,799 @@
+//! Role-based access control.
+//!
+//! Callers present an API key or an HS256-signed JWT as
+//! `Authorization: Bearer <credential>` (or an API key as `X-API-Key`), and
+//! each route group requires a role:
+//!
+//! - `read-only`: queries, reads and detection that stores nothing, such as
+//!   `GET /anomalies`, `POST /query` and `POST /analyze`; enough for
+//!   dashboards.
+//! - `operator`: changes to sensors, tags, shadows, incidents, silences and
+//!   routing, and backfills and replays, which store results.
+//! - `admin`: the runtime config, the audit log and the registry's export
+//!   and import, snapshots of the service's whole state.
+//!
+//! Each role includes those before it. API keys are set in the
+//! `[service.api_keys]` table as `<name> = "<role>:<key>"`; a JWT carries its
+//! role in the `jwt_role_claim` claim (default `role`, a role or a list of
+//! them, the highest counting), is signed with `jwt_secret`, must name its
+//! caller in `sub` and is refused without an `exp` or once past it.
+//!
+//! The `[service.rate_limits]` table limits the requests each caller may
+//! make per minute: `default` every caller, and an entry named after a key
+//! or a JWT `sub` that caller alone. A `sub` named like a key takes its entry
+//! from `"sub:<sub>"` instead, and never shares the key's bucket. Each
+//! caller's limit is a token bucket holding a minute's requests and refilled
+//! evenly, so a burst of up to the whole limit passes, and a request past it
+//! is answered 429 with a `Retry-After` in seconds. A bucket left alone for a
+//! minute is full again, and is dropped.
+//!
+//! ```toml
+//! [service]
+//! jwt_secret = "..."
+//!
+//! [service.api_keys]
+//! grafana = "read-only:4f1c..."
+//! ops = "operator:9a7e..."
+//!
+//! [service.rate_limits]
+//! default = 600
+//! grafana = 60
+//! ```
+//!
+//! With neither keys nor a secret configured every request is allowed, as
+//! before, and no rate limit applies. Otherwise only `/health` needs no
+//! credential: `/metrics` and the dashboard's assets need `read-only`, the
+//! assets unless `public_dashboard` is set, since a browser opening the
+//! dashboard cannot present a key; the dashboard then asks for one before
+//! reading the API. Changes are
+//! audited under the caller's key name or JWT `sub` rather than a supplied
+//! `X-Actor` header, and usage is counted against the caller's tenant (see
+//! `usage`) rather than a supplied `X-Tenant`: a JWT's `tenant` claim, else
+//! its `sub`, or the key name up to its first `.`, so keys named
+//! `acme.grafana` and `acme.ops` share tenant `acme`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use ring::{digest, hmac};
+use serde::Deserialize;
+
+use crate::audit::ACTOR_HEADER;
+use crate::usage::{Limit, TENANT_HEADER};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+pub const DEFAULT_ROLE_CLAIM: &str = "role";
+
+/// API keys shorter than this are refused, as too easy to guess.
+const MIN_KEY_LENGTH: usize = 16;
+
+/// How long an empty bucket takes to refill.
+const REFILL: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    ReadOnly,
+    Operator,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, String> {
+        match raw {
+            "read-only" => Ok(Role::ReadOnly),
+            "operator" => Ok(Role::Operator),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!(
+                "unknown role {:?}; roles are read-only, operator and admin",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::ReadOnly => "read-only",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        })
+    }
+}
+
+/// Who a request came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Identity {
+    pub name: String,
+    pub role: Role,
+    pub tenant: String,
+    pub credential: Credential,
+}
+
+/// What a caller presented: an API key, named `name`, or a JWT whose `sub`
+/// is `name`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Credential {
+    Key,
+    Token,
+}
+
+#[derive(Clone, Default)]
+pub struct Access {
+    /// By the SHA-256 of the key, so lookups take no longer for a near miss.
+    keys: HashMap<Vec<u8>, Identity>,
+    jwt: Option<(hmac::Key, String)>,
+    rate_limits: RateLimits,
+    public_dashboard: bool,
+}
+
+impl fmt::Debug for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.keys.values().map(|id| id.name.as_str()).collect();
+        f.debug_struct("Access")
+            .field("keys", &names)
+            .field("jwt", &self.jwt.as_ref().map(|(_, claim)| claim))
+            .field("rate_limits", &self.rate_limits)
+            .field("public_dashboard", &self.public_dashboard)
+            .finish()
+    }
+}
+
+/// Requests per minute by caller, and the buckets they are taken from.
+#[derive(Clone, Default)]
+pub struct RateLimits {
+    default: Option<u64>,
+    callers: BTreeMap<String, u64>,
+    /// The API key names, whose entries a JWT `sub` of the same name does
+    /// not take.
+    keys: BTreeSet<String>,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl fmt::Debug for RateLimits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimits")
+            .field("default", &self.default)
+            .field("callers", &self.callers)
+            .finish()
+    }
+}
+
+/// By `key:<name>` or `sub:<sub>`.
+#[derive(Default)]
+struct Buckets {
+    by_caller: HashMap<String, Bucket>,
+    swept: Option<Instant>,
+}
+
+impl Buckets {
+    /// Drops the buckets idle for a refill, at most once a refill.
+    fn sweep(&mut self, now: Instant) {
+        if self
+            .swept
+            .is_some_and(|swept| now.saturating_duration_since(swept) < REFILL)
+        {
+            return;
+        }
+        self.by_caller
+            .retain(|_, bucket| now.saturating_duration_since(bucket.updated) < REFILL);
+        self.swept = Some(now);
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimits {
+    /// Reads the `[service.rate_limits]` table.
+    pub fn from_table(table: &BTreeMap<String, Limit>) -> Result<Self, String> {
+        let mut limits = RateLimits::default();
+        for (caller, limit) in table {
+            let per_minute = limit
+                .value()
+                .ok()
+                .filter(|limit| *limit > 0)
+                .ok_or_else(|| {
+                    format!(
+                        "rate_limits.{} must be a positive number of requests per minute",
+                        caller
+                    )
+                })?;
+            if caller == "default" {
+                limits.default = Some(per_minute);
+            } else {
+                limits.callers.insert(caller.clone(), per_minute);
+            }
+        }
+        Ok(limits)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.default.is_some() || !self.callers.is_empty()
+    }
+
+    /// Takes a request from `caller`'s bucket at `now`, or returns how long
+    /// until one is refilled.
+    fn take(&self, caller: &Identity, now: Instant) -> Result<(), Duration> {
+        let name = &caller.name;
+        let (bucket, entry) = match caller.credential {
+            Credential::Key => (format!("key:{}", name), self.callers.get(name)),
+            Credential::Token => {
+                let bucket = format!("sub:{}", name);
+                let entry = self.callers.get(&bucket).or_else(|| {
+                    (!self.keys.contains(name))
+                        .then(|| self.callers.get(name))
+                        .flatten()
+                });
+                (bucket, entry)
+            }
+        };
+        let Some(per_minute) = entry.copied().or(self.default) else {
+            return Ok(());
+        };
+        let capacity = per_minute as f64;
+        let per_second = capacity / REFILL.as_secs_f64();
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.sweep(now);
+        let bucket = buckets.by_caller.entry(bucket).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / per_second))
+        }
+    }
+}
+
+fn fingerprint(key: &str) -> Vec<u8> {
+    digest::digest(&digest::SHA256, key.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Roles {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Access {
+    /// Reads the `api_keys` table and the JWT settings.
+    pub fn new(
+        api_keys: &BTreeMap<String, String>,
+        jwt_secret: Option<&str>,
+        jwt_role_claim: &str,
+    ) -> Result<Self, String> {
+        let mut keys = HashMap::new();
+        for (name, entry) in api_keys {
+            let (role, key) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("api_keys.{} must be \"<role>:<key>\"", name))?;
+            let role = role
+                .parse()
+                .map_err(|e| format!("api_keys.{}: {}", name, e))?;
+            if key.len() < MIN_KEY_LENGTH {
+                return Err(format!(
+                    "api_keys.{}: the key must be at least {} characters",
+                    name, MIN_KEY_LENGTH
+                ));
+            }
+            let tenant = name.split('.').next().unwrap_or(name);
+            let identity = Identity {
+                name: name.clone(),
+                role,
+                tenant: tenant.to_string(),
+                credential: Credential::Key,
+            };
+            if keys.insert(fingerprint(key), identity).is_some() {
+                return Err(format!("api_keys.{}: the key is used twice", name));
+            }
+        }
+        let jwt = match jwt_secret {
+            Some(secret) if secret.len() < MIN_KEY_LENGTH => {
+                return Err(format!(
+                    "jwt_secret must be at least {} characters",
+                    MIN_KEY_LENGTH
+                ));
+            }
+            Some(secret) => Some((
+                hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes()),
+                jwt_role_claim.to_string(),
+            )),
+            None => None,
+        };
+        Ok(Self {
+            keys,
+            jwt,
+            rate_limits: RateLimits::default(),
+            public_dashboard: false,
+        })
+    }
+
+    pub fn with_rate_limits(mut self, mut rate_limits: RateLimits) -> Self {
+        rate_limits.keys = self.keys.values().map(|id| id.name.clone()).collect();
+        self.rate_limits = rate_limits;
+        self
+    }
+
+    /// Serves the dashboard's assets without a credential.
+    pub fn with_public_dashboard(mut self, public_dashboard: bool) -> Self {
+        self.public_dashboard = public_dashboard;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || self.jwt.is_some()
+    }
+
+    pub fn public_dashboard(&self) -> bool {
+        self.public_dashboard
+    }
+
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    /// Who presented the credential in `headers`; 401 without a valid one.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<Identity, (StatusCode, String)> {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+        let credential = header(header::AUTHORIZATION.as_str())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| header(API_KEY_HEADER))
+            .map(str::trim)
+            .ok_or((
+                StatusCode::UNAUTHORIZED,
+                "a credential is required: Authorization: Bearer <API key or JWT>".to_string(),
+            ))?;
+        let unauthorized = |reason: &str| (StatusCode::UNAUTHORIZED, reason.to_string());
+        if let Some(identity) = self.keys.get(&fingerprint(credential)) {
+            return Ok(identity.clone());
+        }
+        match &self.jwt {
+            Some(jwt) if credential.matches('.').count() == 2 => self
+                .verify_jwt(jwt, credential)
+                .map_err(|e| unauthorized(&e)),
+            _ => Err(unauthorized("unknown API key")),
+        }
+    }
+
+    fn verify_jwt(
+        &self,
+        (key, claim): &(hmac::Key, String),
+        token: &str,
+    ) -> Result<Identity, String> {
+        let mut parts = token.split('.');
+        let (Some(head), Some(payload), Some(signature)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err("malformed JWT".to_string());
+        };
+        let decode = |part: &str| {
+            URL_SAFE_NO_PAD
+                .decode(part)
+                .map_err(|_| "malformed JWT".to_string())
+        };
+        let signed = &token[..head.len() + 1 + payload.len()];
+        hmac::verify(key, signed.as_bytes(), &decode(signature)?)
+            .map_err(|_| "invalid JWT signature".to_string())?;
+        let head: JwtHeader =
+            serde_json::from_slice(&decode(head)?).map_err(|_| "malformed JWT".to_string())?;
+        if head.alg != "HS256" {
+            return Err(format!(
+                "JWT algorithm {} is not accepted; use HS256",
+                head.alg
+            ));
+        }
+        let claims: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(&decode(payload)?).map_err(|_| "malformed JWT".to_string())?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        // A token without `exp` would be valid forever.
+        let exp = claims
+            .get("exp")
+            .ok_or("the JWT has no exp claim")?
+            .as_u64()
+            .ok_or("the JWT's exp claim is not a time in seconds")?;
+        if exp <= now {
+            return Err("the JWT has expired".to_string());
+        }
+        let roles = claims
+            .get(claim)
+            .and_then(|roles| Roles::deserialize(roles).ok())
+            .map(|roles| match roles {
+                Roles::One(role) => vec![role],
+                Roles::Many(roles) => roles,
+            })
+            .unwrap_or_default();
+        let role = roles
+            .iter()
+            .filter_map(|role| role.parse::<Role>().ok())
+            .max()
+            .ok_or_else(|| format!("the JWT has no role in its {:?} claim", claim))?;
+        let claim = |name: &str| claims.get(name).and_then(|value| value.as_str());
+        // Tokens without a `sub` would share one identity, tenant and bucket.
+        let name = claim("sub")
+            .filter(|sub| !sub.trim().is_empty())
+            .ok_or("the JWT has no sub claim")?
+            .to_string();
+        let tenant = claim("tenant").unwrap_or(&name).to_string();
+        Ok(Identity {
+            name,
+            role,
+            tenant,
+            credential: Credential::Token,
+        })
+    }
+}
+
+fn forbidden(identity: &Identity, role: Role) -> (StatusCode, String) {
+    (
+        StatusCode::FORBIDDEN,
+        format!(
+            "{} may not do this; it needs the {} role",
+            identity.name, role
+        ),
+    )
+}
+
+/// Whole seconds to wait, at least one.
+fn retry_seconds(retry_after: Duration) -> u64 {
+    retry_after.as_secs_f64().ceil().max(1.0) as u64
+}
+
+fn over_limit(identity: &Identity, seconds: u64) -> (StatusCode, String) {
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        format!(
+            "{} is over its rate limit; retry in {}s",
+            identity.name, seconds
+        ),
+    )
+}
+
+impl Access {
+    /// The caller of `headers` if it may act as `role` now, as [`authorize`]
+    /// checks it, or `None` without access control.
+    pub fn admit(
+        &self,
+        headers: &HeaderMap,
+        role: Role,
+    ) -> Result<Option<Identity>, (StatusCode, String)> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let identity = self.authenticate(headers)?;
+        if identity.role < role {
+            return Err(forbidden(&identity, role));
+        }
+        if let Err(retry_after) = self.rate_limits.take(&identity, Instant::now()) {
+            return Err(over_limit(&identity, retry_seconds(retry_after)));
+        }
+        Ok(Some(identity))
+    }
+}
+
+/// Names `identity` in the headers audited changes and usage are recorded
+/// under.
+pub fn stamp(headers: &mut HeaderMap, identity: &Identity) {
+    for (name, value) in [
+        (ACTOR_HEADER, &identity.name),
+        (TENANT_HEADER, &identity.tenant),
+    ] {
+        match HeaderValue::from_str(value) {
+            Ok(value) => headers.insert(name, value),
+            Err(_) => headers.remove(name),
+        };
+    }
+}
+
+/// Middleware for a route group requiring `role`.
+pub async fn authorize(
+    State((access, role)): State<(Arc<Access>, Role)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if !access.is_enabled() {
+        return next.run(request).await;
+    }
+    let identity = match access.authenticate(request.headers()) {
+        Ok(identity) => identity,
+        Err((status, message)) => {
+            return (status, [(header::WWW_AUTHENTICATE, "Bearer")], message).into_response();
+        }
+    };
+    // The identity travels on the response too, for `request_log`.
+    let mut response = if identity.role < role {
+        forbidden(&identity, role).into_response()
+    } else if let Err(retry_after) = access.rate_limits.take(&identity, Instant::now()) {
+        let seconds = retry_seconds(retry_after);
+        let retry_after = [(header::RETRY_AFTER, seconds.to_string())];
+        (retry_after, over_limit(&identity, seconds)).into_response()
+    } else {
+        // Audited changes and usage name the authenticated caller.
+        stamp(request.headers_mut(), &identity);
+        request.extensions_mut().insert(identity.clone());
+        next.run(request).await
+    };
+    response.extensions_mut().insert(identity);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef-secret";
+
+    /// An `exp` far in the future.
+    const LATER: u64 = 4_102_444_800;
+
+    fn access() -> Access {
+        let keys = BTreeMap::from([
+            (
+                "grafana".to_string(),
+                "read-only:grafana-key-0123456789".to_string(),
+            ),
+            (
+                "acme.ops".to_string(),
+                "operator:ops-key-0123456789abcdef".to_string(),
+            ),
+        ]);
+        Access::new(&keys, Some(SECRET), DEFAULT_ROLE_CLAIM).unwrap()
+    }
+
+    fn authorize(
+        access: &Access,
+        headers: &HeaderMap,
+        role: Role,
+    ) -> Result<Identity, (StatusCode, String)> {
+        let identity = access.authenticate(headers)?;
+        if identity.role < role {
+            return Err(forbidden(&identity, role));
+        }
+        Ok(identity)
+    }
+
+    fn bearer(credential: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {}", credential).parse().unwrap(),
+        );
+        headers
+    }
+
+    fn jwt(secret: &str, alg: &str, claims: serde_json::Value) -> String {
+        let head =
+            URL_SAFE_NO_PAD.encode(serde_json::json!({ "alg": alg, "typ": "JWT" }).to_string());
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let signed = format!("{}.{}", head, payload);
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(hmac::sign(&key, signed.as_bytes()));
+        format!("{}.{}", signed, signature)
+    }
+
+    #[test]
+    fn test_api_keys_grant_their_role() {
+        let access = access();
+        let grafana = bearer("grafana-key-0123456789");
+        assert_eq!(
+            authorize(&access, &grafana, Role::ReadOnly).unwrap().name,
+            "grafana"
+        );
+        let denied = authorize(&access, &grafana, Role::Operator).unwrap_err();
+        assert_eq!(denied.0, StatusCode::FORBIDDEN);
+
+        let mut ops = HeaderMap::new();
+        ops.insert(API_KEY_HEADER, "ops-key-0123456789abcdef".parse().unwrap());
+        let identity = authorize(&access, &ops, Role::Operator).unwrap();
+        assert_eq!(
+            (identity.role, identity.tenant.as_str()),
+            (Role::Operator, "acme")
+        );
+        assert_eq!(
+            authorize(&access, &ops, Role::Admin).unwrap_err().0,
+            StatusCode::FORBIDDEN
+        );
+
+        for headers in [HeaderMap::new(), bearer("guessed-key-0123456789")] {
+            let rejected = authorize(&access, &headers, Role::ReadOnly).unwrap_err();
+            assert_eq!(rejected.0, StatusCode::UNAUTHORIZED);
+        }
+    }
+
+    #[test]
+    fn test_jwt_claims() {
+        let access = access();
+        let token = jwt(
+            SECRET,
+            "HS256",
+            serde_json::json!({ "sub": "alice", "role": ["read-only", "admin"], "exp": LATER }),
+        );
+        assert_eq!(
+            authorize(&access, &bearer(&token), Role::Admin).unwrap(),
+            Identity {
+                name: "alice".to_string(),
+                role: Role::Admin,
+                tenant: "alice".to_string(),
+                credential: Credential::Token,
+            }
+        );
+
+        let rejected = [
+            (
+                "another-secret-0123456789",
+                "HS256",
+                serde_json::json!({ "sub": "bob", "role": "admin", "exp": LATER }),
+                "signature",
+            ),
+            (
+                SECRET,
+                "none",
+                serde_json::json!({ "sub": "bob", "role": "admin", "exp": LATER }),
+                "algorithm",
+            ),
+            (
+                SECRET,
+                "HS256",
+                serde_json::json!({ "sub": "bob", "role": "admin", "exp": 1 }),
+                "expired",
+            ),
+            (
+                SECRET,
+                "HS256",
+                serde_json::json!({ "sub": "bob", "role": "superuser", "exp": LATER }),
+                "no role",
+            ),
+            (
+                SECRET,
+                "HS256",
+                serde_json::json!({ "role": "admin", "exp": LATER }),
+                "no sub",
+            ),
+            (
+                SECRET,
+                "HS256",
+                serde_json::json!({ "sub": "bob", "role": "admin" }),
+                "no exp",
+            ),
+        ];
+        for (secret, alg, claims, reason) in rejected {
+            let token = jwt(secret, alg, claims);
+            let rejection = authorize(&access, &bearer(&token), Role::ReadOnly).unwrap_err();
+            assert_eq!(rejection.0, StatusCode::UNAUTHORIZED, "{}", rejection.1);
+            assert!(rejection.1.contains(reason), "{}", rejection.1);
+        }
+    }
+
+    fn caller(name: &str, credential: Credential) -> Identity {
+        Identity {
+            name: name.to_string(),
+            role: Role::ReadOnly,
+            tenant: name.to_string(),
+            credential,
+        }
+    }
+
+    #[test]
+    fn test_rate_limits_refill_per_caller() {
+        let table = BTreeMap::from([
+            ("default".to_string(), Limit::Number(120)),
+            ("grafana".to_string(), Limit::Text("2".to_string())),
+        ]);
+        let limits = RateLimits::from_table(&table).unwrap();
+        let grafana = caller("grafana", Credential::Key);
+        let ops = caller("ops", Credential::Key);
+        let start = Instant::now();
+        assert!(limits.take(&grafana, start).is_ok());
+        assert!(limits.take(&grafana, start).is_ok());
+        // Two a minute refill one every 30 seconds.
+        let retry = limits.take(&grafana, start + Duration::from_secs(10));
+        assert_eq!(retry, Err(Duration::from_secs(20)));
+        assert!(
+            limits
+                .take(&grafana, start + Duration::from_secs(30))
+                .is_ok()
+        );
+        // Other callers have buckets of their own, of the default size.
+        assert!((0..120).all(|_| limits.take(&ops, start).is_ok()));
+        assert!(limits.take(&ops, start).is_err());
+
+        let unlimited = RateLimits::default();
+        assert!((0..1_000).all(|_| unlimited.take(&ops, start).is_ok()));
+        for invalid in [Limit::Number(0), Limit::Text("fast".to_string())] {
+            let table = BTreeMap::from([("default".to_string(), invalid)]);
+            assert!(
+                RateLimits::from_table(&table).is_err_and(|e| e.contains("rate_limits.default"))
+            );
+        }
+    }
+
+    #[test]
+    fn test_rate_limits_keep_keys_and_subjects_apart_and_drop_idle_buckets() {
+        let table = BTreeMap::from([
+            ("default".to_string(), Limit::Number(60)),
+            ("grafana".to_string(), Limit::Number(1)),
+            ("sub:grafana".to_string(), Limit::Number(2)),
+            ("alice".to_string(), Limit::Number(3)),
+        ]);
+        let limits = access().with_rate_limits(RateLimits::from_table(&table).unwrap());
+        let limits = limits.rate_limits();
+        let start = Instant::now();
+        let taken = |identity: &Identity| {
+            (0..10)
+                .take_while(|_| limits.take(identity, start).is_ok())
+                .count()
+        };
+        // A token whose `sub` names a key has its own bucket and entry.
+        assert_eq!(taken(&caller("grafana", Credential::Key)), 1);
+        assert_eq!(taken(&caller("grafana", Credential::Token)), 2);
+        assert_eq!(taken(&caller("alice", Credential::Token)), 3);
+        // A key without an entry of its own takes the default.
+        assert_eq!(taken(&caller("acme.ops", Credential::Key)), 10);
+
+        for n in 0..1_000 {
+            let subject = caller(&format!("user-{}", n), Credential::Token);
+            assert!(limits.take(&subject, start).is_ok());
+        }
+        assert_eq!(limits.buckets.lock().unwrap().by_caller.len(), 1_004);
+        // A minute on, every bucket is full again and only the new one is kept.
+        let later = start + REFILL;
+        assert!(
+            limits
+                .take(&caller("bob", Credential::Token), later)
+                .is_ok()
+        );
+        assert_eq!(limits.buckets.lock().unwrap().by_caller.len(), 1);
+        assert!(
+            limits
+                .take(&caller("grafana", Credential::Key), later)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let keys = |entry: &str| BTreeMap::from([("key".to_string(), entry.to_string())]);
+        assert!(Access::new(&keys("root:0123456789abcdef"), None, DEFAULT_ROLE_CLAIM).is_err());
+        assert!(Access::new(&keys("admin:short"), None, DEFAULT_ROLE_CLAIM).is_err());
+        assert!(Access::new(&keys("0123456789abcdef"), None, DEFAULT_ROLE_CLAIM).is_err());
+        assert!(Access::new(&BTreeMap::new(), Some("short"), DEFAULT_ROLE_CLAIM).is_err());
+        assert!(
+            !Access::new(&BTreeMap::new(), None, DEFAULT_ROLE_CLAIM)
+                .unwrap()
+                .is_enabled()
+        );
+    }
+}
@@ -0,0 +1,230 @@
+//! Per-sensor activity in bounded memory, for fleets with millions of
+//! sensor ids.
+//!
+//! Every analyzed series of a named sensor is counted in a count-min sketch
+//! and a HyperLogLog, both fixed in size. Per-sensor state, the
+//! `anomaly_sensor_*` metrics, is only kept for the `max_tracked_sensors`
+//! sensors seen most recently, and is dropped for a sensor idle for
+//! `sensor_idle_secs`. `GET /sensors/activity?ids=1,2` reads the sketches.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use detection_core::sketch::{CountMinSketch, HyperLogLog};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::config::Tracking;
+
+/// Sensors ordered by when they were last seen.
+#[derive(Default)]
+pub struct Recent {
+    /// Each sensor's place in `order` and when it was seen.
+    seen: HashMap<i64, (u64, Instant)>,
+    order: BTreeMap<u64, i64>,
+    next: u64,
+}
+
+impl Recent {
+    pub fn touch(&mut self, sensor_id: i64, now: Instant) {
+        if let Some((place, _)) = self.seen.remove(&sensor_id) {
+            self.order.remove(&place);
+        }
+        self.next += 1;
+        self.seen.insert(sensor_id, (self.next, now));
+        self.order.insert(self.next, sensor_id);
+    }
+
+    /// Drops the least recently seen sensors past `limits`.
+    pub fn evict(&mut self, now: Instant, limits: &Tracking) -> Vec<i64> {
+        let mut evicted = Vec::new();
+        while let Some((_, &sensor_id)) = self.order.first_key_value() {
+            let (_, seen) = self.seen[&sensor_id];
+            if self.seen.len() <= limits.max_sensors
+                && now.saturating_duration_since(seen) < limits.idle
+            {
+                break;
+            }
+            self.order.pop_first();
+            self.seen.remove(&sensor_id);
+            evicted.push(sensor_id);
+        }
+        evicted
+    }
+}
+
+struct Sketches {
+    series: CountMinSketch,
+    distinct: HyperLogLog,
+    recent: Recent,
+}
+
+pub struct Activity {
+    limits: Tracking,
+    state: Mutex<Sketches>,
+}
+
+impl Default for Activity {
+    fn default() -> Self {
+        Self::new(Tracking::default())
+    }
+}
+
+impl Activity {
+    pub fn new(limits: Tracking) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(Sketches {
+                series: CountMinSketch::default(),
+                distinct: HyperLogLog::default(),
+                recent: Recent::default(),
+            }),
+        }
+    }
+
+    /// Counts a series of `sensor_id`, returning the sensors whose state is
+    /// to be dropped.
+    pub fn observe(&self, sensor_id: i64, now: Instant) -> Vec<i64> {
+        let mut state = self.state.lock().unwrap();
+        state.series.add(&sensor_id, 1);
+        state.distinct.insert(&sensor_id);
+        state.recent.touch(sensor_id, now);
+        state.recent.evict(now, &self.limits)
+    }
+
+    /// The sensors idle for too long, dropped from tracking.
+    pub fn sweep(&self, now: Instant) -> Vec<i64> {
+        self.state.lock().unwrap().recent.evict(now, &self.limits)
+    }
+
+    /// Estimated distinct sensors seen, and how many are tracked.
+    pub fn sensors(&self) -> (f64, usize) {
+        let state = self.state.lock().unwrap();
+        (state.distinct.estimate(), state.recent.seen.len())
+    }
+
+    /// Estimated series analyzed for `sensor_id`, never fewer than were.
+    pub fn series(&self, sensor_id: i64) -> u64 {
+        self.state.lock().unwrap().series.estimate(&sensor_id)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ActivityQuery {
+    /// Comma-separated sensor ids.
+    ids: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ActivityResponse {
+    distinct_sensors: u64,
+    tracked_sensors: usize,
+    sensors: Vec<SensorActivity>,
+}
+
+#[derive(Debug, Serialize)]
+struct SensorActivity {
+    sensor_id: i64,
+    series: u64,
+}
+
+pub async fn activity(
+    State(state): State<AppState>,
+    Query(query): Query<ActivityQuery>,
+) -> Result<Json<ActivityResponse>, (StatusCode, String)> {
+    let ids = query
+        .ids
+        .iter()
+        .flat_map(|raw| raw.split(','))
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| {
+            id.trim().parse().map_err(|_| {
+                (
+                    StatusCode::BAD_REQUEST,
+                    format!("invalid sensor id {:?}", id),
+                )
+            })
+        })
+        .collect::<Result<Vec<i64>, _>>()?;
+    let activity = state.metrics.activity();
+    let (distinct, tracked) = activity.sensors();
+    Ok(Json(ActivityResponse {
+        distinct_sensors: distinct.round() as u64,
+        tracked_sensors: tracked,
+        sensors: ids
+            .into_iter()
+            .map(|sensor_id| SensorActivity {
+                sensor_id,
+                series: activity.series(sensor_id),
+            })
+            .collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use detection_core::Severity;
+
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recent_and_idle_sensors() {
+        let activity = Activity::new(Tracking {
+            max_sensors: 2,
+            idle: Duration::from_secs(60),
+        });
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(activity.observe(1, at(0)).is_empty());
+        assert!(activity.observe(2, at(1)).is_empty());
+        assert!(activity.observe(1, at(2)).is_empty());
+        assert_eq!(activity.observe(3, at(3)), [2]);
+        assert_eq!(activity.sweep(at(61)), Vec::<i64>::new());
+        assert_eq!(activity.sweep(at(62)), [1]);
+        assert_eq!(activity.sweep(at(63)), [3]);
+
+        let (distinct, tracked) = activity.sensors();
+        assert_eq!((distinct.round(), tracked), (3.0, 0));
+        assert_eq!(activity.series(1), 2);
+        assert_eq!(activity.series(4), 0);
+    }
+
+    #[tokio::test]
+    async fn test_activity_reports_sketched_counts() {
+        let state = AppState::default();
+        state.metrics.observe_sensor(7, [], None);
+        state
+            .metrics
+            .observe_sensor(7, [Severity::High], Some((1.0, 0.5)));
+        state.metrics.observe_sensor(8, [], None);
+
+        let Json(response) = activity(
+            State(state),
+            Query(ActivityQuery {
+                ids: Some("7, 9".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            (response.distinct_sensors, response.tracked_sensors),
+            (2, 2)
+        );
+        let series: Vec<(i64, u64)> = response
+            .sensors
+            .iter()
+            .map(|s| (s.sensor_id, s.series))
+            .collect();
+        assert_eq!(series, [(7, 2), (9, 0)]);
+    }
+}
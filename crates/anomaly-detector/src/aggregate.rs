@@ -0,0 +1,308 @@
+//! Per-window aggregates of the readings of an `/analyze` request, so
+//! dashboards need not fold the raw readings themselves.
+//!
+//! With `window` set to a width such as `30s`, `5m`, `1h` or `1d`, the
+//! response carries `windows`: for each window holding readings, aligned
+//! to multiples of the width since the Unix epoch and in time order, its
+//! `start` and `end`, the `count`, `min`, `max` and `mean` of its readings
+//! and the `anomaly_count` among them. When the readings name their
+//! sensors, each sensor's readings are aggregated apart, under its entry of
+//! `sensors`.
+//!
+//! The readings' timestamps are then parsed as RFC 3339, e.g.
+//! `2026-01-19T10:00:00.250+01:00`; a space may stand for the `T`, and one
+//! without an offset is taken as UTC, as the service stores them.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::time::{format_timestamp, parse_timestamp};
+use crate::validation::ApiError;
+use crate::{Anomaly, Reading};
+
+/// Widest window accepted, a year.
+const MAX_WINDOW_SECONDS: i64 = 366 * 86_400;
+
+/// Seconds in a window width such as `90s`, `5m`, `1h` or `1d`, or what is
+/// wrong with it.
+pub fn parse_window(raw: &str) -> Result<i64, String> {
+    let invalid = || {
+        format!(
+            "must be a positive number of s, m, h or d, e.g. \"5m\", got {:?}",
+            raw
+        )
+    };
+    let split = raw.len().checked_sub(1).ok_or_else(invalid)?;
+    let (count, unit) = raw.split_at_checked(split).ok_or_else(invalid)?;
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        _ => return Err(invalid()),
+    };
+    let seconds = count
+        .parse::<i64>()
+        .ok()
+        .filter(|count| *count > 0)
+        .and_then(|count| count.checked_mul(unit))
+        .ok_or_else(invalid)?;
+    if seconds > MAX_WINDOW_SECONDS {
+        return Err(format!("must be at most 366d, got {:?}", raw));
+    }
+    Ok(seconds)
+}
+
+/// Whole seconds since the Unix epoch of an RFC 3339 timestamp.
+pub fn parse_rfc3339(raw: &str) -> Option<i64> {
+    let seconds = parse_timestamp(raw.get(..19)?)?;
+    let rest = &raw[19..];
+    let rest = match rest.strip_prefix('.') {
+        Some(fraction) => {
+            let digits = fraction
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(fraction.len());
+            if digits == 0 {
+                return None;
+            }
+            &fraction[digits..]
+        }
+        None => rest,
+    };
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.as_bytes()[0] {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let (hours, minutes) = rest[1..].split_once(':')?;
+            if hours.len() != 2 || minutes.len() != 2 {
+                return None;
+            }
+            let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 3_600 + minutes * 60)
+        }
+    };
+    Some(seconds - offset)
+}
+
+/// The aggregates of one window.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WindowAggregate {
+    pub start: String,
+    pub end: String,
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub anomaly_count: usize,
+}
+
+struct Totals {
+    count: usize,
+    min: f64,
+    max: f64,
+    sum: f64,
+    anomalies: usize,
+}
+
+/// Readings folded into windows per sensor, waiting for the anomalies among
+/// them.
+pub struct Windows {
+    width: i64,
+    sensors: BTreeMap<Option<i64>, BTreeMap<i64, Totals>>,
+}
+
+impl Windows {
+    /// Folds `readings` into windows of `window`, e.g. `5m`, apart for each
+    /// sensor; readings naming none belong to `sensor_id`.
+    pub fn of(
+        window: &str,
+        sensor_id: Option<i64>,
+        readings: &[Reading],
+    ) -> Result<Self, ApiError> {
+        let width = parse_window(window).map_err(|e| ApiError::invalid("window", e))?;
+        let mut sensors: BTreeMap<Option<i64>, BTreeMap<i64, Totals>> = BTreeMap::new();
+        for (i, reading) in readings.iter().enumerate() {
+            let seconds = parse_rfc3339(&reading.timestamp).ok_or_else(|| {
+                ApiError::invalid(
+                    format!("readings[{}].timestamp", i),
+                    format_args!("must be RFC 3339, got {:?}", reading.timestamp),
+                )
+            })?;
+            sensors
+                .entry(reading.sensor_id.or(sensor_id))
+                .or_default()
+                .entry(seconds.div_euclid(width))
+                .and_modify(|totals| {
+                    totals.count += 1;
+                    totals.min = totals.min.min(reading.value);
+                    totals.max = totals.max.max(reading.value);
+                    totals.sum += reading.value;
+                })
+                .or_insert(Totals {
+                    count: 1,
+                    min: reading.value,
+                    max: reading.value,
+                    sum: reading.value,
+                    anomalies: 0,
+                });
+        }
+        Ok(Self { width, sensors })
+    }
+
+    /// The windows of `sensor_id`'s readings in time order, counting
+    /// `anomalies` in them.
+    pub fn finish(
+        &mut self,
+        sensor_id: Option<i64>,
+        anomalies: &[Anomaly],
+    ) -> Vec<WindowAggregate> {
+        let mut totals = self.sensors.remove(&sensor_id).unwrap_or_default();
+        for anomaly in anomalies {
+            let index = parse_rfc3339(&anomaly.timestamp).map(|s| s.div_euclid(self.width));
+            if let Some(totals) = index.and_then(|index| totals.get_mut(&index)) {
+                totals.anomalies += 1;
+            }
+        }
+        let stamp = |seconds: i64| format!("{}Z", format_timestamp(seconds));
+        totals
+            .into_iter()
+            .map(|(index, totals)| WindowAggregate {
+                start: stamp(index * self.width),
+                end: stamp((index + 1) * self.width),
+                count: totals.count,
+                min: totals.min,
+                max: totals.max,
+                mean: totals.sum / totals.count as f64,
+                anomaly_count: totals.anomalies,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rfc3339() {
+        let utc = parse_rfc3339("2026-01-19T10:00:00Z").unwrap();
+        assert_eq!(parse_rfc3339("2026-01-19 10:00:00"), Some(utc));
+        assert_eq!(parse_rfc3339("2026-01-19T10:00:00.999z"), Some(utc));
+        assert_eq!(parse_rfc3339("2026-01-19T11:30:00+01:30"), Some(utc));
+        assert_eq!(parse_rfc3339("2026-01-19T09:00:00-01:00"), Some(utc));
+        // Leap days parse, in the years that have them.
+        assert!(parse_rfc3339("2028-02-29T10:00:00Z").is_some());
+        assert!(parse_rfc3339("2000-02-29T10:00:00Z").is_some());
+        for invalid in [
+            "2026-01-19",
+            "2026-01-19T10:00:00.Z",
+            "2026-01-19T10:00:00+1:00",
+            "2026-01-19T10:00:00 UTC",
+            "2026-13-19T10:00:00Z",
+            "2026-02-29T10:00:00Z",
+            "2026-02-31T10:00:00Z",
+            "2026-04-31T10:00:00Z",
+            "2100-02-29T10:00:00Z",
+        ] {
+            assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_parse_window() {
+        assert_eq!(parse_window("90s"), Ok(90));
+        assert_eq!(parse_window("5m"), Ok(300));
+        assert_eq!(parse_window("1h"), Ok(3_600));
+        assert_eq!(parse_window("2d"), Ok(172_800));
+        for invalid in ["", "m", "0m", "-5m", "5", "5w", "1.5h", "400d"] {
+            assert!(parse_window(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_readings_and_anomalies_are_folded_per_window() {
+        let reading = |id, value, timestamp: &str| Reading {
+            id,
+            value,
+            timestamp: timestamp.to_string(),
+            sensor_id: None,
+        };
+        let readings = [
+            reading(1, 10.0, "2026-01-19T10:01:00Z"),
+            reading(2, 14.0, "2026-01-19T11:04:59+01:00"),
+            reading(3, 40.0, "2026-01-19T10:07:30Z"),
+        ];
+        let anomaly = Anomaly {
+            id: 3,
+            value: 40.0,
+            timestamp: readings[2].timestamp.clone(),
+            z_score: 4.0,
+            severity: detection_core::Severity::High,
+            labels: None,
+        };
+
+        let windows = Windows::of("5m", None, &readings)
+            .unwrap()
+            .finish(None, &[anomaly]);
+
+        assert_eq!(
+            windows,
+            [
+                WindowAggregate {
+                    start: "2026-01-19T10:00:00Z".to_string(),
+                    end: "2026-01-19T10:05:00Z".to_string(),
+                    count: 2,
+                    min: 10.0,
+                    max: 14.0,
+                    mean: 12.0,
+                    anomaly_count: 0,
+                },
+                WindowAggregate {
+                    start: "2026-01-19T10:05:00Z".to_string(),
+                    end: "2026-01-19T10:10:00Z".to_string(),
+                    count: 1,
+                    min: 40.0,
+                    max: 40.0,
+                    mean: 40.0,
+                    anomaly_count: 1,
+                },
+            ]
+        );
+        let invalid = [reading(4, 1.0, "yesterday")];
+        let error = Windows::of("5m", None, &invalid).err().unwrap();
+        assert_eq!(error.field.as_deref(), Some("readings[0].timestamp"));
+        let error = Windows::of("5w", None, &readings).err().unwrap();
+        assert_eq!(error.field.as_deref(), Some("window"));
+    }
+
+    #[test]
+    fn test_each_sensor_has_windows_of_its_own() {
+        let reading = |id, sensor_id, value| Reading {
+            id,
+            value,
+            timestamp: "2026-01-19T10:01:00Z".to_string(),
+            sensor_id,
+        };
+        let readings = [
+            reading(1, Some(7), 10.0),
+            reading(2, None, 20.0),
+            reading(3, Some(7), 30.0),
+        ];
+        let mut windows = Windows::of("5m", Some(3), &readings).unwrap();
+
+        let means = |windows: Vec<WindowAggregate>| -> Vec<(usize, f64)> {
+            windows.iter().map(|w| (w.count, w.mean)).collect()
+        };
+        assert_eq!(means(windows.finish(Some(7), &[])), [(2, 20.0)]);
+        assert_eq!(means(windows.finish(Some(3), &[])), [(1, 20.0)]);
+        assert!(windows.finish(Some(9), &[]).is_empty());
+    }
+}
@@ -0,0 +1,284 @@
+//! Query API over anomalies recorded in storage.
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use detection_core::Severity;
+use futures_util::stream;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::export;
+use crate::storage::{AnomalyFilter, Storage, StoredAnomaly};
+
+const DEFAULT_LIMIT: i64 = 1_000;
+const MAX_LIMIT: i64 = 10_000;
+
+/// Rows fetched per query while streaming CSV.
+const CSV_PAGE_SIZE: i64 = 10_000;
+
+#[derive(Deserialize)]
+pub struct AnomalyQuery {
+    sensor_id: Option<i64>,
+    start: Option<String>,
+    end: Option<String>,
+    /// Only return anomalies of this severity or above.
+    min_severity: Option<Severity>,
+    /// Only return anomalies with an id greater than this (cursor pagination).
+    #[serde(default)]
+    after_id: i64,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnomalyPage {
+    anomalies: Vec<StoredAnomaly>,
+    /// Pass as `after_id` to fetch the next page; absent on the last page.
+    next_after_id: Option<i64>,
+}
+
+/// Lists stored anomalies as JSON pages, or as a CSV stream of every match
+/// when the client sends `Accept: text/csv`.
+pub async fn list_anomalies(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<AnomalyQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let storage = configured(&state)?;
+
+    let filter = build_filter(&storage, &query)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "start and end must be valid timestamps".to_string(),
+        ))?;
+
+    if export::wants_csv(&headers) {
+        return Ok(export::csv_response(csv_stream(
+            storage,
+            filter,
+            query.after_id,
+            query.limit,
+        )));
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let anomalies = storage
+        .list_anomalies(&filter, query.after_id, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let next_after_id = if anomalies.len() as i64 == limit {
+        anomalies.last().map(|a| a.id)
+    } else {
+        None
+    };
+
+    Ok(Json(AnomalyPage {
+        anomalies,
+        next_after_id,
+    })
+    .into_response())
+}
+
+/// One stored anomaly.
+pub async fn get_anomaly(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<StoredAnomaly>, (StatusCode, String)> {
+    let storage = configured(&state)?;
+    storage
+        .anomalies_by_id(&[id])
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .pop()
+        .map(Json)
+        .ok_or((StatusCode::NOT_FOUND, format!("no anomaly with id {}", id)))
+}
+
+fn configured(state: &AppState) -> Result<Storage, (StatusCode, String)> {
+    state.storage.clone().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+async fn build_filter(
+    storage: &Storage,
+    query: &AnomalyQuery,
+) -> Result<Option<AnomalyFilter>, sqlx::Error> {
+    let mut filter = AnomalyFilter {
+        sensor_id: query.sensor_id,
+        min_severity: query.min_severity,
+        ..AnomalyFilter::default()
+    };
+    for (raw, bound) in [
+        (&query.start, &mut filter.start),
+        (&query.end, &mut filter.end),
+    ] {
+        if let Some(raw) = raw {
+            match storage.normalize_timestamp(raw).await? {
+                Some(ts) => *bound = Some(ts),
+                None => return Ok(None),
+            }
+        }
+    }
+    Ok(Some(filter))
+}
+
+/// Streams matching anomalies as CSV one page at a time, so exports of any
+/// size use bounded memory.
+fn csv_stream(storage: Storage, filter: AnomalyFilter, after_id: i64, limit: Option<i64>) -> Body {
+    struct Cursor {
+        after_id: i64,
+        remaining: Option<i64>,
+        first: bool,
+        done: bool,
+    }
+
+    let cursor = Cursor {
+        after_id,
+        remaining: limit,
+        first: true,
+        done: false,
+    };
+
+    Body::from_stream(stream::unfold(cursor, move |mut cursor| {
+        let storage = storage.clone();
+        let filter = filter.clone();
+        async move {
+            if cursor.done {
+                return None;
+            }
+            let page_size = cursor
+                .remaining
+                .map_or(CSV_PAGE_SIZE, |r| r.min(CSV_PAGE_SIZE));
+            let page = match storage
+                .list_anomalies(&filter, cursor.after_id, page_size)
+                .await
+            {
+                Ok(page) => page,
+                Err(e) => {
+                    cursor.done = true;
+                    return Some((Err(std::io::Error::other(e)), cursor));
+                }
+            };
+
+            cursor.done = (page.len() as i64) < page_size;
+            if let Some(remaining) = cursor.remaining.as_mut() {
+                *remaining -= page.len() as i64;
+                cursor.done |= *remaining <= 0;
+            }
+            if let Some(last) = page.last() {
+                cursor.after_id = last.id;
+            }
+
+            let chunk = export::encode(&page, cursor.first)
+                .map(Bytes::from)
+                .map_err(std::io::Error::other);
+            cursor.first = false;
+            Some((chunk, cursor))
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{in_memory, insert_anomaly};
+    use axum::http::{HeaderValue, header};
+
+    fn query(sensor_id: Option<i64>, limit: Option<i64>) -> AnomalyQuery {
+        AnomalyQuery {
+            sensor_id,
+            start: None,
+            end: None,
+            min_severity: None,
+            after_id: 0,
+            limit,
+        }
+    }
+
+    async fn seeded_state() -> AppState {
+        let storage = in_memory().await;
+        for minute in 0..3 {
+            let ts = format!("2026-01-19 10:0{}:00.000000", minute);
+            insert_anomaly(&storage, 1, &ts).await;
+        }
+        insert_anomaly(&storage, 2, "2026-01-19 10:00:00.000000").await;
+        AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        }
+    }
+
+    async fn body_string(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_anomalies_json_paginates() {
+        let state = seeded_state().await;
+
+        let response = list_anomalies(
+            State(state),
+            HeaderMap::new(),
+            Query(query(Some(1), Some(2))),
+        )
+        .await
+        .unwrap();
+        let page: serde_json::Value = serde_json::from_str(&body_string(response).await).unwrap();
+
+        assert_eq!(page["anomalies"].as_array().unwrap().len(), 2);
+        assert_eq!(page["next_after_id"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_list_anomalies_csv_streams_all_pages() {
+        let state = seeded_state().await;
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static("text/csv"));
+
+        let response = list_anomalies(State(state), headers, Query(query(Some(1), None)))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            export::CSV_CONTENT_TYPE
+        );
+
+        let body = body_string(response).await;
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("id,reading_id,sensor_id,value,timestamp"));
+    }
+
+    #[tokio::test]
+    async fn test_get_anomaly_by_id() {
+        let state = seeded_state().await;
+        let Json(anomaly) = get_anomaly(State(state.clone()), Path(4)).await.unwrap();
+        assert_eq!((anomaly.id, anomaly.sensor_id), (4, 2));
+
+        let missing = get_anomaly(State(state), Path(99)).await.unwrap_err();
+        assert_eq!(missing.0, StatusCode::NOT_FOUND);
+        let unconfigured = get_anomaly(State(AppState::default()), Path(4)).await;
+        assert_eq!(unconfigured.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_list_anomalies_rejects_invalid_bounds() {
+        let state = seeded_state().await;
+        let mut query = query(None, None);
+        query.start = Some("not a time".to_string());
+
+        let result = list_anomalies(State(state), HeaderMap::new(), Query(query)).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,232 @@
+//! Scheduled export of rows to Parquet before retention deletes them.
+//!
+//! With an `[service.archive]` table, readings and anomalies older than
+//! `after_days` are exported on the cron-style `schedule` (see `schedule`)
+//! to the directory or object store at `url`, `file:///path` or
+//! `s3://bucket/prefix`, with store options as for `export`. Each run writes
+//! `readings/<until>.parquet` and `anomalies/<until>.parquet`, holding the
+//! rows since the previous run's cutoff; a kind with no new rows writes
+//! nothing. The cutoff of each kind is kept in the database, so a failed run
+//! is retried whole by the next, and retention deletes only rows archived.
+//!
+//! ```toml
+//! [service.archive]
+//! url = "s3://archive/factory"
+//! schedule = "30 2 * * *"
+//! after_days = 30
+//! ```
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::config::ArchivePolicy;
+use crate::leader::Lease;
+use crate::object_export::{ExportFormat, ExportRequest, ObjectExporter};
+use crate::storage::{AnomalyFilter, SensorReading, Storage};
+
+pub const READINGS: &str = "readings";
+pub const ANOMALIES: &str = "anomalies";
+
+/// Rows fetched per page while exporting.
+const PAGE_SIZE: i64 = 5_000;
+
+/// How long a run may take before another replica may start one.
+const LEASE_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ArchiveReport {
+    pub readings: usize,
+    pub anomalies: usize,
+    pub objects: Vec<String>,
+}
+
+/// Exports the readings and anomalies older than `after` not yet archived.
+pub async fn run(
+    storage: &Storage,
+    exporter: &ObjectExporter,
+    after: Duration,
+) -> Result<ArchiveReport, String> {
+    let until = storage.cutoff(after).await.map_err(|e| e.to_string())?;
+    let mut report = ArchiveReport::default();
+
+    let (readings, object) = export_kind(storage, exporter, READINGS, &until, |since| {
+        reading_pages(storage.clone(), since, until.clone())
+    })
+    .await?;
+    report.readings = readings;
+    report.objects.extend(object);
+
+    let (anomalies, object) = export_kind(storage, exporter, ANOMALIES, &until, |since| {
+        let filter = AnomalyFilter {
+            start: since,
+            end: Some(until.clone()),
+            ..AnomalyFilter::default()
+        };
+        storage
+            .anomaly_pages(filter, PAGE_SIZE)
+            .map_err(|e| e.to_string())
+    })
+    .await?;
+    report.anomalies = anomalies;
+    report.objects.extend(object);
+    Ok(report)
+}
+
+/// Exports the rows of `kind` from its watermark up to `until`, then moves
+/// the watermark, returning the rows and the object written.
+async fn export_kind<T, S>(
+    storage: &Storage,
+    exporter: &ObjectExporter,
+    kind: &str,
+    until: &str,
+    pages: impl FnOnce(Option<String>) -> S,
+) -> Result<(usize, Option<String>), String>
+where
+    T: Serialize + DeserializeOwned,
+    S: Stream<Item = Result<Vec<T>, String>>,
+{
+    let since = storage
+        .archive_watermark(kind)
+        .await
+        .map_err(|e| e.to_string())?;
+    if since.as_deref().is_some_and(|since| since >= until) {
+        return Ok((0, None));
+    }
+
+    let mut pages = std::pin::pin!(pages(since));
+    let (rows, object) = match pages.try_next().await? {
+        Some(first) => {
+            let request = ExportRequest {
+                format: ExportFormat::Parquet,
+                key: Some(format!("{}/{}.parquet", kind, object_name(until))),
+            };
+            let pages = futures_util::stream::iter([Ok(first)]).chain(pages);
+            let receipt = exporter.export_pages(kind, &request, pages).await?;
+            (receipt.rows, Some(receipt.url))
+        }
+        None => (0, None),
+    };
+    storage
+        .set_archive_watermark(kind, until)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok((rows, object))
+}
+
+/// `2026-01-19T023000` for `2026-01-19 02:30:00`, safe in any store.
+fn object_name(until: &str) -> String {
+    until.replace(' ', "T").replace(':', "")
+}
+
+/// Streams the readings of every sensor in `[since, until)` in pages.
+fn reading_pages(
+    storage: Storage,
+    since: Option<String>,
+    until: String,
+) -> impl Stream<Item = Result<Vec<SensorReading>, String>> {
+    futures_util::stream::try_unfold(Some(0), move |after_id| {
+        let (storage, since, until) = (storage.clone(), since.clone(), until.clone());
+        async move {
+            let Some(after_id) = after_id else {
+                return Ok(None);
+            };
+            let page = storage
+                .readings_between(since.as_deref(), &until, after_id, PAGE_SIZE)
+                .await
+                .map_err(|e| e.to_string())?;
+            if page.is_empty() {
+                return Ok(None);
+            }
+            let next = match page.last() {
+                Some(last) if page.len() as i64 == PAGE_SIZE => Some(last.id),
+                _ => None,
+            };
+            Ok(Some((page, next)))
+        }
+    })
+}
+
+/// Runs [`run`] on `policy.schedule` for the lifetime of the process, on the
+/// replica holding the lease.
+pub fn spawn(storage: Storage, exporter: ObjectExporter, policy: ArchivePolicy) {
+    tokio::spawn(async move {
+        let lease = Lease::new(storage.clone(), "archive", LEASE_INTERVAL);
+        loop {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as i64)
+                .unwrap_or(0);
+            let Some(next) = policy.schedule.next_after(now) else {
+                eprintln!(
+                    "Warning: Archive schedule {:?} never runs",
+                    policy.schedule.to_string()
+                );
+                return;
+            };
+            tokio::time::sleep(Duration::from_secs((next - now) as u64)).await;
+            if !lease.acquire().await {
+                continue;
+            }
+            match run(&storage, &exporter, policy.after).await {
+                Ok(report) => {
+                    if !report.objects.is_empty() {
+                        println!(
+                            "Archive: exported {} readings and {} anomalies to {}",
+                            report.readings,
+                            report.anomalies,
+                            report.objects.join(", ")
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Error: Archive export failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_export::testing;
+    use crate::storage::testing::{in_memory, insert_anomaly, insert_reading};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[tokio::test]
+    async fn test_run_exports_each_row_once() {
+        let storage = in_memory().await;
+        let (exporter, store) = testing::in_memory();
+        insert_reading(&storage, 1, 1.0, "2000-01-01 00:00:00.000000").await;
+        insert_reading(&storage, 1, 2.0, "2000-01-02 00:00:00.000000").await;
+        insert_reading(&storage, 1, 3.0, "2999-01-01 00:00:00.000000").await;
+        insert_anomaly(&storage, 1, "2000-01-02 00:00:00.000000").await;
+
+        let report = run(&storage, &exporter, 30 * DAY).await.unwrap();
+        assert_eq!((report.readings, report.anomalies), (2, 1));
+        let readings = report
+            .objects
+            .iter()
+            .find(|url| url.contains("/readings/"))
+            .unwrap();
+        assert!(readings.ends_with(".parquet"));
+        let bytes = axum::body::Bytes::from(testing::read(&store, readings).await);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut values = Vec::new();
+        for batch in reader {
+            let rows: Vec<SensorReading> = serde_arrow::from_record_batch(&batch.unwrap()).unwrap();
+            values.extend(rows.iter().map(|reading| reading.value));
+        }
+        assert_eq!(values, [1.0, 2.0]);
+
+        // The next run has nothing new to write.
+        let again = run(&storage, &exporter, 30 * DAY).await.unwrap();
+        assert_eq!(again, ArchiveReport::default());
+        assert!(storage.archive_watermark(READINGS).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,258 @@
+//! Audit trail of configuration changes, listed by `GET /audit`.
+//!
+//! Every change made through the API to sensor registry entries, tags and
+//! shadow detectors, runtime settings, notification routing, silences and
+//! webhooks is recorded with who made it (the `X-Actor` header) and the value
+//! before and after. The other channels are only configured through the
+//! notification config file, so they have no entries.
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::redaction::Redaction;
+use crate::storage::{AuditFilter, NewAuditEntry, Storage, StoredAuditEntry};
+
+/// Header naming who made a change.
+pub const ACTOR_HEADER: &str = "x-actor";
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1_000;
+
+/// Who made the change, from the `X-Actor` header.
+pub fn actor(headers: &HeaderMap) -> String {
+    headers
+        .get(ACTOR_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("unknown")
+        .to_string()
+}
+
+/// A change to one resource, about to be recorded.
+pub struct Change {
+    resource: &'static str,
+    resource_id: Option<String>,
+    before: Option<String>,
+    after: Option<String>,
+}
+
+impl Change {
+    /// A change from `before` to `after`; a missing `before` is a creation and
+    /// a missing `after` a deletion.
+    pub fn new<T: Serialize>(
+        resource: &'static str,
+        resource_id: Option<String>,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Self {
+        let encode = |value: Option<&T>| value.and_then(|v| serde_json::to_string(v).ok());
+        Change {
+            resource,
+            resource_id,
+            before: encode(before),
+            after: encode(after),
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match (&self.before, &self.after) {
+            (None, _) => "create",
+            (Some(_), None) => "delete",
+            (Some(_), Some(_)) => "update",
+        }
+    }
+}
+
+/// Records changes made by `actor`, leaving out those that changed nothing.
+///
+/// The changes have already been applied, so a failure is logged rather than
+/// returned.
+pub async fn record(storage: &Storage, actor: &str, changes: impl IntoIterator<Item = Change>) {
+    let entries: Vec<NewAuditEntry> = changes
+        .into_iter()
+        .filter(|change| change.before.is_none() || change.before != change.after)
+        .map(|change| NewAuditEntry {
+            actor: actor.to_string(),
+            resource: change.resource,
+            action: change.action(),
+            resource_id: change.resource_id,
+            before: change.before,
+            after: change.after,
+        })
+        .collect();
+    if entries.is_empty() {
+        return;
+    }
+    if let Err(e) = storage.insert_audit_entries(&entries).await {
+        eprintln!(
+            "Error: Failed to record {} audit entries by {}: {}",
+            entries.len(),
+            actor,
+            e
+        );
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct AuditQuery {
+    resource: Option<String>,
+    resource_id: Option<String>,
+    actor: Option<String>,
+    since: Option<String>,
+    /// Continues a listing from the smallest `id` of the previous page.
+    before_id: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AuditEntry {
+    id: i64,
+    changed_at: String,
+    actor: String,
+    resource: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resource_id: Option<String>,
+    action: String,
+    before: serde_json::Value,
+    after: serde_json::Value,
+}
+
+impl From<StoredAuditEntry> for AuditEntry {
+    fn from(entry: StoredAuditEntry) -> Self {
+        let decode = |raw: Option<String>| {
+            raw.and_then(|raw| serde_json::from_str(&raw).ok())
+                .unwrap_or(serde_json::Value::Null)
+        };
+        Self {
+            id: entry.id,
+            changed_at: entry.changed_at,
+            actor: entry.actor,
+            resource: entry.resource,
+            resource_id: entry.resource_id,
+            action: entry.action,
+            before: decode(entry.before),
+            after: decode(entry.after),
+        }
+    }
+}
+
+impl AuditEntry {
+    /// Redacts the sensor metadata in the change, leaving runtime settings.
+    pub fn redact(&mut self, redaction: &Redaction) {
+        if self.resource != "settings" {
+            redaction.json(&mut self.before);
+            redaction.json(&mut self.after);
+        }
+    }
+}
+
+/// Lists recorded changes, newest first.
+pub async fn list_audit(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let since = match &query.since {
+        Some(since) => Some(
+            storage
+                .normalize_timestamp(since)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+                .ok_or((
+                    StatusCode::BAD_REQUEST,
+                    "since must be a valid timestamp".to_string(),
+                ))?,
+        ),
+        None => None,
+    };
+    let filter = AuditFilter {
+        resource: query.resource,
+        resource_id: query.resource_id,
+        actor: query.actor,
+        since,
+        before_id: query.before_id,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let stored = storage
+        .audit_entries(&filter, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(stored.into_iter().map(AuditEntry::from).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::in_memory;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_records_and_filters_changes() {
+        let storage = in_memory().await;
+        let (old, new) = (json!({ "threshold": 2.0 }), json!({ "threshold": 3.0 }));
+        record(
+            &storage,
+            "alice",
+            [
+                Change::new("sensor", Some("7".to_string()), None, Some(&old)),
+                Change::new("sensor", Some("7".to_string()), Some(&old), Some(&new)),
+                Change::new("sensor", Some("8".to_string()), Some(&new), Some(&new)),
+            ],
+        )
+        .await;
+        record(
+            &storage,
+            "bob",
+            [Change::new(
+                "sensor",
+                Some("7".to_string()),
+                Some(&new),
+                None,
+            )],
+        )
+        .await;
+        let state = AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        };
+        let list = |query: AuditQuery| list_audit(State(state.clone()), Query(query));
+
+        let Json(entries) = list(AuditQuery::default()).await.unwrap();
+        let actions: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|e| (e.actor.as_str(), e.action.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![("bob", "delete"), ("alice", "update"), ("alice", "create")]
+        );
+        assert_eq!(entries[1].before, old);
+        assert_eq!(entries[1].after, new);
+        assert_eq!(entries[0].after, serde_json::Value::Null);
+
+        let Json(page) = list(AuditQuery {
+            actor: Some("alice".to_string()),
+            before_id: Some(entries[1].id),
+            ..AuditQuery::default()
+        })
+        .await
+        .unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].action, "create");
+
+        let result = list(AuditQuery {
+            since: Some("yesterday".to_string()),
+            ..AuditQuery::default()
+        })
+        .await;
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,357 @@
+use axum::{Json, extract::State, http::StatusCode};
+use detection_core::stats::{RunningStats, ZScorer, summarize};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::events::AnomalyEvent;
+use crate::lanes::Lanes;
+use crate::object_export::{ExportReceipt, ExportRequest};
+use crate::sensors::{self, SensorConfig};
+use crate::settings::DetectionSettings;
+use crate::storage::{AnomalyFilter, NewAnomaly, Storage, StoredAnomaly};
+use crate::{AppState, ZSCORE_METHOD, classify, exporter, notify, resolve_threshold};
+
+/// Number of readings fetched from the database per query.
+pub const CHUNK_SIZE: i64 = 10_000;
+
+#[derive(Deserialize)]
+pub struct BackfillRequest {
+    sensor_id: i64,
+    start: String,
+    end: String,
+    /// Defaults to the sensor's registered threshold, then the runtime
+    /// `default_threshold`.
+    #[serde(default)]
+    threshold: Option<f64>,
+    /// Also writes the resulting anomalies to object storage.
+    #[serde(default)]
+    export: Option<ExportRequest>,
+    /// Sends the resulting anomalies to the configured notification channels,
+    /// and resolves open incidents when the range ends on a normal reading.
+    /// Off by default so re-scoring history does not page anyone.
+    #[serde(default)]
+    notify: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BackfillResponse {
+    sensor_id: i64,
+    method: &'static str,
+    start: String,
+    end: String,
+    readings_scanned: u64,
+    anomalies_written: u64,
+    anomalies_replaced: u64,
+    /// Whether the most recent reading in the range was scored anomalous.
+    last_reading_anomalous: bool,
+    mean: f64,
+    std_dev: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    export: Option<ExportReceipt>,
+}
+
+/// Re-scores a sensor's stored readings in `[start, end)` and replaces the
+/// anomalies previously recorded for that range and method.
+///
+/// Readings are streamed in chunks twice: once to compute the statistics and
+/// once to score, so memory use is bounded by `CHUNK_SIZE` regardless of the
+/// size of the range. Each chunk waits its turn in the bulk lane (see
+/// `lanes`), so a long backfill does not hold up `/analyze`.
+pub async fn backfill(
+    State(state): State<AppState>,
+    Json(payload): Json<BackfillRequest>,
+) -> Result<Json<BackfillResponse>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+
+    if let Some(threshold) = payload.threshold
+        && (!threshold.is_finite() || threshold <= 0.0)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("threshold must be a positive number, got {}", threshold),
+        ));
+    }
+
+    let export = payload.export.clone();
+    let notify = payload.notify;
+    let exporter = export.as_ref().map(|_| exporter(&state)).transpose()?;
+
+    let sensor = sensors::registered(&state, Some(payload.sensor_id)).await?;
+    let mut response = run_backfill(
+        storage,
+        payload,
+        &state.settings.detection(),
+        sensor.as_ref(),
+        &state.lanes,
+    )
+    .await
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    .ok_or((
+        StatusCode::BAD_REQUEST,
+        "start and end must be valid timestamps with start before end".to_string(),
+    ))?;
+
+    let filter = AnomalyFilter {
+        sensor_id: Some(response.sensor_id),
+        start: Some(response.start.clone()),
+        end: Some(response.end.clone()),
+        method: Some(response.method.to_string()),
+        min_severity: None,
+    };
+
+    if let (true, Some(notifier)) = (notify, &state.notifier) {
+        let anomalies: Vec<StoredAnomaly> = storage
+            .anomaly_pages(filter.clone(), CHUNK_SIZE)
+            .try_concat()
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        state
+            .events
+            .publish(anomalies.iter().map(AnomalyEvent::from));
+        let recovered = response.readings_scanned > 0 && !response.last_reading_anomalous;
+        let resolve = recovered.then_some((response.sensor_id, response.method));
+        notify::queue::enqueue(notifier, anomalies, resolve).await;
+    }
+
+    if let (Some(exporter), Some(export)) = (exporter, export) {
+        let pages = storage
+            .anomaly_pages(filter, CHUNK_SIZE)
+            .map_err(|e| e.to_string());
+        let receipt = exporter
+            .export_pages("backfill", &export, pages)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, format!("export failed: {}", e)))?;
+        response.export = Some(receipt);
+    }
+
+    Ok(Json(response))
+}
+
+/// Statistics of a sensor's readings in a normalized `[start, end)` range,
+/// read in chunks in the bulk lane.
+pub async fn range_stats(
+    storage: &Storage,
+    lanes: &Lanes,
+    sensor_id: i64,
+    start: &str,
+    end: &str,
+) -> Result<RunningStats, sqlx::Error> {
+    let mut stats = RunningStats::default();
+    let mut after_id = 0;
+    loop {
+        let _permit = lanes.bulk().await;
+        let page = storage
+            .readings_page(sensor_id, start, end, after_id, CHUNK_SIZE)
+            .await?;
+        let Some(last) = page.last() else { break };
+        after_id = last.id;
+        stats.merge(&summarize(page.iter().map(|r| r.value)));
+    }
+    Ok(stats)
+}
+
+async fn run_backfill(
+    storage: &Storage,
+    request: BackfillRequest,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+    lanes: &Lanes,
+) -> Result<Option<BackfillResponse>, sqlx::Error> {
+    let Some((start, end)) = storage
+        .normalize_range(&request.start, &request.end)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let stats = range_stats(storage, lanes, request.sensor_id, &start, &end).await?;
+
+    let method = ZSCORE_METHOD;
+    let anomalies_replaced = storage
+        .delete_anomalies(request.sensor_id, &start, &end, method)
+        .await?;
+
+    let threshold = resolve_threshold(request.threshold, detection, sensor);
+    let scorer = ZScorer::new(&stats, threshold);
+    let mut anomalies_written = 0;
+    let mut last_reading_anomalous = false;
+    let mut after_id = 0;
+    loop {
+        let _permit = lanes.bulk().await;
+        let page = storage
+            .readings_page(request.sensor_id, &start, &end, after_id, CHUNK_SIZE)
+            .await?;
+        let Some(last) = page.last() else { break };
+        after_id = last.id;
+        let last_id = last.id;
+
+        let anomalies: Vec<NewAnomaly> = page
+            .into_iter()
+            .filter_map(|reading| {
+                let (z_score, severity) = classify(&scorer, detection, sensor, reading.value)?;
+                Some(NewAnomaly {
+                    reading_id: reading.id,
+                    sensor_id: request.sensor_id,
+                    value: reading.value,
+                    timestamp: reading.timestamp,
+                    method,
+                    score: z_score,
+                    severity,
+                })
+            })
+            .collect();
+
+        last_reading_anomalous = anomalies.last().is_some_and(|a| a.reading_id == last_id);
+        storage.insert_anomalies(&anomalies).await?;
+        anomalies_written += anomalies.len() as u64;
+    }
+
+    Ok(Some(BackfillResponse {
+        sensor_id: request.sensor_id,
+        method,
+        start,
+        end,
+        readings_scanned: stats.count(),
+        anomalies_written,
+        anomalies_replaced,
+        last_reading_anomalous,
+        mean: stats.mean(),
+        std_dev: stats.std_dev(),
+        export: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{count_anomalies, in_memory, insert_reading};
+
+    async fn seeded_storage() -> Storage {
+        let storage = in_memory().await;
+        for minute in 0..30 {
+            let value = if minute == 15 { 500.0 } else { 50.0 };
+            let ts = format!("2026-01-19 10:{:02}:00.000000", minute);
+            insert_reading(&storage, 7, value, &ts).await;
+        }
+        storage
+    }
+
+    fn request(start: &str, end: &str) -> BackfillRequest {
+        BackfillRequest {
+            sensor_id: 7,
+            start: start.to_string(),
+            end: end.to_string(),
+            threshold: Some(2.0),
+            export: None,
+            notify: false,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_writes_anomalies() {
+        let storage = seeded_storage().await;
+
+        let response = run_backfill(
+            &storage,
+            request("2026-01-19T10:00:00", "2026-01-19T11:00:00"),
+            &DetectionSettings::default(),
+            None,
+            &Lanes::default(),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(response.readings_scanned, 30);
+        assert_eq!(response.anomalies_written, 1);
+        assert_eq!(response.anomalies_replaced, 0);
+        assert!(!response.last_reading_anomalous);
+        assert_eq!(count_anomalies(&storage, 7).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_rerun_replaces_previous_results() {
+        let storage = seeded_storage().await;
+        let range = ("2026-01-19T10:00:00", "2026-01-19T11:00:00");
+
+        let detection = DetectionSettings::default();
+        let lanes = Lanes::default();
+        run_backfill(
+            &storage,
+            request(range.0, range.1),
+            &detection,
+            None,
+            &lanes,
+        )
+        .await
+        .unwrap();
+        let response = run_backfill(
+            &storage,
+            request(range.0, range.1),
+            &detection,
+            None,
+            &lanes,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(response.anomalies_replaced, 1);
+        assert_eq!(count_anomalies(&storage, 7).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_rejects_invalid_range() {
+        let storage = seeded_storage().await;
+
+        let response = run_backfill(
+            &storage,
+            request("2026-01-19T11:00:00", "2026-01-19T10:00:00"),
+            &DetectionSettings::default(),
+            None,
+            &Lanes::default(),
+        )
+        .await
+        .unwrap();
+
+        assert!(response.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_backfill_without_storage() {
+        let result = backfill(
+            State(AppState::default()),
+            Json(request("2026-01-19T10:00:00", "2026-01-19T11:00:00")),
+        )
+        .await;
+
+        assert_eq!(result.unwrap_err().0, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_backfill_exports_written_anomalies() {
+        let (exporter, store) = crate::object_export::testing::in_memory();
+        let state = AppState {
+            storage: Some(seeded_storage().await),
+            exporter: Some(exporter),
+            ..AppState::default()
+        };
+        let mut payload = request("2026-01-19T10:00:00", "2026-01-19T11:00:00");
+        payload.export = Some(ExportRequest {
+            format: crate::object_export::ExportFormat::Csv,
+            key: Some("backfill.csv".to_string()),
+        });
+
+        let Json(response) = backfill(State(state), Json(payload)).await.unwrap();
+
+        let receipt = response.export.unwrap();
+        assert_eq!(receipt.rows, 1);
+        let object = crate::object_export::testing::read(&store, &receipt.url).await;
+        let object = String::from_utf8(object).unwrap();
+        assert!(object.starts_with("id,reading_id,sensor_id,"));
+        assert_eq!(object.lines().count(), 2);
+    }
+}
@@ -0,0 +1,132 @@
+//! Current per-sensor baselines: the mean and spread of recent readings that
+//! new values are scored against, and how far the latest reading is from it.
+
+use std::time::Duration;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::storage::ReadingAggregate;
+
+const DEFAULT_HOURS: u32 = 24;
+const MAX_HOURS: u32 = 24 * 31;
+
+#[derive(Deserialize)]
+pub struct BaselineQuery {
+    /// Length of the window ending now.
+    hours: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Baseline {
+    sensor_id: i64,
+    count: i64,
+    mean: f64,
+    std_dev: f64,
+    last_value: f64,
+    last_timestamp: String,
+    /// Z-score of the latest reading; absent when the readings do not vary.
+    last_score: Option<f64>,
+}
+
+impl From<ReadingAggregate> for Baseline {
+    fn from(aggregate: ReadingAggregate) -> Self {
+        let n = aggregate.count as f64;
+        let variance = if aggregate.count > 1 {
+            ((aggregate.mean_square - aggregate.mean * aggregate.mean) * n / (n - 1.0)).max(0.0)
+        } else {
+            0.0
+        };
+        let std_dev = variance.sqrt();
+        Baseline {
+            sensor_id: aggregate.sensor_id,
+            count: aggregate.count,
+            mean: aggregate.mean,
+            std_dev,
+            last_value: aggregate.last_value,
+            last_timestamp: aggregate.last_timestamp,
+            last_score: (std_dev > 0.0).then(|| (aggregate.last_value - aggregate.mean) / std_dev),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BaselineResponse {
+    since: String,
+    baselines: Vec<Baseline>,
+}
+
+/// Lists the baseline of every sensor with readings in the last `hours`.
+pub async fn baselines(
+    State(state): State<AppState>,
+    Query(query): Query<BaselineQuery>,
+) -> Result<Json<BaselineResponse>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let hours = query.hours.unwrap_or(DEFAULT_HOURS).clamp(1, MAX_HOURS);
+
+    let since = storage
+        .cutoff(Duration::from_secs(u64::from(hours) * 60 * 60))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let aggregates = storage
+        .reading_aggregates(&since)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(BaselineResponse {
+        since,
+        baselines: aggregates.into_iter().map(Baseline::from).collect(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{in_memory, insert_reading};
+
+    #[tokio::test]
+    async fn test_baselines_cover_recent_readings() {
+        let storage = in_memory().await;
+        let at = |minutes: u64| {
+            let storage = storage.clone();
+            async move {
+                storage
+                    .cutoff(Duration::from_secs(minutes * 60))
+                    .await
+                    .unwrap()
+            }
+        };
+        insert_reading(&storage, 1, 1000.0, &at(48 * 60).await).await;
+        for (minutes, value) in [(30, 10.0), (20, 12.0), (10, 14.0), (5, 20.0)] {
+            insert_reading(&storage, 1, value, &at(minutes).await).await;
+        }
+        insert_reading(&storage, 2, 5.0, &at(5).await).await;
+        let state = AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        };
+
+        let Json(response) = baselines(State(state), Query(BaselineQuery { hours: None }))
+            .await
+            .unwrap();
+
+        let [first, second] = &response.baselines[..] else {
+            panic!("expected two baselines, got {:?}", response.baselines);
+        };
+        assert_eq!(first.count, 4);
+        assert_eq!(first.mean, 14.0);
+        assert!((first.std_dev - (56.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(first.last_value, 20.0);
+        assert!((first.last_score.unwrap() - 6.0 / (56.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(second.count, 1);
+        assert_eq!(second.last_score, None);
+    }
+}
@@ -0,0 +1,409 @@
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::json::JsonBody;
+use crate::lanes::Lane;
+use crate::pool::PooledJson;
+use crate::validation::ApiError;
+use crate::{
+    AnalyzeRequest, AnalyzeResponse, AppState, Scoring, detect_timed, publish, reference,
+    requested_scoring, sensors, shadow, usage,
+};
+
+/// Maximum number of series accepted in a single batch request.
+const MAX_BATCH_SERIES: usize = 1000;
+
+#[derive(Deserialize)]
+pub struct BatchSeries {
+    id: String,
+    #[serde(flatten)]
+    request: AnalyzeRequest,
+}
+
+#[derive(Deserialize)]
+pub struct BatchRequest {
+    series: Vec<BatchSeries>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum SeriesOutcome {
+    Ok {
+        result: AnalyzeResponse,
+    },
+    Error {
+        error: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        field: Option<String>,
+    },
+}
+
+impl From<ApiError> for SeriesOutcome {
+    fn from(error: ApiError) -> Self {
+        SeriesOutcome::Error {
+            error: error.error,
+            field: error.field,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SeriesResult {
+    id: String,
+    #[serde(flatten)]
+    outcome: SeriesOutcome,
+}
+
+#[derive(Serialize)]
+pub struct BatchResponse {
+    results: Vec<SeriesResult>,
+    succeeded: usize,
+    failed: usize,
+}
+
+/// Refuses the `index`th series of a batch as `/analyze` would refuse it,
+/// its field under `series[index]`, or if it asks for what a batch does
+/// not do.
+fn validate_series(index: usize, series: &BatchSeries) -> Result<(), ApiError> {
+    let path = format!("series[{}]", index);
+    let unsupported = [
+        ("export", series.request.export.is_some()),
+        ("window", series.request.window.is_some()),
+    ];
+    if let Some((name, _)) = unsupported.iter().find(|(_, set)| *set) {
+        return Err(ApiError::invalid(
+            format!("{}.{}", path, name),
+            "is not supported for batch series",
+        ));
+    }
+    series
+        .request
+        .validate()
+        .map_err(|error| error.within(&path))?;
+    if let Some(position) = series
+        .request
+        .readings
+        .iter()
+        .position(|r| r.sensor_id.is_some() && r.sensor_id != series.request.sensor_id)
+    {
+        return Err(ApiError::invalid(
+            format!("{}.readings[{}].sensor_id", path, position),
+            "names another sensor; send a series per sensor",
+        ));
+    }
+    Ok(())
+}
+
+/// Analyzes many independent series in one request; only series with
+/// method `reference` read another, their reference sensor's.
+///
+/// Each series runs on the blocking pool so large batches spread across all
+/// cores. A series that fails validation (or panics) is reported in its own
+/// result entry without affecting the others.
+pub async fn analyze_batch(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<BatchRequest>,
+) -> Result<PooledJson<BatchResponse>, (StatusCode, String)> {
+    if payload.series.len() > MAX_BATCH_SERIES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "batch contains {} series, the limit is {}",
+                payload.series.len(),
+                MAX_BATCH_SERIES
+            ),
+        ));
+    }
+    let readings = payload
+        .series
+        .iter()
+        .map(|s| s.request.readings.len())
+        .sum();
+    usage::charge(&state, &headers, readings, payload.series.len()).await?;
+    // Bulk batches take a worker per series rather than the whole request.
+    let lane = Lane::of(&headers);
+    let _interactive = (lane == Lane::Interactive).then(|| state.lanes.interactive());
+
+    let detection = state.settings.detection();
+    let sensor_ids: Vec<i64> = payload
+        .series
+        .iter()
+        .filter_map(|s| s.request.sensor_id)
+        .collect();
+    let registry = sensors::lookup(&state, &sensor_ids).await?;
+    let shadows = shadow::lookup(&state, &sensor_ids, &registry).await;
+    let canaries = reference::canaries(payload.series.iter().map(|s| &s.request), &registry);
+    let mut pending = Vec::with_capacity(payload.series.len());
+    for (index, series) in payload.series.into_iter().enumerate() {
+        let sensor = series
+            .request
+            .sensor_id
+            .and_then(|id| registry.get(&id))
+            .cloned();
+        let scoring = validate_series(index, &series).and_then(|()| {
+            match series.request.method.as_deref() {
+                Some(reference::METHOD) => {
+                    reference::scoring(&series.request, sensor.as_ref(), &canaries)
+                }
+                _ => requested_scoring(&state.detectors, &series.request),
+            }
+            .and_then(|scoring| Ok((scoring, series.request.detection(detection)?)))
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))
+        });
+        match scoring {
+            Ok((scoring, detection)) => {
+                let metrics = state.metrics.clone();
+                let sensor_id = series.request.sensor_id;
+                // Shadows try out z-score parameters, so only z-score series
+                // are compared.
+                let shadow = sensor_id
+                    .filter(|_| matches!(scoring, Scoring::ZScore))
+                    .and_then(|id| shadows.get(&id))
+                    .cloned();
+                let method = scoring.method();
+                let permit = match lane {
+                    Lane::Bulk => Some(state.lanes.bulk().await),
+                    Lane::Interactive => None,
+                };
+                let handle = tokio::task::spawn_blocking(move || {
+                    let _permit = permit;
+                    let shadowed = shadow.map(|shadow| shadow.detect(&series.request));
+                    let result = detect_timed(
+                        &metrics,
+                        &scoring,
+                        &detection,
+                        sensor.as_ref(),
+                        series.request,
+                    );
+                    (result, shadowed)
+                });
+                pending.push((series.id, Ok((sensor_id, method, handle))));
+            }
+            Err(error) => pending.push((series.id, Err(error))),
+        }
+    }
+
+    let mut results = Vec::with_capacity(pending.len());
+    for (id, task) in pending {
+        let outcome = match task {
+            Ok((sensor_id, method, handle)) => match handle.await {
+                Ok((result, shadowed)) => {
+                    publish(&state, sensor_id, method, &result);
+                    if let Some(shadowed) = shadowed {
+                        shadow::observe(&state, shadowed, &result.anomalies);
+                    }
+                    SeriesOutcome::Ok { result }
+                }
+                Err(e) => SeriesOutcome::Error {
+                    error: format!("analysis failed: {}", e),
+                    field: None,
+                },
+            },
+            Err(error) => error.into(),
+        };
+        results.push(SeriesResult { id, outcome });
+    }
+
+    let succeeded = results
+        .iter()
+        .filter(|r| matches!(r.outcome, SeriesOutcome::Ok { .. }))
+        .count();
+    let failed = results.len() - succeeded;
+
+    Ok(PooledJson(BatchResponse {
+        results,
+        succeeded,
+        failed,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reading;
+    use crate::storage::StoredSensorConfig;
+    use crate::storage::testing::in_memory;
+
+    fn series(id: &str, values: &[f64], threshold: f64) -> BatchSeries {
+        BatchSeries {
+            id: id.to_string(),
+            request: AnalyzeRequest {
+                sensor_id: None,
+                readings: values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &value)| Reading {
+                        id: i as i64,
+                        value,
+                        timestamp: format!("2026-01-19T10:{:02}:00", i),
+                        sensor_id: None,
+                    })
+                    .collect(),
+                method: None,
+                ensemble: None,
+                threshold: Some(threshold),
+                seed: None,
+                severity: None,
+                export: None,
+                window: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_reports_results_in_order() {
+        let mut spiky = vec![50.0; 20];
+        spiky.push(500.0);
+        let request = BatchRequest {
+            series: vec![
+                series("flat", &[1.0, 1.0, 1.0], 2.0),
+                series("spiky", &spiky, 2.0),
+            ],
+        };
+
+        let PooledJson(response) = analyze_batch(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(request),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.succeeded, 2);
+        assert_eq!(response.failed, 0);
+        assert_eq!(response.results[0].id, "flat");
+        assert_eq!(response.results[1].id, "spiky");
+        match &response.results[1].outcome {
+            SeriesOutcome::Ok { result } => assert_eq!(result.anomalies.len(), 1),
+            SeriesOutcome::Error { error, .. } => panic!("unexpected error: {}", error),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_partial_failure() {
+        let mut request = BatchRequest {
+            series: vec![
+                series("good", &[1.0, 2.0, 3.0], 2.0),
+                series("empty", &[], 2.0),
+                series("bad-threshold", &[1.0, 2.0], -1.0),
+                series("not-finite", &[1.0, f64::NAN], 2.0),
+                series("windowed", &[1.0, 2.0], 2.0),
+            ],
+        };
+        request.series[4].request.window = Some("5m".to_string());
+
+        let PooledJson(response) = analyze_batch(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(request),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.succeeded, 1);
+        assert_eq!(response.failed, 4);
+        let refusals: Vec<(&str, Option<&str>)> = response.results[1..]
+            .iter()
+            .map(|result| match &result.outcome {
+                SeriesOutcome::Error { error, field } => (error.as_str(), field.as_deref()),
+                SeriesOutcome::Ok { .. } => panic!("series {} was scored", result.id),
+            })
+            .collect();
+        // The field /analyze names, under the series.
+        assert_eq!(
+            refusals,
+            [
+                (
+                    "series[1].readings must contain at least one reading",
+                    Some("series[1].readings")
+                ),
+                (
+                    "series[2].threshold must be a positive number, got -1",
+                    Some("series[2].threshold")
+                ),
+                (
+                    "series[3].readings[1].value must be a finite number, got NaN",
+                    Some("series[3].readings[1].value")
+                ),
+                (
+                    "series[4].window is not supported for batch series",
+                    Some("series[4].window")
+                ),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reference_series_are_scored_against_their_canary() {
+        let storage = in_memory().await;
+        let pump = StoredSensorConfig {
+            sensor_id: 8,
+            name: "Pump B".to_string(),
+            unit: String::new(),
+            method: "zscore".to_string(),
+            threshold: None,
+            min_value: None,
+            max_value: None,
+            reference_id: Some(7),
+            rules: None,
+        };
+        storage.save_sensor_config(&pump, &[], true).await.unwrap();
+        let state = AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        };
+
+        // B follows A's swings with an offset, and parts from it once.
+        let canary: Vec<f64> = (0..20).map(|i| 50.0 + 10.0 * (i % 4) as f64).collect();
+        let mut paired: Vec<f64> = canary.iter().map(|value| value + 1.0).collect();
+        paired[12] += 6.0;
+        let mut a = series("a", &canary, 3.0);
+        a.request.sensor_id = Some(7);
+        let mut b = series("b", &paired, 3.0);
+        b.request.sensor_id = Some(8);
+        b.request.method = Some(reference::METHOD.to_string());
+        let mut unpaired = series("c", &paired, 3.0);
+        unpaired.request.method = Some(reference::METHOD.to_string());
+        let request = BatchRequest {
+            series: vec![a, b, unpaired],
+        };
+
+        let PooledJson(response) = analyze_batch(State(state), HeaderMap::new(), JsonBody(request))
+            .await
+            .unwrap();
+
+        match &response.results[1].outcome {
+            SeriesOutcome::Ok { result } => {
+                let ids: Vec<i64> = result.anomalies.iter().map(|a| a.id).collect();
+                assert_eq!(ids, [12]);
+            }
+            SeriesOutcome::Error { error, .. } => panic!("unexpected error: {}", error),
+        }
+        assert!(matches!(
+            response.results[2].outcome,
+            SeriesOutcome::Error { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_rejects_too_many_series() {
+        let request = BatchRequest {
+            series: (0..=MAX_BATCH_SERIES)
+                .map(|i| series(&i.to_string(), &[1.0], 2.0))
+                .collect(),
+        };
+
+        let result = analyze_batch(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(request),
+        )
+        .await;
+
+        assert_eq!(result.err().unwrap().0, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
@@ -0,0 +1,865 @@
+//! Service configuration: the `[service]` section of the settings file
+//! named by `ANOMALY_CONFIG`, overridden by `ANOMALY_*` environment
+//! variables, e.g. `ANOMALY_DATABASE_URL` for `database_url`. See
+//! `config-core` for how the layers resolve.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use config_core::{Loader, Validate, config_file};
+use detection_core::SeverityLabels;
+use detection_core::detector::HoltWintersDetector;
+use serde::{Deserialize, Serialize};
+
+use crate::access::{Access, DEFAULT_ROLE_CLAIM, RateLimits};
+use crate::redaction::Redaction;
+use crate::schedule::Schedule;
+use crate::usage::{Limit, QuotaSetting, Quotas};
+
+const ENV_PREFIX: &str = "ANOMALY_";
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+pub struct Config {
+    pub database_url: Option<String>,
+    /// Where anomalies are kept, see `alert_store::connect`; the database
+    /// at `database_url` when unset.
+    pub alert_store_url: Option<String>,
+    /// Redis replicas share stream windows and notification dedup keys
+    /// through, see `shared`.
+    pub redis_url: Option<String>,
+    pub retention: RetentionPolicy,
+    pub rollup_interval: Duration,
+    pub export: Option<ExportTarget>,
+    pub archive: Option<ArchivePolicy>,
+    /// JSON file describing notification recipients, see `notify`.
+    pub notify_config: Option<PathBuf>,
+    /// Names this replica in background job leases, see `leader`.
+    pub instance_id: String,
+    /// Database receiving a copy of every anomaly, see `analytics`.
+    pub analytics: Option<AnalyticsTarget>,
+    pub tracking: Tracking,
+    pub lanes: LanePolicy,
+    pub stream: StreamPolicy,
+    /// Labels severities are shown with in responses, alerts and
+    /// notifications.
+    pub severity_labels: SeverityLabels,
+    /// Anomaly-rate targets, `default` and per tag, see `slo`.
+    pub slo: BTreeMap<String, f64>,
+    /// Sensor metadata hashed or removed, see `redaction`.
+    pub redaction: Redaction,
+    /// API keys, JWT settings and rate limits, see `access`.
+    pub access: Access,
+    /// Daily limits per tenant, see `usage`.
+    pub quotas: Quotas,
+    /// Port of the gRPC service, see `grpc`; off when unset.
+    pub grpc_port: Option<u16>,
+}
+
+/// How many sensors per-sensor state is kept for, see `activity`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracking {
+    pub max_sensors: usize,
+    pub idle: Duration,
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self {
+            max_sensors: 10_000,
+            idle: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+/// How bulk work shares the service with interactive requests, see
+/// `lanes`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanePolicy {
+    pub bulk_workers: usize,
+    pub latency_budget: Duration,
+}
+
+impl Default for LanePolicy {
+    fn default() -> Self {
+        let cpus = std::thread::available_parallelism().map_or(2, |cpus| cpus.get());
+        Self {
+            bulk_workers: (cpus / 2).max(1),
+            latency_budget: Duration::from_millis(250),
+        }
+    }
+}
+
+/// The window `/stream/ingest` scores each reading against, see `stream`:
+/// the last `window` readings, or an exponentially decaying average when
+/// `decay` is set; with method `holt_winters`, a forecast of a `season`
+/// of readings, from `seasonal_period`, which the `holt_winters` detector
+/// of `/analyze` forecasts too. A request may ask for a window of at most
+/// `max_window` readings.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamPolicy {
+    pub window: usize,
+    pub decay: Option<f64>,
+    pub season: usize,
+    pub max_window: usize,
+}
+
+impl Default for StreamPolicy {
+    fn default() -> Self {
+        Self {
+            window: 100,
+            decay: None,
+            season: HoltWintersDetector::default().period,
+            max_window: 10_000,
+        }
+    }
+}
+
+/// ClickHouse or TimescaleDB table that anomalies are copied to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalyticsTarget {
+    pub url: String,
+    pub table: String,
+    pub batch_size: usize,
+    pub flush_interval: Duration,
+}
+
+/// Object storage location for exported results, plus the store options
+/// taken from the remaining `ANOMALY_EXPORT_*` variables.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExportTarget {
+    pub url: String,
+    pub options: Vec<(String, String)>,
+}
+
+/// Parquet export of rows older than `after` on a schedule, see `archive`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ArchivePolicy {
+    pub schedule: Schedule,
+    pub after: Duration,
+    pub target: ExportTarget,
+}
+
+/// How long stored rows are kept. `None` keeps rows forever.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetentionPolicy {
+    pub readings: Option<Duration>,
+    pub anomalies: Option<Duration>,
+    pub interval: Duration,
+    /// Keeps rows until the archive job has exported them.
+    pub archived: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            readings: None,
+            anomalies: None,
+            interval: Duration::from_secs(60 * 60),
+            archived: false,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    pub fn is_enabled(&self) -> bool {
+        self.readings.is_some() || self.anomalies.is_some()
+    }
+}
+
+impl Config {
+    /// Resolves the `[service]` settings from the file named by
+    /// `ANOMALY_CONFIG`, then `ANOMALY_*` environment variables.
+    pub fn load() -> Result<Self, String> {
+        Self::resolve(config_file(None), &std::env::vars().collect())
+    }
+
+    fn resolve(file: Option<PathBuf>, vars: &HashMap<String, String>) -> Result<Self, String> {
+        let settings: ServiceSettings = Loader::new("service")
+            .file(file)
+            .vars(ENV_PREFIX, vars.clone())
+            .tables(&[
+                "export",
+                "archive",
+                "severity_labels",
+                "slo",
+                "redaction",
+                "api_keys",
+                "rate_limits",
+                "quotas",
+            ])
+            .load()?;
+        let days = |days: Option<u64>| days.map(|d| Duration::from_secs(d * SECONDS_PER_DAY));
+        let archive = settings.archive()?;
+        let redaction = Redaction::from_table(&settings.redaction)?;
+        let access = settings.access()?;
+        let quotas = Quotas::from_table(&settings.quotas)?;
+        let mut export = settings.export;
+        let export = export.remove("url").map(|url| ExportTarget {
+            url,
+            options: export.into_iter().collect(),
+        });
+
+        let analytics = settings.analytics_url.map(|url| AnalyticsTarget {
+            url,
+            table: settings.analytics_table,
+            batch_size: settings.analytics_batch_size,
+            flush_interval: Duration::from_secs(settings.analytics_flush_interval_secs),
+        });
+
+        Ok(Self {
+            database_url: settings.database_url,
+            alert_store_url: settings.alert_store_url,
+            redis_url: settings.redis_url,
+            retention: RetentionPolicy {
+                readings: days(settings.retention_readings_days),
+                anomalies: days(settings.retention_anomalies_days),
+                interval: Duration::from_secs(settings.compaction_interval_secs),
+                archived: archive.is_some(),
+            },
+            rollup_interval: Duration::from_secs(settings.rollup_interval_secs),
+            export,
+            archive,
+            notify_config: settings.notify_config,
+            instance_id: settings.instance_id.unwrap_or_else(|| {
+                let host = vars
+                    .get("HOSTNAME")
+                    .cloned()
+                    .unwrap_or_else(|| "anomaly-detector".to_string());
+                format!("{}-{}", host, std::process::id())
+            }),
+            analytics,
+            tracking: Tracking {
+                max_sensors: settings.max_tracked_sensors,
+                idle: Duration::from_secs(settings.sensor_idle_secs),
+            },
+            lanes: LanePolicy {
+                bulk_workers: settings
+                    .bulk_workers
+                    .unwrap_or_else(|| LanePolicy::default().bulk_workers),
+                latency_budget: Duration::from_millis(settings.interactive_latency_budget_ms),
+            },
+            stream: StreamPolicy {
+                window: settings.stream_window,
+                decay: settings.stream_decay.as_ref().and_then(Ratio::value),
+                season: settings.seasonal_period,
+                max_window: settings.stream_max_window,
+            },
+            severity_labels: settings.severity_labels,
+            slo: settings
+                .slo
+                .into_iter()
+                .filter_map(|(key, target)| Some((key, target.value()?)))
+                .collect(),
+            redaction,
+            access,
+            quotas,
+            grpc_port: settings.grpc_port,
+        })
+    }
+}
+
+/// The `[service]` section as written, in the file or as
+/// `ANOMALY_<KEY>` variables.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default)]
+struct ServiceSettings {
+    database_url: Option<String>,
+    alert_store_url: Option<String>,
+    redis_url: Option<String>,
+    retention_readings_days: Option<u64>,
+    retention_anomalies_days: Option<u64>,
+    compaction_interval_secs: u64,
+    rollup_interval_secs: u64,
+    /// `url` and the object store options, e.g. `aws_region`.
+    export: BTreeMap<String, String>,
+    /// `url`, `schedule`, `after_days` and the object store options.
+    archive: BTreeMap<String, serde_json::Value>,
+    notify_config: Option<PathBuf>,
+    instance_id: Option<String>,
+    analytics_url: Option<String>,
+    analytics_table: String,
+    analytics_batch_size: usize,
+    analytics_flush_interval_secs: u64,
+    max_tracked_sensors: usize,
+    sensor_idle_secs: u64,
+    /// Default half the CPUs.
+    bulk_workers: Option<usize>,
+    interactive_latency_budget_ms: u64,
+    stream_window: usize,
+    /// Weight of each new reading in (0, 1]; replaces `stream_window`.
+    stream_decay: Option<Ratio>,
+    /// Largest `window` a stream request may ask for.
+    stream_max_window: usize,
+    seasonal_period: usize,
+    /// `medium`, `high` and `critical`, each left out keeping its label.
+    severity_labels: SeverityLabels,
+    /// Highest share of anomalous readings, `default` for every sensor and
+    /// per tag for the sensors carrying it.
+    slo: BTreeMap<String, Ratio>,
+    /// `key`, and `hash` or `remove` for `name`, `unit` and `tag:<key>`.
+    redaction: BTreeMap<String, String>,
+    /// `"<role>:<key>"` by key name.
+    api_keys: BTreeMap<String, String>,
+    jwt_secret: Option<String>,
+    jwt_role_claim: String,
+    /// Serves the dashboard's assets without a credential.
+    public_dashboard: bool,
+    /// Requests per minute, `default` for every caller and per key name or
+    /// JWT `sub`.
+    rate_limits: BTreeMap<String, Limit>,
+    /// `readings_per_day` and `analyses_per_day`, for every tenant and in a
+    /// table per tenant.
+    quotas: BTreeMap<String, QuotaSetting>,
+    grpc_port: Option<u16>,
+}
+
+/// A number written in the file, or the text of an environment variable,
+/// which would otherwise only be read to `f32` precision.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum Ratio {
+    Number(f64),
+    Text(String),
+}
+
+impl Ratio {
+    fn value(&self) -> Option<f64> {
+        match self {
+            Ratio::Number(value) => Some(*value),
+            Ratio::Text(text) => text.trim().parse().ok(),
+        }
+    }
+}
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        Self {
+            database_url: None,
+            alert_store_url: None,
+            redis_url: None,
+            retention_readings_days: None,
+            retention_anomalies_days: None,
+            compaction_interval_secs: RetentionPolicy::default().interval.as_secs(),
+            rollup_interval_secs: 60,
+            export: BTreeMap::new(),
+            archive: BTreeMap::new(),
+            notify_config: None,
+            instance_id: None,
+            analytics_url: None,
+            analytics_table: "anomalies".to_string(),
+            analytics_batch_size: 1_000,
+            analytics_flush_interval_secs: 5,
+            max_tracked_sensors: Tracking::default().max_sensors,
+            sensor_idle_secs: Tracking::default().idle.as_secs(),
+            bulk_workers: None,
+            interactive_latency_budget_ms: LanePolicy::default().latency_budget.as_millis() as u64,
+            stream_window: StreamPolicy::default().window,
+            stream_decay: None,
+            stream_max_window: StreamPolicy::default().max_window,
+            seasonal_period: HoltWintersDetector::default().period,
+            severity_labels: SeverityLabels::default(),
+            slo: BTreeMap::new(),
+            redaction: BTreeMap::new(),
+            api_keys: BTreeMap::new(),
+            jwt_secret: None,
+            jwt_role_claim: DEFAULT_ROLE_CLAIM.to_string(),
+            public_dashboard: false,
+            rate_limits: BTreeMap::new(),
+            quotas: BTreeMap::new(),
+            grpc_port: None,
+        }
+    }
+}
+
+impl ServiceSettings {
+    fn access(&self) -> Result<Access, String> {
+        let access = Access::new(
+            &self.api_keys,
+            self.jwt_secret.as_deref(),
+            &self.jwt_role_claim,
+        )?;
+        Ok(access
+            .with_rate_limits(RateLimits::from_table(&self.rate_limits)?)
+            .with_public_dashboard(self.public_dashboard))
+    }
+
+    /// The archive job of the `archive` table, unless it is empty.
+    fn archive(&self) -> Result<Option<ArchivePolicy>, String> {
+        if self.archive.is_empty() {
+            return Ok(None);
+        }
+        // Values are read as written, so `after_days = 30` needs no quotes.
+        let mut options: BTreeMap<String, String> = self
+            .archive
+            .iter()
+            .map(|(key, value)| {
+                let text = value
+                    .as_str()
+                    .map_or_else(|| value.to_string(), str::to_string);
+                (key.clone(), text)
+            })
+            .collect();
+        let mut required = |key: &str| {
+            options
+                .remove(key)
+                .ok_or_else(|| format!("archive options are set but archive.{} is not", key))
+        };
+        let url = required("url")?;
+        let schedule = required("schedule")?
+            .parse()
+            .map_err(|e| format!("archive.schedule: {}", e))?;
+        let after_days = required("after_days")?
+            .parse::<u64>()
+            .ok()
+            .filter(|days| *days > 0)
+            .ok_or("archive.after_days must be a positive number of days")?;
+        for (name, retention) in [
+            ("readings", self.retention_readings_days),
+            ("anomalies", self.retention_anomalies_days),
+        ] {
+            if retention.is_some_and(|days| days <= after_days) {
+                return Err(format!(
+                    "archive.after_days must be less than retention_{}_days, \
+                     or rows age out before they are archived",
+                    name
+                ));
+            }
+        }
+        Ok(Some(ArchivePolicy {
+            schedule,
+            after: Duration::from_secs(after_days * SECONDS_PER_DAY),
+            target: ExportTarget {
+                url,
+                options: options.into_iter().collect(),
+            },
+        }))
+    }
+}
+
+impl Validate for ServiceSettings {
+    fn validate(&self) -> Result<(), String> {
+        if self.compaction_interval_secs == 0 {
+            return Err("compaction_interval_secs must be positive".to_string());
+        }
+        if self.rollup_interval_secs == 0 {
+            return Err("rollup_interval_secs must be positive".to_string());
+        }
+        if !self.export.is_empty() && !self.export.contains_key("url") {
+            return Err("export options are set but export.url is not".to_string());
+        }
+        self.archive()?;
+        Redaction::from_table(&self.redaction)?;
+        self.access()?;
+        Quotas::from_table(&self.quotas)?;
+        if self.analytics_batch_size == 0 {
+            return Err("analytics_batch_size must be positive".to_string());
+        }
+        if self.analytics_flush_interval_secs == 0 {
+            return Err("analytics_flush_interval_secs must be positive".to_string());
+        }
+        if self.max_tracked_sensors == 0 {
+            return Err("max_tracked_sensors must be positive".to_string());
+        }
+        if self.sensor_idle_secs == 0 {
+            return Err("sensor_idle_secs must be positive".to_string());
+        }
+        if self.bulk_workers == Some(0) {
+            return Err("bulk_workers must be positive".to_string());
+        }
+        if self.interactive_latency_budget_ms == 0 {
+            return Err("interactive_latency_budget_ms must be positive".to_string());
+        }
+        if self.stream_window == 0 {
+            return Err("stream_window must be positive".to_string());
+        }
+        if self.stream_window > self.stream_max_window {
+            return Err(format!(
+                "stream_window must be at most stream_max_window ({})",
+                self.stream_max_window
+            ));
+        }
+        if let Some(decay) = &self.stream_decay
+            && !decay
+                .value()
+                .is_some_and(|decay| decay > 0.0 && decay <= 1.0)
+        {
+            return Err("stream_decay must be in (0, 1]".to_string());
+        }
+        if self.seasonal_period < 2 {
+            return Err("seasonal_period must be at least 2".to_string());
+        }
+        if let Some((key, _)) = self.slo.iter().find(|(_, target)| {
+            !target
+                .value()
+                .is_some_and(|target| target > 0.0 && target <= 1.0)
+        }) {
+            return Err(format!("slo.{} must be in (0, 1]", key));
+        }
+        self.severity_labels
+            .validate()
+            .map_err(|e| format!("severity_labels: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Result<Config, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::resolve(None, &vars)
+    }
+
+    #[test]
+    fn test_defaults() {
+        let config = config(&[]).unwrap();
+        assert!(config.database_url.is_none());
+        assert!(config.alert_store_url.is_none());
+        assert!(config.redis_url.is_none());
+        assert_eq!(config.retention, RetentionPolicy::default());
+        assert!(!config.retention.is_enabled());
+        assert_eq!(config.rollup_interval, Duration::from_secs(60));
+        assert!(config.export.is_none());
+        assert!(config.notify_config.is_none());
+        assert!(config.analytics.is_none());
+        assert_eq!(config.tracking, Tracking::default());
+        assert_eq!(config.severity_labels, SeverityLabels::default());
+        assert!(
+            config
+                .instance_id
+                .ends_with(&format!("-{}", std::process::id()))
+        );
+    }
+
+    #[test]
+    fn test_export_target_collects_store_options() {
+        let config = config(&[
+            ("ANOMALY_EXPORT_URL", "s3://results/anomalies"),
+            ("ANOMALY_EXPORT_AWS_REGION", "eu-west-1"),
+            ("ANOMALY_EXPORT_AWS_ENDPOINT", "http://minio:9000"),
+            ("ANOMALY_DATABASE_URL", "sqlite://data/factory.db"),
+        ])
+        .unwrap();
+
+        let export = config.export.unwrap();
+        assert_eq!(export.url, "s3://results/anomalies");
+        assert_eq!(
+            export.options,
+            vec![
+                ("aws_endpoint".to_string(), "http://minio:9000".to_string()),
+                ("aws_region".to_string(), "eu-west-1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archive_policy() {
+        let path =
+            std::env::temp_dir().join(format!("anomaly-archive-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[service]\n\
+             retention_readings_days = 90\n\
+             [service.archive]\n\
+             url = \"s3://archive/factory\"\n\
+             schedule = \"30 2 * * *\"\n\
+             after_days = 30\n",
+        )
+        .unwrap();
+        let vars: HashMap<String, String> = [(
+            "ANOMALY_ARCHIVE_AWS_REGION".to_string(),
+            "eu-west-1".to_string(),
+        )]
+        .into_iter()
+        .collect();
+        let resolved = Config::resolve(Some(path.clone()), &vars);
+        std::fs::remove_file(&path).unwrap();
+        let resolved = resolved.unwrap();
+
+        let archive = resolved.archive.unwrap();
+        assert_eq!(archive.schedule.to_string(), "30 2 * * *");
+        assert_eq!(archive.after, Duration::from_secs(30 * SECONDS_PER_DAY));
+        assert_eq!(
+            archive.target,
+            ExportTarget {
+                url: "s3://archive/factory".to_string(),
+                options: vec![("aws_region".to_string(), "eu-west-1".to_string())],
+            }
+        );
+        assert!(resolved.retention.archived);
+
+        let archive = |after_days: &str, schedule: &str| {
+            config(&[
+                ("ANOMALY_RETENTION_ANOMALIES_DAYS", "30"),
+                ("ANOMALY_ARCHIVE_URL", "file:///var/archive"),
+                ("ANOMALY_ARCHIVE_SCHEDULE", schedule),
+                ("ANOMALY_ARCHIVE_AFTER_DAYS", after_days),
+            ])
+        };
+        assert!(archive("7", "0 * * * *").is_ok());
+        assert!(archive("30", "0 * * * *").is_err_and(|e| e.contains("retention_anomalies_days")));
+        assert!(archive("7", "0 * * *").is_err_and(|e| e.contains("archive.schedule")));
+        assert!(config(&[("ANOMALY_ARCHIVE_URL", "file:///var/archive")]).is_err());
+    }
+
+    #[test]
+    fn test_analytics_target_from_env() {
+        let config = config(&[
+            (
+                "ANOMALY_ANALYTICS_URL",
+                "http://clickhouse:8123/?database=factory",
+            ),
+            ("ANOMALY_ANALYTICS_BATCH_SIZE", "500"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config.analytics,
+            Some(AnalyticsTarget {
+                url: "http://clickhouse:8123/?database=factory".to_string(),
+                table: "anomalies".to_string(),
+                batch_size: 500,
+                flush_interval: Duration::from_secs(5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_retention_from_env() {
+        let config = config(&[
+            ("ANOMALY_RETENTION_READINGS_DAYS", "30"),
+            ("ANOMALY_RETENTION_ANOMALIES_DAYS", "365"),
+            ("ANOMALY_COMPACTION_INTERVAL_SECS", "600"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            config.retention.readings,
+            Some(Duration::from_secs(30 * SECONDS_PER_DAY))
+        );
+        assert_eq!(
+            config.retention.anomalies,
+            Some(Duration::from_secs(365 * SECONDS_PER_DAY))
+        );
+        assert_eq!(config.retention.interval, Duration::from_secs(600));
+        assert!(config.retention.is_enabled());
+    }
+
+    #[test]
+    fn test_invalid_values_are_rejected() {
+        assert!(config(&[("ANOMALY_RETENTION_READINGS_DAYS", "a month")]).is_err());
+        assert!(config(&[("ANOMALY_COMPACTION_INTERVAL_SECS", "0")]).is_err());
+        assert!(config(&[("ANOMALY_ROLLUP_INTERVAL_SECS", "0")]).is_err());
+        assert!(config(&[("ANOMALY_EXPORT_AWS_REGION", "eu-west-1")]).is_err());
+        assert!(config(&[("ANOMALY_ANALYTICS_BATCH_SIZE", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides_the_file() {
+        let path =
+            std::env::temp_dir().join(format!("anomaly-service-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[service]\n\
+             database_url = \"sqlite://data/factory.db\"\n\
+             rollup_interval_secs = 120\n\
+             retention_readings_days = 7\n\
+             [service.export]\n\
+             url = \"s3://results\"\n\
+             aws_region = \"eu-west-1\"\n\
+             [cli]\n\
+             zscore = 3.0\n",
+        )
+        .unwrap();
+        let vars: HashMap<String, String> = [
+            ("ANOMALY_ROLLUP_INTERVAL_SECS", "30"),
+            ("ANOMALY_EXPORT_AWS_REGION", "us-east-1"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        let config = Config::resolve(Some(path.clone()), &vars);
+        std::fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(
+            config.database_url.as_deref(),
+            Some("sqlite://data/factory.db")
+        );
+        assert_eq!(config.rollup_interval, Duration::from_secs(30));
+        assert_eq!(
+            config.retention.readings,
+            Some(Duration::from_secs(7 * SECONDS_PER_DAY))
+        );
+        let export = config.export.unwrap();
+        assert_eq!(export.url, "s3://results");
+        assert_eq!(
+            export.options,
+            vec![("aws_region".to_string(), "us-east-1".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_severity_labels() {
+        let labels = config(&[
+            ("ANOMALY_SEVERITY_LABELS_HIGH", "P2"),
+            ("ANOMALY_SEVERITY_LABELS_CRITICAL", "P1"),
+        ])
+        .unwrap()
+        .severity_labels;
+        assert_eq!(
+            labels,
+            SeverityLabels {
+                high: "P2".to_string(),
+                critical: "P1".to_string(),
+                ..SeverityLabels::default()
+            }
+        );
+
+        let clash = config(&[("ANOMALY_SEVERITY_LABELS_MEDIUM", "critical")]);
+        assert!(clash.is_err_and(|e| e.contains("severity_labels")));
+    }
+
+    #[test]
+    fn test_slo_targets() {
+        let path = std::env::temp_dir().join(format!("anomaly-slo-{}.toml", std::process::id()));
+        std::fs::write(&path, "[service.slo]\n\"line:a\" = 0.002\n").unwrap();
+        let vars: HashMap<String, String> =
+            [("ANOMALY_SLO_DEFAULT".to_string(), "0.01".to_string())]
+                .into_iter()
+                .collect();
+        let resolved = Config::resolve(Some(path.clone()), &vars);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            resolved.unwrap().slo,
+            BTreeMap::from([("default".to_string(), 0.01), ("line:a".to_string(), 0.002)])
+        );
+
+        let invalid = config(&[("ANOMALY_SLO_DEFAULT", "1.5")]);
+        assert!(invalid.is_err_and(|e| e.contains("slo.default")));
+    }
+
+    #[test]
+    fn test_redaction() {
+        let path =
+            std::env::temp_dir().join(format!("anomaly-redaction-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[service.redaction]\nname = \"hash\"\n\"tag:location\" = \"remove\"\n",
+        )
+        .unwrap();
+        let vars: HashMap<String, String> = [(
+            "ANOMALY_REDACTION_KEY".to_string(),
+            "a long random secret".to_string(),
+        )]
+        .into_iter()
+        .collect();
+        let resolved = Config::resolve(Some(path.clone()), &vars);
+        let without_key = Config::resolve(Some(path.clone()), &HashMap::new());
+        std::fs::remove_file(&path).unwrap();
+        assert!(resolved.unwrap().redaction.is_enabled());
+        assert!(without_key.is_err_and(|e| e.contains("redaction.key")));
+        assert!(!config(&[]).unwrap().redaction.is_enabled());
+    }
+
+    #[test]
+    fn test_access() {
+        assert!(!config(&[]).unwrap().access.is_enabled());
+        let access = config(&[
+            ("ANOMALY_API_KEYS_GRAFANA", "read-only:0123456789abcdef"),
+            ("ANOMALY_JWT_SECRET", "a long random secret"),
+        ])
+        .unwrap()
+        .access;
+        assert!(access.is_enabled());
+
+        let invalid = config(&[("ANOMALY_API_KEYS_GRAFANA", "viewer:0123456789abcdef")]);
+        assert!(invalid.is_err_and(|e| e.contains("api_keys.grafana")));
+        let limited = config(&[
+            ("ANOMALY_API_KEYS_GRAFANA", "read-only:0123456789abcdef"),
+            ("ANOMALY_RATE_LIMITS_DEFAULT", "600"),
+            ("ANOMALY_RATE_LIMITS_GRAFANA", "60"),
+        ]);
+        assert!(limited.unwrap().access.rate_limits().is_enabled());
+        let invalid = config(&[("ANOMALY_RATE_LIMITS_GRAFANA", "0")]);
+        assert!(invalid.is_err_and(|e| e.contains("rate_limits.grafana")));
+    }
+
+    #[test]
+    fn test_quotas() {
+        assert!(!config(&[]).unwrap().quotas.is_enabled());
+        let quotas = config(&[
+            ("ANOMALY_QUOTAS_READINGS_PER_DAY", "1000"),
+            ("ANOMALY_QUOTAS_ACME__READINGS_PER_DAY", "5000"),
+            ("ANOMALY_QUOTAS_ACME__ANALYSES_PER_DAY", "10"),
+        ])
+        .unwrap()
+        .quotas;
+        let acme = quotas.of("acme");
+        assert_eq!(
+            (acme.readings_per_day, acme.analyses_per_day),
+            (Some(5000), Some(10))
+        );
+        assert_eq!(quotas.of("other").readings_per_day, Some(1000));
+
+        let invalid = config(&[("ANOMALY_QUOTAS_ACME__BYTES_PER_DAY", "10")]);
+        assert!(invalid.is_err_and(|e| e.contains("quotas.acme.bytes_per_day")));
+    }
+
+    #[test]
+    fn test_lanes() {
+        assert_eq!(config(&[]).unwrap().lanes, LanePolicy::default());
+        let lanes = config(&[
+            ("ANOMALY_BULK_WORKERS", "3"),
+            ("ANOMALY_INTERACTIVE_LATENCY_BUDGET_MS", "100"),
+        ])
+        .unwrap()
+        .lanes;
+        assert_eq!(
+            lanes,
+            LanePolicy {
+                bulk_workers: 3,
+                latency_budget: Duration::from_millis(100),
+            }
+        );
+        assert!(config(&[("ANOMALY_BULK_WORKERS", "0")]).is_err());
+    }
+
+    #[test]
+    fn test_stream() {
+        assert_eq!(config(&[]).unwrap().stream, StreamPolicy::default());
+        let stream = config(&[
+            ("ANOMALY_STREAM_WINDOW", "20"),
+            ("ANOMALY_STREAM_DECAY", "0.1"),
+        ])
+        .unwrap()
+        .stream;
+        assert_eq!(
+            stream,
+            StreamPolicy {
+                window: 20,
+                decay: Some(0.1),
+                season: 24,
+                max_window: 10_000,
+            }
+        );
+        assert!(config(&[("ANOMALY_STREAM_WINDOW", "0")]).is_err());
+        assert!(
+            config(&[
+                ("ANOMALY_STREAM_WINDOW", "200"),
+                ("ANOMALY_STREAM_MAX_WINDOW", "100"),
+            ])
+            .is_err_and(|e| e.contains("stream_max_window"))
+        );
+        assert!(config(&[("ANOMALY_STREAM_DECAY", "1.5")]).is_err());
+
+        let seasonal = config(&[("ANOMALY_SEASONAL_PERIOD", "96")]).unwrap();
+        assert_eq!(seasonal.stream.season, 96);
+        assert!(config(&[("ANOMALY_SEASONAL_PERIOD", "1")]).is_err());
+    }
+}
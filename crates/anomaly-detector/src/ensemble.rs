@@ -0,0 +1,178 @@
+//! Ensembles: `/analyze` requests and batch series with method `ensemble` run
+//! several registered detectors on the same readings and combine their
+//! verdicts, by majority vote (the default) or by weighted score:
+//!
+//! ```json
+//! {
+//!   "method": "ensemble",
+//!   "ensemble": {
+//!     "combine": "weighted",
+//!     "members": [{ "method": "mad", "weight": 2.0 }, { "method": "iqr", "threshold": 2.0 }]
+//!   }
+//! }
+//! ```
+//!
+//! The response carries every member's score of each anomaly, to see which of
+//! them flagged it.
+
+use detection_core::detector::{Combine, Ensemble, Registry, Verdict};
+use serde::{Deserialize, Serialize};
+
+pub const METHOD: &str = "ensemble";
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnsembleRequest {
+    #[serde(default)]
+    combine: Combine,
+    members: Vec<MemberRequest>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MemberRequest {
+    /// A detector of the registry.
+    method: String,
+    /// Defaults to the detector's own.
+    #[serde(default)]
+    threshold: Option<f64>,
+    #[serde(default = "default_weight")]
+    weight: f64,
+}
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+/// Builds the ensemble a request describes from registered detectors.
+pub fn resolve(detectors: &Registry, request: &EnsembleRequest) -> Result<Ensemble, String> {
+    if request.members.is_empty() {
+        return Err("ensemble has no members".to_string());
+    }
+    let mut ensemble = Ensemble::new(request.combine);
+    for member in &request.members {
+        let detector = detectors.get(&member.method).ok_or_else(|| {
+            format!(
+                "unknown ensemble member {:?}, expected one of {}",
+                member.method,
+                detectors.names().collect::<Vec<_>>().join(", ")
+            )
+        })?;
+        let threshold = member
+            .threshold
+            .unwrap_or_else(|| detector.default_threshold());
+        if !(threshold.is_finite() && threshold > 0.0) {
+            return Err(format!(
+                "threshold of ensemble member {:?} must be positive",
+                member.method
+            ));
+        }
+        if !(member.weight.is_finite() && member.weight >= 0.0) {
+            return Err(format!(
+                "weight of ensemble member {:?} must not be negative",
+                member.method
+            ));
+        }
+        ensemble = ensemble.with(detector, threshold, member.weight);
+    }
+    if request.combine == Combine::Weighted && ensemble.members().iter().all(|m| m.weight == 0.0) {
+        return Err("a weighted ensemble needs a member with a positive weight".to_string());
+    }
+    Ok(ensemble)
+}
+
+/// Per-member scores of an ensemble's anomalies.
+#[derive(Debug, Serialize)]
+pub struct EnsembleReport {
+    combine: Combine,
+    members: Vec<MemberReport>,
+}
+
+#[derive(Debug, Serialize)]
+struct MemberReport {
+    method: &'static str,
+    threshold: f64,
+    weight: f64,
+    /// The member's score of each anomaly, in the order of `anomalies`.
+    scores: Vec<f64>,
+}
+
+/// Reports the member scores of the readings at `flagged`, the positions of
+/// the anomalies among the verdicts.
+pub fn report(ensemble: &Ensemble, verdicts: &[Verdict], flagged: &[usize]) -> EnsembleReport {
+    EnsembleReport {
+        combine: ensemble.combine(),
+        members: ensemble
+            .members()
+            .iter()
+            .enumerate()
+            .map(|(index, member)| MemberReport {
+                method: member.detector.name(),
+                threshold: member.threshold,
+                weight: member.weight,
+                scores: flagged
+                    .iter()
+                    .map(|&position| verdicts[position].members[index])
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(json: serde_json::Value) -> EnsembleRequest {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_fills_in_member_defaults() {
+        let ensemble = resolve(
+            &Registry::builtin(),
+            &request(serde_json::json!({
+                "members": [{ "method": "mad" }, { "method": "iqr", "threshold": 2.0, "weight": 3.0 }]
+            })),
+        )
+        .unwrap();
+
+        assert_eq!(ensemble.combine(), Combine::Majority);
+        let members: Vec<(&str, f64, f64)> = ensemble
+            .members()
+            .iter()
+            .map(|m| (m.detector.name(), m.threshold, m.weight))
+            .collect();
+        assert_eq!(members, [("mad", 3.5, 1.0), ("iqr", 2.0, 3.0)]);
+    }
+
+    #[test]
+    fn test_resolve_rejects_invalid_members() {
+        let registry = Registry::builtin();
+        for (json, message) in [
+            (serde_json::json!({ "members": [] }), "no members"),
+            (
+                serde_json::json!({ "members": [{ "method": "ensemble" }] }),
+                "unknown ensemble member",
+            ),
+            (
+                serde_json::json!({ "members": [{ "method": "mad", "threshold": 0.0 }] }),
+                "must be positive",
+            ),
+            (
+                serde_json::json!({ "members": [{ "method": "mad", "weight": -1.0 }] }),
+                "must not be negative",
+            ),
+            (
+                serde_json::json!({
+                    "combine": "weighted",
+                    "members": [{ "method": "mad", "weight": 0.0 }]
+                }),
+                "positive weight",
+            ),
+        ] {
+            let error = resolve(&registry, &request(json)).err().unwrap();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+}
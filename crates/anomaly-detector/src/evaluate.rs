@@ -0,0 +1,289 @@
+//! `POST /evaluate-labels`: scores detector output against labeled ground
+//! truth with precision, recall and F1.
+//!
+//! The flagged readings are either given as `detections`, e.g. exported from
+//! another detector, or found by running detection over `readings` once per
+//! `candidates` parameter set (the current configuration when none is given).
+//! Both can be mixed in one request.
+//!
+//! ```json
+//! {
+//!   "labels": [9, 14],
+//!   "sensor_id": 7,
+//!   "readings": [{ "id": 1, "value": 10.0, "timestamp": "2026-01-19T10:00:00" }],
+//!   "candidates": [{ "name": "tight", "parameters": { "threshold": 2.0 } }]
+//! }
+//! ```
+
+use std::collections::HashSet;
+
+use axum::{Json, extract::State, http::StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::replay::Parameters;
+use crate::{AnalyzeRequest, AppState, Reading, Scoring, detect, sensors};
+
+/// Maximum parameter sets and detector outputs scored in one request.
+const MAX_SETS: usize = 100;
+
+/// Output of a detector, as the ids of the readings it flagged.
+#[derive(Deserialize)]
+pub struct Detection {
+    name: String,
+    flagged: Vec<i64>,
+}
+
+/// A parameter set to run detection with.
+#[derive(Deserialize)]
+pub struct Candidate {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    parameters: Parameters,
+}
+
+#[derive(Deserialize)]
+pub struct EvaluateRequest {
+    /// Ids of the readings that are true anomalies.
+    labels: Vec<i64>,
+    #[serde(default)]
+    detections: Vec<Detection>,
+    /// Applies the sensor's registry entry to the candidates.
+    #[serde(default)]
+    sensor_id: Option<i64>,
+    #[serde(default)]
+    readings: Vec<Reading>,
+    #[serde(default)]
+    candidates: Vec<Candidate>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Evaluation {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parameters: Option<Parameters>,
+    flagged: usize,
+    true_positives: usize,
+    false_positives: usize,
+    false_negatives: usize,
+    /// `null` when nothing was flagged.
+    precision: Option<f64>,
+    /// `null` when nothing is labeled.
+    recall: Option<f64>,
+    /// `null` when either precision or recall is undefined, or both are 0.
+    f1: Option<f64>,
+}
+
+impl Evaluation {
+    fn score(
+        name: String,
+        parameters: Option<Parameters>,
+        labels: &HashSet<i64>,
+        flagged: &HashSet<i64>,
+    ) -> Self {
+        let true_positives = flagged.intersection(labels).count();
+        let false_positives = flagged.len() - true_positives;
+        let false_negatives = labels.len() - true_positives;
+        let ratio = |n: usize, d: usize| (d > 0).then(|| n as f64 / d as f64);
+        let precision = ratio(true_positives, flagged.len());
+        let recall = ratio(true_positives, labels.len());
+        let f1 = match (precision, recall) {
+            (Some(p), Some(r)) if p + r > 0.0 => Some(2.0 * p * r / (p + r)),
+            _ => None,
+        };
+        Evaluation {
+            name,
+            parameters,
+            flagged: flagged.len(),
+            true_positives,
+            false_positives,
+            false_negatives,
+            precision,
+            recall,
+            f1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvaluateResponse {
+    labels: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    total_readings: Option<usize>,
+    /// In the order of `detections`, then `candidates`.
+    evaluations: Vec<Evaluation>,
+}
+
+fn invalid(message: impl Into<String>) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.into())
+}
+
+fn validate(request: &EvaluateRequest) -> Result<(), (StatusCode, String)> {
+    if !request.candidates.is_empty() && request.readings.is_empty() {
+        return Err(invalid("candidates need readings to run on"));
+    }
+    if request.detections.is_empty() && request.readings.is_empty() {
+        return Err(invalid("give detections to score or readings to run on"));
+    }
+    let sets = request.detections.len() + request.candidates.len().max(1);
+    if sets > MAX_SETS {
+        return Err(invalid(format!(
+            "request contains {} detections and candidates, the limit is {}",
+            sets, MAX_SETS
+        )));
+    }
+    if !request.readings.is_empty() {
+        let ids: HashSet<i64> = request.readings.iter().map(|r| r.id).collect();
+        if let Some(label) = request.labels.iter().find(|id| !ids.contains(id)) {
+            return Err(invalid(format!(
+                "label {} is not among the readings",
+                label
+            )));
+        }
+    }
+    Ok(())
+}
+
+pub async fn evaluate_labels(
+    State(state): State<AppState>,
+    Json(payload): Json<EvaluateRequest>,
+) -> Result<Json<EvaluateResponse>, (StatusCode, String)> {
+    validate(&payload)?;
+    let labels: HashSet<i64> = payload.labels.iter().copied().collect();
+    let mut evaluations = Vec::new();
+    for detection in payload.detections {
+        let flagged = detection.flagged.into_iter().collect();
+        evaluations.push(Evaluation::score(detection.name, None, &labels, &flagged));
+    }
+    if payload.readings.is_empty() {
+        return Ok(Json(EvaluateResponse {
+            labels: labels.len(),
+            total_readings: None,
+            evaluations,
+        }));
+    }
+
+    let registered = sensors::registered(&state, payload.sensor_id).await?;
+    let mut candidates = payload.candidates;
+    if candidates.is_empty() {
+        candidates.push(Candidate {
+            name: Some("current".to_string()),
+            parameters: Parameters::default(),
+        });
+    }
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let name = candidate
+            .name
+            .unwrap_or_else(|| format!("candidate {}", index + 1));
+        let mut sensor = registered.clone().unwrap_or_default();
+        let mut detection = state.settings.detection();
+        candidate
+            .parameters
+            .apply(&mut sensor, &mut detection)
+            .map_err(|e| invalid(format!("{}: {}", name, e)))?;
+        let request = AnalyzeRequest {
+            sensor_id: payload.sensor_id,
+            readings: payload.readings.clone(),
+            method: None,
+            ensemble: None,
+            threshold: None,
+            seed: None,
+            severity: None,
+            export: None,
+            window: None,
+        };
+        let response = detect(request, &Scoring::ZScore, &detection, Some(&sensor));
+        let flagged = response.anomalies.iter().map(|a| a.id).collect();
+        evaluations.push(Evaluation::score(
+            name,
+            Some(candidate.parameters),
+            &labels,
+            &flagged,
+        ));
+    }
+
+    Ok(Json(EvaluateResponse {
+        labels: labels.len(),
+        total_readings: Some(payload.readings.len()),
+        evaluations,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(value: serde_json::Value) -> Json<EvaluateRequest> {
+        Json(serde_json::from_value(value).unwrap())
+    }
+
+    fn readings() -> serde_json::Value {
+        let values = [10.0, 12.0, 11.0, 11.5, 10.5, 11.0, 10.8, 14.0, 200.0];
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                json!({
+                    "id": i + 1,
+                    "value": value,
+                    "timestamp": format!("2026-01-19T10:0{}:00", i),
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_evaluates_detections_and_candidates() {
+        let Json(response) = evaluate_labels(
+            State(AppState::default()),
+            request(json!({
+                "labels": [8, 9],
+                "detections": [{ "name": "external", "flagged": [9, 3] }],
+                "readings": readings(),
+                "candidates": [
+                    { "name": "limits", "parameters": { "max_value": 13.0 } },
+                    { "parameters": { "threshold": 50.0 } },
+                ],
+            })),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(response.labels, 2);
+        assert_eq!(response.total_readings, Some(9));
+        let external = &response.evaluations[0];
+        assert_eq!((external.true_positives, external.false_positives), (1, 1));
+        assert_eq!(external.precision, Some(0.5));
+        assert_eq!(external.f1, Some(0.5));
+
+        let limits = &response.evaluations[1];
+        assert_eq!(limits.name, "limits");
+        assert_eq!((limits.precision, limits.recall), (Some(1.0), Some(1.0)));
+
+        let loose = &response.evaluations[2];
+        assert_eq!(loose.name, "candidate 2");
+        assert_eq!(loose.flagged, 0);
+        assert_eq!(loose.false_negatives, 2);
+        assert_eq!((loose.precision, loose.recall), (None, Some(0.0)));
+        assert_eq!(loose.f1, None);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_requests() {
+        let invalid = [
+            json!({ "labels": [1] }),
+            json!({ "labels": [1], "candidates": [{}], "detections": [] }),
+            json!({ "labels": [42], "readings": readings() }),
+            json!({
+                "labels": [1],
+                "readings": readings(),
+                "candidates": [{ "parameters": { "threshold": 0.0 } }],
+            }),
+        ];
+        for value in invalid {
+            let result = evaluate_labels(State(AppState::default()), request(value)).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Live anomaly events pushed to WebSocket clients at `/ws/anomalies`.
+//!
+//! Anomalies found by `/analyze` requests and batch series that name a
+//! `sensor_id`, by `/stream/ingest` and `/stream`, and by backfills with
+//! `notify`, are published as they are detected. Each client only receives the events matching its subscription,
+//! so a dashboard panel for a few sensors does not get the whole stream.
+//!
+//! A client subscribes with `?sensors=1,2&min_severity=high` and can replace
+//! its subscription at any time by sending one as JSON:
+//!
+//! ```json
+//! { "sensors": [1, 2], "min_severity": "high" }
+//! ```
+//!
+//! Every message to the client is JSON with a `type` of `subscribed`,
+//! `anomaly`, `lagged` (events were dropped because the client fell behind)
+//! or `error`.
+
+use axum::{
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::StatusCode,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::AppState;
+use crate::notify::Severity;
+use crate::schemas;
+use crate::storage::StoredAnomaly;
+
+/// Events buffered per client before it is told it lagged.
+const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct AnomalyEvent {
+    pub sensor_id: i64,
+    pub reading_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub method: String,
+    pub score: f64,
+    #[serde(serialize_with = "detection_core::severity::labelled")]
+    pub severity: Severity,
+}
+
+impl From<&StoredAnomaly> for AnomalyEvent {
+    fn from(anomaly: &StoredAnomaly) -> Self {
+        AnomalyEvent {
+            sensor_id: anomaly.sensor_id,
+            reading_id: anomaly.reading_id,
+            value: anomaly.value,
+            timestamp: anomaly.timestamp.clone(),
+            method: anomaly.method.clone(),
+            score: anomaly.score,
+            severity: anomaly.severity,
+        }
+    }
+}
+
+pub struct Events {
+    sender: broadcast::Sender<AnomalyEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    /// Sends events to every connected client; a no-op without clients.
+    pub fn publish(&self, events: impl IntoIterator<Item = AnomalyEvent>) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+        for event in events {
+            schemas::check_alert(&event);
+            let _ = self.sender.send(event);
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AnomalyEvent> {
+        self.sender.subscribe()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Subscription {
+    /// Sensors to receive events for; all of them when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sensors: Option<Vec<i64>>,
+    #[serde(default)]
+    min_severity: Severity,
+}
+
+impl Subscription {
+    fn matches(&self, event: &AnomalyEvent) -> bool {
+        self.sensors
+            .as_ref()
+            .is_none_or(|sensors| sensors.contains(&event.sensor_id))
+            && event.severity >= self.min_severity
+    }
+}
+
+#[derive(Deserialize)]
+pub struct SubscriptionQuery {
+    /// Comma-separated sensor ids.
+    sensors: Option<String>,
+    min_severity: Option<Severity>,
+}
+
+impl TryFrom<SubscriptionQuery> for Subscription {
+    type Error = String;
+
+    fn try_from(query: SubscriptionQuery) -> Result<Self, String> {
+        let sensors = query
+            .sensors
+            .map(|raw| {
+                raw.split(',')
+                    .map(|id| {
+                        id.trim()
+                            .parse()
+                            .map_err(|_| format!("invalid sensor id {:?}", id))
+                    })
+                    .collect::<Result<Vec<i64>, _>>()
+            })
+            .transpose()?;
+        Ok(Subscription {
+            sensors,
+            min_severity: query.min_severity.unwrap_or_default(),
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Outgoing<'a> {
+    Subscribed { subscription: &'a Subscription },
+    Anomaly(&'a AnomalyEvent),
+    Lagged { missed: u64 },
+    Error { error: String },
+}
+
+/// Upgrades to a WebSocket streaming the anomalies matching the query.
+pub async fn subscribe(
+    State(state): State<AppState>,
+    Query(query): Query<SubscriptionQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, String)> {
+    let subscription = Subscription::try_from(query).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let events = state.events.subscribe();
+    Ok(ws.on_upgrade(move |socket| forward(socket, events, subscription)))
+}
+
+async fn send(socket: &mut WebSocket, message: &Outgoing<'_>) -> bool {
+    let Ok(text) = serde_json::to_string(message) else {
+        return false;
+    };
+    socket.send(Message::Text(text.into())).await.is_ok()
+}
+
+/// Forwards matching events until either side closes.
+async fn forward(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<AnomalyEvent>,
+    mut subscription: Subscription,
+) {
+    let subscribed = Outgoing::Subscribed {
+        subscription: &subscription,
+    };
+    if !send(&mut socket, &subscribed).await {
+        return;
+    }
+    loop {
+        let sent = tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) if subscription.matches(&event) => {
+                    send(&mut socket, &Outgoing::Anomaly(&event)).await
+                }
+                Ok(_) => true,
+                Err(RecvError::Lagged(missed)) => {
+                    send(&mut socket, &Outgoing::Lagged { missed }).await
+                }
+                Err(RecvError::Closed) => false,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(update) => {
+                        subscription = update;
+                        let subscribed = Outgoing::Subscribed {
+                            subscription: &subscription,
+                        };
+                        send(&mut socket, &subscribed).await
+                    }
+                    Err(e) => {
+                        let error = format!("invalid subscription: {}", e);
+                        send(&mut socket, &Outgoing::Error { error }).await
+                    }
+                },
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => false,
+                Some(Ok(_)) => true,
+            },
+        };
+        if !sent {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(sensor_id: i64, severity: Severity) -> AnomalyEvent {
+        AnomalyEvent {
+            sensor_id,
+            reading_id: 1,
+            value: 100.0,
+            timestamp: "2026-01-19 10:00:00".to_string(),
+            method: "zscore".to_string(),
+            score: 3.0,
+            severity,
+        }
+    }
+
+    #[test]
+    fn test_subscription_from_query() {
+        let subscription = Subscription::try_from(SubscriptionQuery {
+            sensors: Some("1, 2".to_string()),
+            min_severity: Some(Severity::High),
+        })
+        .unwrap();
+
+        assert!(subscription.matches(&event(2, Severity::Critical)));
+        assert!(!subscription.matches(&event(2, Severity::Medium)));
+        assert!(!subscription.matches(&event(3, Severity::Critical)));
+        assert!(Subscription::default().matches(&event(3, Severity::Medium)));
+
+        let invalid = SubscriptionQuery {
+            sensors: Some("1,x".to_string()),
+            min_severity: None,
+        };
+        assert!(Subscription::try_from(invalid).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publish_reaches_subscribers() {
+        let events = Events::default();
+        events.publish([event(1, Severity::High)]);
+        let mut receiver = events.subscribe();
+
+        events.publish([event(2, Severity::High), event(3, Severity::Critical)]);
+
+        assert_eq!(receiver.recv().await.unwrap().sensor_id, 2);
+        assert_eq!(receiver.recv().await.unwrap().sensor_id, 3);
+        assert!(receiver.try_recv().is_err());
+    }
+}
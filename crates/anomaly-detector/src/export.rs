@@ -0,0 +1,108 @@
+//! CSV output selected by the `Accept` header.
+
+use axum::{
+    http::{HeaderMap, HeaderValue, header},
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+pub const CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+/// Whether the client prefers `text/csv` over JSON.
+///
+/// JSON wins ties and is the default when the header is absent, so existing
+/// clients that send `Accept: */*` keep getting JSON.
+pub fn wants_csv(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    let mut csv_q: f32 = 0.0;
+    let mut json_q: f32 = 0.0;
+    for entry in accept.split(',') {
+        let mut parts = entry.split(';');
+        let media = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = parts
+            .filter_map(|p| p.trim().strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        match media.as_str() {
+            "text/csv" => csv_q = csv_q.max(q),
+            "application/json" | "*/*" => json_q = json_q.max(q),
+            _ => {}
+        }
+    }
+
+    csv_q > json_q
+}
+
+/// Encodes `rows` as CSV, with a header row if `headers` is set.
+pub fn encode<T: Serialize>(rows: &[T], headers: bool) -> Result<Vec<u8>, csv::Error> {
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(headers)
+        .from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.into_inner().map_err(|e| e.into_error().into())
+}
+
+/// Builds a `text/csv` response from already-encoded rows.
+pub fn csv_response(body: impl Into<axum::body::Body>) -> Response {
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(CSV_CONTENT_TYPE),
+        )],
+        body.into(),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_wants_csv() {
+        assert!(!wants_csv(&HeaderMap::new()));
+        assert!(!wants_csv(&accept("*/*")));
+        assert!(!wants_csv(&accept("application/json")));
+        assert!(wants_csv(&accept("text/csv")));
+        assert!(wants_csv(&accept("text/csv, application/json;q=0.5")));
+        assert!(!wants_csv(&accept("text/csv;q=0.5, application/json")));
+        assert!(!wants_csv(&accept("text/csv, */*")));
+    }
+
+    #[derive(Serialize)]
+    struct Row {
+        id: i64,
+        label: &'static str,
+    }
+
+    #[test]
+    fn test_encode() {
+        let rows = [
+            Row { id: 1, label: "a" },
+            Row {
+                id: 2,
+                label: "b,c",
+            },
+        ];
+        assert_eq!(
+            String::from_utf8(encode(&rows, true).unwrap()).unwrap(),
+            "id,label\n1,a\n2,\"b,c\"\n"
+        );
+        assert_eq!(
+            String::from_utf8(encode(&rows[..1], false).unwrap()).unwrap(),
+            "1,a\n"
+        );
+    }
+}
@@ -0,0 +1,354 @@
+//! `POST /generate`: synthetic series for integration tests and detector
+//! benchmarks.
+//!
+//! A series is a baseline plus a linear trend, any number of sine seasonal
+//! components and Gaussian noise, with spikes and dips injected at random
+//! points whose locations are returned. The same `seed` always produces the
+//! same series, and the readings can be posted to `/analyze` as they are.
+//!
+//! ```json
+//! {
+//!   "seed": 42,
+//!   "points": 1440,
+//!   "trend": 0.001,
+//!   "seasonality": [{ "period": 1440, "amplitude": 5.0 }],
+//!   "anomalies": { "count": 3, "magnitude": 12.0 }
+//! }
+//! ```
+
+use std::f64::consts::TAU;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{Json, http::StatusCode};
+use detection_core::stats::box_muller;
+use serde::{Deserialize, Serialize};
+
+use crate::time::{format_timestamp, parse_timestamp};
+
+/// Maximum points generated in one request.
+const MAX_POINTS: usize = 100_000;
+
+/// SplitMix64: small, fast and the same on every platform and release, so a
+/// seed keeps reproducing the same series.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        box_muller(u, self.next_f64())
+    }
+
+    /// Uniform in `0..n`.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_f64() * n as f64) as usize
+    }
+}
+
+fn default_points() -> usize {
+    1_000
+}
+
+fn default_start() -> String {
+    "2026-01-01T00:00:00".to_string()
+}
+
+fn default_interval() -> u64 {
+    60
+}
+
+fn default_baseline() -> f64 {
+    50.0
+}
+
+fn default_noise() -> f64 {
+    1.0
+}
+
+fn default_magnitude() -> f64 {
+    10.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Seasonality {
+    /// In points.
+    period: f64,
+    amplitude: f64,
+    /// Fraction of a period to shift the component by.
+    #[serde(default)]
+    phase: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Injection {
+    #[serde(default)]
+    count: usize,
+    /// How far an injected reading is from its expected value.
+    #[serde(default = "default_magnitude")]
+    magnitude: f64,
+}
+
+impl Default for Injection {
+    fn default() -> Self {
+        Injection {
+            count: 0,
+            magnitude: default_magnitude(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateRequest {
+    /// Picked from the clock, and returned, when absent.
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default = "default_points")]
+    points: usize,
+    /// Timestamp of the first reading, as `YYYY-MM-DDTHH:MM:SS`.
+    #[serde(default = "default_start")]
+    start: String,
+    #[serde(default = "default_interval")]
+    interval_seconds: u64,
+    #[serde(default = "default_baseline")]
+    baseline: f64,
+    /// Change per point.
+    #[serde(default)]
+    trend: f64,
+    #[serde(default)]
+    seasonality: Vec<Seasonality>,
+    /// Standard deviation of the Gaussian noise.
+    #[serde(default = "default_noise")]
+    noise: f64,
+    #[serde(default)]
+    anomalies: Injection,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Spike,
+    Dip,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GeneratedReading {
+    id: i64,
+    value: f64,
+    timestamp: String,
+}
+
+/// Where an anomaly was injected.
+#[derive(Debug, Serialize)]
+pub struct InjectedAnomaly {
+    /// Id of the injected reading, usable as a label for `/evaluate-labels`.
+    id: i64,
+    timestamp: String,
+    value: f64,
+    /// The value the reading would have had without the anomaly, noise
+    /// included.
+    expected: f64,
+    kind: Kind,
+}
+
+#[derive(Debug, Serialize)]
+pub struct GenerateResponse {
+    seed: u64,
+    readings: Vec<GeneratedReading>,
+    anomalies: Vec<InjectedAnomaly>,
+}
+
+impl GenerateRequest {
+    fn validate(&self) -> Result<(), String> {
+        if self.points == 0 || self.points > MAX_POINTS {
+            return Err(format!("points must be between 1 and {}", MAX_POINTS));
+        }
+        if self.interval_seconds == 0 {
+            return Err("interval_seconds must be positive".to_string());
+        }
+        for (name, value) in [
+            ("baseline", self.baseline),
+            ("trend", self.trend),
+            ("anomalies.magnitude", self.anomalies.magnitude),
+        ] {
+            if !value.is_finite() {
+                return Err(format!("{} must be a finite number", name));
+            }
+        }
+        if !self.noise.is_finite() || self.noise < 0.0 {
+            return Err("noise must be a non-negative number".to_string());
+        }
+        for component in &self.seasonality {
+            if !component.period.is_finite() || component.period <= 0.0 {
+                return Err("seasonality period must be a positive number".to_string());
+            }
+            if !component.amplitude.is_finite() || !component.phase.is_finite() {
+                return Err("seasonality amplitude and phase must be finite".to_string());
+            }
+        }
+        if self.anomalies.count > self.points {
+            return Err("anomalies.count cannot exceed points".to_string());
+        }
+        Ok(())
+    }
+}
+
+fn generate(request: &GenerateRequest, seed: u64, start: i64) -> GenerateResponse {
+    let mut rng = Rng(seed);
+    let mut readings: Vec<GeneratedReading> = (0..request.points)
+        .map(|i| {
+            let t = i as f64;
+            let seasonal: f64 = request
+                .seasonality
+                .iter()
+                .map(|s| s.amplitude * (TAU * (t / s.period + s.phase)).sin())
+                .sum();
+            let noise = request.noise * rng.normal();
+            GeneratedReading {
+                id: i as i64 + 1,
+                value: request.baseline + request.trend * t + seasonal + noise,
+                timestamp: format_timestamp(start + (i as u64 * request.interval_seconds) as i64),
+            }
+        })
+        .collect();
+
+    // A partial Fisher-Yates shuffle picks distinct points.
+    let mut indices: Vec<usize> = (0..request.points).collect();
+    for i in 0..request.anomalies.count {
+        let j = i + rng.below(request.points - i);
+        indices.swap(i, j);
+    }
+    let mut chosen = indices[..request.anomalies.count].to_vec();
+    chosen.sort_unstable();
+    let anomalies = chosen
+        .into_iter()
+        .map(|index| {
+            let kind = if rng.next_f64() < 0.5 {
+                Kind::Spike
+            } else {
+                Kind::Dip
+            };
+            let reading = &mut readings[index];
+            let expected = reading.value;
+            reading.value += match kind {
+                Kind::Spike => request.anomalies.magnitude,
+                Kind::Dip => -request.anomalies.magnitude,
+            };
+            InjectedAnomaly {
+                id: reading.id,
+                timestamp: reading.timestamp.clone(),
+                value: reading.value,
+                expected,
+                kind,
+            }
+        })
+        .collect();
+
+    GenerateResponse {
+        seed,
+        readings,
+        anomalies,
+    }
+}
+
+pub async fn generate_series(
+    Json(payload): Json<GenerateRequest>,
+) -> Result<Json<GenerateResponse>, (StatusCode, String)> {
+    payload
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let start = parse_timestamp(&payload.start).ok_or((
+        StatusCode::BAD_REQUEST,
+        "start must be a timestamp like 2026-01-01T00:00:00".to_string(),
+    ))?;
+    let seed = payload.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default()
+    });
+    let response = tokio::task::spawn_blocking(move || generate(&payload, seed, start))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("generation failed: {}", e),
+            )
+        })?;
+    Ok(Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AnalyzeRequest, AppState, analyze};
+    use axum::extract::State;
+    use serde_json::json;
+
+    fn request(value: serde_json::Value) -> Json<GenerateRequest> {
+        Json(serde_json::from_value(value).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_seeded_series_is_reproducible_and_detectable() {
+        let spec = json!({
+            "seed": 7,
+            "points": 500,
+            "interval_seconds": 3600,
+            "seasonality": [{ "period": 24, "amplitude": 2.0 }],
+            "anomalies": { "count": 4, "magnitude": 25.0 },
+        });
+        let Json(first) = generate_series(request(spec.clone())).await.unwrap();
+        let Json(second) = generate_series(request(spec)).await.unwrap();
+        assert_eq!(
+            serde_json::to_value(&first).unwrap(),
+            serde_json::to_value(&second).unwrap()
+        );
+        assert_eq!(first.readings.len(), 500);
+        assert_eq!(first.readings[25].timestamp, "2026-01-02T01:00:00");
+        assert_eq!(first.anomalies.len(), 4);
+
+        let analyzed: AnalyzeRequest = serde_json::from_value(json!({
+            "readings": serde_json::to_value(&first.readings).unwrap(),
+            "threshold": 5.0,
+        }))
+        .unwrap();
+        let Json(response) = analyze(State(AppState::default()), Json(analyzed))
+            .await
+            .unwrap();
+        let mut found: Vec<i64> = response.anomalies.iter().map(|a| a.id).collect();
+        found.sort_unstable();
+        let injected: Vec<i64> = first.anomalies.iter().map(|a| a.id).collect();
+        assert_eq!(found, injected);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_requests() {
+        let invalid = [
+            json!({ "points": 0 }),
+            json!({ "noise": -1.0 }),
+            json!({ "seasonality": [{ "period": 0.0, "amplitude": 1.0 }] }),
+            json!({ "points": 3, "anomalies": { "count": 4 } }),
+            json!({ "start": "yesterday" }),
+        ];
+        for value in invalid {
+            let result = generate_series(request(value)).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+//! The gRPC service of `proto/anomaly.proto`, served on `grpc_port` beside
+//! the HTTP API for services that speak gRPC.
+//!
+//! `Analyze` scores readings like `POST /analyze`, with the same detectors,
+//! registry entries, usage charges and lanes; ensembles and exports are
+//! left to HTTP. `StreamReadings` takes messages of readings, each with the
+//! settings `/stream` reads from its query string, and answers every one
+//! with the anomalies among its readings or an error, sharing the sensors'
+//! windows with `/stream` and `/stream/ingest`.
+//!
+//! Callers present their credential as over HTTP, in `authorization:
+//! Bearer <credential>` or `x-api-key` metadata, and need the role of the
+//! HTTP detection routes (`operator` with storage, else `read-only`); a
+//! stream is admitted, and counted against its caller's rate limit,
+//! once. Other metadata is read as headers, e.g. `x-priority: bulk`.
+//! Refusals carry the gRPC code of their HTTP status: `INVALID_ARGUMENT`
+//! for 400 and 422, `UNAUTHENTICATED` for 401, `PERMISSION_DENIED` for 403,
+//! `NOT_FOUND` for 404 and `RESOURCE_EXHAUSTED` for 429.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use axum::http::{HeaderMap, StatusCode};
+use detection_core::SeverityLabels;
+use futures_util::stream::{self, Stream, StreamExt};
+use tokio::net::TcpListener;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use crate::access::{self, Access};
+use crate::aggregate::WindowAggregate;
+use crate::stream::{Pushed, StreamQuery, receive_readings};
+use crate::{
+    AnalyzeRequest, AnalyzeResponse, Anomaly, AppState, Reading, SensorAnalysis, SeverityRequest,
+    analyze_metered, detection_role,
+};
+
+pub mod proto {
+    tonic::include_proto!("anomaly.v1");
+}
+
+use proto::anomaly_detector_server::{AnomalyDetector, AnomalyDetectorServer};
+use proto::stream_event::Event;
+
+/// Serves the gRPC service on `listener` until the process exits.
+pub async fn serve(listener: TcpListener, state: AppState, access: Arc<Access>) {
+    let incoming = stream::unfold(listener, |listener| async move {
+        let accepted = listener.accept().await.map(|(socket, _)| socket);
+        Some((accepted, listener))
+    });
+    let service = AnomalyDetectorServer::new(Service { state, access });
+    if let Err(e) = tonic::transport::Server::builder()
+        .add_service(service)
+        .serve_with_incoming(incoming)
+        .await
+    {
+        eprintln!("Error: gRPC server failed: {}", e);
+    }
+}
+
+struct Service {
+    state: AppState,
+    access: Arc<Access>,
+}
+
+impl Service {
+    /// The call's metadata as headers naming its caller, if it may call.
+    fn admit(&self, metadata: &MetadataMap) -> Result<HeaderMap, Status> {
+        let mut headers = metadata.clone().into_headers();
+        if let Some(identity) = self
+            .access
+            .admit(&headers, detection_role(&self.state))
+            .map_err(status)?
+        {
+            access::stamp(&mut headers, &identity);
+        }
+        Ok(headers)
+    }
+}
+
+type Events = Pin<Box<dyn Stream<Item = Result<proto::StreamEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AnomalyDetector for Service {
+    async fn analyze(
+        &self,
+        request: Request<proto::AnalyzeRequest>,
+    ) -> Result<Response<proto::AnalyzeResponse>, Status> {
+        let headers = self.admit(request.metadata())?;
+        let payload = AnalyzeRequest::from(request.into_inner());
+        let response = analyze_metered(&self.state, &headers, payload)
+            .await
+            .map_err(|e| status(e.into()))?;
+        Ok(Response::new(response.into()))
+    }
+
+    type StreamReadingsStream = Events;
+
+    async fn stream_readings(
+        &self,
+        request: Request<Streaming<proto::StreamRequest>>,
+    ) -> Result<Response<Events>, Status> {
+        let headers = self.admit(request.metadata())?;
+        let state = self.state.clone();
+        let events = request.into_inner().then(move |message| {
+            let (state, headers) = (state.clone(), headers.clone());
+            async move {
+                let events: Vec<Result<proto::StreamEvent, Status>> = match message {
+                    Ok(message) => {
+                        let query = StreamQuery {
+                            sensor_id: message.sensor_id,
+                            threshold: message.threshold,
+                            window: message
+                                .window
+                                .map(|window| usize::try_from(window).unwrap_or(usize::MAX)),
+                            decay: message.decay,
+                            method: message.method,
+                        };
+                        let readings = message.readings.into_iter().map(Reading::from).collect();
+                        receive_readings(&state, &headers, &query, readings)
+                            .await
+                            .into_iter()
+                            .map(|pushed| Ok(pushed.into()))
+                            .collect()
+                    }
+                    Err(status) => vec![Err(status)],
+                };
+                stream::iter(events)
+            }
+        });
+        Ok(Response::new(Box::pin(events.flatten())))
+    }
+}
+
+/// The gRPC status of a refusal answered over HTTP with `code`.
+fn status((code, message): (StatusCode, String)) -> Status {
+    let code = match code {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::FORBIDDEN => Code::PermissionDenied,
+        StatusCode::NOT_FOUND => Code::NotFound,
+        StatusCode::TOO_MANY_REQUESTS => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    Status::new(code, message)
+}
+
+impl From<proto::Reading> for Reading {
+    fn from(reading: proto::Reading) -> Self {
+        Reading {
+            id: reading.id,
+            value: reading.value,
+            timestamp: reading.timestamp,
+            sensor_id: reading.sensor_id,
+        }
+    }
+}
+
+impl From<proto::AnalyzeRequest> for AnalyzeRequest {
+    fn from(request: proto::AnalyzeRequest) -> Self {
+        AnalyzeRequest {
+            sensor_id: request.sensor_id,
+            readings: request.readings.into_iter().map(Reading::from).collect(),
+            method: request.method,
+            threshold: request.threshold,
+            ensemble: None,
+            seed: request.seed,
+            severity: severity(request.severity, request.severity_labels),
+            export: None,
+            window: request.window,
+        }
+    }
+}
+
+/// The `severity` of an `/analyze` request with the bands and labels of a
+/// gRPC one.
+fn severity(
+    bands: Option<proto::SeverityBands>,
+    labels: Option<proto::SeverityLabels>,
+) -> Option<SeverityRequest> {
+    if bands.is_none() && labels.is_none() {
+        return None;
+    }
+    let canonical = SeverityLabels::default();
+    Some(SeverityRequest {
+        high: bands.and_then(|bands| bands.high),
+        critical: bands.and_then(|bands| bands.critical),
+        labels: labels.map(|labels| SeverityLabels {
+            medium: labels.medium.unwrap_or(canonical.medium),
+            high: labels.high.unwrap_or(canonical.high),
+            critical: labels.critical.unwrap_or(canonical.critical),
+        }),
+    })
+}
+
+impl From<Anomaly> for proto::Anomaly {
+    fn from(anomaly: Anomaly) -> Self {
+        proto::Anomaly {
+            severity: anomaly.label().into_owned(),
+            id: anomaly.id,
+            value: anomaly.value,
+            timestamp: anomaly.timestamp,
+            z_score: anomaly.z_score,
+        }
+    }
+}
+
+impl From<WindowAggregate> for proto::WindowAggregate {
+    fn from(window: WindowAggregate) -> Self {
+        proto::WindowAggregate {
+            start: window.start,
+            end: window.end,
+            count: window.count as u64,
+            min: window.min,
+            max: window.max,
+            mean: window.mean,
+            anomaly_count: window.anomaly_count as u64,
+        }
+    }
+}
+
+impl From<AnalyzeResponse> for proto::AnalyzeResponse {
+    fn from(response: AnalyzeResponse) -> Self {
+        let anomalies = |anomalies: Vec<Anomaly>| anomalies.into_iter().map(Into::into).collect();
+        let windows = |windows: Vec<WindowAggregate>| windows.into_iter().map(Into::into).collect();
+        proto::AnalyzeResponse {
+            anomalies: anomalies(response.anomalies),
+            total_readings: response.total_readings as u64,
+            mean: response.mean,
+            std_dev: response.std_dev,
+            sensors: response
+                .sensors
+                .into_iter()
+                .map(
+                    |SensorAnalysis { sensor_id, result }| proto::SensorAnalysis {
+                        sensor_id,
+                        anomalies: anomalies(result.anomalies),
+                        total_readings: result.total_readings as u64,
+                        mean: result.mean,
+                        std_dev: result.std_dev,
+                        windows: windows(result.windows),
+                    },
+                )
+                .collect(),
+            windows: windows(response.windows),
+        }
+    }
+}
+
+impl From<Pushed> for proto::StreamEvent {
+    fn from(pushed: Pushed) -> Self {
+        let event = match pushed {
+            Pushed::Anomaly(event) => Event::Anomaly(proto::AnomalyEvent {
+                sensor_id: event.sensor_id,
+                reading_id: event.reading_id,
+                value: event.value,
+                timestamp: event.timestamp,
+                method: event.method,
+                score: event.score,
+                severity: event.severity.label().into_owned(),
+            }),
+            Pushed::Error { error, field } => Event::Error(proto::ErrorEvent { error, field }),
+        };
+        proto::StreamEvent { event: Some(event) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use proto::anomaly_detector_client::AnomalyDetectorClient;
+
+    const KEY: &str = "dashboard-key-0123456789";
+
+    fn reading(id: i64, value: f64, sensor_id: Option<i64>) -> proto::Reading {
+        proto::Reading {
+            id,
+            value,
+            timestamp: format!("2026-01-19T10:{:02}:00", id),
+            sensor_id,
+        }
+    }
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        request
+            .metadata_mut()
+            .insert("x-api-key", KEY.parse().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_analyze_and_stream_readings() {
+        let keys = BTreeMap::from([("dashboard".to_string(), format!("read-only:{}", KEY))]);
+        let access = Access::new(&keys, None, access::DEFAULT_ROLE_CLAIM).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, AppState::default(), Arc::new(access)));
+        let mut client = AnomalyDetectorClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+
+        let mut readings: Vec<proto::Reading> =
+            (1..=20).map(|id| reading(id, 50.0, None)).collect();
+        readings.push(reading(21, 500.0, None));
+        let request = proto::AnalyzeRequest {
+            readings,
+            threshold: Some(2.0),
+            ..proto::AnalyzeRequest::default()
+        };
+        let refused = client.analyze(request.clone()).await.unwrap_err();
+        assert_eq!(refused.code(), Code::Unauthenticated);
+        let response = client
+            .analyze(authorized(request.clone()))
+            .await
+            .unwrap()
+            .into_inner();
+        let anomalies: Vec<(i64, &str)> = response
+            .anomalies
+            .iter()
+            .map(|anomaly| (anomaly.id, anomaly.severity.as_str()))
+            .collect();
+        assert_eq!(anomalies, [(21, "critical")]);
+        assert_eq!(response.total_readings, 21);
+        let invalid = proto::AnalyzeRequest {
+            method: Some("magic".to_string()),
+            ..request
+        };
+        let invalid = client.analyze(authorized(invalid)).await.unwrap_err();
+        assert_eq!(invalid.code(), Code::InvalidArgument);
+
+        let steady = [10.0, 10.5, 9.5, 10.2, 9.8];
+        let messages = vec![
+            proto::StreamRequest {
+                readings: (1..)
+                    .zip(steady)
+                    .map(|(id, v)| reading(id, v, None))
+                    .collect(),
+                sensor_id: Some(7),
+                window: Some(4),
+                ..proto::StreamRequest::default()
+            },
+            proto::StreamRequest {
+                readings: vec![reading(6, 10.1, None), reading(7, 30.0, None)],
+                sensor_id: Some(7),
+                window: Some(4),
+                ..proto::StreamRequest::default()
+            },
+            proto::StreamRequest {
+                readings: vec![reading(8, 10.0, None)],
+                ..proto::StreamRequest::default()
+            },
+            // Refused whole, leaving the window to score the next message.
+            proto::StreamRequest {
+                readings: vec![reading(9, 10.0, None), reading(10, f64::NAN, None)],
+                sensor_id: Some(7),
+                window: Some(4),
+                ..proto::StreamRequest::default()
+            },
+            proto::StreamRequest {
+                readings: vec![reading(11, 1000.0, None)],
+                sensor_id: Some(7),
+                window: Some(4),
+                ..proto::StreamRequest::default()
+            },
+        ];
+        let events: Vec<Event> = client
+            .stream_readings(authorized(stream::iter(messages)))
+            .await
+            .unwrap()
+            .into_inner()
+            .map(|event| event.unwrap().event.unwrap())
+            .collect()
+            .await;
+        let [
+            Event::Anomaly(anomaly),
+            Event::Error(unnamed),
+            Event::Error(not_finite),
+            Event::Anomaly(after),
+        ] = events.as_slice()
+        else {
+            panic!("expected anomalies and errors, got {:?}", events);
+        };
+        assert_eq!((anomaly.sensor_id, anomaly.reading_id), (7, 7));
+        assert_eq!(anomaly.method, "rolling");
+        assert_eq!(unnamed.error, "reading 8 names no sensor_id");
+        assert_eq!(unnamed.field, None);
+        assert_eq!(
+            not_finite.error,
+            "readings[1].value must be a finite number, got NaN"
+        );
+        assert_eq!(not_finite.field.as_deref(), Some("readings[1].value"));
+        assert_eq!((after.sensor_id, after.reading_id), (7, 11));
+        assert!(after.score.is_finite(), "{}", after.score);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_keeps_the_runtime_band_left_out() {
+        let access = Access::new(&BTreeMap::new(), None, access::DEFAULT_ROLE_CLAIM).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(serve(listener, AppState::default(), Arc::new(access)));
+        let client = AnomalyDetectorClient::connect(format!("http://{}", address))
+            .await
+            .unwrap();
+
+        let mut readings: Vec<proto::Reading> =
+            (1..=20).map(|id| reading(id, 50.0, None)).collect();
+        readings.push(reading(21, 500.0, None));
+        let severity = |high, critical| {
+            let request = proto::AnalyzeRequest {
+                readings: readings.clone(),
+                threshold: Some(1.0),
+                severity: Some(proto::SeverityBands { high, critical }),
+                ..proto::AnalyzeRequest::default()
+            };
+            let mut client = client.clone();
+            async move {
+                let response = client.analyze(request).await.unwrap().into_inner();
+                response.anomalies[0].severity.clone()
+            }
+        };
+        // The spike's |z| is about 4.4, past the runtime critical band of 3.
+        assert_eq!(severity(Some(1.5), None).await, "critical");
+        assert_eq!(severity(None, Some(10.0)).await, "high");
+    }
+}
@@ -0,0 +1,159 @@
+//! Root-cause hints: changes made through the API to a sensor's registry
+//! entry, tags or shadow detector, or to the runtime settings, shortly
+//! before it turned anomalous, taken from the audit log (see
+//! [`crate::audit`]). A threshold lowered an hour before a burst of
+//! anomalies is usually the first thing to look at.
+//!
+//! Webhook alerts carry the hints of each anomaly, and `GET /incidents/{id}`
+//! those of the incident, with sensor metadata redacted as configured (see
+//! [`crate::redaction`]).
+
+use std::collections::HashMap;
+
+use crate::audit::AuditEntry;
+use crate::redaction;
+use crate::storage::{Storage, StoredAnomaly, StoredAuditEntry};
+use crate::time::parse_timestamp;
+
+/// How long before an anomaly a change counts as a hint.
+pub const LOOKBACK_SECONDS: i64 = 24 * 60 * 60;
+
+fn concerns(entry: &StoredAuditEntry, sensor_id: i64) -> bool {
+    entry.resource == "settings" || entry.resource_id.as_deref() == Some(&sensor_id.to_string())
+}
+
+fn hint(entry: StoredAuditEntry) -> AuditEntry {
+    let mut hint = AuditEntry::from(entry);
+    if let Some(redaction) = redaction::current() {
+        hint.redact(&redaction);
+    }
+    hint
+}
+
+/// The hints of each of `anomalies` that has any, by anomaly id: changes
+/// in the [`LOOKBACK_SECONDS`] before it was detected, newest first.
+pub async fn for_anomalies(
+    storage: &Storage,
+    anomalies: &[StoredAnomaly],
+) -> Result<HashMap<i64, Vec<AuditEntry>>, sqlx::Error> {
+    let detected: Vec<(&StoredAnomaly, i64)> = anomalies
+        .iter()
+        .filter_map(|anomaly| Some((anomaly, parse_timestamp(&anomaly.detected_at)?)))
+        .collect();
+    let (Some(first), Some(last)) = (
+        detected.iter().min_by_key(|(_, at)| *at),
+        detected.iter().max_by_key(|(_, at)| *at),
+    ) else {
+        return Ok(HashMap::new());
+    };
+    let Some(since) = storage
+        .add_seconds(&first.0.detected_at, -LOOKBACK_SECONDS)
+        .await?
+    else {
+        return Ok(HashMap::new());
+    };
+    let mut sensor_ids: Vec<i64> = detected.iter().map(|(a, _)| a.sensor_id).collect();
+    sensor_ids.sort_unstable();
+    sensor_ids.dedup();
+    let changes = storage
+        .sensor_changes(&sensor_ids, &since, &last.0.detected_at)
+        .await?;
+
+    let mut hints = HashMap::new();
+    for (anomaly, at) in detected {
+        let found: Vec<AuditEntry> = changes
+            .iter()
+            .filter(|entry| {
+                concerns(entry, anomaly.sensor_id)
+                    && parse_timestamp(&entry.changed_at)
+                        .is_some_and(|changed| changed <= at && at - changed <= LOOKBACK_SECONDS)
+            })
+            .cloned()
+            .map(hint)
+            .collect();
+        if !found.is_empty() {
+            hints.insert(anomaly.id, found);
+        }
+    }
+    Ok(hints)
+}
+
+/// Changes to the sensor from [`LOOKBACK_SECONDS`] before an incident
+/// opened until it resolved, or until now while it is not resolved, newest
+/// first.
+pub async fn for_incident(
+    storage: &Storage,
+    sensor_id: i64,
+    opened_at: &str,
+    resolved_at: Option<&str>,
+) -> Result<Vec<AuditEntry>, sqlx::Error> {
+    let Some(since) = storage.add_seconds(opened_at, -LOOKBACK_SECONDS).await? else {
+        return Ok(Vec::new());
+    };
+    let until = match resolved_at {
+        Some(resolved_at) => resolved_at.to_string(),
+        None => storage.cutoff(std::time::Duration::ZERO).await?,
+    };
+    let changes = storage.sensor_changes(&[sensor_id], &since, &until).await?;
+    Ok(changes.into_iter().map(hint).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::audit::{self, Change};
+    use crate::notify::Severity;
+    use crate::notify::testing::anomaly;
+    use crate::storage::testing::in_memory;
+
+    #[tokio::test]
+    async fn test_hints_are_recent_changes_to_the_sensor() {
+        let storage = in_memory().await;
+        let (old, new) = (json!({ "threshold": 3.0 }), json!({ "threshold": 2.0 }));
+        audit::record(
+            &storage,
+            "alice",
+            [
+                Change::new("sensor", Some("1".to_string()), Some(&old), Some(&new)),
+                Change::new("sensor_tags", Some("2".to_string()), None, Some(&new)),
+                Change::new("routing", None, None, Some(&new)),
+            ],
+        )
+        .await;
+        let now = storage.cutoff(std::time::Duration::ZERO).await.unwrap();
+        let detected = |sensor_id, detected_at: &str| StoredAnomaly {
+            id: sensor_id * 10,
+            detected_at: detected_at.to_string(),
+            ..anomaly(sensor_id, Severity::High)
+        };
+        let later = storage.add_seconds(&now, 60).await.unwrap().unwrap();
+        let much_later = storage
+            .add_seconds(&now, 2 * LOOKBACK_SECONDS)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let hints = for_anomalies(
+            &storage,
+            &[
+                detected(1, &later),
+                detected(3, &later),
+                StoredAnomaly {
+                    id: 11,
+                    ..detected(1, &much_later)
+                },
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(hints.len(), 1);
+        let hint = serde_json::to_value(&hints[&10][0]).unwrap();
+        assert_eq!(hint["actor"], "alice");
+        assert_eq!(hint["after"], new);
+
+        let incident = for_incident(&storage, 2, &later, None).await.unwrap();
+        assert_eq!(incident.len(), 1);
+    }
+}
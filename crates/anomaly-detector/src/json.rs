@@ -0,0 +1,213 @@
+//! The JSON body extractor of the endpoints taking the largest payloads,
+//! `/analyze` and `/analyze/batch`.
+//!
+//! Built with the `simd-json` feature, bodies are parsed with simd-json, which
+//! validates and indexes the whole document with SIMD instructions before
+//! deserializing it in place; serde_json otherwise. Both answer like axum's
+//! `Json`, with an [`ApiError`]: 415 without a JSON content type, 400 for
+//! malformed JSON and 422 for JSON of the wrong shape, naming the field
+//! that failed, e.g. `readings[2].value`.
+
+use axum::extract::{FromRequest, Request};
+use serde::de::DeserializeOwned;
+
+use crate::validation::ApiError;
+
+pub struct JsonBody<T>(pub T);
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    #[cfg(not(feature = "simd-json"))]
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        let axum::Json(value) = axum::Json::from_request(request, state)
+            .await
+            .map_err(rejected)?;
+        Ok(JsonBody(value))
+    }
+
+    #[cfg(feature = "simd-json")]
+    async fn from_request(request: Request, state: &S) -> Result<Self, ApiError> {
+        use axum::{body::Bytes, http::StatusCode};
+
+        if !json_content_type(request.headers()) {
+            return Err(ApiError::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected request with `Content-Type: application/json`",
+            ));
+        }
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(|rejection| ApiError::new(rejection.status(), rejection.body_text()))?;
+        // simd-json rewrites strings in place; the body is usually the only
+        // reference to its bytes, so this takes them without a copy.
+        let mut buffer = Vec::from(bytes);
+        let mut deserializer = simd_json::Deserializer::from_slice(&mut buffer).map_err(|e| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Failed to parse the request body as JSON: {}", e),
+            )
+        })?;
+        let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| ApiError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            error: format!(
+                "Failed to deserialize the JSON body into the target type: {}",
+                e
+            ),
+            field: field(e.path()),
+        })?;
+        Ok(JsonBody(value))
+    }
+}
+
+/// axum's rejection as an [`ApiError`], naming the field for JSON of the
+/// wrong shape.
+#[cfg(not(feature = "simd-json"))]
+fn rejected(rejection: axum::extract::rejection::JsonRejection) -> ApiError {
+    use axum::extract::rejection::JsonRejection;
+    use std::error::Error;
+
+    let mut error = ApiError::new(rejection.status(), rejection.body_text());
+    if let JsonRejection::JsonDataError(_) = rejection {
+        // axum reads the body with serde_path_to_error, whose error is in
+        // the rejection's chain.
+        let mut source = rejection.source();
+        while let Some(e) = source {
+            if let Some(e) = e.downcast_ref::<serde_path_to_error::Error<serde_json::Error>>() {
+                error.field = field(e.path());
+            }
+            source = e.source();
+        }
+    }
+    error
+}
+
+/// The path of the field a body failed at, unless it failed as a whole.
+fn field(path: &serde_path_to_error::Path) -> Option<String> {
+    let path = path.to_string();
+    (path != ".").then_some(path)
+}
+
+/// `application/json` or an `application/*+json` type, as axum accepts.
+#[cfg(feature = "simd-json")]
+fn json_content_type(headers: &axum::http::HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(axum::http::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence == "application/json"
+        || essence.starts_with("application/") && essence.ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{body::Body, http::StatusCode};
+
+    use super::*;
+    use crate::AnalyzeRequest;
+    use crate::batch::BatchRequest;
+
+    fn request(content_type: &str, body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn status<T: DeserializeOwned>(request: Request) -> StatusCode {
+        match JsonBody::<T>::from_request(request, &()).await {
+            Ok(_) => StatusCode::OK,
+            Err(error) => error.status,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parses_analyze_and_batch_bodies() {
+        let JsonBody(analyze) = JsonBody::<AnalyzeRequest>::from_request(
+            request(
+                "application/json; charset=utf-8",
+                r#"{"sensor_id": 7, "threshold": 2.5, "readings": [
+                    {"id": 1, "value": 1.5, "timestamp": "2026-01-19T10:00:00"},
+                    {"id": 2, "value": -3e2, "timestamp": "2026-01-19T10:01:00"}
+                ]}"#,
+            ),
+            &(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(analyze.sensor_id, Some(7));
+        assert_eq!(analyze.threshold, Some(2.5));
+        let values: Vec<f64> = analyze.readings.iter().map(|r| r.value).collect();
+        assert_eq!(values, [1.5, -300.0]);
+
+        let batch = request(
+            "application/json",
+            r#"{"series": [{"id": "a", "method": "mad", "readings": [
+                {"id": 1, "value": 2.0, "timestamp": "2026-01-19T10:00:00"}
+            ]}]}"#,
+        );
+        assert_eq!(status::<BatchRequest>(batch).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_like_axum_json() {
+        for (content_type, body, expected) in [
+            (
+                "text/plain",
+                r#"{"readings": []}"#,
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ),
+            (
+                "application/json",
+                r#"{"readings": [}"#,
+                StatusCode::BAD_REQUEST,
+            ),
+            (
+                "application/json",
+                r#"{"readings": 5}"#,
+                StatusCode::UNPROCESSABLE_ENTITY,
+            ),
+        ] {
+            let status = status::<AnalyzeRequest>(request(content_type, body)).await;
+            assert_eq!(status, expected, "{}", body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rejection_names_the_field() {
+        let body = r#"{"readings": [
+            {"id": 1, "value": 1.5, "timestamp": "2026-01-19T10:00:00"},
+            {"id": 2, "value": "high", "timestamp": "2026-01-19T10:01:00"}
+        ]}"#;
+        let Err(error) =
+            JsonBody::<AnalyzeRequest>::from_request(request("application/json", body), &()).await
+        else {
+            panic!("expected a rejection");
+        };
+        assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.field.as_deref(), Some("readings[1].value"));
+
+        let Err(error) = JsonBody::<AnalyzeRequest>::from_request(
+            request("application/json", r#"{"readings": [}"#),
+            &(),
+        )
+        .await
+        else {
+            panic!("expected a rejection");
+        };
+        assert_eq!(error.field, None);
+    }
+}
@@ -0,0 +1,232 @@
+//! Priority lanes, so bulk work cannot hold up real-time scoring.
+//!
+//! Requests run in one of two lanes. `interactive` is `/analyze`,
+//! `/analyze/batch` and `/stream/ingest` scoring readings as they arrive.
+//! `bulk` is `/backfill` and `/replay`, which re-score stored history, and
+//! any of the others sent with `X-Priority: bulk`, e.g. a collector catching
+//! up after an outage. The header can only lower a request's priority.
+//!
+//! Interactive requests run as soon as they arrive. Bulk work runs in units,
+//! a page of stored readings or one batch series, and each unit first takes
+//! one of `bulk_workers` permits (default half the CPUs), so bulk work never
+//! occupies more than that many threads. When interactive requests averaged
+//! longer than `interactive_latency_budget_ms` over the last second, each
+//! bulk unit takes every permit instead. Bulk work then runs one unit at a
+//! time until interactive latency is back within budget.
+//!
+//! `anomaly_interactive_request_duration_seconds` and
+//! `anomaly_bulk_wait_seconds` show how each lane is served.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::http::HeaderMap;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::LanePolicy;
+use crate::metrics::Metrics;
+
+/// Header a client lowers a request's priority with.
+pub const PRIORITY_HEADER: &str = "x-priority";
+
+/// How long an interactive request's latency counts towards the average.
+const WINDOW: Duration = Duration::from_secs(1);
+
+/// Weight of the latest interactive request in the moving average.
+const SMOOTHING: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lane {
+    Interactive,
+    Bulk,
+}
+
+impl Lane {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Lane::Interactive => "interactive",
+            Lane::Bulk => "bulk",
+        }
+    }
+
+    /// The lane of an `/analyze` or batch request: bulk if it asks for it.
+    pub fn of(headers: &HeaderMap) -> Lane {
+        let bulk = headers
+            .get(PRIORITY_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case(Lane::Bulk.as_str()));
+        if bulk { Lane::Bulk } else { Lane::Interactive }
+    }
+}
+
+/// Exponential moving average of interactive request latency.
+#[derive(Default)]
+struct Latency {
+    seconds: f64,
+    updated: Option<Instant>,
+}
+
+pub struct Lanes {
+    bulk: Arc<Semaphore>,
+    workers: u32,
+    budget: Duration,
+    latency: Mutex<Latency>,
+    metrics: Arc<Metrics>,
+}
+
+impl Lanes {
+    pub fn new(policy: &LanePolicy, metrics: Arc<Metrics>) -> Self {
+        let workers = u32::try_from(policy.bulk_workers.max(1)).unwrap_or(u32::MAX);
+        Self {
+            bulk: Arc::new(Semaphore::new(workers as usize)),
+            workers,
+            budget: policy.latency_budget,
+            latency: Mutex::new(Latency::default()),
+            metrics,
+        }
+    }
+
+    /// Whether recent interactive requests took longer than the budget.
+    pub fn over_budget(&self) -> bool {
+        let latency = self.latency.lock().unwrap();
+        latency
+            .updated
+            .is_some_and(|updated| updated.elapsed() < WINDOW)
+            && latency.seconds > self.budget.as_secs_f64()
+    }
+
+    fn record(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let mut latency = self.latency.lock().unwrap();
+        // An average gone stale starts over rather than decaying from it.
+        latency.seconds = match latency.updated {
+            Some(updated) if updated.elapsed() < WINDOW => {
+                SMOOTHING * seconds + (1.0 - SMOOTHING) * latency.seconds
+            }
+            _ => seconds,
+        };
+        latency.updated = Some(Instant::now());
+        drop(latency);
+        self.metrics.observe_interactive(elapsed);
+    }
+
+    /// Times an interactive request until the returned guard is dropped.
+    pub fn interactive(self: &Arc<Self>) -> Interactive {
+        Interactive {
+            lanes: self.clone(),
+            started: Instant::now(),
+        }
+    }
+
+    /// Waits for a bulk unit's turn; it runs while the permit is held.
+    pub async fn bulk(&self) -> OwnedSemaphorePermit {
+        let started = Instant::now();
+        let permits = if self.over_budget() { self.workers } else { 1 };
+        let permit = self
+            .bulk
+            .clone()
+            .acquire_many_owned(permits)
+            .await
+            .expect("the bulk semaphore is never closed");
+        self.metrics.observe_bulk_wait(started.elapsed());
+        permit
+    }
+
+    /// Enters `lane` for a whole request.
+    pub async fn enter(self: &Arc<Self>, lane: Lane) -> Ticket {
+        match lane {
+            Lane::Interactive => Ticket {
+                _interactive: Some(self.interactive()),
+                _permit: None,
+            },
+            Lane::Bulk => Ticket {
+                _interactive: None,
+                _permit: Some(self.bulk().await),
+            },
+        }
+    }
+}
+
+impl Default for Lanes {
+    fn default() -> Self {
+        Self::new(&LanePolicy::default(), Arc::default())
+    }
+}
+
+/// An interactive request in progress.
+pub struct Interactive {
+    lanes: Arc<Lanes>,
+    started: Instant,
+}
+
+impl Drop for Interactive {
+    fn drop(&mut self) {
+        self.lanes.record(self.started.elapsed());
+    }
+}
+
+/// A request's place in its lane, held until it is answered.
+pub struct Ticket {
+    _interactive: Option<Interactive>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lanes(workers: usize) -> Arc<Lanes> {
+        let policy = LanePolicy {
+            bulk_workers: workers,
+            latency_budget: Duration::from_millis(50),
+        };
+        Arc::new(Lanes::new(&policy, Arc::default()))
+    }
+
+    #[test]
+    fn test_lane_of_request() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(Lane::of(&headers), Lane::Interactive);
+        headers.insert(PRIORITY_HEADER, "Bulk".parse().unwrap());
+        assert_eq!(Lane::of(&headers), Lane::Bulk);
+        headers.insert(PRIORITY_HEADER, "interactive".parse().unwrap());
+        assert_eq!(Lane::of(&headers), Lane::Interactive);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_work_is_limited_to_its_workers() {
+        let lanes = lanes(2);
+        let first = lanes.bulk().await;
+        let _second = lanes.bulk().await;
+        let third = tokio::time::timeout(Duration::from_millis(20), lanes.bulk()).await;
+        assert!(third.is_err());
+
+        // Interactive requests never wait on bulk work.
+        let ticket =
+            tokio::time::timeout(Duration::from_millis(20), lanes.enter(Lane::Interactive));
+        assert!(ticket.await.is_ok());
+
+        drop(first);
+        assert_eq!(lanes.bulk().await.num_permits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_work_runs_alone_while_over_budget() {
+        let lanes = lanes(4);
+        lanes.record(Duration::from_millis(10));
+        assert!(!lanes.over_budget());
+        lanes.record(Duration::from_millis(500));
+        assert!(lanes.over_budget());
+
+        let first = lanes.bulk().await;
+        assert_eq!(first.num_permits(), 4);
+        let second = tokio::time::timeout(Duration::from_millis(20), lanes.bulk()).await;
+        assert!(second.is_err());
+        drop(first);
+
+        // Latency older than the window no longer holds bulk work back.
+        lanes.latency.lock().unwrap().updated = Some(Instant::now() - WINDOW);
+        assert!(!lanes.over_budget());
+        assert_eq!(lanes.bulk().await.num_permits(), 1);
+    }
+}
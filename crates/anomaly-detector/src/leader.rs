@@ -0,0 +1,111 @@
+//! Leader election for background jobs when several replicas share one
+//! database.
+//!
+//! Each job (rollups, retention compaction, escalation, webhook retries,
+//! stored email digests) is guarded by a lease in `job_leases`. Before every
+//! run a replica takes or renews the lease; only the holder runs the job, and
+//! a lease its holder stops renewing expires after a few intervals, so
+//! another replica takes over. The notification queue needs no lease, since
+//! its entries are claimed one replica at a time.
+
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use crate::storage::Storage;
+
+/// Shortest lease, so jobs that run every few seconds do not flap between
+/// replicas on a slow write.
+const MIN_TTL: Duration = Duration::from_secs(30);
+
+static INSTANCE_ID: OnceLock<String> = OnceLock::new();
+
+/// Names this process as lease holder; the first call wins.
+pub fn set_instance_id(id: String) {
+    let _ = INSTANCE_ID.set(id);
+}
+
+pub fn instance_id() -> &'static str {
+    INSTANCE_ID.get_or_init(|| format!("anomaly-detector-{}", std::process::id()))
+}
+
+/// The right to run one job, renewed at each run.
+pub struct Lease {
+    storage: Storage,
+    job: String,
+    ttl: Duration,
+    held: AtomicBool,
+}
+
+impl Lease {
+    /// A lease for a job that runs every `interval`, valid for three runs.
+    pub fn new(storage: Storage, job: impl Into<String>, interval: Duration) -> Self {
+        Self {
+            storage,
+            job: job.into(),
+            ttl: (interval * 3).max(MIN_TTL),
+            held: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes or renews the lease, returning whether this replica should run
+    /// the job now.
+    pub async fn acquire(&self) -> bool {
+        let held = match self
+            .storage
+            .acquire_lease(&self.job, instance_id(), self.ttl.as_secs() as i64)
+            .await
+        {
+            Ok(held) => held,
+            Err(e) => {
+                eprintln!("Error: Failed to renew the lease on {}: {}", self.job, e);
+                false
+            }
+        };
+        if self.held.swap(held, Ordering::Relaxed) != held {
+            if held {
+                println!("Leader: running {} on {}", self.job, instance_id());
+            } else {
+                println!("Leader: {} moved to another instance", self.job);
+            }
+        }
+        held
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{expire_leases, in_memory};
+
+    #[tokio::test]
+    async fn test_one_holder_until_the_lease_expires() {
+        let storage = in_memory().await;
+        let interval = Duration::from_secs(60);
+        let lease = Lease::new(storage.clone(), "rollups", interval);
+
+        assert!(lease.acquire().await);
+        assert!(lease.acquire().await);
+        assert!(
+            !storage
+                .acquire_lease("rollups", "other", 180)
+                .await
+                .unwrap()
+        );
+        assert!(
+            storage
+                .acquire_lease("retention", "other", 180)
+                .await
+                .unwrap()
+        );
+
+        expire_leases(&storage).await;
+        assert!(
+            storage
+                .acquire_lease("rollups", "other", 180)
+                .await
+                .unwrap()
+        );
+        assert!(!lease.acquire().await);
+    }
+}
@@ -1,34 +1,282 @@
+mod access;
+mod activity;
+mod aggregate;
+mod analytics;
+mod anomalies;
+mod archive;
+mod audit;
+mod backfill;
+mod baselines;
+mod batch;
+mod config;
+mod ensemble;
+mod evaluate;
+mod events;
+mod export;
+mod generate;
+mod grpc;
+mod hints;
+mod json;
+mod lanes;
+mod leader;
+mod metrics;
+mod notify;
+mod object_export;
+mod pool;
+mod query;
+mod redaction;
+mod reference;
+mod replay;
+mod request_log;
+mod retention;
+mod rollups;
+mod schedule;
+mod schemas;
+mod sensors;
+mod settings;
+mod shadow;
+mod shared;
+mod slo;
+mod storage;
+mod stream;
+mod tags;
+mod time;
+mod ui;
+mod usage;
+mod validation;
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
 use axum::{
     Json, Router,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, State},
+    http::{HeaderMap, StatusCode, header},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
 };
-use serde::{Deserialize, Serialize};
+use detection_core::detector::{Detector, Ensemble, HoltWintersDetector, Registry};
+use detection_core::stats::{ZScorer, summarize};
+use detection_core::{Severity, SeverityBands, SeverityLabels};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 
-#[derive(Deserialize)]
+use access::Role;
+use aggregate::{WindowAggregate, Windows};
+use config::Config;
+use ensemble::{EnsembleReport, EnsembleRequest};
+use events::{AnomalyEvent, Events};
+use json::JsonBody;
+use metrics::Metrics;
+use notify::Notifier;
+use object_export::{ExportReceipt, ExportRequest, ObjectExporter};
+use pool::{PooledJson, Scratch};
+use sensors::SensorConfig;
+use settings::{DetectionSettings, Settings};
+use storage::{NewAnomaly, Storage, StoredAnomaly};
+use validation::ApiError;
+
+#[derive(Clone, Default)]
+struct AppState {
+    metrics: Arc<Metrics>,
+    storage: Option<Storage>,
+    /// State shared with other replicas, see `shared`.
+    redis: Option<shared::Redis>,
+    exporter: Option<ObjectExporter>,
+    notifier: Option<Arc<Notifier>>,
+    settings: Arc<Settings>,
+    events: Arc<Events>,
+    query: Option<Arc<query::QueryEngine>>,
+    /// Detectors requests can name as their `method`; other crates' are
+    /// added in `main`.
+    detectors: Arc<Registry>,
+    /// Anomaly-rate targets, see `slo`.
+    slo: Arc<BTreeMap<String, f64>>,
+    /// Daily limits per tenant, see `usage`.
+    quotas: Arc<usage::Quotas>,
+    /// Interactive and bulk work, see `lanes`.
+    lanes: Arc<lanes::Lanes>,
+    /// Per-sensor windows of `/stream/ingest`, see `stream`.
+    streams: Arc<stream::Streams>,
+}
+
+/// The method z-scored anomalies are recorded under, the default of
+/// `/analyze` and the one backfills, replays, shadows and evaluated
+/// candidates score with.
+const ZSCORE_METHOD: &str = "zscore";
+
+#[derive(Clone, Deserialize)]
 struct Reading {
     id: i64,
     value: f64,
     timestamp: String,
+    /// In `/analyze`, scores the reading with the other readings of its
+    /// sensor rather than the whole request.
+    #[serde(default)]
+    sensor_id: Option<i64>,
 }
 
 #[derive(Deserialize)]
 struct AnalyzeRequest {
+    /// Applies the sensor's registry entry, if it has one.
+    #[serde(default)]
+    sensor_id: Option<i64>,
     readings: Vec<Reading>,
-    #[serde(default = "default_threshold")]
-    threshold: f64,
+    /// A detector of the registry, see `GET /detectors`, `ensemble` or, in
+    /// batches, `reference`; z-scores when unset.
+    #[serde(default)]
+    method: Option<String>,
+    /// Defaults to the runtime `default_threshold` for z-scores, and to the
+    /// detector's own default for the others.
+    #[serde(default)]
+    threshold: Option<f64>,
+    /// The members of an `ensemble`.
+    #[serde(default)]
+    ensemble: Option<EnsembleRequest>,
+    /// Seeds the detectors that draw random samples, `reservoir` alone or
+    /// in an ensemble; the same seed and readings give the same anomalies.
+    #[serde(default)]
+    seed: Option<u64>,
+    #[serde(default)]
+    severity: Option<SeverityRequest>,
+    #[serde(default)]
+    export: Option<ExportRequest>,
+    /// Width of the windows the response aggregates readings over, e.g.
+    /// `5m`; see [`aggregate`].
+    #[serde(default)]
+    window: Option<String>,
 }
 
-fn default_threshold() -> f64 {
-    2.0
+/// The severity bands of one request, each left out being the runtime
+/// one, and the labels its anomalies are shown with in place of the
+/// installed ones.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeverityRequest {
+    #[serde(default)]
+    high: Option<f64>,
+    #[serde(default)]
+    critical: Option<f64>,
+    #[serde(default)]
+    labels: Option<SeverityLabels>,
 }
 
-#[derive(Serialize)]
+impl AnalyzeRequest {
+    /// The runtime detection settings with the request's severity bands,
+    /// refusing bands or labels that do not tell the severities apart.
+    fn detection(&self, runtime: DetectionSettings) -> Result<DetectionSettings, String> {
+        let Some(severity) = &self.severity else {
+            return Ok(runtime);
+        };
+        if let Some(labels) = &severity.labels {
+            labels
+                .validate()
+                .map_err(|e| format!("severity.labels: {}", e))?;
+        }
+        let bands = SeverityBands {
+            high: severity.high.unwrap_or(runtime.severity.high),
+            critical: severity.critical.unwrap_or(runtime.severity.critical),
+        };
+        bands.validate().map_err(|e| format!("severity.{}", e))?;
+        Ok(DetectionSettings {
+            severity: bands,
+            ..runtime
+        })
+    }
+
+    /// The labels the request's anomalies are shown with, if it set any.
+    fn labels(&self) -> Option<Arc<SeverityLabels>> {
+        let labels = self.severity.as_ref()?.labels.clone()?;
+        Some(Arc::new(labels))
+    }
+
+    /// Whether any reading names its own sensor.
+    fn names_sensors(&self) -> bool {
+        self.readings.iter().any(|r| r.sensor_id.is_some())
+    }
+
+    /// The sensors of the readings, the request's for those naming none.
+    fn sensor_count(&self) -> usize {
+        let sensors: HashSet<Option<i64>> = self
+            .readings
+            .iter()
+            .map(|r| r.sensor_id.or(self.sensor_id))
+            .collect();
+        sensors.len().max(1)
+    }
+
+    /// A series per sensor, in the order of each sensor's first reading;
+    /// readings naming no sensor belong to the request's.
+    fn by_sensor(self) -> Vec<AnalyzeRequest> {
+        let AnalyzeRequest {
+            sensor_id,
+            readings,
+            method,
+            threshold,
+            ensemble,
+            seed,
+            severity,
+            ..
+        } = self;
+        let mut series: Vec<AnalyzeRequest> = Vec::new();
+        let mut positions: HashMap<Option<i64>, usize> = HashMap::new();
+        for reading in readings {
+            let sensor_id = reading.sensor_id.or(sensor_id);
+            let position = *positions.entry(sensor_id).or_insert_with(|| {
+                series.push(AnalyzeRequest {
+                    sensor_id,
+                    readings: Vec::new(),
+                    method: method.clone(),
+                    threshold,
+                    ensemble: ensemble.clone(),
+                    seed,
+                    severity: severity.clone(),
+                    export: None,
+                    window: None,
+                });
+                series.len() - 1
+            });
+            series[position].readings.push(reading);
+        }
+        series
+    }
+}
+
+#[derive(Clone, Deserialize)]
 struct Anomaly {
     id: i64,
     value: f64,
     timestamp: String,
     z_score: f64,
-    severity: String,
+    severity: Severity,
+    /// The labels of the request's `severity`, if it set any.
+    #[serde(skip)]
+    labels: Option<Arc<SeverityLabels>>,
+}
+
+impl Anomaly {
+    /// The label the severity is shown with: the request's, else the
+    /// installed one.
+    fn label(&self) -> std::borrow::Cow<'static, str> {
+        match &self.labels {
+            Some(labels) => labels.get(self.severity).to_string().into(),
+            None => self.severity.label(),
+        }
+    }
+}
+
+impl Serialize for Anomaly {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut anomaly = serializer.serialize_struct("Anomaly", 5)?;
+        anomaly.serialize_field("id", &self.id)?;
+        anomaly.serialize_field("value", &self.value)?;
+        anomaly.serialize_field("timestamp", &self.timestamp)?;
+        anomaly.serialize_field("z_score", &self.z_score)?;
+        anomaly.serialize_field("severity", &self.label())?;
+        anomaly.end()
+    }
 }
 
 #[derive(Serialize)]
@@ -37,73 +285,923 @@ struct AnalyzeResponse {
     total_readings: usize,
     mean: f64,
     std_dev: f64,
+    /// Where the anomalies were written when the request asked for an export;
+    /// `anomalies` is left empty in that case.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    export: Option<ExportReceipt>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ensemble: Option<EnsembleReport>,
+    /// Each sensor's series when the readings name their sensors; the
+    /// anomalies above are theirs, in the same order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    sensors: Vec<SensorAnalysis>,
+    /// The readings' aggregates per window, when the request set `window`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    windows: Vec<WindowAggregate>,
 }
 
-async fn health_check() -> &'static str {
-    "Anomaly Detector Service is running"
+/// The result for one sensor's readings of an `/analyze` request.
+#[derive(Serialize)]
+struct SensorAnalysis {
+    sensor_id: Option<i64>,
+    #[serde(flatten)]
+    result: AnalyzeResponse,
 }
 
-fn calculate_mean(values: &[f64]) -> f64 {
-    if values.is_empty() {
-        return 0.0;
-    }
-    values.iter().sum::<f64>() / values.len() as f64
+/// How a series is scored.
+#[derive(Clone)]
+enum Scoring {
+    ZScore,
+    Detector(Arc<dyn Detector>),
+    Ensemble(Arc<Ensemble>),
+    /// Z-scores of the differences from a canary's readings.
+    Reference(Arc<reference::Canary>),
 }
 
-fn calculate_std_dev(values: &[f64], mean: f64) -> f64 {
-    if values.len() <= 1 {
-        return 0.0;
+impl Scoring {
+    /// This scoring with its stochastic detectors drawing from `seed`.
+    fn reseeded(self, seed: u64) -> Self {
+        match self {
+            Scoring::ZScore => Scoring::ZScore,
+            Scoring::Detector(detector) => {
+                Scoring::Detector(detector.reseeded(seed).unwrap_or(detector))
+            }
+            Scoring::Ensemble(ensemble) => Scoring::Ensemble(Arc::new(ensemble.reseeded(seed))),
+            Scoring::Reference(canary) => Scoring::Reference(canary),
+        }
+    }
+
+    /// The method anomalies are recorded under.
+    fn method(&self) -> &'static str {
+        match self {
+            Scoring::ZScore => ZSCORE_METHOD,
+            Scoring::Detector(detector) => detector.name(),
+            Scoring::Ensemble(_) => ensemble::METHOD,
+            Scoring::Reference(_) => reference::METHOD,
+        }
     }
-    let variance =
-        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
-    variance.sqrt()
 }
 
-async fn analyze(Json(payload): Json<AnalyzeRequest>) -> Json<AnalyzeResponse> {
-    let values: Vec<f64> = payload.readings.iter().map(|r| r.value).collect();
-    let mean = calculate_mean(&values);
-    let std_dev = calculate_std_dev(&values, mean);
+async fn health_check() -> &'static str {
+    "Anomaly Detector Service is running"
+}
 
-    let mut anomalies = Vec::new();
+async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
 
-    for reading in payload.readings {
-        if std_dev > 0.0 {
-            let z_score = (reading.value - mean) / std_dev;
-            let abs_z = z_score.abs();
+/// The z-score threshold for a series: the requested one, else the sensor's
+/// registered one, else the runtime default.
+fn resolve_threshold(
+    requested: Option<f64>,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+) -> f64 {
+    requested
+        .or(sensor.and_then(|s| s.threshold))
+        .unwrap_or(detection.default_threshold)
+}
 
-            if abs_z > payload.threshold {
-                let severity = if abs_z > 3.0 {
-                    "critical"
-                } else if abs_z > 2.5 {
-                    "high"
-                } else {
-                    "medium"
-                };
+/// Z-score and severity of an anomalous value, or `None` for a normal one.
+///
+/// Values outside the sensor's registered limits are critical whatever their
+/// z-score, and the sensor's rules override both.
+fn classify(
+    scorer: &ZScorer,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+    value: f64,
+) -> Option<(f64, Severity)> {
+    let z_score = scorer.z(value);
+    let detected = if sensor.is_some_and(|s| s.out_of_range(value)) {
+        Some(Severity::Critical)
+    } else {
+        scorer
+            .score(value)
+            .map(|z_score| detection.severity.classify(z_score.abs()))
+    };
+    let severity = match sensor {
+        Some(sensor) => sensor.grade(value, z_score, detected),
+        None => detected,
+    };
+    Some((z_score, severity?))
+}
+
+/// Severity of a reading a detector scored `score`, or `None` for a normal
+/// one; like [`classify`], values outside the sensor's registered limits are
+/// critical whatever their score and the sensor's rules override both.
+fn grade(
+    score: f64,
+    outlier: bool,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+    value: f64,
+) -> Option<Severity> {
+    let detected = if sensor.is_some_and(|s| s.out_of_range(value)) {
+        Some(Severity::Critical)
+    } else if outlier {
+        Some(detection.severity.classify(score.abs()))
+    } else {
+        None
+    };
+    match sensor {
+        Some(sensor) => sensor.grade(value, score, detected),
+        None => detected,
+    }
+}
+
+/// Runs detection over one series of readings: z-scores, a registered
+/// detector, an ensemble of them or the differences from a canary.
+fn detect(
+    request: AnalyzeRequest,
+    scoring: &Scoring,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+) -> AnalyzeResponse {
+    let labels = request.labels();
+    pool::with_scratch(|Scratch { values, graded }| {
+        values.extend(request.readings.iter().map(|r| r.value));
+        let stats = summarize(values.iter().copied());
+        let mut verdicts = None;
+        match scoring {
+            Scoring::ZScore => {
+                let threshold = resolve_threshold(request.threshold, detection, sensor);
+                let scorer = ZScorer::new(&stats, threshold);
+                graded.extend(
+                    values
+                        .iter()
+                        .map(|&value| classify(&scorer, detection, sensor, value)),
+                );
+            }
+            // The registered and runtime thresholds are z-score thresholds.
+            Scoring::Detector(detector) => {
+                let threshold = request
+                    .threshold
+                    .unwrap_or_else(|| detector.default_threshold());
+                graded.extend(detector.scores(values).into_iter().zip(values.iter()).map(
+                    |(score, &value)| {
+                        grade(score, score.abs() > threshold, detection, sensor, value)
+                            .map(|s| (score, s))
+                    },
+                ));
+            }
+            Scoring::Ensemble(ensemble) => {
+                let all = ensemble.verdicts(values);
+                graded.extend(all.iter().zip(values.iter()).map(|(verdict, &value)| {
+                    grade(verdict.score, verdict.outlier, detection, sensor, value)
+                        .map(|s| (verdict.score, s))
+                }));
+                verdicts = Some((ensemble, all));
+            }
+            Scoring::Reference(canary) => {
+                let threshold = resolve_threshold(request.threshold, detection, sensor);
+                let deviations = canary.deviations(&request.readings);
+                let scorer =
+                    ZScorer::new(&summarize(deviations.iter().flatten().copied()), threshold);
+                graded.extend(
+                    deviations
+                        .iter()
+                        .zip(values.iter())
+                        .map(|(deviation, &value)| {
+                            let z_score = deviation.map_or(0.0, |d| scorer.z(d));
+                            let outlier = deviation.is_some_and(|d| scorer.score(d).is_some());
+                            grade(z_score, outlier, detection, sensor, value).map(|s| (z_score, s))
+                        }),
+                );
+            }
+        }
+        let ensemble = verdicts.map(|(ensemble, verdicts)| {
+            let flagged: Vec<usize> = (0..graded.len())
+                .filter(|&position| graded[position].is_some())
+                .collect();
+            ensemble::report(ensemble, &verdicts, &flagged)
+        });
 
-                anomalies.push(Anomaly {
+        let mut anomalies = Vec::with_capacity(graded.iter().flatten().count());
+        anomalies.extend(request.readings.into_iter().zip(graded.iter()).filter_map(
+            |(reading, graded)| {
+                let (z_score, severity) = (*graded)?;
+                Some(Anomaly {
                     id: reading.id,
                     value: reading.value,
                     timestamp: reading.timestamp,
                     z_score,
-                    severity: severity.to_string(),
-                });
+                    severity,
+                    labels: labels.clone(),
+                })
+            },
+        ));
+
+        AnalyzeResponse {
+            anomalies,
+            total_readings: stats.count() as usize,
+            mean: stats.mean(),
+            std_dev: stats.std_dev(),
+            export: None,
+            ensemble,
+            sensors: Vec::new(),
+            windows: Vec::new(),
+        }
+    })
+}
+
+fn detect_timed(
+    metrics: &Metrics,
+    scoring: &Scoring,
+    detection: &DetectionSettings,
+    sensor: Option<&SensorConfig>,
+    request: AnalyzeRequest,
+) -> AnalyzeResponse {
+    let started = Instant::now();
+    let response = detect(request, scoring, detection, sensor);
+    metrics.observe_detection(scoring.method(), response.total_readings, started.elapsed());
+    metrics.observe_anomalies(
+        scoring.method(),
+        response.anomalies.iter().map(|anomaly| anomaly.severity),
+    );
+    response
+}
+
+/// How a request asked for its readings to be scored.
+fn requested_scoring(detectors: &Registry, request: &AnalyzeRequest) -> Result<Scoring, String> {
+    let scoring = match (request.method.as_deref(), &request.ensemble) {
+        (Some(ensemble::METHOD), Some(members)) => Ok(Scoring::Ensemble(Arc::new(
+            ensemble::resolve(detectors, members)?,
+        ))),
+        (Some(ensemble::METHOD), None) => {
+            Err("method \"ensemble\" needs an `ensemble` of members".to_string())
+        }
+        (_, Some(_)) => Err("`ensemble` needs method \"ensemble\"".to_string()),
+        (Some(reference::METHOD), None) => Err(format!(
+            "method \"{}\" compares a series with its reference sensor's in the same batch; use /analyze/batch",
+            reference::METHOD
+        )),
+        (None, None) => Ok(Scoring::ZScore),
+        (Some(ZSCORE_METHOD), None) => Ok(Scoring::ZScore),
+        (Some(name), None) => detectors.get(name).map(Scoring::Detector).ok_or_else(|| {
+            format!(
+                "unknown method {:?}, expected one of {} or {}",
+                name,
+                detectors.names().collect::<Vec<_>>().join(", "),
+                ensemble::METHOD
+            )
+        }),
+    }?;
+    Ok(match request.seed {
+        Some(seed) => scoring.reseeded(seed),
+        None => scoring,
+    })
+}
+
+/// Whether anomalies may be recorded under `method`: z-score, a detector
+/// of `detectors`, an ensemble or a reference comparison.
+fn known_method(detectors: &Registry, method: &str) -> bool {
+    [ZSCORE_METHOD, ensemble::METHOD, reference::METHOD].contains(&method)
+        || detectors.get(method).is_some()
+}
+
+/// Names of the detectors requests can choose with `method`.
+async fn list_detectors(State(state): State<AppState>) -> Json<Vec<&'static str>> {
+    Json(state.detectors.names().collect())
+}
+
+/// Publishes the anomalies of a series that named its sensor, records them
+/// in its per-sensor metrics and, in the background, stores and notifies
+/// them, or without storage notifies them as found, with id 0.
+fn publish(
+    state: &AppState,
+    sensor_id: Option<i64>,
+    method: &'static str,
+    response: &AnalyzeResponse,
+) {
+    let Some(sensor_id) = sensor_id else {
+        return;
+    };
+    let anomalies = &response.anomalies;
+    state.metrics.observe_sensor(
+        sensor_id,
+        anomalies.iter().map(|anomaly| anomaly.severity),
+        (response.total_readings > 0).then_some((response.mean, response.std_dev)),
+    );
+    state
+        .events
+        .publish(anomalies.iter().map(|anomaly| AnomalyEvent {
+            sensor_id,
+            reading_id: anomaly.id,
+            value: anomaly.value,
+            timestamp: anomaly.timestamp.clone(),
+            method: method.to_string(),
+            score: anomaly.z_score,
+            severity: anomaly.severity,
+        }));
+
+    if anomalies.is_empty() {
+        return;
+    }
+    let Some(storage) = state.storage.clone() else {
+        let Some(notifier) = state.notifier.clone() else {
+            return;
+        };
+        let detected_at = time::format_timestamp(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64),
+        );
+        let anomalies: Vec<StoredAnomaly> = anomalies
+            .iter()
+            .map(|anomaly| StoredAnomaly {
+                id: 0,
+                reading_id: anomaly.id,
+                sensor_id,
+                value: anomaly.value,
+                timestamp: anomaly.timestamp.clone(),
+                method: method.to_string(),
+                score: anomaly.z_score,
+                severity: anomaly.severity,
+                detected_at: detected_at.clone(),
+            })
+            .collect();
+        tokio::spawn(async move { notify::queue::enqueue(&notifier, anomalies, None).await });
+        return;
+    };
+    let anomalies: Vec<NewAnomaly> = anomalies
+        .iter()
+        .map(|anomaly| NewAnomaly {
+            reading_id: anomaly.id,
+            sensor_id,
+            value: anomaly.value,
+            timestamp: anomaly.timestamp.clone(),
+            method,
+            score: anomaly.z_score,
+            severity: anomaly.severity,
+        })
+        .collect();
+    let notifier = state.notifier.clone();
+    tokio::spawn(async move {
+        let count = anomalies.len();
+        match storage.record_anomalies(anomalies).await {
+            Ok(stored) => {
+                if let Some(notifier) = &notifier {
+                    notify::queue::enqueue(notifier, stored, None).await;
+                }
             }
+            Err(e) => eprintln!(
+                "Error: Failed to store {} anomalies of sensor {}: {}",
+                count, sensor_id, e
+            ),
         }
+    });
+}
+
+/// Scores the readings as one series, or as a series per sensor when they
+/// name their sensors.
+async fn analyze(
+    State(state): State<AppState>,
+    Json(payload): Json<AnalyzeRequest>,
+) -> Result<Json<AnalyzeResponse>, (StatusCode, String)> {
+    if !payload.names_sensors() {
+        return analyze_series(&state, payload).await.map(Json);
+    }
+    let stats = summarize(payload.readings.iter().map(|r| r.value));
+    let mut sensors = Vec::new();
+    for series in payload.by_sensor() {
+        sensors.push(SensorAnalysis {
+            sensor_id: series.sensor_id,
+            result: analyze_series(&state, series).await?,
+        });
     }
+    Ok(Json(AnalyzeResponse {
+        anomalies: sensors
+            .iter()
+            .flat_map(|sensor| sensor.result.anomalies.iter().cloned())
+            .collect(),
+        total_readings: stats.count() as usize,
+        mean: stats.mean(),
+        std_dev: stats.std_dev(),
+        export: None,
+        ensemble: None,
+        sensors,
+        windows: Vec::new(),
+    }))
+}
 
-    Json(AnalyzeResponse {
-        anomalies,
-        total_readings: values.len(),
-        mean,
-        std_dev,
-    })
+async fn analyze_series(
+    state: &AppState,
+    payload: AnalyzeRequest,
+) -> Result<AnalyzeResponse, (StatusCode, String)> {
+    let sensor_id = payload.sensor_id;
+    let scoring =
+        requested_scoring(&state.detectors, &payload).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let detection = payload
+        .detection(state.settings.detection())
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let sensor = sensors::registered(state, sensor_id).await?;
+    // Shadows try out z-score parameters, so only z-score series are compared.
+    let shadowed = match scoring {
+        Scoring::ZScore => shadow::configured(state, sensor_id, sensor.as_ref())
+            .await
+            .map(|shadow| shadow.detect(&payload)),
+        _ => None,
+    };
+    let response = detect_timed(
+        &state.metrics,
+        &scoring,
+        &detection,
+        sensor.as_ref(),
+        payload,
+    );
+    publish(state, sensor_id, scoring.method(), &response);
+    if let Some(result) = shadowed {
+        shadow::observe(state, result, &response.anomalies);
+    }
+    Ok(response)
+}
+
+/// Looks up the configured exporter for a request that asked for an export.
+fn exporter(state: &AppState) -> Result<&ObjectExporter, (StatusCode, String)> {
+    state.exporter.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "export is not configured; set ANOMALY_EXPORT_URL".to_string(),
+    ))
+}
+
+/// The windows the request's readings are aggregated over, if it set any.
+fn windows(payload: &mut AnalyzeRequest) -> Result<Option<Windows>, ApiError> {
+    payload
+        .window
+        .take()
+        .map(|window| Windows::of(&window, payload.sensor_id, &payload.readings))
+        .transpose()
+}
+
+/// Counts the anomalies of `response` in `windows`, under each of its
+/// sensors when it has them.
+fn aggregate(mut windows: Windows, sensor_id: Option<i64>, response: &mut AnalyzeResponse) {
+    if response.sensors.is_empty() {
+        response.windows = windows.finish(sensor_id, &response.anomalies);
+    }
+    for sensor in &mut response.sensors {
+        sensor.result.windows = windows.finish(sensor.sensor_id, &sensor.result.anomalies);
+    }
+}
+
+/// Scores an `/analyze` request asking for no export: validates it, charges
+/// its usage, runs it in its lane and aggregates its windows. The gRPC
+/// `Analyze` runs the same.
+async fn analyze_metered(
+    state: &AppState,
+    headers: &HeaderMap,
+    mut payload: AnalyzeRequest,
+) -> Result<AnalyzeResponse, ApiError> {
+    payload.validate()?;
+    let windows = windows(&mut payload)?;
+    let analyses = payload.sensor_count();
+    usage::charge(state, headers, payload.readings.len(), analyses).await?;
+    let _lane = state.lanes.enter(lanes::Lane::of(headers)).await;
+    let sensor_id = payload.sensor_id;
+    let Json(mut response) = analyze(State(state.clone()), Json(payload)).await?;
+    if let Some(windows) = windows {
+        aggregate(windows, sensor_id, &mut response);
+    }
+    Ok(response)
+}
+
+/// `/analyze` with content negotiation: anomalies as CSV for `Accept: text/csv`,
+/// with the summary statistics moved into response headers.
+///
+/// When the request carries an `export`, the anomalies are written to object
+/// storage instead and the response carries only the summary and receipt.
+/// Refusals answer with an [`ApiError`].
+async fn analyze_negotiated(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonBody(mut payload): JsonBody<AnalyzeRequest>,
+) -> Response {
+    let analyzed = match payload.export.take() {
+        Some(export) => analyze_exported(&state, &headers, payload, export).await,
+        None => analyze_metered(&state, &headers, payload)
+            .await
+            .map(|response| negotiate(&headers, response)),
+    };
+    analyzed.unwrap_or_else(IntoResponse::into_response)
+}
+
+/// Scores an `/analyze` request asking for an `export` like
+/// [`analyze_metered`], writing its anomalies to object storage.
+async fn analyze_exported(
+    state: &AppState,
+    headers: &HeaderMap,
+    mut payload: AnalyzeRequest,
+    export: ExportRequest,
+) -> Result<Response, ApiError> {
+    payload.validate()?;
+    if payload.names_sensors() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "export is not supported for readings naming their sensor_id",
+        ));
+    }
+    let windows = windows(&mut payload)?;
+    let exporter = exporter(state)?;
+    let scoring = requested_scoring(&state.detectors, &payload)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let detection = payload
+        .detection(state.settings.detection())
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    let analyses = payload.sensor_count();
+    usage::charge(state, headers, payload.readings.len(), analyses).await?;
+    let _lane = state.lanes.enter(lanes::Lane::of(headers)).await;
+
+    let sensor_id = payload.sensor_id;
+    let sensor = sensors::registered(state, sensor_id).await?;
+    // Shadows try out z-score parameters, so only z-score series are compared.
+    let shadowed = match scoring {
+        Scoring::ZScore => shadow::configured(state, sensor_id, sensor.as_ref())
+            .await
+            .map(|shadow| shadow.detect(&payload)),
+        _ => None,
+    };
+    let mut response = detect_timed(
+        &state.metrics,
+        &scoring,
+        &detection,
+        sensor.as_ref(),
+        payload,
+    );
+    publish(state, sensor_id, scoring.method(), &response);
+    if let Some(result) = shadowed {
+        shadow::observe(state, result, &response.anomalies);
+    }
+    if let Some(windows) = windows {
+        aggregate(windows, sensor_id, &mut response);
+    }
+    let anomalies = std::mem::take(&mut response.anomalies);
+    let receipt = exporter
+        .export("analyze", &export, anomalies)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::BAD_GATEWAY, format!("export failed: {}", e)))?;
+    response.export = Some(receipt);
+    Ok(Json(response).into_response())
+}
+
+fn negotiate(headers: &HeaderMap, response: AnalyzeResponse) -> Response {
+    if !export::wants_csv(headers) {
+        return PooledJson(response).into_response();
+    }
+
+    match export::encode(&response.anomalies, true) {
+        Ok(body) => {
+            let mut csv = export::csv_response(body);
+            let summary = csv.headers_mut();
+            summary.insert("x-total-readings", response.total_readings.into());
+            for (name, value) in [("x-mean", response.mean), ("x-std-dev", response.std_dev)] {
+                if let Ok(value) = value.to_string().parse() {
+                    summary.insert(name, value);
+                }
+            }
+            csv
+        }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to encode CSV: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// The role detection requires: `operator` when the anomalies it finds are
+/// stored, since a caller could otherwise fill the database, and
+/// `read-only` when they are not.
+fn detection_role(state: &AppState) -> Role {
+    match state.storage {
+        Some(_) => Role::Operator,
+        None => Role::ReadOnly,
+    }
+}
+
+/// The service's routes, in groups by the role they require (see `access`).
+fn app(state: AppState, access: Arc<access::Access>) -> Router {
+    let require = |role| middleware::from_fn_with_state((access.clone(), role), access::authorize);
+    // Outside `require`, so refused requests are logged too.
+    let log = middleware::from_fn_with_state(state.clone(), request_log::record);
+
+    // Probes need no credential.
+    let public = Router::new().route("/health", get(health_check));
+
+    // The dashboard's assets, which hold no data, need one unless they are
+    // served publicly so a browser can open the dashboard.
+    let dashboard = if access.public_dashboard() {
+        ui::routes()
+    } else {
+        ui::routes().route_layer(require(Role::ReadOnly))
+    };
+
+    // Detection, which stores the anomalies it finds when there is storage.
+    let detect = Router::new()
+        .route("/analyze", post(analyze_negotiated))
+        .route("/analyze/batch", post(batch::analyze_batch))
+        .route("/stream/ingest", post(stream::ingest))
+        .route("/stream", get(stream::connect))
+        .route_layer(require(detection_role(&state)));
+
+    // Queries and scrapes.
+    let read = Router::new()
+        .route("/metrics", get(metrics_handler))
+        .route("/detectors", get(list_detectors))
+        .route("/schemas/alert.json", get(schemas::alert))
+        .route("/evaluate-labels", post(evaluate::evaluate_labels))
+        .route("/generate", post(generate::generate_series))
+        .route("/sensors", get(sensors::list_sensors))
+        .route("/sensors/activity", get(activity::activity))
+        .route("/sensors/{sensor_id}", get(sensors::get_sensor))
+        .route("/sensors/{sensor_id}/series", get(rollups::series))
+        .route("/sensors/{sensor_id}/shadow", get(shadow::get_shadow))
+        .route(
+            "/sensors/{sensor_id}/shadow/agreement",
+            get(shadow::agreement),
+        )
+        .route("/sensors/{sensor_id}/tags", get(tags::get_tags))
+        .route("/anomalies", get(anomalies::list_anomalies))
+        .route("/anomalies/{id}", get(anomalies::get_anomaly))
+        .route("/ws/anomalies", get(events::subscribe))
+        .route("/baselines", get(baselines::baselines))
+        .route("/slo", get(slo::report))
+        .route("/usage", get(usage::report))
+        .route("/query", post(query::query))
+        .route("/incidents", get(notify::incident::list_incidents))
+        .route("/incidents/{id}", get(notify::incident::get_incident))
+        .route("/routing", get(notify::routing::get_routing))
+        .route("/silences", get(notify::silence::list_silences))
+        .route("/webhooks", get(notify::webhook::list_webhooks))
+        .route(
+            "/webhooks/{name}/deliveries",
+            get(notify::webhook::list_deliveries),
+        )
+        .route_layer(require(Role::ReadOnly));
+
+    // Changes to sensors and incident handling, and jobs storing results.
+    let operate = Router::new()
+        .route("/backfill", post(backfill::backfill))
+        .route("/replay", post(replay::replay))
+        .route("/sensors", post(sensors::create_sensor))
+        .route(
+            "/sensors/{sensor_id}",
+            put(sensors::put_sensor).delete(sensors::delete_sensor),
+        )
+        .route(
+            "/sensors/{sensor_id}/shadow",
+            put(shadow::put_shadow).delete(shadow::delete_shadow),
+        )
+        .route("/sensors/{sensor_id}/tags", put(tags::put_tags))
+        .route("/incidents/acknowledge", post(notify::acknowledge))
+        .route(
+            "/incidents/{id}/acknowledge",
+            post(notify::incident::acknowledge_incident),
+        )
+        .route(
+            "/incidents/{id}/resolve",
+            post(notify::incident::resolve_incident),
+        )
+        .route(
+            "/incidents/{id}/assignee",
+            put(notify::incident::assign_incident),
+        )
+        .route("/incidents/{id}/notes", post(notify::incident::add_note))
+        .route("/routing", put(notify::routing::put_routing))
+        .route("/silences", post(notify::silence::create_silence))
+        .route("/silences/{id}", delete(notify::silence::expire_silence))
+        .route_layer(require(Role::Operator))
+        .route_layer(log.clone());
+
+    // The runtime config and webhooks, the audit log and whole-registry
+    // snapshots.
+    let admin = Router::new()
+        .route("/sensors/export", get(sensors::bulk::export_sensors))
+        .route(
+            "/sensors/import",
+            post(sensors::bulk::import_sensors)
+                .layer(DefaultBodyLimit::max(sensors::bulk::MAX_IMPORT_BYTES)),
+        )
+        .route(
+            "/admin/config",
+            get(settings::get_config).put(settings::put_config),
+        )
+        .route("/admin/config/audit", get(settings::get_audit))
+        .route("/webhooks", post(notify::webhook::create_webhook))
+        .route("/webhooks/{name}", delete(notify::webhook::delete_webhook))
+        .route("/audit", get(audit::list_audit))
+        .route("/audit/requests", get(request_log::list_requests))
+        .route("/audit/requests/verify", get(request_log::verify_requests))
+        .route_layer(require(Role::Admin))
+        .route_layer(log);
+
+    public
+        .merge(dashboard)
+        .merge(detect)
+        .merge(read)
+        .merge(operate)
+        .merge(admin)
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics::track,
+        ))
+        .with_state(state)
 }
 
 #[tokio::main]
 async fn main() {
-    let app = Router::new()
-        .route("/health", get(health_check))
-        .route("/analyze", post(analyze));
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    leader::set_instance_id(config.instance_id.clone());
+    config.severity_labels.clone().install();
+    config.redaction.clone().install();
+
+    let mut storage = match &config.database_url {
+        Some(url) => match Storage::connect(url).await {
+            Ok(storage) => Some(storage),
+            Err(e) => {
+                eprintln!("Error: Failed to open database {}: {}", url, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if let Some(url) = &config.alert_store_url {
+        match storage.take() {
+            Some(database) => match alert_store::connect(url).await {
+                Ok(alerts) => storage = Some(database.with_alerts(alerts)),
+                Err(e) => {
+                    eprintln!("Error: Failed to open alert store {}: {}", url, e);
+                    std::process::exit(1);
+                }
+            },
+            None => eprintln!(
+                "Warning: ANOMALY_ALERT_STORE_URL is configured but ANOMALY_DATABASE_URL is not set"
+            ),
+        }
+    }
+
+    let redis = match &config.redis_url {
+        Some(url) => match shared::Redis::connect(url).await {
+            Ok(redis) => Some(redis),
+            Err(e) => {
+                eprintln!("Error: Failed to connect to Redis {}: {}", url, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if config.quotas.is_enabled() && storage.is_none() {
+        eprintln!("Warning: quotas are configured but ANOMALY_DATABASE_URL is not set");
+    }
+    if config.access.rate_limits().is_enabled() && !config.access.is_enabled() {
+        eprintln!("Warning: rate limits are configured but no API keys or JWT secret are");
+    }
+
+    if let Some(storage) = &storage {
+        rollups::spawn(storage.clone(), config.rollup_interval);
+    }
+
+    if config.retention.is_enabled() {
+        match &storage {
+            Some(storage) => retention::spawn(storage.clone(), config.retention.clone()),
+            None => {
+                eprintln!("Warning: Retention is configured but ANOMALY_DATABASE_URL is not set")
+            }
+        }
+    }
+
+    let exporter = match &config.export {
+        Some(target) => match ObjectExporter::from_url(&target.url, &target.options) {
+            Ok(exporter) => Some(exporter),
+            Err(e) => {
+                eprintln!("Error: Failed to configure export to {}: {}", target.url, e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    if let Some(policy) = &config.archive {
+        let exporter = match ObjectExporter::from_url(&policy.target.url, &policy.target.options) {
+            Ok(exporter) => exporter,
+            Err(e) => {
+                eprintln!(
+                    "Error: Failed to configure archive to {}: {}",
+                    policy.target.url, e
+                );
+                std::process::exit(1);
+            }
+        };
+        match &storage {
+            Some(storage) => archive::spawn(storage.clone(), exporter, policy.clone()),
+            None => {
+                eprintln!("Warning: Archive is configured but ANOMALY_DATABASE_URL is not set")
+            }
+        }
+    }
+
+    let settings = Arc::new(Settings::load(storage.as_ref()).await);
+
+    let notifier = match &config.notify_config {
+        Some(path) => match Notifier::load(path, storage.clone()) {
+            Ok(notifier) => {
+                let notifier = match &redis {
+                    Some(redis) => notifier.with_redis(redis.clone()),
+                    None => notifier,
+                };
+                notifier.set_enabled(settings.get().notifications);
+                notifier.restore_webhooks().await;
+                let notifier = Arc::new(notifier);
+                notify::spawn(&notifier);
+                Some(notifier)
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error: Invalid notification config {}: {}",
+                    path.display(),
+                    e
+                );
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let events = Arc::<Events>::default();
+    if let Some(target) = &config.analytics {
+        match analytics::AnalyticsSink::connect(&target.url, &target.table).await {
+            Ok(sink) => analytics::spawn(sink, &events, target),
+            Err(e) => {
+                eprintln!(
+                    "Error: Failed to open analytics database {}: {}",
+                    target.url, e
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let query = match &storage {
+        Some(storage) => match query::QueryEngine::new(storage.clone()) {
+            Ok(engine) => Some(Arc::new(engine)),
+            Err(e) => {
+                eprintln!("Error: Failed to open the query engine: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let metrics = Arc::new(Metrics::with_tracking(config.tracking.clone()));
+    let state = AppState {
+        metrics: metrics.clone(),
+        storage,
+        redis,
+        exporter,
+        notifier,
+        settings,
+        events,
+        query,
+        // Detectors of other crates are registered here, e.g.
+        // `Registry::builtin().with(other_crate::SpectralDetector::new())`.
+        detectors: Arc::new(Registry::builtin().with(HoltWintersDetector {
+            period: config.stream.season,
+            ..HoltWintersDetector::default()
+        })),
+        slo: Arc::new(config.slo.clone()),
+        quotas: Arc::new(config.quotas.clone()),
+        lanes: Arc::new(lanes::Lanes::new(&config.lanes, metrics)),
+        streams: Arc::new(stream::Streams::new(
+            config.stream.clone(),
+            config.tracking.clone(),
+        )),
+    };
+
+    let access = Arc::new(config.access.clone());
+    if let Some(port) = config.grpc_port {
+        let listener = match tokio::net::TcpListener::bind(("0.0.0.0", port)).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error: Failed to bind the gRPC port {}: {}", port, e);
+                std::process::exit(1);
+            }
+        };
+        println!("gRPC service listening on 0.0.0.0:{}", port);
+        tokio::spawn(grpc::serve(listener, state.clone(), access.clone()));
+    }
+    let app = app(state, access);
 
     let listener = match tokio::net::TcpListener::bind("0.0.0.0:3001").await {
         Ok(listener) => listener,
@@ -128,60 +1226,42 @@ async fn main() {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_calculate_mean() {
-        let values = vec![10.0, 20.0, 30.0, 40.0, 50.0];
-        let mean = calculate_mean(&values);
-        assert_eq!(mean, 30.0);
-    }
-
-    #[test]
-    fn test_calculate_mean_empty() {
-        let values = vec![];
-        let mean = calculate_mean(&values);
-        assert_eq!(mean, 0.0);
-    }
-
-    #[test]
-    fn test_calculate_std_dev() {
-        let values = vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
-        let mean = calculate_mean(&values);
-        let std_dev = calculate_std_dev(&values, mean);
-        assert!((std_dev - 2.138).abs() < 0.01);
-    }
-
-    #[test]
-    fn test_calculate_std_dev_single_value() {
-        let values = vec![42.0];
-        let mean = calculate_mean(&values);
-        let std_dev = calculate_std_dev(&values, mean);
-        assert_eq!(std_dev, 0.0);
-    }
-
     #[tokio::test]
     async fn test_analyze_no_anomalies() {
         let request = AnalyzeRequest {
+            sensor_id: None,
             readings: vec![
                 Reading {
                     id: 1,
                     value: 70.0,
                     timestamp: "2026-01-19T10:00:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 2,
                     value: 72.0,
                     timestamp: "2026-01-19T10:01:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 3,
                     value: 71.0,
                     timestamp: "2026-01-19T10:02:00".to_string(),
+                    sensor_id: None,
                 },
             ],
-            threshold: 2.0,
+            method: None,
+            ensemble: None,
+            threshold: Some(2.0),
+            seed: None,
+            severity: None,
+            export: None,
+            window: None,
         };
 
-        let Json(response) = analyze(Json(request)).await;
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
 
         assert_eq!(response.total_readings, 3);
         assert_eq!(response.anomalies.len(), 0);
@@ -192,61 +1272,79 @@ mod tests {
     async fn test_analyze_with_anomalies() {
         // Create a dataset where one value is clearly an outlier
         let request = AnalyzeRequest {
+            sensor_id: None,
             readings: vec![
                 Reading {
                     id: 1,
                     value: 10.0,
                     timestamp: "2026-01-19T10:00:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 2,
                     value: 12.0,
                     timestamp: "2026-01-19T10:01:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 3,
                     value: 11.0,
                     timestamp: "2026-01-19T10:02:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 4,
                     value: 11.5,
                     timestamp: "2026-01-19T10:03:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 5,
                     value: 10.5,
                     timestamp: "2026-01-19T10:04:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 6,
                     value: 11.0,
                     timestamp: "2026-01-19T10:05:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 7,
                     value: 10.8,
                     timestamp: "2026-01-19T10:06:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 8,
                     value: 11.2,
                     timestamp: "2026-01-19T10:07:00".to_string(),
+                    sensor_id: None,
                 },
                 Reading {
                     id: 9,
                     value: 200.0,
                     timestamp: "2026-01-19T10:08:00".to_string(),
+                    sensor_id: None,
                 }, // Extreme outlier
             ],
-            threshold: 2.0,
+            method: None,
+            ensemble: None,
+            threshold: Some(2.0),
+            seed: None,
+            severity: None,
+            export: None,
+            window: None,
         };
 
-        let Json(response) = analyze(Json(request)).await;
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
 
         assert_eq!(response.total_readings, 9);
         assert!(
-            response.anomalies.len() > 0,
+            !response.anomalies.is_empty(),
             "Should detect at least one anomaly"
         );
 
@@ -265,6 +1363,7 @@ mod tests {
                 id: i,
                 value: 50.0,
                 timestamp: format!("2026-01-19T10:{:02}:00", i),
+                sensor_id: None,
             });
         }
         // Add extreme outlier
@@ -272,20 +1371,752 @@ mod tests {
             id: 21,
             value: 500.0,
             timestamp: "2026-01-19T10:21:00".to_string(),
+            sensor_id: None,
         });
 
         let request = AnalyzeRequest {
+            sensor_id: None,
             readings,
-            threshold: 2.0,
+            method: None,
+            ensemble: None,
+            threshold: Some(2.0),
+            seed: None,
+            severity: None,
+            export: None,
+            window: None,
         };
 
-        let Json(response) = analyze(Json(request)).await;
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
 
-        assert!(response.anomalies.len() > 0);
+        assert!(!response.anomalies.is_empty());
 
         // The extreme outlier should be marked as critical
         let critical_anomaly = response.anomalies.iter().find(|a| a.id == 21);
         assert!(critical_anomaly.is_some());
-        assert_eq!(critical_anomaly.unwrap().severity, "critical");
+        assert_eq!(critical_anomaly.unwrap().severity, Severity::Critical);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_with_request_severity_bands() {
+        let request = AnalyzeRequest {
+            severity: Some(SeverityRequest {
+                high: Some(5.0),
+                critical: Some(10.0),
+                labels: None,
+            }),
+            ..spiky_request(None)
+        };
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
+        assert_eq!(response.anomalies.len(), 1);
+        assert_eq!(response.anomalies[0].severity, Severity::Medium);
+
+        let inverted = AnalyzeRequest {
+            severity: Some(SeverityRequest {
+                high: Some(3.0),
+                critical: Some(2.0),
+                labels: None,
+            }),
+            ..spiky_request(None)
+        };
+        let Err((status, _)) = analyze(State(AppState::default()), Json(inverted)).await else {
+            panic!("inverted bands were accepted");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_shows_severities_with_the_request_labels() {
+        let labelled = |critical: &str| AnalyzeRequest {
+            severity: Some(SeverityRequest {
+                labels: Some(SeverityLabels {
+                    critical: critical.to_string(),
+                    ..SeverityLabels::default()
+                }),
+                ..SeverityRequest::default()
+            }),
+            ..spiky_request(None)
+        };
+        let Json(response) = analyze(State(AppState::default()), Json(labelled("P1")))
+            .await
+            .unwrap();
+        assert_eq!(response.anomalies[0].severity, Severity::Critical);
+        let shown = serde_json::to_value(&response).unwrap();
+        assert_eq!(shown["anomalies"][0]["severity"], "P1");
+
+        let Json(unlabelled) = analyze(State(AppState::default()), Json(spiky_request(None)))
+            .await
+            .unwrap();
+        assert!(unlabelled.anomalies[0].labels.is_none());
+
+        let Err((status, message)) =
+            analyze(State(AppState::default()), Json(labelled("high"))).await
+        else {
+            panic!("a label clashing with another severity was accepted");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.starts_with("severity.labels"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_records_detection_metrics() {
+        let state = AppState::default();
+        let request = AnalyzeRequest {
+            sensor_id: None,
+            readings: vec![
+                Reading {
+                    id: 1,
+                    value: 1.0,
+                    timestamp: "2026-01-19T10:00:00".to_string(),
+                    sensor_id: None,
+                },
+                Reading {
+                    id: 2,
+                    value: 2.0,
+                    timestamp: "2026-01-19T10:01:00".to_string(),
+                    sensor_id: None,
+                },
+            ],
+            method: None,
+            ensemble: None,
+            threshold: Some(2.0),
+            seed: None,
+            severity: None,
+            export: None,
+            window: None,
+        };
+
+        let _ = analyze(State(state.clone()), Json(request)).await.unwrap();
+
+        let output = state.metrics.render();
+        assert!(output.contains("anomaly_detection_duration_seconds_count{method=\"zscore\"} 1"));
+        assert!(!output.contains("anomaly_sensor_"));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_records_per_sensor_metrics() {
+        let state = AppState::default();
+        let request = AnalyzeRequest {
+            sensor_id: Some(4),
+            ..spiky_request(None)
+        };
+
+        let Json(response) = analyze(State(state.clone()), Json(request)).await.unwrap();
+
+        let output = state.metrics.render();
+        let expected = format!(
+            "anomaly_sensor_anomalies_total{{sensor_id=\"4\",severity=\"{}\"}} 1",
+            response.anomalies[0].severity
+        );
+        assert!(output.contains(&expected), "{}", output);
+        let mean = format!(
+            "anomaly_sensor_baseline_mean{{sensor_id=\"4\"}} {}",
+            response.mean
+        );
+        assert!(output.contains(&mean), "{}", output);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_applies_sensor_registry() {
+        let storage = storage::testing::in_memory().await;
+        let state = AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        };
+        let mut request = spiky_request(None);
+        request.readings[0].value = 51.0;
+        request.threshold = None;
+        request.sensor_id = Some(7);
+        let Json(unregistered) = analyze(State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(unregistered.anomalies.len(), 1);
+
+        let config = serde_json::from_value(serde_json::json!({
+            "name": "Boiler",
+            "threshold": 10.0,
+            "max_value": 50.5,
+        }))
+        .unwrap();
+        let saved = sensors::put_sensor(
+            State(state.clone()),
+            axum::extract::Path(7),
+            HeaderMap::new(),
+            Json(config),
+        )
+        .await;
+        assert!(saved.is_ok());
+        let mut request = spiky_request(None);
+        request.readings[0].value = 51.0;
+        request.threshold = None;
+        request.sensor_id = Some(7);
+        let Json(registered) = analyze(State(state), Json(request)).await.unwrap();
+
+        let flagged: Vec<(i64, &str)> = registered
+            .anomalies
+            .iter()
+            .map(|a| (a.id, a.severity.as_str()))
+            .collect();
+        assert_eq!(flagged, vec![(1, "critical"), (21, "critical")]);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_groups_readings_by_sensor() {
+        let mut request = spiky_request(None);
+        for reading in &mut request.readings {
+            reading.sensor_id = Some(1);
+        }
+        request.readings.extend((1..=20).map(|i| Reading {
+            id: 100 + i,
+            value: 500.0 + (i % 3) as f64,
+            timestamp: format!("2026-01-19T10:{:02}:00", i),
+            sensor_id: Some(2),
+        }));
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
+
+        let ids: Vec<i64> = response.anomalies.iter().map(|a| a.id).collect();
+        assert_eq!(ids, vec![21]);
+        assert_eq!(response.total_readings, 41);
+        let sensors: Vec<(Option<i64>, usize, usize)> = response
+            .sensors
+            .iter()
+            .map(|s| {
+                (
+                    s.sensor_id,
+                    s.result.total_readings,
+                    s.result.anomalies.len(),
+                )
+            })
+            .collect();
+        assert_eq!(sensors, vec![(Some(1), 21, 1), (Some(2), 20, 0)]);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_publishes_events_for_named_sensors() {
+        let state = AppState::default();
+        let mut events = state.events.subscribe();
+
+        let _ = analyze(State(state.clone()), Json(spiky_request(None)))
+            .await
+            .unwrap();
+        let mut request = spiky_request(None);
+        request.sensor_id = Some(7);
+        let _ = analyze(State(state), Json(request)).await.unwrap();
+
+        let event = events.try_recv().unwrap();
+        assert_eq!((event.sensor_id, event.reading_id), (7, 21));
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_csv_when_requested() {
+        let mut readings: Vec<Reading> = (1..=20)
+            .map(|i| Reading {
+                id: i,
+                value: 50.0,
+                timestamp: format!("2026-01-19T10:{:02}:00", i),
+                sensor_id: None,
+            })
+            .collect();
+        readings.push(Reading {
+            id: 21,
+            value: 500.0,
+            timestamp: "2026-01-19T10:21:00".to_string(),
+            sensor_id: None,
+        });
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, "text/csv".parse().unwrap());
+
+        let response = analyze_negotiated(
+            State(AppState::default()),
+            headers,
+            JsonBody(AnalyzeRequest {
+                sensor_id: None,
+                readings,
+                method: None,
+                threshold: Some(2.0),
+                ensemble: None,
+                seed: None,
+                severity: None,
+                export: None,
+                window: None,
+            }),
+        )
+        .await;
+
+        assert_eq!(response.headers()["x-total-readings"], "21");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines[0], "id,value,timestamp,z_score,severity");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("21,500.0,2026-01-19T10:21:00,"));
+    }
+
+    fn spiky_request(export: Option<ExportRequest>) -> AnalyzeRequest {
+        let mut readings: Vec<Reading> = (1..=20)
+            .map(|i| Reading {
+                id: i,
+                value: 50.0,
+                timestamp: format!("2026-01-19T10:{:02}:00", i),
+                sensor_id: None,
+            })
+            .collect();
+        readings.push(Reading {
+            id: 21,
+            value: 500.0,
+            timestamp: "2026-01-19T10:21:00".to_string(),
+            sensor_id: None,
+        });
+        AnalyzeRequest {
+            sensor_id: None,
+            readings,
+            method: None,
+            ensemble: None,
+            threshold: Some(2.0),
+            seed: None,
+            severity: None,
+            export,
+            window: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_analyze_aggregates_readings_per_window() {
+        let body = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        };
+        let mut request = spiky_request(None);
+        request.window = Some("10m".to_string());
+
+        let response = analyze_negotiated(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(request),
+        )
+        .await;
+
+        let windows = body(response).await["windows"].clone();
+        let counts: Vec<(&str, i64, i64)> = windows
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|w| {
+                (
+                    w["start"].as_str().unwrap(),
+                    w["count"].as_i64().unwrap(),
+                    w["anomaly_count"].as_i64().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            counts,
+            [
+                ("2026-01-19T10:00:00Z", 9, 0),
+                ("2026-01-19T10:10:00Z", 10, 0),
+                ("2026-01-19T10:20:00Z", 2, 1),
+            ]
+        );
+        assert_eq!(windows[2]["max"], 500.0);
+
+        let mut invalid = spiky_request(None);
+        invalid.window = Some("10m".to_string());
+        invalid.readings[0].timestamp = "yesterday".to_string();
+        let response = analyze_negotiated(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(invalid),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body(response).await["field"], "readings[0].timestamp");
+
+        // Readings naming their sensors are aggregated under each sensor.
+        let mut request = spiky_request(None);
+        request.window = Some("10m".to_string());
+        for (i, reading) in request.readings.iter_mut().enumerate() {
+            reading.sensor_id = Some(i as i64 % 2);
+        }
+        let response = analyze_negotiated(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(request),
+        )
+        .await;
+        let body = body(response).await;
+        assert!(body.get("windows").is_none());
+        let counts: Vec<Vec<i64>> = body["sensors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|sensor| {
+                let windows = sensor["windows"].as_array().unwrap();
+                windows
+                    .iter()
+                    .map(|w| w["count"].as_i64().unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(counts, [[5, 5, 1], [4, 5, 1]]);
+    }
+
+    #[test]
+    fn test_seed_reaches_stochastic_detectors() {
+        let registry = Registry::builtin();
+        let values: Vec<f64> = (0..500).map(|i| ((i * 37) % 101) as f64).collect();
+        let scores = |seed: Option<u64>| {
+            let request: AnalyzeRequest = serde_json::from_value(serde_json::json!({
+                "readings": [], "method": "reservoir", "seed": seed
+            }))
+            .unwrap();
+            match requested_scoring(&registry, &request).unwrap() {
+                Scoring::Detector(detector) => detector.scores(&values),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(scores(Some(7)), scores(Some(7)));
+        assert_ne!(scores(Some(7)), scores(None));
+        assert_eq!(scores(Some(0)), scores(None));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_exports_to_object_store() {
+        let (exporter, store) = object_export::testing::in_memory();
+        let state = AppState {
+            exporter: Some(exporter),
+            ..AppState::default()
+        };
+        let export = ExportRequest {
+            format: object_export::ExportFormat::Csv,
+            key: Some("run.csv".to_string()),
+        };
+
+        let response = analyze_negotiated(
+            State(state),
+            HeaderMap::new(),
+            JsonBody(spiky_request(Some(export))),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["anomalies"].as_array().unwrap().len(), 0);
+        assert_eq!(body["export"]["rows"], 1);
+        assert_eq!(body["export"]["url"], "memory://bucket/exports/run.csv");
+
+        let object = object_export::testing::read(&store, "memory://bucket/exports/run.csv").await;
+        let object = String::from_utf8(object).unwrap();
+        assert_eq!(object.lines().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_export_without_exporter() {
+        let export = ExportRequest {
+            format: object_export::ExportFormat::Parquet,
+            key: None,
+        };
+
+        let response = analyze_negotiated(
+            State(AppState::default()),
+            HeaderMap::new(),
+            JsonBody(spiky_request(Some(export))),
+        )
+        .await;
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_refused_exports_are_not_charged() {
+        let (exporter, _store) = object_export::testing::in_memory();
+        let state = AppState {
+            storage: Some(storage::testing::in_memory().await),
+            exporter: Some(exporter),
+            ..AppState::default()
+        };
+        let export = || ExportRequest {
+            format: object_export::ExportFormat::Csv,
+            key: None,
+        };
+        let mut named = spiky_request(Some(export()));
+        named.readings[0].sensor_id = Some(7);
+        let unknown = AnalyzeRequest {
+            method: Some("fourier".to_string()),
+            ..spiky_request(Some(export()))
+        };
+
+        for request in [named, unknown] {
+            let response =
+                analyze_negotiated(State(state.clone()), HeaderMap::new(), JsonBody(request)).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+
+        let Json(usage) = usage::report(
+            State(state),
+            None,
+            axum::extract::Query(usage::UsageQuery::default()),
+        )
+        .await
+        .unwrap();
+        assert!(usage.tenants.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_analyze_with_named_method() {
+        let mut request = AnalyzeRequest {
+            method: Some("mad".to_string()),
+            threshold: None,
+            ..spiky_request(None)
+        };
+        // A flat series has no MAD to scale by.
+        for reading in &mut request.readings[..20] {
+            reading.value += (reading.id % 3) as f64;
+        }
+
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
+
+        assert_eq!(response.anomalies.len(), 1);
+        assert_eq!(response.anomalies[0].id, 21);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_with_ensemble_reports_member_scores() {
+        let mut request = AnalyzeRequest {
+            method: Some("ensemble".to_string()),
+            threshold: None,
+            ensemble: Some(
+                serde_json::from_value(serde_json::json!({
+                    "members": [{ "method": "zscore" }, { "method": "mad" }, { "method": "iqr" }]
+                }))
+                .unwrap(),
+            ),
+            ..spiky_request(None)
+        };
+        for reading in &mut request.readings[..20] {
+            reading.value += (reading.id % 3) as f64;
+        }
+
+        let Json(response) = analyze(State(AppState::default()), Json(request))
+            .await
+            .unwrap();
+
+        assert_eq!(response.anomalies.len(), 1);
+        let body = serde_json::to_value(&response).unwrap();
+        let members = body["ensemble"]["members"].as_array().unwrap();
+        let methods: Vec<&str> = members
+            .iter()
+            .map(|m| m["method"].as_str().unwrap())
+            .collect();
+        assert_eq!(methods, ["zscore", "mad", "iqr"]);
+        let scores: Vec<f64> = members
+            .iter()
+            .map(|m| m["scores"][0].as_f64().unwrap())
+            .collect();
+        let mean = scores.iter().sum::<f64>() / 3.0;
+        assert!((response.anomalies[0].z_score - mean).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_rejects_ensemble_without_its_method() {
+        let request = AnalyzeRequest {
+            ensemble: Some(
+                serde_json::from_value(serde_json::json!({ "members": [{ "method": "mad" }] }))
+                    .unwrap(),
+            ),
+            ..spiky_request(None)
+        };
+
+        let Err((status, _)) = analyze(State(AppState::default()), Json(request)).await else {
+            panic!("ensemble accepted without its method");
+        };
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_rejects_unknown_method() {
+        let request = AnalyzeRequest {
+            method: Some("prophet".to_string()),
+            ..spiky_request(None)
+        };
+
+        let Err((status, message)) = analyze(State(AppState::default()), Json(request)).await
+        else {
+            panic!("unknown method accepted");
+        };
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(message.contains(
+            "ewma, holt_winters, iqr, mad, percentile, reservoir, rolling, zscore or ensemble"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_analyze_rejects_invalid_readings_naming_the_field() {
+        let mut not_finite = spiky_request(None);
+        not_finite.readings[3].value = f64::INFINITY;
+        let mut repeated = spiky_request(Some(ExportRequest {
+            format: object_export::ExportFormat::Csv,
+            key: None,
+        }));
+        repeated.readings[4].id = repeated.readings[2].id;
+
+        for (request, field) in [
+            (not_finite, "readings[3].value"),
+            (repeated, "readings[4].id"),
+        ] {
+            let response = analyze_negotiated(
+                State(AppState::default()),
+                HeaderMap::new(),
+                JsonBody(request),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let error: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(error["field"], field);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_list_detectors() {
+        let Json(names) = list_detectors(State(AppState::default())).await;
+
+        assert_eq!(
+            names,
+            [
+                "ewma",
+                "holt_winters",
+                "iqr",
+                "mad",
+                "percentile",
+                "reservoir",
+                "rolling",
+                "zscore"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_groups_require_roles() {
+        let keys = BTreeMap::from([
+            (
+                "dashboard".to_string(),
+                "read-only:dashboard-key-0123456789".to_string(),
+            ),
+            (
+                "ops".to_string(),
+                "operator:ops-key-0123456789abcdef".to_string(),
+            ),
+        ]);
+        let access = access::Access::new(&keys, None, access::DEFAULT_ROLE_CLAIM).unwrap();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let storage = storage::testing::in_memory().await;
+        let state = AppState {
+            storage: Some(storage.clone()),
+            ..AppState::default()
+        };
+        let app = app(state, Arc::new(access));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let status = |method: reqwest::Method, path: &str, key: Option<&str>| {
+            let mut request = client.request(method, format!("{}{}", base, path));
+            if let Some(key) = key {
+                request = request.bearer_auth(key);
+            }
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+        let (dashboard, ops) = (
+            Some("dashboard-key-0123456789"),
+            Some("ops-key-0123456789abcdef"),
+        );
+        assert_eq!(status(reqwest::Method::GET, "/health", None).await, 200);
+        assert_eq!(status(reqwest::Method::GET, "/metrics", None).await, 401);
+        assert_eq!(
+            status(reqwest::Method::GET, "/metrics", dashboard).await,
+            200
+        );
+        assert_eq!(status(reqwest::Method::GET, "/ui/", None).await, 401);
+        assert_eq!(status(reqwest::Method::GET, "/detectors", None).await, 401);
+        assert_eq!(
+            status(reqwest::Method::GET, "/detectors", dashboard).await,
+            200
+        );
+        // Reading a sensor is allowed, changing one is not.
+        assert_eq!(
+            status(reqwest::Method::GET, "/sensors/1", dashboard).await,
+            404
+        );
+        assert_eq!(
+            status(reqwest::Method::DELETE, "/sensors/1", dashboard).await,
+            403
+        );
+        assert_eq!(
+            status(reqwest::Method::DELETE, "/sensors/1", ops).await,
+            404
+        );
+        assert_eq!(
+            status(reqwest::Method::GET, "/admin/config", ops).await,
+            403
+        );
+        // With storage, detection stores what it finds.
+        assert_eq!(
+            status(reqwest::Method::POST, "/analyze", dashboard).await,
+            403
+        );
+        assert_eq!(status(reqwest::Method::POST, "/analyze", ops).await, 415);
+
+        // Mutating requests are logged, refused or not, and reads are not.
+        let logged = storage
+            .request_entries(&storage::RequestFilter::default(), 10)
+            .await
+            .unwrap();
+        let logged: Vec<(&str, &str, i64)> = logged
+            .iter()
+            .map(|entry| (entry.actor.as_str(), entry.method.as_str(), entry.status))
+            .collect();
+        assert_eq!(
+            logged,
+            [("ops", "DELETE", 404), ("dashboard", "DELETE", 403)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_public_dashboard_serves_only_the_assets_without_a_key() {
+        let keys = BTreeMap::from([(
+            "dashboard".to_string(),
+            "read-only:dashboard-key-0123456789".to_string(),
+        )]);
+        let access = access::Access::new(&keys, None, access::DEFAULT_ROLE_CLAIM)
+            .unwrap()
+            .with_public_dashboard(true);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = app(AppState::default(), Arc::new(access));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let status = |path: &str| {
+            let request = reqwest::Client::new().get(format!("{}{}", base, path));
+            async move { request.send().await.unwrap().status().as_u16() }
+        };
+        assert_eq!(status("/ui/").await, 200);
+        assert_eq!(status("/metrics").await, 401);
     }
 }
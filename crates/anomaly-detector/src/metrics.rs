@@ -0,0 +1,480 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use detection_core::Severity;
+use prometheus::{
+    Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, Opts, Registry, TextEncoder,
+};
+
+use crate::AppState;
+use crate::activity::Activity;
+use crate::config::Tracking;
+
+/// Prometheus metrics exposed on `/metrics`.
+pub struct Metrics {
+    registry: Registry,
+    detection_duration: HistogramVec,
+    detection_throughput: HistogramVec,
+    readings_analyzed: IntCounterVec,
+    anomalies: IntCounterVec,
+    request_duration: HistogramVec,
+    sensor_anomalies: IntCounterVec,
+    sensor_last_anomaly: GaugeVec,
+    sensor_baseline_mean: GaugeVec,
+    sensor_baseline_std_dev: GaugeVec,
+    sensors_distinct: Gauge,
+    sensors_tracked: IntGauge,
+    sensor_evictions: IntCounter,
+    interactive_duration: Histogram,
+    bulk_wait: Histogram,
+    /// Which sensors the `sensor_*` metrics are kept for.
+    activity: Activity,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::with_tracking(Tracking::default())
+    }
+
+    pub fn with_tracking(tracking: Tracking) -> Self {
+        let registry = Registry::new();
+
+        let detection_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "anomaly_detection_duration_seconds",
+                "Time spent running a detector over one batch of readings",
+            )
+            .buckets(prometheus::exponential_buckets(0.000_01, 4.0, 12).unwrap()),
+            &["method"],
+        )
+        .unwrap();
+
+        let detection_throughput = HistogramVec::new(
+            HistogramOpts::new(
+                "anomaly_detection_throughput_readings_per_second",
+                "Readings processed per second by a detector, per batch",
+            )
+            .buckets(prometheus::exponential_buckets(1_000.0, 10.0, 7).unwrap()),
+            &["method"],
+        )
+        .unwrap();
+
+        let readings_analyzed = IntCounterVec::new(
+            Opts::new(
+                "anomaly_readings_analyzed_total",
+                "Readings scored by a detector",
+            ),
+            &["method"],
+        )
+        .unwrap();
+
+        let anomalies = IntCounterVec::new(
+            Opts::new(
+                "anomaly_anomalies_total",
+                "Anomalies detected, of any sensor or none",
+            ),
+            &["method", "severity"],
+        )
+        .unwrap();
+
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "anomaly_http_request_duration_seconds",
+                "Time spent answering an HTTP request, per route and status",
+            )
+            .buckets(prometheus::exponential_buckets(0.000_1, 4.0, 10).unwrap()),
+            &["route", "method", "status"],
+        )
+        .unwrap();
+
+        let sensor_anomalies = IntCounterVec::new(
+            Opts::new(
+                "anomaly_sensor_anomalies_total",
+                "Anomalies detected in the readings of a sensor",
+            ),
+            &["sensor_id", "severity"],
+        )
+        .unwrap();
+
+        let sensor_last_anomaly = GaugeVec::new(
+            Opts::new(
+                "anomaly_sensor_last_anomaly_timestamp_seconds",
+                "Unix time an anomaly was last detected for a sensor",
+            ),
+            &["sensor_id"],
+        )
+        .unwrap();
+
+        let sensor_baseline_mean = GaugeVec::new(
+            Opts::new(
+                "anomaly_sensor_baseline_mean",
+                "Mean of the sensor's readings in its latest analyzed series",
+            ),
+            &["sensor_id"],
+        )
+        .unwrap();
+
+        let sensor_baseline_std_dev = GaugeVec::new(
+            Opts::new(
+                "anomaly_sensor_baseline_std_dev",
+                "Standard deviation of the sensor's readings in its latest analyzed series",
+            ),
+            &["sensor_id"],
+        )
+        .unwrap();
+
+        let sensors_distinct = Gauge::new(
+            "anomaly_sensors_distinct",
+            "Estimated distinct sensors with an analyzed series",
+        )
+        .unwrap();
+
+        let sensors_tracked = IntGauge::new(
+            "anomaly_sensors_tracked",
+            "Sensors the per-sensor metrics are kept for",
+        )
+        .unwrap();
+
+        let sensor_evictions = IntCounter::new(
+            "anomaly_sensors_evicted_total",
+            "Sensors whose per-sensor metrics were dropped, idle or least recently seen",
+        )
+        .unwrap();
+
+        let interactive_duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "anomaly_interactive_request_duration_seconds",
+                "Time spent answering an interactive detection request, see `lanes`",
+            )
+            .buckets(prometheus::exponential_buckets(0.000_1, 4.0, 10).unwrap()),
+        )
+        .unwrap();
+
+        let bulk_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "anomaly_bulk_wait_seconds",
+                "Time a unit of bulk work waited for its turn, see `lanes`",
+            )
+            .buckets(prometheus::exponential_buckets(0.000_1, 4.0, 10).unwrap()),
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(detection_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(detection_throughput.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(readings_analyzed.clone()))
+            .unwrap();
+        registry.register(Box::new(anomalies.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensor_anomalies.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensor_last_anomaly.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensor_baseline_mean.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensor_baseline_std_dev.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensors_distinct.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensors_tracked.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(sensor_evictions.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(interactive_duration.clone()))
+            .unwrap();
+        registry.register(Box::new(bulk_wait.clone())).unwrap();
+
+        Self {
+            registry,
+            detection_duration,
+            detection_throughput,
+            readings_analyzed,
+            anomalies,
+            request_duration,
+            sensor_anomalies,
+            sensor_last_anomaly,
+            sensor_baseline_mean,
+            sensor_baseline_std_dev,
+            sensors_distinct,
+            sensors_tracked,
+            sensor_evictions,
+            interactive_duration,
+            bulk_wait,
+            activity: Activity::new(tracking),
+        }
+    }
+
+    pub fn activity(&self) -> &Activity {
+        &self.activity
+    }
+
+    /// Drops the per-sensor metrics of `evicted` sensors.
+    fn forget(&self, evicted: &[i64]) {
+        for sensor_id in evicted {
+            let sensor = sensor_id.to_string();
+            for severity in [Severity::Medium, Severity::High, Severity::Critical] {
+                let _ = self
+                    .sensor_anomalies
+                    .remove_label_values(&[&sensor, severity.as_str()]);
+            }
+            for gauge in [
+                &self.sensor_last_anomaly,
+                &self.sensor_baseline_mean,
+                &self.sensor_baseline_std_dev,
+            ] {
+                let _ = gauge.remove_label_values(&[&sensor]);
+            }
+            self.sensor_evictions.inc();
+        }
+    }
+
+    /// Records one detector run over `readings` values that took `elapsed`.
+    pub fn observe_detection(&self, method: &str, readings: usize, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        self.detection_duration
+            .with_label_values(&[method])
+            .observe(seconds);
+        self.readings_analyzed
+            .with_label_values(&[method])
+            .inc_by(readings as u64);
+
+        // Throughput is meaningless for empty batches or sub-resolution timings.
+        if readings > 0 && seconds > 0.0 {
+            self.detection_throughput
+                .with_label_values(&[method])
+                .observe(readings as f64 / seconds);
+        }
+    }
+
+    /// Counts the anomalies one detector run found, by severity.
+    pub fn observe_anomalies(&self, method: &str, severities: impl IntoIterator<Item = Severity>) {
+        for severity in severities {
+            self.anomalies
+                .with_label_values(&[method, severity.as_str()])
+                .inc();
+        }
+    }
+
+    /// Records an answered request to `route`, the path pattern it matched.
+    pub fn observe_request(&self, route: &str, method: &str, status: u16, elapsed: Duration) {
+        self.request_duration
+            .with_label_values(&[route, method, &status.to_string()])
+            .observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_interactive(&self, elapsed: Duration) {
+        self.interactive_duration.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn observe_bulk_wait(&self, waited: Duration) {
+        self.bulk_wait.observe(waited.as_secs_f64());
+    }
+
+    /// Records the analysis of one series of a sensor: the severities of
+    /// its anomalies and the mean and standard deviation of its readings,
+    /// `None` for an empty series.
+    pub fn observe_sensor(
+        &self,
+        sensor_id: i64,
+        severities: impl IntoIterator<Item = Severity>,
+        baseline: Option<(f64, f64)>,
+    ) {
+        self.forget(&self.activity.observe(sensor_id, Instant::now()));
+        let sensor = sensor_id.to_string();
+        let mut detected = false;
+        for severity in severities {
+            self.sensor_anomalies
+                .with_label_values(&[sensor.as_str(), severity.as_str()])
+                .inc();
+            detected = true;
+        }
+        if detected {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0.0, |d| d.as_secs_f64());
+            self.sensor_last_anomaly
+                .with_label_values(&[&sensor])
+                .set(now);
+        }
+        if let Some((mean, std_dev)) = baseline {
+            self.sensor_baseline_mean
+                .with_label_values(&[&sensor])
+                .set(mean);
+            self.sensor_baseline_std_dev
+                .with_label_values(&[&sensor])
+                .set(std_dev);
+        }
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        self.forget(&self.activity.sweep(Instant::now()));
+        let (distinct, tracked) = self.activity.sensors();
+        self.sensors_distinct.set(distinct.round());
+        self.sensors_tracked.set(tracked as i64);
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+/// Middleware timing every request. Requests matching no route share the
+/// route label `unmatched`, so scans of random paths do not add series.
+pub async fn track(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map_or("unmatched", MatchedPath::as_str)
+        .to_string();
+    let method = request.method().clone();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    state.metrics.observe_request(
+        &route,
+        method.as_str(),
+        response.status().as_u16(),
+        started.elapsed(),
+    );
+    response
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_observe_detection_renders_per_method() {
+        let metrics = Metrics::new();
+        metrics.observe_detection("zscore", 1_000, Duration::from_millis(2));
+
+        let output = metrics.render();
+        assert!(output.contains("anomaly_detection_duration_seconds_count{method=\"zscore\"} 1"));
+        assert!(output.contains(
+            "anomaly_detection_throughput_readings_per_second_count{method=\"zscore\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_readings_and_anomalies_are_counted_per_method() {
+        let metrics = Metrics::new();
+        metrics.observe_detection("mad", 300, Duration::from_millis(1));
+        metrics.observe_detection("mad", 200, Duration::from_millis(1));
+        metrics.observe_anomalies("mad", [Severity::High, Severity::Critical, Severity::High]);
+
+        let output = metrics.render();
+        assert!(output.contains("anomaly_readings_analyzed_total{method=\"mad\"} 500"));
+        assert!(output.contains("anomaly_anomalies_total{method=\"mad\",severity=\"high\"} 2"));
+        assert!(output.contains("anomaly_anomalies_total{method=\"mad\",severity=\"critical\"} 1"));
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_timed_per_route() {
+        let state = AppState::default();
+        let app = axum::Router::new()
+            .route(
+                "/sensors/{sensor_id}",
+                axum::routing::get(|| async { "ok" }),
+            )
+            .layer(axum::middleware::from_fn_with_state(state.clone(), track))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        for path in ["/sensors/1", "/sensors/2", "/nowhere"] {
+            reqwest::get(format!("{}{}", url, path)).await.unwrap();
+        }
+
+        let output = state.metrics.render();
+        assert!(output.contains(
+            "anomaly_http_request_duration_seconds_count\
+             {method=\"GET\",route=\"/sensors/{sensor_id}\",status=\"200\"} 2"
+        ));
+        assert!(output.contains(
+            "anomaly_http_request_duration_seconds_count\
+             {method=\"GET\",route=\"unmatched\",status=\"404\"} 1"
+        ));
+    }
+
+    #[test]
+    fn test_observe_detection_skips_throughput_for_empty_batch() {
+        let metrics = Metrics::new();
+        metrics.observe_detection("zscore", 0, Duration::from_millis(1));
+
+        let output = metrics.render();
+        assert!(output.contains("anomaly_detection_duration_seconds_count{method=\"zscore\"} 1"));
+        assert!(!output.contains("anomaly_detection_throughput_readings_per_second_count"));
+    }
+
+    #[test]
+    fn test_observe_sensor_counts_by_severity() {
+        let metrics = Metrics::new();
+        metrics.observe_sensor(
+            7,
+            [Severity::High, Severity::Critical, Severity::High],
+            Some((20.5, 1.25)),
+        );
+        metrics.observe_sensor(8, [], Some((3.0, 0.5)));
+        metrics.observe_sensor(9, [], None);
+
+        let output = metrics.render();
+        assert!(
+            output.contains("anomaly_sensor_anomalies_total{sensor_id=\"7\",severity=\"high\"} 2")
+        );
+        assert!(
+            output.contains(
+                "anomaly_sensor_anomalies_total{sensor_id=\"7\",severity=\"critical\"} 1"
+            )
+        );
+        assert!(output.contains("anomaly_sensor_baseline_mean{sensor_id=\"7\"} 20.5"));
+        assert!(output.contains("anomaly_sensor_baseline_std_dev{sensor_id=\"8\"} 0.5"));
+        assert!(output.contains("anomaly_sensor_last_anomaly_timestamp_seconds{sensor_id=\"7\"}"));
+        assert!(!output.contains("anomaly_sensor_last_anomaly_timestamp_seconds{sensor_id=\"8\"}"));
+        assert!(!output.contains("sensor_id=\"9\""));
+    }
+
+    #[test]
+    fn test_per_sensor_metrics_are_dropped_past_the_limit() {
+        let metrics = Metrics::with_tracking(Tracking {
+            max_sensors: 2,
+            idle: Duration::from_secs(3_600),
+        });
+        metrics.observe_sensor(1, [Severity::High], Some((1.0, 0.1)));
+        metrics.observe_sensor(2, [], Some((2.0, 0.2)));
+        metrics.observe_sensor(3, [], Some((3.0, 0.3)));
+
+        let output = metrics.render();
+        assert!(!output.contains("sensor_id=\"1\""));
+        assert!(output.contains("anomaly_sensor_baseline_mean{sensor_id=\"3\"} 3"));
+        assert!(output.contains("anomaly_sensors_distinct 3"));
+        assert!(output.contains("anomaly_sensors_tracked 2"));
+        assert!(output.contains("anomaly_sensors_evicted_total 1"));
+    }
+}
@@ -0,0 +1,307 @@
+//! Alertmanager channel: one alert per anomaly episode via the API v2, so
+//! Alertmanager's own routing, grouping, silences and inhibition apply.
+//!
+//! An episode is identified by sensor and detection method. Its alert is
+//! labelled `alertname`, `sensor_id`, `method` and `severity`, plus the
+//! configured `labels`, and is sent again, with its original `startsAt`,
+//! whenever the episode has new anomalies. Each send sets `endsAt`
+//! `timeout_minutes` ahead, and the alert is sent with `endsAt` now once the
+//! sensor scores normal again. Should the severity change, the alert of the
+//! old severity is resolved in the same request.
+//!
+//! Open alerts are tracked in memory. A replica that did not send an alert
+//! cannot resolve it, in which case Alertmanager resolves it at its `endsAt`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::{Severity, render};
+use crate::storage::StoredAnomaly;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+const ALERT_NAME: &str = "SensorAnomaly";
+
+/// Labels set on every alert, which `labels` may not override.
+const RESERVED_LABELS: [&str; 4] = ["alertname", "sensor_id", "method", "severity"];
+
+#[derive(Deserialize)]
+pub struct AlertmanagerConfig {
+    /// Base URL of Alertmanager, e.g. `http://alertmanager:9093`.
+    url: String,
+    /// Anomalies below this severity do not raise alerts.
+    #[serde(default = "default_min_severity")]
+    min_severity: Severity,
+    /// Added to every alert, e.g. `{"team": "plant"}`.
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    /// Link sent as `generatorURL`; `{sensor_id}` is replaced.
+    #[serde(default)]
+    generator_url: Option<String>,
+    /// How long an alert stays firing without new anomalies or a resolve.
+    #[serde(default = "default_timeout_minutes")]
+    timeout_minutes: u64,
+}
+
+fn default_min_severity() -> Severity {
+    Severity::Medium
+}
+
+fn default_timeout_minutes() -> u64 {
+    30
+}
+
+pub struct AlertmanagerNotifier {
+    client: reqwest::Client,
+    config: AlertmanagerConfig,
+    alerts_url: String,
+    /// The alert last sent for each open episode.
+    open: Mutex<HashMap<(i64, String), Value>>,
+}
+
+impl AlertmanagerNotifier {
+    pub fn new(config: AlertmanagerConfig) -> Result<Self, String> {
+        url::Url::parse(&config.url).map_err(|e| format!("invalid url: {}", e))?;
+        for name in config.labels.keys() {
+            if RESERVED_LABELS.contains(&name.as_str()) {
+                return Err(format!("label {} is set on every alert", name));
+            }
+            let mut chars = name.chars();
+            let valid = chars
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                return Err(format!("invalid label name {:?}", name));
+            }
+        }
+        if config.timeout_minutes == 0 {
+            return Err("timeout_minutes must be positive".to_string());
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            alerts_url: format!("{}/api/v2/alerts", config.url.trim_end_matches('/')),
+            config,
+            open: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Fires (or refreshes) one alert per sensor and method.
+    pub async fn notify(&self, anomalies: &[StoredAnomaly]) {
+        let mut episodes: BTreeMap<(i64, &str), Vec<(&StoredAnomaly, Severity)>> = BTreeMap::new();
+        for anomaly in anomalies {
+            if anomaly.severity >= self.config.min_severity {
+                episodes
+                    .entry((anomaly.sensor_id, anomaly.method.as_str()))
+                    .or_default()
+                    .push((anomaly, anomaly.severity));
+            }
+        }
+        if episodes.is_empty() {
+            return;
+        }
+
+        let now = SystemTime::now();
+        let ends_at = timestamp(now + Duration::from_secs(self.config.timeout_minutes * 60));
+        let mut alerts = Vec::new();
+        let mut firing = Vec::new();
+        for ((sensor_id, method), anomalies) in episodes {
+            let (worst, severity) = anomalies
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    a.1.cmp(&b.1)
+                        .then(a.0.score.abs().total_cmp(&b.0.score.abs()))
+                })
+                .expect("episode has at least one anomaly");
+            let key = (sensor_id, method.to_string());
+            let previous = self.open.lock().unwrap().get(&key).cloned();
+            let starts_at = match &previous {
+                Some(previous) => previous["startsAt"].clone(),
+                None => Value::from(timestamp(now)),
+            };
+
+            let mut labels: BTreeMap<&str, String> = self
+                .config
+                .labels
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.clone()))
+                .collect();
+            labels.insert("alertname", ALERT_NAME.to_string());
+            labels.insert("sensor_id", sensor_id.to_string());
+            labels.insert("method", method.to_string());
+            labels.insert("severity", severity.label().into_owned());
+            let mut alert = json!({
+                "labels": labels,
+                "annotations": {
+                    "summary": format!(
+                        "{} anomaly on sensor {}: value {} ({} score {:.2})",
+                        severity.label(), sensor_id, worst.value, method, worst.score
+                    ),
+                    "value": worst.value.to_string(),
+                    "score": format!("{:.2}", worst.score),
+                    "timestamp": worst.timestamp,
+                    "anomalies": anomalies.len().to_string(),
+                },
+                "startsAt": starts_at,
+                "endsAt": ends_at,
+            });
+            if let Some(template) = &self.config.generator_url {
+                alert["generatorURL"] =
+                    render(template, &[("sensor_id", sensor_id.to_string())]).into();
+            }
+
+            if let Some(mut previous) = previous
+                && previous["labels"] != alert["labels"]
+            {
+                previous["endsAt"] = timestamp(now).into();
+                alerts.push(previous);
+            }
+            alerts.push(alert.clone());
+            firing.push((key, alert));
+        }
+
+        if self.send(&alerts).await {
+            self.open.lock().unwrap().extend(firing);
+        }
+    }
+
+    /// Resolves the sensor's alert if one is open.
+    pub async fn resolve(&self, sensor_id: i64, method: &str) {
+        let key = (sensor_id, method.to_string());
+        let Some(mut alert) = self.open.lock().unwrap().get(&key).cloned() else {
+            return;
+        };
+        alert["endsAt"] = timestamp(SystemTime::now()).into();
+        if self.send(&[alert]).await {
+            self.open.lock().unwrap().remove(&key);
+        }
+    }
+
+    async fn send(&self, alerts: &[Value]) -> bool {
+        let result = self
+            .client
+            .post(&self.alerts_url)
+            .json(alerts)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => {
+                println!("Notify: sent {} alerts to Alertmanager", alerts.len());
+                true
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error: Failed to send {} alerts to Alertmanager: {}",
+                    alerts.len(),
+                    e
+                );
+                false
+            }
+        }
+    }
+}
+
+/// `time` in RFC 3339, as Alertmanager expects.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    format!("{}Z", crate::time::format_timestamp(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::{anomaly, capture_server};
+
+    fn notifier(base_url: &str) -> AlertmanagerNotifier {
+        let config: AlertmanagerConfig = serde_json::from_value(json!({
+            "url": base_url,
+            "labels": { "team": "plant" },
+            "generator_url": "https://dashboard.example.com/sensors/{sensor_id}",
+        }))
+        .unwrap();
+        AlertmanagerNotifier::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_episode_fires_escalates_and_resolves() {
+        let (url, captured) = capture_server().await;
+        let notifier = notifier(&url);
+
+        notifier
+            .notify(&[anomaly(7, Severity::High), anomaly(8, Severity::Medium)])
+            .await;
+        notifier.notify(&[anomaly(7, Severity::Critical)]).await;
+        notifier.resolve(7, "zscore").await;
+        notifier.resolve(7, "zscore").await;
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 3);
+        assert!(captured.iter().all(|(path, _, _)| path == "/api/v2/alerts"));
+
+        let fired = captured[0].2.as_array().unwrap();
+        assert_eq!(fired.len(), 2);
+        assert_eq!(
+            fired[0]["labels"],
+            json!({
+                "alertname": "SensorAnomaly",
+                "method": "zscore",
+                "sensor_id": "7",
+                "severity": "high",
+                "team": "plant",
+            })
+        );
+        assert_eq!(fired[0]["annotations"]["score"], "3.26");
+        assert_eq!(
+            fired[0]["generatorURL"],
+            "https://dashboard.example.com/sensors/7"
+        );
+        let starts_at = fired[0]["startsAt"].as_str().unwrap();
+        assert!(starts_at.ends_with('Z'));
+        assert!(fired[0]["endsAt"].as_str().unwrap() > starts_at);
+
+        // The high alert is resolved as the critical one fires.
+        let escalated = captured[1].2.as_array().unwrap();
+        assert_eq!(escalated.len(), 2);
+        assert_eq!(escalated[0]["labels"]["severity"], "high");
+        assert!(escalated[0]["endsAt"].as_str().unwrap() < fired[0]["endsAt"].as_str().unwrap());
+        assert_eq!(escalated[1]["labels"]["severity"], "critical");
+        assert_eq!(escalated[1]["startsAt"], starts_at);
+
+        let resolved = captured[2].2.as_array().unwrap();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved[0]["labels"]["severity"], "critical");
+        assert!(resolved[0]["endsAt"].as_str().unwrap() < escalated[1]["endsAt"].as_str().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_failed_send_leaves_episode_closed() {
+        let notifier = notifier("http://127.0.0.1:9");
+
+        notifier.notify(&[anomaly(7, Severity::Critical)]).await;
+
+        assert!(notifier.open.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rejects_reserved_and_invalid_labels() {
+        for labels in [json!({ "severity": "page" }), json!({ "line-a": "1" })] {
+            let config: AlertmanagerConfig = serde_json::from_value(
+                json!({ "url": "http://alertmanager:9093", "labels": labels }),
+            )
+            .unwrap();
+            assert!(AlertmanagerNotifier::new(config).is_err());
+        }
+    }
+}
@@ -0,0 +1,197 @@
+//! Circuit breakers in front of each webhook, Slack channel and the
+//! PagerDuty Events API.
+//!
+//! After `failures` consecutive failed requests a target's circuit opens and
+//! its requests are skipped for `open_seconds`, so a dead endpoint neither
+//! holds up deliveries to the others with timeouts nor uses up the attempts
+//! of its queued webhook deliveries, which wait instead. Then one request
+//! goes through as a probe: success closes the circuit, failure opens it
+//! again. Configured for every target by the `circuit_breaker` key.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BreakerConfig {
+    /// Consecutive failures that open a circuit.
+    pub failures: u32,
+    /// How long an open circuit skips requests before letting a probe
+    /// through.
+    pub open_seconds: u64,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        Self {
+            failures: 5,
+            open_seconds: 60,
+        }
+    }
+}
+
+impl BreakerConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.failures == 0 || self.open_seconds == 0 {
+            return Err("failures and open_seconds must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    fn open_for(&self) -> Duration {
+        Duration::from_secs(self.open_seconds)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe went out at `since`; another may once `open_seconds` passed
+    /// without it reporting back.
+    HalfOpen {
+        since: Instant,
+    },
+}
+
+pub struct Breaker {
+    /// The target, as logged.
+    name: String,
+    config: BreakerConfig,
+    state: Mutex<State>,
+}
+
+impl Breaker {
+    pub fn new(name: impl Into<String>, config: BreakerConfig) -> Self {
+        Self {
+            name: name.into(),
+            config,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Whether a request may go out now. An open circuit whose time is up
+    /// lets one through as a probe.
+    pub fn allow(&self) -> bool {
+        self.allow_at(Instant::now())
+    }
+
+    fn allow_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            State::Closed { .. } => true,
+            State::Open { until } if now < until => false,
+            State::HalfOpen { since } if now < since + self.config.open_for() => false,
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { since: now };
+                true
+            }
+        }
+    }
+
+    /// How long until the circuit lets a request through; zero unless open.
+    pub fn retry_in(&self) -> Duration {
+        match *self.state.lock().unwrap() {
+            State::Open { until } => until.saturating_duration_since(Instant::now()),
+            State::HalfOpen { since } => {
+                (since + self.config.open_for()).saturating_duration_since(Instant::now())
+            }
+            State::Closed { .. } => Duration::ZERO,
+        }
+    }
+
+    pub fn succeeded(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Closed { .. }) {
+            println!("Notify: circuit of {} closed", self.name);
+        }
+        *state = State::Closed { failures: 0 };
+    }
+
+    pub fn failed(&self) {
+        self.failed_at(Instant::now());
+    }
+
+    fn failed_at(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let until = now + self.config.open_for();
+        match *state {
+            State::Closed { failures } if failures + 1 < self.config.failures => {
+                *state = State::Closed {
+                    failures: failures + 1,
+                };
+            }
+            State::Closed { failures } => {
+                eprintln!(
+                    "Warning: circuit of {} opened after {} consecutive failures, skipping it for {}s",
+                    self.name,
+                    failures + 1,
+                    self.config.open_seconds
+                );
+                *state = State::Open { until };
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                eprintln!(
+                    "Warning: probe of {} failed, circuit stays open for {}s",
+                    self.name, self.config.open_seconds
+                );
+                *state = State::Open { until };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures_and_probes() {
+        let breaker = Breaker::new(
+            "webhook ops",
+            BreakerConfig {
+                failures: 3,
+                open_seconds: 60,
+            },
+        );
+        let start = Instant::now();
+        let later = |secs| start + Duration::from_secs(secs);
+
+        breaker.failed_at(start);
+        breaker.failed_at(start);
+        // A success in between starts the count over.
+        breaker.succeeded();
+        breaker.failed_at(start);
+        breaker.failed_at(start);
+        assert!(breaker.allow_at(start));
+        breaker.failed_at(start);
+        assert!(!breaker.allow_at(later(59)));
+
+        // One probe once the time is up, and no other while it is out.
+        assert!(breaker.allow_at(later(60)));
+        assert!(!breaker.allow_at(later(61)));
+        breaker.failed_at(later(61));
+        assert!(!breaker.allow_at(later(120)));
+        assert!(breaker.allow_at(later(121)));
+        // A probe that never reports back is replaced.
+        assert!(breaker.allow_at(later(181)));
+        breaker.succeeded();
+        assert!(breaker.allow_at(later(182)));
+        assert_eq!(breaker.retry_in(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_config_must_be_positive() {
+        assert!(BreakerConfig::default().validate().is_ok());
+        let config: BreakerConfig = serde_json::from_str(r#"{"failures": 0}"#).unwrap();
+        assert_eq!(config.open_seconds, 60);
+        assert!(config.validate().is_err());
+        assert!(serde_json::from_str::<BreakerConfig>(r#"{"open": 1}"#).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Correlation of anomalies across related sensors into incidents.
+//!
+//! With a `correlation` key, anomalies of related sensors whose readings are
+//! at most `window_minutes` apart form one incident, so a failing pump
+//! notifies once rather than once per sensor around it. Sensors are related
+//! when they share a tag, or only a tag starting with one of `tag_prefixes`
+//! when set, or the same reference sensor (see `reference` in the registry),
+//! which is related to them too. An anomaly related to two incidents joins
+//! them into one.
+//!
+//! An incident is notified as its most severe anomaly, carrying the
+//! incident's combined severity: the highest of its anomalies, raised one
+//! step once `raise_at_sensors` sensors or more take part. Later anomalies of
+//! the incident are notified only when they raise the combined severity.
+//!
+//! ```json
+//! "correlation": { "window_minutes": 5, "tag_prefixes": ["pump:", "line:"], "raise_at_sensors": 3 }
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use serde::Deserialize;
+
+use super::Severity;
+use crate::storage::{Storage, StoredAnomaly};
+use crate::time::parse_timestamp;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorrelationConfig {
+    window_minutes: u32,
+    #[serde(default)]
+    tag_prefixes: Option<Vec<String>>,
+    #[serde(default)]
+    raise_at_sensors: Option<usize>,
+}
+
+impl CorrelationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.window_minutes == 0 {
+            return Err("window_minutes must be positive".to_string());
+        }
+        if self
+            .tag_prefixes
+            .as_ref()
+            .is_some_and(|prefixes| prefixes.iter().any(String::is_empty))
+        {
+            return Err("tag_prefixes cannot be empty strings".to_string());
+        }
+        if self.raise_at_sensors.is_some_and(|sensors| sensors < 2) {
+            return Err("raise_at_sensors must be at least 2".to_string());
+        }
+        Ok(())
+    }
+
+    fn correlates(&self, tag: &str) -> bool {
+        self.tag_prefixes
+            .as_ref()
+            .is_none_or(|prefixes| prefixes.iter().any(|prefix| tag.starts_with(prefix)))
+    }
+}
+
+struct Incident {
+    /// Tags and cohorts of its sensors, which relate other sensors to it.
+    keys: BTreeSet<String>,
+    sensors: BTreeSet<i64>,
+    /// Seconds since the epoch of its latest reading.
+    last: i64,
+    peak: Severity,
+    /// The combined severity last notified.
+    notified: Option<Severity>,
+    /// Its most severe anomaly of the delivery being correlated.
+    lead: Option<StoredAnomaly>,
+}
+
+impl Incident {
+    fn add(&mut self, time: i64, anomaly: &StoredAnomaly, keys: &BTreeSet<String>) {
+        self.keys.extend(keys.iter().cloned());
+        self.sensors.insert(anomaly.sensor_id);
+        self.last = self.last.max(time);
+        self.peak = self.peak.max(anomaly.severity);
+        self.lead = more_severe(self.lead.take(), Some(anomaly.clone()));
+    }
+
+    fn absorb(&mut self, other: Incident) {
+        self.keys.extend(other.keys);
+        self.sensors.extend(other.sensors);
+        self.last = self.last.max(other.last);
+        self.peak = self.peak.max(other.peak);
+        self.notified = self.notified.max(other.notified);
+        self.lead = more_severe(self.lead.take(), other.lead);
+    }
+
+    fn severity(&self, raise_at_sensors: Option<usize>) -> Severity {
+        match raise_at_sensors {
+            Some(sensors) if self.sensors.len() >= sensors => self.peak.raised(),
+            _ => self.peak,
+        }
+    }
+}
+
+fn more_severe(a: Option<StoredAnomaly>, b: Option<StoredAnomaly>) -> Option<StoredAnomaly> {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            let order = a
+                .severity
+                .cmp(&b.severity)
+                .then(a.score.abs().total_cmp(&b.score.abs()));
+            Some(if order == Ordering::Less { b } else { a })
+        }
+        (a, b) => a.or(b),
+    }
+}
+
+/// Incidents still open to related anomalies.
+pub struct Correlator {
+    config: CorrelationConfig,
+    incidents: Mutex<Vec<Incident>>,
+}
+
+impl Correlator {
+    pub fn new(config: CorrelationConfig) -> Self {
+        Self {
+            config,
+            incidents: Mutex::default(),
+        }
+    }
+
+    /// The anomalies to notify of `anomalies`, at most one per incident.
+    pub async fn apply(
+        &self,
+        storage: &Storage,
+        anomalies: &[StoredAnomaly],
+    ) -> Vec<StoredAnomaly> {
+        let mut sensor_ids: Vec<i64> = anomalies.iter().map(|a| a.sensor_id).collect();
+        sensor_ids.sort_unstable();
+        sensor_ids.dedup();
+        let related = match storage.sensor_tags(&sensor_ids).await {
+            Ok(tags) => storage
+                .sensor_configs(Some(&sensor_ids))
+                .await
+                .map(|configs| (tags, configs)),
+            Err(e) => Err(e),
+        };
+        let (tags, configs) = match related {
+            Ok(related) => related,
+            Err(e) => {
+                eprintln!("Error: Failed to load sensors for correlation: {}", e);
+                return anomalies.to_vec();
+            }
+        };
+
+        let mut keys: HashMap<i64, BTreeSet<String>> = HashMap::new();
+        for (sensor_id, tags) in tags {
+            keys.entry(sensor_id).or_default().extend(
+                tags.into_iter()
+                    .filter(|tag| self.config.correlates(tag))
+                    .map(|tag| format!("tag:{}", tag)),
+            );
+        }
+        for config in configs {
+            if let Some(reference) = config.reference_id {
+                keys.entry(config.sensor_id)
+                    .or_default()
+                    .insert(format!("cohort:{}", reference));
+            }
+        }
+        self.correlate(anomalies, &keys)
+    }
+
+    /// Correlates `anomalies` given the tags and cohorts of their sensors;
+    /// each sensor is always in the cohort of its own id.
+    fn correlate(
+        &self,
+        anomalies: &[StoredAnomaly],
+        keys: &HashMap<i64, BTreeSet<String>>,
+    ) -> Vec<StoredAnomaly> {
+        let window = i64::from(self.config.window_minutes) * 60;
+        let mut notified = Vec::new();
+        let mut timed = Vec::with_capacity(anomalies.len());
+        for anomaly in anomalies {
+            match anomaly.timestamp.get(..19).and_then(parse_timestamp) {
+                Some(time) => timed.push((time, anomaly)),
+                None => notified.push(anomaly.clone()),
+            }
+        }
+        timed.sort_by_key(|(time, _)| *time);
+
+        let mut incidents = self.incidents.lock().unwrap();
+        for &(time, anomaly) in &timed {
+            let mut related = keys.get(&anomaly.sensor_id).cloned().unwrap_or_default();
+            related.insert(format!("cohort:{}", anomaly.sensor_id));
+            let matching: Vec<usize> = incidents
+                .iter()
+                .enumerate()
+                .filter(|(_, incident)| {
+                    (time - incident.last).abs() <= window && !incident.keys.is_disjoint(&related)
+                })
+                .map(|(index, _)| index)
+                .collect();
+            let index = match matching.split_first() {
+                None => {
+                    incidents.push(Incident {
+                        keys: BTreeSet::new(),
+                        sensors: BTreeSet::new(),
+                        last: time,
+                        peak: anomaly.severity,
+                        notified: None,
+                        lead: None,
+                    });
+                    incidents.len() - 1
+                }
+                Some((&first, rest)) => {
+                    for &other in rest.iter().rev() {
+                        let other = incidents.remove(other);
+                        incidents[first].absorb(other);
+                    }
+                    first
+                }
+            };
+            incidents[index].add(time, anomaly, &related);
+        }
+
+        for incident in incidents.iter_mut() {
+            let Some(mut lead) = incident.lead.take() else {
+                continue;
+            };
+            let severity = incident.severity(self.config.raise_at_sensors);
+            if Some(severity) > incident.notified {
+                incident.notified = Some(severity);
+                lead.severity = severity;
+                notified.push(lead);
+            }
+        }
+        if let Some(&(newest, _)) = timed.last() {
+            incidents.retain(|incident| incident.last + window >= newest);
+        }
+
+        if notified.len() < anomalies.len() {
+            println!(
+                "Notify: {} anomalies held back as part of correlated incidents",
+                anomalies.len() - notified.len()
+            );
+        }
+        notified
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::anomaly;
+
+    fn at(sensor_id: i64, severity: Severity, time: &str) -> StoredAnomaly {
+        StoredAnomaly {
+            timestamp: format!("2026-01-19 {}", time),
+            ..anomaly(sensor_id, severity)
+        }
+    }
+
+    #[test]
+    fn test_related_anomalies_notify_once_per_incident() {
+        let correlator = Correlator::new(
+            serde_json::from_value(serde_json::json!({
+                "window_minutes": 5, "tag_prefixes": ["pump:"], "raise_at_sensors": 3
+            }))
+            .unwrap(),
+        );
+        let keys: HashMap<i64, BTreeSet<String>> = [
+            (1, ["tag:pump:p1"]),
+            (2, ["tag:pump:p1"]),
+            (3, ["cohort:2"]),
+            (4, ["tag:pump:p2"]),
+        ]
+        .into_iter()
+        .map(|(sensor_id, keys)| (sensor_id, keys.map(String::from).into()))
+        .collect();
+
+        // Sensor 3 shares no tag but has sensor 2 as its reference.
+        let notified = correlator.correlate(
+            &[
+                at(1, Severity::Medium, "10:00:00"),
+                at(2, Severity::High, "10:02:00"),
+                at(3, Severity::Medium, "10:06:00"),
+                at(4, Severity::Medium, "10:01:00"),
+            ],
+            &keys,
+        );
+        let notified: Vec<(i64, Severity)> =
+            notified.iter().map(|a| (a.sensor_id, a.severity)).collect();
+        assert_eq!(
+            notified,
+            [(2, Severity::Critical), (4, Severity::Medium)],
+            "three sensors raise the incident's severity"
+        );
+
+        // More of the same incident is held back, unless it raises the
+        // severity; the unrelated sensor's incident is still open.
+        let later = correlator.correlate(
+            &[
+                at(1, Severity::High, "10:08:00"),
+                at(4, Severity::High, "10:04:00"),
+            ],
+            &keys,
+        );
+        let later: Vec<(i64, Severity)> = later.iter().map(|a| (a.sensor_id, a.severity)).collect();
+        assert_eq!(later, [(4, Severity::High)]);
+
+        // Past the window a new incident opens.
+        let next = correlator.correlate(&[at(1, Severity::Medium, "10:30:00")], &keys);
+        assert_eq!(next.len(), 1);
+        assert_eq!(correlator.incidents.lock().unwrap().len(), 1);
+
+        let invalid: CorrelationConfig =
+            serde_json::from_value(serde_json::json!({ "window_minutes": 0 })).unwrap();
+        assert!(invalid.validate().is_err());
+    }
+}
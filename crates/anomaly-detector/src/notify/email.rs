@@ -0,0 +1,477 @@
+//! SMTP channel: one message per recipient group, sent immediately or
+//! collected into a digest.
+//!
+//! With storage configured, digests are collected in `email_digest_items`
+//! and only removed once mailed, so a restart or an SMTP outage does not
+//! lose them.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+
+use super::{Severity, render};
+use crate::leader::Lease;
+use crate::storage::{Storage, StoredAnomaly};
+
+/// Anomalies listed in one message; the rest are summarized as a count.
+const MAX_ITEMS_PER_MESSAGE: usize = 500;
+
+#[derive(Deserialize)]
+pub struct EmailConfig {
+    smtp_url: String,
+    from: String,
+    groups: Vec<GroupConfig>,
+}
+
+/// One set of recipients and how they want to be notified.
+///
+/// `subject` and `body` may use `{group}`, `{count}` and `{severity}` (the
+/// highest severity in the message); `body` also takes `{anomalies}`, the
+/// rendered `item` lines. `item` may use `{sensor_id}`, `{value}`,
+/// `{timestamp}`, `{method}`, `{score}` and `{severity}`.
+#[derive(Deserialize)]
+pub struct GroupConfig {
+    name: String,
+    recipients: Vec<String>,
+    #[serde(default)]
+    min_severity: Severity,
+    #[serde(default)]
+    digest_minutes: Option<u64>,
+    #[serde(default = "default_subject")]
+    subject: String,
+    #[serde(default = "default_body")]
+    body: String,
+    #[serde(default = "default_item")]
+    item: String,
+}
+
+fn default_subject() -> String {
+    "[{severity}] {count} anomalies detected".to_string()
+}
+
+fn default_body() -> String {
+    "{count} anomalies detected for {group}:\n\n{anomalies}\n".to_string()
+}
+
+fn default_item() -> String {
+    "sensor {sensor_id}: {value} at {timestamp} ({method} score {score}, {severity})".to_string()
+}
+
+enum Mailer {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    #[cfg(test)]
+    Stub(lettre::transport::stub::AsyncStubTransport),
+}
+
+impl Mailer {
+    async fn send(&self, message: Message) -> Result<(), String> {
+        match self {
+            Mailer::Smtp(transport) => transport
+                .send(message)
+                .await
+                .map(drop)
+                .map_err(|e| e.to_string()),
+            #[cfg(test)]
+            Mailer::Stub(transport) => transport.send(message).await.map_err(|e| e.to_string()),
+        }
+    }
+}
+
+struct Group {
+    config: GroupConfig,
+    recipients: Vec<Mailbox>,
+    pending: Mutex<Vec<StoredAnomaly>>,
+}
+
+pub struct EmailNotifier {
+    from: Mailbox,
+    mailer: Mailer,
+    groups: Vec<Group>,
+    storage: Option<Storage>,
+}
+
+impl EmailNotifier {
+    pub fn new(config: EmailConfig) -> Result<Self, String> {
+        let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(&config.smtp_url)
+            .map_err(|e| format!("invalid smtp_url: {}", e))?
+            .build();
+        Self::with_mailer(config, Mailer::Smtp(transport))
+    }
+
+    fn with_mailer(config: EmailConfig, mailer: Mailer) -> Result<Self, String> {
+        let from = config
+            .from
+            .parse()
+            .map_err(|e| format!("invalid from address {:?}: {}", config.from, e))?;
+
+        let mut groups = Vec::with_capacity(config.groups.len());
+        for group in config.groups {
+            if group.recipients.is_empty() {
+                return Err(format!("group {:?} has no recipients", group.name));
+            }
+            if group.digest_minutes == Some(0) {
+                return Err(format!(
+                    "group {:?}: digest_minutes must be positive",
+                    group.name
+                ));
+            }
+            let recipients = group
+                .recipients
+                .iter()
+                .map(|r| {
+                    r.parse().map_err(|e| {
+                        format!("group {:?}: invalid recipient {:?}: {}", group.name, r, e)
+                    })
+                })
+                .collect::<Result<_, String>>()?;
+            groups.push(Group {
+                config: group,
+                recipients,
+                pending: Mutex::new(Vec::new()),
+            });
+        }
+
+        Ok(Self {
+            from,
+            mailer,
+            groups,
+            storage: None,
+        })
+    }
+
+    /// Keeps digests in `storage` until they are mailed.
+    pub fn with_storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn has_group(&self, name: &str) -> bool {
+        self.groups.iter().any(|g| g.config.name == name)
+    }
+
+    /// Mails anomalies to every group (or only the named one) whose severity
+    /// filter they pass: immediately for groups without a digest, otherwise
+    /// at the next flush.
+    pub async fn notify(&self, anomalies: &[StoredAnomaly], only: Option<&str>) {
+        for group in &self.groups {
+            if only.is_some_and(|name| name != group.config.name) {
+                continue;
+            }
+            let matching: Vec<&StoredAnomaly> = anomalies
+                .iter()
+                .filter(|a| a.severity >= group.config.min_severity)
+                .collect();
+            if matching.is_empty() {
+                continue;
+            }
+
+            if group.config.digest_minutes.is_some() {
+                self.collect(group, matching).await;
+            } else {
+                self.send(group, &matching).await;
+            }
+        }
+    }
+
+    /// Adds anomalies to the group's digest, in storage when there is one.
+    async fn collect(&self, group: &Group, anomalies: Vec<&StoredAnomaly>) {
+        if let Some(storage) = &self.storage {
+            let items: Vec<String> = anomalies
+                .iter()
+                .filter_map(|a| serde_json::to_string(a).ok())
+                .collect();
+            match storage.queue_digest_items(&group.config.name, &items).await {
+                Ok(()) => return,
+                Err(e) => eprintln!(
+                    "Error: Failed to store the digest of group {}, keeping it in memory: {}",
+                    group.config.name, e
+                ),
+            }
+        }
+        let mut pending = group.pending.lock().unwrap();
+        pending.extend(anomalies.into_iter().cloned());
+    }
+
+    /// Sends the pending digest for the group at `index`, if any. Stored
+    /// items are removed and in-memory ones dropped only once mailed.
+    async fn flush(&self, index: usize) {
+        let group = &self.groups[index];
+        let mut stored = Vec::new();
+        if let Some(storage) = &self.storage {
+            match storage.digest_items(&group.config.name).await {
+                Ok(items) => stored = items,
+                Err(e) => eprintln!(
+                    "Error: Failed to load the digest of group {}: {}",
+                    group.config.name, e
+                ),
+            }
+        }
+        let pending = std::mem::take(&mut *group.pending.lock().unwrap());
+
+        let mut anomalies: Vec<StoredAnomaly> = stored
+            .iter()
+            .filter_map(|(id, item)| match serde_json::from_str(item) {
+                Ok(anomaly) => Some(anomaly),
+                Err(e) => {
+                    eprintln!("Warning: Dropping unreadable digest item {}: {}", id, e);
+                    None
+                }
+            })
+            .collect();
+        anomalies.extend(pending.iter().cloned());
+        let sent = anomalies.is_empty()
+            || self
+                .send(group, &anomalies.iter().collect::<Vec<_>>())
+                .await;
+        if !sent {
+            group.pending.lock().unwrap().splice(0..0, pending);
+            return;
+        }
+        if let Some(storage) = &self.storage
+            && !stored.is_empty()
+        {
+            let ids: Vec<i64> = stored.iter().map(|(id, _)| *id).collect();
+            if let Err(e) = storage.delete_digest_items(&ids).await {
+                eprintln!(
+                    "Error: Failed to clear the mailed digest of group {}: {}",
+                    group.config.name, e
+                );
+            }
+        }
+    }
+
+    /// Mails one message to the group and reports whether it was accepted.
+    async fn send(&self, group: &Group, anomalies: &[&StoredAnomaly]) -> bool {
+        let (subject, body) = compose(&group.config, anomalies);
+        let mut builder = Message::builder()
+            .from(self.from.clone())
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for recipient in &group.recipients {
+            builder = builder.to(recipient.clone());
+        }
+
+        let result = match builder.body(body) {
+            Ok(message) => self.mailer.send(message).await,
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(()) => {
+                println!(
+                    "Notify: mailed {} anomalies to group {}",
+                    anomalies.len(),
+                    group.config.name
+                );
+                true
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to mail group {}: {}", group.config.name, e);
+                false
+            }
+        }
+    }
+}
+
+/// Renders the subject and body of one message for `anomalies`.
+fn compose(group: &GroupConfig, anomalies: &[&StoredAnomaly]) -> (String, String) {
+    let severity = anomalies
+        .iter()
+        .map(|a| a.severity)
+        .max()
+        .unwrap_or_default();
+    let count = anomalies.len().to_string();
+
+    let mut items: Vec<String> = anomalies
+        .iter()
+        .take(MAX_ITEMS_PER_MESSAGE)
+        .map(|a| {
+            render(
+                &group.item,
+                &[
+                    ("sensor_id", a.sensor_id.to_string()),
+                    ("value", a.value.to_string()),
+                    ("timestamp", a.timestamp.clone()),
+                    ("method", a.method.clone()),
+                    ("score", format!("{:.2}", a.score)),
+                    ("severity", a.severity.label().into_owned()),
+                ],
+            )
+        })
+        .collect();
+    if anomalies.len() > MAX_ITEMS_PER_MESSAGE {
+        items.push(format!(
+            "... and {} more",
+            anomalies.len() - MAX_ITEMS_PER_MESSAGE
+        ));
+    }
+
+    let vars = [
+        ("group", group.name.clone()),
+        ("count", count),
+        ("severity", severity.label().into_owned()),
+        ("anomalies", items.join("\n")),
+    ];
+    (
+        render(&group.subject, &vars[..3]),
+        render(&group.body, &vars),
+    )
+}
+
+/// Starts one flush loop per digest group.
+pub fn spawn(notifier: Arc<EmailNotifier>) {
+    for (index, group) in notifier.groups.iter().enumerate() {
+        let Some(minutes) = group.config.digest_minutes else {
+            continue;
+        };
+        let interval = Duration::from_secs(minutes * 60);
+        // Stored digests are shared by all replicas and mailed by one.
+        let lease = notifier.storage.clone().map(|storage| {
+            Lease::new(
+                storage,
+                format!("email-digest:{}", group.config.name),
+                interval,
+            )
+        });
+        let notifier = notifier.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                if let Some(lease) = &lease
+                    && !lease.acquire().await
+                {
+                    continue;
+                }
+                notifier.flush(index).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::anomaly;
+    use lettre::transport::stub::AsyncStubTransport;
+
+    fn config(groups: &str) -> EmailConfig {
+        serde_json::from_str(&format!(
+            r#"{{"smtp_url": "smtp://localhost", "from": "alerts@example.com", "groups": {}}}"#,
+            groups
+        ))
+        .unwrap()
+    }
+
+    fn notifier(groups: &str) -> (EmailNotifier, AsyncStubTransport) {
+        let stub = AsyncStubTransport::new_ok();
+        let notifier =
+            EmailNotifier::with_mailer(config(groups), Mailer::Stub(stub.clone())).unwrap();
+        (notifier, stub)
+    }
+
+    #[tokio::test]
+    async fn test_immediate_group_filters_by_severity() {
+        let (notifier, stub) = notifier(
+            r#"[{"name": "oncall", "recipients": ["oncall@example.com"], "min_severity": "critical",
+                 "subject": "{count} {severity} for {group}", "item": "sensor {sensor_id} score {score}"}]"#,
+        );
+
+        notifier
+            .notify(
+                &[anomaly(1, Severity::Medium), anomaly(2, Severity::Critical)],
+                None,
+            )
+            .await;
+        notifier.notify(&[anomaly(3, Severity::High)], None).await;
+
+        let messages = stub.messages().await;
+        assert_eq!(messages.len(), 1);
+        let (envelope, raw) = &messages[0];
+        assert_eq!(envelope.to()[0].to_string(), "oncall@example.com");
+        assert!(raw.contains("Subject: 1 critical for oncall"));
+        assert!(raw.contains("sensor 2 score 3.26"));
+        assert!(!raw.contains("sensor 1 "));
+    }
+
+    #[tokio::test]
+    async fn test_digest_group_batches_until_flush() {
+        let (notifier, stub) = notifier(
+            r#"[{"name": "eng", "recipients": ["eng@example.com"], "digest_minutes": 15}]"#,
+        );
+
+        notifier.notify(&[anomaly(1, Severity::Medium)], None).await;
+        notifier.notify(&[anomaly(2, Severity::High)], None).await;
+        assert!(stub.messages().await.is_empty());
+
+        notifier.flush(0).await;
+        notifier.flush(0).await;
+
+        let messages = stub.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert!(
+            messages[0]
+                .1
+                .contains("Subject: [high] 2 anomalies detected")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stored_digest_is_kept_until_mailed() {
+        let storage = crate::storage::testing::in_memory().await;
+        let group = r#"[{"name": "eng", "recipients": ["eng@example.com"], "digest_minutes": 15}]"#;
+        let failing = EmailNotifier::with_mailer(
+            config(group),
+            Mailer::Stub(AsyncStubTransport::new_error()),
+        )
+        .unwrap()
+        .with_storage(storage.clone());
+        failing.notify(&[anomaly(1, Severity::Medium)], None).await;
+        failing.flush(0).await;
+        assert_eq!(storage.digest_items("eng").await.unwrap().len(), 1);
+
+        // A restarted process picks the digest up from storage.
+        let (notifier, stub) = notifier(group);
+        let notifier = notifier.with_storage(storage.clone());
+        notifier
+            .notify(&[anomaly(2, Severity::Critical)], None)
+            .await;
+        notifier.flush(0).await;
+
+        let messages = stub.messages().await;
+        assert_eq!(messages.len(), 1);
+        assert!(
+            messages[0]
+                .1
+                .contains("Subject: [critical] 2 anomalies detected")
+        );
+        assert!(storage.digest_items("eng").await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_groups_are_rejected() {
+        let stub = || Mailer::Stub(AsyncStubTransport::new_ok());
+
+        assert!(
+            EmailNotifier::with_mailer(config(r#"[{"name": "a", "recipients": []}]"#), stub())
+                .is_err()
+        );
+        assert!(
+            EmailNotifier::with_mailer(
+                config(r#"[{"name": "a", "recipients": ["not an address"]}]"#),
+                stub()
+            )
+            .is_err()
+        );
+        assert!(
+            EmailNotifier::with_mailer(
+                config(r#"[{"name": "a", "recipients": ["a@example.com"], "digest_minutes": 0}]"#),
+                stub()
+            )
+            .is_err()
+        );
+    }
+}
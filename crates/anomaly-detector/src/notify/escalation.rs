@@ -0,0 +1,231 @@
+//! Escalation of incidents nobody acknowledges.
+//!
+//! With escalation configured, each sensor and method with notified
+//! anomalies has an incident in storage until a backfill resolves it. When
+//! an incident is still unacknowledged (see `POST /incidents/acknowledge`)
+//! `after_minutes` after it opened, its latest anomaly is notified again to
+//! the level's `channels`, raising its severity one step per level with
+//! `bump_severity`. Levels without `channels` go through the routing rules
+//! again, so a raised severity reaches the channels that route it.
+//!
+//! ```json
+//! "escalation": {
+//!   "levels": [
+//!     { "after_minutes": 15, "channels": ["slack:#supervisors"] },
+//!     { "after_minutes": 60, "bump_severity": true }
+//!   ]
+//! }
+//! ```
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use super::Notifier;
+use super::routing::Target;
+use crate::leader::Lease;
+use crate::storage::Storage;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationConfig {
+    levels: Vec<Level>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Level {
+    /// Minutes after the incident opened.
+    after_minutes: u32,
+    #[serde(default)]
+    channels: Option<Vec<Target>>,
+    #[serde(default)]
+    bump_severity: bool,
+}
+
+impl EscalationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.levels.is_empty() {
+            return Err("at least one level is required".to_string());
+        }
+        let mut previous = 0;
+        for (index, level) in self.levels.iter().enumerate() {
+            if level.after_minutes <= previous {
+                return Err(format!(
+                    "level {}: after_minutes must be positive and increase with each level",
+                    index + 1
+                ));
+            }
+            if level.channels.is_none() && !level.bump_severity {
+                return Err(format!(
+                    "level {}: set channels, bump_severity or both",
+                    index + 1
+                ));
+            }
+            previous = level.after_minutes;
+        }
+        Ok(())
+    }
+
+    pub fn targets(&self) -> impl Iterator<Item = &Target> {
+        self.levels
+            .iter()
+            .flat_map(|level| level.channels.iter().flatten())
+    }
+}
+
+/// Escalates every incident that is due, one level at a time.
+pub async fn escalate_due(
+    notifier: &Notifier,
+    config: &EscalationConfig,
+    storage: &Storage,
+) -> Result<(), sqlx::Error> {
+    for (index, level) in config.levels.iter().enumerate() {
+        let cutoff = storage
+            .cutoff(Duration::from_secs(u64::from(level.after_minutes) * 60))
+            .await?;
+        let incidents = storage.pending_incidents(index as i64, &cutoff).await?;
+        if incidents.is_empty() {
+            continue;
+        }
+
+        let ids: Vec<i64> = incidents.iter().map(|i| i.last_anomaly_id).collect();
+        let mut anomalies = storage.anomalies_by_id(&ids).await?;
+        let bumps = config.levels[..=index]
+            .iter()
+            .filter(|level| level.bump_severity)
+            .count();
+        for anomaly in &mut anomalies {
+            for _ in 0..bumps {
+                anomaly.severity = anomaly.severity.raised();
+            }
+        }
+
+        notifier
+            .escalate(level.channels.as_deref(), &anomalies)
+            .await;
+        for incident in &incidents {
+            storage.escalate_incident(incident.id).await?;
+        }
+        println!(
+            "Notify: escalated {} incidents to level {}",
+            incidents.len(),
+            index + 1
+        );
+    }
+    Ok(())
+}
+
+/// Checks for due escalations every minute, on the replica holding the
+/// lease.
+pub fn spawn(notifier: Arc<Notifier>) {
+    tokio::spawn(async move {
+        let (Some(config), Some(storage)) = (&notifier.escalation, &notifier.storage) else {
+            return;
+        };
+        let lease = Lease::new(storage.clone(), "escalation", CHECK_INTERVAL);
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if !lease.acquire().await {
+                continue;
+            }
+            if let Err(e) = escalate_due(&notifier, config, storage).await {
+                eprintln!("Error: Escalation failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::capture_server;
+    use crate::notify::webhook::WebhookNotifier;
+    use crate::storage::testing::{backdate_incidents, in_memory, insert_anomaly};
+    use crate::storage::{AnomalyFilter, StoredAnomaly};
+    use serde_json::json;
+
+    fn config(value: serde_json::Value) -> EscalationConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unacknowledged_incident_escalates_each_level_once() {
+        let (url, captured) = capture_server().await;
+        let storage = in_memory().await;
+        insert_anomaly(&storage, 1, "2026-01-19 10:00:00").await;
+        insert_anomaly(&storage, 2, "2026-01-19 10:00:00").await;
+        let anomalies: Vec<StoredAnomaly> = storage
+            .list_anomalies(&AnomalyFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        let escalation = config(json!({
+            "levels": [
+                { "after_minutes": 10, "channels": ["webhook:supervisors"] },
+                { "after_minutes": 30, "channels": ["webhook:managers"], "bump_severity": true },
+            ],
+        }));
+        let notifier = Notifier {
+            webhooks: Some(
+                WebhookNotifier::new(
+                    serde_json::from_value(json!([
+                        { "name": "oncall", "url": format!("{}/oncall", url) },
+                        { "name": "supervisors", "url": format!("{}/supervisors", url) },
+                        { "name": "managers", "url": format!("{}/managers", url) },
+                    ]))
+                    .unwrap(),
+                )
+                .unwrap(),
+            ),
+            escalation: Some(escalation),
+            storage: Some(storage.clone()),
+            ..Notifier::default()
+        };
+        notifier
+            .set_routing(
+                serde_json::from_value(json!({
+                    "rules": [],
+                    "default": ["webhook:oncall"],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let config = notifier.escalation.as_ref().unwrap();
+
+        notifier.notify(&anomalies).await;
+        storage.acknowledge_incident(2, "zscore").await.unwrap();
+        escalate_due(&notifier, config, &storage).await.unwrap();
+        backdate_incidents(&storage, 15).await;
+        escalate_due(&notifier, config, &storage).await.unwrap();
+        escalate_due(&notifier, config, &storage).await.unwrap();
+        backdate_incidents(&storage, 20).await;
+        escalate_due(&notifier, config, &storage).await.unwrap();
+
+        let captured = captured.lock().unwrap();
+        let paths: Vec<&str> = captured.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/oncall", "/supervisors", "/managers"]);
+        assert_eq!(captured[1].2["anomalies"][0]["sensor_id"], 1);
+        assert_eq!(captured[1].2["anomalies"][0]["severity"], "high");
+        assert_eq!(captured[2].2["anomalies"][0]["severity"], "critical");
+    }
+
+    #[test]
+    fn test_invalid_levels_are_rejected() {
+        let invalid = [
+            json!({ "levels": [] }),
+            json!({ "levels": [{ "after_minutes": 0, "bump_severity": true }] }),
+            json!({ "levels": [
+                { "after_minutes": 30, "bump_severity": true },
+                { "after_minutes": 10, "bump_severity": true },
+            ] }),
+            json!({ "levels": [{ "after_minutes": 10 }] }),
+        ];
+        for value in invalid {
+            assert!(config(value).validate().is_err());
+        }
+    }
+}
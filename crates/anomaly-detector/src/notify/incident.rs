@@ -0,0 +1,378 @@
+//! The incident lifecycle API.
+//!
+//! Notifying anomalies opens an incident per sensor and method, linked to
+//! each anomaly notified while it is open. An incident is `open` until
+//! acknowledged, and `resolved` once a backfill ends on a normal reading or
+//! someone resolves it here; the next anomaly then opens a new one.
+//! Incidents can be assigned and carry notes:
+//!
+//! ```json
+//! PUT /incidents/12/assignee  { "assignee": "alice" }
+//! POST /incidents/12/notes    { "body": "bearing replaced", "author": "alice" }
+//! ```
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, AuditEntry, Change};
+use crate::storage::{IncidentFilter, IncidentNote, Storage, StoredAnomaly, StoredIncident};
+use crate::{AppState, hints};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1_000;
+const STATES: [&str; 3] = ["open", "acknowledged", "resolved"];
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Incident {
+    pub id: i64,
+    pub sensor_id: i64,
+    pub method: String,
+    /// `open`, `acknowledged` or `resolved`.
+    pub state: &'static str,
+    pub assignee: Option<String>,
+    pub opened_at: String,
+    pub acknowledged_at: Option<String>,
+    pub resolved_at: Option<String>,
+    pub escalation_level: i64,
+    pub last_anomaly_id: i64,
+}
+
+impl From<StoredIncident> for Incident {
+    fn from(stored: StoredIncident) -> Self {
+        let state = match (&stored.resolved_at, &stored.acknowledged_at) {
+            (Some(_), _) => "resolved",
+            (None, Some(_)) => "acknowledged",
+            (None, None) => "open",
+        };
+        Self {
+            id: stored.id,
+            sensor_id: stored.sensor_id,
+            method: stored.method,
+            state,
+            assignee: stored.assignee,
+            opened_at: stored.opened_at,
+            acknowledged_at: stored.acknowledged_at,
+            resolved_at: stored.resolved_at,
+            escalation_level: stored.escalation_level,
+            last_anomaly_id: stored.last_anomaly_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct IncidentDetails {
+    #[serde(flatten)]
+    pub incident: Incident,
+    pub notes: Vec<IncidentNote>,
+    /// Changes to the sensor that may explain it, see [`crate::hints`].
+    pub hints: Vec<AuditEntry>,
+    /// The anomalies linked to it, leaving out ones deleted since.
+    pub anomalies: Vec<StoredAnomaly>,
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+async fn find(storage: &Storage, id: i64) -> Result<Incident, (StatusCode, String)> {
+    storage
+        .incident(id)
+        .await
+        .map_err(internal)?
+        .map(Incident::from)
+        .ok_or((StatusCode::NOT_FOUND, format!("no incident with id {}", id)))
+}
+
+#[derive(Deserialize)]
+pub struct IncidentQuery {
+    #[serde(default)]
+    state: Option<String>,
+    #[serde(default)]
+    sensor_id: Option<i64>,
+    #[serde(default)]
+    assignee: Option<String>,
+    #[serde(default)]
+    limit: Option<i64>,
+}
+
+/// Lists incidents, newest first.
+pub async fn list_incidents(
+    State(state): State<AppState>,
+    Query(query): Query<IncidentQuery>,
+) -> Result<Json<Vec<Incident>>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    if let Some(state) = &query.state
+        && !STATES.contains(&state.as_str())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("state must be one of {}", STATES.join(", ")),
+        ));
+    }
+    let filter = IncidentFilter {
+        state: query.state,
+        sensor_id: query.sensor_id,
+        assignee: query.assignee,
+        limit: query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT),
+    };
+    let incidents = storage.list_incidents(&filter).await.map_err(internal)?;
+    Ok(Json(incidents.into_iter().map(Incident::from).collect()))
+}
+
+/// An incident with its notes, root-cause hints and anomalies.
+pub async fn get_incident(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+) -> Result<Json<IncidentDetails>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let incident = find(storage, id).await?;
+    let notes = storage.incident_notes(id).await.map_err(internal)?;
+    let ids = storage.incident_anomaly_ids(id).await.map_err(internal)?;
+    let anomalies = storage.anomalies_by_id(&ids).await.map_err(internal)?;
+    let hints = hints::for_incident(
+        storage,
+        incident.sensor_id,
+        &incident.opened_at,
+        incident.resolved_at.as_deref(),
+    )
+    .await
+    .map_err(internal)?;
+    Ok(Json(IncidentDetails {
+        incident,
+        notes,
+        hints,
+        anomalies,
+    }))
+}
+
+/// Acknowledges an open incident, stopping its escalation, and the matching
+/// PagerDuty incident.
+pub async fn acknowledge_incident(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<Json<Incident>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let before = find(storage, id).await?;
+    if before.state != "open" {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("incident {} is already {}", id, before.state),
+        ));
+    }
+    storage
+        .acknowledge_incident(before.sensor_id, &before.method)
+        .await
+        .map_err(internal)?;
+    if let Some(pagerduty) = state.notifier.as_ref().and_then(|n| n.pagerduty.as_ref())
+        && !pagerduty
+            .acknowledge(before.sensor_id, &before.method)
+            .await
+    {
+        eprintln!(
+            "Warning: PagerDuty rejected the acknowledgement of incident {}",
+            id
+        );
+    }
+    changed(storage, &headers, before).await
+}
+
+/// Resolves an incident, and the matching incidents of channels that track
+/// them; the sensor's next anomaly opens a new one.
+pub async fn resolve_incident(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<Json<Incident>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let before = find(storage, id).await?;
+    if before.state == "resolved" {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("incident {} is already resolved", id),
+        ));
+    }
+    match &state.notifier {
+        Some(notifier) => notifier.resolve(before.sensor_id, &before.method).await,
+        None => storage
+            .resolve_incident(before.sensor_id, &before.method)
+            .await
+            .map_err(internal)?,
+    }
+    changed(storage, &headers, before).await
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssigneeRequest {
+    /// `null` unassigns the incident.
+    assignee: Option<String>,
+}
+
+pub async fn assign_incident(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<AssigneeRequest>,
+) -> Result<Json<Incident>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let before = find(storage, id).await?;
+    let assignee = payload.assignee.as_deref().map(str::trim);
+    if assignee == Some("") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "assignee cannot be empty; use null to unassign".to_string(),
+        ));
+    }
+    storage
+        .assign_incident(id, assignee)
+        .await
+        .map_err(internal)?;
+    changed(storage, &headers, before).await
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoteRequest {
+    body: String,
+    /// Defaults to the caller named by the audit header.
+    #[serde(default)]
+    author: Option<String>,
+}
+
+pub async fn add_note(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+    Json(payload): Json<NoteRequest>,
+) -> Result<(StatusCode, Json<IncidentNote>), (StatusCode, String)> {
+    let storage = storage(&state)?;
+    find(storage, id).await?;
+    if payload.body.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "a note needs a body".to_string()));
+    }
+    let author = payload.author.unwrap_or_else(|| audit::actor(&headers));
+    let note = storage
+        .add_incident_note(id, &author, &payload.body)
+        .await
+        .map_err(internal)?;
+    Ok((StatusCode::CREATED, Json(note)))
+}
+
+/// Reads the incident back after a change and records it in the audit log.
+async fn changed(
+    storage: &Storage,
+    headers: &HeaderMap,
+    before: Incident,
+) -> Result<Json<Incident>, (StatusCode, String)> {
+    let after = find(storage, before.id).await?;
+    let change = Change::new(
+        "incident",
+        Some(before.id.to_string()),
+        Some(&before),
+        Some(&after),
+    );
+    audit::record(storage, &audit::actor(headers), [change]).await;
+    Ok(Json(after))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{in_memory, insert_anomaly};
+
+    #[tokio::test]
+    async fn test_incident_lifecycle() {
+        let storage = in_memory().await;
+        insert_anomaly(&storage, 1, "2026-01-19 10:00:00").await;
+        insert_anomaly(&storage, 1, "2026-01-19 10:01:00").await;
+        let anomalies = storage.anomalies_by_id(&[1, 2]).await.unwrap();
+        let episodes: Vec<(i64, &str, i64)> = anomalies
+            .iter()
+            .map(|a| (a.sensor_id, a.method.as_str(), a.id))
+            .collect();
+        storage.open_incidents(&episodes).await.unwrap();
+        let state = AppState {
+            storage: Some(storage.clone()),
+            ..AppState::default()
+        };
+        let query = |state: Option<&str>| {
+            Query(IncidentQuery {
+                state: state.map(String::from),
+                sensor_id: None,
+                assignee: None,
+                limit: None,
+            })
+        };
+
+        let Json(open) = list_incidents(State(state.clone()), query(Some("open")))
+            .await
+            .unwrap();
+        assert_eq!(open.len(), 1);
+        let id = open[0].id;
+        assert_eq!(open[0].last_anomaly_id, 2);
+
+        let Json(assigned) = assign_incident(
+            State(state.clone()),
+            Path(id),
+            HeaderMap::new(),
+            Json(AssigneeRequest {
+                assignee: Some("alice".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(assigned.assignee.as_deref(), Some("alice"));
+        let (status, _) = add_note(
+            State(state.clone()),
+            Path(id),
+            HeaderMap::new(),
+            Json(NoteRequest {
+                body: "bearing replaced".to_string(),
+                author: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+
+        let Json(acknowledged) =
+            acknowledge_incident(State(state.clone()), Path(id), HeaderMap::new())
+                .await
+                .unwrap();
+        assert_eq!(acknowledged.state, "acknowledged");
+        let again = acknowledge_incident(State(state.clone()), Path(id), HeaderMap::new()).await;
+        assert_eq!(again.unwrap_err().0, StatusCode::CONFLICT);
+        let Json(resolved) = resolve_incident(State(state.clone()), Path(id), HeaderMap::new())
+            .await
+            .unwrap();
+        assert_eq!(resolved.state, "resolved");
+
+        let Json(details) = get_incident(State(state.clone()), Path(id)).await.unwrap();
+        let ids: Vec<i64> = details.anomalies.iter().map(|a| a.id).collect();
+        assert_eq!(ids, [1, 2]);
+        assert_eq!(details.notes[0].body, "bearing replaced");
+        assert_eq!(details.notes[0].author, "unknown");
+        assert!(
+            list_incidents(State(state.clone()), query(Some("open")))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let invalid = list_incidents(State(state.clone()), query(Some("closed"))).await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let missing = get_incident(State(state), Path(id + 1)).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,340 @@
+//! PagerDuty channel: one incident per anomaly episode via the Events API v2.
+//!
+//! An episode is identified by sensor and detection method, which also forms
+//! the dedup key, so repeated anomalies update the open incident instead of
+//! creating new ones. The incident is resolved once the sensor scores normal
+//! again.
+//!
+//! Open incidents are tracked in `pagerduty_incidents` when there is storage,
+//! so a replica can resolve an incident another one triggered, and in memory
+//! otherwise.
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::Severity;
+use super::breaker::{Breaker, BreakerConfig};
+use crate::storage::{Storage, StoredAnomaly};
+
+const DEFAULT_EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+pub struct PagerDutyConfig {
+    routing_key: String,
+    #[serde(default = "default_events_url")]
+    events_url: String,
+    /// Anomalies below this severity do not open incidents.
+    #[serde(default = "default_min_severity")]
+    min_severity: Severity,
+    #[serde(default = "default_source")]
+    source: String,
+}
+
+fn default_events_url() -> String {
+    DEFAULT_EVENTS_URL.to_string()
+}
+
+fn default_min_severity() -> Severity {
+    Severity::High
+}
+
+fn default_source() -> String {
+    "anomaly-detector".to_string()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Action {
+    Trigger,
+    Acknowledge,
+    Resolve,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Trigger => "trigger",
+            Action::Acknowledge => "acknowledge",
+            Action::Resolve => "resolve",
+        }
+    }
+}
+
+pub struct PagerDutyNotifier {
+    client: reqwest::Client,
+    config: PagerDutyConfig,
+    /// Dedup keys of incidents triggered and not yet resolved, without
+    /// storage.
+    open: Mutex<HashSet<String>>,
+    storage: Option<Storage>,
+    breaker: Breaker,
+}
+
+fn dedup_key(sensor_id: i64, method: &str) -> String {
+    format!("sensor-{}-{}", sensor_id, method)
+}
+
+fn pagerduty_severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical => "critical",
+        Severity::High => "error",
+        Severity::Medium => "warning",
+    }
+}
+
+impl PagerDutyNotifier {
+    pub fn new(config: PagerDutyConfig) -> Result<Self, String> {
+        if config.routing_key.trim().is_empty() {
+            return Err("routing_key must not be empty".to_string());
+        }
+        url::Url::parse(&config.events_url).map_err(|e| format!("invalid events_url: {}", e))?;
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            config,
+            open: Mutex::new(HashSet::new()),
+            storage: None,
+            breaker: Breaker::new("PagerDuty", BreakerConfig::default()),
+        })
+    }
+
+    /// Puts the Events API behind a circuit breaker configured as `config`.
+    pub fn with_breaker(mut self, config: BreakerConfig) -> Self {
+        self.breaker = Breaker::new("PagerDuty", config);
+        self
+    }
+
+    /// Tracks open incidents in `storage` instead of in memory.
+    pub fn with_storage(mut self, storage: Storage) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    async fn mark_open(&self, key: String) {
+        let Some(storage) = &self.storage else {
+            self.open.lock().unwrap().insert(key);
+            return;
+        };
+        if let Err(e) = storage.open_pagerduty_incident(&key).await {
+            eprintln!("Error: Failed to record PagerDuty incident {}: {}", key, e);
+        }
+    }
+
+    /// Whether the incident may be open. Assumes it is when storage cannot
+    /// tell, as resolving a closed incident is harmless.
+    async fn is_open(&self, key: &str) -> bool {
+        let Some(storage) = &self.storage else {
+            return self.open.lock().unwrap().contains(key);
+        };
+        storage
+            .pagerduty_incident_open(key)
+            .await
+            .unwrap_or_else(|e| {
+                eprintln!("Error: Failed to look up PagerDuty incident {}: {}", key, e);
+                true
+            })
+    }
+
+    async fn mark_resolved(&self, key: &str) {
+        let Some(storage) = &self.storage else {
+            self.open.lock().unwrap().remove(key);
+            return;
+        };
+        if let Err(e) = storage.close_pagerduty_incident(key).await {
+            eprintln!("Error: Failed to clear PagerDuty incident {}: {}", key, e);
+        }
+    }
+
+    /// Triggers (or updates) one incident per sensor and method.
+    pub async fn notify(&self, anomalies: &[StoredAnomaly]) {
+        let mut episodes: BTreeMap<(i64, &str), Vec<(&StoredAnomaly, Severity)>> = BTreeMap::new();
+        for anomaly in anomalies {
+            if anomaly.severity >= self.config.min_severity {
+                episodes
+                    .entry((anomaly.sensor_id, anomaly.method.as_str()))
+                    .or_default()
+                    .push((anomaly, anomaly.severity));
+            }
+        }
+
+        for ((sensor_id, method), anomalies) in episodes {
+            let key = dedup_key(sensor_id, method);
+            let (worst, severity) = anomalies
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    a.1.cmp(&b.1)
+                        .then(a.0.score.abs().total_cmp(&b.0.score.abs()))
+                })
+                .expect("episode has at least one anomaly");
+            let payload = json!({
+                "summary": format!(
+                    "{} anomaly on sensor {}: value {} ({} score {:.2})",
+                    severity.label(), sensor_id, worst.value, method, worst.score
+                ),
+                "source": self.config.source,
+                "severity": pagerduty_severity(severity),
+                "component": format!("sensor-{}", sensor_id),
+                "class": method,
+                "custom_details": {
+                    "sensor_id": sensor_id,
+                    "anomalies": anomalies.len(),
+                    "value": worst.value,
+                    "score": worst.score,
+                    "timestamp": worst.timestamp,
+                },
+            });
+            if self.send(Action::Trigger, &key, Some(payload)).await {
+                self.mark_open(key).await;
+            }
+        }
+    }
+
+    /// Resolves the sensor's incident if one is open.
+    pub async fn resolve(&self, sensor_id: i64, method: &str) {
+        let key = dedup_key(sensor_id, method);
+        if !self.is_open(&key).await {
+            return;
+        }
+        if self.send(Action::Resolve, &key, None).await {
+            self.mark_resolved(&key).await;
+        }
+    }
+
+    /// Acknowledges the sensor's incident, returning whether PagerDuty
+    /// accepted the event.
+    pub async fn acknowledge(&self, sensor_id: i64, method: &str) -> bool {
+        self.send(Action::Acknowledge, &dedup_key(sensor_id, method), None)
+            .await
+    }
+
+    async fn send(&self, action: Action, key: &str, payload: Option<Value>) -> bool {
+        let mut event = json!({
+            "routing_key": self.config.routing_key,
+            "event_action": action.as_str(),
+            "dedup_key": key,
+        });
+        if let Some(payload) = payload {
+            event["payload"] = payload;
+        }
+        if !self.breaker.allow() {
+            eprintln!(
+                "Error: Circuit of PagerDuty is open, not sending {} for {}",
+                action.as_str(),
+                key
+            );
+            return false;
+        }
+
+        let result = self
+            .client
+            .post(&self.config.events_url)
+            .json(&event)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        match result {
+            Ok(_) => {
+                self.breaker.succeeded();
+                println!("Notify: sent PagerDuty {} for {}", action.as_str(), key);
+                true
+            }
+            Err(e) => {
+                self.breaker.failed();
+                eprintln!(
+                    "Error: Failed to send PagerDuty {} for {}: {}",
+                    action.as_str(),
+                    key,
+                    e
+                );
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::{anomaly, capture_server};
+
+    fn notifier(base_url: &str) -> PagerDutyNotifier {
+        let config: PagerDutyConfig = serde_json::from_value(json!({
+            "routing_key": "R0UTING",
+            "events_url": format!("{}/v2/enqueue", base_url),
+        }))
+        .unwrap();
+        PagerDutyNotifier::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_episode_trigger_and_resolve() {
+        let (url, captured) = capture_server().await;
+        let notifier = notifier(&url);
+
+        notifier
+            .notify(&[
+                anomaly(7, Severity::High),
+                anomaly(7, Severity::Critical),
+                anomaly(8, Severity::Medium),
+            ])
+            .await;
+        notifier.resolve(7, "zscore").await;
+        notifier.resolve(8, "zscore").await;
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+
+        let trigger = &captured[0].2;
+        assert_eq!(trigger["routing_key"], "R0UTING");
+        assert_eq!(trigger["event_action"], "trigger");
+        assert_eq!(trigger["dedup_key"], "sensor-7-zscore");
+        assert_eq!(trigger["payload"]["severity"], "critical");
+        assert_eq!(trigger["payload"]["custom_details"]["anomalies"], 2);
+
+        let resolve = &captured[1].2;
+        assert_eq!(resolve["event_action"], "resolve");
+        assert_eq!(resolve["dedup_key"], "sensor-7-zscore");
+        assert!(resolve.get("payload").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_replica_resolves_incident_triggered_by_another() {
+        let (url, captured) = capture_server().await;
+        let storage = crate::storage::testing::in_memory().await;
+        let first = notifier(&url).with_storage(storage.clone());
+        let second = notifier(&url).with_storage(storage);
+
+        first.notify(&[anomaly(7, Severity::Critical)]).await;
+        second.resolve(7, "zscore").await;
+        first.resolve(7, "zscore").await;
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+        assert_eq!(captured[1].2["event_action"], "resolve");
+    }
+
+    #[tokio::test]
+    async fn test_failed_trigger_leaves_episode_closed() {
+        let notifier = notifier("http://127.0.0.1:9");
+
+        notifier.notify(&[anomaly(7, Severity::Critical)]).await;
+
+        assert!(notifier.open.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_requires_routing_key() {
+        let config: PagerDutyConfig =
+            serde_json::from_value(json!({ "routing_key": " " })).unwrap();
+        assert!(PagerDutyNotifier::new(config).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+//! Durable hand-off of anomalies to the channels.
+//!
+//! With storage configured, anomalies to notify, and incidents to resolve
+//! after them, are written to `notification_queue` first and delivered from
+//! there in order by a background worker, so a restart between detection and
+//! delivery loses nothing. An entry is claimed before it is delivered and
+//! marked delivered after; one whose claim is older than [`CLAIM_TIMEOUT`]
+//! belonged to a process that stopped midway and is delivered again. A
+//! channel may therefore see an anomaly twice, never zero times.
+//!
+//! Each anomaly carries a dedup key,
+//! `<sensor_id>-<method>-<reading_id>-<timestamp>-<severity>`, which webhooks
+//! receive as `dedup_key` to drop the repeat; sensors sharing reading ids, and
+//! a sensor reusing one at another time, get keys of their own. The queue takes
+//! an anomaly once per key, so a backfill over a range that was already
+//! notified only notifies what changed. Without storage but with Redis (see
+//! `shared`), the keys are recorded there instead, so that of replicas
+//! detecting the same anomaly only one notifies it.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::Notifier;
+use crate::storage::{NewNotification, QueuedNotification, Storage, StoredAnomaly};
+
+/// How long a claimed entry may take to deliver before it is claimed again.
+pub const CLAIM_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How often the queue is checked when nothing wakes the worker.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Entries claimed per round.
+const BATCH_SIZE: i64 = 500;
+
+/// How long delivered entries, and so their dedup keys, are kept.
+const DEDUP_WINDOW: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// Identifies an anomaly across deliveries, and across backfills that detect
+/// it again.
+pub fn dedup_key(anomaly: &StoredAnomaly) -> String {
+    format!(
+        "{}-{}-{}-{}-{}",
+        anomaly.sensor_id, anomaly.method, anomaly.reading_id, anomaly.timestamp, anomaly.severity
+    )
+}
+
+/// Notifies `anomalies` and then, with `resolve`, resolves the sensor's
+/// incident: through the queue when there is storage, otherwise directly in
+/// the background.
+pub async fn enqueue(
+    notifier: &Arc<Notifier>,
+    anomalies: Vec<StoredAnomaly>,
+    resolve: Option<(i64, &'static str)>,
+) {
+    if let Some(storage) = &notifier.storage {
+        let mut entries: Vec<NewNotification> = anomalies
+            .iter()
+            .map(|anomaly| NewNotification {
+                anomaly_id: Some(anomaly.id),
+                sensor_id: anomaly.sensor_id,
+                method: anomaly.method.clone(),
+                dedup_key: Some(dedup_key(anomaly)),
+            })
+            .collect();
+        entries.extend(resolve.map(|(sensor_id, method)| NewNotification {
+            anomaly_id: None,
+            sensor_id,
+            method: method.to_string(),
+            dedup_key: None,
+        }));
+        match storage.enqueue_notifications(&entries).await {
+            Ok(queued) => {
+                if queued < entries.len() as u64 {
+                    println!(
+                        "Notify: {} anomalies were already queued",
+                        entries.len() as u64 - queued
+                    );
+                }
+                notifier.queued.notify_one();
+                return;
+            }
+            Err(e) => eprintln!(
+                "Error: Failed to queue {} notifications, sending them directly: {}",
+                entries.len(),
+                e
+            ),
+        }
+    }
+
+    let notifier = notifier.clone();
+    tokio::spawn(async move {
+        let anomalies = match &notifier.redis {
+            Some(redis) => {
+                let mut unseen = Vec::with_capacity(anomalies.len());
+                for anomaly in anomalies {
+                    match redis
+                        .claim_dedup_key(&dedup_key(&anomaly), DEDUP_WINDOW)
+                        .await
+                    {
+                        Ok(false) => {}
+                        Ok(true) => unseen.push(anomaly),
+                        Err(e) => {
+                            eprintln!(
+                                "Error: Failed to record a dedup key, notifying anyway: {}",
+                                e
+                            );
+                            unseen.push(anomaly);
+                        }
+                    }
+                }
+                unseen
+            }
+            None => anomalies,
+        };
+        if !anomalies.is_empty() {
+            notifier.notify(&anomalies).await;
+        }
+        if let Some((sensor_id, method)) = resolve {
+            notifier.resolve(sensor_id, method).await;
+        }
+    });
+}
+
+/// Delivers claimed entries in order, notifying each run of anomalies
+/// together and resolving incidents between them.
+async fn deliver(
+    notifier: &Notifier,
+    storage: &Storage,
+    entries: &[QueuedNotification],
+) -> Result<(), sqlx::Error> {
+    let redelivered = entries.iter().filter(|e| e.attempts > 1).count();
+    if redelivered > 0 {
+        println!(
+            "Notify: delivering {} queued notifications again after an interrupted attempt",
+            redelivered
+        );
+    }
+    for run in entries.chunk_by(|a, b| a.anomaly_id.is_some() == b.anomaly_id.is_some()) {
+        let ids: Vec<i64> = run.iter().filter_map(|e| e.anomaly_id).collect();
+        if ids.is_empty() {
+            for entry in run {
+                notifier.resolve(entry.sensor_id, &entry.method).await;
+            }
+        } else {
+            let anomalies = storage.anomalies_by_id(&ids).await?;
+            if anomalies.len() < ids.len() {
+                println!(
+                    "Notify: {} queued anomalies were deleted before delivery",
+                    ids.len() - anomalies.len()
+                );
+            }
+            notifier.notify(&anomalies).await;
+        }
+        let done: Vec<i64> = run.iter().map(|e| e.id).collect();
+        storage.complete_notifications(&done).await?;
+    }
+    Ok(())
+}
+
+/// Delivers everything that is due from the queue.
+pub async fn drain(notifier: &Notifier, storage: &Storage) -> Result<(), sqlx::Error> {
+    loop {
+        let entries = storage
+            .claim_notifications(CLAIM_TIMEOUT.as_secs() as i64, BATCH_SIZE)
+            .await?;
+        if entries.is_empty() {
+            return Ok(());
+        }
+        deliver(notifier, storage, &entries).await?;
+    }
+}
+
+/// Delivers queued notifications as they arrive, and prunes old ones.
+pub fn spawn(notifier: Arc<Notifier>) {
+    tokio::spawn(async move {
+        let Some(storage) = &notifier.storage else {
+            return;
+        };
+        loop {
+            if let Err(e) = drain(&notifier, storage).await {
+                eprintln!("Error: Failed to deliver queued notifications: {}", e);
+            }
+            let pruned = match storage.cutoff(DEDUP_WINDOW).await {
+                Ok(cutoff) => storage.prune_notifications(&cutoff).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = pruned {
+                eprintln!("Error: Failed to prune the notification queue: {}", e);
+            }
+            let _ = tokio::time::timeout(POLL_INTERVAL, notifier.queued.notified()).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ZSCORE_METHOD;
+    use crate::notify::Severity;
+    use crate::notify::testing::capture_server;
+    use crate::notify::webhook::WebhookNotifier;
+    use crate::storage::testing::{expire_claims, in_memory};
+    use crate::storage::{AnomalyFilter, NewAnomaly};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_queued_anomalies_survive_until_delivered_once_per_key() {
+        let (url, captured) = capture_server().await;
+        let storage = in_memory().await;
+        let new = |sensor_id: i64| NewAnomaly {
+            reading_id: sensor_id * 10,
+            sensor_id,
+            value: 99.0,
+            timestamp: "2026-01-19 10:00:00".to_string(),
+            method: "zscore",
+            score: 3.0,
+            severity: Severity::High,
+        };
+        storage.insert_anomalies(&[new(1), new(2)]).await.unwrap();
+        let anomalies = storage
+            .list_anomalies(&AnomalyFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        let webhooks = WebhookNotifier::new(
+            serde_json::from_value(json!([{ "name": "ops", "url": url }])).unwrap(),
+        )
+        .unwrap();
+        let notifier = Arc::new(Notifier {
+            webhooks: Some(webhooks),
+            storage: Some(storage.clone()),
+            ..Notifier::default()
+        });
+
+        enqueue(&notifier, anomalies.clone(), Some((1, ZSCORE_METHOD))).await;
+        enqueue(&notifier, anomalies.clone(), None).await;
+        assert!(captured.lock().unwrap().is_empty());
+
+        // A claim left behind by a stopped process is not redelivered early.
+        let abandoned = storage.claim_notifications(120, 1).await.unwrap();
+        assert_eq!(abandoned.len(), 1);
+        drain(&notifier, &storage).await.unwrap();
+        {
+            let captured = captured.lock().unwrap();
+            assert_eq!(captured.len(), 1);
+            assert_eq!(captured[0].2["count"], 1);
+            assert_eq!(captured[0].2["anomalies"][0]["sensor_id"], 2);
+        }
+
+        expire_claims(&storage).await;
+        let redelivered = storage.claim_notifications(120, 10).await.unwrap();
+        assert_eq!(redelivered.len(), 1);
+        assert_eq!(redelivered[0].attempts, 2);
+        deliver(&notifier, &storage, &redelivered).await.unwrap();
+        drain(&notifier, &storage).await.unwrap();
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 2);
+        let first = &captured[1].2["anomalies"][0];
+        assert_eq!(first["sensor_id"], 1);
+        assert_eq!(first["dedup_key"], dedup_key(&anomalies[0]));
+    }
+
+    #[tokio::test]
+    async fn test_sensors_sharing_a_reading_id_are_each_delivered() {
+        let (url, captured) = capture_server().await;
+        let storage = in_memory().await;
+        let new = |sensor_id: i64, timestamp: &str| NewAnomaly {
+            reading_id: 7,
+            sensor_id,
+            value: 99.0,
+            timestamp: timestamp.to_string(),
+            method: "zscore",
+            score: 3.0,
+            severity: Severity::High,
+        };
+        storage
+            .insert_anomalies(&[
+                new(1, "2026-01-19 10:00:00"),
+                new(2, "2026-01-19 10:00:00"),
+                new(2, "2026-01-20 10:00:00"),
+            ])
+            .await
+            .unwrap();
+        let anomalies = storage
+            .list_anomalies(&AnomalyFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        let webhooks = WebhookNotifier::new(
+            serde_json::from_value(json!([{ "name": "ops", "url": url }])).unwrap(),
+        )
+        .unwrap();
+        let notifier = Arc::new(Notifier {
+            webhooks: Some(webhooks),
+            storage: Some(storage.clone()),
+            ..Notifier::default()
+        });
+
+        enqueue(&notifier, anomalies, None).await;
+        drain(&notifier, &storage).await.unwrap();
+
+        let captured = captured.lock().unwrap();
+        let mut delivered: Vec<i64> = captured
+            .iter()
+            .flat_map(|(_, _, body)| body["anomalies"].as_array().unwrap().clone())
+            .map(|a| a["sensor_id"].as_i64().unwrap())
+            .collect();
+        delivered.sort();
+        assert_eq!(delivered, vec![1, 2, 2]);
+    }
+}
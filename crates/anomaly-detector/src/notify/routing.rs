@@ -0,0 +1,426 @@
+//! Rules deciding which channels receive each anomaly.
+//!
+//! Rules are checked in order and the first match decides the channels,
+//! unless it sets `"continue": true`, in which case later rules may add more.
+//! Anomalies that match no rule go to `default`, or to every channel when
+//! `default` is omitted. Every condition of a rule's `match` must hold;
+//! omitted conditions match anything.
+//!
+//! ```json
+//! {
+//!   "utc_offset_minutes": 60,
+//!   "rules": [
+//!     { "name": "night shift", "match": { "severities": ["critical"], "hours": { "from": "22:00", "to": "06:00" } },
+//!       "channels": ["pagerduty"] },
+//!     { "name": "line a", "match": { "tags": ["line:a"] }, "channels": ["slack:#line-a", "email:line-a"] }
+//!   ],
+//!   "default": ["email"]
+//! }
+//! ```
+//!
+//! Channels are named `email`, `slack`, `pagerduty`, `alertmanager` or
+//! `webhook`; `email`, `slack` and `webhook` may be narrowed to one group,
+//! channel or webhook with `:name`.
+
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use super::Severity;
+use crate::AppState;
+use crate::audit::{self, Change};
+use crate::storage::StoredAnomaly;
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+
+/// A channel, or one named destination within it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    Email(Option<String>),
+    Slack(Option<String>),
+    PagerDuty,
+    Webhook(Option<String>),
+    Alertmanager,
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, String> {
+        let (channel, name) = match raw.split_once(':') {
+            Some((channel, name)) => (channel, Some(name.to_string())),
+            None => (raw.as_str(), None),
+        };
+        match (channel, name) {
+            ("email", name) => Ok(Target::Email(name)),
+            ("slack", name) => Ok(Target::Slack(name)),
+            ("webhook", name) => Ok(Target::Webhook(name)),
+            ("pagerduty", None) => Ok(Target::PagerDuty),
+            ("alertmanager", None) => Ok(Target::Alertmanager),
+            _ => Err(format!("unknown channel {:?}", raw)),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (channel, name) = match self {
+            Target::Email(name) => ("email", name),
+            Target::Slack(name) => ("slack", name),
+            Target::PagerDuty => ("pagerduty", &None),
+            Target::Webhook(name) => ("webhook", name),
+            Target::Alertmanager => ("alertmanager", &None),
+        };
+        match name {
+            Some(name) => write!(f, "{}:{}", channel, name),
+            None => f.write_str(channel),
+        }
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> String {
+        target.to_string()
+    }
+}
+
+/// Minutes since midnight, written as `"HH:MM"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay(u16);
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(raw: String) -> Result<Self, String> {
+        let invalid = || format!("invalid time of day {:?}, expected HH:MM", raw);
+        let (hours, minutes) = raw.split_once(':').ok_or_else(invalid)?;
+        let hours: u16 = hours.parse().map_err(|_| invalid())?;
+        let minutes: u16 = minutes.parse().map_err(|_| invalid())?;
+        if hours > 23 || minutes > 59 {
+            return Err(invalid());
+        }
+        Ok(TimeOfDay(hours * 60 + minutes))
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> String {
+        format!("{:02}:{:02}", time.0 / 60, time.0 % 60)
+    }
+}
+
+/// `[from, to)`; wraps past midnight when `to` is earlier than `from`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct HourWindow {
+    from: TimeOfDay,
+    to: TimeOfDay,
+}
+
+impl HourWindow {
+    fn contains(&self, minute: u16) -> bool {
+        let (from, to) = (self.from.0, self.to.0);
+        if from <= to {
+            from <= minute && minute < to
+        } else {
+            minute >= from || minute < to
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Mon,
+    Tue,
+    Wed,
+    Thu,
+    Fri,
+    Sat,
+    Sun,
+}
+
+const WEEKDAYS: [Weekday; 7] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+    Weekday::Sat,
+    Weekday::Sun,
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Conditions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sensors: Option<Vec<i64>>,
+    /// The sensor must carry every listed tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severities: Option<Vec<Severity>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    methods: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hours: Option<HourWindow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    days: Option<Vec<Weekday>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Rule {
+    name: String,
+    #[serde(default, rename = "match")]
+    conditions: Conditions,
+    channels: Vec<Target>,
+    #[serde(default, rename = "continue")]
+    continue_matching: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct RoutingConfig {
+    /// Offset of local time from UTC, applied to `hours` and `days`.
+    #[serde(default)]
+    utc_offset_minutes: i32,
+    rules: Vec<Rule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    default: Option<Vec<Target>>,
+}
+
+/// Where one anomaly should be delivered.
+#[derive(Debug, PartialEq)]
+pub enum Route {
+    All,
+    Targets(Vec<Target>),
+}
+
+/// Local day of week and minute of day.
+#[derive(Clone, Copy, Debug)]
+pub struct LocalTime {
+    weekday: Weekday,
+    minute: u16,
+}
+
+impl RoutingConfig {
+    pub fn targets(&self) -> impl Iterator<Item = &Target> {
+        self.rules
+            .iter()
+            .flat_map(|rule| &rule.channels)
+            .chain(self.default.iter().flatten())
+    }
+
+    /// Whether any rule needs sensor tags, which are looked up per delivery.
+    pub fn uses_tags(&self) -> bool {
+        self.rules.iter().any(|rule| rule.conditions.tags.is_some())
+    }
+
+    pub fn local_time(&self, now: SystemTime) -> LocalTime {
+        let minutes = now
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64 / 60)
+            .unwrap_or(0)
+            + self.utc_offset_minutes as i64;
+        let days = minutes.div_euclid(MINUTES_PER_DAY);
+        // 1970-01-01 was a Thursday.
+        LocalTime {
+            weekday: WEEKDAYS[(days + 3).rem_euclid(7) as usize],
+            minute: minutes.rem_euclid(MINUTES_PER_DAY) as u16,
+        }
+    }
+
+    pub fn route(&self, anomaly: &StoredAnomaly, tags: &[String], now: LocalTime) -> Route {
+        let mut targets: Vec<Target> = Vec::new();
+        let mut matched = false;
+        for rule in &self.rules {
+            if !rule.conditions.matches(anomaly, tags, now) {
+                continue;
+            }
+            matched = true;
+            for target in &rule.channels {
+                if !targets.contains(target) {
+                    targets.push(target.clone());
+                }
+            }
+            if !rule.continue_matching {
+                break;
+            }
+        }
+
+        match (matched, &self.default) {
+            (true, _) => Route::Targets(targets),
+            (false, Some(default)) => Route::Targets(default.clone()),
+            (false, None) => Route::All,
+        }
+    }
+}
+
+impl Conditions {
+    fn matches(&self, anomaly: &StoredAnomaly, tags: &[String], now: LocalTime) -> bool {
+        self.sensors
+            .as_ref()
+            .is_none_or(|s| s.contains(&anomaly.sensor_id))
+            && self
+                .tags
+                .as_ref()
+                .is_none_or(|required| required.iter().all(|t| tags.contains(t)))
+            && self
+                .severities
+                .as_ref()
+                .is_none_or(|s| s.contains(&anomaly.severity))
+            && self
+                .methods
+                .as_ref()
+                .is_none_or(|m| m.contains(&anomaly.method))
+            && self.hours.as_ref().is_none_or(|h| h.contains(now.minute))
+            && self.days.as_ref().is_none_or(|d| d.contains(&now.weekday))
+    }
+}
+
+fn notifier(state: &AppState) -> Result<&super::Notifier, (StatusCode, String)> {
+    state.notifier.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "notifications are not configured; set ANOMALY_NOTIFY_CONFIG".to_string(),
+    ))
+}
+
+/// Returns the routing rules in effect.
+pub async fn get_routing(
+    State(state): State<AppState>,
+) -> Result<Json<RoutingConfig>, (StatusCode, String)> {
+    Ok(Json(notifier(&state)?.routing().unwrap_or_default()))
+}
+
+/// Replaces the routing rules until the next restart.
+pub async fn put_routing(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<RoutingConfig>,
+) -> Result<Json<RoutingConfig>, (StatusCode, String)> {
+    let notifier = notifier(&state)?;
+    let previous = notifier.routing();
+    notifier
+        .set_routing(payload.clone())
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    if let Some(storage) = &state.storage {
+        let change = Change::new("routing", None, previous.as_ref(), Some(&payload));
+        audit::record(storage, &audit::actor(&headers), [change]).await;
+    }
+    Ok(Json(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::anomaly;
+    use serde_json::json;
+
+    fn config(value: serde_json::Value) -> RoutingConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn at(weekday: Weekday, time: &str) -> LocalTime {
+        LocalTime {
+            weekday,
+            minute: TimeOfDay::try_from(time.to_string()).unwrap().0,
+        }
+    }
+
+    fn targets(raw: &[&str]) -> Route {
+        Route::Targets(
+            raw.iter()
+                .map(|t| Target::try_from(t.to_string()).unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_first_match_wins_unless_continue() {
+        let routing = config(json!({
+            "rules": [
+                { "name": "tagged", "match": { "tags": ["line:a"] }, "channels": ["slack:#line-a"], "continue": true },
+                { "name": "critical", "match": { "severities": ["critical"] }, "channels": ["pagerduty"] },
+                { "name": "catch-all", "channels": ["email"] },
+            ],
+        }));
+        let tags = vec!["line:a".to_string()];
+        let now = at(Weekday::Mon, "12:00");
+
+        assert_eq!(
+            routing.route(&anomaly(1, Severity::Critical), &tags, now),
+            targets(&["slack:#line-a", "pagerduty"])
+        );
+        assert_eq!(
+            routing.route(&anomaly(1, Severity::High), &[], now),
+            targets(&["email"])
+        );
+    }
+
+    #[test]
+    fn test_unmatched_uses_default_or_all() {
+        let rules =
+            json!([{ "name": "one", "match": { "sensors": [1] }, "channels": ["email:ops"] }]);
+        let now = at(Weekday::Mon, "12:00");
+
+        let without_default = config(json!({ "rules": rules }));
+        assert_eq!(
+            without_default.route(&anomaly(2, Severity::High), &[], now),
+            Route::All
+        );
+
+        let with_default = config(json!({ "rules": rules, "default": [] }));
+        assert_eq!(
+            with_default.route(&anomaly(2, Severity::High), &[], now),
+            Route::Targets(vec![])
+        );
+    }
+
+    #[test]
+    fn test_time_windows() {
+        let routing = config(json!({
+            "rules": [{
+                "name": "night",
+                "match": { "hours": { "from": "22:00", "to": "06:00" }, "days": ["sat", "sun"] },
+                "channels": ["pagerduty"],
+            }],
+            "default": [],
+        }));
+        let route = |now| routing.route(&anomaly(1, Severity::High), &[], now);
+
+        assert_eq!(route(at(Weekday::Sat, "23:30")), targets(&["pagerduty"]));
+        assert_eq!(route(at(Weekday::Sun, "05:59")), targets(&["pagerduty"]));
+        assert_eq!(route(at(Weekday::Sun, "06:00")), Route::Targets(vec![]));
+        assert_eq!(route(at(Weekday::Fri, "23:30")), Route::Targets(vec![]));
+    }
+
+    #[test]
+    fn test_local_time_applies_offset() {
+        let routing = config(json!({ "utc_offset_minutes": 120, "rules": [] }));
+        // 2026-01-19 23:30 UTC is Tuesday 01:30 at UTC+2.
+        let now = UNIX_EPOCH + std::time::Duration::from_secs(1_768_865_400);
+
+        let local = routing.local_time(now);
+        assert_eq!(local.weekday, Weekday::Tue);
+        assert_eq!(local.minute, 90);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let invalid = [
+            json!({ "rules": [{ "name": "a", "channels": ["fax"] }] }),
+            json!({ "rules": [{ "name": "a", "channels": ["pagerduty:x"] }] }),
+            json!({ "rules": [{ "name": "a", "match": { "hours": { "from": "25:00", "to": "01:00" } }, "channels": [] }] }),
+            json!({ "rules": [{ "name": "a", "match": { "colour": "red" }, "channels": [] }] }),
+        ];
+        for value in invalid {
+            assert!(serde_json::from_value::<RoutingConfig>(value).is_err());
+        }
+    }
+}
@@ -0,0 +1,358 @@
+//! Silences: temporary mutes for notifications, in the style of Alertmanager.
+//!
+//! A silence holds matchers and a time window. Anomalies matching every
+//! matcher of a silence in effect are still stored and listed by
+//! `/anomalies`, but no channel is notified about them.
+//!
+//! ```json
+//! {
+//!   "matchers": { "sensors": [7], "severities": ["medium", "high"] },
+//!   "duration_minutes": 120,
+//!   "comment": "recalibrating",
+//!   "created_by": "alice"
+//! }
+//! ```
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use super::Severity;
+use crate::AppState;
+use crate::audit::{self, Change};
+use crate::storage::{NewSilence, Storage, StoredAnomaly, StoredSilence};
+
+/// Conditions an anomaly must all meet to be silenced; omitted ones match
+/// anything, but at least one is required.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Matchers {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sensors: Option<Vec<i64>>,
+    /// The sensor must carry every listed tag.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severities: Option<Vec<Severity>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    methods: Option<Vec<String>>,
+}
+
+impl Matchers {
+    fn is_empty(&self) -> bool {
+        self.sensors.is_none()
+            && self.tags.is_none()
+            && self.severities.is_none()
+            && self.methods.is_none()
+    }
+
+    pub fn uses_tags(&self) -> bool {
+        self.tags.is_some()
+    }
+
+    pub fn matches(&self, anomaly: &StoredAnomaly, tags: &[String]) -> bool {
+        self.sensors
+            .as_ref()
+            .is_none_or(|s| s.contains(&anomaly.sensor_id))
+            && self
+                .tags
+                .as_ref()
+                .is_none_or(|required| required.iter().all(|t| tags.contains(t)))
+            && self
+                .severities
+                .as_ref()
+                .is_none_or(|s| s.contains(&anomaly.severity))
+            && self
+                .methods
+                .as_ref()
+                .is_none_or(|m| m.contains(&anomaly.method))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SilenceRequest {
+    matchers: Matchers,
+    /// Defaults to now.
+    #[serde(default)]
+    starts_at: Option<String>,
+    /// Exactly one of `ends_at` and `duration_minutes` is required.
+    #[serde(default)]
+    ends_at: Option<String>,
+    #[serde(default)]
+    duration_minutes: Option<u32>,
+    #[serde(default)]
+    comment: String,
+    #[serde(default)]
+    created_by: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Silence {
+    pub id: i64,
+    pub matchers: Matchers,
+    pub comment: String,
+    pub created_by: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub created_at: String,
+}
+
+impl TryFrom<StoredSilence> for Silence {
+    type Error = String;
+
+    fn try_from(stored: StoredSilence) -> Result<Self, String> {
+        let matchers = serde_json::from_str(&stored.matchers)
+            .map_err(|e| format!("silence {} has invalid matchers: {}", stored.id, e))?;
+        Ok(Silence {
+            id: stored.id,
+            matchers,
+            comment: stored.comment,
+            created_by: stored.created_by,
+            starts_at: stored.starts_at,
+            ends_at: stored.ends_at,
+            created_at: stored.created_at,
+        })
+    }
+}
+
+/// Silences in effect now; ones that cannot be read are logged and skipped.
+pub async fn active(storage: &Storage) -> Vec<Silence> {
+    match storage.list_silences(true).await {
+        Ok(stored) => stored
+            .into_iter()
+            .filter_map(|s| {
+                Silence::try_from(s)
+                    .map_err(|e| eprintln!("Error: {}", e))
+                    .ok()
+            })
+            .collect(),
+        Err(e) => {
+            eprintln!("Error: Failed to load silences: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+fn bad_request(message: &str) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, message.to_string())
+}
+
+pub async fn create_silence(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<SilenceRequest>,
+) -> Result<(StatusCode, Json<Silence>), (StatusCode, String)> {
+    let storage = storage(&state)?;
+    if payload.matchers.is_empty() {
+        return Err(bad_request("a silence needs at least one matcher"));
+    }
+
+    let now = storage
+        .normalize_timestamp("now")
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| internal("failed to read the current time"))?;
+    let starts_at = match &payload.starts_at {
+        Some(starts_at) => storage
+            .normalize_timestamp(starts_at)
+            .await
+            .map_err(internal)?
+            .ok_or_else(|| bad_request("starts_at must be a valid timestamp"))?,
+        None => now.clone(),
+    };
+    let ends_at = match (&payload.ends_at, payload.duration_minutes) {
+        (Some(ends_at), None) => storage.normalize_timestamp(ends_at).await,
+        (None, Some(minutes)) => {
+            storage
+                .add_seconds(&starts_at, i64::from(minutes) * 60)
+                .await
+        }
+        _ => {
+            return Err(bad_request(
+                "set exactly one of ends_at and duration_minutes",
+            ));
+        }
+    }
+    .map_err(internal)?
+    .ok_or_else(|| bad_request("ends_at must be a valid timestamp"))?;
+    if ends_at <= starts_at || ends_at <= now {
+        return Err(bad_request(
+            "ends_at must be in the future and after starts_at",
+        ));
+    }
+
+    let stored = storage
+        .insert_silence(&NewSilence {
+            matchers: serde_json::to_string(&payload.matchers).map_err(internal)?,
+            comment: payload.comment,
+            created_by: payload.created_by,
+            starts_at,
+            ends_at,
+        })
+        .await
+        .map_err(internal)?;
+    let silence = Silence::try_from(stored).map_err(internal)?;
+    let change = Change::new(
+        "silence",
+        Some(silence.id.to_string()),
+        None,
+        Some(&silence),
+    );
+    audit::record(storage, &audit::actor(&headers), [change]).await;
+    println!("Notify: created silence {}", silence.id);
+    Ok((StatusCode::CREATED, Json(silence)))
+}
+
+#[derive(Deserialize)]
+pub struct SilenceQuery {
+    /// Only silences in effect now, leaving out ones that start later.
+    #[serde(default)]
+    active: bool,
+}
+
+/// Lists silences that have not expired.
+pub async fn list_silences(
+    State(state): State<AppState>,
+    Query(query): Query<SilenceQuery>,
+) -> Result<Json<Vec<Silence>>, (StatusCode, String)> {
+    let stored = storage(&state)?
+        .list_silences(query.active)
+        .await
+        .map_err(internal)?;
+    let silences = stored
+        .into_iter()
+        .map(Silence::try_from)
+        .collect::<Result<_, _>>()
+        .map_err(internal)?;
+    Ok(Json(silences))
+}
+
+/// Expires a silence immediately.
+pub async fn expire_silence(
+    State(state): State<AppState>,
+    Path(id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let previous = storage
+        .list_silences(false)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .find(|s| s.id == id)
+        .and_then(|s| Silence::try_from(s).ok());
+    if storage.expire_silence(id).await.map_err(internal)? {
+        let change = Change::new("silence", Some(id.to_string()), previous.as_ref(), None);
+        audit::record(storage, &audit::actor(&headers), [change]).await;
+        println!("Notify: expired silence {}", id);
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            format!("no unexpired silence with id {}", id),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::anomaly;
+    use crate::storage::testing::in_memory;
+    use serde_json::json;
+
+    async fn state() -> AppState {
+        AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        }
+    }
+
+    fn request(value: serde_json::Value) -> Json<SilenceRequest> {
+        Json(serde_json::from_value(value).unwrap())
+    }
+
+    #[test]
+    fn test_matchers() {
+        let matchers: Matchers =
+            serde_json::from_value(json!({ "tags": ["line:a"], "severities": ["medium"] }))
+                .unwrap();
+        let tags = vec!["line:a".to_string(), "boiler".to_string()];
+
+        assert!(matchers.matches(&anomaly(1, Severity::Medium), &tags));
+        assert!(!matchers.matches(&anomaly(1, Severity::High), &tags));
+        assert!(!matchers.matches(&anomaly(1, Severity::Medium), &[]));
+    }
+
+    #[tokio::test]
+    async fn test_create_list_and_expire() {
+        let state = state().await;
+
+        let (status, Json(created)) = create_silence(
+            State(state.clone()),
+            HeaderMap::new(),
+            request(json!({ "matchers": { "sensors": [7] }, "duration_minutes": 30, "comment": "maintenance" })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created.comment, "maintenance");
+
+        let Json(listed) =
+            list_silences(State(state.clone()), Query(SilenceQuery { active: true }))
+                .await
+                .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].matchers, created.matchers);
+
+        let storage = state.storage.clone().unwrap();
+        assert_eq!(active(&storage).await.len(), 1);
+
+        assert_eq!(
+            expire_silence(State(state.clone()), Path(created.id), HeaderMap::new())
+                .await
+                .unwrap(),
+            StatusCode::NO_CONTENT
+        );
+        assert!(active(&storage).await.is_empty());
+        assert_eq!(
+            expire_silence(State(state), Path(created.id), HeaderMap::new())
+                .await
+                .unwrap_err()
+                .0,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_invalid_requests() {
+        let state = state().await;
+        let invalid = [
+            json!({ "matchers": {}, "duration_minutes": 30 }),
+            json!({ "matchers": { "sensors": [1] } }),
+            json!({ "matchers": { "sensors": [1] }, "duration_minutes": 30, "ends_at": "2999-01-01" }),
+            json!({ "matchers": { "sensors": [1] }, "ends_at": "2000-01-01" }),
+            json!({ "matchers": { "sensors": [1] }, "ends_at": "soon" }),
+        ];
+        for value in invalid {
+            let result =
+                create_silence(State(state.clone()), HeaderMap::new(), request(value)).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Slack channel: posts anomalies to incoming webhooks, routed by severity.
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use super::breaker::{Breaker, BreakerConfig};
+use super::{Severity, render, routed};
+use crate::storage::StoredAnomaly;
+
+/// Anomalies listed in one message, below Slack's limit of 50 blocks.
+const MAX_BLOCKS_PER_MESSAGE: usize = 20;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Deserialize)]
+pub struct SlackConfig {
+    /// Link added to each anomaly; may use `{sensor_id}`, `{anomaly_id}` and
+    /// `{timestamp}`, which are URL-encoded.
+    #[serde(default)]
+    details_url: Option<String>,
+    channels: Vec<ChannelConfig>,
+}
+
+/// One incoming webhook and the severities routed to it (all when omitted).
+#[derive(Deserialize)]
+pub struct ChannelConfig {
+    name: String,
+    webhook_url: String,
+    #[serde(default)]
+    severities: Option<Vec<Severity>>,
+}
+
+pub struct SlackNotifier {
+    client: reqwest::Client,
+    details_url: Option<String>,
+    channels: Vec<ChannelConfig>,
+    /// The circuit breaker of each channel, in order.
+    breakers: Vec<Breaker>,
+}
+
+fn breakers(channels: &[ChannelConfig], config: BreakerConfig) -> Vec<Breaker> {
+    channels
+        .iter()
+        .map(|channel| Breaker::new(format!("Slack channel {}", channel.name), config))
+        .collect()
+}
+
+impl SlackNotifier {
+    pub fn new(config: SlackConfig) -> Result<Self, String> {
+        for channel in &config.channels {
+            url::Url::parse(&channel.webhook_url)
+                .map_err(|e| format!("channel {:?}: invalid webhook_url: {}", channel.name, e))?;
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            client,
+            details_url: config.details_url,
+            breakers: breakers(&config.channels, BreakerConfig::default()),
+            channels: config.channels,
+        })
+    }
+
+    /// Puts each channel behind a circuit breaker configured as `config`.
+    pub fn with_breakers(mut self, config: BreakerConfig) -> Self {
+        self.breakers = breakers(&self.channels, config);
+        self
+    }
+
+    pub fn has_channel(&self, name: &str) -> bool {
+        self.channels.iter().any(|c| c.name == name)
+    }
+
+    /// Posts one message per channel (or only the named one) listing the
+    /// anomalies routed to it.
+    pub async fn notify(&self, anomalies: &[StoredAnomaly], only: Option<&str>) {
+        for (channel, breaker) in self.channels.iter().zip(&self.breakers) {
+            if only.is_some_and(|name| name != channel.name) {
+                continue;
+            }
+            let routed = routed(anomalies, channel.severities.as_deref());
+            if routed.is_empty() {
+                continue;
+            }
+            if !breaker.allow() {
+                eprintln!(
+                    "Error: Circuit of Slack channel {} is open, dropping {} anomalies",
+                    channel.name,
+                    routed.len()
+                );
+                continue;
+            }
+
+            let result = self
+                .client
+                .post(&channel.webhook_url)
+                .json(&self.message(&routed))
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+            match result {
+                Ok(_) => {
+                    breaker.succeeded();
+                    println!(
+                        "Notify: posted {} anomalies to Slack channel {}",
+                        routed.len(),
+                        channel.name
+                    );
+                }
+                Err(e) => {
+                    breaker.failed();
+                    eprintln!(
+                        "Error: Failed to post to Slack channel {}: {}",
+                        channel.name, e
+                    );
+                }
+            }
+        }
+    }
+
+    fn message(&self, anomalies: &[&StoredAnomaly]) -> Value {
+        let summary = match anomalies {
+            [one] => format!(
+                "{} anomaly on sensor {}: {}",
+                one.severity, one.sensor_id, one.value
+            ),
+            _ => format!("{} anomalies detected", anomalies.len()),
+        };
+
+        let mut blocks: Vec<Value> = anomalies
+            .iter()
+            .take(MAX_BLOCKS_PER_MESSAGE)
+            .map(|a| {
+                json!({
+                    "type": "section",
+                    "text": { "type": "mrkdwn", "text": self.describe(a) },
+                })
+            })
+            .collect();
+        if anomalies.len() > MAX_BLOCKS_PER_MESSAGE {
+            blocks.push(json!({
+                "type": "context",
+                "elements": [{
+                    "type": "mrkdwn",
+                    "text": format!("… and {} more", anomalies.len() - MAX_BLOCKS_PER_MESSAGE),
+                }],
+            }));
+        }
+
+        json!({ "text": summary, "blocks": blocks })
+    }
+
+    fn describe(&self, anomaly: &StoredAnomaly) -> String {
+        let icon = match anomaly.severity {
+            Severity::Critical => ":red_circle:",
+            Severity::High => ":large_orange_circle:",
+            Severity::Medium => ":large_yellow_circle:",
+        };
+        let mut text = format!(
+            "{} *{}* anomaly on sensor *{}*\nValue `{}` at {} ({} score {:.2})",
+            icon,
+            anomaly.severity.label(),
+            anomaly.sensor_id,
+            anomaly.value,
+            anomaly.timestamp,
+            anomaly.method,
+            anomaly.score
+        );
+        if let Some(template) = &self.details_url {
+            let encode =
+                |value: String| url::form_urlencoded::byte_serialize(value.as_bytes()).collect();
+            let link = render(
+                template,
+                &[
+                    ("sensor_id", anomaly.sensor_id.to_string()),
+                    ("anomaly_id", anomaly.id.to_string()),
+                    ("timestamp", encode(anomaly.timestamp.clone())),
+                ],
+            );
+            text.push_str(&format!(" <{}|details>", link));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::{anomaly, capture_server};
+
+    fn notifier(base_url: &str) -> SlackNotifier {
+        let config: SlackConfig = serde_json::from_value(json!({
+            "details_url": "https://dash.example.com/sensors/{sensor_id}?at={timestamp}",
+            "channels": [
+                { "name": "pager", "webhook_url": format!("{}/pager", base_url), "severities": ["critical"] },
+                { "name": "all", "webhook_url": format!("{}/all", base_url) },
+            ],
+        }))
+        .unwrap();
+        SlackNotifier::new(config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_severity() {
+        let (url, captured) = capture_server().await;
+
+        notifier(&url)
+            .notify(
+                &[anomaly(1, Severity::Medium), anomaly(2, Severity::Critical)],
+                None,
+            )
+            .await;
+
+        let captured = captured.lock().unwrap();
+        let paths: Vec<&str> = captured.iter().map(|(path, _, _)| path.as_str()).collect();
+        assert_eq!(paths, vec!["/pager", "/all"]);
+
+        let pager = &captured[0].2;
+        assert_eq!(pager["text"], "critical anomaly on sensor 2: 99.5");
+        assert_eq!(pager["blocks"].as_array().unwrap().len(), 1);
+        let text = pager["blocks"][0]["text"]["text"].as_str().unwrap();
+        assert!(text.contains("sensor *2*"));
+        assert!(
+            text.contains(
+                "<https://dash.example.com/sensors/2?at=2026-01-19+10%3A00%3A00|details>"
+            )
+        );
+
+        assert_eq!(captured[1].2["text"], "2 anomalies detected");
+    }
+
+    #[test]
+    fn test_message_truncates_long_lists() {
+        let notifier = notifier("http://localhost");
+        let anomalies: Vec<StoredAnomaly> = (0..25).map(|i| anomaly(i, Severity::High)).collect();
+        let refs: Vec<&StoredAnomaly> = anomalies.iter().collect();
+
+        let message = notifier.message(&refs);
+
+        let blocks = message["blocks"].as_array().unwrap();
+        assert_eq!(blocks.len(), MAX_BLOCKS_PER_MESSAGE + 1);
+        assert_eq!(
+            blocks[MAX_BLOCKS_PER_MESSAGE]["elements"][0]["text"],
+            "… and 5 more"
+        );
+    }
+}
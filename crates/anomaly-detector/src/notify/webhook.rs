@@ -0,0 +1,1096 @@
+//! Generic webhook channel with templated bodies and custom headers, for
+//! downstream systems that need their own payload shape.
+//!
+//! Bodies are [minijinja] templates rendered with:
+//!
+//! - `anomalies`: the delivered anomalies, each with `id`, `reading_id`,
+//!   `sensor_id`, `value`, `timestamp`, `method`, `score`, `severity`,
+//!   `detected_at` and `dedup_key`, the same for every delivery of the
+//!   anomaly (see [`super::queue`]), and with storage `hints` when recent
+//!   changes to the sensor may explain it (see [`crate::hints`])
+//! - `count`: the number of anomalies
+//! - `severity`: the highest severity among them
+//! - `webhook`: the webhook's name
+//!
+//! Use the `tojson` filter to embed values in JSON safely, e.g.
+//! `{"short_description": {{ ("Sensor " ~ anomalies[0].sensor_id)|tojson }}}`.
+//! Without a template the body is `{"count": .., "anomalies": [..]}`.
+//!
+//! With storage configured every request goes through an outbox: it is
+//! stored before the first attempt, and a request that fails with a network
+//! error, a timeout, 429 or a 5xx response is retried after `backoff_seconds`,
+//! doubling up to an hour, until `max_attempts` attempts were made. Other
+//! responses fail it at once. Each request carries its outbox id in an
+//! `X-Delivery-Id` header, the same across retries, and
+//! `GET /webhooks/{name}/deliveries` lists them. Without storage a request is
+//! attempted once.
+//!
+//! Webhooks of the config file may be joined by others at runtime:
+//! `POST /webhooks` takes the same fields and `DELETE /webhooks/{name}`
+//! removes one. With storage those added are kept beside the outbox and
+//! registered again on start, so their queued deliveries still find them;
+//! without, they last until the next restart, as does the removal of a
+//! webhook of the config file. `GET /webhooks` lists them without their
+//! headers, which may hold credentials.
+//!
+//! While a webhook's [circuit](super::breaker) is open, its queued
+//! deliveries wait for it to close without using up attempts, and without
+//! storage its requests are dropped.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use minijinja::Environment;
+use minijinja::value::Serde;
+use reqwest::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::breaker::{Breaker, BreakerConfig};
+use super::{Notifier, Severity, routed};
+use crate::AppState;
+use crate::audit::{self, AuditEntry, Change};
+use crate::leader::Lease;
+use crate::storage::{DeliveryAttempt, Storage, StoredAnomaly, StoredDelivery};
+use crate::{hints, schemas};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the outbox is checked for retries that are due.
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Deliveries retried per check.
+const RETRY_BATCH_SIZE: i64 = 100;
+
+/// Longest delay between two attempts.
+const MAX_BACKOFF_SECONDS: u64 = 3_600;
+
+/// Header carrying the outbox id of a request.
+const DELIVERY_ID_HEADER: &str = "x-delivery-id";
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1_000;
+
+#[derive(Deserialize, Serialize)]
+pub struct WebhookConfig {
+    name: String,
+    url: String,
+    #[serde(default = "default_method")]
+    method: String,
+    /// Sent with every request; `Content-Type` defaults to `application/json`.
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+    #[serde(default)]
+    body_template: Option<String>,
+    /// Severities delivered to this webhook; all when omitted.
+    #[serde(default)]
+    severities: Option<Vec<Severity>>,
+    /// Attempts made before a request is given up on.
+    #[serde(default = "default_max_attempts")]
+    max_attempts: u32,
+    /// Delay before the first retry.
+    #[serde(default = "default_backoff_seconds")]
+    backoff_seconds: u64,
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_backoff_seconds() -> u64 {
+    30
+}
+
+struct Webhook {
+    name: String,
+    url: String,
+    method: reqwest::Method,
+    headers: HeaderMap,
+    templated: bool,
+    severities: Option<Vec<Severity>>,
+    max_attempts: u32,
+    backoff_seconds: u64,
+    breaker: Breaker,
+}
+
+impl Webhook {
+    fn summary(&self) -> WebhookSummary {
+        WebhookSummary {
+            name: self.name.clone(),
+            url: self.url.clone(),
+            method: self.method.to_string(),
+            templated: self.templated,
+            severities: self.severities.clone(),
+            max_attempts: self.max_attempts,
+            backoff_seconds: self.backoff_seconds,
+        }
+    }
+
+    /// Seconds to wait after the `attempts`th failed attempt.
+    fn backoff(&self, attempts: u32) -> i64 {
+        let factor = 2u64.saturating_pow(attempts.saturating_sub(1));
+        self.backoff_seconds
+            .saturating_mul(factor)
+            .min(MAX_BACKOFF_SECONDS) as i64
+    }
+}
+
+/// A configured webhook as the API shows it: without its headers, which
+/// may hold credentials, or its body template.
+#[derive(Debug, Serialize)]
+pub struct WebhookSummary {
+    name: String,
+    url: String,
+    method: String,
+    templated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severities: Option<Vec<Severity>>,
+    max_attempts: u32,
+    backoff_seconds: u64,
+}
+
+/// Why a request failed.
+struct Failure {
+    error: String,
+    status_code: Option<u16>,
+    retryable: bool,
+}
+
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    templates: RwLock<Environment<'static>>,
+    webhooks: RwLock<Vec<Arc<Webhook>>>,
+    breakers: BreakerConfig,
+    /// Where requests wait to be delivered or retried.
+    outbox: Option<Storage>,
+}
+
+impl WebhookNotifier {
+    pub fn new(configs: Vec<WebhookConfig>) -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| e.to_string())?;
+        let notifier = Self {
+            client,
+            templates: RwLock::new(Environment::new()),
+            webhooks: RwLock::default(),
+            breakers: BreakerConfig::default(),
+            outbox: None,
+        };
+        for config in configs {
+            let name = config.name.clone();
+            if notifier.register(config)?.is_none() {
+                return Err(format!("webhook {:?}: duplicate webhook name", name));
+            }
+        }
+        Ok(notifier)
+    }
+
+    /// Sends requests through an outbox in `storage`, so failed ones are
+    /// retried.
+    pub fn with_outbox(mut self, storage: Storage) -> Self {
+        self.outbox = Some(storage);
+        self
+    }
+
+    /// Puts each webhook, and those registered later, behind a circuit
+    /// breaker configured as `config`.
+    pub fn with_breakers(mut self, config: BreakerConfig) -> Self {
+        self.breakers = config;
+        for webhook in self.webhooks.get_mut().unwrap() {
+            let webhook = Arc::get_mut(webhook).expect("webhooks are not shared yet");
+            webhook.breaker = Breaker::new(format!("webhook {}", webhook.name), config);
+        }
+        self
+    }
+
+    pub fn has_webhook(&self, name: &str) -> bool {
+        self.webhooks.read().unwrap().iter().any(|w| w.name == name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.read().unwrap().is_empty()
+    }
+
+    /// The configured webhooks, in the order they were added.
+    pub fn list(&self) -> Vec<WebhookSummary> {
+        let webhooks = self.webhooks.read().unwrap();
+        webhooks.iter().map(|webhook| webhook.summary()).collect()
+    }
+
+    /// Adds a webhook, unless one of the same name is configured: `None`
+    /// then.
+    pub fn register(&self, config: WebhookConfig) -> Result<Option<WebhookSummary>, String> {
+        let context = |e: &dyn std::fmt::Display| format!("webhook {:?}: {}", config.name, e);
+
+        let mut webhooks = self.webhooks.write().unwrap();
+        if webhooks.iter().any(|w| w.name == config.name) {
+            return Ok(None);
+        }
+        url::Url::parse(&config.url).map_err(|e| context(&e))?;
+        if config.max_attempts == 0 || config.backoff_seconds == 0 {
+            return Err(context(
+                &"max_attempts and backoff_seconds must be positive",
+            ));
+        }
+        let method = config
+            .method
+            .to_uppercase()
+            .parse::<reqwest::Method>()
+            .map_err(|e| context(&e))?;
+
+        let mut headers = HeaderMap::new();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        for (name, value) in &config.headers {
+            let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| context(&e))?;
+            let value = HeaderValue::from_str(value).map_err(|e| context(&e))?;
+            headers.insert(name, value);
+        }
+
+        let templated = config.body_template.is_some();
+        if let Some(template) = config.body_template {
+            self.templates
+                .write()
+                .unwrap()
+                .add_template_owned(config.name.clone(), template)
+                .map_err(|e| context(&e))?;
+        }
+
+        let webhook = Webhook {
+            breaker: Breaker::new(format!("webhook {}", config.name), self.breakers),
+            name: config.name,
+            url: config.url,
+            method,
+            headers,
+            templated,
+            severities: config.severities,
+            max_attempts: config.max_attempts,
+            backoff_seconds: config.backoff_seconds,
+        };
+        let summary = webhook.summary();
+        webhooks.push(Arc::new(webhook));
+        Ok(Some(summary))
+    }
+
+    /// Registers the webhooks added at runtime that the outbox's storage
+    /// keeps, skipping those the config file now names too.
+    pub async fn restore(&self) {
+        let Some(storage) = &self.outbox else {
+            return;
+        };
+        let stored = match storage.webhook_configs().await {
+            Ok(stored) => stored,
+            Err(e) => {
+                eprintln!("Error: Failed to load the webhooks added at runtime: {}", e);
+                return;
+            }
+        };
+        for (name, config) in stored {
+            let registered = serde_json::from_str(&config)
+                .map_err(|e| e.to_string())
+                .and_then(|config| self.register(config));
+            match registered {
+                Ok(Some(_)) => {}
+                Ok(None) => println!(
+                    "Notify: webhook {} of the config file replaces the one added at runtime",
+                    name
+                ),
+                Err(e) => eprintln!("Error: Skipping stored webhook {}: {}", name, e),
+            }
+        }
+    }
+
+    /// Removes the named webhook, returning it if it was configured. Its
+    /// queued deliveries fail when they are next due.
+    pub fn remove(&self, name: &str) -> Option<WebhookSummary> {
+        let mut webhooks = self.webhooks.write().unwrap();
+        let index = webhooks.iter().position(|w| w.name == name)?;
+        let webhook = webhooks.remove(index);
+        if webhook.templated {
+            self.templates.write().unwrap().remove_template(name);
+        }
+        Some(webhook.summary())
+    }
+
+    /// Sends one request per webhook (or only the named one) with the
+    /// anomalies routed to it.
+    pub async fn notify(&self, anomalies: &[StoredAnomaly], only: Option<&str>) {
+        let hints = match &self.outbox {
+            Some(storage) => hints::for_anomalies(storage, anomalies)
+                .await
+                .unwrap_or_else(|e| {
+                    eprintln!("Error: Failed to load root-cause hints: {}", e);
+                    HashMap::new()
+                }),
+            None => HashMap::new(),
+        };
+        let webhooks = self.webhooks.read().unwrap().clone();
+        for webhook in &webhooks {
+            if only.is_some_and(|name| name != webhook.name) {
+                continue;
+            }
+            let anomalies = routed(anomalies, webhook.severities.as_deref());
+            if anomalies.is_empty() {
+                continue;
+            }
+
+            let body = match self.body(webhook, &anomalies, &hints) {
+                Ok(body) => body,
+                Err(e) => {
+                    eprintln!(
+                        "Error: Failed to render body for webhook {}: {}",
+                        webhook.name, e
+                    );
+                    continue;
+                }
+            };
+
+            let count = anomalies.len() as i64;
+            let queued = match &self.outbox {
+                Some(storage) => {
+                    let retry_in = webhook.backoff(1);
+                    match storage
+                        .queue_webhook_delivery(&webhook.name, &body, count, retry_in)
+                        .await
+                    {
+                        Ok(id) => Some((storage, id)),
+                        Err(e) => {
+                            eprintln!(
+                                "Error: Failed to queue webhook {}, sending without retries: {}",
+                                webhook.name, e
+                            );
+                            None
+                        }
+                    }
+                }
+                None => None,
+            };
+            match queued {
+                Some((storage, id)) => self.attempt(storage, webhook, id, &body, count, 0).await,
+                None if !webhook.breaker.allow() => eprintln!(
+                    "Error: Circuit of webhook {} is open, dropping {} anomalies",
+                    webhook.name, count
+                ),
+                None => match self.send(webhook, &body, None).await {
+                    Ok(_) => println!(
+                        "Notify: delivered {} anomalies to webhook {}",
+                        count, webhook.name
+                    ),
+                    Err(failure) => eprintln!(
+                        "Error: Failed to deliver webhook {}: {}",
+                        webhook.name, failure.error
+                    ),
+                },
+            }
+        }
+    }
+
+    /// Makes one request and reports its outcome to the webhook's circuit
+    /// breaker, for which only failures worth retrying count: a webhook
+    /// that answers otherwise is up.
+    async fn send(
+        &self,
+        webhook: &Webhook,
+        body: &str,
+        delivery_id: Option<i64>,
+    ) -> Result<u16, Failure> {
+        let result = self.request(webhook, body, delivery_id).await;
+        match &result {
+            Err(failure) if failure.retryable => webhook.breaker.failed(),
+            _ => webhook.breaker.succeeded(),
+        }
+        result
+    }
+
+    async fn request(
+        &self,
+        webhook: &Webhook,
+        body: &str,
+        delivery_id: Option<i64>,
+    ) -> Result<u16, Failure> {
+        let mut request = self
+            .client
+            .request(webhook.method.clone(), &webhook.url)
+            .headers(webhook.headers.clone())
+            .body(body.to_string());
+        if let Some(id) = delivery_id {
+            request = request.header(DELIVERY_ID_HEADER, id);
+        }
+        let response = request.send().await.map_err(|e| Failure {
+            error: e.to_string(),
+            status_code: None,
+            retryable: true,
+        })?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(status.as_u16());
+        }
+        Err(Failure {
+            error: format!("HTTP {}", status),
+            status_code: Some(status.as_u16()),
+            retryable: status.is_server_error()
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT,
+        })
+    }
+
+    /// Makes one attempt at a queued delivery that `attempts` were already
+    /// made at, and records the outcome; while the webhook's circuit is
+    /// open, puts the delivery off until it may close instead.
+    async fn attempt(
+        &self,
+        storage: &Storage,
+        webhook: &Webhook,
+        id: i64,
+        body: &str,
+        anomalies: i64,
+        attempts: u32,
+    ) {
+        if !webhook.breaker.allow() {
+            let retry_in = webhook.breaker.retry_in().as_secs().max(1) as i64;
+            println!(
+                "Notify: circuit of webhook {} is open, delivery {} waits {}s",
+                webhook.name, id, retry_in
+            );
+            if let Err(e) = storage.defer_webhook_delivery(id, retry_in).await {
+                eprintln!(
+                    "Error: Failed to put off delivery {} to webhook {}: {}",
+                    id, webhook.name, e
+                );
+            }
+            return;
+        }
+        let attempts = attempts + 1;
+        let result = self.send(webhook, body, Some(id)).await;
+        let attempt = match &result {
+            Ok(status_code) => {
+                println!(
+                    "Notify: delivered {} anomalies to webhook {}",
+                    anomalies, webhook.name
+                );
+                DeliveryAttempt {
+                    status: "delivered",
+                    error: None,
+                    status_code: Some(i64::from(*status_code)),
+                    retry_in: 0,
+                }
+            }
+            Err(failure) if failure.retryable && attempts < webhook.max_attempts => {
+                let retry_in = webhook.backoff(attempts);
+                eprintln!(
+                    "Warning: Failed to deliver webhook {} (attempt {} of {}), retrying in {}s: {}",
+                    webhook.name, attempts, webhook.max_attempts, retry_in, failure.error
+                );
+                DeliveryAttempt {
+                    status: "pending",
+                    error: Some(&failure.error),
+                    status_code: failure.status_code.map(i64::from),
+                    retry_in,
+                }
+            }
+            Err(failure) => {
+                eprintln!(
+                    "Error: Failed to deliver webhook {} after {} attempts: {}",
+                    webhook.name, attempts, failure.error
+                );
+                DeliveryAttempt {
+                    status: "failed",
+                    error: Some(&failure.error),
+                    status_code: failure.status_code.map(i64::from),
+                    retry_in: 0,
+                }
+            }
+        };
+        if let Err(e) = storage.record_delivery_attempt(id, &attempt).await {
+            eprintln!(
+                "Error: Failed to record delivery {} to webhook {}: {}",
+                id, webhook.name, e
+            );
+        }
+    }
+
+    /// Retries the queued deliveries that are due.
+    pub async fn retry_due(&self) -> Result<(), sqlx::Error> {
+        let Some(storage) = &self.outbox else {
+            return Ok(());
+        };
+        for delivery in storage.due_webhook_deliveries(RETRY_BATCH_SIZE).await? {
+            let webhook = self
+                .webhooks
+                .read()
+                .unwrap()
+                .iter()
+                .find(|w| w.name == delivery.webhook)
+                .cloned();
+            let Some(webhook) = webhook else {
+                let attempt = DeliveryAttempt {
+                    status: "failed",
+                    error: Some("webhook is no longer configured"),
+                    status_code: None,
+                    retry_in: 0,
+                };
+                storage
+                    .record_delivery_attempt(delivery.id, &attempt)
+                    .await?;
+                continue;
+            };
+            let attempts = u32::try_from(delivery.attempts).unwrap_or(u32::MAX);
+            self.attempt(
+                storage,
+                &webhook,
+                delivery.id,
+                &delivery.body,
+                delivery.anomalies,
+                attempts,
+            )
+            .await;
+        }
+        Ok(())
+    }
+
+    fn body(
+        &self,
+        webhook: &Webhook,
+        anomalies: &[&StoredAnomaly],
+        hints: &HashMap<i64, Vec<AuditEntry>>,
+    ) -> Result<String, String> {
+        let payload: Vec<serde_json::Value> = anomalies
+            .iter()
+            .map(|anomaly| {
+                let mut value = json!(anomaly);
+                value["dedup_key"] = json!(super::queue::dedup_key(anomaly));
+                if let Some(hints) = hints.get(&anomaly.id) {
+                    value["hints"] = json!(hints);
+                }
+                schemas::check_alert(&value);
+                value
+            })
+            .collect();
+        if !webhook.templated {
+            return Ok(json!({ "count": anomalies.len(), "anomalies": payload }).to_string());
+        }
+
+        let severity = anomalies
+            .iter()
+            .map(|a| a.severity)
+            .max()
+            .unwrap_or_default();
+        let context = json!({
+            "anomalies": payload,
+            "count": anomalies.len(),
+            "severity": severity.label(),
+            "webhook": webhook.name,
+        });
+        self.templates
+            .read()
+            .unwrap()
+            .get_template(&webhook.name)
+            .and_then(|template| template.render(Serde(context)))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Retries due webhook deliveries every few seconds.
+pub fn spawn(notifier: Arc<Notifier>) {
+    tokio::spawn(async move {
+        let Some(webhooks) = &notifier.webhooks else {
+            return;
+        };
+        let Some(outbox) = &webhooks.outbox else {
+            return;
+        };
+        let lease = Lease::new(outbox.clone(), "webhook-retries", RETRY_INTERVAL);
+        let mut ticker = tokio::time::interval(RETRY_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if !lease.acquire().await {
+                continue;
+            }
+            if let Err(e) = webhooks.retry_due().await {
+                eprintln!("Error: Webhook retries failed: {}", e);
+            }
+        }
+    });
+}
+
+fn notifier(state: &AppState) -> Result<&Notifier, (StatusCode, String)> {
+    state.notifier.as_deref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "notifications are not configured; set ANOMALY_NOTIFY_CONFIG".to_string(),
+    ))
+}
+
+fn webhooks(state: &AppState) -> Result<&WebhookNotifier, (StatusCode, String)> {
+    notifier(state)?.webhooks.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "webhooks are not configured; see ANOMALY_NOTIFY_CONFIG".to_string(),
+    ))
+}
+
+/// Lists the configured webhooks.
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookSummary>>, (StatusCode, String)> {
+    Ok(Json(webhooks(&state)?.list()))
+}
+
+/// Adds a webhook, kept in the outbox's storage when there is one.
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<WebhookConfig>,
+) -> Result<(StatusCode, Json<WebhookSummary>), (StatusCode, String)> {
+    let webhooks = webhooks(&state)?;
+    let name = payload.name.clone();
+    let config = serde_json::to_string(&payload)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let summary = webhooks
+        .register(payload)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?
+        .ok_or_else(|| {
+            (
+                StatusCode::CONFLICT,
+                format!("webhook {} already exists", name),
+            )
+        })?;
+    if let Some(outbox) = &webhooks.outbox
+        && let Err(e) = outbox.save_webhook_config(&name, &config).await
+    {
+        webhooks.remove(&name);
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to store webhook {}: {}", name, e),
+        ));
+    }
+    if let Some(storage) = &state.storage {
+        let change = Change::new("webhook", Some(summary.name.clone()), None, Some(&summary));
+        audit::record(storage, &audit::actor(&headers), [change]).await;
+    }
+    Ok((StatusCode::CREATED, Json(summary)))
+}
+
+/// Removes a webhook, unless routing or escalation still sends to it, and
+/// the stored one of that name.
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let removed = notifier(&state)?
+        .remove_webhook(&name)
+        .map_err(|e| (StatusCode::CONFLICT, e))?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("webhook {} is not configured", name),
+        ))?;
+    if let Some(outbox) = &webhooks(&state)?.outbox
+        && let Err(e) = outbox.delete_webhook_config(&name).await
+    {
+        eprintln!("Error: Failed to delete stored webhook {}: {}", name, e);
+    }
+    if let Some(storage) = &state.storage {
+        let change = Change::new("webhook", Some(name), Some(&removed), None);
+        audit::record(storage, &audit::actor(&headers), [change]).await;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Default, Deserialize)]
+pub struct DeliveriesQuery {
+    /// `pending`, `delivered` or `failed`.
+    status: Option<String>,
+    /// Continues a listing from the smallest `id` of the previous page.
+    before_id: Option<i64>,
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Delivery {
+    id: i64,
+    status: String,
+    attempts: i64,
+    anomalies: i64,
+    created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_attempt_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delivered_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_status_code: Option<i64>,
+    body: String,
+}
+
+impl From<StoredDelivery> for Delivery {
+    fn from(stored: StoredDelivery) -> Self {
+        Delivery {
+            next_attempt_at: (stored.status == "pending").then_some(stored.next_attempt_at),
+            id: stored.id,
+            status: stored.status,
+            attempts: stored.attempts,
+            anomalies: stored.anomalies,
+            created_at: stored.created_at,
+            delivered_at: stored.delivered_at,
+            last_error: stored.last_error,
+            last_status_code: stored.last_status_code,
+            body: stored.body,
+        }
+    }
+}
+
+/// Lists a webhook's deliveries from the outbox, newest first.
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<DeliveriesQuery>,
+) -> Result<Json<Vec<Delivery>>, (StatusCode, String)> {
+    let webhooks = webhooks(&state)?;
+    let storage = webhooks.outbox.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    if !webhooks.has_webhook(&name) {
+        return Err((
+            StatusCode::NOT_FOUND,
+            format!("webhook {} is not configured", name),
+        ));
+    }
+    if let Some(status) = &query.status
+        && !["pending", "delivered", "failed"].contains(&status.as_str())
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "status must be pending, delivered or failed, got {:?}",
+                status
+            ),
+        ));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let deliveries = storage
+        .webhook_deliveries(&name, query.status.as_deref(), query.before_id, limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(deliveries.into_iter().map(Delivery::from).collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::testing::{anomaly, capture_server};
+    use crate::storage::testing::{expire_backoff, in_memory};
+    use std::sync::Mutex;
+
+    fn notifier(configs: serde_json::Value) -> WebhookNotifier {
+        WebhookNotifier::new(serde_json::from_value(configs).unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_templated_body_and_headers() {
+        let (url, captured) = capture_server().await;
+        let notifier = notifier(json!([{
+            "name": "servicenow",
+            "url": format!("{}/api/now/table/incident", url),
+            "headers": { "Authorization": "Bearer s3cret", "X-Source": "factory" },
+            "body_template": r#"{"short_description": {{ (count ~ " " ~ severity ~ " anomalies")|tojson }},
+                "sensors": [{% for a in anomalies %}{{ a.sensor_id }}{% if not loop.last %}, {% endif %}{% endfor %}]}"#,
+            "severities": ["high", "critical"],
+        }]));
+
+        notifier
+            .notify(
+                &[
+                    anomaly(1, Severity::Critical),
+                    anomaly(2, Severity::Medium),
+                    anomaly(3, Severity::High),
+                ],
+                None,
+            )
+            .await;
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.len(), 1);
+        let (path, headers, body) = &captured[0];
+        assert_eq!(path, "/api/now/table/incident");
+        assert_eq!(headers["authorization"], "Bearer s3cret");
+        assert_eq!(headers["x-source"], "factory");
+        assert_eq!(headers["content-type"], "application/json");
+        assert_eq!(body["short_description"], "2 critical anomalies");
+        assert_eq!(body["sensors"], json!([1, 3]));
+    }
+
+    #[tokio::test]
+    async fn test_default_body() {
+        let (url, captured) = capture_server().await;
+        let notifier = notifier(json!([{ "name": "plain", "url": url }]));
+
+        notifier.notify(&[anomaly(4, Severity::Medium)], None).await;
+
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured[0].2["count"], 1);
+        assert_eq!(captured[0].2["anomalies"][0]["sensor_id"], 4);
+    }
+
+    /// Local server answering with `statuses` in turn, then 200, and counting
+    /// the requests per delivery id. Returns its base URL.
+    async fn flaky_server(statuses: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let seen: Arc<Mutex<Vec<String>>> = Arc::default();
+        let statuses = Arc::new(Mutex::new(statuses.into_iter()));
+        let app = axum::Router::new().fallback({
+            let seen = seen.clone();
+            move |headers: axum::http::HeaderMap| async move {
+                let id = headers
+                    .get(DELIVERY_ID_HEADER)
+                    .and_then(|id| id.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                seen.lock().unwrap().push(id);
+                let status = statuses.lock().unwrap().next().unwrap_or(200);
+                StatusCode::from_u16(status).unwrap()
+            }
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (url, seen)
+    }
+
+    async fn deliveries(storage: &Storage, webhook: &str) -> Vec<StoredDelivery> {
+        storage
+            .webhook_deliveries(webhook, None, None, 10)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried_after_backoff() {
+        let (url, seen) = flaky_server(vec![503]).await;
+        let storage = in_memory().await;
+        let notifier = notifier(json!([{ "name": "ops", "url": url, "max_attempts": 3 }]))
+            .with_outbox(storage.clone());
+
+        notifier.notify(&[anomaly(1, Severity::High)], None).await;
+        let queued = deliveries(&storage, "ops").await;
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].status, "pending");
+        assert_eq!(queued[0].attempts, 1);
+        assert_eq!(queued[0].last_status_code, Some(503));
+
+        notifier.retry_due().await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        expire_backoff(&storage).await;
+        notifier.retry_due().await.unwrap();
+        let delivered = deliveries(&storage, "ops").await;
+        assert_eq!(delivered[0].status, "delivered");
+        assert_eq!(delivered[0].attempts, 2);
+        assert!(delivered[0].delivered_at.is_some());
+        let id = queued[0].id.to_string();
+        assert_eq!(*seen.lock().unwrap(), vec![id.clone(), id]);
+    }
+
+    #[tokio::test]
+    async fn test_delivery_fails_on_client_error_or_last_attempt() {
+        let (rejecting, _) = flaky_server(vec![400]).await;
+        let (down, _) = flaky_server(vec![503, 502]).await;
+        let storage = in_memory().await;
+        let notifier = notifier(json!([
+            { "name": "rejecting", "url": rejecting },
+            { "name": "down", "url": down, "max_attempts": 2 },
+        ]))
+        .with_outbox(storage.clone());
+
+        notifier.notify(&[anomaly(1, Severity::High)], None).await;
+        expire_backoff(&storage).await;
+        notifier.retry_due().await.unwrap();
+
+        let rejected = deliveries(&storage, "rejecting").await;
+        assert_eq!(
+            (rejected[0].status.as_str(), rejected[0].attempts),
+            ("failed", 1)
+        );
+        let exhausted = deliveries(&storage, "down").await;
+        assert_eq!(
+            (exhausted[0].status.as_str(), exhausted[0].attempts),
+            ("failed", 2)
+        );
+        assert_eq!(
+            exhausted[0].last_error.as_deref(),
+            Some("HTTP 502 Bad Gateway")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_open_circuit_puts_deliveries_off_without_attempts() {
+        let (down, seen) = flaky_server(vec![503, 503, 503]).await;
+        let (up, _) = flaky_server(Vec::new()).await;
+        let storage = in_memory().await;
+        let notifier = notifier(json!([
+            { "name": "down", "url": down },
+            { "name": "up", "url": up },
+        ]))
+        .with_breakers(BreakerConfig {
+            failures: 2,
+            open_seconds: 60,
+        })
+        .with_outbox(storage.clone());
+
+        for sensor_id in 1..=3 {
+            notifier
+                .notify(&[anomaly(sensor_id, Severity::High)], None)
+                .await;
+        }
+        // The third delivery finds the circuit open and waits unattempted.
+        assert_eq!(seen.lock().unwrap().len(), 2);
+        expire_backoff(&storage).await;
+        notifier.retry_due().await.unwrap();
+        assert_eq!(seen.lock().unwrap().len(), 2);
+
+        let waiting = deliveries(&storage, "down").await;
+        let attempts: Vec<(&str, i64)> = waiting
+            .iter()
+            .map(|d| (d.status.as_str(), d.attempts))
+            .collect();
+        assert_eq!(attempts, [("pending", 0), ("pending", 1), ("pending", 1)]);
+        let delivered = deliveries(&storage, "up").await;
+        assert_eq!(delivered.len(), 3);
+        assert!(delivered.iter().all(|d| d.status == "delivered"));
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let notifier = notifier(json!([{ "name": "a", "url": "http://x", "backoff_seconds": 60 }]));
+        let webhook = notifier.webhooks.read().unwrap()[0].clone();
+        let delays: Vec<i64> = [1, 2, 3, 10].map(|n| webhook.backoff(n)).to_vec();
+        assert_eq!(delays, vec![60, 120, 240, 3_600]);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_are_added_and_removed_at_runtime() {
+        let notifier = Notifier {
+            webhooks: Some(notifier(json!([{ "name": "ops", "url": "http://ops" }]))),
+            ..Notifier::default()
+        };
+        notifier
+            .set_routing(
+                serde_json::from_value(json!({
+                    "rules": [{ "name": "a", "channels": ["webhook:ops"] }],
+                }))
+                .unwrap(),
+            )
+            .unwrap();
+        let state = AppState {
+            notifier: Some(Arc::new(notifier)),
+            ..AppState::default()
+        };
+        let create = |config: serde_json::Value| {
+            create_webhook(
+                State(state.clone()),
+                HeaderMap::new(),
+                Json(serde_json::from_value(config).unwrap()),
+            )
+        };
+        let delete = |name: &str| {
+            delete_webhook(
+                State(state.clone()),
+                HeaderMap::new(),
+                Path(name.to_string()),
+            )
+        };
+
+        let (status, Json(created)) = create(json!({
+            "name": "teams",
+            "url": "http://teams",
+            "headers": { "X-Api-Key": "s3cret" },
+            "severities": ["critical"],
+        }))
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created.max_attempts, 5);
+        let duplicate = create(json!({ "name": "teams", "url": "http://other" })).await;
+        assert_eq!(duplicate.unwrap_err().0, StatusCode::CONFLICT);
+        let invalid = create(json!({ "name": "bad", "url": "not a url" })).await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let Json(listed) = list_webhooks(State(state.clone())).await.unwrap();
+        let listed = serde_json::to_value(listed).unwrap();
+        assert_eq!(listed[1]["name"], "teams");
+        assert_eq!(listed[1]["severities"], json!(["critical"]));
+        assert!(!listed.to_string().contains("s3cret"));
+
+        assert_eq!(delete("ops").await.unwrap_err().0, StatusCode::CONFLICT);
+        assert_eq!(delete("teams").await.unwrap(), StatusCode::NO_CONTENT);
+        assert_eq!(delete("teams").await.unwrap_err().0, StatusCode::NOT_FOUND);
+        let Json(left) = list_webhooks(State(state.clone())).await.unwrap();
+        assert_eq!(left.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_added_at_runtime_survive_a_restart() {
+        let storage = in_memory().await;
+        let start = || {
+            notifier(json!([{ "name": "ops", "url": "http://ops" }])).with_outbox(storage.clone())
+        };
+        let state = AppState {
+            notifier: Some(Arc::new(Notifier {
+                webhooks: Some(start()),
+                ..Notifier::default()
+            })),
+            ..AppState::default()
+        };
+        for name in ["teams", "chat"] {
+            let config = json!({ "name": name, "url": format!("http://{}", name) });
+            let (status, _) = create_webhook(
+                State(state.clone()),
+                HeaderMap::new(),
+                Json(serde_json::from_value(config).unwrap()),
+            )
+            .await
+            .unwrap();
+            assert_eq!(status, StatusCode::CREATED);
+        }
+        delete_webhook(
+            State(state.clone()),
+            HeaderMap::new(),
+            Path("chat".to_string()),
+        )
+        .await
+        .unwrap();
+
+        let restarted = start();
+        restarted.restore().await;
+        let names: Vec<String> = restarted.list().into_iter().map(|w| w.name).collect();
+        assert_eq!(names, ["ops", "teams"]);
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let invalid = [
+            json!([{ "name": "a", "url": "not a url" }]),
+            json!([{ "name": "a", "url": "http://x", "headers": { "bad header": "v" } }]),
+            json!([{ "name": "a", "url": "http://x", "body_template": "{{ unclosed" }]),
+            json!([{ "name": "a", "url": "http://x" }, { "name": "a", "url": "http://y" }]),
+            json!([{ "name": "a", "url": "http://x", "max_attempts": 0 }]),
+        ];
+        for config in invalid {
+            let configs: Vec<WebhookConfig> = serde_json::from_value(config).unwrap();
+            assert!(WebhookNotifier::new(configs).is_err());
+        }
+    }
+}
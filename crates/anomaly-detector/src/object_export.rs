@@ -0,0 +1,387 @@
+//! Writes full result sets to object storage as CSV or Parquet.
+//!
+//! The target is configured with `ANOMALY_EXPORT_URL` (`s3://bucket/prefix`
+//! or `file:///path`). Any other `ANOMALY_EXPORT_*` variable is passed to the
+//! store with the prefix stripped and lowercased, so credentials are supplied
+//! as e.g. `ANOMALY_EXPORT_AWS_ACCESS_KEY_ID` and `ANOMALY_EXPORT_AWS_ENDPOINT`.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use arrow_schema::FieldRef;
+use futures_util::{Stream, StreamExt};
+use object_store::{ObjectStore, buffered::BufWriter, path::Path};
+use parquet::arrow::ArrowWriter;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_arrow::schema::{SchemaLike, TracingOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::export;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+/// Asks for a result set to be written to object storage instead of being
+/// returned in the response body.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    /// Object name below the configured prefix; generated when absent.
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportReceipt {
+    pub url: String,
+    pub format: ExportFormat,
+    pub rows: usize,
+}
+
+#[derive(Clone)]
+pub struct ObjectExporter {
+    store: Arc<dyn ObjectStore>,
+    prefix: Path,
+    base_url: String,
+}
+
+static SEQUENCE: AtomicU64 = AtomicU64::new(0);
+
+impl ObjectExporter {
+    pub fn from_url(url: &str, options: &[(String, String)]) -> Result<Self, String> {
+        let parsed = url::Url::parse(url).map_err(|e| format!("invalid export URL: {}", e))?;
+        let (store, prefix) = object_store::parse_url_opts(&parsed, options.iter().cloned())
+            .map_err(|e| format!("invalid export target: {}", e))?;
+        Ok(Self::new(Arc::from(store), prefix, url))
+    }
+
+    fn new(store: Arc<dyn ObjectStore>, prefix: Path, url: &str) -> Self {
+        let base_url = match url.find("://") {
+            Some(scheme_end) => {
+                let after_scheme = &url[scheme_end + 3..];
+                let authority = after_scheme.split('/').next().unwrap_or("");
+                format!("{}://{}", &url[..scheme_end], authority)
+            }
+            None => url.to_string(),
+        };
+        Self {
+            store,
+            prefix,
+            base_url,
+        }
+    }
+
+    /// Location of an object, generating a unique name from `kind` unless the
+    /// request names one.
+    fn location(&self, kind: &str, request: &ExportRequest) -> Result<Path, String> {
+        let name = match &request.key {
+            Some(key) => key.trim_start_matches('/').to_string(),
+            None => {
+                let millis = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or(0);
+                let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+                format!("{}-{}-{}.{}", kind, millis, seq, request.format.extension())
+            }
+        };
+
+        let mut location = self.prefix.clone();
+        for part in name.split('/') {
+            let part = object_store::path::PathPart::parse(part)
+                .map_err(|e| format!("invalid export key {:?}: {}", name, e))?;
+            location = location.clone().join(part);
+        }
+        Ok(location)
+    }
+
+    /// Writes `rows` as one object.
+    pub async fn export<T>(
+        &self,
+        kind: &str,
+        request: &ExportRequest,
+        rows: Vec<T>,
+    ) -> Result<ExportReceipt, String>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        self.export_pages(kind, request, futures_util::stream::iter([Ok(rows)]))
+            .await
+    }
+
+    /// Writes a stream of row pages as one object via a multipart upload, so
+    /// only one page is held in memory at a time.
+    pub async fn export_pages<T, S>(
+        &self,
+        kind: &str,
+        request: &ExportRequest,
+        pages: S,
+    ) -> Result<ExportReceipt, String>
+    where
+        T: Serialize + DeserializeOwned,
+        S: Stream<Item = Result<Vec<T>, String>>,
+    {
+        let location = self.location(kind, request)?;
+        let mut upload = BufWriter::new(self.store.clone(), location.clone());
+        let mut encoder = PageEncoder::<T>::new(request.format)?;
+        let mut rows = 0;
+
+        let mut pages = std::pin::pin!(pages);
+        while let Some(page) = pages.next().await {
+            let page = page?;
+            rows += page.len();
+            let bytes = encoder.encode(&page)?;
+            upload.write_all(&bytes).await.map_err(|e| e.to_string())?;
+        }
+        let bytes = encoder.finish()?;
+        upload.write_all(&bytes).await.map_err(|e| e.to_string())?;
+        upload.shutdown().await.map_err(|e| e.to_string())?;
+
+        Ok(ExportReceipt {
+            url: format!("{}/{}", self.base_url, location),
+            format: request.format,
+            rows,
+        })
+    }
+}
+
+/// Incremental encoder that hands back the bytes produced for each page.
+enum PageEncoder<T> {
+    Csv {
+        first: bool,
+        _rows: std::marker::PhantomData<T>,
+    },
+    Parquet {
+        /// `None` until the first page for types whose schema cannot be
+        /// traced from the type alone, like a `Severity` parsed from text.
+        writer: Option<ParquetWriter>,
+    },
+}
+
+struct ParquetWriter {
+    fields: Vec<FieldRef>,
+    writer: Box<ArrowWriter<Vec<u8>>>,
+}
+
+impl ParquetWriter {
+    fn new(fields: Vec<FieldRef>) -> Result<Self, String> {
+        let schema = Arc::new(arrow_schema::Schema::new(fields.clone()));
+        let writer = ArrowWriter::try_new(Vec::new(), schema, None).map_err(|e| e.to_string())?;
+        Ok(Self {
+            fields,
+            writer: Box::new(writer),
+        })
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> PageEncoder<T> {
+    fn new(format: ExportFormat) -> Result<Self, String> {
+        Ok(match format {
+            ExportFormat::Csv => PageEncoder::Csv {
+                first: true,
+                _rows: std::marker::PhantomData,
+            },
+            ExportFormat::Parquet => PageEncoder::Parquet {
+                writer: Vec::<FieldRef>::from_type::<T>(TracingOptions::default())
+                    .ok()
+                    .map(ParquetWriter::new)
+                    .transpose()?,
+            },
+        })
+    }
+
+    fn encode(&mut self, page: &[T]) -> Result<Vec<u8>, String> {
+        match self {
+            PageEncoder::Csv { first, .. } => {
+                // An empty page writes nothing, so the header waits for `finish`.
+                if page.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let bytes = export::encode(page, *first).map_err(|e| e.to_string())?;
+                *first = false;
+                Ok(bytes)
+            }
+            PageEncoder::Parquet { writer } => {
+                if page.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let ParquetWriter { fields, writer } = match writer {
+                    Some(writer) => writer,
+                    None => {
+                        let options = TracingOptions::default().allow_null_fields(true);
+                        let fields = Vec::<FieldRef>::from_samples(page, options)
+                            .map_err(|e| e.to_string())?;
+                        writer.insert(ParquetWriter::new(fields)?)
+                    }
+                };
+                let batch =
+                    serde_arrow::to_record_batch(fields, &page).map_err(|e| e.to_string())?;
+                writer.write(&batch).map_err(|e| e.to_string())?;
+                writer.flush().map_err(|e| e.to_string())?;
+                // The writer tracks its own offsets, so the flushed row group
+                // can be drained from the buffer and uploaded.
+                Ok(std::mem::take(writer.inner_mut()))
+            }
+        }
+    }
+
+    fn finish(self) -> Result<Vec<u8>, String> {
+        match self {
+            PageEncoder::Csv { first, .. } if first => header_only::<T>(),
+            PageEncoder::Csv { .. } => Ok(Vec::new()),
+            // Without a traced schema or any rows, the file has no columns.
+            PageEncoder::Parquet { writer } => match writer {
+                Some(writer) => writer.writer.into_inner(),
+                None => ParquetWriter::new(Vec::new())?.writer.into_inner(),
+            }
+            .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// CSV header row for `T`, derived from its field names.
+fn header_only<T: DeserializeOwned>() -> Result<Vec<u8>, String> {
+    let fields =
+        Vec::<FieldRef>::from_type::<T>(TracingOptions::default()).map_err(|e| e.to_string())?;
+    let names: Vec<&str> = fields.iter().map(|f| f.name().as_str()).collect();
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(&names).map_err(|e| e.to_string())?;
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+pub mod testing {
+    use super::*;
+    use object_store::memory::InMemory;
+
+    /// Exporter backed by an in-memory store, returning both.
+    pub fn in_memory() -> (ObjectExporter, Arc<InMemory>) {
+        let store = Arc::new(InMemory::new());
+        let exporter = ObjectExporter::new(store.clone(), Path::from("exports"), "memory://bucket");
+        (exporter, store)
+    }
+
+    pub async fn read(store: &InMemory, url: &str) -> Vec<u8> {
+        use object_store::ObjectStoreExt;
+        let path = url.trim_start_matches("memory://bucket/");
+        store
+            .get(&Path::from(path))
+            .await
+            .unwrap()
+            .bytes()
+            .await
+            .unwrap()
+            .to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::testing::*;
+    use super::*;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Row {
+        id: i64,
+        value: f64,
+        label: String,
+    }
+
+    fn rows(range: std::ops::Range<i64>) -> Vec<Row> {
+        range
+            .map(|id| Row {
+                id,
+                value: id as f64 * 1.5,
+                label: format!("row-{}", id),
+            })
+            .collect()
+    }
+
+    fn request(format: ExportFormat, key: Option<&str>) -> ExportRequest {
+        ExportRequest {
+            format,
+            key: key.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_export_csv() {
+        let (exporter, store) = in_memory();
+
+        let receipt = exporter
+            .export(
+                "test",
+                &request(ExportFormat::Csv, Some("out.csv")),
+                rows(0..2),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(receipt.url, "memory://bucket/exports/out.csv");
+        assert_eq!(receipt.rows, 2);
+        let body = String::from_utf8(read(&store, &receipt.url).await).unwrap();
+        assert_eq!(body, "id,value,label\n0,0.0,row-0\n1,1.5,row-1\n");
+    }
+
+    #[tokio::test]
+    async fn test_export_empty_csv_has_header() {
+        let (exporter, store) = in_memory();
+
+        let receipt = exporter
+            .export("test", &request(ExportFormat::Csv, None), Vec::<Row>::new())
+            .await
+            .unwrap();
+
+        assert!(receipt.url.starts_with("memory://bucket/exports/test-"));
+        assert!(receipt.url.ends_with(".csv"));
+        let body = String::from_utf8(read(&store, &receipt.url).await).unwrap();
+        assert_eq!(body, "id,value,label\n");
+    }
+
+    #[tokio::test]
+    async fn test_export_parquet_pages_round_trip() {
+        let (exporter, store) = in_memory();
+        let pages = futures_util::stream::iter([Ok(rows(0..3)), Ok(rows(3..5))]);
+
+        let receipt = exporter
+            .export_pages("test", &request(ExportFormat::Parquet, None), pages)
+            .await
+            .unwrap();
+        assert_eq!(receipt.rows, 5);
+
+        let bytes = axum::body::Bytes::from(read(&store, &receipt.url).await);
+        let reader = ParquetRecordBatchReaderBuilder::try_new(bytes)
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut decoded = Vec::new();
+        for batch in reader {
+            decoded.extend(serde_arrow::from_record_batch::<Vec<Row>>(&batch.unwrap()).unwrap());
+        }
+        assert_eq!(decoded, rows(0..5));
+    }
+
+    #[test]
+    fn test_from_url_local_filesystem() {
+        let dir = std::env::temp_dir();
+        let url = format!("file://{}", dir.display());
+        let exporter = ObjectExporter::from_url(&url, &[]).unwrap();
+        assert_eq!(exporter.base_url, "file://");
+        assert!(ObjectExporter::from_url("not a url", &[]).is_err());
+    }
+}
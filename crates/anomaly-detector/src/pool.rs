@@ -0,0 +1,132 @@
+//! Buffers reused across requests by the hot handlers, `/analyze` and batch
+//! series, instead of allocated afresh for each one.
+//!
+//! Each thread keeps [`Scratch`] vectors for detection's working data and a
+//! `BytesMut` that JSON responses are written into. A response body is split
+//! off the buffer, so the next one is written after it in the same
+//! allocation, and the space is reclaimed once the bodies sent before are
+//! dropped. Buffers grown past [`MAX_RETAINED`] by an unusually large
+//! request are released rather than kept for the thread's lifetime.
+
+use std::cell::RefCell;
+
+use axum::{
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use bytes::{BufMut, BytesMut};
+use detection_core::Severity;
+use serde::Serialize;
+
+/// Readings, or bytes of response, a thread keeps buffers for.
+pub const MAX_RETAINED: usize = 1 << 20;
+
+/// Response bytes allocated at once.
+const BODY_CAPACITY: usize = 64 << 10;
+
+/// Per-reading working data of one detection.
+#[derive(Default)]
+pub struct Scratch {
+    pub values: Vec<f64>,
+    /// Score and severity of the flagged readings.
+    pub graded: Vec<Option<(f64, Severity)>>,
+}
+
+impl Scratch {
+    fn clear(&mut self) {
+        self.values.clear();
+        self.graded.clear();
+    }
+
+    fn trim(&mut self) {
+        if self.values.capacity() > MAX_RETAINED {
+            self.values = Vec::new();
+        }
+        if self.graded.capacity() > MAX_RETAINED {
+            self.graded = Vec::new();
+        }
+    }
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+    static BODY: RefCell<BytesMut> = RefCell::new(BytesMut::with_capacity(BODY_CAPACITY));
+}
+
+/// Runs `f` with this thread's scratch buffers, empty.
+pub fn with_scratch<R>(f: impl FnOnce(&mut Scratch) -> R) -> R {
+    SCRATCH.with(|cell| match cell.try_borrow_mut() {
+        Ok(mut scratch) => {
+            scratch.clear();
+            let result = f(&mut scratch);
+            scratch.trim();
+            result
+        }
+        // Only reached if `f` nests another detection; it gets its own.
+        Err(_) => f(&mut Scratch::default()),
+    })
+}
+
+/// A JSON response written into this thread's body buffer, otherwise as
+/// axum's `Json`.
+pub struct PooledJson<T>(pub T);
+
+impl<T: Serialize> IntoResponse for PooledJson<T> {
+    fn into_response(self) -> Response {
+        let body = BODY.with(|cell| {
+            let mut buffer = cell.borrow_mut();
+            let written = serde_json::to_writer((&mut *buffer).writer(), &self.0);
+            let body = buffer.split().freeze();
+            if body.len() > MAX_RETAINED {
+                *buffer = BytesMut::with_capacity(BODY_CAPACITY);
+            }
+            written.map(|()| body)
+        });
+        match body {
+            Ok(body) => (
+                [(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )],
+                body,
+            )
+                .into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_scratch_is_reused_empty_and_trimmed() {
+        let capacity = with_scratch(|scratch| {
+            scratch.values.extend([1.0; 100]);
+            scratch.values.capacity()
+        });
+        with_scratch(|scratch| {
+            assert!(scratch.values.is_empty() && scratch.graded.is_empty());
+            assert_eq!(scratch.values.capacity(), capacity);
+            scratch.values.resize(MAX_RETAINED + 1, 0.0);
+        });
+        with_scratch(|scratch| assert_eq!(scratch.values.capacity(), 0));
+    }
+
+    #[tokio::test]
+    async fn test_bodies_share_the_thread_buffer() {
+        let first = PooledJson(serde_json::json!({ "anomalies": [1, 2, 3] })).into_response();
+        let second = PooledJson(vec![4.5, 6.0]).into_response();
+        assert_eq!(first.headers()[header::CONTENT_TYPE], "application/json");
+
+        let first = to_bytes(first.into_body(), usize::MAX).await.unwrap();
+        let second = to_bytes(second.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&first[..], br#"{"anomalies":[1,2,3]}"#);
+        assert_eq!(&second[..], b"[4.5,6.0]");
+        // Written one after the other into the same allocation.
+        assert_eq!(second.as_ptr(), first.as_ptr().wrapping_add(first.len()));
+    }
+}
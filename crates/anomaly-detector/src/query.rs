@@ -0,0 +1,543 @@
+//! Ad-hoc SQL over stored data: `POST /query` runs one read-only query in
+//! an embedded DuckDB holding copies of the `readings`, `anomalies` and
+//! `rollups` tables, so analysis needs no export first.
+//!
+//! The copies live in memory and are refreshed before a query once they
+//! are `REFRESH_INTERVAL` old: new readings are appended by id and those
+//! removed by retention dropped, rollup buckets folded since the last
+//! refresh are replaced, and anomalies, which backfills rewrite, are copied
+//! again whole. Timestamps become DuckDB `TIMESTAMP`s.
+//!
+//! Only a single query is accepted, run as a subquery inside a transaction
+//! that is always rolled back, on a database that can reach no files and
+//! whose settings cannot be changed. Queries stop after `QUERY_TIMEOUT` and
+//! return at most `MAX_ROWS` rows.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{Json, extract::State, http::StatusCode};
+use duckdb::types::Value;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::storage::{AnomalyFilter, Storage};
+use crate::time::format_timestamp;
+
+/// How stale the copies may get before a query refreshes them.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+const DEFAULT_ROWS: usize = 1_000;
+const MAX_ROWS: usize = 10_000;
+
+/// Rows copied per round trip to storage.
+const COPY_PAGE_SIZE: i64 = 50_000;
+
+/// Wider than any rollup resolution, so replacing buckets from the start
+/// of this window covers every bucket a folded reading changed.
+const ROLLUP_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+const SCHEMA: &str = "\
+    CREATE TABLE readings (id BIGINT, sensor_id BIGINT, value DOUBLE, timestamp TIMESTAMP);
+    CREATE TABLE anomalies (id BIGINT, reading_id BIGINT, sensor_id BIGINT, value DOUBLE,
+        timestamp TIMESTAMP, method VARCHAR, score DOUBLE, severity VARCHAR,
+        detected_at TIMESTAMP);
+    CREATE TABLE rollups (sensor_id BIGINT, resolution BIGINT, bucket TIMESTAMP, count BIGINT,
+        sum DOUBLE, min DOUBLE, max DOUBLE);
+    CREATE TEMP TABLE staged_readings (id BIGINT, sensor_id BIGINT, value DOUBLE,
+        timestamp VARCHAR);
+    CREATE TEMP TABLE staged_anomalies (id BIGINT, reading_id BIGINT, sensor_id BIGINT,
+        value DOUBLE, timestamp VARCHAR, method VARCHAR, score DOUBLE, severity VARCHAR,
+        detected_at VARCHAR);
+    CREATE TEMP TABLE staged_rollups (sensor_id BIGINT, resolution BIGINT, bucket VARCHAR,
+        count BIGINT, sum DOUBLE, min DOUBLE, max DOUBLE);
+    SET lock_configuration = true;";
+
+/// How far the copies have caught up with storage.
+#[derive(Default)]
+struct Progress {
+    last_reading_id: i64,
+    /// The rollup progress of the last refresh.
+    rollups_through: i64,
+    refreshed_at: Option<Instant>,
+}
+
+pub struct QueryEngine {
+    storage: Storage,
+    db: Arc<Mutex<duckdb::Connection>>,
+    /// Held while refreshing, so concurrent queries refresh once.
+    progress: tokio::sync::Mutex<Progress>,
+}
+
+#[derive(Deserialize)]
+pub struct QueryRequest {
+    sql: String,
+    /// Rows returned at most; defaults to 1000, capped at 10 000.
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueryResponse {
+    columns: Vec<String>,
+    rows: Vec<Vec<serde_json::Value>>,
+    /// Whether rows beyond `limit` were left out.
+    truncated: bool,
+}
+
+impl QueryEngine {
+    pub fn new(storage: Storage) -> Result<Self, String> {
+        let config = duckdb::Config::default()
+            .enable_external_access(false)
+            .map_err(|e| e.to_string())?;
+        let db =
+            duckdb::Connection::open_in_memory_with_flags(config).map_err(|e| e.to_string())?;
+        db.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        Ok(Self {
+            storage,
+            db: Arc::new(Mutex::new(db)),
+            progress: tokio::sync::Mutex::default(),
+        })
+    }
+
+    /// Runs `sql` once the copies are fresh, returning up to `limit` rows.
+    pub async fn query(&self, sql: &str, limit: usize) -> Result<QueryResponse, QueryError> {
+        let sql = single_statement(sql).map_err(QueryError::Invalid)?;
+        self.refresh()
+            .await
+            .map_err(|e| QueryError::Failed(format!("refreshing the copies failed: {}", e)))?;
+
+        let wrapped = format!("SELECT * FROM (\n{}\n) AS query LIMIT {}", sql, limit + 1);
+        let interrupt = self.db.lock().unwrap().interrupt_handle();
+        let db = self.db.clone();
+        let run = tokio::task::spawn_blocking(move || {
+            let mut db = db.lock().unwrap();
+            let tx = db.transaction()?;
+            let result = run(&tx, &wrapped, limit);
+            tx.rollback()?;
+            result
+        });
+        match tokio::time::timeout(QUERY_TIMEOUT, run).await {
+            Ok(result) => result
+                .map_err(|e| QueryError::Failed(e.to_string()))?
+                .map_err(|e| QueryError::Invalid(e.to_string())),
+            Err(_) => {
+                interrupt.interrupt();
+                Err(QueryError::Invalid(format!(
+                    "query ran longer than {} seconds",
+                    QUERY_TIMEOUT.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// Brings the copies up to date if they are older than
+    /// `REFRESH_INTERVAL`.
+    async fn refresh(&self) -> Result<(), String> {
+        let mut progress = self.progress.lock().await;
+        if progress
+            .refreshed_at
+            .is_some_and(|at| at.elapsed() < REFRESH_INTERVAL)
+        {
+            return Ok(());
+        }
+        let error = |e: sqlx::Error| e.to_string();
+
+        loop {
+            let readings = self
+                .storage
+                .readings_after(progress.last_reading_id, COPY_PAGE_SIZE)
+                .await
+                .map_err(error)?;
+            let Some(last) = readings.last() else {
+                break;
+            };
+            progress.last_reading_id = last.id;
+            self.with_db(move |db| {
+                let mut appender = db.appender("staged_readings")?;
+                for r in &readings {
+                    appender.append_row(duckdb::params![
+                        r.id,
+                        r.sensor_id,
+                        r.value,
+                        r.timestamp
+                    ])?;
+                }
+                appender.flush()?;
+                db.execute_batch(
+                    "INSERT INTO readings SELECT id, sensor_id, value, \
+                     TRY_CAST(timestamp AS TIMESTAMP) FROM staged_readings; \
+                     DELETE FROM staged_readings;",
+                )
+            })
+            .await?;
+        }
+        if let Some(first) = self.storage.first_reading_id().await.map_err(error)? {
+            self.with_db(move |db| {
+                db.execute("DELETE FROM readings WHERE id < ?", [first])
+                    .map(|_| ())
+            })
+            .await?;
+        }
+
+        let folded = self.storage.rollup_progress().await.map_err(error)?;
+        if folded != progress.rollups_through {
+            // Everything on the first refresh, then the buckets folded since.
+            let since = match progress.rollups_through {
+                0 => None,
+                through => self
+                    .storage
+                    .first_bucket_between(through, folded, ROLLUP_WINDOW_SECONDS)
+                    .await
+                    .map_err(error)?,
+            };
+            let rollups = self
+                .storage
+                .rollups_since(since.as_deref())
+                .await
+                .map_err(error)?;
+            self.with_db(move |db| {
+                let tx = db.transaction()?;
+                {
+                    let mut appender = tx.appender("staged_rollups")?;
+                    for r in &rollups {
+                        appender.append_row(duckdb::params![
+                            r.sensor_id,
+                            r.resolution,
+                            r.bucket,
+                            r.count,
+                            r.sum,
+                            r.min,
+                            r.max
+                        ])?;
+                    }
+                }
+                match &since {
+                    Some(since) => {
+                        tx.execute(
+                            "DELETE FROM rollups WHERE bucket >= CAST(? AS TIMESTAMP)",
+                            [since],
+                        )?;
+                    }
+                    None => {
+                        tx.execute("DELETE FROM rollups", [])?;
+                    }
+                }
+                tx.execute_batch(
+                    "INSERT INTO rollups SELECT sensor_id, resolution, \
+                     TRY_CAST(bucket AS TIMESTAMP), count, sum, min, max FROM staged_rollups; \
+                     DELETE FROM staged_rollups;",
+                )?;
+                tx.commit()
+            })
+            .await?;
+            progress.rollups_through = folded;
+        }
+
+        let anomalies: Vec<_> = self
+            .storage
+            .anomaly_pages(AnomalyFilter::default(), COPY_PAGE_SIZE)
+            .try_concat()
+            .await
+            .map_err(error)?;
+        self.with_db(move |db| {
+            let tx = db.transaction()?;
+            {
+                let mut appender = tx.appender("staged_anomalies")?;
+                for a in &anomalies {
+                    appender.append_row(duckdb::params![
+                        a.id,
+                        a.reading_id,
+                        a.sensor_id,
+                        a.value,
+                        a.timestamp,
+                        a.method,
+                        a.score,
+                        a.severity.as_str(),
+                        a.detected_at
+                    ])?;
+                }
+            }
+            tx.execute_batch(
+                "DELETE FROM anomalies; \
+                 INSERT INTO anomalies SELECT id, reading_id, sensor_id, value, \
+                 TRY_CAST(timestamp AS TIMESTAMP), method, score, severity, \
+                 TRY_CAST(detected_at AS TIMESTAMP) FROM staged_anomalies; \
+                 DELETE FROM staged_anomalies;",
+            )?;
+            tx.commit()
+        })
+        .await?;
+
+        progress.refreshed_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Runs `f` on the database off the async runtime.
+    async fn with_db<F>(&self, f: F) -> Result<(), String>
+    where
+        F: FnOnce(&mut duckdb::Connection) -> duckdb::Result<()> + Send + 'static,
+    {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || f(&mut db.lock().unwrap()))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum QueryError {
+    /// The query was rejected or failed by its own fault.
+    Invalid(String),
+    Failed(String),
+}
+
+fn run(db: &duckdb::Connection, sql: &str, limit: usize) -> duckdb::Result<QueryResponse> {
+    let mut statement = db.prepare(sql)?;
+    let mut rows = statement.query([])?;
+    let columns = rows
+        .as_ref()
+        .map(|statement| statement.column_names())
+        .unwrap_or_default();
+    let mut out = Vec::new();
+    let mut truncated = false;
+    while let Some(row) = rows.next()? {
+        if out.len() == limit {
+            truncated = true;
+            break;
+        }
+        let values = (0..columns.len())
+            .map(|i| row.get::<_, Value>(i).map(json))
+            .collect::<duckdb::Result<_>>()?;
+        out.push(values);
+    }
+    Ok(QueryResponse {
+        columns,
+        rows: out,
+        truncated,
+    })
+}
+
+/// `sql` without a trailing semicolon, or an error if it holds more than
+/// one statement. Semicolons in strings, quoted names and comments are
+/// allowed.
+fn single_statement(sql: &str) -> Result<&str, String> {
+    let sql = sql.trim();
+    let mut chars = sql.char_indices().peekable();
+    let mut end = None;
+    while let Some((i, c)) = chars.next() {
+        if end.is_some() && !c.is_whitespace() {
+            return Err("send one statement per query".to_string());
+        }
+        match c {
+            '\'' | '"' => {
+                for (_, next) in chars.by_ref() {
+                    if next == c {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek().is_some_and(|&(_, next)| next == '-') => {
+                for (_, next) in chars.by_ref() {
+                    if next == '\n' {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek().is_some_and(|&(_, next)| next == '*') => {
+                chars.next();
+                let mut star = false;
+                for (_, next) in chars.by_ref() {
+                    if star && next == '/' {
+                        break;
+                    }
+                    star = next == '*';
+                }
+            }
+            ';' => end = Some(i),
+            _ => {}
+        }
+    }
+    let sql = &sql[..end.unwrap_or(sql.len())];
+    if sql.trim().is_empty() {
+        return Err("sql is empty".to_string());
+    }
+    Ok(sql)
+}
+
+/// A DuckDB value as JSON; timestamps and dates as ISO 8601 text.
+fn json(value: Value) -> serde_json::Value {
+    use serde_json::Value as Json;
+    match value {
+        Value::Null => Json::Null,
+        Value::Boolean(b) => b.into(),
+        Value::TinyInt(i) => i.into(),
+        Value::SmallInt(i) => i.into(),
+        Value::Int(i) => i.into(),
+        Value::BigInt(i) => i.into(),
+        Value::HugeInt(i) => i64::try_from(i).map_or_else(|_| i.to_string().into(), Json::from),
+        Value::UHugeInt(i) => u64::try_from(i).map_or_else(|_| i.to_string().into(), Json::from),
+        Value::UTinyInt(i) => i.into(),
+        Value::USmallInt(i) => i.into(),
+        Value::UInt(i) => i.into(),
+        Value::UBigInt(i) => i.into(),
+        // Non-finite floats are null, as JSON has no NaN.
+        Value::Float(f) => f64::from(f).into(),
+        Value::Double(f) => f.into(),
+        Value::Decimal(d) => d.to_string().parse::<f64>().map_or(Json::Null, Json::from),
+        Value::Timestamp(unit, t) => timestamp(unit.to_micros(t)).into(),
+        Value::Date32(days) => timestamp(i64::from(days) * 86_400_000_000)[..10]
+            .to_string()
+            .into(),
+        Value::Time64(unit, t) => timestamp(unit.to_micros(t))[11..].to_string().into(),
+        Value::Text(s) | Value::Enum(s) => s.into(),
+        Value::Interval {
+            months,
+            days,
+            nanos,
+        } => format!("{} months {} days {} ns", months, days, nanos).into(),
+        Value::List(values) | Value::Array(values) => {
+            values.into_iter().map(json).collect::<Vec<_>>().into()
+        }
+        Value::Struct(fields) => fields
+            .iter()
+            .map(|(name, value)| (name.clone(), json(value.clone())))
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::Map(entries) => entries
+            .iter()
+            .map(|(key, value)| Json::from(vec![json(key.clone()), json(value.clone())]))
+            .collect::<Vec<_>>()
+            .into(),
+        Value::Union(value) => json(*value),
+        Value::Blob(_) | Value::Geometry(_) => Json::from("<binary>"),
+        // Types added to DuckDB after this was written.
+        _ => Json::Null,
+    }
+}
+
+/// Microseconds since the epoch as `YYYY-MM-DDTHH:MM:SS[.ffffff]`.
+fn timestamp(micros: i64) -> String {
+    let seconds = micros.div_euclid(1_000_000);
+    let fraction = micros.rem_euclid(1_000_000);
+    match fraction {
+        0 => format_timestamp(seconds),
+        _ => format!("{}.{:06}", format_timestamp(seconds), fraction),
+    }
+}
+
+/// Runs a read-only SQL query over the stored readings, anomalies and
+/// rollups.
+pub async fn query(
+    State(state): State<AppState>,
+    Json(payload): Json<QueryRequest>,
+) -> Result<Json<QueryResponse>, (StatusCode, String)> {
+    let engine = state.query.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let limit = payload.limit.unwrap_or(DEFAULT_ROWS).clamp(1, MAX_ROWS);
+    match engine.query(&payload.sql, limit).await {
+        Ok(response) => Ok(Json(response)),
+        Err(QueryError::Invalid(e)) => Err((StatusCode::BAD_REQUEST, e)),
+        Err(QueryError::Failed(e)) => Err((StatusCode::INTERNAL_SERVER_ERROR, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing;
+
+    #[test]
+    fn test_single_statement() {
+        assert_eq!(single_statement(" SELECT 1; \n"), Ok("SELECT 1"));
+        assert_eq!(
+            single_statement("SELECT ';' AS \"a;b\" -- trailing; comment\n"),
+            Ok("SELECT ';' AS \"a;b\" -- trailing; comment")
+        );
+        assert_eq!(single_statement("SELECT /* ; */ 1"), Ok("SELECT /* ; */ 1"));
+        assert!(single_statement("SELECT 1; DROP TABLE readings").is_err());
+        assert!(single_statement(" ; ").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queries_copies_of_stored_tables() {
+        let storage = testing::in_memory().await;
+        testing::enforce_sensors(&storage, &[1, 2]).await;
+        testing::insert_reading(&storage, 1, 10.0, "2026-01-19 10:00:00").await;
+        testing::insert_reading(&storage, 1, 30.0, "2026-01-19 10:00:30").await;
+        testing::insert_reading(&storage, 2, 5.0, "2026-01-19 10:01:00").await;
+        testing::insert_anomaly(&storage, 1, "2026-01-19 10:00:30").await;
+        crate::rollups::fold_all(&storage).await.unwrap();
+        let engine = QueryEngine::new(storage.clone()).unwrap();
+
+        let response = engine
+            .query(
+                "SELECT sensor_id, avg(value) AS mean, max(timestamp) AS last \
+                 FROM readings GROUP BY sensor_id ORDER BY sensor_id",
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.columns, ["sensor_id", "mean", "last"]);
+        assert_eq!(
+            response.rows,
+            [
+                serde_json::json!([1, 20.0, "2026-01-19T10:00:30"]),
+                serde_json::json!([2, 5.0, "2026-01-19T10:01:00"]),
+            ]
+            .map(|row| row.as_array().unwrap().clone())
+        );
+        assert!(!response.truncated);
+
+        let response = engine
+            .query(
+                "SELECT count(*) FROM anomalies a JOIN rollups r USING (sensor_id) \
+                 WHERE r.resolution = 60",
+                10,
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.rows, [vec![serde_json::Value::from(1)]]);
+
+        let response = engine.query("SELECT * FROM range(5)", 3).await.unwrap();
+        assert_eq!(response.rows.len(), 3);
+        assert!(response.truncated);
+
+        // Fresh copies are reused; stale ones pick up new readings.
+        testing::insert_reading(&storage, 2, 7.0, "2026-01-19 10:02:00").await;
+        let count = "SELECT count(*) FROM readings";
+        let response = engine.query(count, 10).await.unwrap();
+        assert_eq!(response.rows, [vec![serde_json::Value::from(3)]]);
+        engine.progress.lock().await.refreshed_at = None;
+        let response = engine.query(count, 10).await.unwrap();
+        assert_eq!(response.rows, [vec![serde_json::Value::from(4)]]);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_writes_and_file_access() {
+        let storage = testing::in_memory().await;
+        let engine = QueryEngine::new(storage).unwrap();
+
+        for sql in [
+            "DELETE FROM readings",
+            "SELECT 1; DELETE FROM readings",
+            "SELECT * FROM read_csv('/etc/passwd')",
+            "SET lock_configuration = false",
+            "COPY readings TO '/tmp/readings.csv'",
+        ] {
+            let error = engine.query(sql, 10).await.unwrap_err();
+            assert!(
+                matches!(error, QueryError::Invalid(_)),
+                "{}: {:?}",
+                sql,
+                error
+            );
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Redaction of personal data in sensor metadata, for GDPR-sensitive
+//! deployments.
+//!
+//! Readings carry no metadata of their own; what names people or places is
+//! the registry's `name` and `unit` and the values of tags such as
+//! `operator:jane`. The `[service.redaction]` table names which of them are
+//! hashed or removed before a registry entry or tags are stored, and before
+//! the root-cause hints of notifications (see [`crate::hints`]) leave the
+//! service, covering entries stored before redaction was turned on.
+//!
+//! ```toml
+//! [service.redaction]
+//! key = "a long random secret"
+//! name = "hash"
+//! "tag:operator" = "hash"
+//! "tag:location" = "remove"
+//! ```
+//!
+//! A hashed value is `h:` and 16 hex digits of its HMAC-SHA256 under `key`,
+//! so an operator hashes the same everywhere and tag routing, silences and
+//! correlation keep working, but the name cannot be guessed back without
+//! the key. A hashed tag keeps its key, `operator:h:…`; a removed one is
+//! dropped, a removed name becomes `[redacted]` and a removed unit empty.
+//! Values already hashed are left alone, so an exported registry can be
+//! imported again.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+use ring::hmac;
+use serde_json::Value;
+
+use crate::sensors::SensorConfig;
+
+/// The installed redaction; nothing is redacted while `None`.
+static CURRENT: RwLock<Option<Arc<Redaction>>> = RwLock::new(None);
+
+const HASH_PREFIX: &str = "h:";
+const HASH_DIGITS: usize = 16;
+const REMOVED_NAME: &str = "[redacted]";
+const MIN_KEY_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Hash,
+    Remove,
+}
+
+#[derive(Clone, Default)]
+pub struct Redaction {
+    key: Option<hmac::Key>,
+    name: Option<Action>,
+    unit: Option<Action>,
+    /// By tag key, the part before the first `:`.
+    tags: BTreeMap<String, Action>,
+}
+
+impl fmt::Debug for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Redaction")
+            .field("name", &self.name)
+            .field("unit", &self.unit)
+            .field("tags", &self.tags)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Redaction {
+    /// Reads the rules of the `[service.redaction]` table.
+    pub fn from_table(table: &BTreeMap<String, String>) -> Result<Self, String> {
+        let mut redaction = Redaction::default();
+        let mut key = None;
+        for (field, action) in table {
+            if field == "key" {
+                key = Some(action);
+                continue;
+            }
+            let action = match action.as_str() {
+                "hash" => Action::Hash,
+                "remove" => Action::Remove,
+                other => {
+                    return Err(format!(
+                        "redaction.{}: unknown action {:?}; actions are hash and remove",
+                        field, other
+                    ));
+                }
+            };
+            match field.as_str() {
+                "name" => redaction.name = Some(action),
+                "unit" => redaction.unit = Some(action),
+                _ => match field.strip_prefix("tag:").filter(|key| !key.is_empty()) {
+                    Some(tag) => {
+                        redaction.tags.insert(tag.to_string(), action);
+                    }
+                    None => {
+                        return Err(format!(
+                            "redaction.{}: unknown field; fields are name, unit and tag:<key>",
+                            field
+                        ));
+                    }
+                },
+            }
+        }
+
+        let hashes = [redaction.name, redaction.unit]
+            .into_iter()
+            .flatten()
+            .chain(redaction.tags.values().copied())
+            .any(|action| action == Action::Hash);
+        match key {
+            Some(key) if key.len() < MIN_KEY_LENGTH => {
+                return Err(format!(
+                    "redaction.key must be at least {} characters",
+                    MIN_KEY_LENGTH
+                ));
+            }
+            Some(key) => redaction.key = Some(hmac::Key::new(hmac::HMAC_SHA256, key.as_bytes())),
+            None if hashes => return Err("redaction.key is required to hash".to_string()),
+            None => {}
+        }
+        Ok(redaction)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.name.is_some() || self.unit.is_some() || !self.tags.is_empty()
+    }
+
+    /// Makes this the redaction applied process-wide.
+    pub fn install(self) {
+        *CURRENT.write().unwrap() = self.is_enabled().then(|| Arc::new(self));
+    }
+
+    fn hash(&self, value: &str) -> String {
+        if value.strip_prefix(HASH_PREFIX).is_some_and(|digits| {
+            digits.len() == HASH_DIGITS && digits.bytes().all(|b| b.is_ascii_hexdigit())
+        }) {
+            return value.to_string();
+        }
+        let key = self.key.as_ref().expect("a key when hashing");
+        let tag = hmac::sign(key, value.as_bytes());
+        let digits: String = tag.as_ref()[..HASH_DIGITS / 2]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("{}{}", HASH_PREFIX, digits)
+    }
+
+    fn field(&self, action: Option<Action>, value: &mut String, removed: &str) {
+        match action {
+            Some(Action::Hash) if !value.is_empty() => *value = self.hash(value),
+            Some(Action::Remove) => *value = removed.to_string(),
+            _ => {}
+        }
+    }
+
+    /// Redacts `tags` in place, keeping them sorted and unique.
+    pub fn tags(&self, tags: &mut Vec<String>) {
+        if self.tags.is_empty() {
+            return;
+        }
+        tags.retain_mut(|tag| {
+            let Some((key, value)) = tag.split_once(':') else {
+                return true;
+            };
+            match self.tags.get(key) {
+                Some(Action::Hash) => {
+                    *tag = format!("{}:{}", key, self.hash(value));
+                    true
+                }
+                Some(Action::Remove) => false,
+                None => true,
+            }
+        });
+        tags.sort();
+        tags.dedup();
+    }
+
+    pub fn sensor(&self, config: &mut SensorConfig) {
+        self.field(self.name, &mut config.name, REMOVED_NAME);
+        self.field(self.unit, &mut config.unit, "");
+        self.tags(&mut config.tags);
+    }
+
+    /// Redacts the `name`, `unit` and `tags` of every object in `value`, as
+    /// in the before and after of audited sensor changes.
+    pub fn json(&self, value: &mut Value) {
+        match value {
+            Value::Object(object) => {
+                for (key, value) in object.iter_mut() {
+                    match (key.as_str(), value) {
+                        ("name", Value::String(name)) => self.field(self.name, name, REMOVED_NAME),
+                        ("unit", Value::String(unit)) => self.field(self.unit, unit, ""),
+                        ("tags", Value::Array(items)) => {
+                            let mut tags: Vec<String> = items
+                                .iter()
+                                .filter_map(|tag| tag.as_str().map(str::to_string))
+                                .collect();
+                            if tags.len() == items.len() {
+                                self.tags(&mut tags);
+                                *items = tags.into_iter().map(Value::String).collect();
+                            }
+                        }
+                        (_, value) => self.json(value),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(|item| self.json(item)),
+            _ => {}
+        }
+    }
+}
+
+/// The installed redaction, if any.
+pub fn current() -> Option<Arc<Redaction>> {
+    CURRENT.read().unwrap().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn redaction(rules: &[(&str, &str)]) -> Result<Redaction, String> {
+        let table = rules
+            .iter()
+            .map(|(field, action)| (field.to_string(), action.to_string()))
+            .collect();
+        Redaction::from_table(&table)
+    }
+
+    #[test]
+    fn test_redacts_selected_metadata() {
+        let redaction = redaction(&[
+            ("key", "0123456789abcdef0123"),
+            ("name", "hash"),
+            ("tag:operator", "hash"),
+            ("tag:location", "remove"),
+        ])
+        .unwrap();
+        let mut config = SensorConfig {
+            name: "Jane's press".to_string(),
+            unit: "bar".to_string(),
+            tags: vec![
+                "line:a".to_string(),
+                "location:Main St 5".to_string(),
+                "operator:jane".to_string(),
+            ],
+            ..SensorConfig::default()
+        };
+        redaction.sensor(&mut config);
+
+        assert!(config.name.starts_with("h:") && config.name.len() == 18);
+        assert_eq!(config.unit, "bar");
+        let operator = format!("operator:{}", redaction.hash("jane"));
+        assert_eq!(config.tags, ["line:a".to_string(), operator.clone()]);
+        // Hashing is stable and does not hash a hash again.
+        let mut again = config.clone();
+        redaction.sensor(&mut again);
+        assert_eq!(again, config);
+
+        let mut change =
+            json!({ "sensor_id": 1, "name": "Jane's press", "tags": ["operator:jane"] });
+        redaction.json(&mut change);
+        assert_eq!(
+            change,
+            json!({ "sensor_id": 1, "name": config.name, "tags": [operator] })
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_rules() {
+        assert!(redaction(&[("name", "hash")]).is_err_and(|e| e.contains("key")));
+        assert!(redaction(&[("name", "remove")]).is_ok());
+        assert!(redaction(&[("name", "blur")]).is_err());
+        assert!(redaction(&[("location", "remove")]).is_err());
+        assert!(redaction(&[("key", "short"), ("unit", "remove")]).is_err());
+    }
+}
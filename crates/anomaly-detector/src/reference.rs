@@ -0,0 +1,120 @@
+//! Reference sensors: a sensor registered with a `reference` (the canary of
+//! its cohort, e.g. the other half of a redundant pair) can be scored by how
+//! far its readings are from the canary's. Batch series with method
+//! `reference` are z-scored on the difference from the canary's reading at
+//! the same timestamp, taken from the canary's series in the same batch:
+//!
+//! ```json
+//! {
+//!   "series": [
+//!     { "id": "a", "sensor_id": 7, "readings": [...] },
+//!     { "id": "b", "sensor_id": 8, "method": "reference", "readings": [...] }
+//!   ]
+//! }
+//! ```
+//!
+//! Readings the canary has none at the same timestamp for are not scored,
+//! though those outside the sensor's limits are still critical anomalies.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::sensors::SensorConfig;
+use crate::{AnalyzeRequest, Reading, Scoring};
+
+pub const METHOD: &str = "reference";
+
+/// The readings of a canary, by timestamp.
+#[derive(Debug, Default)]
+pub struct Canary(HashMap<String, f64>);
+
+impl Canary {
+    pub fn new(readings: &[Reading]) -> Self {
+        Self(
+            readings
+                .iter()
+                .map(|reading| (reading.timestamp.clone(), reading.value))
+                .collect(),
+        )
+    }
+
+    /// How far each of `readings` is from the canary's reading at its
+    /// timestamp; `None` where the canary has none.
+    pub fn deviations(&self, readings: &[Reading]) -> Vec<Option<f64>> {
+        readings
+            .iter()
+            .map(|reading| {
+                let canary = self.0.get(&reading.timestamp)?;
+                Some(reading.value - canary)
+            })
+            .collect()
+    }
+}
+
+/// The canaries of a batch: the series of each sensor that is some
+/// registered sensor's reference.
+pub fn canaries<'a>(
+    requests: impl IntoIterator<Item = &'a AnalyzeRequest>,
+    registry: &HashMap<i64, SensorConfig>,
+) -> HashMap<i64, Arc<Canary>> {
+    let mut canaries = HashMap::new();
+    for request in requests {
+        let Some(sensor_id) = request.sensor_id else {
+            continue;
+        };
+        if registry.values().any(|s| s.reference == Some(sensor_id)) {
+            canaries
+                .entry(sensor_id)
+                .or_insert_with(|| Arc::new(Canary::new(&request.readings)));
+        }
+    }
+    canaries
+}
+
+/// Scoring of a batch series against its sensor's canary.
+pub fn scoring(
+    request: &AnalyzeRequest,
+    sensor: Option<&SensorConfig>,
+    canaries: &HashMap<i64, Arc<Canary>>,
+) -> Result<Scoring, String> {
+    if request.ensemble.is_some() {
+        return Err(format!(
+            "`ensemble` needs method \"{}\"",
+            crate::ensemble::METHOD
+        ));
+    }
+    let sensor_id = request
+        .sensor_id
+        .ok_or_else(|| format!("method \"{}\" needs a sensor_id", METHOD))?;
+    let reference = sensor
+        .and_then(|sensor| sensor.reference)
+        .ok_or_else(|| format!("sensor {} has no reference registered", sensor_id))?;
+    let canary = canaries
+        .get(&reference)
+        .ok_or_else(|| format!("the batch has no series of reference sensor {}", reference))?;
+    Ok(Scoring::Reference(canary.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings(values: &[(u32, f64)]) -> Vec<Reading> {
+        values
+            .iter()
+            .map(|&(minute, value)| Reading {
+                id: minute as i64,
+                value,
+                timestamp: format!("2026-01-19T10:{:02}:00", minute),
+                sensor_id: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deviations_pair_readings_by_timestamp() {
+        let canary = Canary::new(&readings(&[(0, 10.0), (1, 11.0), (3, 12.0)]));
+        let deviations = canary.deviations(&readings(&[(0, 10.5), (1, 10.0), (2, 9.0)]));
+        assert_eq!(deviations, [Some(0.5), Some(-1.0), None]);
+    }
+}
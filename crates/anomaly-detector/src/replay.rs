@@ -0,0 +1,392 @@
+//! `POST /replay`: re-runs detection over stored readings with alternative
+//! parameters and diffs the result against the anomalies stored for the
+//! range, without writing anything.
+//!
+//! Parameters left out keep their current values: the sensor's registry
+//! entry, then the runtime settings.
+//!
+//! ```json
+//! {
+//!   "sensor_id": 7,
+//!   "start": "2026-01-01",
+//!   "end": "2026-02-01",
+//!   "parameters": { "threshold": 3.0, "max_value": 95.0 }
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use axum::{Json, extract::State, http::StatusCode};
+use detection_core::Severity;
+use detection_core::stats::ZScorer;
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::backfill::{CHUNK_SIZE, range_stats};
+use crate::lanes::Lanes;
+use crate::sensors::SensorConfig;
+use crate::settings::{DetectionSettings, SeverityBands};
+use crate::storage::{AnomalyFilter, Storage, StoredAnomaly};
+use crate::{AppState, ZSCORE_METHOD, classify, resolve_threshold, sensors};
+
+const DEFAULT_LIMIT: usize = 1_000;
+const MAX_LIMIT: usize = 10_000;
+
+/// Overrides of the parameters in effect.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Parameters {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    min_value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_value: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severity: Option<SeverityBands>,
+}
+
+impl Parameters {
+    pub fn threshold(&self) -> Option<f64> {
+        self.threshold
+    }
+
+    /// Overrides a sensor's registry entry and the runtime detection settings.
+    pub fn apply(
+        &self,
+        sensor: &mut SensorConfig,
+        detection: &mut DetectionSettings,
+    ) -> Result<(), String> {
+        self.validate()?;
+        sensor.threshold = self.threshold.or(sensor.threshold);
+        sensor.min_value = self.min_value.or(sensor.min_value);
+        sensor.max_value = self.max_value.or(sensor.max_value);
+        if let (Some(min), Some(max)) = (sensor.min_value, sensor.max_value)
+            && min >= max
+        {
+            return Err("min_value must be below max_value".to_string());
+        }
+        if let Some(bands) = self.severity {
+            detection.severity = bands;
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(threshold) = self.threshold
+            && (!threshold.is_finite() || threshold <= 0.0)
+        {
+            return Err(format!(
+                "threshold must be a positive number, got {}",
+                threshold
+            ));
+        }
+        for (name, limit) in [("min_value", self.min_value), ("max_value", self.max_value)] {
+            if limit.is_some_and(|limit| !limit.is_finite()) {
+                return Err(format!("{} must be a finite number", name));
+            }
+        }
+        if let Some(bands) = self.severity
+            && !(bands.high.is_finite() && bands.high > 0.0 && bands.critical > bands.high)
+        {
+            return Err("severity.critical must be above severity.high, both positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ReplayRequest {
+    sensor_id: i64,
+    start: String,
+    end: String,
+    #[serde(default)]
+    parameters: Parameters,
+    /// Maximum entries listed in each diff; the counts always cover all.
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+/// A reading flagged by one side of the diff.
+#[derive(Debug, Serialize)]
+pub struct Flagged {
+    reading_id: i64,
+    timestamp: String,
+    value: f64,
+    score: f64,
+    #[serde(serialize_with = "detection_core::severity::labelled")]
+    severity: Severity,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeverityChange {
+    reading_id: i64,
+    timestamp: String,
+    value: f64,
+    #[serde(serialize_with = "detection_core::severity::labelled")]
+    original: Severity,
+    #[serde(serialize_with = "detection_core::severity::labelled")]
+    replayed: Severity,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct DiffCounts {
+    original: usize,
+    replayed: usize,
+    unchanged: usize,
+    newly_flagged: usize,
+    no_longer_flagged: usize,
+    severity_changed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayResponse {
+    sensor_id: i64,
+    method: &'static str,
+    start: String,
+    end: String,
+    readings_scanned: u64,
+    mean: f64,
+    std_dev: f64,
+    /// Z-score threshold the replay used.
+    threshold: f64,
+    counts: DiffCounts,
+    newly_flagged: Vec<Flagged>,
+    no_longer_flagged: Vec<Flagged>,
+    severity_changed: Vec<SeverityChange>,
+}
+
+pub async fn replay(
+    State(state): State<AppState>,
+    Json(payload): Json<ReplayRequest>,
+) -> Result<Json<ReplayResponse>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+
+    let mut sensor = sensors::registered(&state, Some(payload.sensor_id))
+        .await?
+        .unwrap_or_default();
+    let mut detection = state.settings.detection();
+    payload
+        .parameters
+        .apply(&mut sensor, &mut detection)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    run_replay(storage, payload, &detection, &sensor, &state.lanes)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "start and end must be valid timestamps with start before end".to_string(),
+        ))
+}
+
+async fn run_replay(
+    storage: &Storage,
+    request: ReplayRequest,
+    detection: &DetectionSettings,
+    sensor: &SensorConfig,
+    lanes: &Lanes,
+) -> Result<Option<ReplayResponse>, sqlx::Error> {
+    let Some((start, end)) = storage
+        .normalize_range(&request.start, &request.end)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let limit = request.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+
+    let stats = range_stats(storage, lanes, request.sensor_id, &start, &end).await?;
+    let threshold = resolve_threshold(None, detection, Some(sensor));
+    let scorer = ZScorer::new(&stats, threshold);
+    let mut replayed = BTreeMap::new();
+    let mut after_id = 0;
+    loop {
+        let _permit = lanes.bulk().await;
+        let page = storage
+            .readings_page(request.sensor_id, &start, &end, after_id, CHUNK_SIZE)
+            .await?;
+        let Some(last) = page.last() else { break };
+        after_id = last.id;
+        for reading in page {
+            if let Some((score, severity)) =
+                classify(&scorer, detection, Some(sensor), reading.value)
+            {
+                replayed.insert(
+                    reading.id,
+                    Flagged {
+                        reading_id: reading.id,
+                        timestamp: reading.timestamp,
+                        value: reading.value,
+                        score,
+                        severity,
+                    },
+                );
+            }
+        }
+    }
+
+    let filter = AnomalyFilter {
+        sensor_id: Some(request.sensor_id),
+        start: Some(start.clone()),
+        end: Some(end.clone()),
+        method: Some(ZSCORE_METHOD.to_string()),
+        min_severity: None,
+    };
+    let original: Vec<StoredAnomaly> = storage
+        .anomaly_pages(filter, CHUNK_SIZE)
+        .try_concat()
+        .await?;
+
+    let mut counts = DiffCounts {
+        original: original.len(),
+        replayed: replayed.len(),
+        ..DiffCounts::default()
+    };
+    let mut no_longer_flagged = Vec::new();
+    let mut severity_changed = Vec::new();
+    for anomaly in original {
+        match replayed.remove(&anomaly.reading_id) {
+            Some(now) if now.severity == anomaly.severity => counts.unchanged += 1,
+            Some(now) => {
+                counts.severity_changed += 1;
+                if severity_changed.len() < limit {
+                    severity_changed.push(SeverityChange {
+                        reading_id: anomaly.reading_id,
+                        timestamp: anomaly.timestamp,
+                        value: anomaly.value,
+                        original: anomaly.severity,
+                        replayed: now.severity,
+                    });
+                }
+            }
+            None => {
+                counts.no_longer_flagged += 1;
+                if no_longer_flagged.len() < limit {
+                    no_longer_flagged.push(Flagged {
+                        reading_id: anomaly.reading_id,
+                        timestamp: anomaly.timestamp,
+                        value: anomaly.value,
+                        score: anomaly.score,
+                        severity: anomaly.severity,
+                    });
+                }
+            }
+        }
+    }
+    counts.newly_flagged = replayed.len();
+
+    Ok(Some(ReplayResponse {
+        sensor_id: request.sensor_id,
+        method: ZSCORE_METHOD,
+        start,
+        end,
+        readings_scanned: stats.count(),
+        mean: stats.mean(),
+        std_dev: stats.std_dev(),
+        threshold,
+        counts,
+        newly_flagged: replayed.into_values().take(limit).collect(),
+        no_longer_flagged,
+        severity_changed,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{count_anomalies, in_memory, insert_reading};
+    use serde_json::json;
+
+    async fn state() -> AppState {
+        let storage = in_memory().await;
+        for minute in 0..30 {
+            let value = match minute {
+                10 => 80.0,
+                20 => 500.0,
+                _ => 50.0 + (minute % 3) as f64,
+            };
+            let ts = format!("2026-01-19 10:{:02}:00.000000", minute);
+            insert_reading(&storage, 7, value, &ts).await;
+        }
+        AppState {
+            storage: Some(storage),
+            ..AppState::default()
+        }
+    }
+
+    fn request(value: serde_json::Value) -> Json<ReplayRequest> {
+        let mut request = json!({
+            "sensor_id": 7,
+            "start": "2026-01-19T10:00:00",
+            "end": "2026-01-19T11:00:00",
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(value.as_object().unwrap().clone());
+        Json(serde_json::from_value(request).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_replay_diffs_against_stored_anomalies() {
+        let state = state().await;
+        let storage = state.storage.clone().unwrap();
+        let Json(baseline) = crate::backfill::backfill(
+            State(state.clone()),
+            Json(
+                serde_json::from_value(json!({
+                    "sensor_id": 7,
+                    "start": "2026-01-19T10:00:00",
+                    "end": "2026-01-19T11:00:00",
+                    "threshold": 4.0,
+                }))
+                .unwrap(),
+            ),
+        )
+        .await
+        .unwrap();
+        let written = count_anomalies(&storage, 7).await;
+        assert_eq!(written, 1, "backfill wrote {:?}", baseline);
+
+        let Json(tighter) = replay(
+            State(state.clone()),
+            request(json!({ "parameters": { "threshold": 0.5, "max_value": 60.0 } })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(tighter.counts.original, 1);
+        assert_eq!(tighter.counts.unchanged, 1);
+        assert_eq!(tighter.counts.newly_flagged, 1);
+        assert_eq!(tighter.newly_flagged[0].value, 80.0);
+        assert_eq!(tighter.newly_flagged[0].severity, Severity::Critical);
+
+        let Json(looser) = replay(
+            State(state),
+            request(json!({ "parameters": { "threshold": 10.0 } })),
+        )
+        .await
+        .unwrap();
+        assert_eq!(looser.counts.no_longer_flagged, 1);
+        assert_eq!(looser.no_longer_flagged[0].value, 500.0);
+        assert_eq!(count_anomalies(&storage, 7).await, written);
+    }
+
+    #[tokio::test]
+    async fn test_replay_rejects_invalid_parameters() {
+        let state = state().await;
+        let invalid = [
+            json!({ "parameters": { "threshold": -1.0 } }),
+            json!({ "parameters": { "min_value": 10.0, "max_value": 5.0 } }),
+            json!({ "parameters": { "severity": { "high": 3.0, "critical": 2.0 } } }),
+            json!({ "start": "soon" }),
+        ];
+        for value in invalid {
+            let result = replay(State(state.clone()), request(value)).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Tamper-evident log of every mutating request.
+//!
+//! Where `audit` records what a change did to a resource, this records every
+//! request that could change something, the `operator` and `admin` route
+//! groups (see `access`) other than their reads, with who made it (the
+//! authenticated caller, else the `X-Actor` header), the method and path, the
+//! SHA-256 of its body and the status it was answered with, refusals
+//! included. Entries are only ever appended: the table's triggers refuse
+//! updates and deletes, and each entry's `hash` is the SHA-256 of its fields
+//! and the previous entry's hash, so an entry edited or removed out of band
+//! breaks the chain.
+//!
+//! `GET /audit/requests?actor=&method=&since=&before_id=&limit=` lists the
+//! entries, newest first, and `GET /audit/requests/verify` walks the chain
+//! from the start, reporting the first entry that does not match. A chain
+//! cut short at its end still verifies, so keep the `head` it reports
+//! somewhere else to compare against later.
+
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    extract::{Query, Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ring::digest;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::access::Identity;
+use crate::audit;
+use crate::sensors::bulk::MAX_IMPORT_BYTES;
+use crate::storage::{NewRequestEntry, RequestFilter, Storage, StoredRequestEntry};
+
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1_000;
+
+/// Entries checked per page while verifying.
+const PAGE_SIZE: i64 = 1_000;
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    digest::digest(&digest::SHA256, bytes)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The hash of an entry recorded at `received_at` after one hashing to
+/// `prev_hash`.
+fn hash(prev_hash: &str, received_at: &str, entry: &NewRequestEntry) -> String {
+    let fields = serde_json::json!([
+        prev_hash,
+        received_at,
+        entry.actor,
+        entry.method,
+        entry.path,
+        entry.body_sha256,
+        entry.status,
+    ]);
+    sha256_hex(fields.to_string().as_bytes())
+}
+
+/// Appends `entry` to the log; the request has been answered, so a failure
+/// is logged rather than returned.
+pub async fn append(storage: &Storage, entry: NewRequestEntry) {
+    let appended = storage
+        .append_request(&entry, |prev_hash, received_at| {
+            hash(prev_hash, received_at, &entry)
+        })
+        .await;
+    if let Err(e) = appended {
+        eprintln!(
+            "Error: Failed to log {} {} by {}: {}",
+            entry.method, entry.path, entry.actor, e
+        );
+    }
+}
+
+/// Middleware logging the mutating requests of a route group.
+pub async fn record(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(storage) = state.storage.clone() else {
+        return next.run(request).await;
+    };
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) {
+        return next.run(request).await;
+    }
+
+    let (parts, body) = request.into_parts();
+    // No route takes a larger body than an import.
+    let body: Bytes = match axum::body::to_bytes(body, MAX_IMPORT_BYTES).await {
+        Ok(body) => body,
+        Err(e) => return (StatusCode::PAYLOAD_TOO_LARGE, e.to_string()).into_response(),
+    };
+    let body_sha256 = sha256_hex(&body);
+    let method = parts.method.to_string();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or_else(|| parts.uri.path().to_string(), |path| path.to_string());
+    let header_actor = audit::actor(&parts.headers);
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let actor = response
+        .extensions()
+        .get::<Identity>()
+        .map_or(header_actor, |identity| identity.name.clone());
+    let entry = NewRequestEntry {
+        actor,
+        method,
+        path,
+        body_sha256,
+        status: response.status().as_u16(),
+    };
+    append(&storage, entry).await;
+    response
+}
+
+#[derive(Default, Deserialize)]
+pub struct RequestQuery {
+    actor: Option<String>,
+    method: Option<String>,
+    since: Option<String>,
+    /// Continues a listing from the smallest `id` of the previous page.
+    before_id: Option<i64>,
+    limit: Option<i64>,
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+/// Lists logged requests, newest first.
+pub async fn list_requests(
+    State(state): State<AppState>,
+    Query(query): Query<RequestQuery>,
+) -> Result<Json<Vec<StoredRequestEntry>>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+    let since = match &query.since {
+        Some(since) => Some(
+            storage
+                .normalize_timestamp(since)
+                .await
+                .map_err(internal)?
+                .ok_or((
+                    StatusCode::BAD_REQUEST,
+                    "since must be a valid timestamp".to_string(),
+                ))?,
+        ),
+        None => None,
+    };
+    let filter = RequestFilter {
+        actor: query.actor,
+        method: query.method.map(|method| method.to_ascii_uppercase()),
+        since,
+        before_id: query.before_id,
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let entries = storage
+        .request_entries(&filter, limit)
+        .await
+        .map_err(internal)?;
+    Ok(Json(entries))
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Verification {
+    /// Entries checked, up to the first broken one.
+    pub entries: u64,
+    pub valid: bool,
+    /// The first entry whose hash or link to the one before does not match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub broken_at: Option<i64>,
+    /// The hash of the last entry checked.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub head: Option<String>,
+}
+
+/// Checks the whole chain, oldest entry first.
+pub async fn verify(storage: &Storage) -> Result<Verification, sqlx::Error> {
+    let mut verification = Verification {
+        entries: 0,
+        valid: true,
+        broken_at: None,
+        head: None,
+    };
+    let mut after_id = 0;
+    loop {
+        let page = storage.request_chain(after_id, PAGE_SIZE).await?;
+        for stored in &page {
+            let entry = NewRequestEntry {
+                actor: stored.actor.clone(),
+                method: stored.method.clone(),
+                path: stored.path.clone(),
+                body_sha256: stored.body_sha256.clone(),
+                status: u16::try_from(stored.status).unwrap_or_default(),
+            };
+            let prev_hash = verification.head.as_deref().unwrap_or_default();
+            if stored.prev_hash != prev_hash
+                || stored.hash != hash(prev_hash, &stored.received_at, &entry)
+            {
+                verification.valid = false;
+                verification.broken_at = Some(stored.id);
+                return Ok(verification);
+            }
+            verification.entries += 1;
+            verification.head = Some(stored.hash.clone());
+        }
+        match page.last() {
+            Some(last) if page.len() as i64 == PAGE_SIZE => after_id = last.id,
+            _ => return Ok(verification),
+        }
+    }
+}
+
+/// Verifies the request log's hash chain.
+pub async fn verify_requests(
+    State(state): State<AppState>,
+) -> Result<Json<Verification>, (StatusCode, String)> {
+    let verification = verify(storage(&state)?)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(verification))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{execute, in_memory};
+
+    fn entry(actor: &str, path: &str) -> NewRequestEntry {
+        NewRequestEntry {
+            actor: actor.to_string(),
+            method: "PUT".to_string(),
+            path: path.to_string(),
+            body_sha256: sha256_hex(b"{}"),
+            status: 200,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_log_is_chained_and_append_only() {
+        let storage = in_memory().await;
+        append(&storage, entry("alice", "/sensors/1")).await;
+        append(&storage, entry("bob", "/routing")).await;
+        append(&storage, entry("alice", "/sensors/2")).await;
+
+        let state = AppState {
+            storage: Some(storage.clone()),
+            ..AppState::default()
+        };
+        let Json(entries) = list_requests(
+            State(state.clone()),
+            Query(RequestQuery {
+                actor: Some("alice".to_string()),
+                ..RequestQuery::default()
+            }),
+        )
+        .await
+        .unwrap();
+        let paths: Vec<&str> = entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(paths, ["/sensors/2", "/sensors/1"]);
+        assert_eq!(entries[1].prev_hash, "");
+
+        let Json(verification) = verify_requests(State(state)).await.unwrap();
+        assert_eq!((verification.entries, verification.valid), (3, true));
+        assert_eq!(verification.head.as_ref(), Some(&entries[0].hash));
+
+        let tampered = execute(
+            &storage,
+            "UPDATE request_log SET actor = 'mallory' WHERE id = 2",
+        );
+        assert!(tampered.await.is_err());
+        assert!(execute(&storage, "DELETE FROM request_log").await.is_err());
+
+        // Bypassing the triggers still shows.
+        execute(
+            &storage,
+            "DROP TRIGGER request_log_no_update; \
+             UPDATE request_log SET actor = 'mallory' WHERE id = 2",
+        )
+        .await
+        .unwrap();
+        let verification = verify(&storage).await.unwrap();
+        assert_eq!((verification.entries, verification.broken_at), (1, Some(2)));
+    }
+}
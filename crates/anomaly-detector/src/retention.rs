@@ -0,0 +1,179 @@
+//! Background deletion of rows older than the configured retention.
+
+use std::time::Duration;
+
+use crate::archive;
+use crate::config::RetentionPolicy;
+use crate::leader::Lease;
+use crate::storage::Storage;
+
+/// Rows deleted per statement, so a large purge never holds the write lock
+/// long enough to stall the Python API's inserts.
+const DELETE_BATCH_SIZE: i64 = 5_000;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CompactionReport {
+    pub readings_deleted: u64,
+    pub anomalies_deleted: u64,
+}
+
+/// Deletes expired readings and anomalies, then truncates the WAL. With
+/// archiving on, rows the archive job has not exported yet are kept.
+pub async fn compact(
+    storage: &Storage,
+    policy: &RetentionPolicy,
+) -> Result<CompactionReport, sqlx::Error> {
+    let mut report = CompactionReport::default();
+
+    if let Some(cutoff) = cutoff(storage, policy, policy.readings, archive::READINGS).await? {
+        report.readings_deleted =
+            delete_in_batches(|| storage.delete_before("readings", &cutoff, DELETE_BATCH_SIZE))
+                .await?;
+    }
+    if let Some(cutoff) = cutoff(storage, policy, policy.anomalies, archive::ANOMALIES).await? {
+        report.anomalies_deleted =
+            delete_in_batches(|| storage.delete_anomalies_before(&cutoff, DELETE_BATCH_SIZE))
+                .await?;
+    }
+
+    storage.checkpoint().await?;
+    Ok(report)
+}
+
+/// The timestamp rows of `kind` older than `age` are deleted before, if any.
+async fn cutoff(
+    storage: &Storage,
+    policy: &RetentionPolicy,
+    age: Option<Duration>,
+    kind: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let Some(age) = age else {
+        return Ok(None);
+    };
+    let cutoff = storage.cutoff(age).await?;
+    if !policy.archived {
+        return Ok(Some(cutoff));
+    }
+    let archived = storage.archive_watermark(kind).await?;
+    Ok(archived.map(|archived| archived.min(cutoff)))
+}
+
+/// Calls `delete_batch` until it deletes less than a full batch.
+async fn delete_in_batches<F>(mut delete_batch: impl FnMut() -> F) -> Result<u64, sqlx::Error>
+where
+    F: Future<Output = Result<u64, sqlx::Error>>,
+{
+    let mut total = 0;
+    loop {
+        let deleted = delete_batch().await?;
+        total += deleted;
+        if deleted < DELETE_BATCH_SIZE as u64 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Runs [`compact`] every `policy.interval` for the lifetime of the process,
+/// on the replica holding the lease.
+pub fn spawn(storage: Storage, policy: RetentionPolicy) {
+    tokio::spawn(async move {
+        let lease = Lease::new(storage.clone(), "retention", policy.interval);
+        let mut ticker = tokio::time::interval(policy.interval);
+        loop {
+            ticker.tick().await;
+            if !lease.acquire().await {
+                continue;
+            }
+            match compact(&storage, &policy).await {
+                Ok(report) => {
+                    if report.readings_deleted > 0 || report.anomalies_deleted > 0 {
+                        println!(
+                            "Retention: deleted {} readings and {} anomalies",
+                            report.readings_deleted, report.anomalies_deleted
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Retention compaction failed: {}", e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::storage::testing::{count_anomalies, in_memory, insert_anomaly, insert_reading};
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[tokio::test]
+    async fn test_compact_deletes_only_expired_rows() {
+        let storage = in_memory().await;
+        insert_reading(&storage, 1, 1.0, "2000-01-01 00:00:00.000000").await;
+        insert_reading(&storage, 1, 2.0, "2999-01-01 00:00:00.000000").await;
+        insert_anomaly(&storage, 1, "2000-01-01 00:00:00.000000").await;
+        insert_anomaly(&storage, 1, "2999-01-01 00:00:00.000000").await;
+
+        let policy = RetentionPolicy {
+            readings: Some(30 * DAY),
+            anomalies: Some(365 * DAY),
+            ..RetentionPolicy::default()
+        };
+        let report = compact(&storage, &policy).await.unwrap();
+
+        assert_eq!(
+            report,
+            CompactionReport {
+                readings_deleted: 1,
+                anomalies_deleted: 1,
+            }
+        );
+        assert_eq!(count_anomalies(&storage, 1).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_compact_keeps_rows_not_yet_archived() {
+        let storage = in_memory().await;
+        insert_reading(&storage, 1, 1.0, "2000-01-01 00:00:00.000000").await;
+        insert_reading(&storage, 1, 2.0, "2000-03-01 00:00:00.000000").await;
+        insert_anomaly(&storage, 1, "2000-01-01 00:00:00.000000").await;
+        storage
+            .set_archive_watermark(archive::READINGS, "2000-02-01 00:00:00")
+            .await
+            .unwrap();
+
+        let policy = RetentionPolicy {
+            readings: Some(DAY),
+            anomalies: Some(DAY),
+            archived: true,
+            ..RetentionPolicy::default()
+        };
+        let report = compact(&storage, &policy).await.unwrap();
+
+        assert_eq!(
+            report,
+            CompactionReport {
+                readings_deleted: 1,
+                anomalies_deleted: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_keeps_tables_without_policy() {
+        let storage = in_memory().await;
+        insert_reading(&storage, 1, 1.0, "2000-01-01 00:00:00.000000").await;
+        insert_anomaly(&storage, 1, "2000-01-01 00:00:00.000000").await;
+
+        let policy = RetentionPolicy {
+            readings: Some(DAY),
+            ..RetentionPolicy::default()
+        };
+        let report = compact(&storage, &policy).await.unwrap();
+
+        assert_eq!(report.readings_deleted, 1);
+        assert_eq!(report.anomalies_deleted, 0);
+        assert_eq!(count_anomalies(&storage, 1).await, 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! Downsampled reading rollups and the series query endpoint served from them.
+
+use std::time::Duration;
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::leader::Lease;
+use crate::storage::{SeriesPoint, Storage};
+
+/// Readings folded per transaction.
+const FOLD_BATCH_SIZE: i64 = 50_000;
+
+/// Most points returned by a series query; `auto` picks the finest
+/// resolution that stays under this.
+const MAX_POINTS: i64 = 2_000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Resolution {
+    #[default]
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl Resolution {
+    const ROLLUPS: [Resolution; 3] = [
+        Resolution::OneMinute,
+        Resolution::FiveMinutes,
+        Resolution::OneHour,
+    ];
+
+    /// Bucket width in seconds; `None` for raw and auto.
+    fn seconds(self) -> Option<i64> {
+        match self {
+            Resolution::OneMinute => Some(60),
+            Resolution::FiveMinutes => Some(5 * 60),
+            Resolution::OneHour => Some(60 * 60),
+            Resolution::Auto | Resolution::Raw => None,
+        }
+    }
+
+    /// Finest rollup resolution that covers `range_seconds` within `MAX_POINTS` buckets.
+    fn for_range(range_seconds: i64) -> Resolution {
+        Self::ROLLUPS
+            .into_iter()
+            .find(|r| range_seconds / r.seconds().unwrap() <= MAX_POINTS)
+            .unwrap_or(Resolution::OneHour)
+    }
+}
+
+/// Folds new readings into rollups until caught up.
+pub async fn fold_all(storage: &Storage) -> Result<u64, sqlx::Error> {
+    let resolutions: Vec<i64> = Resolution::ROLLUPS
+        .iter()
+        .filter_map(|r| r.seconds())
+        .collect();
+
+    let mut total = 0;
+    loop {
+        let folded = storage.fold_rollups(&resolutions, FOLD_BATCH_SIZE).await?;
+        total += folded;
+        if folded == 0 {
+            return Ok(total);
+        }
+    }
+}
+
+/// Runs [`fold_all`] every `interval` for the lifetime of the process, on
+/// the replica holding the lease.
+pub fn spawn(storage: Storage, interval: Duration) {
+    tokio::spawn(async move {
+        let lease = Lease::new(storage.clone(), "rollups", interval);
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if !lease.acquire().await {
+                continue;
+            }
+            if let Err(e) = fold_all(&storage).await {
+                eprintln!("Rollup maintenance failed: {}", e);
+            }
+        }
+    });
+}
+
+#[derive(Deserialize)]
+pub struct SeriesQuery {
+    start: String,
+    end: String,
+    #[serde(default)]
+    resolution: Resolution,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SeriesResponse {
+    sensor_id: i64,
+    resolution: Resolution,
+    start: String,
+    end: String,
+    points: Vec<SeriesPoint>,
+}
+
+/// Returns a sensor's readings in `[start, end)`, downsampled to `resolution`.
+pub async fn series(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    Query(query): Query<SeriesQuery>,
+) -> Result<Json<SeriesResponse>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+
+    query_series(storage, sensor_id, query)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map(Json)
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "start and end must be valid timestamps with start before end".to_string(),
+        ))
+}
+
+async fn query_series(
+    storage: &Storage,
+    sensor_id: i64,
+    query: SeriesQuery,
+) -> Result<Option<SeriesResponse>, sqlx::Error> {
+    let Some((start, end)) = storage.normalize_range(&query.start, &query.end).await? else {
+        return Ok(None);
+    };
+
+    let resolution = match query.resolution {
+        Resolution::Auto => Resolution::for_range(storage.range_seconds(&start, &end).await?),
+        other => other,
+    };
+
+    let points = match resolution.seconds() {
+        Some(seconds) => {
+            storage
+                .rollup_points(sensor_id, seconds, &start, &end, MAX_POINTS)
+                .await?
+        }
+        None => {
+            storage
+                .raw_points(sensor_id, &start, &end, MAX_POINTS)
+                .await?
+        }
+    };
+
+    Ok(Some(SeriesResponse {
+        sensor_id,
+        resolution,
+        start,
+        end,
+        points,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{in_memory, insert_reading};
+
+    fn query(start: &str, end: &str, resolution: Resolution) -> SeriesQuery {
+        SeriesQuery {
+            start: start.to_string(),
+            end: end.to_string(),
+            resolution,
+        }
+    }
+
+    #[test]
+    fn test_resolution_for_range() {
+        assert_eq!(Resolution::for_range(60 * 60), Resolution::OneMinute);
+        assert_eq!(
+            Resolution::for_range(5 * 24 * 60 * 60),
+            Resolution::FiveMinutes
+        );
+        assert_eq!(
+            Resolution::for_range(30 * 24 * 60 * 60),
+            Resolution::OneHour
+        );
+        assert_eq!(
+            Resolution::for_range(365 * 24 * 60 * 60),
+            Resolution::OneHour
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fold_all_is_incremental() {
+        let storage = in_memory().await;
+        insert_reading(&storage, 1, 10.0, "2026-01-19 10:00:05.000000").await;
+        insert_reading(&storage, 1, 20.0, "2026-01-19 10:00:40.000000").await;
+        assert_eq!(fold_all(&storage).await.unwrap(), 2);
+
+        insert_reading(&storage, 1, 60.0, "2026-01-19 10:00:59.000000").await;
+        insert_reading(&storage, 1, 5.0, "2026-01-19 10:03:00.000000").await;
+        assert_eq!(fold_all(&storage).await.unwrap(), 2);
+        assert_eq!(fold_all(&storage).await.unwrap(), 0);
+
+        let minutes = storage
+            .rollup_points(1, 60, "2026-01-19 10:00:00", "2026-01-19 11:00:00", 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            minutes[0],
+            SeriesPoint {
+                timestamp: "2026-01-19 10:00:00".to_string(),
+                mean: 30.0,
+                min: 10.0,
+                max: 60.0,
+                count: 3,
+            }
+        );
+        assert_eq!(minutes[1].timestamp, "2026-01-19 10:03:00");
+
+        let hours = storage
+            .rollup_points(1, 3600, "2026-01-19 10:00:00", "2026-01-19 11:00:00", 10)
+            .await
+            .unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].count, 4);
+        assert_eq!(hours[0].min, 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_query_series_auto_and_raw() {
+        let storage = in_memory().await;
+        for minute in 0..3 {
+            let ts = format!("2026-01-19 10:0{}:30.000000", minute);
+            insert_reading(&storage, 4, minute as f64, &ts).await;
+        }
+        fold_all(&storage).await.unwrap();
+
+        let auto = query_series(
+            &storage,
+            4,
+            query(
+                "2026-01-19T10:00:00",
+                "2026-01-19T11:00:00",
+                Resolution::Auto,
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(auto.resolution, Resolution::OneMinute);
+        assert_eq!(auto.points.len(), 3);
+
+        let raw = query_series(
+            &storage,
+            4,
+            query(
+                "2026-01-19T10:00:00",
+                "2026-01-19T10:01:00",
+                Resolution::Raw,
+            ),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(raw.points.len(), 1);
+        assert_eq!(raw.points[0].count, 1);
+    }
+}
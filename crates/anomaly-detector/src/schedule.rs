@@ -0,0 +1,185 @@
+//! Cron-style schedules for background jobs, in UTC.
+//!
+//! A schedule has the five fields of a crontab line: minute (0-59), hour
+//! (0-23), day of month (1-31), month (1-12) and day of week (0-7, Sunday
+//! being 0 or 7). Each field is `*` or a comma-separated list of values and
+//! `a-b` ranges, any of them with a `/step`. As in cron, when both the day
+//! of month and the day of week are restricted a day matching either runs.
+//!
+//! ```text
+//! 30 2 * * *      02:30 every day
+//! 0 */6 * * 1-5   every six hours on weekdays
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use detection_core::calendar::civil_from_days;
+
+/// How far ahead a run is looked for, covering a schedule for 29 February.
+const MAX_DAYS_AHEAD: i64 = 8 * 366;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    /// Whether the day of month and day of week fields are not `*`.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = source.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!(
+                "schedule {:?} must have 5 fields: minute hour day month weekday",
+                source
+            ));
+        };
+        let restricted = |raw: &str| raw != "*";
+        let (days_restricted, weekdays_restricted) = (restricted(days), restricted(weekdays));
+        let mut weekdays = field(weekdays, "weekday", 0, 7)?;
+        // Sunday is both 0 and 7.
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Self {
+            source: source.to_string(),
+            minutes: field(minutes, "minute", 0, 59)?,
+            hours: field(hours, "hour", 0, 23)?,
+            days: field(days, "day", 1, 31)?,
+            months: field(months, "month", 1, 12)?,
+            weekdays,
+            days_restricted,
+            weekdays_restricted,
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// The values of one field as a bit set.
+fn field(raw: &str, name: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("invalid {} field {:?}", name, raw);
+    let mut set = 0u64;
+    for item in raw.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| invalid())?),
+            None => (item, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                start.parse().map_err(|_| invalid())?,
+                end.parse().map_err(|_| invalid())?,
+            ),
+            // `5/15` runs from 5 to the end of the field.
+            None if item.contains('/') => (range.parse().map_err(|_| invalid())?, max),
+            None => {
+                let value = range.parse().map_err(|_| invalid())?;
+                (value, value)
+            }
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(format!(
+                "invalid {} field {:?}; values are {}-{}",
+                name, raw, min, max
+            ));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            set |= 1 << value;
+        }
+    }
+    Ok(set)
+}
+
+impl Schedule {
+    fn runs_on(&self, days_since_epoch: i64) -> bool {
+        let (_, month, day) = civil_from_days(days_since_epoch);
+        // The epoch was a Thursday.
+        let weekday = (days_since_epoch + 4).rem_euclid(7);
+        let on_day = self.days & (1 << day) != 0;
+        let on_weekday = self.weekdays & (1 << weekday) != 0;
+        let on_date = match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => on_day || on_weekday,
+            _ => on_day && on_weekday,
+        };
+        on_date && self.months & (1 << month) != 0
+    }
+
+    /// The first run strictly after `seconds` since the Unix epoch, or `None`
+    /// for a schedule that never runs, like 31 February.
+    pub fn next_after(&self, seconds: i64) -> Option<i64> {
+        let start = seconds.div_euclid(60) + 1;
+        let first_day = start.div_euclid(24 * 60);
+        for day in first_day..first_day + MAX_DAYS_AHEAD {
+            if !self.runs_on(day) {
+                continue;
+            }
+            for hour in (0..24).filter(|hour| self.hours & (1 << hour) != 0) {
+                for minute in (0..60).filter(|minute| self.minutes & (1 << minute) != 0) {
+                    let at = (day * 24 + hour) * 60 + minute;
+                    if at >= start {
+                        return Some(at * 60);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time::{format_timestamp, parse_timestamp};
+
+    fn next(schedule: &str, after: &str) -> Option<String> {
+        let schedule: Schedule = schedule.parse().unwrap();
+        schedule
+            .next_after(parse_timestamp(after).unwrap())
+            .map(format_timestamp)
+    }
+
+    #[test]
+    fn test_next_run() {
+        let after = "2026-01-19T10:15:30";
+        assert_eq!(next("* * * * *", after).unwrap(), "2026-01-19T10:16:00");
+        assert_eq!(next("30 2 * * *", after).unwrap(), "2026-01-20T02:30:00");
+        assert_eq!(next("*/20 10 * * *", after).unwrap(), "2026-01-19T10:20:00");
+        assert_eq!(next("0 */6 * * *", after).unwrap(), "2026-01-19T12:00:00");
+        // 2026-01-19 is a Monday.
+        assert_eq!(next("0 0 * * 0", after).unwrap(), "2026-01-25T00:00:00");
+        assert_eq!(next("0 0 * * 7", after).unwrap(), "2026-01-25T00:00:00");
+        assert_eq!(next("0 0 * 3 *", after).unwrap(), "2026-03-01T00:00:00");
+        // Either the day of month or the day of week.
+        assert_eq!(next("0 0 1 * 3", after).unwrap(), "2026-01-21T00:00:00");
+        assert_eq!(next("0 0 29 2 *", after).unwrap(), "2028-02-29T00:00:00");
+        assert_eq!(next("0 0 31 2 *", after), None);
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        for invalid in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 5-2 * * *",
+            "*/0 * * * *",
+            "x * * * *",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{:?}", invalid);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! The published schemas, served under `/schemas`, and the check of outgoing
+//! payloads against them.
+//!
+//! Debug builds, the tests included, validate every alert sent to webhooks
+//! and WebSocket subscribers and panic on one the schema rejects, so a field
+//! changed without the schema fails there rather than at a consumer. Release
+//! builds skip the check.
+
+use std::sync::LazyLock;
+
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+use detection_core::schema;
+use serde::Serialize;
+use serde_json::Value;
+
+static ALERT_VALIDATOR: LazyLock<jsonschema::Validator> = LazyLock::new(|| {
+    let alert = serde_json::from_str(schema::ALERT).expect("the alert schema is JSON");
+    jsonschema::validator_for(&alert).expect("the alert schema is a valid schema")
+});
+
+pub async fn alert() -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/schema+json"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        schema::ALERT,
+    )
+        .into_response()
+}
+
+/// Panics if `alert` does not conform to the alert schema, in debug builds.
+pub fn check_alert(alert: &impl Serialize) {
+    if cfg!(debug_assertions) {
+        let alert = serde_json::to_value(alert).expect("alerts serialize to JSON");
+        let violations = alert_violations(&alert);
+        assert!(
+            violations.is_empty(),
+            "alert {} does not conform to /schemas/alert.json: {}",
+            alert,
+            violations.join("; ")
+        );
+    }
+}
+
+fn alert_violations(alert: &Value) -> Vec<String> {
+    ALERT_VALIDATOR
+        .iter_errors(alert)
+        .map(|error| format!("{} at {}", error, error.instance_path()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::to_bytes;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_the_alert_schema() {
+        let response = alert().await;
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "application/schema+json"
+        );
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let served: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(served["version"], schema::ALERT_VERSION);
+    }
+
+    #[test]
+    fn test_alert_violations() {
+        let mut alert = json!({
+            "sensor_id": 7,
+            "reading_id": 42,
+            "value": 99.5,
+            "timestamp": "2026-01-19 10:00:00",
+            "method": "zscore",
+            "score": 3.2,
+            "severity": "high",
+        });
+        assert!(alert_violations(&alert).is_empty());
+        alert["id"] = json!(1);
+        alert["detected_at"] = json!("2026-01-19 10:00:01");
+        alert["dedup_key"] = json!("7-zscore-42-2026-01-19 10:00:00-high");
+        alert["hints"] = json!([{
+            "id": 3, "changed_at": "2026-01-19 09:30:00", "actor": "alice",
+            "resource": "sensor", "resource_id": "7", "action": "update",
+            "before": { "threshold": 3.0 }, "after": { "threshold": 2.0 },
+        }]);
+        assert!(alert_violations(&alert).is_empty());
+
+        alert["severity"] = json!("");
+        alert["extra"] = json!(true);
+        alert.as_object_mut().unwrap().remove("score");
+        assert_eq!(alert_violations(&alert).len(), 3);
+    }
+}
@@ -0,0 +1,424 @@
+//! Bulk export and import of the whole sensor registry, as JSON or CSV.
+//!
+//! An import replaces the entries it lists and is all or nothing: every entry
+//! is validated and they are written in one transaction, so a rejected file
+//! leaves the registry untouched and reports every problem at once. With
+//! `?dry_run=true` the same checks run, including that each sensor exists,
+//! and nothing is kept.
+//!
+//! CSV files have the columns `sensor_id,name,unit,tags,threshold,min_value,
+//! max_value,reference`; `tags` are separated by `;`, empty cells
+//! are unset and the `reference` column may be left out. Sensor rules only
+//! travel as JSON.
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use super::{RegisteredSensor, SensorConfig, internal, is_missing_sensor, load, storage};
+use crate::audit::{self, Change};
+use crate::{AppState, export};
+
+/// Largest accepted import body; a plant's registry is a few megabytes.
+pub const MAX_IMPORT_BYTES: usize = 32 * 1024 * 1024;
+const MAX_IMPORT_ENTRIES: usize = 50_000;
+const TAG_SEPARATOR: char = ';';
+
+#[derive(Debug, Deserialize, Serialize)]
+struct CsvRow {
+    sensor_id: i64,
+    name: String,
+    unit: String,
+    tags: String,
+    threshold: Option<f64>,
+    min_value: Option<f64>,
+    max_value: Option<f64>,
+    #[serde(default)]
+    reference: Option<i64>,
+}
+
+impl From<RegisteredSensor> for CsvRow {
+    fn from(sensor: RegisteredSensor) -> Self {
+        let config = sensor.config;
+        CsvRow {
+            sensor_id: sensor.sensor_id,
+            name: config.name,
+            unit: config.unit,
+            tags: config.tags.join(&TAG_SEPARATOR.to_string()),
+            threshold: config.threshold,
+            min_value: config.min_value,
+            max_value: config.max_value,
+            reference: config.reference,
+        }
+    }
+}
+
+impl TryFrom<CsvRow> for RegisteredSensor {
+    type Error = String;
+
+    fn try_from(row: CsvRow) -> Result<Self, String> {
+        let tags = row
+            .tags
+            .split(TAG_SEPARATOR)
+            .filter(|tag| !tag.trim().is_empty())
+            .map(str::to_string)
+            .collect();
+        Ok(RegisteredSensor {
+            sensor_id: row.sensor_id,
+            config: SensorConfig {
+                name: row.name,
+                unit: row.unit,
+                tags,
+                threshold: row.threshold,
+                min_value: row.min_value,
+                max_value: row.max_value,
+                reference: row.reference,
+                rules: Default::default(),
+            },
+        })
+    }
+}
+
+/// Exports every registry entry, as CSV for `Accept: text/csv`.
+pub async fn export_sensors(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let sensors = load(storage(&state)?, None).await.map_err(internal)?;
+    if !export::wants_csv(&headers) {
+        return Ok(Json(sensors).into_response());
+    }
+
+    if let Some(sensor) = sensors
+        .iter()
+        .find(|s| s.config.tags.iter().any(|t| t.contains(TAG_SEPARATOR)))
+    {
+        return Err((
+            StatusCode::NOT_ACCEPTABLE,
+            format!(
+                "sensor {} has a tag containing {:?}; export it as JSON",
+                sensor.sensor_id, TAG_SEPARATOR
+            ),
+        ));
+    }
+    if let Some(sensor) = sensors.iter().find(|s| !s.config.rules.is_empty()) {
+        return Err((
+            StatusCode::NOT_ACCEPTABLE,
+            format!("sensor {} has rules; export it as JSON", sensor.sensor_id),
+        ));
+    }
+    let rows: Vec<CsvRow> = sensors.into_iter().map(CsvRow::from).collect();
+    let body = export::encode(&rows, true).map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("failed to encode CSV: {}", e),
+        )
+    })?;
+    Ok(export::csv_response(body))
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// A rejected entry; `entry` counts from 1 in file order, excluding the CSV
+/// header.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct EntryError {
+    entry: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sensor_id: Option<i64>,
+    error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    dry_run: bool,
+    entries: usize,
+    created: u64,
+    updated: u64,
+    errors: Vec<EntryError>,
+}
+
+/// Parses the body as CSV for `Content-Type: text/csv`, otherwise as a JSON
+/// array, keeping the entries that parse and an error for each that does not.
+fn parse(
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<(Vec<RegisteredSensor>, Vec<EntryError>), String> {
+    let is_csv = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim_start().to_ascii_lowercase().starts_with("text/csv"));
+
+    let mut sensors = Vec::new();
+    let mut errors = Vec::new();
+    let mut push = |entry: usize, parsed: Result<RegisteredSensor, String>| match parsed {
+        Ok(sensor) => sensors.push(sensor),
+        Err(error) => errors.push(EntryError {
+            entry,
+            sensor_id: None,
+            error,
+        }),
+    };
+    if is_csv {
+        let mut reader = csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(body);
+        for (index, row) in reader.deserialize::<CsvRow>().enumerate() {
+            push(
+                index + 1,
+                row.map_err(|e| e.to_string())
+                    .and_then(RegisteredSensor::try_from),
+            );
+        }
+    } else {
+        let values: Vec<serde_json::Value> = serde_json::from_slice(body)
+            .map_err(|e| format!("body must be a JSON array of sensors: {}", e))?;
+        for (index, value) in values.into_iter().enumerate() {
+            push(
+                index + 1,
+                serde_json::from_value(value).map_err(|e| e.to_string()),
+            );
+        }
+    }
+    Ok((sensors, errors))
+}
+
+/// Imports registry entries; responds 422 with the report if any is rejected.
+pub async fn import_sensors(
+    State(state): State<AppState>,
+    Query(query): Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<ImportReport>), (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let (sensors, mut errors) = parse(&headers, &body).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let entries = sensors.len() + errors.len();
+    if entries > MAX_IMPORT_ENTRIES {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "import contains {} entries, the limit is {}",
+                entries, MAX_IMPORT_ENTRIES
+            ),
+        ));
+    }
+
+    // Entry numbers of the parsed sensors, skipping those that failed to parse.
+    let failed: HashSet<usize> = errors.iter().map(|e| e.entry).collect();
+    let numbers: Vec<usize> = (1..=entries).filter(|n| !failed.contains(n)).collect();
+    let mut seen = HashSet::new();
+    let mut valid = Vec::with_capacity(sensors.len());
+    for (mut sensor, &entry) in sensors.into_iter().zip(&numbers) {
+        let checked = if seen.insert(sensor.sensor_id) {
+            sensor.validate()
+        } else {
+            Err("sensor_id appears more than once".to_string())
+        };
+        match checked {
+            Ok(()) => valid.push(sensor),
+            Err(error) => errors.push(EntryError {
+                entry,
+                sensor_id: Some(sensor.sensor_id),
+                error,
+            }),
+        }
+    }
+
+    let mut report = ImportReport {
+        dry_run: query.dry_run,
+        entries,
+        created: 0,
+        updated: 0,
+        errors,
+    };
+    if !report.errors.is_empty() {
+        report.errors.sort_by_key(|e| e.entry);
+        return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(report)));
+    }
+
+    let ids: Vec<i64> = valid.iter().map(|s| s.sensor_id).collect();
+    let mut previous: HashMap<i64, RegisteredSensor> = load(storage, Some(&ids))
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .map(|sensor| (sensor.sensor_id, sensor))
+        .collect();
+    let rows: Vec<_> = valid
+        .iter()
+        .map(|sensor| (sensor.to_stored(), sensor.config.tags.clone()))
+        .collect();
+    match storage.import_sensor_configs(&rows, query.dry_run).await {
+        Ok(created) => {
+            report.created = created;
+            report.updated = valid.len() as u64 - created;
+        }
+        Err((Some(index), e)) if is_missing_sensor(&e) => {
+            report.errors.push(EntryError {
+                entry: numbers[index],
+                sensor_id: Some(valid[index].sensor_id),
+                error: "sensor does not exist".to_string(),
+            });
+            return Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(report)));
+        }
+        Err((_, e)) => return Err(internal(e)),
+    }
+    if !query.dry_run {
+        let changes = valid.iter().map(|sensor| {
+            let before = previous.remove(&sensor.sensor_id);
+            Change::new(
+                "sensor",
+                Some(sensor.sensor_id.to_string()),
+                before.as_ref(),
+                Some(sensor),
+            )
+        });
+        audit::record(storage, &audit::actor(&headers), changes).await;
+        println!(
+            "Admin: imported {} sensors ({} new)",
+            report.entries, report.created
+        );
+    }
+    Ok((StatusCode::OK, Json(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::{enforce_sensors, in_memory};
+    use axum::http::HeaderValue;
+
+    async fn state() -> AppState {
+        AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        }
+    }
+
+    fn content_type(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(value));
+        headers
+    }
+
+    async fn import(
+        state: &AppState,
+        dry_run: bool,
+        csv: &'static str,
+    ) -> (StatusCode, ImportReport) {
+        let (status, Json(report)) = import_sensors(
+            State(state.clone()),
+            Query(ImportQuery { dry_run }),
+            content_type("text/csv"),
+            Bytes::from_static(csv.as_bytes()),
+        )
+        .await
+        .unwrap();
+        (status, report)
+    }
+
+    const PLANT: &str = "\
+sensor_id,name,unit,tags,threshold,min_value,max_value
+1,Boiler,°C,line:a;critical-path,3.0,,95
+2,Pump,bar,,,0,
+";
+
+    #[tokio::test]
+    async fn test_csv_import_round_trips_through_export() {
+        let state = state().await;
+
+        let (status, report) = import(&state, true, PLANT).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report.created, 2);
+        let Json(sensors) = super::super::list_sensors(State(state.clone()))
+            .await
+            .unwrap();
+        assert!(sensors.is_empty(), "dry run wrote {:?}", sensors);
+
+        import(&state, false, PLANT).await;
+        let (_, report) = import(&state, false, PLANT).await;
+        assert_eq!((report.created, report.updated), (0, 2));
+
+        let mut accept = HeaderMap::new();
+        accept.insert(header::ACCEPT, HeaderValue::from_static("text/csv"));
+        let response = export_sensors(State(state.clone()), accept).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let exported = String::from_utf8(body.to_vec()).unwrap();
+        assert_eq!(
+            exported.lines().nth(1),
+            Some("1,Boiler,°C,critical-path;line:a,3.0,,95.0,")
+        );
+
+        let json = export_sensors(State(state.clone()), HeaderMap::new())
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(json.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let (status, Json(report)) = import_sensors(
+            State(state),
+            Query(ImportQuery { dry_run: false }),
+            HeaderMap::new(),
+            body,
+        )
+        .await
+        .unwrap();
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report.updated, 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_reports_every_rejected_entry() {
+        let state = state().await;
+        let (status, report) = import(
+            &state,
+            false,
+            "\
+sensor_id,name,unit,tags,threshold,min_value,max_value
+1,Boiler,°C,,,,
+x,Broken,,,,,
+3,,bar,,,,
+1,Again,°C,,,,
+5,Flow,m3/h,,-1,,
+",
+        )
+        .await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let entries: Vec<usize> = report.errors.iter().map(|e| e.entry).collect();
+        assert_eq!(entries, vec![2, 3, 4, 5]);
+        assert_eq!(report.errors[2].sensor_id, Some(1));
+        let Json(sensors) = super::super::list_sensors(State(state)).await.unwrap();
+        assert!(sensors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_unknown_sensors() {
+        let state = state().await;
+        enforce_sensors(state.storage.as_ref().unwrap(), &[1]).await;
+
+        let (status, report) = import(&state, true, PLANT).await;
+
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            report.errors,
+            vec![EntryError {
+                entry: 2,
+                sensor_id: Some(2),
+                error: "sensor does not exist".to_string(),
+            }]
+        );
+    }
+}
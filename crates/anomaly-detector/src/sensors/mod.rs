@@ -0,0 +1,509 @@
+//! Sensor registry: per-sensor metadata and detection configuration.
+//!
+//! `/analyze` requests naming a `sensor_id`, batch series and backfills use
+//! the registered threshold when they do not set their own, and a
+//! reading outside the registered `[min_value, max_value]` is always an
+//! anomaly with `critical` severity. Sensors without an entry use the runtime
+//! defaults. A `reference` names the canary of the sensor's cohort, which
+//! batch series with method `reference` are compared against, see
+//! `reference`.
+//!
+//! `rules` are `condition => severity` rules on each reading's `value` and
+//! `score` (see `detection_core::rules`), tried in order before the
+//! detector and the limits: the first matching grades the reading, or
+//! clears it with `normal`. The Python bindings' `rule_outliers` takes the
+//! same rules, so a rule tried out in a batch job grades the same online.
+//!
+//! ```json
+//! {
+//!   "sensor_id": 7,
+//!   "name": "Boiler 2 outlet",
+//!   "unit": "°C",
+//!   "tags": ["line:a"],
+//!   "threshold": 3.0,
+//!   "max_value": 95.0,
+//!   "rules": ["abs(score) > 4 and value > 80 => critical", "value < 1 => normal"]
+//! }
+//! ```
+
+pub mod bulk;
+
+use std::collections::HashMap;
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use detection_core::Severity;
+use detection_core::rules::{Reading, Rules};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, Change};
+use crate::redaction;
+use crate::storage::{Storage, StoredSensorConfig};
+use crate::{AppState, ZSCORE_METHOD, tags};
+
+const MAX_NAME_LENGTH: usize = 200;
+const MAX_UNIT_LENGTH: usize = 50;
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+pub struct SensorConfig {
+    pub name: String,
+    #[serde(default)]
+    pub unit: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Z-score threshold; defaults to the runtime `default_threshold`.
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    /// Readings below this are critical anomalies.
+    #[serde(default)]
+    pub min_value: Option<f64>,
+    /// Readings above this are critical anomalies.
+    #[serde(default)]
+    pub max_value: Option<f64>,
+    /// The reference (canary) sensor this one is compared against.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference: Option<i64>,
+    /// Grade readings before the detector does.
+    #[serde(default, skip_serializing_if = "Rules::is_empty")]
+    pub rules: Rules,
+}
+
+impl SensorConfig {
+    /// Whether `value` falls outside the configured hard limits.
+    pub fn out_of_range(&self, value: f64) -> bool {
+        self.min_value.is_some_and(|min| value < min)
+            || self.max_value.is_some_and(|max| value > max)
+    }
+
+    /// The severity of a reading scored `score`, given the detector's
+    /// `detected`: the first matching rule's verdict, else `detected`.
+    pub fn grade(&self, value: f64, score: f64, detected: Option<Severity>) -> Option<Severity> {
+        self.rules.grade(&Reading { value, score }, detected)
+    }
+
+    fn validate(&mut self) -> Result<(), String> {
+        self.name = self.name.trim().to_string();
+        self.unit = self.unit.trim().to_string();
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LENGTH {
+            return Err(format!("name must be 1 to {} characters", MAX_NAME_LENGTH));
+        }
+        if self.unit.len() > MAX_UNIT_LENGTH {
+            return Err(format!(
+                "unit must be at most {} characters",
+                MAX_UNIT_LENGTH
+            ));
+        }
+        tags::normalize(&mut self.tags)?;
+        if let Some(threshold) = self.threshold
+            && (!threshold.is_finite() || threshold <= 0.0)
+        {
+            return Err(format!(
+                "threshold must be a positive number, got {}",
+                threshold
+            ));
+        }
+        for (name, limit) in [("min_value", self.min_value), ("max_value", self.max_value)] {
+            if limit.is_some_and(|limit| !limit.is_finite()) {
+                return Err(format!("{} must be a finite number", name));
+            }
+        }
+        if let (Some(min), Some(max)) = (self.min_value, self.max_value)
+            && min >= max
+        {
+            return Err("min_value must be below max_value".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RegisteredSensor {
+    pub sensor_id: i64,
+    #[serde(flatten)]
+    pub config: SensorConfig,
+}
+
+impl RegisteredSensor {
+    fn validate(&mut self) -> Result<(), String> {
+        self.config.validate()?;
+        if self.config.reference == Some(self.sensor_id) {
+            return Err("a sensor cannot be its own reference".to_string());
+        }
+        // Every entry stored passes through here, so none keeps what is redacted.
+        if let Some(redaction) = redaction::current() {
+            redaction.sensor(&mut self.config);
+        }
+        Ok(())
+    }
+
+    fn to_stored(&self) -> StoredSensorConfig {
+        StoredSensorConfig {
+            sensor_id: self.sensor_id,
+            name: self.config.name.clone(),
+            unit: self.config.unit.clone(),
+            method: ZSCORE_METHOD.to_string(),
+            threshold: self.config.threshold,
+            min_value: self.config.min_value,
+            max_value: self.config.max_value,
+            reference_id: self.config.reference,
+            rules: (!self.config.rules.is_empty())
+                .then(|| serde_json::to_string(&self.config.rules).unwrap_or_default()),
+        }
+    }
+
+    fn from_stored(
+        stored: StoredSensorConfig,
+        tags: &mut HashMap<i64, Vec<String>>,
+    ) -> Result<Self, String> {
+        let rules = match &stored.rules {
+            Some(rules) => serde_json::from_str(rules)
+                .map_err(|e| format!("sensor {} has invalid rules: {}", stored.sensor_id, e))?,
+            None => Rules::default(),
+        };
+        Ok(RegisteredSensor {
+            sensor_id: stored.sensor_id,
+            config: SensorConfig {
+                name: stored.name,
+                unit: stored.unit,
+                tags: tags.remove(&stored.sensor_id).unwrap_or_default(),
+                threshold: stored.threshold,
+                min_value: stored.min_value,
+                max_value: stored.max_value,
+                reference: stored.reference_id,
+                rules,
+            },
+        })
+    }
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Whether writing an entry failed because its sensor is not in `sensors`.
+pub fn is_missing_sensor(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .is_some_and(|e| e.is_foreign_key_violation())
+}
+
+fn not_found(sensor_id: i64) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("sensor {} is not registered", sensor_id),
+    )
+}
+
+/// Registry entries of `sensor_ids`, or of every sensor when `None`.
+async fn load(
+    storage: &Storage,
+    sensor_ids: Option<&[i64]>,
+) -> Result<Vec<RegisteredSensor>, String> {
+    let stored = storage
+        .sensor_configs(sensor_ids)
+        .await
+        .map_err(|e| e.to_string())?;
+    let ids: Vec<i64> = stored.iter().map(|s| s.sensor_id).collect();
+    let mut tags = storage.sensor_tags(&ids).await.map_err(|e| e.to_string())?;
+    stored
+        .into_iter()
+        .map(|s| RegisteredSensor::from_stored(s, &mut tags))
+        .collect()
+}
+
+/// Configurations of those of `sensor_ids` that are registered, for the
+/// detection paths; empty when storage is not configured.
+pub async fn lookup(
+    state: &AppState,
+    sensor_ids: &[i64],
+) -> Result<HashMap<i64, SensorConfig>, (StatusCode, String)> {
+    let Some(storage) = &state.storage else {
+        return Ok(HashMap::new());
+    };
+    if sensor_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let sensors = load(storage, Some(sensor_ids)).await.map_err(internal)?;
+    Ok(sensors
+        .into_iter()
+        .map(|sensor| (sensor.sensor_id, sensor.config))
+        .collect())
+}
+
+/// Configuration of `sensor_id`, if one is given and registered.
+pub async fn registered(
+    state: &AppState,
+    sensor_id: Option<i64>,
+) -> Result<Option<SensorConfig>, (StatusCode, String)> {
+    let mut found = lookup(state, sensor_id.as_slice()).await?;
+    Ok(sensor_id.and_then(|id| found.remove(&id)))
+}
+
+async fn save(
+    storage: &Storage,
+    mut sensor: RegisteredSensor,
+    replace: bool,
+) -> Result<Option<RegisteredSensor>, (StatusCode, String)> {
+    sensor
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    match storage
+        .save_sensor_config(&sensor.to_stored(), &sensor.config.tags, replace)
+        .await
+    {
+        Ok(true) => Ok(Some(sensor)),
+        Ok(false) => Ok(None),
+        Err(e) if is_missing_sensor(&e) => Err((
+            StatusCode::NOT_FOUND,
+            format!("sensor {} does not exist", sensor.sensor_id),
+        )),
+        Err(e) => Err(internal(e)),
+    }
+}
+
+pub async fn list_sensors(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<RegisteredSensor>>, (StatusCode, String)> {
+    let sensors = load(storage(&state)?, None).await.map_err(internal)?;
+    Ok(Json(sensors))
+}
+
+/// Records a change to `sensor_id`'s registry entry.
+async fn audit(
+    storage: &Storage,
+    headers: &HeaderMap,
+    sensor_id: i64,
+    before: Option<&RegisteredSensor>,
+    after: Option<&RegisteredSensor>,
+) {
+    let change = Change::new("sensor", Some(sensor_id.to_string()), before, after);
+    audit::record(storage, &audit::actor(headers), [change]).await;
+}
+
+/// The registry entry of `sensor_id`, if it has one.
+async fn find(
+    storage: &Storage,
+    sensor_id: i64,
+) -> Result<Option<RegisteredSensor>, (StatusCode, String)> {
+    let mut sensors = load(storage, Some(&[sensor_id])).await.map_err(internal)?;
+    Ok(sensors.pop())
+}
+
+/// Registers a sensor; fails with 409 if it already is.
+pub async fn create_sensor(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<RegisteredSensor>,
+) -> Result<(StatusCode, Json<RegisteredSensor>), (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let sensor_id = payload.sensor_id;
+    match save(storage, payload, false).await? {
+        Some(sensor) => {
+            audit(storage, &headers, sensor_id, None, Some(&sensor)).await;
+            Ok((StatusCode::CREATED, Json(sensor)))
+        }
+        None => Err((
+            StatusCode::CONFLICT,
+            format!("sensor {} is already registered", sensor_id),
+        )),
+    }
+}
+
+pub async fn get_sensor(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+) -> Result<Json<RegisteredSensor>, (StatusCode, String)> {
+    let sensor = find(storage(&state)?, sensor_id).await?;
+    sensor.map(Json).ok_or_else(|| not_found(sensor_id))
+}
+
+/// Registers a sensor or replaces its entry, including its tags.
+pub async fn put_sensor(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    headers: HeaderMap,
+    Json(config): Json<SensorConfig>,
+) -> Result<Json<RegisteredSensor>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let previous = find(storage, sensor_id).await?;
+    let sensor = RegisteredSensor { sensor_id, config };
+    let saved = save(storage, sensor, true)
+        .await?
+        .ok_or_else(|| not_found(sensor_id))?;
+    audit(
+        storage,
+        &headers,
+        sensor_id,
+        previous.as_ref(),
+        Some(&saved),
+    )
+    .await;
+    Ok(Json(saved))
+}
+
+/// Removes a sensor's entry and tags; its readings and anomalies are kept.
+pub async fn delete_sensor(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let previous = find(storage, sensor_id).await?;
+    if storage
+        .delete_sensor_config(sensor_id)
+        .await
+        .map_err(internal)?
+    {
+        audit(storage, &headers, sensor_id, previous.as_ref(), None).await;
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(not_found(sensor_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::AuditFilter;
+    use crate::storage::testing::in_memory;
+    use serde_json::json;
+
+    async fn state() -> AppState {
+        AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        }
+    }
+
+    fn sensor(value: serde_json::Value) -> RegisteredSensor {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_crud() {
+        let state = state().await;
+        let boiler = sensor(json!({
+            "sensor_id": 7,
+            "name": " Boiler ",
+            "unit": "°C",
+            "tags": ["line:a", "line:a"],
+            "threshold": 3.0,
+        }));
+
+        let (status, Json(created)) =
+            create_sensor(State(state.clone()), HeaderMap::new(), Json(boiler.clone()))
+                .await
+                .unwrap();
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created.config.name, "Boiler");
+        assert_eq!(created.config.tags, vec!["line:a"]);
+        let result = create_sensor(State(state.clone()), HeaderMap::new(), Json(boiler)).await;
+        assert_eq!(result.unwrap_err().0, StatusCode::CONFLICT);
+
+        let mut changed = created.config.clone();
+        changed.max_value = Some(95.0);
+        changed.reference = Some(8);
+        changed.rules = Rules::parse(["value > 90 => high"]).unwrap();
+        changed.tags.clear();
+        let Json(replaced) = put_sensor(
+            State(state.clone()),
+            Path(7),
+            HeaderMap::new(),
+            Json(changed.clone()),
+        )
+        .await
+        .unwrap();
+        let Json(fetched) = get_sensor(State(state.clone()), Path(7)).await.unwrap();
+        assert_eq!(fetched, replaced);
+        assert_eq!(fetched.config, changed);
+        let Json(listed) = list_sensors(State(state.clone())).await.unwrap();
+        assert_eq!(listed, vec![fetched]);
+
+        assert_eq!(
+            delete_sensor(State(state.clone()), Path(7), HeaderMap::new())
+                .await
+                .unwrap(),
+            StatusCode::NO_CONTENT
+        );
+        for result in [
+            get_sensor(State(state.clone()), Path(7)).await.map(|_| ()),
+            delete_sensor(State(state.clone()), Path(7), HeaderMap::new())
+                .await
+                .map(|_| ()),
+        ] {
+            assert_eq!(result.unwrap_err().0, StatusCode::NOT_FOUND);
+        }
+
+        let audited = state
+            .storage
+            .unwrap()
+            .audit_entries(&AuditFilter::default(), 10)
+            .await
+            .unwrap();
+        let actions: Vec<&str> = audited.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, vec!["delete", "update", "create"]);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_configs_are_rejected() {
+        let state = state().await;
+        let invalid = [
+            json!({ "name": "" }),
+            json!({ "name": "a", "threshold": 0.0 }),
+            json!({ "name": "a", "min_value": 10.0, "max_value": 5.0 }),
+            json!({ "name": "a", "tags": [" "] }),
+            json!({ "name": "a", "reference": 1 }),
+        ];
+        for value in invalid {
+            let config = serde_json::from_value(value).unwrap();
+            let result = put_sensor(
+                State(state.clone()),
+                Path(1),
+                HeaderMap::new(),
+                Json(config),
+            )
+            .await;
+            assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let config: SensorConfig =
+            serde_json::from_value(json!({ "name": "a", "min_value": 0.0, "max_value": 10.0 }))
+                .unwrap();
+        assert!(!config.out_of_range(10.0));
+        assert!(config.out_of_range(-0.5));
+        assert!(config.out_of_range(10.5));
+    }
+
+    #[test]
+    fn test_rules_override_detection() {
+        let config: SensorConfig = serde_json::from_value(json!({
+            "name": "a",
+            "rules": ["value < 0 => normal", "abs(score) > 2 and value > 50 => high"],
+        }))
+        .unwrap();
+        assert_eq!(config.grade(-1.0, 4.0, Some(Severity::Critical)), None);
+        assert_eq!(config.grade(60.0, 2.5, None), Some(Severity::High));
+        assert_eq!(
+            config.grade(40.0, 2.5, Some(Severity::Medium)),
+            Some(Severity::Medium)
+        );
+
+        let invalid = serde_json::from_value::<SensorConfig>(json!({
+            "name": "a",
+            "rules": ["value >> 1 => high"],
+        }));
+        assert!(invalid.is_err());
+    }
+}
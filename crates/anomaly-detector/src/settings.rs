@@ -0,0 +1,261 @@
+//! Settings that can be changed at runtime through `/admin/config`.
+//!
+//! Every change is recorded in the `config_changes` table with who made it
+//! and the settings before and after, and the latest entry is loaded again on
+//! startup, so changes survive restarts without a redeploy.
+
+use std::sync::RwLock;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+pub use detection_core::SeverityBands;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::audit::{self, Change};
+use crate::notify::ChannelToggles;
+use crate::storage::Storage;
+
+const DEFAULT_AUDIT_LIMIT: i64 = 50;
+const MAX_AUDIT_LIMIT: i64 = 1_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetectionSettings {
+    /// Z-score threshold for requests that do not set one.
+    pub default_threshold: f64,
+    pub severity: SeverityBands,
+}
+
+impl Default for DetectionSettings {
+    fn default() -> Self {
+        Self {
+            default_threshold: 2.0,
+            severity: SeverityBands::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeSettings {
+    pub detection: DetectionSettings,
+    /// Channels that receive notifications; others are skipped.
+    pub notifications: ChannelToggles,
+}
+
+impl RuntimeSettings {
+    fn validate(&self) -> Result<(), String> {
+        let positive = |name: &str, value: f64| {
+            if value.is_finite() && value > 0.0 {
+                Ok(())
+            } else {
+                Err(format!("{} must be a positive number, got {}", name, value))
+            }
+        };
+        let detection = &self.detection;
+        positive("detection.default_threshold", detection.default_threshold)?;
+        positive("detection.severity.high", detection.severity.high)?;
+        positive("detection.severity.critical", detection.severity.critical)?;
+        if detection.severity.critical <= detection.severity.high {
+            return Err("detection.severity.critical must be above high".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Settings {
+    current: RwLock<RuntimeSettings>,
+    /// Serializes updates so each audit entry records the right previous value.
+    update: tokio::sync::Mutex<()>,
+}
+
+impl Settings {
+    /// Settings from the latest audit entry, or the defaults when there is none
+    /// or it cannot be read.
+    pub async fn load(storage: Option<&Storage>) -> Self {
+        let settings = Settings::default();
+        let Some(storage) = storage else {
+            return settings;
+        };
+        match storage.latest_settings().await {
+            Ok(Some(raw)) => match serde_json::from_str::<RuntimeSettings>(&raw) {
+                Ok(loaded) => *settings.current.write().unwrap() = loaded,
+                Err(e) => eprintln!("Warning: Ignoring stored runtime settings: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: Failed to load runtime settings: {}", e),
+        }
+        settings
+    }
+
+    pub fn get(&self) -> RuntimeSettings {
+        *self.current.read().unwrap()
+    }
+
+    pub fn detection(&self) -> DetectionSettings {
+        self.get().detection
+    }
+}
+
+/// Returns the settings in effect.
+pub async fn get_config(State(state): State<AppState>) -> Json<RuntimeSettings> {
+    Json(state.settings.get())
+}
+
+/// Replaces the settings, recording the change in the audit trail.
+pub async fn put_config(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<RuntimeSettings>,
+) -> Result<Json<RuntimeSettings>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL to keep an audit trail".to_string(),
+    ))?;
+    payload
+        .validate()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    let actor = audit::actor(&headers);
+
+    let _update = state.settings.update.lock().await;
+    let previous = state.settings.get();
+    let encode = |settings: &RuntimeSettings| {
+        serde_json::to_string(settings)
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+    };
+    storage
+        .insert_config_change(&actor, &encode(&previous)?, &encode(&payload)?)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    *state.settings.current.write().unwrap() = payload;
+    if let Some(notifier) = &state.notifier {
+        notifier.set_enabled(payload.notifications);
+    }
+    audit::record(
+        storage,
+        &actor,
+        [Change::new(
+            "settings",
+            None,
+            Some(&previous),
+            Some(&payload),
+        )],
+    )
+    .await;
+    println!("Admin: runtime settings changed by {}", actor);
+    Ok(Json(payload))
+}
+
+#[derive(Deserialize)]
+pub struct AuditQuery {
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEntry {
+    id: i64,
+    changed_at: String,
+    actor: String,
+    previous: serde_json::Value,
+    settings: serde_json::Value,
+}
+
+/// Lists recorded changes, newest first.
+pub async fn get_audit(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_AUDIT_LIMIT)
+        .clamp(1, MAX_AUDIT_LIMIT);
+
+    let changes = storage
+        .config_changes(limit)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let decode = |raw: &str| serde_json::from_str(raw).unwrap_or(serde_json::Value::Null);
+    Ok(Json(
+        changes
+            .into_iter()
+            .map(|change| AuditEntry {
+                id: change.id,
+                changed_at: change.changed_at,
+                actor: change.actor,
+                previous: decode(&change.previous),
+                settings: decode(&change.settings),
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::ACTOR_HEADER;
+    use crate::storage::testing::in_memory;
+
+    fn changed() -> RuntimeSettings {
+        let mut settings = RuntimeSettings::default();
+        settings.detection.default_threshold = 3.0;
+        settings.detection.severity.critical = 4.0;
+        settings
+    }
+
+    #[tokio::test]
+    async fn test_put_records_audit_and_survives_reload() {
+        let storage = in_memory().await;
+        let state = AppState {
+            storage: Some(storage.clone()),
+            ..AppState::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(ACTOR_HEADER, "alice".parse().unwrap());
+
+        let Json(stored) = put_config(State(state.clone()), headers, Json(changed()))
+            .await
+            .unwrap();
+
+        let Json(current) = get_config(State(state.clone())).await;
+        assert_eq!(stored, changed());
+        assert_eq!(current, changed());
+        let Json(audit) = get_audit(State(state), Query(AuditQuery { limit: None }))
+            .await
+            .unwrap();
+        assert_eq!(audit.len(), 1);
+        assert_eq!(audit[0].actor, "alice");
+        assert_eq!(audit[0].previous["detection"]["default_threshold"], 2.0);
+        assert_eq!(audit[0].settings["detection"]["default_threshold"], 3.0);
+
+        let reloaded = Settings::load(Some(&storage)).await;
+        assert_eq!(reloaded.get(), changed());
+    }
+
+    #[tokio::test]
+    async fn test_put_rejects_invalid_settings() {
+        let state = AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        };
+        let mut inverted = RuntimeSettings::default();
+        inverted.detection.severity.critical = 2.0;
+        let mut negative = RuntimeSettings::default();
+        negative.detection.default_threshold = -1.0;
+
+        for settings in [inverted, negative] {
+            let result = put_config(State(state.clone()), HeaderMap::new(), Json(settings)).await;
+            assert_eq!(result.unwrap_err().0, StatusCode::UNPROCESSABLE_ENTITY);
+        }
+        assert_eq!(state.settings.get(), RuntimeSettings::default());
+    }
+}
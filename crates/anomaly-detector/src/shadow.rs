@@ -0,0 +1,578 @@
+//! Shadow detectors: a second detector per sensor that runs next to the
+//! primary one on live traffic, to try out parameters before adopting them.
+//!
+//! `/analyze` requests and batch series naming a sensor with a shadow are also
+//! run through the shadow. Its anomalies are logged and stored apart in
+//! `shadow_anomalies`, but never notified or published. How far it agreed with
+//! the primary is recorded per series, and
+//! `GET /sensors/{sensor_id}/shadow/agreement` sums that up over time.
+//!
+//! A shadow takes the same overrides as `/replay`:
+//!
+//! ```json
+//! { "parameters": { "threshold": 2.5, "max_value": 95.0 } }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use detection_core::stats::{ZScorer, summarize};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::{self, Change};
+use crate::replay::Parameters;
+use crate::sensors::{self, SensorConfig};
+use crate::settings::DetectionSettings;
+use crate::storage::{NewAnomaly, NewShadowRun, ShadowAgreement, Storage};
+use crate::{AnalyzeRequest, Anomaly, AppState, ZSCORE_METHOD, classify, resolve_threshold};
+
+/// A sensor's shadow, stored as JSON in `sensor_shadows`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ShadowConfig {
+    #[serde(default)]
+    parameters: Parameters,
+}
+
+/// A shadow resolved against its sensor's registry entry and the runtime
+/// settings.
+#[derive(Clone)]
+pub struct Shadow {
+    sensor_id: i64,
+    threshold: Option<f64>,
+    sensor: SensorConfig,
+    detection: DetectionSettings,
+}
+
+impl Shadow {
+    fn resolve(
+        sensor_id: i64,
+        config: &ShadowConfig,
+        primary: Option<&SensorConfig>,
+        mut detection: DetectionSettings,
+    ) -> Result<Shadow, String> {
+        let mut sensor = primary.cloned().unwrap_or_default();
+        config.parameters.apply(&mut sensor, &mut detection)?;
+        Ok(Shadow {
+            sensor_id,
+            threshold: config.parameters.threshold(),
+            sensor,
+            detection,
+        })
+    }
+
+    /// Runs the shadow over a series the primary is about to analyze.
+    ///
+    /// A threshold requested for the series applies unless the shadow sets
+    /// its own.
+    pub fn detect(&self, request: &AnalyzeRequest) -> ShadowResult {
+        let stats = summarize(request.readings.iter().map(|r| r.value));
+        let threshold = resolve_threshold(
+            self.threshold.or(request.threshold),
+            &self.detection,
+            Some(&self.sensor),
+        );
+        let scorer = ZScorer::new(&stats, threshold);
+        let anomalies = request
+            .readings
+            .iter()
+            .filter_map(|reading| {
+                let (score, severity) =
+                    classify(&scorer, &self.detection, Some(&self.sensor), reading.value)?;
+                Some(NewAnomaly {
+                    reading_id: reading.id,
+                    sensor_id: self.sensor_id,
+                    value: reading.value,
+                    timestamp: reading.timestamp.clone(),
+                    method: ZSCORE_METHOD,
+                    score,
+                    severity,
+                })
+            })
+            .collect();
+        ShadowResult {
+            sensor_id: self.sensor_id,
+            readings: request.readings.len(),
+            anomalies,
+        }
+    }
+}
+
+/// What a shadow found in one series.
+pub struct ShadowResult {
+    sensor_id: i64,
+    readings: usize,
+    anomalies: Vec<NewAnomaly>,
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+fn internal(e: impl std::fmt::Display) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Stored shadow configurations of those of `sensor_ids` that have one.
+async fn load(
+    storage: &Storage,
+    sensor_ids: &[i64],
+) -> Result<HashMap<i64, ShadowConfig>, sqlx::Error> {
+    let stored = storage.shadow_configs(sensor_ids).await?;
+    Ok(stored
+        .into_iter()
+        .filter_map(|(sensor_id, raw)| match serde_json::from_str(&raw) {
+            Ok(config) => Some((sensor_id, config)),
+            Err(e) => {
+                eprintln!(
+                    "Warning: Ignoring invalid shadow for sensor {}: {}",
+                    sensor_id, e
+                );
+                None
+            }
+        })
+        .collect())
+}
+
+/// Shadows of those of `sensor_ids` that have one, for the detection paths.
+///
+/// A shadow that cannot be loaded is logged and left out rather than failing
+/// the primary detection.
+pub async fn lookup(
+    state: &AppState,
+    sensor_ids: &[i64],
+    registry: &HashMap<i64, SensorConfig>,
+) -> HashMap<i64, Shadow> {
+    let Some(storage) = &state.storage else {
+        return HashMap::new();
+    };
+    if sensor_ids.is_empty() {
+        return HashMap::new();
+    }
+    let configs = match load(storage, sensor_ids).await {
+        Ok(configs) => configs,
+        Err(e) => {
+            eprintln!("Error: Failed to load shadow detectors: {}", e);
+            return HashMap::new();
+        }
+    };
+    let detection = state.settings.detection();
+    configs
+        .into_iter()
+        .filter_map(|(sensor_id, config)| {
+            let primary = registry.get(&sensor_id);
+            match Shadow::resolve(sensor_id, &config, primary, detection) {
+                Ok(shadow) => Some((sensor_id, shadow)),
+                Err(e) => {
+                    eprintln!("Warning: Ignoring shadow for sensor {}: {}", sensor_id, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// The shadow of `sensor_id`, if one is given and has a shadow.
+pub async fn configured(
+    state: &AppState,
+    sensor_id: Option<i64>,
+    sensor: Option<&SensorConfig>,
+) -> Option<Shadow> {
+    let sensor_id = sensor_id?;
+    let registry = sensor.map(|s| (sensor_id, s.clone())).into_iter().collect();
+    lookup(state, &[sensor_id], &registry)
+        .await
+        .remove(&sensor_id)
+}
+
+/// Compares a shadow's result with the primary's anomalies for the same
+/// series, logs it and stores both in the background.
+pub fn observe(state: &AppState, result: ShadowResult, primary: &[Anomaly]) {
+    let primary: HashSet<i64> = primary.iter().map(|a| a.id).collect();
+    let shadow: HashSet<i64> = result.anomalies.iter().map(|a| a.reading_id).collect();
+    let both = primary.intersection(&shadow).count();
+    let run = NewShadowRun {
+        sensor_id: result.sensor_id,
+        readings: result.readings as i64,
+        both_flagged: both as i64,
+        primary_only: (primary.len() - both) as i64,
+        shadow_only: (shadow.len() - both) as i64,
+    };
+    if run.primary_only > 0 || run.shadow_only > 0 {
+        println!(
+            "Shadow: Sensor {} disagreed on {} of {} readings ({} flagged by the primary only, {} by the shadow only)",
+            run.sensor_id,
+            run.primary_only + run.shadow_only,
+            run.readings,
+            run.primary_only,
+            run.shadow_only
+        );
+    }
+
+    let Some(storage) = state.storage.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        if let Err(e) = storage.insert_shadow_run(&run, &result.anomalies).await {
+            eprintln!(
+                "Error: Failed to record shadow run for sensor {}: {}",
+                run.sensor_id, e
+            );
+        }
+    });
+}
+
+fn no_shadow(sensor_id: i64) -> (StatusCode, String) {
+    (
+        StatusCode::NOT_FOUND,
+        format!("sensor {} has no shadow detector", sensor_id),
+    )
+}
+
+async fn find(
+    storage: &Storage,
+    sensor_id: i64,
+) -> Result<Option<ShadowConfig>, (StatusCode, String)> {
+    let mut configs = load(storage, &[sensor_id]).await.map_err(internal)?;
+    Ok(configs.remove(&sensor_id))
+}
+
+/// Records a change to `sensor_id`'s shadow.
+async fn audit(
+    storage: &Storage,
+    headers: &HeaderMap,
+    sensor_id: i64,
+    before: Option<&ShadowConfig>,
+    after: Option<&ShadowConfig>,
+) {
+    let change = Change::new("sensor_shadow", Some(sensor_id.to_string()), before, after);
+    audit::record(storage, &audit::actor(headers), [change]).await;
+}
+
+pub async fn get_shadow(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+) -> Result<Json<ShadowConfig>, (StatusCode, String)> {
+    let shadow = find(storage(&state)?, sensor_id).await?;
+    shadow.map(Json).ok_or_else(|| no_shadow(sensor_id))
+}
+
+/// Sets or replaces a sensor's shadow.
+pub async fn put_shadow(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    headers: HeaderMap,
+    Json(config): Json<ShadowConfig>,
+) -> Result<Json<ShadowConfig>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let primary = sensors::registered(&state, Some(sensor_id)).await?;
+    Shadow::resolve(
+        sensor_id,
+        &config,
+        primary.as_ref(),
+        state.settings.detection(),
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let previous = find(storage, sensor_id).await?;
+    let raw = serde_json::to_string(&config).map_err(internal)?;
+    match storage.set_shadow_config(sensor_id, &raw).await {
+        Ok(()) => {}
+        Err(e) if sensors::is_missing_sensor(&e) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                format!("sensor {} does not exist", sensor_id),
+            ));
+        }
+        Err(e) => return Err(internal(e)),
+    }
+    audit(
+        storage,
+        &headers,
+        sensor_id,
+        previous.as_ref(),
+        Some(&config),
+    )
+    .await;
+    Ok(Json(config))
+}
+
+/// Removes a sensor's shadow; the runs it recorded are kept.
+pub async fn delete_shadow(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let previous = find(storage, sensor_id).await?;
+    if !storage
+        .delete_shadow_config(sensor_id)
+        .await
+        .map_err(internal)?
+    {
+        return Err(no_shadow(sensor_id));
+    }
+    audit(storage, &headers, sensor_id, previous.as_ref(), None).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Bucket {
+    Hour,
+    #[default]
+    Day,
+}
+
+impl Bucket {
+    fn seconds(self) -> i64 {
+        match self {
+            Bucket::Hour => 3_600,
+            Bucket::Day => 86_400,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AgreementQuery {
+    start: String,
+    end: String,
+    #[serde(default)]
+    bucket: Bucket,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Agreement {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bucket: Option<String>,
+    runs: i64,
+    readings: i64,
+    both_flagged: i64,
+    primary_only: i64,
+    shadow_only: i64,
+    /// Share of the readings flagged by either detector that both flagged;
+    /// `null` when neither flagged any.
+    agreement: Option<f64>,
+}
+
+impl Agreement {
+    fn add(&mut self, other: &Agreement) {
+        self.runs += other.runs;
+        self.readings += other.readings;
+        self.both_flagged += other.both_flagged;
+        self.primary_only += other.primary_only;
+        self.shadow_only += other.shadow_only;
+        self.agreement = self.share();
+    }
+
+    fn share(&self) -> Option<f64> {
+        let flagged = self.both_flagged + self.primary_only + self.shadow_only;
+        (flagged > 0).then(|| self.both_flagged as f64 / flagged as f64)
+    }
+}
+
+impl From<ShadowAgreement> for Agreement {
+    fn from(stored: ShadowAgreement) -> Self {
+        let mut agreement = Agreement {
+            bucket: Some(stored.bucket),
+            runs: stored.runs,
+            readings: stored.readings,
+            both_flagged: stored.both_flagged,
+            primary_only: stored.primary_only,
+            shadow_only: stored.shadow_only,
+            agreement: None,
+        };
+        agreement.agreement = agreement.share();
+        agreement
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgreementResponse {
+    sensor_id: i64,
+    start: String,
+    end: String,
+    bucket: Bucket,
+    total: Agreement,
+    buckets: Vec<Agreement>,
+}
+
+/// How far a sensor's shadow agreed with the primary between `start` and
+/// `end`, per hour or day.
+pub async fn agreement(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    Query(query): Query<AgreementQuery>,
+) -> Result<Json<AgreementResponse>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    let (start, end) = storage
+        .normalize_range(&query.start, &query.end)
+        .await
+        .map_err(internal)?
+        .ok_or((
+            StatusCode::BAD_REQUEST,
+            "start and end must be valid timestamps with start before end".to_string(),
+        ))?;
+    let buckets: Vec<Agreement> = storage
+        .shadow_agreement(sensor_id, &start, &end, query.bucket.seconds())
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .map(Agreement::from)
+        .collect();
+    let mut total = Agreement::default();
+    for bucket in &buckets {
+        total.add(bucket);
+    }
+    Ok(Json(AgreementResponse {
+        sensor_id,
+        start,
+        end,
+        bucket: query.bucket,
+        total,
+        buckets,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::ACTOR_HEADER;
+    use crate::storage::testing::{enforce_sensors, in_memory};
+    use serde_json::json;
+
+    fn analyze_request(sensor_id: i64) -> Json<AnalyzeRequest> {
+        let values = [10.0, 12.0, 11.0, 11.5, 10.5, 11.0, 10.8, 14.0, 200.0];
+        let readings: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                json!({
+                    "id": i + 1,
+                    "value": value,
+                    "timestamp": format!("2026-01-19T10:0{}:00", i),
+                })
+            })
+            .collect();
+        let request = json!({ "sensor_id": sensor_id, "readings": readings });
+        Json(serde_json::from_value(request).unwrap())
+    }
+
+    fn config(value: serde_json::Value) -> Json<ShadowConfig> {
+        Json(serde_json::from_value(value).unwrap())
+    }
+
+    async fn agreement_of(state: &AppState, sensor_id: i64) -> AgreementResponse {
+        let query = AgreementQuery {
+            start: "2000-01-01".to_string(),
+            end: "2100-01-01".to_string(),
+            bucket: Bucket::Hour,
+        };
+        let Json(response) = agreement(State(state.clone()), Path(sensor_id), Query(query))
+            .await
+            .unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_shadow_runs_beside_primary_without_publishing() {
+        let state = AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        };
+        let _ = put_shadow(
+            State(state.clone()),
+            Path(7),
+            HeaderMap::new(),
+            config(json!({ "parameters": { "max_value": 13.0 } })),
+        )
+        .await
+        .unwrap();
+        let mut events = state.events.subscribe();
+
+        let Json(response) = crate::analyze(State(state.clone()), analyze_request(7))
+            .await
+            .unwrap();
+        assert_eq!(response.anomalies.len(), 1);
+        assert_eq!(events.recv().await.unwrap().reading_id, 9);
+        assert!(events.try_recv().is_err());
+
+        let mut recorded = agreement_of(&state, 7).await;
+        for _ in 0..100 {
+            if recorded.total.runs > 0 {
+                break;
+            }
+            tokio::task::yield_now().await;
+            recorded = agreement_of(&state, 7).await;
+        }
+        assert_eq!(recorded.buckets.len(), 1);
+        assert_eq!(recorded.total.runs, 1);
+        assert_eq!(recorded.total.readings, 9);
+        assert_eq!(recorded.total.both_flagged, 1);
+        assert_eq!(recorded.total.primary_only, 0);
+        assert_eq!(recorded.total.shadow_only, 1);
+        assert_eq!(recorded.total.agreement, Some(0.5));
+
+        let _ = crate::analyze(State(state.clone()), analyze_request(8))
+            .await
+            .unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(agreement_of(&state, 8).await.total.runs, 0);
+    }
+
+    #[tokio::test]
+    async fn test_put_shadow_validates_and_audits() {
+        let storage = in_memory().await;
+        enforce_sensors(&storage, &[7]).await;
+        let state = AppState {
+            storage: Some(storage.clone()),
+            ..AppState::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(ACTOR_HEADER, "alice".parse().unwrap());
+        let put = |sensor_id: i64, value: serde_json::Value| {
+            put_shadow(
+                State(state.clone()),
+                Path(sensor_id),
+                headers.clone(),
+                config(value),
+            )
+        };
+
+        let invalid = put(7, json!({ "parameters": { "threshold": -1.0 } })).await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::BAD_REQUEST);
+        let unknown = put(9, json!({ "parameters": { "threshold": 2.0 } })).await;
+        assert_eq!(unknown.unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let _ = put(7, json!({ "parameters": { "threshold": 2.0 } }))
+            .await
+            .unwrap();
+        let Json(shadow) = get_shadow(State(state.clone()), Path(7)).await.unwrap();
+        assert_eq!(shadow.parameters.threshold(), Some(2.0));
+
+        let deleted = delete_shadow(State(state.clone()), Path(7), headers.clone()).await;
+        assert_eq!(deleted.unwrap(), StatusCode::NO_CONTENT);
+        let missing = get_shadow(State(state.clone()), Path(7)).await;
+        assert_eq!(missing.unwrap_err().0, StatusCode::NOT_FOUND);
+
+        let entries = storage
+            .audit_entries(&Default::default(), 10)
+            .await
+            .unwrap();
+        let actions: Vec<(&str, &str)> = entries
+            .iter()
+            .map(|e| (e.resource.as_str(), e.action.as_str()))
+            .collect();
+        assert_eq!(
+            actions,
+            vec![("sensor_shadow", "delete"), ("sensor_shadow", "create")]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! State replicas share through Redis, so that replicas behind a load
+//! balancer give the same verdicts without a shared database, or without
+//! putting streamed windows through it.
+//!
+//! With `redis_url` set, the `/stream/ingest` and `/stream` windows (see
+//! `stream`) live in Redis rather than in `stream_windows`: each sensor's
+//! window is a hash of its `version` and the JSON of its `state`, written
+//! back by a script only if its version is still the one read, and expiring
+//! once untouched for `sensor_idle_secs`, so an idle sensor starts over.
+//! Without storage, the dedup keys of notified anomalies (see
+//! `notify::queue`) are kept here too, for as long as the queue would keep
+//! them, so an anomaly detected by several replicas is notified once.
+
+use std::time::Duration;
+
+use redis::aio::ConnectionManager;
+use redis::{RedisResult, Script};
+
+/// Prefix of every key the service writes.
+const KEY_PREFIX: &str = "anomaly-detector:";
+
+/// Writes a window if its version is still `ARGV[1]`, empty for a window
+/// not stored yet, and bumps the version.
+const SAVE_WINDOW: &str = r"
+local version = redis.call('HGET', KEYS[1], 'version')
+if (version or '') ~= ARGV[1] then
+  return 0
+end
+redis.call('HSET', KEYS[1], 'version', (tonumber(version) or 0) + 1, 'state', ARGV[2])
+redis.call('EXPIRE', KEYS[1], ARGV[3])
+return 1
+";
+
+#[derive(Clone)]
+pub struct Redis {
+    connection: ConnectionManager,
+}
+
+impl Redis {
+    pub async fn connect(url: &str) -> RedisResult<Self> {
+        let connection = redis::Client::open(url)?.get_connection_manager().await?;
+        Ok(Self { connection })
+    }
+
+    fn window_key(sensor_id: i64) -> String {
+        format!("{}stream_window:{}", KEY_PREFIX, sensor_id)
+    }
+
+    /// The version and state of `sensor_id`'s stream window; `None` once it
+    /// was left idle.
+    pub async fn stream_window(&self, sensor_id: i64) -> RedisResult<Option<(i64, String)>> {
+        let (version, state): (Option<i64>, Option<String>) = redis::cmd("HMGET")
+            .arg(Self::window_key(sensor_id))
+            .arg("version")
+            .arg("state")
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(version.zip(state))
+    }
+
+    /// Writes `sensor_id`'s stream window, kept for `idle`, if its version
+    /// is still `read`, `None` for a window not stored yet; `false` if
+    /// another replica wrote it first.
+    pub async fn save_stream_window(
+        &self,
+        sensor_id: i64,
+        read: Option<i64>,
+        state: &str,
+        idle: Duration,
+    ) -> RedisResult<bool> {
+        let written: i64 = Script::new(SAVE_WINDOW)
+            .key(Self::window_key(sensor_id))
+            .arg(read.map_or_else(String::new, |version| version.to_string()))
+            .arg(state)
+            .arg(idle.as_secs().max(1))
+            .invoke_async(&mut self.connection.clone())
+            .await?;
+        Ok(written == 1)
+    }
+
+    /// Records `dedup_key` for `window`; `false` if it was recorded already.
+    pub async fn claim_dedup_key(&self, dedup_key: &str, window: Duration) -> RedisResult<bool> {
+        let set: Option<String> = redis::cmd("SET")
+            .arg(format!("{}dedup:{}", KEY_PREFIX, dedup_key))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(window.as_secs().max(1))
+            .query_async(&mut self.connection.clone())
+            .await?;
+        Ok(set.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs against the Redis at `ANOMALY_TEST_REDIS_URL`, under sensor and
+    /// key names of its own; skipped when it is not set.
+    #[tokio::test]
+    async fn test_redis_windows_and_dedup_keys() {
+        let Ok(url) = std::env::var("ANOMALY_TEST_REDIS_URL") else {
+            eprintln!("ANOMALY_TEST_REDIS_URL is not set; skipping");
+            return;
+        };
+        let redis = Redis::connect(&url).await.unwrap();
+        let sensor_id = -i64::from(std::process::id());
+        let idle = Duration::from_secs(60);
+
+        assert_eq!(redis.stream_window(sensor_id).await.unwrap(), None);
+        assert!(
+            redis
+                .save_stream_window(sensor_id, None, "{}", idle)
+                .await
+                .unwrap()
+        );
+        // A replica writing back a window another one wrote since loses.
+        assert!(
+            !redis
+                .save_stream_window(sensor_id, None, "[]", idle)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            redis.stream_window(sensor_id).await.unwrap(),
+            Some((1, "{}".to_string()))
+        );
+        assert!(
+            redis
+                .save_stream_window(sensor_id, Some(1), "[]", idle)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            redis.stream_window(sensor_id).await.unwrap(),
+            Some((2, "[]".to_string()))
+        );
+        assert!(
+            !redis
+                .save_stream_window(sensor_id, Some(1), "{}", idle)
+                .await
+                .unwrap()
+        );
+
+        let key = format!("test-{}", sensor_id);
+        assert!(redis.claim_dedup_key(&key, idle).await.unwrap());
+        assert!(!redis.claim_dedup_key(&key, idle).await.unwrap());
+
+        redis::cmd("DEL")
+            .arg(Redis::window_key(sensor_id))
+            .arg(format!("{}dedup:{}", KEY_PREFIX, key))
+            .exec_async(&mut redis.connection.clone())
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,345 @@
+//! Anomaly-rate SLOs and error-budget burn, for reliability reviews.
+//!
+//! A target is the highest share of a sensor's readings that may be
+//! anomalous, set in the `[service.slo]` table: `default` for every sensor,
+//! and per tag for the cohort of sensors carrying it. A sensor gets the
+//! strictest target of its tags, else the default; sensors with neither are
+//! not reported.
+//!
+//! ```toml
+//! [service.slo]
+//! default = 0.01
+//! "line:a" = 0.002
+//! ```
+//!
+//! `GET /slo?windows=1h,1d,7d` reports each sensor with a target and each
+//! cohort over each window ending now: its readings, the readings any
+//! method found anomalous, their `rate`, the `burn_rate` (the rate over the
+//! target; above 1 spends the error budget faster than the SLO allows) and
+//! `budget_remaining`, the share of the window's budget (target × readings)
+//! left, negative once overspent.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use futures_util::TryStreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::storage::{AnomalyFilter, Storage};
+
+/// Key of the target of sensors no tagged target covers.
+pub const DEFAULT_TARGET: &str = "default";
+
+const DEFAULT_WINDOWS: &str = "1h,1d,7d";
+
+/// Anomalies fetched per page when counting.
+const PAGE_SIZE: i64 = 5_000;
+
+const WINDOWS: [(&str, u64); 5] = [
+    ("1h", 60 * 60),
+    ("6h", 6 * 60 * 60),
+    ("1d", 24 * 60 * 60),
+    ("7d", 7 * 24 * 60 * 60),
+    ("30d", 30 * 24 * 60 * 60),
+];
+
+#[derive(Deserialize)]
+pub struct SloQuery {
+    /// Comma-separated, from `1h`, `6h`, `1d`, `7d` and `30d`.
+    #[serde(default)]
+    windows: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SloReport {
+    pub sensors: Vec<SensorSlo>,
+    pub cohorts: Vec<CohortSlo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SensorSlo {
+    pub sensor_id: i64,
+    pub target: f64,
+    pub windows: Vec<Burn>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CohortSlo {
+    pub tag: String,
+    pub target: f64,
+    pub sensors: Vec<i64>,
+    pub windows: Vec<Burn>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Burn {
+    pub window: &'static str,
+    pub readings: i64,
+    pub anomalies: i64,
+    pub rate: f64,
+    pub burn_rate: f64,
+    pub budget_remaining: f64,
+}
+
+impl Burn {
+    fn new(window: &'static str, target: f64, readings: i64, anomalies: i64) -> Self {
+        let rate = if readings > 0 {
+            anomalies as f64 / readings as f64
+        } else {
+            0.0
+        };
+        Self {
+            window,
+            readings,
+            anomalies,
+            rate,
+            burn_rate: rate / target,
+            budget_remaining: 1.0 - rate / target,
+        }
+    }
+}
+
+fn parse_windows(raw: &str) -> Result<Vec<(&'static str, u64)>, String> {
+    let mut windows = Vec::new();
+    for name in raw.split(',').map(str::trim) {
+        let window = WINDOWS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(|| {
+                let known: Vec<&str> = WINDOWS.iter().map(|(known, _)| *known).collect();
+                format!(
+                    "unknown window {:?}; windows are {}",
+                    name,
+                    known.join(", ")
+                )
+            })?;
+        if !windows.contains(window) {
+            windows.push(*window);
+        }
+    }
+    Ok(windows)
+}
+
+/// The target of a sensor with `tags`: the strictest of its tags', else the
+/// default.
+fn target_of(targets: &BTreeMap<String, f64>, tags: &[String]) -> Option<f64> {
+    tags.iter()
+        .filter_map(|tag| targets.get(tag))
+        .copied()
+        .reduce(f64::min)
+        .or_else(|| targets.get(DEFAULT_TARGET).copied())
+}
+
+/// Readings and anomalous readings per sensor in each window.
+struct Counts {
+    readings: Vec<HashMap<i64, i64>>,
+    anomalies: Vec<HashMap<i64, i64>>,
+}
+
+async fn count(storage: &Storage, windows: &[(&'static str, u64)]) -> Result<Counts, sqlx::Error> {
+    let mut starts = Vec::with_capacity(windows.len());
+    let mut readings = Vec::with_capacity(windows.len());
+    for (_, seconds) in windows {
+        let start = storage.cutoff(Duration::from_secs(*seconds)).await?;
+        let aggregates = storage.reading_aggregates(&start).await?;
+        readings.push(aggregates.iter().map(|a| (a.sensor_id, a.count)).collect());
+        starts.push(start);
+    }
+
+    let earliest = starts.iter().min().cloned();
+    let filter = AnomalyFilter {
+        start: earliest,
+        ..AnomalyFilter::default()
+    };
+    // A reading more than one method flagged counts once.
+    let mut anomalous: HashMap<(i64, i64), String> = HashMap::new();
+    let mut pages = std::pin::pin!(storage.anomaly_pages(filter, PAGE_SIZE));
+    while let Some(page) = pages.try_next().await? {
+        for anomaly in page {
+            anomalous
+                .entry((anomaly.sensor_id, anomaly.reading_id))
+                .or_insert(anomaly.timestamp);
+        }
+    }
+    let anomalies = starts
+        .iter()
+        .map(|start| {
+            let mut counts = HashMap::new();
+            for ((sensor_id, _), timestamp) in &anomalous {
+                if timestamp >= start {
+                    *counts.entry(*sensor_id).or_default() += 1;
+                }
+            }
+            counts
+        })
+        .collect();
+    Ok(Counts {
+        readings,
+        anomalies,
+    })
+}
+
+/// Anomaly rates and error-budget burn per sensor and cohort.
+pub async fn report(
+    State(state): State<AppState>,
+    Query(query): Query<SloQuery>,
+) -> Result<Json<SloReport>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let targets = state.slo.as_ref();
+    if targets.is_empty() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "no SLO targets are configured; set [service.slo]".to_string(),
+        ));
+    }
+    let windows = parse_windows(query.windows.as_deref().unwrap_or(DEFAULT_WINDOWS))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    let counts = count(storage, &windows).await.map_err(internal)?;
+    let mut sensor_ids: Vec<i64> = counts
+        .readings
+        .iter()
+        .flat_map(|readings| readings.keys())
+        .copied()
+        .collect::<HashSet<i64>>()
+        .into_iter()
+        .collect();
+    sensor_ids.sort_unstable();
+    let tags = storage.sensor_tags(&sensor_ids).await.map_err(internal)?;
+    let tags_of = |sensor_id: i64| tags.get(&sensor_id).map_or(&[][..], Vec::as_slice);
+    let burns = |target: f64, sensors: &[i64]| -> Vec<Burn> {
+        windows
+            .iter()
+            .enumerate()
+            .map(|(index, (window, _))| {
+                let total = |counts: &HashMap<i64, i64>| {
+                    sensors
+                        .iter()
+                        .map(|id| counts.get(id).copied().unwrap_or(0))
+                        .sum()
+                };
+                Burn::new(
+                    window,
+                    target,
+                    total(&counts.readings[index]),
+                    total(&counts.anomalies[index]),
+                )
+            })
+            .collect()
+    };
+
+    let sensors = sensor_ids
+        .iter()
+        .filter_map(|&sensor_id| {
+            let target = target_of(targets, tags_of(sensor_id))?;
+            Some(SensorSlo {
+                sensor_id,
+                target,
+                windows: burns(target, &[sensor_id]),
+            })
+        })
+        .collect();
+    let cohorts = targets
+        .iter()
+        .filter(|(tag, _)| tag.as_str() != DEFAULT_TARGET)
+        .map(|(tag, &target)| {
+            let members: Vec<i64> = sensor_ids
+                .iter()
+                .copied()
+                .filter(|&sensor_id| tags_of(sensor_id).contains(tag))
+                .collect();
+            CohortSlo {
+                tag: tag.clone(),
+                target,
+                windows: burns(target, &members),
+                sensors: members,
+            }
+        })
+        .collect();
+    Ok(Json(SloReport { sensors, cohorts }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::storage::testing::{in_memory, insert_anomaly, insert_reading};
+
+    #[tokio::test]
+    async fn test_reports_burn_per_sensor_and_cohort() {
+        let storage = in_memory().await;
+        let now = storage.cutoff(Duration::ZERO).await.unwrap();
+        let ago = |seconds: i64| {
+            let storage = storage.clone();
+            let now = now.clone();
+            async move { storage.add_seconds(&now, -seconds).await.unwrap().unwrap() }
+        };
+        for minute in 0..10 {
+            insert_reading(&storage, 1, 1.0, &ago(minute * 60 + 1).await).await;
+            insert_reading(&storage, 2, 1.0, &ago(minute * 60 + 1).await).await;
+        }
+        // Two hours ago: inside 1d, outside 1h.
+        insert_reading(&storage, 2, 1.0, &ago(2 * 60 * 60).await).await;
+        insert_anomaly(&storage, 1, &ago(30).await).await;
+        insert_anomaly(&storage, 2, &ago(2 * 60 * 60).await).await;
+        storage
+            .set_sensor_tags(2, &["line:a".to_string()])
+            .await
+            .unwrap();
+
+        let state = AppState {
+            storage: Some(storage),
+            slo: Arc::new(BTreeMap::from([
+                (DEFAULT_TARGET.to_string(), 0.2),
+                ("line:a".to_string(), 0.05),
+            ])),
+            ..AppState::default()
+        };
+        let Json(report) = report(
+            State(state.clone()),
+            Query(SloQuery {
+                windows: Some("1h,1d".to_string()),
+            }),
+        )
+        .await
+        .unwrap();
+
+        let sensor = &report.sensors[0];
+        assert_eq!((sensor.sensor_id, sensor.target), (1, 0.2));
+        assert_eq!(sensor.windows[0], Burn::new("1h", 0.2, 10, 1));
+        assert!((sensor.windows[0].burn_rate - 0.5).abs() < 1e-9);
+        let cohort = &report.cohorts[0];
+        assert_eq!((cohort.tag.as_str(), cohort.target), ("line:a", 0.05));
+        assert_eq!(cohort.sensors, [2]);
+        assert_eq!(cohort.windows[0].anomalies, 0);
+        assert_eq!(cohort.windows[1], Burn::new("1d", 0.05, 11, 1));
+        assert!(cohort.windows[1].budget_remaining < 0.0);
+
+        let invalid = report_with(&state, "1h,2w").await;
+        assert_eq!(invalid.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+
+    async fn report_with(
+        state: &AppState,
+        windows: &str,
+    ) -> Result<Json<SloReport>, (StatusCode, String)> {
+        report(
+            State(state.clone()),
+            Query(SloQuery {
+                windows: Some(windows.to_string()),
+            }),
+        )
+        .await
+    }
+}
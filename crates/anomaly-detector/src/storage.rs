@@ -0,0 +1,2329 @@
+//! SQLite storage shared with the Python API.
+//!
+//! Readings are written by the Python side into the `readings` table; this
+//! service only reads them. Anomalies go to an `alert-store` backend, by
+//! default the `anomalies` table of the same database. The tables it owns (`rollups`,
+//! `sensor_tags`, `sensor_configs`, `sensor_references`, `sensor_rules`, `silences`, `incidents`, `incident_assignees`,
+//! `incident_notes`, `incident_anomalies`, `config_changes`,
+//! `audit_log`, `sensor_shadows`, `shadow_runs`, `shadow_anomalies`,
+//! `webhooks`, `webhook_deliveries`, `notification_queue`, `email_digest_items`,
+//! `pagerduty_incidents`, `job_leases`, `archive_watermarks`, `request_log`, `tenant_usage`,
+//! `stream_windows`) are defined in `db/migrations/` and also applied on
+//! connect, so the service works against a database that predates those
+//! migrations.
+//!
+//! Everything replicas must agree on lives here, so any number of them can
+//! run against the same database.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alert_store::{AlertStore, SqliteAlertStore};
+
+use sqlx::sqlite::{
+    SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePool, SqlitePoolOptions,
+};
+
+const SCHEMA: &[&str] = &[
+    include_str!("../../../db/migrations/006_rollups.sql"),
+    include_str!("../../../db/migrations/007_sensor_tags.sql"),
+    include_str!("../../../db/migrations/008_silences.sql"),
+    include_str!("../../../db/migrations/009_incidents.sql"),
+    include_str!("../../../db/migrations/010_config_changes.sql"),
+    include_str!("../../../db/migrations/011_sensor_configs.sql"),
+    include_str!("../../../db/migrations/012_audit_log.sql"),
+    include_str!("../../../db/migrations/013_shadow_detectors.sql"),
+    include_str!("../../../db/migrations/014_webhook_outbox.sql"),
+    include_str!("../../../db/migrations/015_notification_queue.sql"),
+    include_str!("../../../db/migrations/016_pagerduty_incidents.sql"),
+    include_str!("../../../db/migrations/017_job_leases.sql"),
+    include_str!("../../../db/migrations/018_sensor_references.sql"),
+    include_str!("../../../db/migrations/019_incident_lifecycle.sql"),
+    include_str!("../../../db/migrations/020_archive_watermarks.sql"),
+    include_str!("../../../db/migrations/021_request_log.sql"),
+    include_str!("../../../db/migrations/022_tenant_usage.sql"),
+    include_str!("../../../db/migrations/023_sensor_rules.sql"),
+    include_str!("../../../db/migrations/024_webhooks.sql"),
+    include_str!("../../../db/migrations/025_stream_windows.sql"),
+    include_str!("../../../db/migrations/026_shadow_methods.sql"),
+];
+
+/// A notification silence; `matchers` is stored as JSON.
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredSilence {
+    pub id: i64,
+    pub matchers: String,
+    pub comment: String,
+    pub created_by: String,
+    pub starts_at: String,
+    pub ends_at: String,
+    pub created_at: String,
+}
+
+#[derive(Debug)]
+pub struct NewSilence {
+    pub matchers: String,
+    pub comment: String,
+    pub created_by: String,
+    pub starts_at: String,
+    pub ends_at: String,
+}
+
+/// An open, unacknowledged incident.
+#[derive(Debug, sqlx::FromRow)]
+pub struct PendingIncident {
+    pub id: i64,
+    pub last_anomaly_id: i64,
+}
+
+/// An incident with its assignee, for the `/incidents` API.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct StoredIncident {
+    pub id: i64,
+    pub sensor_id: i64,
+    pub method: String,
+    pub last_anomaly_id: i64,
+    pub opened_at: String,
+    pub acknowledged_at: Option<String>,
+    pub resolved_at: Option<String>,
+    pub escalation_level: i64,
+    pub assignee: Option<String>,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct IncidentNote {
+    pub id: i64,
+    pub author: String,
+    pub body: String,
+    pub created_at: String,
+}
+
+/// Which incidents `Storage::list_incidents` returns; unset fields match
+/// any.
+#[derive(Debug, Default)]
+pub struct IncidentFilter {
+    /// `open`, `acknowledged` or `resolved`.
+    pub state: Option<String>,
+    pub sensor_id: Option<i64>,
+    pub assignee: Option<String>,
+    pub limit: i64,
+}
+
+/// Aggregates of one sensor's recent readings.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ReadingAggregate {
+    pub sensor_id: i64,
+    pub count: i64,
+    pub mean: f64,
+    pub mean_square: f64,
+    pub last_value: f64,
+    pub last_timestamp: String,
+}
+
+/// One change of the runtime settings; both settings are stored as JSON.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ConfigChange {
+    pub id: i64,
+    pub changed_at: String,
+    pub actor: String,
+    pub previous: String,
+    pub settings: String,
+}
+
+/// One audited change; `before` and `after` are stored as JSON.
+#[derive(Clone, Debug, sqlx::FromRow)]
+pub struct StoredAuditEntry {
+    pub id: i64,
+    pub changed_at: String,
+    pub actor: String,
+    pub resource: String,
+    pub resource_id: Option<String>,
+    pub action: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct NewAuditEntry {
+    pub actor: String,
+    pub resource: &'static str,
+    pub resource_id: Option<String>,
+    pub action: &'static str,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+/// Optional constraints on listed audit entries; `since` is a normalized
+/// timestamp and `before_id` pages backwards.
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    pub resource: Option<String>,
+    pub resource_id: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<String>,
+    pub before_id: Option<i64>,
+}
+
+/// One logged mutating request; `hash` chains it to the entry before, whose
+/// hash is its `prev_hash`.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct StoredRequestEntry {
+    pub id: i64,
+    pub received_at: String,
+    pub actor: String,
+    pub method: String,
+    pub path: String,
+    pub body_sha256: String,
+    pub status: i64,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+#[derive(Debug)]
+pub struct NewRequestEntry {
+    pub actor: String,
+    pub method: String,
+    pub path: String,
+    pub body_sha256: String,
+    pub status: u16,
+}
+
+/// Optional constraints on listed request log entries, as for
+/// [`AuditFilter`].
+#[derive(Debug, Default)]
+pub struct RequestFilter {
+    pub actor: Option<String>,
+    pub method: Option<String>,
+    pub since: Option<String>,
+    pub before_id: Option<i64>,
+}
+
+/// What one tenant used on one UTC day.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct TenantUsage {
+    pub tenant: String,
+    pub day: String,
+    pub readings: i64,
+    pub analyses: i64,
+    pub rejected: i64,
+}
+
+/// A sensor's registry entry; its tags live in `sensor_tags`.
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredSensorConfig {
+    pub sensor_id: i64,
+    pub name: String,
+    pub unit: String,
+    pub method: String,
+    pub threshold: Option<f64>,
+    pub min_value: Option<f64>,
+    pub max_value: Option<f64>,
+    pub reference_id: Option<i64>,
+    /// JSON array of rule strings.
+    pub rules: Option<String>,
+}
+
+/// How a shadow detector's results on one series compared with the primary's.
+#[derive(Debug, Default)]
+pub struct NewShadowRun {
+    pub sensor_id: i64,
+    pub readings: i64,
+    pub both_flagged: i64,
+    pub primary_only: i64,
+    pub shadow_only: i64,
+}
+
+/// Shadow runs summed over one time bucket.
+#[derive(Debug, PartialEq, sqlx::FromRow)]
+pub struct ShadowAgreement {
+    pub bucket: String,
+    pub runs: i64,
+    pub readings: i64,
+    pub both_flagged: i64,
+    pub primary_only: i64,
+    pub shadow_only: i64,
+}
+
+/// A webhook request in the outbox.
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredDelivery {
+    pub id: i64,
+    pub webhook: String,
+    pub body: String,
+    pub anomalies: i64,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub last_error: Option<String>,
+    pub last_status_code: Option<i64>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+/// The outcome of one attempt at a webhook delivery.
+#[derive(Debug)]
+pub struct DeliveryAttempt<'a> {
+    /// `delivered`, `pending` to be retried, or `failed` for good.
+    pub status: &'static str,
+    pub error: Option<&'a str>,
+    pub status_code: Option<i64>,
+    /// Seconds until the next attempt of a pending delivery.
+    pub retry_in: i64,
+}
+
+/// An entry for the notification queue: an anomaly to notify, or with no
+/// `anomaly_id` the sensor's incident to resolve.
+#[derive(Debug)]
+pub struct NewNotification {
+    pub anomaly_id: Option<i64>,
+    pub sensor_id: i64,
+    pub method: String,
+    /// An anomaly is queued once per key.
+    pub dedup_key: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct QueuedNotification {
+    pub id: i64,
+    pub anomaly_id: Option<i64>,
+    pub sensor_id: i64,
+    pub method: String,
+    pub attempts: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredReading {
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+}
+
+/// A reading of any sensor.
+#[derive(Debug, serde::Deserialize, serde::Serialize, sqlx::FromRow)]
+pub struct SensorReading {
+    pub id: i64,
+    pub sensor_id: i64,
+    pub value: f64,
+    pub timestamp: String,
+}
+
+/// One bucket of the `rollups` table.
+#[derive(Debug, sqlx::FromRow)]
+pub struct StoredRollup {
+    pub sensor_id: i64,
+    pub resolution: i64,
+    pub bucket: String,
+    pub count: i64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// One point of a (possibly downsampled) series.
+#[derive(Debug, PartialEq, serde::Serialize, sqlx::FromRow)]
+pub struct SeriesPoint {
+    pub timestamp: String,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub count: i64,
+}
+
+pub use alert_store::{AnomalyFilter, NewAnomaly, StoredAnomaly};
+
+const SELECT_DELIVERIES: &str = "SELECT id, webhook, body, anomalies, status, attempts, \
+         CAST(next_attempt_at AS TEXT) AS next_attempt_at, last_error, last_status_code, \
+         CAST(created_at AS TEXT) AS created_at, CAST(delivered_at AS TEXT) AS delivered_at \
+     FROM webhook_deliveries";
+
+#[derive(Clone)]
+pub struct Storage {
+    pool: SqlitePool,
+    alerts: Arc<dyn AlertStore>,
+}
+
+/// Inserts a registry entry, or replaces it if `replace` is set, along with
+/// the sensor's tags; `false` if an existing entry was left alone.
+async fn write_sensor_config(
+    conn: &mut SqliteConnection,
+    config: &StoredSensorConfig,
+    tags: &[String],
+    replace: bool,
+) -> Result<bool, sqlx::Error> {
+    let written = sqlx::query(
+        "INSERT INTO sensor_configs \
+             (sensor_id, name, unit, method, threshold, min_value, max_value) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7) \
+         ON CONFLICT (sensor_id) DO UPDATE SET \
+             name = excluded.name, unit = excluded.unit, method = excluded.method, \
+             threshold = excluded.threshold, min_value = excluded.min_value, \
+             max_value = excluded.max_value, updated_at = CURRENT_TIMESTAMP \
+         WHERE ?8",
+    )
+    .bind(config.sensor_id)
+    .bind(&config.name)
+    .bind(&config.unit)
+    .bind(&config.method)
+    .bind(config.threshold)
+    .bind(config.min_value)
+    .bind(config.max_value)
+    .bind(replace)
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+    if written == 0 {
+        return Ok(false);
+    }
+    replace_tags(conn, config.sensor_id, tags).await?;
+    sqlx::query("DELETE FROM sensor_references WHERE sensor_id = ?1")
+        .bind(config.sensor_id)
+        .execute(&mut *conn)
+        .await?;
+    if let Some(reference_id) = config.reference_id {
+        sqlx::query("INSERT INTO sensor_references (sensor_id, reference_id) VALUES (?1, ?2)")
+            .bind(config.sensor_id)
+            .bind(reference_id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM sensor_rules WHERE sensor_id = ?1")
+        .bind(config.sensor_id)
+        .execute(&mut *conn)
+        .await?;
+    if let Some(rules) = &config.rules {
+        sqlx::query("INSERT INTO sensor_rules (sensor_id, rules) VALUES (?1, ?2)")
+            .bind(config.sensor_id)
+            .bind(rules)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(true)
+}
+
+async fn replace_tags(
+    conn: &mut SqliteConnection,
+    sensor_id: i64,
+    tags: &[String],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM sensor_tags WHERE sensor_id = ?1")
+        .bind(sensor_id)
+        .execute(&mut *conn)
+        .await?;
+    for tag in tags {
+        sqlx::query("INSERT OR IGNORE INTO sensor_tags (sensor_id, tag) VALUES (?1, ?2)")
+            .bind(sensor_id)
+            .bind(tag)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+impl Storage {
+    pub async fn connect(url: &str) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(Duration::from_secs(5));
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: SqlitePool) -> Result<Self, sqlx::Error> {
+        for migration in SCHEMA {
+            sqlx::raw_sql(migration).execute(&pool).await?;
+        }
+        let alerts = Arc::new(SqliteAlertStore::from_pool(pool.clone()).await?);
+        Ok(Self { pool, alerts })
+    }
+
+    /// Keeps anomalies in `alerts` instead of this database.
+    pub fn with_alerts(self, alerts: Arc<dyn AlertStore>) -> Self {
+        Self { alerts, ..self }
+    }
+
+    /// Normalizes a `[start, end)` range to SQLite's datetime format.
+    ///
+    /// Returns `None` if either bound is not a valid timestamp or the range is
+    /// empty. Normalized bounds compare correctly against stored timestamps.
+    pub async fn normalize_range(
+        &self,
+        start: &str,
+        end: &str,
+    ) -> Result<Option<(String, String)>, sqlx::Error> {
+        let (start, end): (Option<String>, Option<String>) =
+            sqlx::query_as("SELECT datetime(?1), datetime(?2)")
+                .bind(start)
+                .bind(end)
+                .fetch_one(&self.pool)
+                .await?;
+
+        Ok(match (start, end) {
+            (Some(start), Some(end)) if start < end => Some((start, end)),
+            _ => None,
+        })
+    }
+
+    /// Normalizes a single timestamp to SQLite's datetime format.
+    pub async fn normalize_timestamp(
+        &self,
+        timestamp: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar("SELECT datetime(?1)")
+            .bind(timestamp)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// The timestamp `seconds` after `timestamp`, in SQLite's datetime format.
+    pub async fn add_seconds(
+        &self,
+        timestamp: &str,
+        seconds: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar("SELECT datetime(?1, ?2)")
+            .bind(timestamp)
+            .bind(format!("{:+} seconds", seconds))
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Length of a normalized range in seconds.
+    pub async fn range_seconds(&self, start: &str, end: &str) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT CAST(strftime('%s', ?2) AS INTEGER) - CAST(strftime('%s', ?1) AS INTEGER)",
+        )
+        .bind(start)
+        .bind(end)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Fetches up to `limit` readings of a sensor in `[start, end)` with an id
+    /// greater than `after_id`, ordered by id.
+    pub async fn readings_page(
+        &self,
+        sensor_id: i64,
+        start: &str,
+        end: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<StoredReading>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, value, CAST(timestamp AS TEXT) AS timestamp FROM readings \
+             WHERE sensor_id = ?1 AND timestamp >= ?2 AND timestamp < ?3 AND id > ?4 \
+             ORDER BY id LIMIT ?5",
+        )
+        .bind(sensor_id)
+        .bind(start)
+        .bind(end)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Fetches up to `limit` readings of any sensor with an id greater than
+    /// `after_id`, ordered by id.
+    pub async fn readings_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SensorReading>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, sensor_id, value, CAST(timestamp AS TEXT) AS timestamp FROM readings \
+             WHERE id > ?1 ORDER BY id LIMIT ?2",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Fetches up to `limit` readings of any sensor before `end`, and from
+    /// `start` when given, with an id greater than `after_id`, ordered by id.
+    pub async fn readings_between(
+        &self,
+        start: Option<&str>,
+        end: &str,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<SensorReading>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, sensor_id, value, CAST(timestamp AS TEXT) AS timestamp FROM readings \
+             WHERE (?1 IS NULL OR timestamp >= ?1) AND timestamp < ?2 AND id > ?3 \
+             ORDER BY id LIMIT ?4",
+        )
+        .bind(start)
+        .bind(end)
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// The lowest reading id, which rises as retention deletes readings.
+    pub async fn first_reading_id(&self) -> Result<Option<i64>, sqlx::Error> {
+        sqlx::query_scalar("SELECT MIN(id) FROM readings")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Deletes anomalies previously recorded by `method` for a sensor in `[start, end)`.
+    pub async fn delete_anomalies(
+        &self,
+        sensor_id: i64,
+        start: &str,
+        end: &str,
+        method: &str,
+    ) -> Result<u64, sqlx::Error> {
+        self.alerts
+            .delete_range(sensor_id, start, end, method)
+            .await
+    }
+
+    /// Folds readings added since the last call into the rollups of every
+    /// resolution (bucket width in seconds), at most `batch` readings at a time.
+    ///
+    /// Progress is tracked by reading id, so late-arriving readings with old
+    /// timestamps still land in the right bucket. Returns the number of readings folded.
+    pub async fn fold_rollups(&self, resolutions: &[i64], batch: i64) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let last: i64 = sqlx::query_scalar(
+            "SELECT COALESCE((SELECT last_reading_id FROM rollup_progress WHERE id = 1), 0)",
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+        let upper: Option<i64> = sqlx::query_scalar(
+            "SELECT MAX(id) FROM (SELECT id FROM readings WHERE id > ?1 ORDER BY id LIMIT ?2)",
+        )
+        .bind(last)
+        .bind(batch)
+        .fetch_one(&mut *tx)
+        .await?;
+        let Some(upper) = upper else {
+            return Ok(0);
+        };
+
+        let folded: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM readings WHERE id > ?1 AND id <= ?2")
+                .bind(last)
+                .bind(upper)
+                .fetch_one(&mut *tx)
+                .await?;
+
+        for &resolution in resolutions {
+            sqlx::query(
+                "INSERT INTO rollups (sensor_id, resolution, bucket, count, sum, min, max) \
+                 SELECT sensor_id, ?3, \
+                        datetime(CAST(strftime('%s', timestamp) AS INTEGER) / ?3 * ?3, 'unixepoch'), \
+                        COUNT(*), SUM(value), MIN(value), MAX(value) \
+                 FROM readings WHERE id > ?1 AND id <= ?2 \
+                 GROUP BY 1, 3 \
+                 ON CONFLICT (sensor_id, resolution, bucket) DO UPDATE SET \
+                    count = rollups.count + excluded.count, \
+                    sum = rollups.sum + excluded.sum, \
+                    min = MIN(rollups.min, excluded.min), \
+                    max = MAX(rollups.max, excluded.max)",
+            )
+            .bind(last)
+            .bind(upper)
+            .bind(resolution)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query(
+            "INSERT INTO rollup_progress (id, last_reading_id) VALUES (1, ?1) \
+             ON CONFLICT (id) DO UPDATE SET last_reading_id = excluded.last_reading_id",
+        )
+        .bind(upper)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(folded as u64)
+    }
+
+    /// The highest reading id folded into the rollups.
+    pub async fn rollup_progress(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT COALESCE((SELECT last_reading_id FROM rollup_progress WHERE id = 1), 0)",
+        )
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// The start of the earliest `width`-second bucket holding a reading
+    /// with an id in `(after_id, upto_id]`, i.e. the first bucket folding
+    /// those readings changed.
+    pub async fn first_bucket_between(
+        &self,
+        after_id: i64,
+        upto_id: i64,
+        width: i64,
+    ) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT datetime(MIN(CAST(strftime('%s', timestamp) AS INTEGER)) / ?3 * ?3, \
+                             'unixepoch') \
+             FROM readings WHERE id > ?1 AND id <= ?2",
+        )
+        .bind(after_id)
+        .bind(upto_id)
+        .bind(width)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Fetches the rollup buckets of every sensor and resolution starting at
+    /// or after `since`, or all of them for `None`.
+    pub async fn rollups_since(
+        &self,
+        since: Option<&str>,
+    ) -> Result<Vec<StoredRollup>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT sensor_id, resolution, CAST(bucket AS TEXT) AS bucket, count, sum, min, max \
+             FROM rollups WHERE ?1 IS NULL OR bucket >= ?1",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Fetches rollup buckets of one resolution for a sensor in `[start, end)`.
+    pub async fn rollup_points(
+        &self,
+        sensor_id: i64,
+        resolution: i64,
+        start: &str,
+        end: &str,
+        limit: i64,
+    ) -> Result<Vec<SeriesPoint>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT bucket AS timestamp, sum / count AS mean, min, max, count FROM rollups \
+             WHERE sensor_id = ?1 AND resolution = ?2 AND bucket >= ?3 AND bucket < ?4 \
+             ORDER BY bucket LIMIT ?5",
+        )
+        .bind(sensor_id)
+        .bind(resolution)
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Aggregates readings at or after `since` per sensor, ordered by sensor.
+    pub async fn reading_aggregates(
+        &self,
+        since: &str,
+    ) -> Result<Vec<ReadingAggregate>, sqlx::Error> {
+        // With a single max() aggregate, SQLite takes the bare `value` from
+        // the row holding the latest timestamp.
+        sqlx::query_as(
+            "SELECT sensor_id, COUNT(*) AS count, AVG(value) AS mean, \
+                    AVG(value * value) AS mean_square, value AS last_value, \
+                    CAST(MAX(timestamp) AS TEXT) AS last_timestamp \
+             FROM readings WHERE timestamp >= ?1 \
+             GROUP BY sensor_id ORDER BY sensor_id",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Fetches raw readings for a sensor in `[start, end)` as single-value points.
+    pub async fn raw_points(
+        &self,
+        sensor_id: i64,
+        start: &str,
+        end: &str,
+        limit: i64,
+    ) -> Result<Vec<SeriesPoint>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT CAST(timestamp AS TEXT) AS timestamp, value AS mean, value AS min, \
+                    value AS max, 1 AS count FROM readings \
+             WHERE sensor_id = ?1 AND timestamp >= ?2 AND timestamp < ?3 \
+             ORDER BY timestamp LIMIT ?4",
+        )
+        .bind(sensor_id)
+        .bind(start)
+        .bind(end)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Returns the datetime `age` before now, in SQLite's datetime format.
+    pub async fn cutoff(&self, age: Duration) -> Result<String, sqlx::Error> {
+        sqlx::query_scalar("SELECT datetime('now', ?1)")
+            .bind(format!("-{} seconds", age.as_secs()))
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Deletes up to `limit` rows of `table` with a timestamp before `cutoff`.
+    pub async fn delete_before(
+        &self,
+        table: &'static str,
+        cutoff: &str,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        let sql = format!(
+            "DELETE FROM {table} WHERE id IN \
+             (SELECT id FROM {table} WHERE timestamp < ?1 LIMIT ?2)"
+        );
+        let result = sqlx::query(&sql)
+            .bind(cutoff)
+            .bind(limit)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Deletes up to `limit` anomalies with a timestamp before `cutoff`.
+    pub async fn delete_anomalies_before(
+        &self,
+        cutoff: &str,
+        limit: i64,
+    ) -> Result<u64, sqlx::Error> {
+        self.alerts.delete_before(cutoff, limit).await
+    }
+
+    /// Checkpoints and truncates the WAL so it does not grow between restarts.
+    pub async fn checkpoint(&self) -> Result<(), sqlx::Error> {
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Lists anomalies matching `filter` with an id greater than `after_id`, ordered by id.
+    pub async fn list_anomalies(
+        &self,
+        filter: &AnomalyFilter,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<StoredAnomaly>, sqlx::Error> {
+        self.alerts.list(filter, after_id, limit).await
+    }
+
+    /// Streams every anomaly matching `filter` in pages of `page_size`.
+    pub fn anomaly_pages(
+        &self,
+        filter: AnomalyFilter,
+        page_size: i64,
+    ) -> impl futures_util::Stream<Item = Result<Vec<StoredAnomaly>, sqlx::Error>> + use<> {
+        let storage = self.clone();
+        futures_util::stream::try_unfold(Some(0), move |after_id| {
+            let storage = storage.clone();
+            let filter = filter.clone();
+            async move {
+                let Some(after_id) = after_id else {
+                    return Ok(None);
+                };
+                let page = storage.list_anomalies(&filter, after_id, page_size).await?;
+                if page.is_empty() {
+                    return Ok(None);
+                }
+                let next = match page.last() {
+                    Some(last) if page.len() as i64 == page_size => Some(last.id),
+                    _ => None,
+                };
+                Ok(Some((page, next)))
+            }
+        })
+    }
+
+    /// Tags of each of `sensor_ids` that has any, sorted.
+    pub async fn sensor_tags(
+        &self,
+        sensor_ids: &[i64],
+    ) -> Result<std::collections::HashMap<i64, Vec<String>>, sqlx::Error> {
+        let ids = serde_json::to_string(sensor_ids).unwrap_or_else(|_| "[]".to_string());
+        let rows: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT sensor_id, tag FROM sensor_tags \
+             WHERE sensor_id IN (SELECT value FROM json_each(?1)) \
+             ORDER BY sensor_id, tag",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut tags = std::collections::HashMap::<i64, Vec<String>>::new();
+        for (sensor_id, tag) in rows {
+            tags.entry(sensor_id).or_default().push(tag);
+        }
+        Ok(tags)
+    }
+
+    /// Replaces all tags of a sensor.
+    pub async fn set_sensor_tags(
+        &self,
+        sensor_id: i64,
+        tags: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        replace_tags(&mut tx, sensor_id, tags).await?;
+        tx.commit().await
+    }
+
+    /// Registry entries of `sensor_ids`, or of every sensor when `None`.
+    pub async fn sensor_configs(
+        &self,
+        sensor_ids: Option<&[i64]>,
+    ) -> Result<Vec<StoredSensorConfig>, sqlx::Error> {
+        let ids =
+            sensor_ids.map(|ids| serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string()));
+        sqlx::query_as(
+            "SELECT sensor_id, name, unit, method, threshold, min_value, max_value, \
+                 reference_id, rules \
+             FROM sensor_configs LEFT JOIN sensor_references USING (sensor_id) \
+                 LEFT JOIN sensor_rules USING (sensor_id) \
+             WHERE ?1 IS NULL OR sensor_id IN (SELECT value FROM json_each(?1)) \
+             ORDER BY sensor_id",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Writes a registry entry and replaces the sensor's tags.
+    ///
+    /// With `replace` unset an existing entry is left alone and `false` is
+    /// returned.
+    pub async fn save_sensor_config(
+        &self,
+        config: &StoredSensorConfig,
+        tags: &[String],
+        replace: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        if !write_sensor_config(&mut tx, config, tags, replace).await? {
+            return Ok(false);
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Writes registry entries in one transaction, replacing existing ones,
+    /// and returns how many were new.
+    ///
+    /// With `dry_run` the transaction is rolled back instead. If an entry
+    /// cannot be written nothing is, and the error comes with its index.
+    pub async fn import_sensor_configs(
+        &self,
+        entries: &[(StoredSensorConfig, Vec<String>)],
+        dry_run: bool,
+    ) -> Result<u64, (Option<usize>, sqlx::Error)> {
+        let mut tx = self.pool.begin().await.map_err(|e| (None, e))?;
+        let mut created = 0;
+        for (index, (config, tags)) in entries.iter().enumerate() {
+            let existed: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM sensor_configs WHERE sensor_id = ?1)",
+            )
+            .bind(config.sensor_id)
+            .fetch_one(&mut *tx)
+            .await
+            .map_err(|e| (Some(index), e))?;
+            write_sensor_config(&mut tx, config, tags, true)
+                .await
+                .map_err(|e| (Some(index), e))?;
+            if !existed {
+                created += 1;
+            }
+        }
+        if dry_run {
+            tx.rollback().await.map_err(|e| (None, e))?;
+        } else {
+            tx.commit().await.map_err(|e| (None, e))?;
+        }
+        Ok(created)
+    }
+
+    /// Removes a sensor's registry entry, tags, reference and rules; `false`
+    /// if it had no entry.
+    pub async fn delete_sensor_config(&self, sensor_id: i64) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let deleted = sqlx::query("DELETE FROM sensor_configs WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        sqlx::query("DELETE FROM sensor_tags WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM sensor_references WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM sensor_rules WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted > 0)
+    }
+
+    /// Shadow detector configurations of those of `sensor_ids` that have one,
+    /// stored as JSON.
+    pub async fn shadow_configs(
+        &self,
+        sensor_ids: &[i64],
+    ) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        let ids = serde_json::to_string(sensor_ids).unwrap_or_else(|_| "[]".to_string());
+        sqlx::query_as(
+            "SELECT sensor_id, config FROM sensor_shadows \
+             WHERE sensor_id IN (SELECT value FROM json_each(?1))",
+        )
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn set_shadow_config(&self, sensor_id: i64, config: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO sensor_shadows (sensor_id, config) VALUES (?1, ?2) \
+             ON CONFLICT (sensor_id) DO UPDATE SET \
+                 config = excluded.config, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(sensor_id)
+        .bind(config)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a sensor's shadow detector, keeping its past runs; `false` if it
+    /// had none.
+    pub async fn delete_shadow_config(&self, sensor_id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM sensor_shadows WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records a shadow run and the anomalies the shadow found in it.
+    pub async fn insert_shadow_run(
+        &self,
+        run: &NewShadowRun,
+        anomalies: &[NewAnomaly],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let run_id: i64 = sqlx::query_scalar(
+            "INSERT INTO shadow_runs \
+             (sensor_id, readings, both_flagged, primary_only, shadow_only) \
+             VALUES (?1, ?2, ?3, ?4, ?5) RETURNING id",
+        )
+        .bind(run.sensor_id)
+        .bind(run.readings)
+        .bind(run.both_flagged)
+        .bind(run.primary_only)
+        .bind(run.shadow_only)
+        .fetch_one(&mut *tx)
+        .await?;
+        for anomaly in anomalies {
+            sqlx::query(
+                "INSERT INTO shadow_anomalies \
+                 (run_id, reading_id, sensor_id, value, timestamp, method, score, severity) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            )
+            .bind(run_id)
+            .bind(anomaly.reading_id)
+            .bind(anomaly.sensor_id)
+            .bind(anomaly.value)
+            .bind(&anomaly.timestamp)
+            .bind(anomaly.method)
+            .bind(anomaly.score)
+            .bind(anomaly.severity.as_str())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// A sensor's shadow runs between normalized bounds, summed per bucket of
+    /// `bucket_seconds`, oldest first.
+    pub async fn shadow_agreement(
+        &self,
+        sensor_id: i64,
+        start: &str,
+        end: &str,
+        bucket_seconds: i64,
+    ) -> Result<Vec<ShadowAgreement>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT datetime(CAST(strftime('%s', ran_at) AS INTEGER) / ?4 * ?4, 'unixepoch') \
+                        AS bucket, \
+                    COUNT(*) AS runs, SUM(readings) AS readings, \
+                    SUM(both_flagged) AS both_flagged, SUM(primary_only) AS primary_only, \
+                    SUM(shadow_only) AS shadow_only \
+             FROM shadow_runs \
+             WHERE sensor_id = ?1 AND ran_at >= ?2 AND ran_at < ?3 \
+             GROUP BY bucket ORDER BY bucket",
+        )
+        .bind(sensor_id)
+        .bind(start)
+        .bind(end)
+        .bind(bucket_seconds)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Configurations of the webhooks added at runtime, stored as JSON, in
+    /// the order they were added.
+    pub async fn webhook_configs(&self) -> Result<Vec<(String, String)>, sqlx::Error> {
+        sqlx::query_as("SELECT name, config FROM webhooks ORDER BY created_at, rowid")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn save_webhook_config(&self, name: &str, config: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO webhooks (name, config) VALUES (?1, ?2) \
+             ON CONFLICT (name) DO UPDATE SET config = excluded.config",
+        )
+        .bind(name)
+        .bind(config)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Removes a webhook added at runtime; `false` if it was not stored.
+    pub async fn delete_webhook_config(&self, name: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE name = ?1")
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Adds a webhook request to the outbox, due `retry_in` seconds from now
+    /// unless an attempt is recorded first; returns its id.
+    pub async fn queue_webhook_delivery(
+        &self,
+        webhook: &str,
+        body: &str,
+        anomalies: i64,
+        retry_in: i64,
+    ) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar(
+            "INSERT INTO webhook_deliveries (webhook, body, anomalies, next_attempt_at) \
+             VALUES (?1, ?2, ?3, datetime('now', ?4)) RETURNING id",
+        )
+        .bind(webhook)
+        .bind(body)
+        .bind(anomalies)
+        .bind(format!("{:+} seconds", retry_in))
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    pub async fn record_delivery_attempt(
+        &self,
+        id: i64,
+        attempt: &DeliveryAttempt<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE webhook_deliveries SET \
+                 status = ?2, attempts = attempts + 1, last_error = ?3, last_status_code = ?4, \
+                 next_attempt_at = datetime('now', ?5), \
+                 delivered_at = CASE WHEN ?2 = 'delivered' THEN CURRENT_TIMESTAMP END \
+             WHERE id = ?1",
+        )
+        .bind(id)
+        .bind(attempt.status)
+        .bind(attempt.error)
+        .bind(attempt.status_code)
+        .bind(format!("{:+} seconds", attempt.retry_in))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Moves the next attempt of a pending delivery `retry_in` seconds out,
+    /// without counting one.
+    pub async fn defer_webhook_delivery(&self, id: i64, retry_in: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE webhook_deliveries SET next_attempt_at = datetime('now', ?2) \
+             WHERE id = ?1 AND status = 'pending'",
+        )
+        .bind(id)
+        .bind(format!("{:+} seconds", retry_in))
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Up to `limit` pending webhook deliveries whose next attempt is due,
+    /// oldest first.
+    pub async fn due_webhook_deliveries(
+        &self,
+        limit: i64,
+    ) -> Result<Vec<StoredDelivery>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "{} WHERE status = 'pending' AND next_attempt_at <= CURRENT_TIMESTAMP \
+             ORDER BY next_attempt_at, id LIMIT ?1",
+            SELECT_DELIVERIES
+        ))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Up to `limit` deliveries to `webhook`, optionally with one `status`,
+    /// newest first; `before_id` pages backwards.
+    pub async fn webhook_deliveries(
+        &self,
+        webhook: &str,
+        status: Option<&str>,
+        before_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<StoredDelivery>, sqlx::Error> {
+        sqlx::query_as(&format!(
+            "{} WHERE webhook = ?1 AND (?2 IS NULL OR status = ?2) AND (?3 IS NULL OR id < ?3) \
+             ORDER BY id DESC LIMIT ?4",
+            SELECT_DELIVERIES
+        ))
+        .bind(webhook)
+        .bind(status)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Adds entries to the notification queue, skipping anomalies whose
+    /// dedup key was queued before; returns how many were added.
+    pub async fn enqueue_notifications(
+        &self,
+        entries: &[NewNotification],
+    ) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut queued = 0;
+        for entry in entries {
+            queued += sqlx::query(
+                "INSERT OR IGNORE INTO notification_queue \
+                 (kind, anomaly_id, sensor_id, method, dedup_key) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(if entry.anomaly_id.is_some() {
+                "anomaly"
+            } else {
+                "resolve"
+            })
+            .bind(entry.anomaly_id)
+            .bind(entry.sensor_id)
+            .bind(&entry.method)
+            .bind(&entry.dedup_key)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(queued)
+    }
+
+    /// Claims up to `limit` undelivered entries, oldest first, that are not
+    /// claimed or whose claim is more than `timeout_seconds` old.
+    pub async fn claim_notifications(
+        &self,
+        timeout_seconds: i64,
+        limit: i64,
+    ) -> Result<Vec<QueuedNotification>, sqlx::Error> {
+        let mut claimed: Vec<QueuedNotification> = sqlx::query_as(
+            "UPDATE notification_queue SET claimed_at = CURRENT_TIMESTAMP, attempts = attempts + 1 \
+             WHERE id IN ( \
+                 SELECT id FROM notification_queue \
+                 WHERE delivered_at IS NULL \
+                   AND (claimed_at IS NULL OR claimed_at < datetime('now', ?1)) \
+                 ORDER BY id LIMIT ?2) \
+             RETURNING id, anomaly_id, sensor_id, method, attempts",
+        )
+        .bind(format!("-{} seconds", timeout_seconds))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        claimed.sort_by_key(|entry| entry.id);
+        Ok(claimed)
+    }
+
+    pub async fn complete_notifications(&self, ids: &[i64]) -> Result<(), sqlx::Error> {
+        let ids = serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string());
+        sqlx::query(
+            "UPDATE notification_queue SET delivered_at = CURRENT_TIMESTAMP \
+             WHERE id IN (SELECT value FROM json_each(?1))",
+        )
+        .bind(ids)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes entries delivered before `cutoff`, after which their anomalies
+    /// could be queued again.
+    pub async fn prune_notifications(&self, cutoff: &str) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM notification_queue WHERE delivered_at < ?1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Adds anomalies, as JSON, to an email group's next digest.
+    pub async fn queue_digest_items(
+        &self,
+        group: &str,
+        anomalies: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for anomaly in anomalies {
+            sqlx::query("INSERT INTO email_digest_items (group_name, anomaly) VALUES (?1, ?2)")
+                .bind(group)
+                .bind(anomaly)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await
+    }
+
+    /// The anomalies waiting for an email group's digest, with their ids.
+    pub async fn digest_items(&self, group: &str) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, anomaly FROM email_digest_items WHERE group_name = ?1 ORDER BY id",
+        )
+        .bind(group)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn delete_digest_items(&self, ids: &[i64]) -> Result<(), sqlx::Error> {
+        let ids = serde_json::to_string(ids).unwrap_or_else(|_| "[]".to_string());
+        sqlx::query("DELETE FROM email_digest_items WHERE id IN (SELECT value FROM json_each(?1))")
+            .bind(ids)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn insert_silence(&self, silence: &NewSilence) -> Result<StoredSilence, sqlx::Error> {
+        sqlx::query_as(
+            "INSERT INTO silences (matchers, comment, created_by, starts_at, ends_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5) \
+             RETURNING id, matchers, comment, created_by, CAST(starts_at AS TEXT) AS starts_at, \
+                       CAST(ends_at AS TEXT) AS ends_at, CAST(created_at AS TEXT) AS created_at",
+        )
+        .bind(&silence.matchers)
+        .bind(&silence.comment)
+        .bind(&silence.created_by)
+        .bind(&silence.starts_at)
+        .bind(&silence.ends_at)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Lists silences ordered by id: those in effect now when `active_only`,
+    /// otherwise every silence that has not expired, including future ones.
+    pub async fn list_silences(
+        &self,
+        active_only: bool,
+    ) -> Result<Vec<StoredSilence>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, matchers, comment, created_by, CAST(starts_at AS TEXT) AS starts_at, \
+                    CAST(ends_at AS TEXT) AS ends_at, CAST(created_at AS TEXT) AS created_at \
+             FROM silences \
+             WHERE ends_at > datetime('now') AND (?1 = 0 OR starts_at <= datetime('now')) \
+             ORDER BY id",
+        )
+        .bind(active_only)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Ends a silence now, returning whether it had not already expired.
+    pub async fn expire_silence(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE silences SET ends_at = datetime('now') \
+             WHERE id = ?1 AND ends_at > datetime('now')",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Takes the lease on `job` for `ttl_seconds` when it is free, expired or
+    /// already held by `holder`, returning whether `holder` now has it.
+    pub async fn acquire_lease(
+        &self,
+        job: &str,
+        holder: &str,
+        ttl_seconds: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO job_leases (job, holder, expires_at)              VALUES (?1, ?2, datetime('now', ?3 || ' seconds'))              ON CONFLICT (job) DO UPDATE SET                  acquired_at = CASE WHEN holder = excluded.holder                      THEN acquired_at ELSE excluded.acquired_at END,                  holder = excluded.holder, expires_at = excluded.expires_at              WHERE holder = excluded.holder OR expires_at <= datetime('now')",
+        )
+        .bind(job)
+        .bind(holder)
+        .bind(ttl_seconds)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Until when rows of `kind` have been archived, if ever.
+    pub async fn archive_watermark(&self, kind: &str) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT CAST(exported_until AS TEXT) FROM archive_watermarks WHERE kind = ?1",
+        )
+        .bind(kind)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    pub async fn set_archive_watermark(
+        &self,
+        kind: &str,
+        exported_until: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO archive_watermarks (kind, exported_until) VALUES (?1, ?2) \
+             ON CONFLICT (kind) DO UPDATE SET \
+                 exported_until = excluded.exported_until, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(kind)
+        .bind(exported_until)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// The version and state of `sensor_id`'s stream window, and whether it
+    /// was written in the last `idle_secs`.
+    pub async fn stream_window(
+        &self,
+        sensor_id: i64,
+        idle_secs: u64,
+    ) -> Result<Option<(i64, String, bool)>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT version, state, updated_at >= datetime('now', ?2) FROM stream_windows \
+             WHERE sensor_id = ?1",
+        )
+        .bind(sensor_id)
+        .bind(format!("-{} seconds", idle_secs))
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Writes `sensor_id`'s stream window if its version is still `read`,
+    /// `None` for a window not stored yet; `false` if another replica wrote
+    /// it first.
+    pub async fn save_stream_window(
+        &self,
+        sensor_id: i64,
+        read: Option<i64>,
+        state: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = match read {
+            None => {
+                sqlx::query(
+                    "INSERT INTO stream_windows (sensor_id, state, version) VALUES (?1, ?2, 1) \
+                     ON CONFLICT (sensor_id) DO NOTHING",
+                )
+                .bind(sensor_id)
+                .bind(state)
+                .execute(&self.pool)
+                .await?
+            }
+            Some(version) => {
+                sqlx::query(
+                    "UPDATE stream_windows SET state = ?2, version = version + 1, \
+                     updated_at = CURRENT_TIMESTAMP WHERE sensor_id = ?1 AND version = ?3",
+                )
+                .bind(sensor_id)
+                .bind(state)
+                .bind(version)
+                .execute(&self.pool)
+                .await?
+            }
+        };
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Records a triggered PagerDuty incident.
+    pub async fn open_pagerduty_incident(&self, dedup_key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT OR IGNORE INTO pagerduty_incidents (dedup_key) VALUES (?1)")
+            .bind(dedup_key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn pagerduty_incident_open(&self, dedup_key: &str) -> Result<bool, sqlx::Error> {
+        let open: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM pagerduty_incidents WHERE dedup_key = ?1")
+                .bind(dedup_key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(open.is_some())
+    }
+
+    pub async fn close_pagerduty_incident(&self, dedup_key: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM pagerduty_incidents WHERE dedup_key = ?1")
+            .bind(dedup_key)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Opens an incident per `(sensor_id, method, anomaly_id)`, or records the
+    /// newer anomaly on the incident already open, and links the anomaly to
+    /// it.
+    pub async fn open_incidents(&self, episodes: &[(i64, &str, i64)]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for (sensor_id, method, anomaly_id) in episodes {
+            let (incident_id,): (i64,) = sqlx::query_as(
+                "INSERT INTO incidents (sensor_id, method, last_anomaly_id) VALUES (?1, ?2, ?3) \
+                 ON CONFLICT (sensor_id, method) WHERE resolved_at IS NULL \
+                 DO UPDATE SET last_anomaly_id = MAX(last_anomaly_id, excluded.last_anomaly_id) \
+                 RETURNING id",
+            )
+            .bind(sensor_id)
+            .bind(method)
+            .bind(anomaly_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            sqlx::query(
+                "INSERT OR IGNORE INTO incident_anomalies (incident_id, anomaly_id) \
+                 VALUES (?1, ?2)",
+            )
+            .bind(incident_id)
+            .bind(anomaly_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Incidents matching `filter`, newest first.
+    pub async fn list_incidents(
+        &self,
+        filter: &IncidentFilter,
+    ) -> Result<Vec<StoredIncident>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, sensor_id, method, last_anomaly_id, opened_at, acknowledged_at, \
+                 resolved_at, escalation_level, assignee \
+             FROM incidents LEFT JOIN incident_assignees ON incident_id = id \
+             WHERE (?1 IS NULL OR CASE \
+                     WHEN resolved_at IS NOT NULL THEN 'resolved' \
+                     WHEN acknowledged_at IS NOT NULL THEN 'acknowledged' \
+                     ELSE 'open' END = ?1) \
+               AND (?2 IS NULL OR sensor_id = ?2) \
+               AND (?3 IS NULL OR assignee = ?3) \
+             ORDER BY id DESC LIMIT ?4",
+        )
+        .bind(&filter.state)
+        .bind(filter.sensor_id)
+        .bind(&filter.assignee)
+        .bind(filter.limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn incident(&self, id: i64) -> Result<Option<StoredIncident>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, sensor_id, method, last_anomaly_id, opened_at, acknowledged_at, \
+                 resolved_at, escalation_level, assignee \
+             FROM incidents LEFT JOIN incident_assignees ON incident_id = id \
+             WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+
+    /// Ids of the anomalies linked to an incident, in order.
+    pub async fn incident_anomaly_ids(&self, id: i64) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar(
+            "SELECT anomaly_id FROM incident_anomalies WHERE incident_id = ?1 \
+             ORDER BY anomaly_id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn incident_notes(&self, id: i64) -> Result<Vec<IncidentNote>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, author, body, created_at FROM incident_notes \
+             WHERE incident_id = ?1 ORDER BY id",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn add_incident_note(
+        &self,
+        id: i64,
+        author: &str,
+        body: &str,
+    ) -> Result<IncidentNote, sqlx::Error> {
+        sqlx::query_as(
+            "INSERT INTO incident_notes (incident_id, author, body) VALUES (?1, ?2, ?3) \
+             RETURNING id, author, body, created_at",
+        )
+        .bind(id)
+        .bind(author)
+        .bind(body)
+        .fetch_one(&self.pool)
+        .await
+    }
+
+    /// Assigns an incident to `assignee`, or unassigns it for `None`.
+    pub async fn assign_incident(
+        &self,
+        id: i64,
+        assignee: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        match assignee {
+            Some(assignee) => sqlx::query(
+                "INSERT INTO incident_assignees (incident_id, assignee) VALUES (?1, ?2) \
+                 ON CONFLICT (incident_id) DO UPDATE \
+                 SET assignee = excluded.assignee, assigned_at = CURRENT_TIMESTAMP",
+            )
+            .bind(id)
+            .bind(assignee),
+            None => sqlx::query("DELETE FROM incident_assignees WHERE incident_id = ?1").bind(id),
+        }
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Acknowledges the open incident, returning whether there was one not
+    /// yet acknowledged.
+    pub async fn acknowledge_incident(
+        &self,
+        sensor_id: i64,
+        method: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE incidents SET acknowledged_at = datetime('now') \
+             WHERE sensor_id = ?1 AND method = ?2 \
+               AND resolved_at IS NULL AND acknowledged_at IS NULL",
+        )
+        .bind(sensor_id)
+        .bind(method)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn resolve_incident(&self, sensor_id: i64, method: &str) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE incidents SET resolved_at = datetime('now') \
+             WHERE sensor_id = ?1 AND method = ?2 AND resolved_at IS NULL",
+        )
+        .bind(sensor_id)
+        .bind(method)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Unacknowledged open incidents at `level` that were opened before
+    /// `cutoff`, ordered by id.
+    pub async fn pending_incidents(
+        &self,
+        level: i64,
+        cutoff: &str,
+    ) -> Result<Vec<PendingIncident>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, last_anomaly_id FROM incidents \
+             WHERE resolved_at IS NULL AND acknowledged_at IS NULL \
+               AND escalation_level = ?1 AND opened_at < ?2 \
+             ORDER BY id",
+        )
+        .bind(level)
+        .bind(cutoff)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Moves an incident to the next escalation level.
+    pub async fn escalate_incident(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE incidents SET escalation_level = escalation_level + 1, \
+                    escalated_at = datetime('now') \
+             WHERE id = ?1",
+        )
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn anomalies_by_id(&self, ids: &[i64]) -> Result<Vec<StoredAnomaly>, sqlx::Error> {
+        self.alerts.by_id(ids).await
+    }
+
+    pub async fn insert_config_change(
+        &self,
+        actor: &str,
+        previous: &str,
+        settings: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("INSERT INTO config_changes (actor, previous, settings) VALUES (?1, ?2, ?3)")
+            .bind(actor)
+            .bind(previous)
+            .bind(settings)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// The settings recorded by the latest change, if any.
+    pub async fn latest_settings(&self) -> Result<Option<String>, sqlx::Error> {
+        sqlx::query_scalar("SELECT settings FROM config_changes ORDER BY id DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// The latest `limit` changes, newest first.
+    pub async fn config_changes(&self, limit: i64) -> Result<Vec<ConfigChange>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, CAST(changed_at AS TEXT) AS changed_at, actor, previous, settings \
+             FROM config_changes ORDER BY id DESC LIMIT ?1",
+        )
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Inserts audit entries in a single transaction.
+    pub async fn insert_audit_entries(&self, entries: &[NewAuditEntry]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for entry in entries {
+            sqlx::query(
+                "INSERT INTO audit_log (actor, resource, resource_id, action, before, after) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(&entry.actor)
+            .bind(entry.resource)
+            .bind(&entry.resource_id)
+            .bind(entry.action)
+            .bind(&entry.before)
+            .bind(&entry.after)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await
+    }
+
+    /// Up to `limit` audit entries matching `filter`, newest first.
+    pub async fn audit_entries(
+        &self,
+        filter: &AuditFilter,
+        limit: i64,
+    ) -> Result<Vec<StoredAuditEntry>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, CAST(changed_at AS TEXT) AS changed_at, actor, resource, resource_id, \
+                    action, before, after \
+             FROM audit_log \
+             WHERE (?1 IS NULL OR resource = ?1) \
+               AND (?2 IS NULL OR resource_id = ?2) \
+               AND (?3 IS NULL OR actor = ?3) \
+               AND (?4 IS NULL OR changed_at >= ?4) \
+               AND (?5 IS NULL OR id < ?5) \
+             ORDER BY id DESC LIMIT ?6",
+        )
+        .bind(&filter.resource)
+        .bind(&filter.resource_id)
+        .bind(&filter.actor)
+        .bind(&filter.since)
+        .bind(filter.before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Appends `entry` to the request log. `hash` is given the previous
+    /// entry's hash, empty for the first entry, and the time the entry is
+    /// recorded at, and returns the entry's hash.
+    ///
+    /// The write lock is taken up front, so concurrent appends, from this
+    /// replica or another, each chain to the one before.
+    pub async fn append_request(
+        &self,
+        entry: &NewRequestEntry,
+        hash: impl FnOnce(&str, &str) -> String,
+    ) -> Result<StoredRequestEntry, sqlx::Error> {
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        let prev_hash: String =
+            sqlx::query_scalar("SELECT hash FROM request_log ORDER BY id DESC LIMIT 1")
+                .fetch_optional(&mut *tx)
+                .await?
+                .unwrap_or_default();
+        let received_at: String = sqlx::query_scalar("SELECT datetime('now')")
+            .fetch_one(&mut *tx)
+            .await?;
+        let hash = hash(&prev_hash, &received_at);
+        let id = sqlx::query(
+            "INSERT INTO request_log \
+                 (received_at, actor, method, path, body_sha256, status, prev_hash, hash) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )
+        .bind(&received_at)
+        .bind(&entry.actor)
+        .bind(&entry.method)
+        .bind(&entry.path)
+        .bind(&entry.body_sha256)
+        .bind(entry.status)
+        .bind(&prev_hash)
+        .bind(&hash)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+        tx.commit().await?;
+        Ok(StoredRequestEntry {
+            id,
+            received_at,
+            actor: entry.actor.clone(),
+            method: entry.method.clone(),
+            path: entry.path.clone(),
+            body_sha256: entry.body_sha256.clone(),
+            status: i64::from(entry.status),
+            prev_hash,
+            hash,
+        })
+    }
+
+    /// Up to `limit` request log entries matching `filter`, newest first.
+    pub async fn request_entries(
+        &self,
+        filter: &RequestFilter,
+        limit: i64,
+    ) -> Result<Vec<StoredRequestEntry>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, CAST(received_at AS TEXT) AS received_at, actor, method, path, \
+                    body_sha256, status, prev_hash, hash \
+             FROM request_log \
+             WHERE (?1 IS NULL OR actor = ?1) \
+               AND (?2 IS NULL OR method = ?2) \
+               AND (?3 IS NULL OR received_at >= ?3) \
+               AND (?4 IS NULL OR id < ?4) \
+             ORDER BY id DESC LIMIT ?5",
+        )
+        .bind(&filter.actor)
+        .bind(&filter.method)
+        .bind(&filter.since)
+        .bind(filter.before_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Up to `limit` request log entries after `after_id`, oldest first.
+    pub async fn request_chain(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<StoredRequestEntry>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, CAST(received_at AS TEXT) AS received_at, actor, method, path, \
+                    body_sha256, status, prev_hash, hash \
+             FROM request_log WHERE id > ?1 ORDER BY id LIMIT ?2",
+        )
+        .bind(after_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Adds `readings` and `analyses` to `tenant`'s usage today, unless that
+    /// would take it past `max_readings` or `max_analyses`, in which case the
+    /// request is counted as rejected instead. Returns whether it was added.
+    pub async fn charge_usage(
+        &self,
+        tenant: &str,
+        readings: i64,
+        analyses: i64,
+        max_readings: Option<i64>,
+        max_analyses: Option<i64>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with("BEGIN IMMEDIATE").await?;
+        sqlx::query(
+            "INSERT INTO tenant_usage (tenant, day) VALUES (?1, date('now')) \
+             ON CONFLICT (tenant, day) DO NOTHING",
+        )
+        .bind(tenant)
+        .execute(&mut *tx)
+        .await?;
+        let charged = sqlx::query(
+            "UPDATE tenant_usage SET readings = readings + ?2, analyses = analyses + ?3 \
+             WHERE tenant = ?1 AND day = date('now') \
+               AND (?4 IS NULL OR readings + ?2 <= ?4) \
+               AND (?5 IS NULL OR analyses + ?3 <= ?5)",
+        )
+        .bind(tenant)
+        .bind(readings)
+        .bind(analyses)
+        .bind(max_readings)
+        .bind(max_analyses)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected()
+            > 0;
+        if !charged {
+            sqlx::query(
+                "UPDATE tenant_usage SET rejected = rejected + 1 \
+                 WHERE tenant = ?1 AND day = date('now')",
+            )
+            .bind(tenant)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(charged)
+    }
+
+    /// Daily usage from `since` to `until`, both `YYYY-MM-DD` and included,
+    /// of `tenant` or of every tenant, by tenant and day.
+    pub async fn tenant_usage(
+        &self,
+        tenant: Option<&str>,
+        since: &str,
+        until: &str,
+    ) -> Result<Vec<TenantUsage>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT tenant, CAST(day AS TEXT) AS day, readings, analyses, rejected \
+             FROM tenant_usage \
+             WHERE (?1 IS NULL OR tenant = ?1) AND day >= ?2 AND day <= ?3 \
+             ORDER BY tenant, day",
+        )
+        .bind(tenant)
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Audit entries of changes between `since` and `until` to the registry
+    /// entries, tags or shadow detectors of `sensor_ids`, or to the runtime
+    /// settings, newest first.
+    pub async fn sensor_changes(
+        &self,
+        sensor_ids: &[i64],
+        since: &str,
+        until: &str,
+    ) -> Result<Vec<StoredAuditEntry>, sqlx::Error> {
+        let ids: Vec<String> = sensor_ids.iter().map(i64::to_string).collect();
+        let ids = serde_json::to_string(&ids).unwrap_or_else(|_| "[]".to_string());
+        sqlx::query_as(
+            "SELECT id, CAST(changed_at AS TEXT) AS changed_at, actor, resource, resource_id, \
+                    action, before, after \
+             FROM audit_log \
+             WHERE changed_at >= ?2 AND changed_at <= ?3 \
+               AND (resource = 'settings' \
+                    OR resource IN ('sensor', 'sensor_tags', 'sensor_shadow') \
+                       AND resource_id IN (SELECT value FROM json_each(?1))) \
+             ORDER BY id DESC",
+        )
+        .bind(ids)
+        .bind(since)
+        .bind(until)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Inserts anomalies in a single transaction, returning them as stored.
+    pub async fn insert_anomalies(
+        &self,
+        anomalies: &[NewAnomaly],
+    ) -> Result<Vec<StoredAnomaly>, sqlx::Error> {
+        self.alerts.insert(anomalies).await
+    }
+
+    /// Inserts anomalies scored from request readings, whose timestamps are
+    /// normalized first so range filters match them; one that is not a
+    /// valid timestamp is kept as given.
+    pub async fn record_anomalies(
+        &self,
+        mut anomalies: Vec<NewAnomaly>,
+    ) -> Result<Vec<StoredAnomaly>, sqlx::Error> {
+        for anomaly in &mut anomalies {
+            if let Some(timestamp) = self.normalize_timestamp(&anomaly.timestamp).await? {
+                anomaly.timestamp = timestamp;
+            }
+        }
+        self.insert_anomalies(&anomalies).await
+    }
+}
+
+#[cfg(test)]
+pub mod testing {
+    use detection_core::Severity;
+
+    use super::*;
+
+    const READINGS_SCHEMA: &str = include_str!("../../../db/migrations/004_readings.sql");
+
+    /// In-memory database with the `readings` and `anomalies` tables.
+    ///
+    /// Foreign keys are off because the parent `sensors` table is not created.
+    pub async fn in_memory() -> Storage {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")
+            .unwrap()
+            .foreign_keys(false);
+        // A single connection keeps the in-memory database alive and shared.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect_with(options)
+            .await
+            .unwrap();
+        sqlx::raw_sql(READINGS_SCHEMA).execute(&pool).await.unwrap();
+        Storage::from_pool(pool).await.unwrap()
+    }
+
+    /// Creates a minimal `sensors` table holding `ids` and turns foreign keys
+    /// on, so writes for other sensors fail as they do in production.
+    pub async fn enforce_sensors(storage: &Storage, ids: &[i64]) {
+        sqlx::raw_sql("CREATE TABLE sensors (id INTEGER PRIMARY KEY)")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+        for id in ids {
+            sqlx::query("INSERT INTO sensors (id) VALUES (?1)")
+                .bind(id)
+                .execute(&storage.pool)
+                .await
+                .unwrap();
+        }
+        sqlx::raw_sql("PRAGMA foreign_keys = ON")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    pub async fn insert_reading(storage: &Storage, sensor_id: i64, value: f64, timestamp: &str) {
+        sqlx::query("INSERT INTO readings (sensor_id, value, timestamp) VALUES (?1, ?2, ?3)")
+            .bind(sensor_id)
+            .bind(value)
+            .bind(timestamp)
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    pub async fn insert_anomaly(storage: &Storage, sensor_id: i64, timestamp: &str) {
+        storage
+            .insert_anomalies(&[NewAnomaly {
+                reading_id: 0,
+                sensor_id,
+                value: 0.0,
+                timestamp: timestamp.to_string(),
+                method: "zscore",
+                score: 3.0,
+                severity: Severity::High,
+            }])
+            .await
+            .unwrap();
+    }
+
+    /// Moves the opening time of every incident `minutes` into the past.
+    pub async fn backdate_incidents(storage: &Storage, minutes: i64) {
+        sqlx::query("UPDATE incidents SET opened_at = datetime(opened_at, ?1)")
+            .bind(format!("-{} minutes", minutes))
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    /// Lets every job lease expire.
+    pub async fn expire_leases(storage: &Storage) {
+        sqlx::query("UPDATE job_leases SET expires_at = datetime('now', '-1 second')")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    /// Makes every claim on the notification queue an hour old.
+    pub async fn expire_claims(storage: &Storage) {
+        sqlx::query("UPDATE notification_queue SET claimed_at = datetime(claimed_at, '-1 hour')")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    /// Makes every pending webhook delivery due now.
+    pub async fn expire_backoff(storage: &Storage) {
+        sqlx::query("UPDATE webhook_deliveries SET next_attempt_at = datetime('now', '-1 second')")
+            .execute(&storage.pool)
+            .await
+            .unwrap();
+    }
+
+    /// Runs `sql` as written, changing tables behind the service's back.
+    pub async fn execute(storage: &Storage, sql: &str) -> Result<(), sqlx::Error> {
+        sqlx::raw_sql(sql).execute(&storage.pool).await.map(|_| ())
+    }
+
+    pub async fn count_anomalies(storage: &Storage, sensor_id: i64) -> i64 {
+        sqlx::query_scalar("SELECT COUNT(*) FROM anomalies WHERE sensor_id = ?1")
+            .bind(sensor_id)
+            .fetch_one(&storage.pool)
+            .await
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use detection_core::Severity;
+
+    use super::testing::*;
+    use super::*;
+
+    #[tokio::test]
+    async fn test_normalize_range() {
+        let storage = in_memory().await;
+
+        let range = storage
+            .normalize_range("2026-01-19T10:00:00", "2026-01-20")
+            .await
+            .unwrap();
+        assert_eq!(
+            range,
+            Some((
+                "2026-01-19 10:00:00".to_string(),
+                "2026-01-20 00:00:00".to_string()
+            ))
+        );
+
+        assert!(
+            storage
+                .normalize_range("2026-01-20", "2026-01-19")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            storage
+                .normalize_range("yesterday", "2026-01-19")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_readings_page_filters_and_paginates() {
+        let storage = in_memory().await;
+        for minute in 0..5 {
+            let ts = format!("2026-01-19 10:0{}:00.000000", minute);
+            insert_reading(&storage, 1, minute as f64, &ts).await;
+        }
+        insert_reading(&storage, 2, 99.0, "2026-01-19 10:02:00.000000").await;
+
+        let first = storage
+            .readings_page(1, "2026-01-19 10:01:00", "2026-01-19 10:04:00", 0, 2)
+            .await
+            .unwrap();
+        assert_eq!(first.len(), 2);
+        assert_eq!(first[0].value, 1.0);
+
+        let rest = storage
+            .readings_page(
+                1,
+                "2026-01-19 10:01:00",
+                "2026-01-19 10:04:00",
+                first[1].id,
+                2,
+            )
+            .await
+            .unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].value, 3.0);
+        assert_eq!(rest[0].timestamp, "2026-01-19 10:03:00.000000");
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_pages_streams_all_matches() {
+        use futures_util::TryStreamExt;
+
+        let storage = in_memory().await;
+        for minute in 0..5 {
+            insert_anomaly(&storage, 1, &format!("2026-01-19 10:0{}:00", minute)).await;
+        }
+        insert_anomaly(&storage, 2, "2026-01-19 10:00:00").await;
+
+        let filter = AnomalyFilter {
+            sensor_id: Some(1),
+            method: Some("zscore".to_string()),
+            ..AnomalyFilter::default()
+        };
+        let pages: Vec<Vec<StoredAnomaly>> = storage
+            .anomaly_pages(filter, 2)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_anomalies_go_to_the_configured_alert_store() {
+        let storage = in_memory()
+            .await
+            .with_alerts(Arc::new(alert_store::MemoryAlertStore::default()));
+        insert_anomaly(&storage, 1, "2026-01-19 10:00:00").await;
+
+        let listed = storage
+            .list_anomalies(&AnomalyFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(
+            storage
+                .anomalies_by_id(&[listed[0].id])
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(count_anomalies(&storage, 1).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_record_anomalies_normalizes_timestamps() {
+        let storage = in_memory().await;
+        let anomaly = |reading_id, timestamp: &str| NewAnomaly {
+            reading_id,
+            sensor_id: 1,
+            value: 99.5,
+            timestamp: timestamp.to_string(),
+            method: "zscore",
+            score: 4.2,
+            severity: Severity::High,
+        };
+        storage
+            .record_anomalies(vec![
+                anomaly(1, "2026-01-19T10:00:00Z"),
+                anomaly(2, "yesterday"),
+            ])
+            .await
+            .unwrap();
+
+        let listed = storage
+            .list_anomalies(&AnomalyFilter::default(), 0, 10)
+            .await
+            .unwrap();
+        let timestamps: Vec<&str> = listed.iter().map(|a| a.timestamp.as_str()).collect();
+        assert_eq!(timestamps, ["2026-01-19 10:00:00", "yesterday"]);
+    }
+
+    #[tokio::test]
+    async fn test_sensor_tags_replace_and_lookup() {
+        let storage = in_memory().await;
+        let tags = |list: &[&str]| list.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+
+        storage.set_sensor_tags(1, &tags(&["old"])).await.unwrap();
+        storage
+            .set_sensor_tags(1, &tags(&["line:a", "critical-path", "line:a"]))
+            .await
+            .unwrap();
+        storage
+            .set_sensor_tags(2, &tags(&["line:b"]))
+            .await
+            .unwrap();
+
+        let found = storage.sensor_tags(&[1, 3]).await.unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[&1], tags(&["critical-path", "line:a"]));
+    }
+
+    #[tokio::test]
+    async fn test_silence_lifecycle() {
+        let storage = in_memory().await;
+        let silence = |starts_at: &str, ends_at: &str| NewSilence {
+            matchers: r#"{"sensors":[1]}"#.to_string(),
+            comment: String::new(),
+            created_by: String::new(),
+            starts_at: starts_at.to_string(),
+            ends_at: ends_at.to_string(),
+        };
+
+        let active = storage
+            .insert_silence(&silence("2000-01-01 00:00:00", "2999-01-01 00:00:00"))
+            .await
+            .unwrap();
+        let pending = storage
+            .insert_silence(&silence("2998-01-01 00:00:00", "2999-01-01 00:00:00"))
+            .await
+            .unwrap();
+        storage
+            .insert_silence(&silence("2000-01-01 00:00:00", "2000-01-02 00:00:00"))
+            .await
+            .unwrap();
+
+        let ids = |silences: Vec<StoredSilence>| silences.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(active.ends_at, "2999-01-01 00:00:00");
+        assert_eq!(
+            ids(storage.list_silences(true).await.unwrap()),
+            vec![active.id]
+        );
+        assert_eq!(
+            ids(storage.list_silences(false).await.unwrap()),
+            vec![active.id, pending.id]
+        );
+
+        assert!(storage.expire_silence(active.id).await.unwrap());
+        assert!(!storage.expire_silence(active.id).await.unwrap());
+        assert!(storage.list_silences(true).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_incident_lifecycle() {
+        let storage = in_memory().await;
+        let now = storage.cutoff(Duration::ZERO).await.unwrap();
+        let pending = |level| {
+            let storage = storage.clone();
+            let now = now.clone();
+            async move {
+                storage
+                    .pending_incidents(level, &now)
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|i| i.last_anomaly_id)
+                    .collect::<Vec<_>>()
+            }
+        };
+
+        storage
+            .open_incidents(&[(1, "zscore", 10), (2, "zscore", 11)])
+            .await
+            .unwrap();
+        storage.open_incidents(&[(1, "zscore", 12)]).await.unwrap();
+        backdate_incidents(&storage, 5).await;
+        assert_eq!(pending(0).await, vec![12, 11]);
+
+        assert!(storage.acknowledge_incident(2, "zscore").await.unwrap());
+        assert!(!storage.acknowledge_incident(2, "zscore").await.unwrap());
+        let first = storage.pending_incidents(0, &now).await.unwrap();
+        storage.escalate_incident(first[0].id).await.unwrap();
+        assert!(pending(0).await.is_empty());
+        assert_eq!(pending(1).await, vec![12]);
+
+        storage.resolve_incident(1, "zscore").await.unwrap();
+        storage.open_incidents(&[(1, "zscore", 13)]).await.unwrap();
+        assert!(pending(1).await.is_empty());
+    }
+}
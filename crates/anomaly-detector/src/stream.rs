@@ -0,0 +1,1117 @@
+//! Stateful scoring of readings as they arrive: `POST /stream/ingest`, and
+//! the `/stream` WebSocket for gateways that keep a connection open.
+//!
+//! The service keeps a window per sensor and scores each reading against
+//! the readings of that sensor before it, so a collector sends only its new
+//! readings instead of the whole history. The window is the last
+//! `stream_window` readings, their z-score scoring starting once the window
+//! is full, or with `stream_decay` set an exponentially weighted average in
+//! which each new reading weighs `stream_decay`, scoring starting once
+//! `1 / stream_decay` readings were seen. A request may ask for another
+//! `window`, of at most `stream_max_window` readings, or `decay`, which
+//! starts the sensor's window over, or for
+//! `method` `holt_winters`, which scores readings by their residual from a
+//! forecast of the sensor's level, trend and season of `seasonal_period`
+//! readings, scoring starting after two seasons, like the detector of
+//! `/analyze`.
+//!
+//! The sensor's registry entry applies as in `/analyze`: its threshold,
+//! limits and rules. Windows are kept for the `max_tracked_sensors` sensors
+//! streamed most recently and dropped after `sensor_idle_secs`, like the
+//! per-sensor metrics; a sensor coming back starts over.
+//!
+//! With `redis_url` set windows live in Redis (see `shared`), else with
+//! storage in `stream_windows`, so replicas behind a load balancer score a
+//! sensor against the same window: each request reads the sensor's window,
+//! scores a copy and writes it back only if no other replica wrote it
+//! meanwhile, else scores again against theirs. Without either each
+//! process keeps its own windows, so a sensor's streams need to reach the
+//! same one.
+//!
+//! A `/stream` client sends readings as JSON objects, one per line and any
+//! number of lines per message, each naming its `sensor_id` or falling back
+//! to the one of the query string, e.g.
+//! `/stream?sensor_id=7&threshold=3.5`, which may also set `window` or
+//! `decay`. The readings share their sensors' windows with
+//! `/stream/ingest`. Every anomaly among them is sent back as soon as its
+//! message is scored, as JSON with a `type` of `anomaly` and the fields of
+//! a `/ws/anomalies` event; a message or sensor that cannot be scored is
+//! answered with an `error`. The gRPC `StreamReadings` (see `grpc`) takes
+//! readings and those settings in each message and answers the same.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    Json,
+    extract::{
+        Query, State,
+        ws::{Message, WebSocket, WebSocketUpgrade},
+    },
+    http::{HeaderMap, StatusCode},
+    response::Response,
+};
+use detection_core::detector::{Detector, HoltWintersDetector};
+use detection_core::stats::{Ewma, HoltWinters, RunningStats, ZScorer, summarize};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::activity::Recent;
+use crate::config::{StreamPolicy, Tracking};
+use crate::events::AnomalyEvent;
+use crate::json::JsonBody;
+use crate::lanes::Lane;
+use crate::shared::Redis;
+use crate::storage::Storage;
+use crate::validation::ApiError;
+use crate::{
+    AnalyzeResponse, Anomaly, AppState, Reading, grade, publish, resolve_threshold, sensors, usage,
+    validation,
+};
+
+/// Most readings accepted in one request; a collector sends what arrived
+/// since its last one.
+const MAX_STREAM_READINGS: usize = 10_000;
+
+/// Times a stored window is read and scored again after another replica
+/// wrote it first.
+const MAX_WINDOW_WRITES: usize = 5;
+
+#[derive(Deserialize)]
+pub struct IngestRequest {
+    sensor_id: i64,
+    readings: Vec<Reading>,
+    /// Defaults to the sensor's registered threshold, else the runtime
+    /// `default_threshold`.
+    #[serde(default)]
+    threshold: Option<f64>,
+    /// Replaces `stream_window` for this sensor.
+    #[serde(default)]
+    window: Option<usize>,
+    /// Replaces `stream_decay` for this sensor.
+    #[serde(default)]
+    decay: Option<f64>,
+    /// `holt_winters` to forecast the sensor's readings rather than keep
+    /// a window of them.
+    #[serde(default)]
+    method: Option<String>,
+}
+
+/// How a sensor's window is kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shape {
+    Rolling(usize),
+    Decaying(f64),
+    /// A season of that many readings.
+    Seasonal(usize),
+}
+
+impl Shape {
+    fn of(
+        policy: &StreamPolicy,
+        method: Option<&str>,
+        window: Option<usize>,
+        decay: Option<f64>,
+    ) -> Result<Shape, String> {
+        let seasonal = HoltWintersDetector::default().name();
+        match method {
+            None => {}
+            Some(method) if method == seasonal => {
+                return match (window, decay) {
+                    (None, None) => Ok(Shape::Seasonal(policy.season)),
+                    _ => Err(format!("window and decay do not apply to {}", seasonal)),
+                };
+            }
+            Some(method) => {
+                return Err(format!(
+                    "unknown method {:?}; streams take {}, or a window or decay",
+                    method, seasonal
+                ));
+            }
+        }
+        match (window, decay) {
+            (Some(_), Some(_)) => Err("set either window or decay, not both".to_string()),
+            (Some(0), None) => Err("window must be positive".to_string()),
+            (Some(window), None) if window > policy.max_window => Err(format!(
+                "window must be at most {}, got {}",
+                policy.max_window, window
+            )),
+            (Some(window), None) => Ok(Shape::Rolling(window)),
+            (None, Some(decay)) if decay > 0.0 && decay <= 1.0 => Ok(Shape::Decaying(decay)),
+            (None, Some(_)) => Err("decay must be in (0, 1]".to_string()),
+            (None, None) => Ok(match policy.decay {
+                Some(decay) => Shape::Decaying(decay),
+                None => Shape::Rolling(policy.window),
+            }),
+        }
+    }
+
+    fn method(&self) -> &'static str {
+        match self {
+            Shape::Rolling(_) => "rolling",
+            Shape::Decaying(_) => "ewma",
+            Shape::Seasonal(_) => HoltWintersDetector::default().name(),
+        }
+    }
+}
+
+/// The readings of one sensor that its next reading is scored against.
+#[derive(Clone, Deserialize, Serialize)]
+enum Window {
+    /// The last `size` values and their running statistics, summarized
+    /// afresh each `size` values dropped so rounding does not build up.
+    Rolling {
+        size: usize,
+        values: VecDeque<f64>,
+        stats: RunningStats,
+        dropped: usize,
+    },
+    Decaying(Ewma),
+    Seasonal(HoltWinters),
+}
+
+impl Window {
+    fn new(shape: Shape) -> Self {
+        match shape {
+            Shape::Rolling(size) => Window::Rolling {
+                size,
+                values: VecDeque::with_capacity(size.min(MAX_STREAM_READINGS)),
+                stats: RunningStats::default(),
+                dropped: 0,
+            },
+            Shape::Decaying(alpha) => Window::Decaying(Ewma::new(alpha)),
+            Shape::Seasonal(period) => Window::Seasonal(
+                HoltWintersDetector {
+                    period,
+                    ..HoltWintersDetector::default()
+                }
+                .model(),
+            ),
+        }
+    }
+
+    fn shape(&self) -> Shape {
+        match self {
+            Window::Rolling { size, .. } => Shape::Rolling(*size),
+            Window::Decaying(ewma) => Shape::Decaying(ewma.alpha()),
+            Window::Seasonal(model) => Shape::Seasonal(model.period()),
+        }
+    }
+
+    /// The z-score of `value` and whether it is past `threshold`, then adds
+    /// it to the window. Readings are not outliers until the window is warm.
+    fn score(&mut self, value: f64, threshold: f64) -> (f64, bool) {
+        match self {
+            Window::Rolling {
+                size,
+                values,
+                stats,
+                dropped,
+            } => {
+                let scorer = ZScorer::new(stats, threshold);
+                let warm = values.len() >= *size;
+                let scored = (scorer.z(value), warm && scorer.score(value).is_some());
+                values.push_back(value);
+                stats.push(value);
+                if warm && let Some(oldest) = values.pop_front() {
+                    stats.remove(oldest);
+                    *dropped += 1;
+                    if *dropped >= *size {
+                        *stats = summarize(values.iter().copied());
+                        *dropped = 0;
+                    }
+                }
+                scored
+            }
+            Window::Decaying(ewma) => {
+                let warm = ewma.count() as f64 * ewma.alpha() >= 1.0;
+                let z = ewma.z(value);
+                ewma.push(value);
+                (z, warm && z.abs() > threshold)
+            }
+            Window::Seasonal(model) => {
+                let warm = model.is_fitted();
+                let z = model.z(value);
+                model.push(value);
+                (z, warm && z.abs() > threshold)
+            }
+        }
+    }
+
+    fn mean_and_std_dev(&self) -> (f64, f64) {
+        match self {
+            Window::Rolling { stats, .. } => (stats.mean(), stats.std_dev()),
+            Window::Decaying(ewma) => (ewma.mean(), ewma.std_dev()),
+            // The level, and the deviation of the residuals scored by.
+            Window::Seasonal(model) => (model.level(), model.residual_std_dev()),
+        }
+    }
+}
+
+/// Where replicas keep the windows they share.
+#[derive(Clone, Copy)]
+enum Shared<'a> {
+    Redis(&'a Redis),
+    Storage(&'a Storage),
+}
+
+impl<'a> Shared<'a> {
+    fn of(state: &'a AppState) -> Option<Self> {
+        match (&state.redis, &state.storage) {
+            (Some(redis), _) => Some(Self::Redis(redis)),
+            (None, Some(storage)) => Some(Self::Storage(storage)),
+            (None, None) => None,
+        }
+    }
+
+    /// The version and state of `sensor_id`'s window, and whether it was
+    /// written within `idle`.
+    async fn load(
+        self,
+        sensor_id: i64,
+        idle: Duration,
+    ) -> Result<Option<(i64, String, bool)>, String> {
+        match self {
+            // Redis expires the windows left idle itself.
+            Self::Redis(redis) => redis
+                .stream_window(sensor_id)
+                .await
+                .map(|stored| stored.map(|(version, state)| (version, state, true)))
+                .map_err(|e| e.to_string()),
+            Self::Storage(storage) => storage
+                .stream_window(sensor_id, idle.as_secs())
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+
+    async fn save(
+        self,
+        sensor_id: i64,
+        read: Option<i64>,
+        state: &str,
+        idle: Duration,
+    ) -> Result<bool, String> {
+        match self {
+            Self::Redis(redis) => redis
+                .save_stream_window(sensor_id, read, state, idle)
+                .await
+                .map_err(|e| e.to_string()),
+            Self::Storage(storage) => storage
+                .save_stream_window(sensor_id, read, state)
+                .await
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// A sensor's window as this replica last scored it, and the version of
+/// the stored window it was read from or written as.
+#[derive(Default)]
+struct Held {
+    window: Option<Window>,
+    version: Option<i64>,
+}
+
+struct Windows {
+    by_sensor: HashMap<i64, Arc<AsyncMutex<Held>>>,
+    recent: Recent,
+}
+
+/// Every streamed sensor's window, each behind a lock of its own, so
+/// scoring one sensor's readings holds up no other sensor.
+pub struct Streams {
+    policy: StreamPolicy,
+    limits: Tracking,
+    windows: Mutex<Windows>,
+}
+
+impl Default for Streams {
+    fn default() -> Self {
+        Self::new(StreamPolicy::default(), Tracking::default())
+    }
+}
+
+impl Streams {
+    pub fn new(policy: StreamPolicy, limits: Tracking) -> Self {
+        Self {
+            policy,
+            limits,
+            windows: Mutex::new(Windows {
+                by_sensor: HashMap::new(),
+                recent: Recent::default(),
+            }),
+        }
+    }
+
+    /// Runs `f` on the window of `sensor_id`, started over when it has
+    /// another shape.
+    ///
+    /// With Redis or storage the window is the shared one, which every
+    /// replica scores against: `f` runs on a copy, written back only if no
+    /// other replica wrote the window since it was read, else `f` runs again
+    /// on theirs.
+    async fn with_window<T>(
+        &self,
+        shared: Option<Shared<'_>>,
+        sensor_id: i64,
+        shape: Shape,
+        mut f: impl FnMut(&mut Window) -> T,
+    ) -> Result<T, (StatusCode, String)> {
+        let now = Instant::now();
+        let held = {
+            let mut windows = self.windows.lock().unwrap();
+            windows.recent.touch(sensor_id, now);
+            for evicted in windows.recent.evict(now, &self.limits) {
+                windows.by_sensor.remove(&evicted);
+            }
+            windows.by_sensor.entry(sensor_id).or_default().clone()
+        };
+        let mut held = held.lock().await;
+        let Some(shared) = shared else {
+            let window = held.window.get_or_insert_with(|| Window::new(shape));
+            if window.shape() != shape {
+                *window = Window::new(shape);
+            }
+            return Ok(f(window));
+        };
+        let internal = |e: &dyn std::fmt::Display| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("stream window of sensor {}: {}", sensor_id, e),
+            )
+        };
+        for _ in 0..MAX_WINDOW_WRITES {
+            let stored = shared
+                .load(sensor_id, self.limits.idle)
+                .await
+                .map_err(|e| internal(&e))?;
+            let read = stored.as_ref().map(|(version, ..)| *version);
+            match stored {
+                // A window left idle starts over, as one dropped here would.
+                Some((_, _, false)) | None => held.window = None,
+                Some((version, state, true)) if held.version != Some(version) => {
+                    held.window = serde_json::from_str(&state).ok();
+                }
+                Some(_) => {}
+            }
+            held.version = read;
+            let mut window = match &held.window {
+                Some(window) if window.shape() == shape => window.clone(),
+                _ => Window::new(shape),
+            };
+            let result = f(&mut window);
+            let state = serde_json::to_string(&window).map_err(|e| internal(&e))?;
+            if shared
+                .save(sensor_id, read, &state, self.limits.idle)
+                .await
+                .map_err(|e| internal(&e))?
+            {
+                held.window = Some(window);
+                held.version = Some(read.map_or(1, |version| version + 1));
+                return Ok(result);
+            }
+        }
+        Err((
+            StatusCode::CONFLICT,
+            format!(
+                "the stream window of sensor {} kept changing under other replicas; retry",
+                sensor_id
+            ),
+        ))
+    }
+}
+
+/// Scores readings of one sensor against its window, in order, and adds
+/// them to it; `mean` and `std_dev` are the window's afterwards.
+pub async fn ingest(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<IngestRequest>,
+) -> Result<Json<AnalyzeResponse>, ApiError> {
+    if payload.readings.len() > MAX_STREAM_READINGS {
+        return Err(ApiError::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!(
+                "request contains {} readings, the limit is {}",
+                payload.readings.len(),
+                MAX_STREAM_READINGS
+            ),
+        ));
+    }
+    if payload.readings.is_empty() {
+        return Err(ApiError::invalid(
+            "readings",
+            "must contain at least one reading",
+        ));
+    }
+    if let Some(position) = payload
+        .readings
+        .iter()
+        .position(|r| r.sensor_id.is_some_and(|id| id != payload.sensor_id))
+    {
+        return Err(ApiError::invalid(
+            format!("readings[{}].sensor_id", position),
+            format_args!("names another sensor than {}", payload.sensor_id),
+        ));
+    }
+    validation::validate_readings(&payload.readings, Some(payload.sensor_id))?;
+    validation::validate_threshold(payload.threshold)?;
+    let shape = Shape::of(
+        &state.streams.policy,
+        payload.method.as_deref(),
+        payload.window,
+        payload.decay,
+    )
+    .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    usage::charge(&state, &headers, payload.readings.len(), 1).await?;
+    let _lane = state.lanes.enter(Lane::of(&headers)).await;
+
+    let response = score(
+        &state,
+        payload.sensor_id,
+        payload.readings,
+        payload.threshold,
+        shape,
+    )
+    .await?;
+    Ok(Json(response))
+}
+
+/// Scores `readings` of one sensor against its window, then publishes,
+/// stores and notifies their anomalies like `/analyze`.
+async fn score(
+    state: &AppState,
+    sensor_id: i64,
+    readings: Vec<Reading>,
+    threshold: Option<f64>,
+    shape: Shape,
+) -> Result<AnalyzeResponse, (StatusCode, String)> {
+    let sensor = sensors::registered(state, Some(sensor_id)).await?;
+    let detection = state.settings.detection();
+    let threshold = resolve_threshold(threshold, &detection, sensor.as_ref());
+    let started = Instant::now();
+    let total_readings = readings.len();
+    let score = |window: &mut Window| {
+        let anomalies = readings
+            .iter()
+            .filter_map(|reading| {
+                let (z_score, outlier) = window.score(reading.value, threshold);
+                let severity = grade(z_score, outlier, &detection, sensor.as_ref(), reading.value)?;
+                Some(Anomaly {
+                    id: reading.id,
+                    value: reading.value,
+                    timestamp: reading.timestamp.clone(),
+                    z_score,
+                    severity,
+                    labels: None,
+                })
+            })
+            .collect::<Vec<_>>();
+        (anomalies, window.mean_and_std_dev())
+    };
+    let (anomalies, (mean, std_dev)) = state
+        .streams
+        .with_window(Shared::of(state), sensor_id, shape, score)
+        .await?;
+    state
+        .metrics
+        .observe_detection(shape.method(), total_readings, started.elapsed());
+    state.metrics.observe_anomalies(
+        shape.method(),
+        anomalies.iter().map(|anomaly| anomaly.severity),
+    );
+
+    let response = AnalyzeResponse {
+        anomalies,
+        total_readings,
+        mean,
+        std_dev,
+        export: None,
+        ensemble: None,
+        sensors: Vec::new(),
+        windows: Vec::new(),
+    };
+    publish(state, Some(sensor_id), shape.method(), &response);
+    Ok(response)
+}
+
+#[derive(Default, Deserialize)]
+pub struct StreamQuery {
+    /// Sensor of the readings that do not name theirs.
+    #[serde(default)]
+    pub sensor_id: Option<i64>,
+    #[serde(default)]
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub window: Option<usize>,
+    #[serde(default)]
+    pub decay: Option<f64>,
+    #[serde(default)]
+    pub method: Option<String>,
+}
+
+/// What `/stream` sends back.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Pushed {
+    Anomaly(AnomalyEvent),
+    Error {
+        error: String,
+        /// Path of the reading to blame, e.g. `readings[3].value`, the
+        /// readings of a message numbered from 0.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        field: Option<String>,
+    },
+}
+
+impl From<ApiError> for Pushed {
+    fn from(error: ApiError) -> Self {
+        Pushed::Error {
+            error: error.error,
+            field: error.field,
+        }
+    }
+}
+
+/// Upgrades to a WebSocket taking newline-delimited JSON readings and
+/// answering with the anomalies among them, scored like `/stream/ingest`.
+pub async fn connect(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<StreamQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    validation::validate_threshold(query.threshold)?;
+    let shape = Shape::of(
+        &state.streams.policy,
+        query.method.as_deref(),
+        query.window,
+        query.decay,
+    )
+    .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+    Ok(ws.on_upgrade(move |socket| feed(socket, state, headers, query, shape)))
+}
+
+/// Scores each message received until the client closes.
+async fn feed(
+    mut socket: WebSocket,
+    state: AppState,
+    headers: HeaderMap,
+    query: StreamQuery,
+    shape: Shape,
+) {
+    while let Some(Ok(message)) = socket.recv().await {
+        let text = match message {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        for pushed in receive(&state, &headers, &query, shape, &text).await {
+            let Ok(text) = serde_json::to_string(&pushed) else {
+                continue;
+            };
+            if socket.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Scores the readings of one message, a sensor at a time, and returns the
+/// anomalies found, or why a sensor's readings or the message were refused.
+async fn receive(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &StreamQuery,
+    shape: Shape,
+    text: &str,
+) -> Vec<Pushed> {
+    let by_sensor = match parse(text, query.sensor_id) {
+        Ok(by_sensor) => by_sensor,
+        Err(error) => return vec![error.into()],
+    };
+    score_sensors(state, headers, query.threshold, shape, by_sensor).await
+}
+
+/// Scores the readings of a gRPC `StreamReadings` message, with the
+/// settings of `query`, as [`receive`] the lines of a `/stream` message.
+/// A message with a value that is not a finite number, or an id repeated
+/// within a sensor's readings, is refused whole, naming the reading.
+pub async fn receive_readings(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &StreamQuery,
+    readings: Vec<Reading>,
+) -> Vec<Pushed> {
+    let shape = validation::validate_threshold(query.threshold).and_then(|()| {
+        Shape::of(
+            &state.streams.policy,
+            query.method.as_deref(),
+            query.window,
+            query.decay,
+        )
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))
+    });
+    let shape = match shape {
+        Ok(shape) => shape,
+        Err(error) => return vec![error.into()],
+    };
+    if readings.len() > MAX_STREAM_READINGS {
+        return vec![Pushed::Error {
+            error: format!(
+                "message contains more than {} readings",
+                MAX_STREAM_READINGS
+            ),
+            field: None,
+        }];
+    }
+    if let Err(error) = validation::validate_readings(&readings, query.sensor_id) {
+        return vec![error.into()];
+    }
+    let mut by_sensor = Vec::new();
+    for reading in readings {
+        let Some(sensor_id) = reading.sensor_id.or(query.sensor_id) else {
+            return vec![Pushed::Error {
+                error: format!("reading {} names no sensor_id", reading.id),
+                field: None,
+            }];
+        };
+        group(&mut by_sensor, sensor_id, reading);
+    }
+    score_sensors(state, headers, query.threshold, shape, by_sensor).await
+}
+
+/// Adds `reading` to the readings of `sensor_id`, sensors in the order they
+/// first appear.
+fn group(by_sensor: &mut Vec<(i64, Vec<Reading>)>, sensor_id: i64, reading: Reading) {
+    match by_sensor.iter_mut().find(|(id, _)| *id == sensor_id) {
+        Some((_, readings)) => readings.push(reading),
+        None => by_sensor.push((sensor_id, vec![reading])),
+    }
+}
+
+/// Scores each sensor's readings in turn, charging them and entering a
+/// lane like `/stream/ingest`.
+async fn score_sensors(
+    state: &AppState,
+    headers: &HeaderMap,
+    threshold: Option<f64>,
+    shape: Shape,
+    by_sensor: Vec<(i64, Vec<Reading>)>,
+) -> Vec<Pushed> {
+    let mut pushed = Vec::new();
+    for (sensor_id, readings) in by_sensor {
+        let scored = async {
+            usage::charge(state, headers, readings.len(), 1).await?;
+            let _lane = state.lanes.enter(Lane::of(headers)).await;
+            score(state, sensor_id, readings, threshold, shape).await
+        };
+        match scored.await {
+            Ok(response) => pushed.extend(response.anomalies.into_iter().map(|anomaly| {
+                Pushed::Anomaly(AnomalyEvent {
+                    sensor_id,
+                    reading_id: anomaly.id,
+                    value: anomaly.value,
+                    timestamp: anomaly.timestamp,
+                    method: shape.method().to_string(),
+                    score: anomaly.z_score,
+                    severity: anomaly.severity,
+                })
+            })),
+            Err((_, error)) => pushed.push(Pushed::Error {
+                error: format!("sensor {}: {}", sensor_id, error),
+                field: None,
+            }),
+        }
+    }
+    pushed
+}
+
+/// The readings of a message, one JSON object per line, grouped by sensor
+/// in the order the sensors first appear, unless
+/// [`validation::validate_readings`] refuses them.
+fn parse(text: &str, default_sensor: Option<i64>) -> Result<Vec<(i64, Vec<Reading>)>, ApiError> {
+    let refused = |error: String| ApiError::new(StatusCode::BAD_REQUEST, error);
+    let mut readings = Vec::new();
+    let mut sensors = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if readings.len() == MAX_STREAM_READINGS {
+            return Err(refused(format!(
+                "message contains more than {} readings",
+                MAX_STREAM_READINGS
+            )));
+        }
+        let reading: Reading = serde_json::from_str(line)
+            .map_err(|e| refused(format!("line {}: {}", number + 1, e)))?;
+        let sensor_id = reading
+            .sensor_id
+            .or(default_sensor)
+            .ok_or_else(|| refused(format!("line {}: reading names no sensor_id", number + 1)))?;
+        sensors.push(sensor_id);
+        readings.push(reading);
+    }
+    validation::validate_readings(&readings, default_sensor)?;
+    let mut by_sensor = Vec::new();
+    for (sensor_id, reading) in sensors.into_iter().zip(readings) {
+        group(&mut by_sensor, sensor_id, reading);
+    }
+    Ok(by_sensor)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::storage::testing::in_memory;
+
+    fn request(sensor_id: i64, first_id: i64, values: &[f64]) -> IngestRequest {
+        IngestRequest {
+            sensor_id,
+            readings: values
+                .iter()
+                .enumerate()
+                .map(|(i, &value)| Reading {
+                    id: first_id + i as i64,
+                    value,
+                    timestamp: "2024-01-01T00:00:00Z".to_string(),
+                    sensor_id: None,
+                })
+                .collect(),
+            threshold: None,
+            window: None,
+            decay: None,
+            method: None,
+        }
+    }
+
+    async fn ingest_ids(state: &AppState, request: IngestRequest) -> Vec<i64> {
+        let Json(response) = ingest(State(state.clone()), HeaderMap::new(), JsonBody(request))
+            .await
+            .unwrap();
+        response.anomalies.iter().map(|a| a.id).collect()
+    }
+
+    #[tokio::test]
+    async fn test_readings_are_scored_against_earlier_requests() {
+        let state = AppState {
+            streams: Arc::new(Streams::new(
+                StreamPolicy {
+                    window: 4,
+                    decay: None,
+                    season: 24,
+                    ..StreamPolicy::default()
+                },
+                Tracking::default(),
+            )),
+            ..AppState::default()
+        };
+        let steady = [10.0, 10.5, 9.5, 10.2, 9.8, 10.1];
+        // Not warm yet: the spike of the first request is not scored.
+        assert!(
+            ingest_ids(&state, request(1, 1, &[10.0, 30.0]))
+                .await
+                .is_empty()
+        );
+        assert!(ingest_ids(&state, request(1, 3, &steady)).await.is_empty());
+        assert_eq!(ingest_ids(&state, request(1, 9, &[10.0, 30.0])).await, [10]);
+        // Another sensor has its own window.
+        assert!(ingest_ids(&state, request(2, 1, &[30.0])).await.is_empty());
+
+        // A decay instead starts the sensor over.
+        let mut decaying = request(1, 11, &steady);
+        decaying.decay = Some(0.25);
+        assert!(ingest_ids(&state, decaying).await.is_empty());
+        let mut spike = request(1, 17, &[10.0, 30.0]);
+        spike.decay = Some(0.25);
+        assert_eq!(ingest_ids(&state, spike).await, [18]);
+
+        let mut invalid = request(1, 19, &[10.0]);
+        invalid.window = Some(4);
+        invalid.decay = Some(0.5);
+        let result = ingest(State(state.clone()), HeaderMap::new(), JsonBody(invalid)).await;
+        assert!(matches!(
+            result,
+            Err(ApiError {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            })
+        ));
+        // No request may hold more than `max_window` readings of a sensor.
+        let mut huge = request(1, 19, &[10.0]);
+        huge.window = Some(StreamPolicy::default().max_window + 1);
+        let result = ingest(State(state.clone()), HeaderMap::new(), JsonBody(huge)).await;
+        assert!(matches!(
+            result,
+            Err(ApiError {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_replicas_score_against_the_stored_window() {
+        let storage = in_memory().await;
+        let replica = || AppState {
+            storage: Some(storage.clone()),
+            streams: Arc::new(Streams::new(
+                StreamPolicy {
+                    window: 4,
+                    ..StreamPolicy::default()
+                },
+                Tracking::default(),
+            )),
+            ..AppState::default()
+        };
+        let (first, second) = (replica(), replica());
+        let steady = [10.0, 10.5, 9.5, 10.2];
+        assert!(ingest_ids(&first, request(1, 1, &steady)).await.is_empty());
+        // The second replica goes on with the window the first filled...
+        assert_eq!(ingest_ids(&second, request(1, 5, &[10.0, 30.0])).await, [6]);
+        // ...and the first with the spike the second added to it.
+        assert!(ingest_ids(&first, request(1, 7, &[30.0])).await.is_empty());
+        // A replica writing back a window another one wrote since loses.
+        assert!(!storage.save_stream_window(1, Some(1), "{}").await.unwrap());
+    }
+
+    /// Runs against the Redis at `ANOMALY_TEST_REDIS_URL`; skipped when it
+    /// is not set.
+    #[tokio::test]
+    async fn test_replicas_score_against_the_redis_window() {
+        let Ok(url) = std::env::var("ANOMALY_TEST_REDIS_URL") else {
+            eprintln!("ANOMALY_TEST_REDIS_URL is not set; skipping");
+            return;
+        };
+        let redis = Redis::connect(&url).await.unwrap();
+        // Redis is preferred over storage, which no window reaches.
+        let storage = in_memory().await;
+        let replica = || AppState {
+            storage: Some(storage.clone()),
+            redis: Some(redis.clone()),
+            streams: Arc::new(Streams::new(
+                StreamPolicy {
+                    window: 4,
+                    ..StreamPolicy::default()
+                },
+                Tracking::default(),
+            )),
+            ..AppState::default()
+        };
+        let sensor_id = 1_000_000 + i64::from(std::process::id());
+        let (first, second) = (replica(), replica());
+        let steady = [10.0, 10.5, 9.5, 10.2];
+        assert!(
+            ingest_ids(&first, request(sensor_id, 1, &steady))
+                .await
+                .is_empty()
+        );
+        assert_eq!(
+            ingest_ids(&second, request(sensor_id, 5, &[10.0, 30.0])).await,
+            [6]
+        );
+        assert!(
+            ingest_ids(&first, request(sensor_id, 7, &[30.0]))
+                .await
+                .is_empty()
+        );
+        assert_eq!(storage.stream_window(sensor_id, 3600).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_holt_winters_streams_score_after_two_seasons() {
+        let state = AppState {
+            streams: Arc::new(Streams::new(
+                StreamPolicy {
+                    window: 4,
+                    decay: None,
+                    season: 24,
+                    ..StreamPolicy::default()
+                },
+                Tracking::default(),
+            )),
+            ..AppState::default()
+        };
+        let seasonal = |first_id: i64, values: &[f64]| {
+            let mut request = request(3, first_id, values);
+            request.method = Some("holt_winters".to_string());
+            request.threshold = Some(3.0);
+            request
+        };
+        // A day of 24 hourly readings, with a night as warm as the
+        // afternoons on the fourth.
+        let mut hours: Vec<f64> = (0..96)
+            .map(|hour| {
+                let phase = (hour % 24) as f64 / 24.0 * std::f64::consts::TAU;
+                let noise = ((hour * 37) % 11) as f64 / 10.0 - 0.5;
+                20.0 + 8.0 * phase.sin() + noise
+            })
+            .collect();
+        hours[90] += 6.0;
+        // The two training seasons arrive over several requests, and none
+        // of their readings is scored.
+        for start in (0..48).step_by(12) {
+            let found = ingest_ids(
+                &state,
+                seasonal(start as i64 + 1, &hours[start..start + 12]),
+            );
+            assert!(found.await.is_empty(), "hours from {}", start);
+        }
+        assert!(
+            ingest_ids(&state, seasonal(49, &hours[48..72]))
+                .await
+                .is_empty()
+        );
+        assert_eq!(ingest_ids(&state, seasonal(73, &hours[72..])).await, [91]);
+
+        let mut unknown = seasonal(97, &[20.0]);
+        unknown.method = Some("prophet".to_string());
+        let result = ingest(State(state.clone()), HeaderMap::new(), JsonBody(unknown)).await;
+        assert!(matches!(
+            result,
+            Err(ApiError {
+                status: StatusCode::BAD_REQUEST,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_readings_are_refused_naming_the_field() {
+        let state = AppState::default();
+        let refused = |request: IngestRequest| {
+            let state = state.clone();
+            async move {
+                let Err(error) = ingest(State(state), HeaderMap::new(), JsonBody(request)).await
+                else {
+                    panic!("invalid readings accepted");
+                };
+                assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+                error.field.unwrap()
+            }
+        };
+        assert_eq!(refused(request(1, 1, &[])).await, "readings");
+        assert_eq!(
+            refused(request(1, 1, &[10.0, f64::NAN])).await,
+            "readings[1].value"
+        );
+        let mut repeated = request(1, 1, &[10.0, 11.0]);
+        repeated.readings[1].id = 1;
+        assert_eq!(refused(repeated).await, "readings[1].id");
+        let mut other = request(1, 1, &[10.0]);
+        other.readings[0].sensor_id = Some(2);
+        assert_eq!(refused(other).await, "readings[0].sensor_id");
+        let mut threshold = request(1, 1, &[10.0]);
+        threshold.threshold = Some(0.0);
+        assert_eq!(refused(threshold).await, "threshold");
+        // Nothing refused reached the window.
+        assert!(state.streams.windows.lock().unwrap().by_sensor.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stream_messages_answer_with_their_anomalies() {
+        let state = AppState {
+            streams: Arc::new(Streams::new(
+                StreamPolicy {
+                    window: 4,
+                    decay: None,
+                    season: 24,
+                    ..StreamPolicy::default()
+                },
+                Tracking::default(),
+            )),
+            ..AppState::default()
+        };
+        let query = StreamQuery {
+            sensor_id: Some(1),
+            threshold: None,
+            window: None,
+            decay: None,
+            method: None,
+        };
+        let line = |id: i64, value: f64, sensor: &str| {
+            format!(
+                r#"{{"id": {}, "value": {}, "timestamp": "2024-01-01T00:00:00Z"{}}}"#,
+                id, value, sensor
+            )
+        };
+        let message = |lines: Vec<String>| lines.join("\n");
+        let receive = |text: String| {
+            let (state, query) = (state.clone(), &query);
+            async move { receive(&state, &HeaderMap::new(), query, Shape::Rolling(4), &text).await }
+        };
+
+        // The window is shared with `/stream/ingest`.
+        let steady = [10.0, 10.5, 9.5, 10.2, 9.8, 10.1];
+        assert!(ingest_ids(&state, request(1, 1, &steady)).await.is_empty());
+        let pushed = receive(message(vec![
+            line(7, 10.0, ""),
+            String::new(),
+            line(8, 30.0, ""),
+            line(1, 30.0, r#", "sensor_id": 2"#),
+        ]))
+        .await;
+        assert_eq!(pushed.len(), 1);
+        let Pushed::Anomaly(event) = &pushed[0] else {
+            panic!("expected an anomaly, got {:?}", pushed[0]);
+        };
+        assert_eq!((event.sensor_id, event.reading_id), (1, 8));
+        assert_eq!(event.method, "rolling");
+
+        let invalid = receive(message(vec![line(9, 10.0, ""), "{".to_string()])).await;
+        let Pushed::Error { error, .. } = &invalid[0] else {
+            panic!("expected an error, got {:?}", invalid[0]);
+        };
+        assert!(error.starts_with("line 2:"), "{}", error);
+        let unnamed = parse(&line(9, 10.0, ""), None).err();
+        assert_eq!(unnamed.unwrap().error, "line 1: reading names no sensor_id");
+        let repeated = receive(message(vec![line(9, 10.0, ""), line(9, 11.0, "")])).await;
+        assert_eq!(
+            repeated,
+            [Pushed::Error {
+                error: "readings[1].id 9 repeats the id of readings[0]".to_string(),
+                field: Some("readings[1].id".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_windows_of_idle_sensors_are_dropped() {
+        let streams = Streams::new(
+            StreamPolicy::default(),
+            Tracking {
+                max_sensors: 1,
+                idle: Duration::from_secs(60),
+            },
+        );
+        let shape = Shape::Rolling(4);
+        for sensor_id in [1, 2] {
+            let scored =
+                streams.with_window(None, sensor_id, shape, |window| window.score(10.0, 3.0));
+            scored.await.unwrap();
+        }
+        let windows = streams.windows.lock().unwrap();
+        assert_eq!(windows.by_sensor.keys().collect::<Vec<_>>(), [&2]);
+    }
+
+    #[test]
+    fn test_rolling_windows_keep_the_statistics_of_their_values() {
+        let values: Vec<f64> = (0..1_000)
+            .map(|i| 1e6 + (i as f64 * 0.7).sin() * 5.0)
+            .collect();
+        let mut window = Window::new(Shape::Rolling(50));
+        for &value in &values {
+            window.score(value, 3.0);
+        }
+        let last = summarize(values[950..].iter().copied());
+        let (mean, std_dev) = window.mean_and_std_dev();
+        assert!((mean - last.mean()).abs() < 1e-6);
+        assert!((std_dev - last.std_dev()).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,136 @@
+//! Sensor tags used by notification routing rules.
+
+use axum::{
+    Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::audit::{self, Change};
+use crate::redaction;
+use crate::storage::Storage;
+
+const MAX_TAGS: usize = 100;
+const MAX_TAG_LENGTH: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct SensorTags {
+    tags: Vec<String>,
+}
+
+fn storage(state: &AppState) -> Result<&Storage, (StatusCode, String)> {
+    state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))
+}
+
+pub async fn get_tags(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+) -> Result<Json<SensorTags>, (StatusCode, String)> {
+    let mut tags = storage(&state)?
+        .sensor_tags(&[sensor_id])
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(SensorTags {
+        tags: tags.remove(&sensor_id).unwrap_or_default(),
+    }))
+}
+
+/// Trims, sorts and deduplicates tags, rejecting empty or oversized ones.
+pub fn normalize(tags: &mut Vec<String>) -> Result<(), String> {
+    if tags.len() > MAX_TAGS {
+        return Err(format!("at most {} tags are allowed", MAX_TAGS));
+    }
+    for tag in tags.iter_mut() {
+        *tag = tag.trim().to_string();
+        if tag.is_empty() || tag.len() > MAX_TAG_LENGTH {
+            return Err(format!("tags must be 1 to {} characters", MAX_TAG_LENGTH));
+        }
+    }
+    tags.sort();
+    tags.dedup();
+    Ok(())
+}
+
+/// Replaces a sensor's tags.
+pub async fn put_tags(
+    State(state): State<AppState>,
+    Path(sensor_id): Path<i64>,
+    headers: HeaderMap,
+    Json(mut payload): Json<SensorTags>,
+) -> Result<Json<SensorTags>, (StatusCode, String)> {
+    let storage = storage(&state)?;
+    normalize(&mut payload.tags).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    if let Some(redaction) = redaction::current() {
+        redaction.tags(&mut payload.tags);
+    }
+
+    let previous = SensorTags {
+        tags: storage
+            .sensor_tags(&[sensor_id])
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+            .remove(&sensor_id)
+            .unwrap_or_default(),
+    };
+    storage
+        .set_sensor_tags(sensor_id, &payload.tags)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let change = Change::new(
+        "sensor_tags",
+        Some(sensor_id.to_string()),
+        Some(&previous),
+        Some(&payload),
+    );
+    audit::record(storage, &audit::actor(&headers), [change]).await;
+    Ok(Json(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::testing::in_memory;
+
+    #[tokio::test]
+    async fn test_put_then_get_tags() {
+        let state = AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        };
+        let tags = SensorTags {
+            tags: vec![
+                " line:a ".to_string(),
+                "boiler".to_string(),
+                "line:a".to_string(),
+            ],
+        };
+
+        let Json(stored) = put_tags(State(state.clone()), Path(3), HeaderMap::new(), Json(tags))
+            .await
+            .unwrap();
+        let Json(fetched) = get_tags(State(state), Path(3)).await.unwrap();
+
+        assert_eq!(stored.tags, vec!["boiler", "line:a"]);
+        assert_eq!(fetched.tags, stored.tags);
+    }
+
+    #[tokio::test]
+    async fn test_put_rejects_empty_tag() {
+        let state = AppState {
+            storage: Some(in_memory().await),
+            ..AppState::default()
+        };
+        let tags = SensorTags {
+            tags: vec!["  ".to_string()],
+        };
+
+        let result = put_tags(State(state), Path(3), HeaderMap::new(), Json(tags)).await;
+
+        assert_eq!(result.unwrap_err().0, StatusCode::BAD_REQUEST);
+    }
+}
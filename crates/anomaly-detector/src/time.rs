@@ -0,0 +1,57 @@
+//! `YYYY-MM-DDTHH:MM:SS` timestamps, in UTC, as the service stores them,
+//! to and from seconds since the Unix epoch.
+
+use detection_core::calendar::{civil_from_days, days_from_civil, days_in_month};
+
+/// Seconds since the Unix epoch of a `YYYY-MM-DDTHH:MM:SS` timestamp.
+pub fn parse_timestamp(raw: &str) -> Option<i64> {
+    let (date, time) = raw.split_once(['T', ' '])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..60).contains(&second)
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SS`.
+pub fn format_timestamp(seconds: i64) -> String {
+    let (days, secs) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_round_trip() {
+        for raw in [
+            "1970-01-01T00:00:00",
+            "2024-02-29T23:59:59",
+            "2026-01-01T00:00:00",
+        ] {
+            assert_eq!(format_timestamp(parse_timestamp(raw).unwrap()), raw);
+        }
+        assert_eq!(parse_timestamp("2026-01-01T00:00:00"), Some(1_767_225_600));
+        assert_eq!(parse_timestamp("2026-13-01T00:00:00"), None);
+        assert_eq!(parse_timestamp("2026-02-29T00:00:00"), None);
+    }
+}
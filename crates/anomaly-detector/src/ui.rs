@@ -0,0 +1,74 @@
+//! Minimal triage dashboard served at `/ui`.
+//!
+//! The assets in `ui/` are compiled into the binary, so the dashboard needs
+//! no separate deployment; it reads everything from the JSON API.
+
+use axum::{
+    Router,
+    http::header,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+
+use crate::AppState;
+
+const INDEX_HTML: &str = include_str!("../ui/index.html");
+const APP_JS: &str = include_str!("../ui/app.js");
+const STYLE_CSS: &str = include_str!("../ui/style.css");
+
+fn asset(content_type: &'static str, body: &'static str) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn index() -> Response {
+    asset("text/html; charset=utf-8", INDEX_HTML)
+}
+
+async fn app_js() -> Response {
+    asset("text/javascript; charset=utf-8", APP_JS)
+}
+
+async fn style_css() -> Response {
+    asset("text/css; charset=utf-8", STYLE_CSS)
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/ui", get(|| async { Redirect::permanent("/ui/") }))
+        .route("/ui/", get(index))
+        .route("/ui/app.js", get(app_js))
+        .route("/ui/style.css", get(style_css))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_serves_embedded_assets() {
+        let index = index().await;
+        assert_eq!(
+            index.headers()[header::CONTENT_TYPE],
+            "text/html; charset=utf-8"
+        );
+
+        for (path, response) in [
+            ("/ui/app.js", app_js().await),
+            ("/ui/style.css", style_css().await),
+        ] {
+            assert!(
+                INDEX_HTML.contains(path),
+                "index.html does not load {}",
+                path
+            );
+            assert_eq!(response.headers()[header::CACHE_CONTROL], "no-cache");
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Per-tenant usage accounting and quotas, for billing.
+//!
+//! Every `/analyze`, `/analyze/batch` and `/stream/ingest` request counts its
+//! readings and analyses (one per series) against the caller's tenant for the
+//! UTC day: the authenticated caller's (see `access`), else the `X-Tenant`
+//! header, else `default`. Backfills and replays re-score readings already
+//! counted and are not. Usage is kept in the database, so it needs storage
+//! and is shared by every replica.
+//!
+//! Daily quotas are set in the `[service.quotas]` table, for every tenant
+//! and per tenant; a request that would take a tenant past one is refused
+//! with 429 and counted as `rejected`.
+//!
+//! ```toml
+//! [service.quotas]
+//! readings_per_day = 10_000_000
+//! analyses_per_day = 100_000
+//!
+//! [service.quotas.acme]
+//! readings_per_day = 50_000_000
+//! ```
+//!
+//! or `ANOMALY_QUOTAS_READINGS_PER_DAY` and
+//! `ANOMALY_QUOTAS_ACME__READINGS_PER_DAY`.
+//!
+//! `GET /usage?tenant=&since=&until=` reports each tenant's usage per day
+//! from `since` to `until` (default the last 30 days) with its quota. With
+//! access control on, only admins see other tenants than their own.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+use crate::access::{Identity, Role};
+
+/// Header naming the tenant a request is counted against.
+pub const TENANT_HEADER: &str = "x-tenant";
+
+pub const DEFAULT_TENANT: &str = "default";
+
+const READINGS_PER_DAY: &str = "readings_per_day";
+const ANALYSES_PER_DAY: &str = "analyses_per_day";
+
+/// Days reported without a `since`.
+const DEFAULT_DAYS: u64 = 30;
+
+/// The tenant a request is counted against, from the `X-Tenant` header.
+pub fn tenant(headers: &HeaderMap) -> String {
+    headers
+        .get(TENANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|tenant| !tenant.is_empty())
+        .unwrap_or(DEFAULT_TENANT)
+        .to_string()
+}
+
+/// Daily limits; `None` is unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Quota {
+    pub readings_per_day: Option<u64>,
+    pub analyses_per_day: Option<u64>,
+}
+
+impl Quota {
+    fn set(&mut self, field: &str, limit: &Limit) -> Result<(), String> {
+        let limit = limit.value().map_err(|e| format!("{}: {}", field, e))?;
+        match field {
+            READINGS_PER_DAY => self.readings_per_day = Some(limit),
+            ANALYSES_PER_DAY => self.analyses_per_day = Some(limit),
+            _ => {
+                return Err(format!(
+                    "{}: unknown quota; quotas are {} and {}",
+                    field, READINGS_PER_DAY, ANALYSES_PER_DAY
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An entry of the `[service.quotas]` table: a limit for every tenant, or
+/// a tenant's own limits.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum QuotaSetting {
+    Limit(Limit),
+    Tenant(BTreeMap<String, Limit>),
+}
+
+/// A limit written in the file, or the text of an environment variable,
+/// which an untagged enum does not convert.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Limit {
+    Number(u64),
+    Text(String),
+}
+
+impl Limit {
+    pub fn value(&self) -> Result<u64, String> {
+        match self {
+            Limit::Number(value) => Ok(*value),
+            Limit::Text(text) => text
+                .trim()
+                .parse()
+                .map_err(|_| format!("expected a whole number, got {:?}", text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quotas {
+    default: Quota,
+    tenants: BTreeMap<String, Quota>,
+}
+
+impl Quotas {
+    /// Reads the `[service.quotas]` table.
+    pub fn from_table(table: &BTreeMap<String, QuotaSetting>) -> Result<Self, String> {
+        let mut quotas = Quotas::default();
+        for (key, setting) in table {
+            match setting {
+                QuotaSetting::Limit(limit) => quotas
+                    .default
+                    .set(key, limit)
+                    .map_err(|e| format!("quotas.{}", e))?,
+                QuotaSetting::Tenant(limits) => {
+                    let quota = quotas.tenants.entry(key.clone()).or_default();
+                    for (field, limit) in limits {
+                        quota
+                            .set(field, limit)
+                            .map_err(|e| format!("quotas.{}.{}", key, e))?;
+                    }
+                }
+            }
+        }
+        Ok(quotas)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.default != Quota::default() || !self.tenants.is_empty()
+    }
+
+    /// `tenant`'s quota: its own limits, else those for every tenant.
+    pub fn of(&self, tenant: &str) -> Quota {
+        let own = self.tenants.get(tenant).copied().unwrap_or_default();
+        Quota {
+            readings_per_day: own.readings_per_day.or(self.default.readings_per_day),
+            analyses_per_day: own.analyses_per_day.or(self.default.analyses_per_day),
+        }
+    }
+}
+
+/// Counts `readings` and `analyses` against the tenant of `headers`, or
+/// refuses them with 429 when that would exceed its quota.
+///
+/// Accounting that fails is logged and the request let through, so a busy
+/// database does not stop detection.
+pub async fn charge(
+    state: &AppState,
+    headers: &HeaderMap,
+    readings: usize,
+    analyses: usize,
+) -> Result<(), (StatusCode, String)> {
+    let Some(storage) = &state.storage else {
+        return Ok(());
+    };
+    let tenant = tenant(headers);
+    let quota = state.quotas.of(&tenant);
+    let limit = |limit: Option<u64>| limit.map(|limit| limit.min(i64::MAX as u64) as i64);
+    let charged = storage
+        .charge_usage(
+            &tenant,
+            readings as i64,
+            analyses as i64,
+            limit(quota.readings_per_day),
+            limit(quota.analyses_per_day),
+        )
+        .await;
+    match charged {
+        Ok(true) => Ok(()),
+        Ok(false) => Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "tenant {} would exceed its daily quota of {} readings and {} analyses; \
+                 usage resets at 00:00 UTC",
+                tenant,
+                describe(quota.readings_per_day),
+                describe(quota.analyses_per_day)
+            ),
+        )),
+        Err(e) => {
+            eprintln!("Error: Failed to count usage of tenant {}: {}", tenant, e);
+            Ok(())
+        }
+    }
+}
+
+fn describe(limit: Option<u64>) -> String {
+    limit.map_or_else(|| "unlimited".to_string(), |limit| limit.to_string())
+}
+
+#[derive(Default, Deserialize)]
+pub struct UsageQuery {
+    tenant: Option<String>,
+    /// First day reported, default 30 days ago.
+    since: Option<String>,
+    /// Last day reported, default today.
+    until: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub since: String,
+    pub until: String,
+    pub tenants: Vec<TenantReport>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TenantReport {
+    pub tenant: String,
+    pub quota: Quota,
+    pub readings: i64,
+    pub analyses: i64,
+    pub rejected: i64,
+    pub days: Vec<DayUsage>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct DayUsage {
+    pub day: String,
+    pub readings: i64,
+    pub analyses: i64,
+    pub rejected: i64,
+}
+
+/// Usage per tenant and day.
+pub async fn report(
+    State(state): State<AppState>,
+    identity: Option<Extension<Identity>>,
+    Query(query): Query<UsageQuery>,
+) -> Result<Json<UsageReport>, (StatusCode, String)> {
+    let storage = state.storage.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "storage is not configured; set ANOMALY_DATABASE_URL".to_string(),
+    ))?;
+    let internal = |e: sqlx::Error| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string());
+
+    let tenant = match identity {
+        Some(Extension(identity)) if identity.role < Role::Admin => {
+            if query.tenant.as_ref().is_some_and(|t| *t != identity.tenant) {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    "only admins see the usage of other tenants".to_string(),
+                ));
+            }
+            Some(identity.tenant)
+        }
+        _ => query.tenant,
+    };
+    let day = |raw: Option<String>, default: Duration| async move {
+        let timestamp = match raw {
+            Some(raw) => storage.normalize_timestamp(&raw).await.map_err(internal)?,
+            None => Some(storage.cutoff(default).await.map_err(internal)?),
+        };
+        timestamp
+            .map(|timestamp| timestamp.chars().take(10).collect::<String>())
+            .ok_or((
+                StatusCode::BAD_REQUEST,
+                "since and until must be valid dates".to_string(),
+            ))
+    };
+    let since = day(
+        query.since,
+        Duration::from_secs(DEFAULT_DAYS * 24 * 60 * 60),
+    )
+    .await?;
+    let until = day(query.until, Duration::ZERO).await?;
+
+    let rows = storage
+        .tenant_usage(tenant.as_deref(), &since, &until)
+        .await
+        .map_err(internal)?;
+    let mut tenants: Vec<TenantReport> = Vec::new();
+    for row in rows {
+        if tenants.last().is_none_or(|last| last.tenant != row.tenant) {
+            tenants.push(TenantReport {
+                quota: state.quotas.of(&row.tenant),
+                tenant: row.tenant.clone(),
+                readings: 0,
+                analyses: 0,
+                rejected: 0,
+                days: Vec::new(),
+            });
+        }
+        let report = tenants.last_mut().expect("a tenant was just pushed");
+        report.readings += row.readings;
+        report.analyses += row.analyses;
+        report.rejected += row.rejected;
+        report.days.push(DayUsage {
+            day: row.day,
+            readings: row.readings,
+            analyses: row.analyses,
+            rejected: row.rejected,
+        });
+    }
+    Ok(Json(UsageReport {
+        since,
+        until,
+        tenants,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::access::Credential;
+    use crate::storage::testing::in_memory;
+
+    fn headers(tenant: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(TENANT_HEADER, tenant.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_quotas() {
+        let acme = BTreeMap::from([
+            (READINGS_PER_DAY.to_string(), Limit::Number(1_000)),
+            (ANALYSES_PER_DAY.to_string(), Limit::Text("10".to_string())),
+        ]);
+        let table = BTreeMap::from([
+            (
+                READINGS_PER_DAY.to_string(),
+                QuotaSetting::Limit(Limit::Number(100)),
+            ),
+            ("acme".to_string(), QuotaSetting::Tenant(acme)),
+        ]);
+        let quotas = Quotas::from_table(&table).unwrap();
+        assert_eq!(
+            quotas.of("acme"),
+            Quota {
+                readings_per_day: Some(1_000),
+                analyses_per_day: Some(10),
+            }
+        );
+        assert_eq!(quotas.of("other").readings_per_day, Some(100));
+        assert_eq!(quotas.of("other").analyses_per_day, None);
+
+        let bytes = BTreeMap::from([("bytes_per_day".to_string(), Limit::Number(1))]);
+        let unknown = BTreeMap::from([("acme".to_string(), QuotaSetting::Tenant(bytes))]);
+        assert!(Quotas::from_table(&unknown).is_err_and(|e| e.contains("acme.bytes_per_day")));
+    }
+
+    #[tokio::test]
+    async fn test_charges_usage_up_to_the_quota() {
+        let storage = in_memory().await;
+        let acme = BTreeMap::from([(READINGS_PER_DAY.to_string(), Limit::Number(10))]);
+        let table = BTreeMap::from([("acme".to_string(), QuotaSetting::Tenant(acme))]);
+        let state = AppState {
+            storage: Some(storage),
+            quotas: Arc::new(Quotas::from_table(&table).unwrap()),
+            ..AppState::default()
+        };
+        let acme = headers("acme");
+        charge(&state, &acme, 6, 1).await.unwrap();
+        let refused = charge(&state, &acme, 5, 1).await.unwrap_err();
+        assert_eq!(refused.0, StatusCode::TOO_MANY_REQUESTS);
+        charge(&state, &acme, 4, 1).await.unwrap();
+        // Other tenants have no quota.
+        charge(&state, &HeaderMap::new(), 50, 2).await.unwrap();
+
+        let Json(all) = report(State(state.clone()), None, Query(UsageQuery::default()))
+            .await
+            .unwrap();
+        let totals: Vec<(&str, i64, i64, i64)> = all
+            .tenants
+            .iter()
+            .map(|t| (t.tenant.as_str(), t.readings, t.analyses, t.rejected))
+            .collect();
+        assert_eq!(totals, [("acme", 10, 2, 1), ("default", 50, 2, 0)]);
+        assert_eq!(all.tenants[0].days.len(), 1);
+
+        // A tenant's own key sees only its usage.
+        let caller = Identity {
+            name: "acme.grafana".to_string(),
+            role: Role::ReadOnly,
+            tenant: "acme".to_string(),
+            credential: Credential::Key,
+        };
+        let Json(own) = report(
+            State(state.clone()),
+            Some(Extension(caller.clone())),
+            Query(UsageQuery::default()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(own.tenants.len(), 1);
+        let other = report(
+            State(state),
+            Some(Extension(caller)),
+            Query(UsageQuery {
+                tenant: Some("default".to_string()),
+                ..UsageQuery::default()
+            }),
+        )
+        .await;
+        assert_eq!(other.unwrap_err().0, StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,226 @@
+//! Validation of the readings and parameters of `/analyze` requests and
+//! `/analyze/batch` series, of the readings streamed to the service, and
+//! the [`ApiError`] they, and bodies
+//! [`crate::json::JsonBody`] cannot read, are refused with.
+//!
+//! Refusals answer with a JSON body of the `error` and, when one field is
+//! to blame, its path in the request body as `field`, e.g.
+//! `{"error": "readings[3].value must be a finite number, got NaN",
+//! "field": "readings[3].value"}`: 400 for malformed JSON and 422 for JSON
+//! of the wrong shape or values no detector can score. A batch reports a
+//! series' error in its own result instead.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+use crate::{AnalyzeRequest, Reading};
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        ApiError {
+            status,
+            error: error.into(),
+            field: None,
+        }
+    }
+
+    /// A 422 for the value of `field`, `problem` completing the sentence
+    /// that starts with its path.
+    pub fn invalid(field: impl Into<String>, problem: impl fmt::Display) -> Self {
+        let field = field.into();
+        ApiError {
+            status: StatusCode::UNPROCESSABLE_ENTITY,
+            error: format!("{} {}", field, problem),
+            field: Some(field),
+        }
+    }
+}
+
+impl ApiError {
+    /// The error of a request nested at `path` of another, e.g. of a
+    /// batch's `series[2]`, its field, if it names one, under `path`.
+    pub fn within(self, path: &str) -> Self {
+        match self.field {
+            Some(field) => ApiError {
+                status: self.status,
+                error: format!("{}.{}", path, self.error),
+                field: Some(format!("{}.{}", path, field)),
+            },
+            None => self,
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.error)
+    }
+}
+
+impl From<(StatusCode, String)> for ApiError {
+    fn from((status, error): (StatusCode, String)) -> Self {
+        ApiError::new(status, error)
+    }
+}
+
+impl From<ApiError> for (StatusCode, String) {
+    fn from(error: ApiError) -> Self {
+        (error.status, error.error)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+impl AnalyzeRequest {
+    /// Refuses a request without readings, with a reading whose value is
+    /// not a finite number or whose id repeats an earlier reading's of the
+    /// same sensor, or with a threshold that is not a positive number.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.readings.is_empty() {
+            return Err(ApiError::invalid(
+                "readings",
+                "must contain at least one reading",
+            ));
+        }
+        validate_readings(&self.readings, self.sensor_id)?;
+        validate_threshold(self.threshold)
+    }
+}
+
+/// Refuses a threshold that is not a positive number.
+pub fn validate_threshold(threshold: Option<f64>) -> Result<(), ApiError> {
+    match threshold {
+        Some(threshold) if !(threshold.is_finite() && threshold > 0.0) => Err(ApiError::invalid(
+            "threshold",
+            format_args!("must be a positive number, got {}", threshold),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Refuses `readings` if one's value is not a finite number or its id
+/// repeats an earlier reading's of the same sensor, `sensor_id` being the
+/// sensor of those naming none.
+pub fn validate_readings(readings: &[Reading], sensor_id: Option<i64>) -> Result<(), ApiError> {
+    let mut positions: HashMap<(Option<i64>, i64), usize> = HashMap::with_capacity(readings.len());
+    for (position, reading) in readings.iter().enumerate() {
+        if !reading.value.is_finite() {
+            return Err(ApiError::invalid(
+                format!("readings[{}].value", position),
+                format_args!("must be a finite number, got {}", reading.value),
+            ));
+        }
+        let sensor_id = reading.sensor_id.or(sensor_id);
+        if let Some(first) = positions.insert((sensor_id, reading.id), position) {
+            return Err(ApiError::invalid(
+                format!("readings[{}].id", position),
+                format_args!("{} repeats the id of readings[{}]", reading.id, first),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings(values: &[f64]) -> AnalyzeRequest {
+        let mut request: AnalyzeRequest =
+            serde_json::from_value(serde_json::json!({ "readings": [] })).unwrap();
+        request.readings = (1..)
+            .zip(values)
+            .map(|(id, &value)| Reading {
+                id,
+                value,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+                sensor_id: None,
+            })
+            .collect();
+        request
+    }
+
+    #[test]
+    fn test_validate_names_the_failing_field() {
+        assert_eq!(readings(&[1.0, 2.0]).validate(), Ok(()));
+
+        let failed = |request: AnalyzeRequest| {
+            let error = request.validate().unwrap_err();
+            assert_eq!(error.status, StatusCode::UNPROCESSABLE_ENTITY);
+            (error.field.unwrap(), error.error)
+        };
+        assert_eq!(
+            failed(readings(&[])),
+            (
+                "readings".to_string(),
+                "readings must contain at least one reading".to_string()
+            )
+        );
+        assert_eq!(
+            failed(readings(&[1.0, f64::NAN])),
+            (
+                "readings[1].value".to_string(),
+                "readings[1].value must be a finite number, got NaN".to_string()
+            )
+        );
+        assert_eq!(failed(readings(&[f64::INFINITY])).0, "readings[0].value");
+
+        let mut repeated = readings(&[1.0, 2.0, 3.0]);
+        repeated.readings[2].id = 1;
+        assert_eq!(
+            failed(repeated),
+            (
+                "readings[2].id".to_string(),
+                "readings[2].id 1 repeats the id of readings[0]".to_string()
+            )
+        );
+        // Readings of different sensors may share ids.
+        let mut sensors = readings(&[1.0, 2.0]);
+        sensors.readings[1].id = 1;
+        sensors.readings[1].sensor_id = Some(8);
+        assert_eq!(sensors.validate(), Ok(()));
+
+        for threshold in [-1.0, 0.0, f64::NAN] {
+            let mut request = readings(&[1.0]);
+            request.threshold = Some(threshold);
+            assert_eq!(failed(request).0, "threshold");
+        }
+    }
+
+    #[test]
+    fn test_error_body_is_json() {
+        let error = ApiError::invalid("threshold", "must be a positive number, got -1");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({
+                "error": "threshold must be a positive number, got -1",
+                "field": "threshold",
+            })
+        );
+        let error = ApiError::new(StatusCode::BAD_REQUEST, "unknown method");
+        assert_eq!(
+            serde_json::to_value(&error).unwrap(),
+            serde_json::json!({ "error": "unknown method" })
+        );
+    }
+}
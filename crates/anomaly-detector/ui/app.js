@@ -0,0 +1,124 @@
+// Dashboard for on-call triage, built on the service's JSON API:
+// /baselines for the sensor table, /sensors/{id}/series for sparklines and
+// /anomalies for the recent anomaly list.
+
+const REFRESH_MS = 30000;
+const MAX_ANOMALIES = 100;
+const SPARKLINE = { width: 160, height: 32 };
+
+const hours = document.getElementById("hours");
+const status = document.getElementById("status");
+
+function escape(text) {
+  const div = document.createElement("div");
+  div.textContent = String(text);
+  return div.innerHTML;
+}
+
+function number(value, digits = 2) {
+  return value == null ? "–" : Number(value).toFixed(digits);
+}
+
+// With access control on, the API key or JWT is asked for once and kept
+// for the browser session.
+function credentials() {
+  const token = sessionStorage.getItem("token");
+  return token ? { Authorization: `Bearer ${token}` } : {};
+}
+
+async function fetchJson(path) {
+  let response = await fetch(path, { headers: credentials() });
+  if (response.status === 401) {
+    const token = window.prompt("API key or token");
+    if (token) {
+      sessionStorage.setItem("token", token.trim());
+      response = await fetch(path, { headers: credentials() });
+    }
+  }
+  if (!response.ok) {
+    throw new Error(`${path}: ${response.status} ${await response.text()}`);
+  }
+  return response.json();
+}
+
+function sparkline(points) {
+  const { width, height } = SPARKLINE;
+  if (points.length < 2) {
+    return "";
+  }
+  const means = points.map((p) => p.mean);
+  const min = Math.min(...means);
+  const span = Math.max(...means) - min || 1;
+  const coords = means
+    .map((mean, i) => {
+      const x = (i / (means.length - 1)) * width;
+      const y = height - 1 - ((mean - min) / span) * (height - 2);
+      return `${x.toFixed(1)},${y.toFixed(1)}`;
+    })
+    .join(" ");
+  return `<svg class="sparkline" width="${width}" height="${height}"><polyline points="${coords}"/></svg>`;
+}
+
+async function renderSensors(since, end) {
+  const { baselines } = await fetchJson(`/baselines?hours=${hours.value}`);
+  const series = await Promise.all(
+    baselines.map((b) =>
+      fetchJson(
+        `/sensors/${b.sensor_id}/series?start=${encodeURIComponent(since)}&end=${encodeURIComponent(end)}`,
+      )
+        .then((s) => s.points)
+        .catch(() => []),
+    ),
+  );
+  document.getElementById("sensors").innerHTML = baselines
+    .map(
+      (b, i) => `<tr>
+        <td>${escape(b.sensor_id)}</td>
+        <td>${sparkline(series[i])}</td>
+        <td class="number" title="${escape(b.last_timestamp)}">${number(b.last_value)}</td>
+        <td class="number">${number(b.mean)}</td>
+        <td class="number">${number(b.std_dev)}</td>
+        <td class="number">${number(b.last_score)}</td>
+        <td class="number">${escape(b.count)}</td>
+      </tr>`,
+    )
+    .join("");
+}
+
+async function renderAnomalies(since) {
+  const { anomalies } = await fetchJson(
+    `/anomalies?start=${encodeURIComponent(since)}&limit=10000`,
+  );
+  document.getElementById("anomalies").innerHTML = anomalies
+    .slice(-MAX_ANOMALIES)
+    .reverse()
+    .map(
+      (a) => `<tr>
+        <td>${escape(a.timestamp)}</td>
+        <td>${escape(a.sensor_id)}</td>
+        <td><span class="severity ${escape(a.severity)}">${escape(a.severity)}</span></td>
+        <td class="number">${number(a.value)}</td>
+        <td>${escape(a.method)}</td>
+        <td class="number">${number(a.score)}</td>
+      </tr>`,
+    )
+    .join("");
+}
+
+async function refresh() {
+  const end = new Date();
+  const since = new Date(end.getTime() - hours.value * 3600 * 1000);
+  try {
+    await Promise.all([
+      renderSensors(since.toISOString(), end.toISOString()),
+      renderAnomalies(since.toISOString()),
+    ]);
+    status.textContent = `Updated ${end.toLocaleTimeString()}`;
+  } catch (e) {
+    status.textContent = `Error: ${e.message}`;
+  }
+}
+
+hours.addEventListener("change", refresh);
+refresh();
+setInterval(refresh, REFRESH_MS);
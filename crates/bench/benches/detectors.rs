@@ -0,0 +1,47 @@
+//! Each detector over batches of 100 to 100 000 readings.
+
+use std::hint::black_box;
+
+use bench::data::series;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use detection_core::stats::summarize;
+use detection_core::{
+    SeverityBands, check_thresholds, ewma_outliers, iqr_outliers, mad_outliers, rolling_outliers,
+    zscore_outliers,
+};
+
+const SIZES: [usize; 4] = [100, 1_000, 10_000, 100_000];
+
+fn detectors(c: &mut Criterion) {
+    let bands = SeverityBands::default();
+    let mut group = c.benchmark_group("detectors");
+    for size in SIZES {
+        let readings = series(size, 42);
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::new("thresholds", size), &readings, |b, r| {
+            b.iter(|| check_thresholds(r.iter().copied(), Some(40.0), Some(60.0)))
+        });
+        group.bench_with_input(BenchmarkId::new("summarize", size), &readings, |b, r| {
+            b.iter(|| summarize(r.iter().map(|r| r.1)))
+        });
+        group.bench_with_input(BenchmarkId::new("zscore", size), &readings, |b, r| {
+            b.iter(|| zscore_outliers(black_box(r), 3.0, &bands))
+        });
+        group.bench_with_input(BenchmarkId::new("mad", size), &readings, |b, r| {
+            b.iter(|| mad_outliers(black_box(r), 3.5, &bands))
+        });
+        group.bench_with_input(BenchmarkId::new("iqr", size), &readings, |b, r| {
+            b.iter(|| iqr_outliers(black_box(r), 1.5, &bands))
+        });
+        group.bench_with_input(BenchmarkId::new("ewma", size), &readings, |b, r| {
+            b.iter(|| ewma_outliers(black_box(r), 0.1, 3.0, &bands))
+        });
+        group.bench_with_input(BenchmarkId::new("rolling", size), &readings, |b, r| {
+            b.iter(|| rolling_outliers(black_box(r), 60, 3.0, &bands))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, detectors);
+criterion_main!(benches);
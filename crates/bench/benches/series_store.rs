@@ -0,0 +1,77 @@
+//! Ingestion into and one-sensor time-range scans of series-store, with and
+//! without compression.
+
+use std::hint::black_box;
+use std::path::PathBuf;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use series_store::{Compression, Options, Reading, Store};
+
+/// Readings per append, as an ingestion loop batches them.
+const BATCH: usize = 10_000;
+const SENSORS: i64 = 100;
+const READINGS: usize = 2_000_000;
+
+/// Round-robin over the sensors, one reading every millisecond per sensor.
+fn batch(offset: usize) -> Vec<Reading> {
+    (offset..offset + BATCH)
+        .map(|i| Reading {
+            sensor_id: i as i64 % SENSORS,
+            timestamp: (i as i64 / SENSORS) * 1_000,
+            value: 50.0 + (i % 17) as f64 * 0.25,
+        })
+        .collect()
+}
+
+fn dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("series-store-bench-{}", name));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+fn series_store(c: &mut Criterion) {
+    for compression in [Compression::None, Compression::Lz4] {
+        let name = format!("{:?}", compression).to_lowercase();
+        let options = Options {
+            compression,
+            ..Options::default()
+        };
+
+        let mut group = c.benchmark_group("series_store_append");
+        group.throughput(Throughput::Elements(BATCH as u64));
+        let ingest = dir(&format!("append-{}", name));
+        let mut store = Store::open(&ingest, options.clone()).unwrap();
+        let mut offset = 0;
+        group.bench_function(BenchmarkId::from_parameter(&name), |b| {
+            b.iter(|| {
+                store.append(&batch(offset)).unwrap();
+                offset += BATCH;
+            })
+        });
+        group.finish();
+        store.flush().unwrap();
+        drop(store);
+        std::fs::remove_dir_all(ingest).unwrap();
+
+        let scanned = dir(&format!("scan-{}", name));
+        let mut store = Store::open(&scanned, options).unwrap();
+        for offset in (0..READINGS).step_by(BATCH) {
+            store.append(&batch(offset)).unwrap();
+        }
+        store.seal().unwrap();
+        // A tenth of the series of one sensor, from the middle.
+        let span = (READINGS as i64 / SENSORS) * 1_000;
+        let (start, end) = (span / 2, span / 2 + span / 10);
+        let mut group = c.benchmark_group("series_store_scan");
+        group.throughput(Throughput::Elements(READINGS as u64 / SENSORS as u64 / 10));
+        group.bench_function(BenchmarkId::from_parameter(&name), |b| {
+            b.iter(|| store.scan(black_box(42), start, end).unwrap())
+        });
+        group.finish();
+        drop(store);
+        std::fs::remove_dir_all(scanned).unwrap();
+    }
+}
+
+criterion_group!(benches, series_store);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+//! Reproducible sensor-like series: a daily cycle with Gaussian noise and a
+//! spike every [`SPIKE_EVERY`] readings, so detectors have outliers to find.
+
+use std::f64::consts::TAU;
+
+use detection_core::stats::box_muller;
+
+/// Readings between injected spikes.
+pub const SPIKE_EVERY: usize = 500;
+
+/// Readings per simulated day, at one per minute.
+const DAY: f64 = 1_440.0;
+
+/// xorshift64*: fast, and the same on every platform for a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+
+    /// Uniform in (0, 1].
+    fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        let bits = self.0.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11;
+        (bits + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal.
+    fn normal(&mut self) -> f64 {
+        let u = self.uniform();
+        box_muller(u, self.uniform())
+    }
+}
+
+/// `len` `(reading_id, value)` readings with ids from 1; the same `seed`
+/// gives the same series.
+pub fn series(len: usize, seed: u64) -> Vec<(i64, f64)> {
+    let mut rng = Rng::new(seed);
+    (0..len)
+        .map(|i| {
+            let cycle = 5.0 * (TAU * i as f64 / DAY).sin();
+            let mut value = 50.0 + cycle + rng.normal();
+            if i % SPIKE_EVERY == SPIKE_EVERY - 1 {
+                value += 25.0;
+            }
+            (i as i64 + 1, value)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detection_core::stats::summarize;
+
+    #[test]
+    fn test_series_is_reproducible_with_spikes() {
+        let values = series(2_000, 7);
+        assert_eq!(values, series(2_000, 7));
+        assert_ne!(values, series(2_000, 8));
+
+        let stats = summarize(values.iter().map(|r| r.1));
+        assert!((stats.mean() - 50.0).abs() < 2.0, "mean {}", stats.mean());
+        let spikes = values.iter().filter(|r| r.1 > 65.0).count();
+        assert_eq!(spikes, 2_000 / SPIKE_EVERY);
+    }
+}
@@ -0,0 +1,9 @@
+//! Performance harness: criterion benchmarks for the detectors in
+//! `detection-core` (`cargo bench -p bench`), and `loadgen`, which replays
+//! recorded request payloads against a running service.
+//!
+//! - [`data`]: reproducible reading series shared by the benchmarks
+//! - [`replay`]: payload files, the load generator and its report
+
+pub mod data;
+pub mod replay;
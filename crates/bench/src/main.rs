@@ -0,0 +1,97 @@
+//! `loadgen`: replays a payload file against a running anomaly detector.
+//!
+//! ```text
+//! loadgen crates/bench/payloads/analyze.ndjson --url http://localhost:3001 -c 16 -n 10000
+//! ```
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+use bench::replay::{self, Options};
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(name = "loadgen", version, about)]
+struct Cli {
+    /// Recorded requests, one JSON object per line.
+    payloads: PathBuf,
+    /// Base URL of the service.
+    #[arg(long, default_value = "http://localhost:3001")]
+    url: String,
+    /// Requests in flight at once.
+    #[arg(short, long, default_value_t = 8)]
+    concurrency: usize,
+    /// Requests to send in total.
+    #[arg(short = 'n', long, default_value_t = 1_000)]
+    requests: usize,
+    /// Requests per second over all connections; unlimited when absent.
+    #[arg(long)]
+    rate: Option<f64>,
+    /// Per-request timeout in seconds.
+    #[arg(long, default_value_t = 30)]
+    timeout_secs: u64,
+    /// Exits with an error when the p99 latency exceeds this many
+    /// milliseconds.
+    #[arg(long)]
+    max_p99_ms: Option<f64>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let text = match std::fs::read_to_string(&cli.payloads) {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("Error: Failed to read {}: {}", cli.payloads.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let payloads = match replay::parse(&text) {
+        Ok(payloads) => payloads,
+        Err(e) => {
+            eprintln!(
+                "Error: Invalid payload file {}: {}",
+                cli.payloads.display(),
+                e
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+    let options = Options {
+        concurrency: cli.concurrency,
+        requests: cli.requests,
+        rate: cli.rate,
+        timeout: Duration::from_secs(cli.timeout_secs),
+    };
+    println!(
+        "Replaying {} payloads to {}: {} requests, {} at a time",
+        payloads.len(),
+        cli.url,
+        options.requests,
+        options.concurrency
+    );
+
+    let report = match replay::run(&cli.url, payloads, &options).await {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("Error: Failed to create the HTTP client: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{}", report);
+    if report.failures() > 0 {
+        println!(
+            "Warning: {} requests failed or got an error status",
+            report.failures()
+        );
+    }
+    if let (Some(limit), Some(p99)) = (cli.max_p99_ms, report.percentile(0.99)) {
+        let p99 = p99.as_secs_f64() * 1e3;
+        if p99 > limit {
+            eprintln!("Error: p99 latency {:.2}ms exceeds {}ms", p99, limit);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
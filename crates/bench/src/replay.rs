@@ -0,0 +1,314 @@
+//! Replays recorded request payloads against the service and reports
+//! throughput and latency.
+//!
+//! A payload file has one request per line, as JSON; `method` defaults to
+//! POST when there is a `body` and GET otherwise:
+//!
+//! ```json
+//! {"path": "/analyze", "body": {"readings": [{"id": 1, "value": 10.0, "timestamp": "2026-01-19T10:00:00"}]}}
+//! {"method": "GET", "path": "/baselines?hours=1"}
+//! ```
+//!
+//! Payloads are sent in file order, starting over at the end, by
+//! `concurrency` workers until `requests` were sent.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Line {
+    #[serde(default)]
+    method: Option<String>,
+    path: String,
+    #[serde(default)]
+    body: Option<Value>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Payload {
+    pub method: Method,
+    /// Path and query, appended to the base URL.
+    pub path: String,
+    pub body: Option<Value>,
+}
+
+/// Parses a payload file, skipping blank lines.
+pub fn parse(text: &str) -> Result<Vec<Payload>, String> {
+    let mut payloads = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |e: String| format!("line {}: {}", index + 1, e);
+        let line: Line = serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?;
+        let method = match &line.method {
+            Some(method) => Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| invalid(format!("invalid method {:?}", method)))?,
+            None if line.body.is_some() => Method::POST,
+            None => Method::GET,
+        };
+        if !line.path.starts_with('/') {
+            return Err(invalid(format!(
+                "path must start with /, got {:?}",
+                line.path
+            )));
+        }
+        payloads.push(Payload {
+            method,
+            path: line.path,
+            body: line.body,
+        });
+    }
+    if payloads.is_empty() {
+        return Err("no payloads".to_string());
+    }
+    Ok(payloads)
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub concurrency: usize,
+    pub requests: usize,
+    /// Requests per second over all workers; as fast as possible when absent.
+    pub rate: Option<f64>,
+    pub timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Responses by status code.
+    pub statuses: BTreeMap<u16, usize>,
+    /// Requests that got no response: connection errors and timeouts.
+    pub errors: usize,
+    /// Of every request, with or without a response.
+    latencies: Vec<Duration>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn requests(&self) -> usize {
+        self.latencies.len()
+    }
+
+    /// Requests that got no response or a status other than 2xx.
+    pub fn failures(&self) -> usize {
+        let failed_statuses: usize = self
+            .statuses
+            .iter()
+            .filter(|(status, _)| !(200..300).contains(*status))
+            .map(|(_, count)| count)
+            .sum();
+        self.errors + failed_statuses
+    }
+
+    /// The latency `p` (0 to 1) of requests were at or below, by the
+    /// nearest-rank method.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let rank = (p * self.latencies.len() as f64).ceil() as usize;
+        Some(self.latencies[rank.clamp(1, self.latencies.len()) - 1])
+    }
+
+    fn merge(&mut self, other: Report) {
+        for (status, count) in other.statuses {
+            *self.statuses.entry(status).or_default() += count;
+        }
+        self.errors += other.errors;
+        self.latencies.extend(other.latencies);
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64();
+        writeln!(
+            f,
+            "requests:  {} in {:.2}s ({:.1}/s)",
+            self.requests(),
+            seconds,
+            self.requests() as f64 / seconds.max(f64::EPSILON)
+        )?;
+        let statuses: Vec<String> = self
+            .statuses
+            .iter()
+            .map(|(status, count)| format!("{} x {}", status, count))
+            .collect();
+        writeln!(f, "statuses:  {}", statuses.join(", "))?;
+        writeln!(f, "errors:    {}", self.errors)?;
+        let millis = |p: f64| {
+            self.percentile(p).map_or("-".to_string(), |d| {
+                format!("{:.2}ms", d.as_secs_f64() * 1e3)
+            })
+        };
+        write!(
+            f,
+            "latency:   p50 {}  p90 {}  p99 {}  max {}",
+            millis(0.5),
+            millis(0.9),
+            millis(0.99),
+            millis(1.0)
+        )
+    }
+}
+
+/// Sends `options.requests` requests cycling through `payloads`.
+pub async fn run(
+    base_url: &str,
+    payloads: Vec<Payload>,
+    options: &Options,
+) -> Result<Report, reqwest::Error> {
+    let client = reqwest::Client::builder()
+        .timeout(options.timeout)
+        .pool_max_idle_per_host(options.concurrency)
+        .build()?;
+    let base_url = Arc::new(base_url.trim_end_matches('/').to_string());
+    let payloads = Arc::new(payloads);
+    let next = Arc::new(AtomicUsize::new(0));
+    let concurrency = options.concurrency.max(1);
+    let period = options
+        .rate
+        .filter(|rate| *rate > 0.0)
+        .map(|rate| Duration::from_secs_f64(concurrency as f64 / rate));
+
+    let started = Instant::now();
+    let mut workers = Vec::new();
+    for _ in 0..concurrency {
+        let (client, base_url, payloads, next) = (
+            client.clone(),
+            base_url.clone(),
+            payloads.clone(),
+            next.clone(),
+        );
+        let requests = options.requests;
+        workers.push(tokio::spawn(async move {
+            let mut report = Report::default();
+            let mut interval = period.map(tokio::time::interval);
+            loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                if index >= requests {
+                    return report;
+                }
+                if let Some(interval) = &mut interval {
+                    interval.tick().await;
+                }
+                let payload = &payloads[index % payloads.len()];
+                let mut request = client.request(
+                    payload.method.clone(),
+                    format!("{}{}", base_url, payload.path),
+                );
+                if let Some(body) = &payload.body {
+                    request = request.json(body);
+                }
+                let sent = Instant::now();
+                let outcome = match request.send().await {
+                    Ok(response) => {
+                        let status = response.status().as_u16();
+                        // The latency includes reading the whole body.
+                        response.bytes().await.map(|_| status)
+                    }
+                    Err(e) => Err(e),
+                };
+                report.latencies.push(sent.elapsed());
+                match outcome {
+                    Ok(status) => *report.statuses.entry(status).or_default() += 1,
+                    Err(_) => report.errors += 1,
+                }
+            }
+        }));
+    }
+
+    let mut report = Report::default();
+    for worker in workers {
+        report.merge(worker.await.expect("load generator worker panicked"));
+    }
+    report.elapsed = started.elapsed();
+    report.latencies.sort();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use axum::http::StatusCode;
+    use axum::routing::{get, post};
+
+    #[test]
+    fn test_parse_payloads() {
+        let payloads = parse(
+            "{\"path\": \"/analyze\", \"body\": {\"readings\": []}}\n\
+             \n\
+             {\"method\": \"get\", \"path\": \"/health\"}\n\
+             {\"path\": \"/baselines?hours=1\"}\n",
+        )
+        .unwrap();
+        let methods: Vec<&Method> = payloads.iter().map(|p| &p.method).collect();
+        assert_eq!(methods, [Method::POST, Method::GET, Method::GET]);
+        assert_eq!(payloads[2].path, "/baselines?hours=1");
+
+        assert_eq!(parse("\n").unwrap_err(), "no payloads");
+        assert!(
+            parse("{\"path\": \"health\"}")
+                .unwrap_err()
+                .starts_with("line 1:")
+        );
+        assert!(parse("{}\n").unwrap_err().starts_with("line 1:"));
+        let sample = include_str!("../payloads/analyze.ndjson");
+        assert!(parse(sample).unwrap().len() >= 3);
+    }
+
+    #[test]
+    fn test_percentiles_use_nearest_rank() {
+        let report = Report {
+            latencies: (1..=10).map(Duration::from_millis).collect(),
+            ..Report::default()
+        };
+        assert_eq!(report.percentile(0.5), Some(Duration::from_millis(5)));
+        assert_eq!(report.percentile(0.99), Some(Duration::from_millis(10)));
+        assert_eq!(report.percentile(0.0), Some(Duration::from_millis(1)));
+        assert_eq!(Report::default().percentile(0.5), None);
+    }
+
+    #[tokio::test]
+    async fn test_run_cycles_through_payloads() {
+        let app = Router::new()
+            .route("/health", get(|| async { "ok" }))
+            .route(
+                "/analyze",
+                post(|| async { StatusCode::SERVICE_UNAVAILABLE }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let payloads = parse(
+            "{\"path\": \"/health\"}\n\
+             {\"path\": \"/analyze\", \"body\": {}}\n\
+             {\"path\": \"/health\"}\n",
+        )
+        .unwrap();
+        let options = Options {
+            concurrency: 4,
+            requests: 30,
+            rate: None,
+            timeout: Duration::from_secs(5),
+        };
+        let report = run(&url, payloads, &options).await.unwrap();
+
+        assert_eq!(report.requests(), 30);
+        assert_eq!(report.statuses[&200], 20);
+        assert_eq!(report.statuses[&503], 10);
+        assert_eq!(report.failures(), 10);
+        assert!(report.to_string().contains("200 x 20, 503 x 10"));
+    }
+}
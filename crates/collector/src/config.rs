@@ -0,0 +1,1080 @@
+//! The `[collector]` section of the settings file, overridden by
+//! `COLLECTOR_*` environment variables and then by the flags; see
+//! `config-core`. Sources are only read from the file.
+//!
+//! ```toml
+//! [collector]
+//! sink = "http"
+//! url = "http://localhost:3001"
+//!
+//! [[collector.sources]]
+//! name = "press-4-oil-temp"
+//! sensor_id = 12
+//! interval_secs = 5
+//! kind = "modbus"
+//! address = "10.0.4.20:502"
+//! register = 3
+//! type = "i16"
+//! scale = 0.1
+//!
+//! [[collector.sources]]
+//! name = "tank-2-level"
+//! sensor_id = 14
+//! kind = "modbus"
+//! serial = "/dev/ttyUSB0"
+//! baud_rate = 19200
+//! parity = "even"
+//! unit = 7
+//! table = "input"
+//! register = 0
+//! type = "f32"
+//!
+//! [[collector.sources]]
+//! name = "line-a-flow"
+//! sensor_id = 13
+//! kind = "http"
+//! url = "http://plc-gateway/api/flow"
+//! pointer = "/value"
+//!
+//! [[collector.sources]]
+//! name = "press-6-cpu"
+//! sensor_id = 26
+//! interval_secs = 30
+//! kind = "prometheus"
+//! url = "http://prometheus:9090"
+//! query = "sum(rate(node_cpu_seconds_total{instance=\"press6:9100\",mode!=\"idle\"}[1m]))"
+//!
+//! [[collector.sources]]
+//! name = "core-switch-uplink-in"
+//! sensor_id = 30
+//! kind = "snmp"
+//! address = "10.0.9.1"
+//! version = "3"
+//! username = "collector"
+//! auth_password = "..."
+//! privacy_password = "..."
+//! oid = "1.3.6.1.2.1.31.1.1.1.6.49"
+//! scale = 8
+//!
+//! [[collector.opcua]]
+//! name = "line-b-plc"
+//! endpoint = "opc.tcp://10.0.5.2:4840"
+//! security_policy = "Basic256Sha256"
+//! security_mode = "signandencrypt"
+//! items = [{ node_id = "ns=2;s=Press6.OilTemp", sensor_id = 21 }]
+//!
+//! [collector.influx]
+//! udp = "0.0.0.0:8089"
+//!
+//! [[collector.influx.rules]]
+//! measurement = "modbus"
+//! field = "oil_temp"
+//! tags = { host = "press-6" }
+//! sensor_id = 22
+//!
+//! [[collector.influx.rules]]
+//! measurement = "sensors"
+//! sensor_id_tag = "sensor"
+//!
+//! [collector.statsd]
+//! udp = "0.0.0.0:8125"
+//! tcp = "0.0.0.0:2003"
+//!
+//! [[collector.statsd.rules]]
+//! metric = "press6.*.cycle_time"
+//! statistic = "median"
+//! sensor_id = 23
+//!
+//! [[collector.remote_write.rules]]
+//! metric = "node_hwmon_temp_celsius"
+//! labels = { instance = "press6:9100", chip = "platform_coretemp_0" }
+//! sensor_id = 25
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use config_core::{Loader, Validate, config_file};
+use detection_core::SeverityBands;
+use serde::{Deserialize, Serialize};
+
+const ENV_PREFIX: &str = "COLLECTOR_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    /// `POST /analyze` on the service at `url`.
+    Http,
+    /// The service's z-score detection, run in this process.
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub sink: SinkKind,
+    /// Base URL of the service, for the http sink.
+    pub url: String,
+    /// Readings of a sensor sent or checked together.
+    pub batch_size: usize,
+    /// Sends a sensor's readings at least this often, even if its batch is
+    /// not full.
+    pub flush_interval_secs: u64,
+    /// Readings waiting for the sink at most; past it, sources wait and
+    /// HTTP writes are refused, see `queue`.
+    pub queue_capacity: usize,
+    /// Write-ahead log of the readings written over HTTP, replayed at
+    /// startup, see `wal`; off when unset.
+    pub wal_path: Option<PathBuf>,
+    /// Z-score threshold of the local sink; the http sink uses the
+    /// service's unless this is set.
+    pub threshold: Option<f64>,
+    pub high: f64,
+    pub critical: f64,
+    /// Readings of each sensor kept as a uniform sample of its history by
+    /// the local sink, which scores batches against it; each batch is
+    /// scored against itself when unset.
+    pub reservoir_size: Option<usize>,
+    /// Polls of a failing source are retried after this, doubling up to
+    /// `max_backoff_secs`.
+    pub initial_backoff_secs: f64,
+    pub max_backoff_secs: f64,
+    /// Address of the health endpoint, e.g. `0.0.0.0:9102`; off when unset.
+    pub listen: Option<String>,
+    pub sources: Vec<SourceConfig>,
+    /// OPC UA servers whose monitored items report readings as they change.
+    pub opcua: Vec<OpcUaServer>,
+    /// InfluxDB line protocol pushed by Telegraf and the like.
+    pub influx: Option<InfluxSettings>,
+    /// StatsD and Graphite plaintext metrics, aggregated into readings.
+    pub statsd: Option<StatsdSettings>,
+    /// Series streamed by Prometheus remote_write.
+    pub remote_write: Option<RemoteWriteSettings>,
+    /// YAML file of pipelines for groups of sensors, see `pipeline`;
+    /// reloaded on SIGHUP.
+    pub pipelines: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let bands = SeverityBands::default();
+        Self {
+            sink: SinkKind::Http,
+            url: "http://localhost:3001".to_string(),
+            batch_size: 60,
+            flush_interval_secs: 60,
+            queue_capacity: 10_000,
+            wal_path: None,
+            threshold: None,
+            high: bands.high,
+            critical: bands.critical,
+            reservoir_size: None,
+            initial_backoff_secs: 1.0,
+            max_backoff_secs: 300.0,
+            listen: None,
+            sources: Vec::new(),
+            opcua: Vec::new(),
+            influx: None,
+            statsd: None,
+            remote_write: None,
+            pipelines: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn bands(&self) -> SeverityBands {
+        SeverityBands {
+            high: self.high,
+            critical: self.critical,
+        }
+    }
+}
+
+/// One polled source, producing the readings of `sensor_id`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SourceConfig {
+    pub name: String,
+    pub sensor_id: i64,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: f64,
+    /// Time a poll may take before it counts as failed.
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: f64,
+    #[serde(flatten)]
+    pub kind: SourceKind,
+}
+
+fn default_interval_secs() -> f64 {
+    10.0
+}
+
+fn default_timeout_secs() -> f64 {
+    5.0
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum SourceKind {
+    /// A JSON document from `GET url`; `pointer` (RFC 6901) selects the
+    /// number, the whole document when empty.
+    Http {
+        url: String,
+        #[serde(default)]
+        pointer: String,
+    },
+    /// Registers of a Modbus device, over TCP or an RTU serial line.
+    Modbus(ModbusSource),
+    /// The first number in a file, e.g. a sysfs sensor or a file a PLC
+    /// gateway keeps rewriting.
+    File { path: PathBuf },
+    /// An OID of an SNMP agent, over v2c or v3.
+    Snmp(SnmpSource),
+    /// A PromQL instant query against the HTTP API of Prometheus at `url`,
+    /// which must come down to a single series.
+    Prometheus { url: String, query: String },
+}
+
+/// Where a Modbus value is and how it becomes a reading in engineering
+/// units: `raw * scale + offset`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ModbusSource {
+    /// `host:port` of a Modbus TCP device; the port defaults to 502.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    /// Serial device of a Modbus RTU line, e.g. `/dev/ttyUSB0`, instead of
+    /// `address`. Sources on the same line are polled one at a time.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    #[serde(default = "default_baud_rate")]
+    pub baud_rate: u32,
+    /// Parity of the RTU line, with 8 data bits and 1 stop bit.
+    #[serde(default)]
+    pub parity: Parity,
+    #[serde(default = "default_unit")]
+    pub unit: u8,
+    #[serde(default)]
+    pub table: RegisterTable,
+    pub register: u16,
+    #[serde(rename = "type", default)]
+    pub register_type: RegisterType,
+    /// Multiplies the raw value, e.g. 0.1 for tenths of a degree.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    /// Added after scaling, e.g. -40 for a 0 to 165 register holding -40 to
+    /// 125 °C.
+    #[serde(default)]
+    pub offset: f64,
+}
+
+fn default_baud_rate() -> u32 {
+    9_600
+}
+
+fn default_unit() -> u8 {
+    1
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    #[default]
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterTable {
+    /// Read with function 3.
+    #[default]
+    Holding,
+    /// Read-only registers, read with function 4.
+    Input,
+}
+
+/// How a Modbus value is laid out; 32-bit values span two registers, high
+/// word first.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegisterType {
+    #[default]
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+/// Where an SNMP value is: gauges and integers are read as they are,
+/// counters as their rate per second since the previous poll, both times
+/// `scale`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct SnmpSource {
+    /// `host:port` of the agent; the port defaults to 161.
+    pub address: String,
+    /// Numeric, e.g. `1.3.6.1.2.1.2.2.1.10.3` for ifInOctets of interface 3.
+    pub oid: String,
+    #[serde(default)]
+    pub version: SnmpVersion,
+    /// Community of a v2c agent.
+    #[serde(default = "default_community")]
+    pub community: String,
+    /// User of a v3 agent; the security level follows from the passwords
+    /// set: none, `auth_password`, or both.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub auth_protocol: AuthProtocol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_password: Option<String>,
+    #[serde(default)]
+    pub privacy_protocol: PrivacyProtocol,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub privacy_password: Option<String>,
+    /// Multiplies the value, e.g. 8 for the bits per second of an octet
+    /// counter.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+}
+
+fn default_community() -> String {
+    "public".to_string()
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SnmpVersion {
+    #[default]
+    #[serde(rename = "2c")]
+    V2c,
+    #[serde(rename = "3")]
+    V3,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthProtocol {
+    Md5,
+    #[default]
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PrivacyProtocol {
+    Des,
+    #[default]
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+/// A session with an OPC UA server and one subscription to its `items`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct OpcUaServer {
+    pub name: String,
+    /// `opc.tcp://host:port[/path]`.
+    pub endpoint: String,
+    /// `None`, `Basic128Rsa15`, `Basic256`, `Basic256Sha256`,
+    /// `Aes128Sha256RsaOaep` or `Aes256Sha256RsaPss`.
+    #[serde(default = "default_security_policy")]
+    pub security_policy: String,
+    #[serde(default)]
+    pub security_mode: SecurityMode,
+    /// Signs in with a user name and password instead of anonymously.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// Where the client's certificate and key live, with the `trusted/` and
+    /// `rejected/` directories of server certificates.
+    #[serde(default = "default_pki_dir")]
+    pub pki_dir: PathBuf,
+    /// The client's certificate (DER) and private key (PEM), relative to
+    /// `pki_dir`; a self-signed pair is created there when missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub private_key: Option<PathBuf>,
+    /// Accepts any server certificate instead of only those copied into
+    /// `trusted/`; an untrusted one is saved to `rejected/` for review.
+    #[serde(default)]
+    pub trust_server_certs: bool,
+    #[serde(default = "default_publishing_interval_ms")]
+    pub publishing_interval_ms: f64,
+    pub items: Vec<MonitoredItem>,
+}
+
+fn default_security_policy() -> String {
+    "None".to_string()
+}
+
+fn default_pki_dir() -> PathBuf {
+    PathBuf::from("pki")
+}
+
+fn default_publishing_interval_ms() -> f64 {
+    1_000.0
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityMode {
+    #[default]
+    None,
+    Sign,
+    SignAndEncrypt,
+}
+
+/// A variable of an OPC UA server whose value changes become readings of
+/// `sensor_id`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct MonitoredItem {
+    /// E.g. `ns=2;s=Press6.OilTemp` or `ns=3;i=1001`.
+    pub node_id: String,
+    pub sensor_id: i64,
+    /// How often the server samples the value; the publishing interval
+    /// when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampling_interval_ms: Option<f64>,
+}
+
+/// Line protocol accepted on `POST /write` of `listen` and, when set, in UDP
+/// datagrams to `udp`; each field of a point becomes a reading of the
+/// sensor of the first rule it matches, and the others are dropped.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InfluxSettings {
+    /// Address to take datagrams on, e.g. `0.0.0.0:8089`; off when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp: Option<String>,
+    pub rules: Vec<InfluxRule>,
+}
+
+/// Fields named `field` of `measurement` points carrying at least `tags`,
+/// as readings of `sensor_id` or of the sensor in the `sensor_id_tag` tag.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct InfluxRule {
+    pub measurement: String,
+    #[serde(default = "default_field")]
+    pub field: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id_tag: Option<String>,
+}
+
+fn default_field() -> String {
+    "value".to_string()
+}
+
+/// StatsD (`name:value|type`) and Graphite (`path value [timestamp]`) lines
+/// taken in datagrams on `udp` and over connections to `tcp`, and summed up
+/// into a reading per matching metric every `flush_interval_secs`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StatsdSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub udp: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tcp: Option<String>,
+    #[serde(default = "default_statsd_flush_interval_secs")]
+    pub flush_interval_secs: f64,
+    pub rules: Vec<StatsdRule>,
+}
+
+fn default_statsd_flush_interval_secs() -> f64 {
+    10.0
+}
+
+/// Metrics named like `metric`, in which a `*` stands for any one
+/// dot-separated segment, as readings of `sensor_id`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct StatsdRule {
+    pub metric: String,
+    pub sensor_id: i64,
+    /// What a timer or histogram reports per interval.
+    #[serde(default)]
+    pub statistic: Statistic,
+}
+
+/// Prometheus remote_write requests taken on `POST /api/v1/write` of
+/// `listen`; each sample of a series becomes a reading of the sensor of the
+/// first rule it matches, and the other series are dropped.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RemoteWriteSettings {
+    pub rules: Vec<RemoteWriteRule>,
+}
+
+/// Series of `metric` carrying at least `labels`, as readings of `sensor_id`
+/// or of the sensor in the `sensor_id_label` label.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct RemoteWriteRule {
+    pub metric: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sensor_id_label: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Statistic {
+    #[default]
+    Mean,
+    Median,
+    Min,
+    Max,
+    Sum,
+    Count,
+}
+
+impl Validate for Settings {
+    fn validate(&self) -> Result<(), String> {
+        if self.batch_size == 0 {
+            return Err("batch_size must be at least 1".to_string());
+        }
+        if self.flush_interval_secs == 0 {
+            return Err("flush_interval_secs must be positive".to_string());
+        }
+        if self.queue_capacity == 0 {
+            return Err("queue_capacity must be at least 1".to_string());
+        }
+        if let Some(threshold) = self.threshold
+            && !(threshold.is_finite() && threshold > 0.0)
+        {
+            return Err(format!(
+                "threshold must be a positive number, got {}",
+                threshold
+            ));
+        }
+        if self.reservoir_size == Some(0) {
+            return Err("reservoir_size must be at least 1".to_string());
+        }
+        if !(self.high > 0.0 && self.critical > self.high) {
+            return Err("critical must be above high, both positive".to_string());
+        }
+        if !(self.initial_backoff_secs > 0.0 && self.max_backoff_secs >= self.initial_backoff_secs)
+        {
+            return Err(
+                "initial_backoff_secs must be positive and at most max_backoff_secs".to_string(),
+            );
+        }
+        if self.sources.is_empty()
+            && self.opcua.is_empty()
+            && self.influx.is_none()
+            && self.statsd.is_none()
+            && self.remote_write.is_none()
+        {
+            return Err("no sources configured; add [[collector.sources]] or \
+                 [[collector.opcua]] tables, or a [collector.influx], \
+                 [collector.statsd] or [collector.remote_write] section"
+                .to_string());
+        }
+        let mut names = BTreeSet::new();
+        if let Some(influx) = &self.influx {
+            // Its health is reported under this name.
+            names.insert("influx");
+            if self.listen.is_none() && influx.udp.is_none() {
+                return Err("influx: set listen or influx.udp to receive line protocol".to_string());
+            }
+            if influx.rules.is_empty() {
+                return Err("influx: no rules mapping points to sensors".to_string());
+            }
+            for (number, rule) in (1..).zip(&influx.rules) {
+                if rule.sensor_id.is_some() == rule.sensor_id_tag.is_some() {
+                    return Err(format!(
+                        "influx: rule {}: set one of sensor_id and sensor_id_tag",
+                        number
+                    ));
+                }
+            }
+        }
+        if let Some(statsd) = &self.statsd {
+            names.insert("statsd");
+            if statsd.udp.is_none() && statsd.tcp.is_none() {
+                return Err("statsd: set udp, tcp or both".to_string());
+            }
+            if !(statsd.flush_interval_secs.is_finite() && statsd.flush_interval_secs > 0.0) {
+                return Err("statsd: flush_interval_secs must be positive".to_string());
+            }
+            if statsd.rules.is_empty() {
+                return Err("statsd: no rules mapping metrics to sensors".to_string());
+            }
+            for (number, rule) in (1..).zip(&statsd.rules) {
+                if rule.metric.split('.').any(str::is_empty) {
+                    return Err(format!(
+                        "statsd: rule {}: invalid metric {:?}",
+                        number, rule.metric
+                    ));
+                }
+            }
+        }
+        if let Some(remote_write) = &self.remote_write {
+            names.insert("remote_write");
+            if self.listen.is_none() {
+                return Err("remote_write: set listen to receive remote_write".to_string());
+            }
+            if remote_write.rules.is_empty() {
+                return Err("remote_write: no rules mapping series to sensors".to_string());
+            }
+            for (number, rule) in (1..).zip(&remote_write.rules) {
+                if rule.sensor_id.is_some() == rule.sensor_id_label.is_some() {
+                    return Err(format!(
+                        "remote_write: rule {}: set one of sensor_id and sensor_id_label",
+                        number
+                    ));
+                }
+            }
+        }
+        for source in &self.sources {
+            if !names.insert(source.name.as_str()) {
+                return Err(format!("source {:?} is configured twice", source.name));
+            }
+            if !(source.interval_secs > 0.0 && source.timeout_secs > 0.0) {
+                return Err(format!(
+                    "source {:?}: interval_secs and timeout_secs must be positive",
+                    source.name
+                ));
+            }
+            if let SourceKind::Modbus(modbus) = &source.kind
+                && modbus.address.is_some() == modbus.serial.is_some()
+            {
+                return Err(format!(
+                    "source {:?}: set one of address (Modbus TCP) and serial (Modbus RTU)",
+                    source.name
+                ));
+            }
+            if let SourceKind::Snmp(snmp) = &source.kind {
+                snmp.validate()
+                    .map_err(|e| format!("source {:?}: {}", source.name, e))?;
+            }
+        }
+        for server in &self.opcua {
+            if !names.insert(server.name.as_str()) {
+                return Err(format!("source {:?} is configured twice", server.name));
+            }
+            server
+                .validate()
+                .map_err(|e| format!("OPC UA server {:?}: {}", server.name, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl SnmpSource {
+    fn validate(&self) -> Result<(), String> {
+        if self.oid.parse::<snmp2::Oid>().is_err() {
+            return Err(format!(
+                "invalid oid {:?}, expected e.g. 1.3.6.1.2.1.1.3.0",
+                self.oid
+            ));
+        }
+        let passwords = [&self.auth_password, &self.privacy_password];
+        match self.version {
+            SnmpVersion::V2c => {
+                if self.username.is_some() || passwords.iter().any(|p| p.is_some()) {
+                    return Err("username and passwords are for version 3".to_string());
+                }
+            }
+            SnmpVersion::V3 => {
+                if self.username.is_none() {
+                    return Err("version 3 needs a username".to_string());
+                }
+                if self.privacy_password.is_some() && self.auth_password.is_none() {
+                    return Err("privacy_password needs an auth_password".to_string());
+                }
+                // RFC 3414 derives the keys from at least 8 characters.
+                if passwords.into_iter().flatten().any(|p| p.len() < 8) {
+                    return Err("passwords must be at least 8 characters".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OpcUaServer {
+    fn validate(&self) -> Result<(), String> {
+        if !self.endpoint.starts_with("opc.tcp://") {
+            return Err(format!(
+                "endpoint must be opc.tcp://..., got {:?}",
+                self.endpoint
+            ));
+        }
+        const POLICIES: [&str; 6] = [
+            "None",
+            "Basic128Rsa15",
+            "Basic256",
+            "Basic256Sha256",
+            "Aes128Sha256RsaOaep",
+            "Aes256Sha256RsaPss",
+        ];
+        if !POLICIES.contains(&self.security_policy.as_str()) {
+            return Err(format!(
+                "unknown security_policy {:?}, expected one of {}",
+                self.security_policy,
+                POLICIES.join(", ")
+            ));
+        }
+        if (self.security_policy == "None") != (self.security_mode == SecurityMode::None) {
+            return Err(
+                "security_mode must be none exactly when security_policy is None".to_string(),
+            );
+        }
+        if self.username.is_some() != self.password.is_some() {
+            return Err("set both username and password, or neither".to_string());
+        }
+        if self.certificate.is_some() != self.private_key.is_some() {
+            return Err("set both certificate and private_key, or neither".to_string());
+        }
+        if !(self.publishing_interval_ms.is_finite() && self.publishing_interval_ms > 0.0) {
+            return Err("publishing_interval_ms must be positive".to_string());
+        }
+        if self.items.is_empty() {
+            return Err("no items to monitor".to_string());
+        }
+        for item in &self.items {
+            if item.node_id.parse::<opcua::types::NodeId>().is_err() {
+                return Err(format!("invalid node_id {:?}", item.node_id));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn load(config: Option<&Path>, flags: &impl Serialize) -> Result<Settings, String> {
+    Loader::new("collector")
+        .file(config_file(config))
+        .env(ENV_PREFIX)
+        .overrides(flags)
+        .load()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_text(name: &str, text: &str, flags: &impl Serialize) -> Result<Settings, String> {
+        let path = std::env::temp_dir().join(format!("collector-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        let settings = load(Some(&path), flags);
+        std::fs::remove_file(&path).unwrap();
+        settings
+    }
+
+    #[test]
+    fn test_reads_sources_from_the_file() {
+        let text = "[collector]\n\
+            sink = \"local\"\n\
+            threshold = 3.0\n\
+            [[collector.sources]]\n\
+            name = \"oil\"\n\
+            sensor_id = 12\n\
+            kind = \"modbus\"\n\
+            address = \"plc:502\"\n\
+            register = 3\n\
+            type = \"i16\"\n\
+            scale = 0.1\n\
+            [[collector.sources]]\n\
+            name = \"flow\"\n\
+            sensor_id = 13\n\
+            interval_secs = 1.5\n\
+            kind = \"http\"\n\
+            url = \"http://gateway/flow\"\n";
+        let flags = serde_json::json!({"url": "http://detector:3001"});
+        let settings = load_text("sources.toml", text, &flags).unwrap();
+        assert_eq!(settings.sink, SinkKind::Local);
+        assert_eq!(settings.url, "http://detector:3001");
+        assert_eq!(settings.sources.len(), 2);
+        assert_eq!(
+            settings.sources[0].kind,
+            SourceKind::Modbus(ModbusSource {
+                address: Some("plc:502".to_string()),
+                serial: None,
+                baud_rate: 9_600,
+                parity: Parity::None,
+                unit: 1,
+                table: RegisterTable::Holding,
+                register: 3,
+                register_type: RegisterType::I16,
+                scale: 0.1,
+                offset: 0.0,
+            })
+        );
+        assert_eq!(settings.sources[0].interval_secs, 10.0);
+        assert_eq!(settings.sources[1].interval_secs, 1.5);
+
+        let error = load_text(
+            "duplicate.toml",
+            "[[collector.sources]]\nname = \"a\"\nsensor_id = 1\nkind = \"file\"\npath = \"/x\"\n\
+             [[collector.sources]]\nname = \"a\"\nsensor_id = 2\nkind = \"file\"\npath = \"/y\"\n",
+            &serde_json::json!({}),
+        );
+        assert_eq!(error.unwrap_err(), "source \"a\" is configured twice");
+        let error = load_text(
+            "both.toml",
+            "[[collector.sources]]\nname = \"a\"\nsensor_id = 1\nkind = \"modbus\"\n\
+             address = \"plc\"\nserial = \"/dev/ttyS0\"\nregister = 0\n",
+            &serde_json::json!({}),
+        );
+        assert!(error.unwrap_err().contains("set one of address"));
+    }
+
+    #[test]
+    fn test_reads_and_checks_snmp_sources() {
+        let source = "[[collector.sources]]\n\
+            name = \"uplink\"\n\
+            sensor_id = 30\n\
+            kind = \"snmp\"\n\
+            address = \"switch\"\n\
+            version = \"3\"\n\
+            username = \"collector\"\n\
+            auth_protocol = \"sha256\"\n\
+            auth_password = \"authpass1\"\n\
+            oid = \"1.3.6.1.2.1.31.1.1.1.6.49\"\n";
+        let settings = load_text("snmp.toml", source, &serde_json::json!({})).unwrap();
+        let SourceKind::Snmp(snmp) = &settings.sources[0].kind else {
+            panic!("expected an SNMP source");
+        };
+        assert_eq!(snmp.version, SnmpVersion::V3);
+        assert_eq!(snmp.auth_protocol, AuthProtocol::Sha256);
+        assert_eq!(snmp.privacy_protocol, PrivacyProtocol::Aes128);
+        assert_eq!((snmp.community.as_str(), snmp.scale), ("public", 1.0));
+
+        for (name, broken, expected) in [
+            (
+                "oid",
+                source.replace("1.3.6.1.2.1.31.1.1.1.6.49", "ifHCInOctets.49"),
+                "invalid oid",
+            ),
+            (
+                "v2c",
+                source.replace("version = \"3\"", "version = \"2c\""),
+                "are for version 3",
+            ),
+            (
+                "user",
+                source.replace("username = \"collector\"\n", ""),
+                "needs a username",
+            ),
+            (
+                "privacy",
+                source.replace("auth_password", "privacy_password"),
+                "needs an auth_password",
+            ),
+            (
+                "short",
+                source.replace("authpass1", "short"),
+                "at least 8 characters",
+            ),
+        ] {
+            let error = load_text(name, &broken, &serde_json::json!({})).unwrap_err();
+            assert!(
+                error.starts_with("source \"uplink\": ") && error.contains(expected),
+                "{}: {}",
+                name,
+                error
+            );
+        }
+    }
+
+    #[test]
+    fn test_reads_and_checks_opcua_servers() {
+        let server = "[[collector.opcua]]\n\
+            name = \"plc\"\n\
+            endpoint = \"opc.tcp://plc:4840\"\n\
+            security_policy = \"Basic256Sha256\"\n\
+            security_mode = \"signandencrypt\"\n\
+            items = [{ node_id = \"ns=2;s=Oil\", sensor_id = 21 }, \
+                     { node_id = \"ns=3;i=1001\", sensor_id = 22, sampling_interval_ms = 100 }]\n";
+        let settings = load_text("opcua.toml", server, &serde_json::json!({})).unwrap();
+        let plc = &settings.opcua[0];
+        assert_eq!(plc.security_mode, SecurityMode::SignAndEncrypt);
+        assert_eq!(plc.pki_dir, PathBuf::from("pki"));
+        assert_eq!(plc.items[1].sampling_interval_ms, Some(100.0));
+        assert!(!plc.trust_server_certs);
+
+        for (name, broken, expected) in [
+            (
+                "mode",
+                server.replace("signandencrypt", "none"),
+                "security_mode",
+            ),
+            (
+                "policy",
+                server.replace("Basic256Sha256", "Rot13"),
+                "unknown security_policy",
+            ),
+            (
+                "node",
+                server.replace("ns=2;s=Oil", "Oil"),
+                "invalid node_id",
+            ),
+            (
+                "endpoint",
+                server.replace("opc.tcp", "http"),
+                "endpoint must be",
+            ),
+        ] {
+            let error = load_text(
+                &format!("opcua-{}.toml", name),
+                &broken,
+                &serde_json::json!({}),
+            );
+            let error = error.unwrap_err();
+            assert!(error.contains(expected), "{}", error);
+            assert!(error.contains("OPC UA server \"plc\""), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_reads_and_checks_influx_rules() {
+        let influx = "[collector]\n\
+            listen = \"127.0.0.1:9102\"\n\
+            [[collector.influx.rules]]\n\
+            measurement = \"modbus\"\n\
+            field = \"oil_temp\"\n\
+            tags = { host = \"press-6\" }\n\
+            sensor_id = 22\n\
+            [[collector.influx.rules]]\n\
+            measurement = \"sensors\"\n\
+            sensor_id_tag = \"sensor\"\n";
+        let settings = load_text("influx.toml", influx, &serde_json::json!({})).unwrap();
+        let rules = &settings.influx.as_ref().unwrap().rules;
+        assert_eq!(rules[0].tags["host"], "press-6");
+        assert_eq!(rules[1].field, "value");
+        assert!(settings.sources.is_empty());
+
+        for (name, broken, expected) in [
+            (
+                "both",
+                influx.replace(
+                    "sensor_id_tag = \"sensor\"",
+                    "sensor_id = 1\nsensor_id_tag = \"s\"",
+                ),
+                "rule 2: set one of sensor_id and sensor_id_tag",
+            ),
+            (
+                "unreachable",
+                influx.replace("listen = \"127.0.0.1:9102\"", ""),
+                "set listen or influx.udp",
+            ),
+            (
+                "name",
+                format!(
+                    "{}[[collector.sources]]\nname = \"influx\"\nsensor_id = 1\n\
+                     kind = \"file\"\npath = \"/x\"\n",
+                    influx
+                ),
+                "source \"influx\" is configured twice",
+            ),
+        ] {
+            let error = load_text(
+                &format!("influx-{}.toml", name),
+                &broken,
+                &serde_json::json!({}),
+            );
+            let error = error.unwrap_err();
+            assert!(error.contains(expected), "{}: {}", name, error);
+        }
+    }
+
+    #[test]
+    fn test_reads_and_checks_statsd_rules() {
+        let statsd = "[collector.statsd]\n\
+            udp = \"127.0.0.1:8125\"\n\
+            [[collector.statsd.rules]]\n\
+            metric = \"press6.*.cycle_time\"\n\
+            statistic = \"median\"\n\
+            sensor_id = 23\n\
+            [[collector.statsd.rules]]\n\
+            metric = \"press6.oil\"\n\
+            sensor_id = 24\n";
+        let settings = load_text("statsd.toml", statsd, &serde_json::json!({})).unwrap();
+        let statsd_settings = settings.statsd.as_ref().unwrap();
+        assert_eq!(statsd_settings.flush_interval_secs, 10.0);
+        assert_eq!(statsd_settings.rules[0].statistic, Statistic::Median);
+        assert_eq!(statsd_settings.rules[1].statistic, Statistic::Mean);
+
+        for (name, broken, expected) in [
+            (
+                "metric",
+                statsd.replace("press6.oil", "press6..oil"),
+                "rule 2: invalid metric",
+            ),
+            (
+                "unreachable",
+                statsd.replace("udp = \"127.0.0.1:8125\"", ""),
+                "set udp, tcp or both",
+            ),
+            (
+                "flush",
+                statsd.replace(
+                    "[collector.statsd]\n",
+                    "[collector.statsd]\nflush_interval_secs = 0\n",
+                ),
+                "flush_interval_secs must be positive",
+            ),
+        ] {
+            let error = load_text(
+                &format!("statsd-{}.toml", name),
+                &broken,
+                &serde_json::json!({}),
+            );
+            let error = error.unwrap_err();
+            assert!(error.contains(expected), "{}: {}", name, error);
+        }
+    }
+
+    #[test]
+    fn test_reads_and_checks_remote_write_rules() {
+        let remote_write = "[collector]\n\
+            listen = \"127.0.0.1:9102\"\n\
+            [[collector.remote_write.rules]]\n\
+            metric = \"node_hwmon_temp_celsius\"\n\
+            labels = { instance = \"press6:9100\" }\n\
+            sensor_id = 25\n\
+            [[collector.remote_write.rules]]\n\
+            metric = \"plant_sensor_value\"\n\
+            sensor_id_label = \"sensor\"\n";
+        let settings =
+            load_text("remote-write.toml", remote_write, &serde_json::json!({})).unwrap();
+        let rules = &settings.remote_write.as_ref().unwrap().rules;
+        assert_eq!(rules[0].labels["instance"], "press6:9100");
+        assert_eq!(rules[1].sensor_id_label.as_deref(), Some("sensor"));
+
+        for (name, broken, expected) in [
+            (
+                "both",
+                remote_write.replace("sensor_id_label", "sensor_id = 1\nsensor_id_label"),
+                "rule 2: set one of sensor_id and sensor_id_label",
+            ),
+            (
+                "unreachable",
+                remote_write.replace("listen = \"127.0.0.1:9102\"", ""),
+                "set listen to receive remote_write",
+            ),
+        ] {
+            let error = load_text(
+                &format!("remote-write-{}.toml", name),
+                &broken,
+                &serde_json::json!({}),
+            );
+            let error = error.unwrap_err();
+            assert!(error.contains(expected), "{}: {}", name, error);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! `GET /health`: `ok` while no source is failing, `degraded` otherwise,
+//! with the health of each source and how full the queue to the sink is.
+//! `GET /metrics` gives the queue and the readings of each source in the
+//! Prometheus text format.
+
+use std::fmt::Write;
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde_json::{Value, json};
+
+use crate::queue::Depth;
+use crate::scheduler::{Health, SourceHealth, Status};
+
+pub fn routes(health: Health, depth: Depth) -> Router {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/metrics", get(metrics))
+        .with_state((health, depth))
+}
+
+async fn health_check(State((health, depth)): State<(Health, Depth)>) -> Json<Value> {
+    Json(report(&health, &depth))
+}
+
+async fn metrics(State((health, depth)): State<(Health, Depth)>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        exposition(&health, &depth),
+    )
+}
+
+fn report(health: &Health, depth: &Depth) -> Value {
+    let sources = health.lock().unwrap().clone();
+    let failing = sources
+        .values()
+        .filter(|source| source.status == Status::Failing)
+        .count();
+    let (queued, capacity) = depth.get().unwrap_or_default();
+    json!({
+        "status": if failing == 0 { "ok" } else { "degraded" },
+        "failing": failing,
+        "queue": { "depth": queued, "capacity": capacity },
+        "sources": sources,
+    })
+}
+
+fn exposition(health: &Health, depth: &Depth) -> String {
+    let (queued, capacity) = depth.get().unwrap_or_default();
+    let mut text = format!(
+        "# HELP collector_queue_depth Readings queued for the sink.\n\
+         # TYPE collector_queue_depth gauge\n\
+         collector_queue_depth {}\n\
+         # HELP collector_queue_capacity Readings the queue to the sink holds.\n\
+         # TYPE collector_queue_capacity gauge\n\
+         collector_queue_capacity {}\n",
+        queued, capacity
+    );
+    let sources = health.lock().unwrap();
+    let mut counter = |name: &str, help: &str, count: fn(&SourceHealth) -> u64| {
+        let _ = write!(text, "# HELP {} {}\n# TYPE {} counter\n", name, help, name);
+        for (source_name, source) in sources.iter() {
+            let _ = writeln!(
+                text,
+                "{}{{source=\"{}\"}} {}",
+                name,
+                escape(source_name),
+                count(source)
+            );
+        }
+    };
+    counter(
+        "collector_readings_total",
+        "Readings taken from each source.",
+        |source| source.readings,
+    );
+    counter(
+        "collector_shed_readings_total",
+        "Readings written to each listener and refused while the queue was full.",
+        |source| source.shed,
+    );
+    text
+}
+
+/// Escapes a label value of the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::scheduler::Sample;
+
+    #[test]
+    fn test_reports_failing_sources() {
+        let source = |status, consecutive_failures| SourceHealth {
+            sensors: vec![1],
+            status,
+            consecutive_failures,
+            readings: 0,
+            shed: 0,
+            last_success: None,
+            last_error: None,
+        };
+        let health: Health = Arc::new(Mutex::new(BTreeMap::from([
+            ("a".to_string(), source(Status::Ok, 0)),
+            ("b".to_string(), source(Status::Pending, 0)),
+        ])));
+        let (sender, _receiver) = mpsc::channel(8);
+        let depth = Depth::new(&sender);
+        assert_eq!(report(&health, &depth)["status"], "ok");
+
+        health
+            .lock()
+            .unwrap()
+            .insert("c".to_string(), source(Status::Failing, 3));
+        let report = report(&health, &depth);
+        assert_eq!(report["status"], "degraded");
+        assert_eq!(report["failing"], 1);
+        assert_eq!(report["queue"]["capacity"], 8);
+        assert_eq!(report["sources"]["c"]["consecutive_failures"], 3);
+        assert_eq!(report["sources"]["b"]["status"], "pending");
+    }
+
+    #[tokio::test]
+    async fn test_exposes_queue_depth_and_shed_readings() {
+        let health: Health = Arc::new(Mutex::new(BTreeMap::from([(
+            "influx".to_string(),
+            SourceHealth {
+                sensors: vec![22],
+                status: Status::Ok,
+                consecutive_failures: 0,
+                readings: 40,
+                shed: 7,
+                last_success: None,
+                last_error: None,
+            },
+        )])));
+        let (sender, _receiver) = mpsc::channel(16);
+        sender
+            .send(Sample {
+                sensor_id: 22,
+                id: 1,
+                value: 1.0,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+                lsn: None,
+            })
+            .await
+            .unwrap();
+
+        let text = exposition(&health, &Depth::new(&sender));
+        for line in [
+            "collector_queue_depth 1",
+            "collector_queue_capacity 16",
+            "collector_readings_total{source=\"influx\"} 40",
+            "collector_shed_readings_total{source=\"influx\"} 7",
+        ] {
+            assert!(text.lines().any(|l| l == line), "{}\n{}", line, text);
+        }
+        assert_eq!(escape("a\"b\\"), r#"a\"b\\"#);
+    }
+}
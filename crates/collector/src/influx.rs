@@ -0,0 +1,640 @@
+//! InfluxDB line protocol, as Telegraf's `influxdb` and `socket_writer`
+//! outputs send it: `POST /write` on the health listener and datagrams on
+//! `influx.udp`, whose fields become readings by the `influx.rules`.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+
+use crate::config::{InfluxRule, InfluxSettings};
+use crate::queue::{self, Shed};
+use crate::scheduler::{Health, Sample, Status, format_timestamp};
+use crate::wal::Wal;
+
+/// The health entry of the listener.
+const NAME: &str = "influx";
+
+/// Largest body taken once decompressed.
+const MAX_BODY: u64 = 32 * 1024 * 1024;
+
+/// A point of a line, with its numeric fields; string fields are left out.
+#[derive(Clone, Debug, PartialEq)]
+struct Point {
+    measurement: String,
+    tags: Vec<(String, String)>,
+    fields: Vec<(String, f64)>,
+    /// When the point was taken, if the line says.
+    time: Option<SystemTime>,
+}
+
+impl Point {
+    fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == key)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends the readings of the lines written to it on to the sink.
+pub struct Ingest {
+    rules: Vec<InfluxRule>,
+    samples: mpsc::Sender<Sample>,
+    health: Health,
+    /// The last reading id of each sensor.
+    ids: Mutex<HashMap<i64, i64>>,
+    wal: Option<Arc<Wal>>,
+}
+
+impl Ingest {
+    pub fn new(settings: &InfluxSettings, samples: mpsc::Sender<Sample>, health: Health) -> Self {
+        Self {
+            rules: settings.rules.clone(),
+            samples,
+            health,
+            ids: Mutex::default(),
+            wal: None,
+        }
+    }
+
+    /// Logs what is written in `wal` before it is queued, numbering the
+    /// readings of each sensor on from those the log holds.
+    pub fn with_wal(mut self, wal: Arc<Wal>) -> Self {
+        self.ids = Mutex::new(wal.last_ids().clone());
+        self.wal = Some(wal);
+        self
+    }
+
+    /// The readings of the points in `text`, with timestamps counted in
+    /// `precision` nanoseconds, and the error of the first bad line.
+    fn parse(&self, text: &str, precision: u64) -> (Vec<Sample>, Option<String>) {
+        let now = SystemTime::now();
+        let mut samples = Vec::new();
+        let mut error = None;
+        for (number, line) in (1..).zip(text.lines()) {
+            let point = match parse_line(line, precision) {
+                Ok(Some(point)) => point,
+                Ok(None) => continue,
+                Err(e) => {
+                    error.get_or_insert_with(|| format!("line {}: {}", number, e));
+                    continue;
+                }
+            };
+            let timestamp = format_timestamp(point.time.unwrap_or(now));
+            for (field, value) in &point.fields {
+                if let Some(sensor_id) = self.sensor(&point, field) {
+                    samples.push(Sample {
+                        sensor_id,
+                        id: self.next_id(sensor_id),
+                        value: *value,
+                        timestamp: timestamp.clone(),
+                        lsn: None,
+                    });
+                }
+            }
+        }
+        (samples, error)
+    }
+
+    /// Notes `readings` taken and the error of a bad line in the health of
+    /// the listener.
+    fn record(&self, readings: usize, error: Option<&String>) {
+        let mut health = self.health.lock().unwrap();
+        let entry = health.get_mut(NAME).expect("the listener has health");
+        if readings > 0 {
+            entry.status = Status::Ok;
+            entry.readings += readings as u64;
+            entry.last_success = Some(format_timestamp(SystemTime::now()));
+        }
+        if let Some(error) = error {
+            entry.last_error = Some(error.clone());
+        }
+    }
+
+    /// Sends the readings of the points in `text`, waiting for room in the
+    /// queue. A bad line fails the write after the others are sent, as a
+    /// partial write does in InfluxDB.
+    async fn write(&self, text: &str, precision: u64) -> Result<(), String> {
+        let (samples, error) = self.parse(text, precision);
+        self.record(samples.len(), error.as_ref());
+        for sample in samples {
+            if self.samples.send(sample).await.is_err() {
+                break;
+            }
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    /// As [`write`](Self::write), but refuses the whole write, bad lines
+    /// and all, when the queue has no room for its readings now.
+    fn offer(&self, text: &str, precision: u64) -> Result<Result<(), String>, Shed> {
+        let (samples, error) = self.parse(text, precision);
+        let readings = samples.len();
+        if let Err(shed) = queue::offer(&self.samples, self.wal.as_deref(), samples) {
+            queue::record(&self.health, NAME, readings, shed);
+            return Err(shed);
+        }
+        self.record(readings, error.as_ref());
+        Ok(error.map_or(Ok(()), Err))
+    }
+
+    /// The sensor of the first rule that gives one for `field` of `point`.
+    fn sensor(&self, point: &Point, field: &str) -> Option<i64> {
+        self.rules
+            .iter()
+            .filter(|rule| {
+                rule.measurement == point.measurement
+                    && rule.field == field
+                    && rule
+                        .tags
+                        .iter()
+                        .all(|(key, value)| point.tag(key) == Some(value.as_str()))
+            })
+            .find_map(|rule| match &rule.sensor_id_tag {
+                Some(tag) => point.tag(tag)?.parse().ok(),
+                None => rule.sensor_id,
+            })
+    }
+
+    fn next_id(&self, sensor_id: i64) -> i64 {
+        let mut ids = self.ids.lock().unwrap();
+        let id = ids.entry(sensor_id).or_insert(0);
+        *id += 1;
+        *id
+    }
+}
+
+pub fn routes(ingest: Arc<Ingest>) -> Router {
+    Router::new()
+        .route("/write", post(write))
+        .with_state(ingest)
+}
+
+#[derive(Deserialize)]
+struct WriteQuery {
+    precision: Option<String>,
+}
+
+/// Answers 204 once the readings are queued for the sink, and logged when
+/// there is a write-ahead log, and 429 with `Retry-After` when the queue
+/// cannot take them, which Telegraf retries, as it does a 503 when the log
+/// fails.
+async fn write(
+    State(ingest): State<Arc<Ingest>>,
+    Query(query): Query<WriteQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let text = precision(query.precision.as_deref())
+        .and_then(|precision| Ok((precision, decode(&headers, &body)?)));
+    let written = match text {
+        Ok((precision, text)) => match ingest.offer(&text, precision) {
+            Ok(written) => written,
+            Err(shed) => {
+                let error = Json(json!({ "error": shed.to_string() }));
+                return (shed.status(), shed.retry_after(), error).into_response();
+            }
+        },
+        Err(error) => Err(error),
+    };
+    match written {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(error) => (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response(),
+    }
+}
+
+/// Takes line protocol datagrams on `socket`, with nanosecond timestamps,
+/// until the task is aborted.
+pub async fn listen_udp(socket: UdpSocket, ingest: Arc<Ingest>) {
+    let mut buffer = vec![0; 65_536];
+    loop {
+        let (length, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Warning: {}: {}", NAME, e);
+                continue;
+            }
+        };
+        let text = String::from_utf8_lossy(&buffer[..length]);
+        if let Err(error) = ingest.write(&text, 1).await {
+            eprintln!("Warning: {}: {} from {}", NAME, error, peer);
+        }
+    }
+}
+
+/// Nanoseconds per timestamp unit of the `precision` query parameter, in
+/// either the 1.x or the 2.x spelling.
+fn precision(name: Option<&str>) -> Result<u64, String> {
+    Ok(match name.unwrap_or("ns") {
+        "ns" | "n" => 1,
+        "us" | "u" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60_000_000_000,
+        "h" => 3_600_000_000_000,
+        other => return Err(format!("unknown precision {:?}", other)),
+    })
+}
+
+fn decode(headers: &HeaderMap, body: &[u8]) -> Result<String, String> {
+    let encoding = headers.get(header::CONTENT_ENCODING);
+    match encoding.map(|value| value.as_bytes()) {
+        None | Some(b"identity") => {
+            String::from_utf8(body.to_vec()).map_err(|_| "body is not UTF-8".to_string())
+        }
+        Some(b"gzip") => {
+            let mut text = String::new();
+            GzDecoder::new(body)
+                .take(MAX_BODY + 1)
+                .read_to_string(&mut text)
+                .map_err(|e| format!("gzip body: {}", e))?;
+            if text.len() as u64 > MAX_BODY {
+                return Err(format!("body over {} bytes once decompressed", MAX_BODY));
+            }
+            Ok(text)
+        }
+        Some(other) => Err(format!(
+            "unsupported Content-Encoding {:?}",
+            String::from_utf8_lossy(other)
+        )),
+    }
+}
+
+/// Parses `measurement[,tag=value...] field=value[,field=value...]
+/// [timestamp]`; blank lines and `#` comments are `None`.
+fn parse_line(line: &str, precision: u64) -> Result<Option<Point>, String> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+
+    // Quotes only delimit string field values, not names.
+    let key = split(line, ' ', false)[0];
+    let rest = split(line[key.len()..].trim_start(), ' ', true);
+    let mut sections = rest.into_iter().filter(|section| !section.is_empty());
+    let fields = sections.next().ok_or("missing fields")?;
+    let timestamp = sections.next();
+    if sections.next().is_some() {
+        return Err("unexpected text after the timestamp".to_string());
+    }
+
+    let mut names = split(key, ',', false).into_iter();
+    let measurement = unescape(names.next().unwrap_or_default(), &[',', ' ']);
+    if measurement.is_empty() {
+        return Err("missing measurement".to_string());
+    }
+    let tags = names
+        .map(|tag| match split(tag, '=', false)[..] {
+            [key, value] if !key.is_empty() && !value.is_empty() => Ok((
+                unescape(key, &[',', '=', ' ']),
+                unescape(value, &[',', '=', ' ']),
+            )),
+            _ => Err(format!("invalid tag {:?}", tag)),
+        })
+        .collect::<Result<_, _>>()?;
+
+    let mut numbers = Vec::new();
+    for field in split(fields, ',', true) {
+        let [key, value] = split(field, '=', true)[..] else {
+            return Err(format!("invalid field {:?}", field));
+        };
+        if key.is_empty() {
+            return Err(format!("invalid field {:?}", field));
+        }
+        if let Some(number) = field_value(value)? {
+            numbers.push((unescape(key, &[',', '=', ' ']), number));
+        }
+    }
+
+    let time = match timestamp {
+        Some(timestamp) => {
+            let units: i64 = timestamp
+                .parse()
+                .map_err(|_| format!("invalid timestamp {:?}", timestamp))?;
+            let nanos = units
+                .unsigned_abs()
+                .checked_mul(precision)
+                .ok_or_else(|| format!("timestamp {} out of range", timestamp))?;
+            let offset = Duration::from_nanos(nanos);
+            Some(if units < 0 {
+                UNIX_EPOCH - offset
+            } else {
+                UNIX_EPOCH + offset
+            })
+        }
+        None => None,
+    };
+    Ok(Some(Point {
+        measurement,
+        tags,
+        fields: numbers,
+        time,
+    }))
+}
+
+/// The number of a field value: floats, `i` integers, `u` unsigned integers
+/// and booleans as 0 or 1. Strings are `None`.
+fn field_value(text: &str) -> Result<Option<f64>, String> {
+    if text.starts_with('"') {
+        return if text.len() >= 2 && text.ends_with('"') {
+            Ok(None)
+        } else {
+            Err(format!("unterminated string {}", text))
+        };
+    }
+    let number = match text {
+        "t" | "T" | "true" | "True" | "TRUE" => Some(1.0),
+        "f" | "F" | "false" | "False" | "FALSE" => Some(0.0),
+        _ => match (text.strip_suffix('i'), text.strip_suffix('u')) {
+            (Some(integer), _) => integer.parse::<i64>().ok().map(|value| value as f64),
+            (_, Some(unsigned)) => unsigned.parse::<u64>().ok().map(|value| value as f64),
+            _ => text.parse::<f64>().ok().filter(|value| value.is_finite()),
+        },
+    };
+    number
+        .map(Some)
+        .ok_or_else(|| format!("invalid field value {:?}", text))
+}
+
+/// Splits `text` at each `separator` that is not escaped with a backslash
+/// or, when `quoted`, inside double quotes.
+fn split(text: &str, separator: char, quoted: bool) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let (mut start, mut escaped, mut quoting) = (0, false, false);
+    for (at, c) in text.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if quoted && c == '"' {
+            quoting = !quoting;
+        } else if c == separator && !quoting {
+            parts.push(&text[start..at]);
+            start = at + c.len_utf8();
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+/// Drops the backslash before each of `escaped`; any other stays.
+fn unescape(text: &str, escaped: &[char]) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(next) if c == '\\' && escaped.contains(next) => {}
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use super::*;
+    use crate::config::Settings;
+
+    #[test]
+    fn test_parses_line_protocol() {
+        let point = parse_line(
+            r#"cpu\ load,host=press\,6,rack\=a=b\ c usage=0.5,cores=8i,free=3u,up=t,note="a, b=c d" 1768816800000000000"#,
+            1,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(point.measurement, "cpu load");
+        assert_eq!(
+            point.tags,
+            [
+                ("host".to_string(), "press,6".to_string()),
+                ("rack=a".to_string(), "b c".to_string()),
+            ]
+        );
+        assert_eq!(
+            point.fields,
+            [
+                ("usage".to_string(), 0.5),
+                ("cores".to_string(), 8.0),
+                ("free".to_string(), 3.0),
+                ("up".to_string(), 1.0),
+            ]
+        );
+        assert_eq!(
+            point.time,
+            Some(UNIX_EPOCH + Duration::from_secs(1_768_816_800))
+        );
+
+        let seconds = parse_line("oil value=-1.5e1 1768816800", 1_000_000_000).unwrap();
+        assert_eq!(seconds.as_ref().unwrap().fields[0].1, -15.0);
+        assert_eq!(seconds.unwrap().time, point.time);
+        assert_eq!(parse_line("oil value=1", 1).unwrap().unwrap().time, None);
+        assert_eq!(parse_line("  # a comment", 1), Ok(None));
+        assert_eq!(parse_line("", 1), Ok(None));
+
+        for (line, expected) in [
+            ("oil", "missing fields"),
+            ("oil,host value=1", "invalid tag"),
+            ("oil value", "invalid field"),
+            ("oil value=abc", "invalid field value"),
+            ("oil value=NaN", "invalid field value"),
+            ("oil value=1.5i", "invalid field value"),
+            ("oil note=\"open", "unterminated string"),
+            ("oil value=1 soon", "invalid timestamp"),
+            ("oil value=1 9223372036854775807", "out of range"),
+            ("oil value=1 1 2", "after the timestamp"),
+            (",host=a value=1", "missing measurement"),
+        ] {
+            let precision = 1_000_000_000;
+            let error = parse_line(line, precision).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", line, error);
+        }
+    }
+
+    #[test]
+    fn test_decodes_gzip_bodies_and_precisions() {
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(b"oil value=1").unwrap();
+        let body = gzip.finish().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_ENCODING, "gzip".parse().unwrap());
+        assert_eq!(decode(&headers, &body).unwrap(), "oil value=1");
+        headers.insert(header::CONTENT_ENCODING, "br".parse().unwrap());
+        assert!(decode(&headers, &body).unwrap_err().contains("unsupported"));
+        assert!(decode(&HeaderMap::new(), &body).is_err());
+
+        assert_eq!(precision(None), Ok(1));
+        assert_eq!(precision(Some("u")), Ok(1_000));
+        assert_eq!(precision(Some("s")), Ok(1_000_000_000));
+        assert!(precision(Some("d")).is_err());
+    }
+
+    fn rule(measurement: &str, field: &str, tags: &[(&str, &str)]) -> InfluxRule {
+        InfluxRule {
+            measurement: measurement.to_string(),
+            field: field.to_string(),
+            tags: tags
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            sensor_id: None,
+            sensor_id_tag: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writes_become_readings_of_the_matching_rules() {
+        let settings = Settings {
+            influx: Some(InfluxSettings {
+                udp: None,
+                rules: vec![
+                    InfluxRule {
+                        sensor_id: Some(22),
+                        ..rule("modbus", "oil_temp", &[("host", "press-6")])
+                    },
+                    InfluxRule {
+                        sensor_id_tag: Some("sensor".to_string()),
+                        ..rule("sensors", "value", &[])
+                    },
+                ],
+            }),
+            ..Settings::default()
+        };
+        let health = crate::scheduler::health(&settings);
+        let (sender, mut receiver) = mpsc::channel(16);
+        let ingest = Arc::new(Ingest::new(
+            settings.influx.as_ref().unwrap(),
+            sender,
+            health.clone(),
+        ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/write", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, routes(ingest.clone())).into_future());
+        let http = reqwest::Client::new();
+        let response = http
+            .post(format!("{}?db=telegraf&precision=s", url))
+            .body(
+                "modbus,host=press-6 oil_temp=41.5,pressure=3 1768816800\n\
+                 modbus,host=press-7 oil_temp=39 1768816800\n\
+                 sensors,sensor=31 value=7i 1768816801\n\
+                 sensors,sensor=thirty-one value=8i\n",
+            )
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let first = receiver.recv().await.unwrap();
+        assert_eq!(
+            first,
+            Sample {
+                sensor_id: 22,
+                id: 1,
+                value: 41.5,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+                lsn: None,
+            }
+        );
+        let second = receiver.recv().await.unwrap();
+        assert_eq!((second.sensor_id, second.id, second.value), (31, 1, 7.0));
+        assert_eq!(second.timestamp, "2026-01-19T10:00:01");
+
+        let response = http
+            .post(&url)
+            .body("sensors,sensor=31 value=9\nsensors value=\n")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert!(error["error"].as_str().unwrap().starts_with("line 2: "));
+        let partial = receiver.recv().await.unwrap();
+        assert_eq!((partial.sensor_id, partial.id, partial.value), (31, 2, 9.0));
+
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = socket.local_addr().unwrap();
+        let udp = tokio::spawn(listen_udp(socket, ingest));
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(b"modbus,host=press-6 oil_temp=42", address)
+            .await
+            .unwrap();
+        let datagram = receiver.recv().await.unwrap();
+        assert_eq!(
+            (datagram.sensor_id, datagram.id, datagram.value),
+            (22, 2, 42.0)
+        );
+
+        {
+            let health = health.lock().unwrap();
+            let entry = &health[NAME];
+            assert_eq!((entry.status, entry.readings), (Status::Ok, 4));
+            assert_eq!(entry.sensors, [22]);
+            assert!(entry.last_error.as_ref().unwrap().starts_with("line 2: "));
+        }
+        udp.abort();
+    }
+
+    #[tokio::test]
+    async fn test_sheds_writes_the_queue_cannot_take() {
+        let settings = Settings {
+            influx: Some(InfluxSettings {
+                udp: None,
+                rules: vec![InfluxRule {
+                    sensor_id: Some(22),
+                    ..rule("modbus", "oil_temp", &[])
+                }],
+            }),
+            ..Settings::default()
+        };
+        let health = crate::scheduler::health(&settings);
+        let (sender, mut receiver) = mpsc::channel(2);
+        let ingest = Ingest::new(settings.influx.as_ref().unwrap(), sender, health.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/write", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, routes(Arc::new(ingest))).into_future());
+        let http = reqwest::Client::new();
+        let write = |body: &'static str| http.post(&url).body(body).send();
+
+        let response = write("modbus oil_temp=41\nmodbus oil_temp=42\n")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let response = write("modbus oil_temp=43\n").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        let response = write("modbus oil_temp=1\nmodbus oil_temp=2\nmodbus oil_temp=3\n")
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::PAYLOAD_TOO_LARGE);
+
+        // Once the sink takes a reading, there is room again.
+        assert_eq!(receiver.recv().await.unwrap().value, 41.0);
+        let response = write("modbus oil_temp=44\n").await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        assert_eq!(receiver.recv().await.unwrap().value, 42.0);
+        assert_eq!(receiver.recv().await.unwrap().value, 44.0);
+
+        let health = health.lock().unwrap();
+        assert_eq!((health[NAME].readings, health[NAME].shed), (3, 4));
+    }
+}
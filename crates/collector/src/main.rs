@@ -0,0 +1,285 @@
+//! `collector`: polls industrial data sources (HTTP gateways, Modbus TCP and
+//! RTU devices, SNMP agents, Prometheus queries, files) on their intervals,
+//! subscribes to OPC UA servers, takes InfluxDB line protocol from Telegraf,
+//! StatsD or Graphite metrics from legacy apps and series from Prometheus
+//! remote_write, and feeds the readings to the anomaly detector, writing the
+//! anomalies it finds to stdout as NDJSON.
+//!
+//! ```text
+//! collector --config plant.toml
+//! collector --config plant.toml --sink local --listen 0.0.0.0:9102
+//! ```
+//!
+//! Sources are the `[[collector.sources]]` and `[[collector.opcua]]` tables of
+//! the settings file (`--config` or `ANOMALY_CONFIG`), and what is pushed to
+//! `POST /write`, the StatsD listeners or `POST /api/v1/write`, mapped to
+//! sensors by the rules of `[collector.influx]`, `[collector.statsd]` and
+//! `[collector.remote_write]`; the other settings also come
+//! from `COLLECTOR_*` environment variables, which the flags override. A
+//! failing source is retried with exponential backoff while the others carry
+//! on, and `GET /health` on `--listen` reports each source's state. The
+//! readings wait for the sink in a queue of `queue_capacity`, which sheds
+//! HTTP writes with 429 once full, and whose depth `GET /metrics` shows.
+//! With `wal_path`, HTTP writes are logged before they are acknowledged and
+//! what the sink had not sent is queued again at startup.
+//! Groups of sensors can instead flow through the transforms, detectors and
+//! sinks of a pipelines file (`--pipelines`), reloaded on SIGHUP.
+
+mod config;
+mod health;
+mod influx;
+mod modbus;
+mod pipeline;
+mod prometheus;
+mod queue;
+mod remote_write;
+mod scheduler;
+mod sink;
+mod snmp;
+mod source;
+mod statsd;
+mod subscription;
+mod wal;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::Parser;
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::config::SinkKind;
+use crate::pipeline::Pipelines;
+use crate::sink::Detector;
+
+// Every flag left out falls back to the settings file, the environment
+// and then the default in brackets.
+#[derive(Parser, Serialize)]
+#[command(name = "collector", version, about)]
+struct Cli {
+    /// Settings file with a `[collector]` section and its sources; defaults
+    /// to $ANOMALY_CONFIG.
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Where readings go: the service over HTTP, or detection in this
+    /// process [default: http].
+    #[arg(long, value_enum)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sink: Option<SinkKind>,
+    /// Base URL of the service, for the http sink
+    /// [default: http://localhost:3001].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Address to serve `GET /health`, `GET /metrics`, `POST /write` and
+    /// `POST /api/v1/write` on, e.g. 0.0.0.0:9102; off when unset.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    listen: Option<String>,
+    /// YAML file of pipelines routing groups of sensors through their own
+    /// transforms, detectors and sinks; reloaded on SIGHUP.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pipelines: Option<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let settings = match config::load(cli.config.as_deref(), &cli) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let detector = match Detector::new(&settings) {
+        Ok(detector) => detector,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let client = reqwest::Client::new();
+    let pipelines = match Pipelines::load(&settings, client.clone()) {
+        Ok(pipelines) => Arc::new(pipelines),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let (wal, replay) = match settings.wal_path.as_deref().map(wal::Wal::open) {
+        Some(Ok((wal, replay))) => (Some(Arc::new(wal)), replay),
+        Some(Err(e)) => {
+            eprintln!("Error: write-ahead log: {}", e);
+            return ExitCode::FAILURE;
+        }
+        None => (None, Vec::new()),
+    };
+
+    let (sender, receiver) = mpsc::channel(settings.queue_capacity);
+    let health = scheduler::health(&settings);
+    let ingest = settings.influx.as_ref().map(|influx| {
+        let ingest = influx::Ingest::new(influx, sender.clone(), health.clone());
+        Arc::new(match &wal {
+            Some(wal) => ingest.with_wal(wal.clone()),
+            None => ingest,
+        })
+    });
+    let mut tasks = scheduler::start(&settings, client, sender.clone(), &health);
+    if !replay.is_empty() {
+        eprintln!("Replaying {} logged readings", replay.len());
+        let sender = sender.clone();
+        tasks.push(tokio::spawn(async move {
+            for sample in replay {
+                if sender.send(sample).await.is_err() {
+                    break;
+                }
+            }
+        }));
+    }
+    if let Some(path) = pipelines.path() {
+        eprintln!("{} pipelines from {}", pipelines.len(), path.display());
+        tasks.push(tokio::spawn(pipeline::reload_on_hangup(pipelines.clone())));
+    }
+    if let Some(statsd) = &settings.statsd {
+        let aggregator = Arc::new(statsd::Aggregator::new(statsd, health.clone()));
+        if let Some(address) = &statsd.udp {
+            let socket = match tokio::net::UdpSocket::bind(address).await {
+                Ok(socket) => socket,
+                Err(e) => {
+                    eprintln!("Error: Failed to bind to {}: {}", address, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            eprintln!("StatsD and Graphite on udp://{}", address);
+            tasks.push(tokio::spawn(statsd::listen_udp(socket, aggregator.clone())));
+        }
+        if let Some(address) = &statsd.tcp {
+            let listener = match tokio::net::TcpListener::bind(address).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("Error: Failed to bind to {}: {}", address, e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            eprintln!("StatsD and Graphite on tcp://{}", address);
+            tasks.push(tokio::spawn(statsd::listen_tcp(
+                listener,
+                aggregator.clone(),
+            )));
+        }
+        let every = Duration::from_secs_f64(statsd.flush_interval_secs);
+        tasks.push(tokio::spawn(statsd::flush(
+            aggregator,
+            every,
+            sender.clone(),
+        )));
+    }
+    tasks.extend(subscription::start(&settings, sender.clone(), &health));
+
+    if let (Some(address), Some(ingest)) = (
+        settings
+            .influx
+            .as_ref()
+            .and_then(|influx| influx.udp.as_ref()),
+        &ingest,
+    ) {
+        let socket = match tokio::net::UdpSocket::bind(address).await {
+            Ok(socket) => socket,
+            Err(e) => {
+                eprintln!("Error: Failed to bind to {}: {}", address, e);
+                return ExitCode::FAILURE;
+            }
+        };
+        eprintln!("Line protocol on udp://{}", address);
+        tasks.push(tokio::spawn(influx::listen_udp(socket, ingest.clone())));
+    }
+
+    let mut server = None;
+    if let Some(address) = &settings.listen {
+        let listener = match tokio::net::TcpListener::bind(address).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                eprintln!("Error: Failed to bind to {}: {}", address, e);
+                return ExitCode::FAILURE;
+            }
+        };
+        eprintln!("Health on http://{}/health", address);
+        let mut app = health::routes(health.clone(), queue::Depth::new(&sender));
+        if let Some(ingest) = &ingest {
+            eprintln!("Line protocol on http://{}/write", address);
+            app = app.merge(influx::routes(ingest.clone()));
+        }
+        if let Some(remote_write) = &settings.remote_write {
+            eprintln!("Prometheus remote_write on http://{}/api/v1/write", address);
+            let mut receiver = remote_write::Receiver::new(remote_write, sender.clone(), health);
+            if let Some(wal) = &wal {
+                receiver = receiver.with_wal(wal.clone());
+            }
+            app = app.merge(remote_write::routes(Arc::new(receiver)));
+        }
+        // Writes in flight finish on ctrl-c, and idle connections close, so
+        // that the channel to the sink closes too.
+        let shutdown = async {
+            let _ = tokio::signal::ctrl_c().await;
+        };
+        server = Some(tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app)
+                .with_graceful_shutdown(shutdown)
+                .await
+            {
+                eprintln!("Error: health endpoint: {}", e);
+            }
+        }));
+    }
+    // Only the tasks hold senders from here on, or the sink would wait for
+    // these at shutdown.
+    drop((sender, ingest));
+
+    eprintln!(
+        "Collecting from {} sources into {}",
+        settings.sources.len()
+            + settings.opcua.len()
+            + usize::from(settings.influx.is_some())
+            + usize::from(settings.statsd.is_some())
+            + usize::from(settings.remote_write.is_some()),
+        match settings.sink {
+            SinkKind::Http => settings.url.as_str(),
+            SinkKind::Local => "local detection",
+        }
+    );
+    let sink = tokio::spawn(async move {
+        let mut out = std::io::stdout();
+        sink::run(
+            receiver,
+            detector,
+            &pipelines,
+            wal.as_deref(),
+            settings.batch_size,
+            Duration::from_secs(settings.flush_interval_secs),
+            &mut out,
+        )
+        .await;
+    });
+
+    // Stopping the pollers, subscriptions and listeners closes the channel,
+    // so the sink sends what is pending before it returns.
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        eprintln!("Error: waiting for ctrl-c: {}", e);
+    }
+    for task in tasks {
+        task.abort();
+    }
+    if let Some(server) = server {
+        let _ = server.await;
+    }
+    let _ = sink.await;
+    ExitCode::SUCCESS
+}
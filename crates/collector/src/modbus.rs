@@ -0,0 +1,207 @@
+//! Modbus sources, read with tokio-modbus over TCP or an RTU serial line:
+//! one request per poll, on a connection opened for it.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex};
+
+use tokio::net::TcpStream;
+use tokio_modbus::client::{Context, Reader, rtu, tcp};
+use tokio_modbus::{ExceptionCode, Slave};
+use tokio_serial::SerialPortBuilderExt;
+
+use crate::config::{ModbusSource, Parity, RegisterTable, RegisterType};
+
+const DEFAULT_PORT: u16 = 502;
+
+/// A lock per RTU line: a serial device has a single master, so the sources
+/// sharing one take turns.
+static LINES: LazyLock<Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Mutex::default);
+
+/// Reads the value of `source`, in engineering units.
+pub async fn read(source: &ModbusSource) -> Result<f64, String> {
+    let count = match source.register_type {
+        RegisterType::U16 | RegisterType::I16 => 1,
+        RegisterType::U32 | RegisterType::I32 | RegisterType::F32 => 2,
+    };
+    let slave = Slave(source.unit);
+    let words = match (&source.address, &source.serial) {
+        (Some(address), _) => {
+            let address = if address.contains(':') {
+                address.to_string()
+            } else {
+                format!("{}:{}", address, DEFAULT_PORT)
+            };
+            let stream = TcpStream::connect(&address)
+                .await
+                .map_err(|e| format!("{}: {}", address, e))?;
+            let mut context = tcp::attach_slave(stream, slave);
+            read_registers(&mut context, source.table, source.register, count)
+                .await
+                .map_err(|e| format!("{}: {}", address, e))?
+        }
+        (None, Some(serial)) => {
+            let line = LINES
+                .lock()
+                .unwrap()
+                .entry(serial.clone())
+                .or_default()
+                .clone();
+            let _turn = line.lock().await;
+            let stream = tokio_serial::new(serial, source.baud_rate)
+                .parity(match source.parity {
+                    Parity::None => tokio_serial::Parity::None,
+                    Parity::Even => tokio_serial::Parity::Even,
+                    Parity::Odd => tokio_serial::Parity::Odd,
+                })
+                .open_native_async()
+                .map_err(|e| format!("{}: {}", serial, e))?;
+            let mut context = rtu::attach_slave(stream, slave);
+            read_registers(&mut context, source.table, source.register, count)
+                .await
+                .map_err(|e| format!("{}: {}", serial, e))?
+        }
+        (None, None) => return Err("no address or serial line".to_string()),
+    };
+    Ok(decode(&words, source.register_type) * source.scale + source.offset)
+}
+
+async fn read_registers(
+    context: &mut Context,
+    table: RegisterTable,
+    register: u16,
+    count: u16,
+) -> Result<Vec<u16>, String> {
+    let response = match table {
+        RegisterTable::Holding => context.read_holding_registers(register, count).await,
+        RegisterTable::Input => context.read_input_registers(register, count).await,
+    };
+    let words = response
+        .map_err(|e| e.to_string())?
+        .map_err(|code: ExceptionCode| format!("device answered with exception: {}", code))?;
+    if words.len() != count as usize {
+        return Err(format!("expected {} registers, got {}", count, words.len()));
+    }
+    Ok(words)
+}
+
+fn decode(words: &[u16], register_type: RegisterType) -> f64 {
+    let wide = || ((words[0] as u32) << 16) | words[1] as u32;
+    match register_type {
+        RegisterType::U16 => words[0] as f64,
+        RegisterType::I16 => words[0] as i16 as f64,
+        RegisterType::U32 => wide() as f64,
+        RegisterType::I32 => wide() as i32 as f64,
+        RegisterType::F32 => f32::from_bits(wide()) as f64,
+    }
+}
+
+/// A Modbus TCP device on a local port serving `holding` and `input`
+/// registers from address 0, for tests.
+#[cfg(test)]
+pub async fn fake_device(holding: Vec<u16>, input: Vec<u16>) -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let tables = Arc::new((holding, input));
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let tables = tables.clone();
+            tokio::spawn(async move {
+                let mut request = [0; 12];
+                while stream.read_exact(&mut request).await.is_ok() {
+                    let function = request[7];
+                    let start = u16::from_be_bytes([request[8], request[9]]) as usize;
+                    let count = u16::from_be_bytes([request[10], request[11]]) as usize;
+                    let table = if function == 4 { &tables.1 } else { &tables.0 };
+                    let mut pdu = Vec::new();
+                    match table.get(start..start + count) {
+                        Some(words) => {
+                            pdu.push(function);
+                            pdu.push((count * 2) as u8);
+                            for word in words {
+                                pdu.extend_from_slice(&word.to_be_bytes());
+                            }
+                        }
+                        // Illegal data address.
+                        None => pdu.extend_from_slice(&[function | 0x80, 2]),
+                    }
+                    let mut response = request[..4].to_vec();
+                    response.extend_from_slice(&(pdu.len() as u16 + 1).to_be_bytes());
+                    response.push(request[6]);
+                    response.extend_from_slice(&pdu);
+                    if stream.write_all(&response).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(
+        address: &str,
+        table: RegisterTable,
+        register: u16,
+        register_type: RegisterType,
+    ) -> ModbusSource {
+        ModbusSource {
+            address: Some(address.to_string()),
+            serial: None,
+            baud_rate: 9_600,
+            parity: Parity::None,
+            unit: 1,
+            table,
+            register,
+            register_type,
+            scale: 1.0,
+            offset: 0.0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reads_and_decodes_registers() {
+        let pi = std::f32::consts::PI.to_bits();
+        let holding = vec![0xFFFE, 0x0001, 0x0002, (pi >> 16) as u16, pi as u16];
+        let address = fake_device(holding, vec![125]).await;
+        let read_holding = |register, register_type| {
+            let source = source(&address, RegisterTable::Holding, register, register_type);
+            async move { read(&source).await }
+        };
+
+        assert_eq!(read_holding(0, RegisterType::I16).await.unwrap(), -2.0);
+        assert_eq!(read_holding(0, RegisterType::U16).await.unwrap(), 65534.0);
+        assert_eq!(read_holding(1, RegisterType::U32).await.unwrap(), 65538.0);
+        let value = read_holding(3, RegisterType::F32).await.unwrap();
+        assert_eq!(value as f32, std::f32::consts::PI);
+
+        let error = read_holding(4, RegisterType::I32).await.unwrap_err();
+        assert!(
+            error.contains("device answered with exception"),
+            "{}",
+            error
+        );
+
+        let mut level = source(&address, RegisterTable::Input, 0, RegisterType::U16);
+        (level.scale, level.offset) = (0.5, -40.0);
+        assert_eq!(read(&level).await.unwrap(), 22.5);
+    }
+
+    #[tokio::test]
+    async fn test_reports_a_missing_serial_line() {
+        let mut line = source("", RegisterTable::Holding, 0, RegisterType::U16);
+        (line.address, line.serial) = (None, Some("/dev/collector-test-missing".to_string()));
+        let error = read(&line).await.unwrap_err();
+        assert!(
+            error.starts_with("/dev/collector-test-missing: "),
+            "{}",
+            error
+        );
+    }
+}
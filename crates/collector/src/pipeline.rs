@@ -0,0 +1,722 @@
+//! Pipelines: flows from a group of sensors through transforms and
+//! detectors to sinks, declared in the YAML file named by `pipelines` and
+//! reloaded on SIGHUP, so wiring a new flow needs no code change.
+//!
+//! ```yaml
+//! pipelines:
+//!   - name: presses
+//!     sources: [press-4-oil-temp]
+//!     sensors: [21, 22]
+//!     transforms:
+//!       - { kind: resample, interval_secs: 60, aggregate: mean }
+//!       - { kind: detrend }
+//!     detectors:
+//!       - { method: mad, threshold: 3.5 }
+//!       - { method: ewma, alpha: 0.2, threshold: 3.0 }
+//!     sinks:
+//!       - { kind: stdout }
+//!       - { kind: file, path: /var/log/collector/presses.ndjson }
+//!       - { kind: webhook, url: "http://hooks.example.com/anomalies" }
+//! ```
+//!
+//! The group is the sensors of the named `sources` (polled sources and OPC
+//! UA servers) plus `sensors`. Each batch of a sensor in the group goes
+//! through the transforms in order, then every detector, and what they find
+//! goes to every sink: one NDJSON line per anomaly to stdout and files, one
+//! JSON array per batch to webhooks. A sensor may be in several pipelines;
+//! sensors in none go to the collector's sink as before. A file that fails
+//! to load on reload is reported and the pipelines before it are kept.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, UNIX_EPOCH};
+
+use detection_core::{
+    Outlier, Severity, SeverityBands, ewma_outliers, iqr_outliers, mad_outliers, rolling_outliers,
+    zscore_outliers,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Settings;
+use crate::scheduler::{Sample, format_timestamp, parse_timestamp};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineFile {
+    pipelines: Vec<PipelineConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PipelineConfig {
+    name: String,
+    /// Names of polled sources and OPC UA servers whose sensors are in the
+    /// group.
+    #[serde(default)]
+    sources: Vec<String>,
+    #[serde(default)]
+    sensors: Vec<i64>,
+    #[serde(default)]
+    transforms: Vec<Transform>,
+    detectors: Vec<DetectorConfig>,
+    sinks: Vec<SinkConfig>,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Transform {
+    /// One reading per `interval_secs`, aligned to the epoch, with the
+    /// `aggregate` of the readings in it and the time the interval starts.
+    Resample {
+        interval_secs: u64,
+        #[serde(default)]
+        aggregate: Aggregate,
+    },
+    /// Removes the least-squares line through the batch, leaving how far
+    /// each reading is off the trend.
+    Detrend,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregate {
+    #[default]
+    Mean,
+    Min,
+    Max,
+    Last,
+}
+
+/// A detector of `detection-core`, with the threshold it is customarily
+/// used at as the default.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase", deny_unknown_fields)]
+pub enum DetectorConfig {
+    Zscore {
+        #[serde(default = "default_zscore_threshold")]
+        threshold: f64,
+    },
+    Mad {
+        #[serde(default = "default_mad_threshold")]
+        threshold: f64,
+    },
+    Iqr {
+        #[serde(default = "default_iqr_k")]
+        k: f64,
+    },
+    Ewma {
+        alpha: f64,
+        #[serde(default = "default_zscore_threshold")]
+        threshold: f64,
+    },
+    Rolling {
+        window: usize,
+        #[serde(default = "default_zscore_threshold")]
+        threshold: f64,
+    },
+}
+
+fn default_zscore_threshold() -> f64 {
+    3.0
+}
+
+fn default_mad_threshold() -> f64 {
+    3.5
+}
+
+fn default_iqr_k() -> f64 {
+    1.5
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum SinkConfig {
+    Stdout,
+    /// Appended to, created if missing.
+    File {
+        path: PathBuf,
+    },
+    /// `POST`ed to.
+    Webhook {
+        url: String,
+    },
+}
+
+/// A pipeline of the file, its group resolved to sensor ids.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pipeline {
+    pub name: String,
+    pub sensors: BTreeSet<i64>,
+    pub transforms: Vec<Transform>,
+    pub detectors: Vec<DetectorConfig>,
+    pub sinks: Vec<SinkConfig>,
+}
+
+/// An anomaly a pipeline found, as written to its sinks. `value` is the
+/// reading after the transforms.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Detected {
+    pub pipeline: String,
+    pub method: &'static str,
+    pub sensor_id: i64,
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub score: f64,
+    pub severity: Severity,
+}
+
+impl DetectorConfig {
+    fn method(&self) -> &'static str {
+        match self {
+            DetectorConfig::Zscore { .. } => "zscore",
+            DetectorConfig::Mad { .. } => "mad",
+            DetectorConfig::Iqr { .. } => "iqr",
+            DetectorConfig::Ewma { .. } => "ewma",
+            DetectorConfig::Rolling { .. } => "rolling",
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let positive = |name: &str, value: f64| {
+            if value.is_finite() && value > 0.0 {
+                Ok(())
+            } else {
+                Err(format!("{}: {} must be positive", self.method(), name))
+            }
+        };
+        match *self {
+            DetectorConfig::Zscore { threshold } | DetectorConfig::Mad { threshold } => {
+                positive("threshold", threshold)
+            }
+            DetectorConfig::Iqr { k } => positive("k", k),
+            DetectorConfig::Ewma { alpha, threshold } => {
+                if !(alpha > 0.0 && alpha <= 1.0) {
+                    return Err("ewma: alpha must be in (0, 1]".to_string());
+                }
+                positive("threshold", threshold)
+            }
+            DetectorConfig::Rolling { window, threshold } => {
+                if window == 0 {
+                    return Err("rolling: window must be positive".to_string());
+                }
+                positive("threshold", threshold)
+            }
+        }
+    }
+
+    fn detect(&self, readings: &[(i64, f64)], bands: &SeverityBands) -> Vec<Outlier> {
+        match *self {
+            DetectorConfig::Zscore { threshold } => zscore_outliers(readings, threshold, bands),
+            DetectorConfig::Mad { threshold } => mad_outliers(readings, threshold, bands),
+            DetectorConfig::Iqr { k } => iqr_outliers(readings, k, bands),
+            DetectorConfig::Ewma { alpha, threshold } => {
+                ewma_outliers(readings, alpha, threshold, bands)
+            }
+            DetectorConfig::Rolling { window, threshold } => {
+                rolling_outliers(readings, window, threshold, bands)
+            }
+        }
+    }
+}
+
+impl Transform {
+    fn apply(&self, batch: Vec<Sample>) -> Vec<Sample> {
+        match *self {
+            Transform::Resample {
+                interval_secs,
+                aggregate,
+            } => resample(batch, interval_secs as i64, aggregate),
+            Transform::Detrend => detrend(batch),
+        }
+    }
+}
+
+/// Buckets `batch` into intervals of `interval` seconds; readings without a
+/// timestamp of `format_timestamp` are dropped.
+fn resample(batch: Vec<Sample>, interval: i64, aggregate: Aggregate) -> Vec<Sample> {
+    let mut buckets: BTreeMap<i64, Vec<Sample>> = BTreeMap::new();
+    for sample in batch {
+        if let Some(seconds) = parse_timestamp(&sample.timestamp) {
+            let start = seconds.div_euclid(interval) * interval;
+            buckets.entry(start).or_default().push(sample);
+        }
+    }
+    buckets
+        .into_iter()
+        .map(|(start, samples)| {
+            let values = samples.iter().map(|s| s.value);
+            let value = match aggregate {
+                Aggregate::Mean => values.sum::<f64>() / samples.len() as f64,
+                Aggregate::Min => values.fold(f64::INFINITY, f64::min),
+                Aggregate::Max => values.fold(f64::NEG_INFINITY, f64::max),
+                Aggregate::Last => samples[samples.len() - 1].value,
+            };
+            let last = &samples[samples.len() - 1];
+            Sample {
+                sensor_id: last.sensor_id,
+                id: last.id,
+                value,
+                timestamp: format_timestamp(UNIX_EPOCH + Duration::from_secs(start.max(0) as u64)),
+                lsn: None,
+            }
+        })
+        .collect()
+}
+
+/// Subtracts the least-squares line through the values of `batch`, by
+/// position.
+fn detrend(mut batch: Vec<Sample>) -> Vec<Sample> {
+    let n = batch.len() as f64;
+    if batch.len() < 2 {
+        return batch;
+    }
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = batch.iter().map(|s| s.value).sum::<f64>() / n;
+    let (mut sxy, mut sxx) = (0.0, 0.0);
+    for (x, sample) in batch.iter().enumerate() {
+        let dx = x as f64 - mean_x;
+        sxy += dx * (sample.value - mean_y);
+        sxx += dx * dx;
+    }
+    let slope = sxy / sxx;
+    for (x, sample) in batch.iter_mut().enumerate() {
+        sample.value -= mean_y + slope * (x as f64 - mean_x);
+    }
+    batch
+}
+
+impl Pipeline {
+    /// What the pipeline finds in a batch of one sensor.
+    pub fn detect(&self, batch: &[Sample], bands: &SeverityBands) -> Vec<Detected> {
+        let batch = self
+            .transforms
+            .iter()
+            .fold(batch.to_vec(), |batch, transform| transform.apply(batch));
+        let readings: Vec<(i64, f64)> = batch.iter().map(|s| (s.id, s.value)).collect();
+        let samples: BTreeMap<i64, &Sample> = batch.iter().map(|s| (s.id, s)).collect();
+        self.detectors
+            .iter()
+            .flat_map(|detector| {
+                detector
+                    .detect(&readings, bands)
+                    .into_iter()
+                    .map(|outlier| {
+                        let sample = samples[&outlier.reading_id];
+                        Detected {
+                            pipeline: self.name.clone(),
+                            method: detector.method(),
+                            sensor_id: sample.sensor_id,
+                            id: sample.id,
+                            value: outlier.value,
+                            timestamp: sample.timestamp.clone(),
+                            score: outlier.score,
+                            severity: outlier.severity,
+                        }
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Parses and checks a pipelines file, with `sources` mapping source names
+/// to the sensors they report as.
+fn parse(text: &str, sources: &BTreeMap<String, Vec<i64>>) -> Result<Vec<Pipeline>, String> {
+    let file: PipelineFile = serde_yaml::from_str(text).map_err(|e| e.to_string())?;
+    let mut names = BTreeSet::new();
+    file.pipelines
+        .into_iter()
+        .map(|config| {
+            let name = config.name;
+            if name.is_empty() {
+                return Err("a pipeline has no name".to_string());
+            }
+            if !names.insert(name.clone()) {
+                return Err(format!("pipeline {:?} is defined twice", name));
+            }
+            let mut sensors: BTreeSet<i64> = config.sensors.into_iter().collect();
+            for source in &config.sources {
+                let ids = sources
+                    .get(source)
+                    .ok_or_else(|| format!("pipeline {:?}: unknown source {:?}", name, source))?;
+                sensors.extend(ids);
+            }
+            if sensors.is_empty() {
+                return Err(format!("pipeline {:?}: no sources or sensors", name));
+            }
+            for transform in &config.transforms {
+                if let Transform::Resample {
+                    interval_secs: 0, ..
+                } = transform
+                {
+                    return Err(format!(
+                        "pipeline {:?}: resample: interval_secs must be positive",
+                        name
+                    ));
+                }
+            }
+            if config.detectors.is_empty() {
+                return Err(format!("pipeline {:?}: no detectors", name));
+            }
+            for detector in &config.detectors {
+                detector
+                    .validate()
+                    .map_err(|e| format!("pipeline {:?}: {}", name, e))?;
+            }
+            if config.sinks.is_empty() {
+                return Err(format!("pipeline {:?}: no sinks", name));
+            }
+            Ok(Pipeline {
+                name,
+                sensors,
+                transforms: config.transforms,
+                detectors: config.detectors,
+                sinks: config.sinks,
+            })
+        })
+        .collect()
+}
+
+/// The pipelines of the file, swapped whole on reload.
+#[derive(Default)]
+pub struct Pipelines {
+    path: Option<PathBuf>,
+    sources: BTreeMap<String, Vec<i64>>,
+    bands: SeverityBands,
+    current: RwLock<Arc<Vec<Pipeline>>>,
+    client: reqwest::Client,
+}
+
+impl Pipelines {
+    /// Loads the file named by the `pipelines` setting; none without one.
+    pub fn load(settings: &Settings, client: reqwest::Client) -> Result<Self, String> {
+        let sources = settings
+            .sources
+            .iter()
+            .map(|source| (source.name.clone(), vec![source.sensor_id]))
+            .chain(settings.opcua.iter().map(|server| {
+                let sensors = server.items.iter().map(|item| item.sensor_id).collect();
+                (server.name.clone(), sensors)
+            }))
+            .collect();
+        let pipelines = Self {
+            path: settings.pipelines.clone(),
+            sources,
+            bands: settings.bands(),
+            current: RwLock::default(),
+            client,
+        };
+        pipelines.reload()?;
+        Ok(pipelines)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.current.read().unwrap().len()
+    }
+
+    /// Reads the file again, keeping the pipelines loaded before if it is
+    /// invalid.
+    pub fn reload(&self) -> Result<(), String> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("reading {}: {}", path.display(), e))?;
+        let pipelines =
+            parse(&text, &self.sources).map_err(|e| format!("{}: {}", path.display(), e))?;
+        *self.current.write().unwrap() = Arc::new(pipelines);
+        Ok(())
+    }
+
+    /// Runs a batch of `sensor_id` through every pipeline of its group and
+    /// sends what they find to their sinks, stdout being `out`. False when
+    /// the sensor is in no pipeline.
+    pub async fn run(&self, sensor_id: i64, batch: &[Sample], out: &mut impl Write) -> bool {
+        let current = self.current.read().unwrap().clone();
+        let mut matched = false;
+        for pipeline in current.iter().filter(|p| p.sensors.contains(&sensor_id)) {
+            matched = true;
+            let found = pipeline.detect(batch, &self.bands);
+            if found.is_empty() {
+                continue;
+            }
+            for sink in &pipeline.sinks {
+                if let Err(e) = self.send(sink, &found, out).await {
+                    eprintln!(
+                        "Warning: pipeline {:?}: dropping {} anomalies of sensor {}: {}",
+                        pipeline.name,
+                        found.len(),
+                        sensor_id,
+                        e
+                    );
+                }
+            }
+        }
+        matched
+    }
+
+    async fn send(
+        &self,
+        sink: &SinkConfig,
+        found: &[Detected],
+        out: &mut impl Write,
+    ) -> Result<(), String> {
+        let lines = || {
+            found
+                .iter()
+                .map(|anomaly| serde_json::to_string(anomaly).expect("anomalies serialize") + "\n")
+                .collect::<String>()
+        };
+        match sink {
+            SinkConfig::Stdout => out
+                .write_all(lines().as_bytes())
+                .and_then(|()| out.flush())
+                .map_err(|e| e.to_string()),
+            SinkConfig::File { path } => std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(lines().as_bytes()))
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            SinkConfig::Webhook { url } => {
+                self.client
+                    .post(url)
+                    .json(found)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|e| e.to_string())?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Reloads `pipelines` on every SIGHUP.
+pub async fn reload_on_hangup(pipelines: Arc<Pipelines>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            eprintln!("Error: pipelines will not reload: {}", e);
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match pipelines.reload() {
+            Ok(()) => eprintln!("Reloaded {} pipelines", pipelines.len()),
+            Err(e) => eprintln!("Error: keeping the previous pipelines: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(id: i64, value: f64, timestamp: &str) -> Sample {
+        Sample {
+            sensor_id: 7,
+            id,
+            value,
+            timestamp: timestamp.to_string(),
+            lsn: None,
+        }
+    }
+
+    fn sources() -> BTreeMap<String, Vec<i64>> {
+        BTreeMap::from([
+            ("oil".to_string(), vec![12]),
+            ("line-b-plc".to_string(), vec![21, 22]),
+        ])
+    }
+
+    #[test]
+    fn test_parses_pipelines_and_resolves_sources() {
+        let text = "pipelines:\n\
+            \x20 - name: presses\n\
+            \x20   sources: [oil, line-b-plc]\n\
+            \x20   sensors: [30, 12]\n\
+            \x20   transforms:\n\
+            \x20     - { kind: resample, interval_secs: 60, aggregate: max }\n\
+            \x20     - { kind: detrend }\n\
+            \x20   detectors:\n\
+            \x20     - { method: mad }\n\
+            \x20     - { method: ewma, alpha: 0.2, threshold: 2.5 }\n\
+            \x20   sinks:\n\
+            \x20     - { kind: stdout }\n\
+            \x20     - { kind: file, path: /tmp/presses.ndjson }\n\
+            \x20     - { kind: webhook, url: \"http://hooks.example.com/anomalies\" }\n";
+
+        let pipelines = parse(text, &sources()).unwrap();
+
+        assert_eq!(
+            pipelines,
+            [Pipeline {
+                name: "presses".to_string(),
+                sensors: BTreeSet::from([12, 21, 22, 30]),
+                transforms: vec![
+                    Transform::Resample {
+                        interval_secs: 60,
+                        aggregate: Aggregate::Max,
+                    },
+                    Transform::Detrend,
+                ],
+                detectors: vec![
+                    DetectorConfig::Mad { threshold: 3.5 },
+                    DetectorConfig::Ewma {
+                        alpha: 0.2,
+                        threshold: 2.5,
+                    },
+                ],
+                sinks: vec![
+                    SinkConfig::Stdout,
+                    SinkConfig::File {
+                        path: PathBuf::from("/tmp/presses.ndjson"),
+                    },
+                    SinkConfig::Webhook {
+                        url: "http://hooks.example.com/anomalies".to_string(),
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_pipelines() {
+        let pipeline = |body: &str| {
+            format!(
+                "pipelines:\n  - name: p\n{}",
+                body.lines()
+                    .map(|l| format!("    {}\n", l))
+                    .collect::<String>()
+            )
+        };
+        let valid = "sensors: [1]\ndetectors: [{ method: zscore }]\nsinks: [{ kind: stdout }]";
+        assert!(parse(&pipeline(valid), &sources()).is_ok());
+
+        for body in [
+            "detectors: [{ method: zscore }]\nsinks: [{ kind: stdout }]",
+            "sources: [missing]\ndetectors: [{ method: zscore }]\nsinks: [{ kind: stdout }]",
+            "sensors: [1]\ndetectors: []\nsinks: [{ kind: stdout }]",
+            "sensors: [1]\ndetectors: [{ method: zscore }]\nsinks: []",
+            "sensors: [1]\ndetectors: [{ method: ewma, alpha: 1.5 }]\nsinks: [{ kind: stdout }]",
+            "sensors: [1]\ndetectors: [{ method: zscore, threshold: -1 }]\nsinks: [{ kind: stdout }]",
+            "sensors: [1]\ndetectors: [{ method: prophet }]\nsinks: [{ kind: stdout }]",
+            "sensors: [1]\ntransforms: [{ kind: resample, interval_secs: 0 }]\n\
+             detectors: [{ method: zscore }]\nsinks: [{ kind: stdout }]",
+            "sensor: [1]\ndetectors: [{ method: zscore }]\nsinks: [{ kind: stdout }]",
+        ] {
+            assert!(parse(&pipeline(body), &sources()).is_err(), "{}", body);
+        }
+        let twice = format!(
+            "{}{}",
+            pipeline(valid),
+            pipeline(valid).replace("pipelines:\n", "")
+        );
+        assert!(parse(&twice, &sources()).is_err());
+    }
+
+    #[test]
+    fn test_resample_aggregates_each_interval() {
+        let batch = vec![
+            sample(1, 1.0, "2026-01-19T10:00:05"),
+            sample(2, 3.0, "2026-01-19T10:00:55"),
+            sample(3, 10.0, "2026-01-19T10:01:10"),
+            sample(4, 99.0, "not a time"),
+        ];
+
+        let mean = resample(batch.clone(), 60, Aggregate::Mean);
+        assert_eq!(
+            mean,
+            [
+                sample(2, 2.0, "2026-01-19T10:00:00"),
+                sample(3, 10.0, "2026-01-19T10:01:00"),
+            ]
+        );
+        let max = resample(batch, 60, Aggregate::Max);
+        assert_eq!(max[0].value, 3.0);
+    }
+
+    #[test]
+    fn test_detrend_removes_a_linear_climb() {
+        let batch: Vec<Sample> = (0..5)
+            .map(|i| sample(i, 10.0 + 2.0 * i as f64, "2026-01-19T10:00:00"))
+            .collect();
+
+        for sample in detrend(batch) {
+            assert!(sample.value.abs() < 1e-9, "{:?}", sample);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_runs_matching_pipelines_into_their_sinks() {
+        let file = std::env::temp_dir().join(format!("collector-{}-pipeline", std::process::id()));
+        let _ = std::fs::remove_file(&file);
+        // A steady climb, with one reading knocked off it.
+        let batch: Vec<Sample> = (1..=30)
+            .map(|i| {
+                let value = i as f64 + if i == 20 { 12.0 } else { 0.0 };
+                sample(i, value, &format!("2026-01-19T10:00:{:02}", i))
+            })
+            .collect();
+        let pipelines = Pipelines::default();
+        *pipelines.current.write().unwrap() = Arc::new(vec![Pipeline {
+            name: "trend".to_string(),
+            sensors: BTreeSet::from([7]),
+            transforms: vec![Transform::Detrend],
+            detectors: vec![DetectorConfig::Zscore { threshold: 3.0 }],
+            sinks: vec![SinkConfig::Stdout, SinkConfig::File { path: file.clone() }],
+        }]);
+
+        let mut out = Vec::new();
+        assert!(pipelines.run(7, &batch, &mut out).await);
+        assert!(!pipelines.run(8, &batch, &mut out).await);
+
+        let out = String::from_utf8(out).unwrap();
+        let written = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_file(&file).unwrap();
+        assert_eq!(out, written);
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1, "{}", out);
+        assert_eq!(lines[0]["pipeline"], "trend");
+        assert_eq!(lines[0]["method"], "zscore");
+        assert_eq!(
+            (&lines[0]["sensor_id"], &lines[0]["id"]),
+            (&7.into(), &20.into())
+        );
+    }
+
+    #[test]
+    fn test_reload_keeps_the_pipelines_when_the_file_is_invalid() {
+        let path =
+            std::env::temp_dir().join(format!("collector-{}-pipelines.yaml", std::process::id()));
+        let valid = "pipelines:\n  - { name: a, sensors: [1], detectors: [{ method: iqr }], sinks: [{ kind: stdout }] }\n";
+        std::fs::write(&path, valid).unwrap();
+        let settings = Settings {
+            pipelines: Some(path.clone()),
+            ..Settings::default()
+        };
+        let pipelines = Pipelines::load(&settings, reqwest::Client::new()).unwrap();
+        assert_eq!(pipelines.len(), 1);
+
+        std::fs::write(&path, format!("{}  - {{ name: b }}\n", valid)).unwrap();
+        assert!(pipelines.reload().is_err());
+        assert_eq!(pipelines.len(), 1);
+
+        std::fs::write(&path, "pipelines: []\n").unwrap();
+        pipelines.reload().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(pipelines.len(), 0);
+    }
+}
@@ -0,0 +1,135 @@
+//! Prometheus sources: a PromQL instant query per poll, `GET
+//! /api/v1/query`, whose one series or scalar is the reading, so series
+//! already scraped need no second pipeline.
+
+use reqwest::Url;
+use serde_json::Value;
+
+/// Evaluates `query` now on the Prometheus at `url`.
+pub async fn query(http: &reqwest::Client, url: &str, query: &str) -> Result<f64, String> {
+    let endpoint = format!("{}/api/v1/query", url.trim_end_matches('/'));
+    let endpoint = Url::parse_with_params(&endpoint, [("query", query)])
+        .map_err(|e| format!("{}: {}", url, e))?;
+    let response = http.get(endpoint).send().await.map_err(|e| e.to_string())?;
+    // Failed queries answer 400, 422 or 503 with the reason in the body.
+    let status = response.status();
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| format!("{} answered {}: {}", url, status, e))?;
+    value(&body)
+}
+
+/// The value of an API response holding a vector of one series or a
+/// scalar.
+fn value(body: &Value) -> Result<f64, String> {
+    if body["status"] != "success" {
+        return Err(format!(
+            "query failed: {}",
+            body["error"].as_str().unwrap_or("no reason given")
+        ));
+    }
+    let data = &body["data"];
+    let sample = match data["resultType"].as_str() {
+        Some("scalar") => &data["result"],
+        Some("vector") => match data["result"].as_array().map(Vec::as_slice) {
+            Some([series]) => &series["value"],
+            Some([]) => return Err("query returned no series".to_string()),
+            Some(series) => {
+                return Err(format!(
+                    "query returned {} series; aggregate them into one, e.g. with sum()",
+                    series.len()
+                ));
+            }
+            None => return Err("vector result is not a list".to_string()),
+        },
+        Some(other) => {
+            return Err(format!(
+                "query returned a {}, expected an instant vector or a scalar",
+                other
+            ));
+        }
+        None => return Err("not a Prometheus query response".to_string()),
+    };
+    // `[unix time, "value"]`, the value a string to carry NaN and Inf.
+    sample[1]
+        .as_str()
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("query returned the sample {}", sample))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::Json;
+    use axum::extract::Query;
+    use axum::http::StatusCode;
+    use axum::routing::get;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_reads_one_series_or_a_scalar() {
+        let result = |kind: &str, result: Value| json!({"status": "success", "data": {"resultType": kind, "result": result}});
+        let vector = |series: Value| result("vector", series);
+        let series = |value: &str| json!({"metric": {"instance": "press6:9100"}, "value": [1768816800.5, value]});
+        assert_eq!(value(&vector(json!([series("0.75")]))), Ok(0.75));
+        let scalar = result("scalar", json!([1768816800, "2"]));
+        assert_eq!(value(&scalar), Ok(2.0));
+        assert!(value(&vector(json!([series("NaN")]))).unwrap().is_nan());
+
+        for (body, expected) in [
+            (vector(json!([])), "no series"),
+            (
+                vector(json!([series("1"), series("2")])),
+                "returned 2 series",
+            ),
+            (result("matrix", json!([])), "returned a matrix"),
+            (
+                json!({"status": "error", "errorType": "bad_data", "error": "parse error"}),
+                "query failed: parse error",
+            ),
+            (vector(json!([{"value": [1, 2]}])), "returned the sample"),
+        ] {
+            let error = value(&body).unwrap_err();
+            assert!(error.contains(expected), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queries_the_http_api() {
+        let app = axum::Router::new().route(
+            "/prometheus/api/v1/query",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                let series = json!([{"metric": {}, "value": [1768816800, "3"]}]);
+                match params["query"].as_str() {
+                    "sum(up)" => (
+                        StatusCode::OK,
+                        Json(json!({
+                            "status": "success",
+                            "data": {"resultType": "vector", "result": series},
+                        })),
+                    ),
+                    _ => (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({
+                            "status": "error",
+                            "errorType": "bad_data",
+                            "error": "unknown query",
+                        })),
+                    ),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/prometheus/", listener.local_addr().unwrap());
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        let http = reqwest::Client::new();
+        assert_eq!(query(&http, &url, "sum(up)").await, Ok(3.0));
+        let error = query(&http, &url, "up{").await.unwrap_err();
+        assert_eq!(error, "query failed: unknown query");
+    }
+}
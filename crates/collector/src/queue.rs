@@ -0,0 +1,171 @@
+//! The bounded queue of readings from the sources to the sink, holding
+//! `queue_capacity` of them. When the sink falls behind, pollers, OPC UA
+//! subscriptions, the StatsD flush and the UDP listener wait for room, so
+//! they stop reading rather than buffer; HTTP writes, whose clients retry,
+//! are refused whole with 429 and `Retry-After` instead of held in memory.
+//! With a write-ahead log, see `wal`, a write is logged before it is queued.
+
+use std::fmt;
+
+use axum::http::{HeaderName, StatusCode, header};
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::scheduler::{Health, Sample};
+use crate::wal::Wal;
+
+/// Seconds a write refused for a full queue is told to wait.
+pub const RETRY_AFTER_SECS: u64 = 1;
+
+/// Why a write was refused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shed {
+    /// The queue has no room for the write now.
+    Full,
+    /// The write has more readings than the queue holds at all.
+    TooLarge { readings: usize, capacity: usize },
+    /// The write-ahead log could not take the write.
+    Unlogged,
+}
+
+impl Shed {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Shed::Full => StatusCode::TOO_MANY_REQUESTS,
+            Shed::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Shed::Unlogged => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    /// The `Retry-After` header of a refusal worth retrying as it is.
+    pub fn retry_after(&self) -> Option<[(HeaderName, String); 1]> {
+        (!matches!(self, Shed::TooLarge { .. }))
+            .then(|| [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())])
+    }
+}
+
+impl fmt::Display for Shed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shed::Full => write!(f, "the queue to the sink is full, retry later"),
+            Shed::TooLarge { readings, capacity } => write!(
+                f,
+                "{} readings in one write, more than the queue holds ({}); send smaller batches",
+                readings, capacity
+            ),
+            Shed::Unlogged => write!(f, "the write-ahead log is failing, retry later"),
+        }
+    }
+}
+
+/// Queues all of `samples` if there is room for them now, and none of them
+/// otherwise, logging them in `wal` first. Once the sink is gone they are
+/// dropped, as waiting sends drop theirs.
+pub fn offer(
+    queue: &mpsc::Sender<Sample>,
+    wal: Option<&Wal>,
+    mut samples: Vec<Sample>,
+) -> Result<(), Shed> {
+    if samples.len() > queue.max_capacity() {
+        return Err(Shed::TooLarge {
+            readings: samples.len(),
+            capacity: queue.max_capacity(),
+        });
+    }
+    match queue.try_reserve_many(samples.len()) {
+        Ok(permits) => {
+            if let Some(wal) = wal
+                && let Err(e) = wal.append(&mut samples)
+            {
+                eprintln!("Error: write-ahead log: {}", e);
+                return Err(Shed::Unlogged);
+            }
+            for (permit, sample) in permits.zip(samples) {
+                permit.send(sample);
+            }
+            Ok(())
+        }
+        Err(TrySendError::Full(())) => Err(Shed::Full),
+        Err(TrySendError::Closed(())) => Ok(()),
+    }
+}
+
+/// Counts `readings` refused by the listener `name` in its health.
+pub fn record(health: &Health, name: &str, readings: usize, shed: Shed) {
+    let mut health = health.lock().unwrap();
+    let entry = health.get_mut(name).expect("the listener has health");
+    entry.shed += readings as u64;
+    entry.last_error = Some(shed.to_string());
+}
+
+/// How full the queue is, read without keeping it open.
+#[derive(Clone)]
+pub struct Depth(mpsc::WeakSender<Sample>);
+
+impl Depth {
+    pub fn new(queue: &mpsc::Sender<Sample>) -> Self {
+        Self(queue.downgrade())
+    }
+
+    /// The readings waiting and the queue's capacity; `None` once every
+    /// source has stopped.
+    pub fn get(&self) -> Option<(usize, usize)> {
+        let queue = self.0.upgrade()?;
+        Some((
+            queue.max_capacity() - queue.capacity(),
+            queue.max_capacity(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(count: i64) -> Vec<Sample> {
+        (1..=count)
+            .map(|id| Sample {
+                sensor_id: 1,
+                id,
+                value: id as f64,
+                timestamp: "2026-01-19T10:00:00".to_string(),
+                lsn: None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_offers_whole_writes_or_sheds_them() {
+        let (sender, mut receiver) = mpsc::channel(4);
+        let depth = Depth::new(&sender);
+
+        assert_eq!(offer(&sender, None, samples(3)), Ok(()));
+        assert_eq!(depth.get(), Some((3, 4)));
+        // Room for one of the two, so neither is queued.
+        assert_eq!(offer(&sender, None, samples(2)), Err(Shed::Full));
+        assert_eq!(depth.get(), Some((3, 4)));
+        assert_eq!(
+            offer(&sender, None, samples(5)),
+            Err(Shed::TooLarge {
+                readings: 5,
+                capacity: 4
+            })
+        );
+        assert_eq!(Shed::Full.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(
+            Shed::TooLarge {
+                readings: 5,
+                capacity: 4
+            }
+            .retry_after()
+            .is_none()
+        );
+
+        assert_eq!(receiver.recv().await.unwrap().id, 1);
+        assert_eq!(receiver.recv().await.unwrap().id, 2);
+        assert_eq!(offer(&sender, None, samples(2)), Ok(()));
+        assert_eq!(depth.get(), Some((3, 4)));
+
+        drop(sender);
+        assert_eq!(depth.get(), None);
+    }
+}
@@ -0,0 +1,355 @@
+//! Prometheus remote_write 1.0: snappy-compressed `WriteRequest` protobufs
+//! on `POST /api/v1/write` of the health listener, whose series become
+//! readings by the `remote_write.rules`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use prost::Message;
+use tokio::sync::mpsc;
+
+use crate::config::{RemoteWriteRule, RemoteWriteSettings};
+use crate::queue::{self, Shed};
+use crate::scheduler::{Health, Sample, Status, format_timestamp};
+use crate::wal::Wal;
+
+/// The health entry of the receiver.
+const NAME: &str = "remote_write";
+
+/// Largest body taken once decompressed.
+const MAX_BODY: usize = 32 * 1024 * 1024;
+
+// The messages of `prometheus/prompb`, with only the fields read here;
+// metadata, exemplars and histograms are skipped.
+
+#[derive(Clone, PartialEq, Message)]
+struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    timeseries: Vec<TimeSeries>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    samples: Vec<Point>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Label {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(string, tag = "2")]
+    value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+struct Point {
+    #[prost(double, tag = "1")]
+    value: f64,
+    /// Milliseconds since the epoch.
+    #[prost(int64, tag = "2")]
+    timestamp: i64,
+}
+
+impl TimeSeries {
+    fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|label| label.name == name)
+            .map(|label| label.value.as_str())
+    }
+}
+
+/// Sends the samples of the series written to it on to the sink.
+pub struct Receiver {
+    rules: Vec<RemoteWriteRule>,
+    samples: mpsc::Sender<Sample>,
+    health: Health,
+    /// The last reading id of each sensor.
+    ids: Mutex<HashMap<i64, i64>>,
+    wal: Option<Arc<Wal>>,
+}
+
+impl Receiver {
+    pub fn new(
+        settings: &RemoteWriteSettings,
+        samples: mpsc::Sender<Sample>,
+        health: Health,
+    ) -> Self {
+        Self {
+            rules: settings.rules.clone(),
+            samples,
+            health,
+            ids: Mutex::default(),
+            wal: None,
+        }
+    }
+
+    /// Logs what is written in `wal` before it is queued, numbering the
+    /// readings of each sensor on from those the log holds.
+    pub fn with_wal(mut self, wal: Arc<Wal>) -> Self {
+        self.ids = Mutex::new(wal.last_ids().clone());
+        self.wal = Some(wal);
+        self
+    }
+
+    /// Queues the samples of the series of `request` that a rule maps to a
+    /// sensor, all of them or, when the queue has no room for them now,
+    /// none. Stale markers, which are NaN, and samples before the epoch are
+    /// skipped.
+    fn write(&self, request: WriteRequest) -> Result<(), Shed> {
+        let mut samples = Vec::new();
+        for series in &request.timeseries {
+            let Some(sensor_id) = self.sensor(series) else {
+                continue;
+            };
+            for point in &series.samples {
+                if !point.value.is_finite() || point.timestamp < 0 {
+                    continue;
+                }
+                let time = UNIX_EPOCH + Duration::from_millis(point.timestamp as u64);
+                samples.push(Sample {
+                    sensor_id,
+                    id: self.next_id(sensor_id),
+                    value: point.value,
+                    timestamp: format_timestamp(time),
+                    lsn: None,
+                });
+            }
+        }
+
+        let readings = samples.len();
+        if let Err(shed) = queue::offer(&self.samples, self.wal.as_deref(), samples) {
+            queue::record(&self.health, NAME, readings, shed);
+            return Err(shed);
+        }
+        if readings > 0 {
+            let mut health = self.health.lock().unwrap();
+            let entry = health.get_mut(NAME).expect("the receiver has health");
+            entry.status = Status::Ok;
+            entry.readings += readings as u64;
+            entry.last_success = Some(format_timestamp(SystemTime::now()));
+        }
+        Ok(())
+    }
+
+    /// The sensor of the first rule that gives one for `series`.
+    fn sensor(&self, series: &TimeSeries) -> Option<i64> {
+        let metric = series.label("__name__")?;
+        self.rules
+            .iter()
+            .filter(|rule| {
+                rule.metric == metric
+                    && rule
+                        .labels
+                        .iter()
+                        .all(|(name, value)| series.label(name) == Some(value.as_str()))
+            })
+            .find_map(|rule| match &rule.sensor_id_label {
+                Some(label) => series.label(label)?.parse().ok(),
+                None => rule.sensor_id,
+            })
+    }
+
+    fn next_id(&self, sensor_id: i64) -> i64 {
+        let mut ids = self.ids.lock().unwrap();
+        let id = ids.entry(sensor_id).or_insert(0);
+        *id += 1;
+        *id
+    }
+
+    fn failed(&self, error: &str) {
+        let mut health = self.health.lock().unwrap();
+        let entry = health.get_mut(NAME).expect("the receiver has health");
+        entry.last_error = Some(error.to_string());
+    }
+}
+
+pub fn routes(receiver: Arc<Receiver>) -> Router {
+    Router::new()
+        .route("/api/v1/write", post(write))
+        .with_state(receiver)
+}
+
+/// Answers 204 once the samples are queued for the sink, and logged when
+/// there is a write-ahead log, and 429 with `Retry-After` when the queue
+/// cannot take them, or 503 when the log fails; Prometheus retries a
+/// request answered 429 or 5xx and drops one answered with another 4xx.
+async fn write(State(receiver): State<Arc<Receiver>>, headers: HeaderMap, body: Bytes) -> Response {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type.contains("io.prometheus.write.v2.Request") {
+        let error = "remote_write 2.0 is not supported, send 1.0 (prometheus.WriteRequest)";
+        receiver.failed(error);
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, error).into_response();
+    }
+    match decode(&body) {
+        Ok(request) => match receiver.write(request) {
+            Ok(()) => StatusCode::NO_CONTENT.into_response(),
+            Err(shed) => (shed.status(), shed.retry_after(), shed.to_string()).into_response(),
+        },
+        Err(error) => {
+            receiver.failed(&error);
+            (StatusCode::BAD_REQUEST, error).into_response()
+        }
+    }
+}
+
+fn decode(body: &[u8]) -> Result<WriteRequest, String> {
+    // snap's errors start with "snappy: ".
+    let length = snap::raw::decompress_len(body).map_err(|e| e.to_string())?;
+    if length > MAX_BODY {
+        return Err(format!("body over {} bytes once decompressed", MAX_BODY));
+    }
+    let bytes = snap::raw::Decoder::new()
+        .decompress_vec(body)
+        .map_err(|e| e.to_string())?;
+    WriteRequest::decode(bytes.as_slice()).map_err(|e| format!("protobuf body: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::config::Settings;
+
+    fn series(labels: &[(&str, &str)], samples: &[(f64, i64)]) -> TimeSeries {
+        TimeSeries {
+            labels: labels
+                .iter()
+                .map(|&(name, value)| Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect(),
+            samples: samples
+                .iter()
+                .map(|&(value, timestamp)| Point { value, timestamp })
+                .collect(),
+        }
+    }
+
+    fn compress(request: &WriteRequest) -> Vec<u8> {
+        snap::raw::Encoder::new()
+            .compress_vec(&request.encode_to_vec())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_series_become_readings_of_the_matching_rules() {
+        let rule = |metric: &str, labels: &[(&str, &str)]| RemoteWriteRule {
+            metric: metric.to_string(),
+            labels: labels
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            sensor_id: None,
+            sensor_id_label: None,
+        };
+        let settings = Settings {
+            remote_write: Some(RemoteWriteSettings {
+                rules: vec![
+                    RemoteWriteRule {
+                        sensor_id: Some(25),
+                        ..rule("temp_celsius", &[("instance", "press6:9100")])
+                    },
+                    RemoteWriteRule {
+                        sensor_id_label: Some("sensor".to_string()),
+                        ..rule("plant_sensor_value", &[])
+                    },
+                ],
+            }),
+            ..Settings::default()
+        };
+        let health = crate::scheduler::health(&settings);
+        let (sender, mut receiver) = mpsc::channel(16);
+        let remote_write = Receiver::new(
+            settings.remote_write.as_ref().unwrap(),
+            sender,
+            health.clone(),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        let app = routes(Arc::new(remote_write));
+        tokio::spawn(axum::serve(listener, app).into_future());
+
+        // A stale marker, as Prometheus sends when a series disappears.
+        let stale = f64::from_bits(0x7ff0_0000_0000_0002);
+        let request = WriteRequest {
+            timeseries: vec![
+                series(
+                    &[("__name__", "temp_celsius"), ("instance", "press6:9100")],
+                    &[(41.5, 1_768_816_800_000), (stale, 1_768_816_815_000)],
+                ),
+                series(
+                    &[("__name__", "temp_celsius"), ("instance", "press7:9100")],
+                    &[(39.0, 1_768_816_800_000)],
+                ),
+                series(
+                    &[("__name__", "plant_sensor_value"), ("sensor", "31")],
+                    &[(7.0, 1_768_816_801_000), (8.0, 1_768_816_802_000)],
+                ),
+                series(&[("sensor", "31")], &[(9.0, 1_768_816_803_000)]),
+            ],
+        };
+        let http = reqwest::Client::new();
+        let response = http
+            .post(&url)
+            .header(header::CONTENT_ENCODING, "snappy")
+            .header(header::CONTENT_TYPE, "application/x-protobuf")
+            .header("X-Prometheus-Remote-Write-Version", "0.1.0")
+            .body(compress(&request))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::NO_CONTENT);
+        let mut readings = Vec::new();
+        for _ in 0..3 {
+            let sample = receiver.recv().await.unwrap();
+            readings.push((sample.sensor_id, sample.id, sample.value, sample.timestamp));
+        }
+        assert_eq!(
+            readings,
+            [
+                (25, 1, 41.5, "2026-01-19T10:00:00".to_string()),
+                (31, 1, 7.0, "2026-01-19T10:00:01".to_string()),
+                (31, 2, 8.0, "2026-01-19T10:00:02".to_string()),
+            ]
+        );
+
+        let response = http.post(&url).body("not snappy").send().await.unwrap();
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        let response = http
+            .post(&url)
+            .header(
+                header::CONTENT_TYPE,
+                "application/x-protobuf;proto=io.prometheus.write.v2.Request",
+            )
+            .body(compress(&request))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(
+            response.status(),
+            reqwest::StatusCode::UNSUPPORTED_MEDIA_TYPE
+        );
+
+        let health = health.lock().unwrap();
+        let entry = &health[NAME];
+        assert_eq!((entry.status, entry.readings), (Status::Ok, 3));
+        assert!(entry.last_error.as_ref().unwrap().contains("2.0"));
+    }
+}
@@ -0,0 +1,350 @@
+//! Polls each source on its interval in a task of its own, backing off while
+//! it fails, and keeps the health of every source for `GET /health`.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use detection_core::calendar::{civil_from_days, days_from_civil};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::{Settings, SourceConfig};
+use crate::{snmp, source};
+
+/// A value read from a source, on its way to the sink.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub sensor_id: i64,
+    /// Counts the source's readings since the collector started.
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    /// Position in the write-ahead log of a reading written over HTTP, see
+    /// `wal`.
+    pub lsn: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Not polled yet.
+    Pending,
+    Ok,
+    /// The last poll failed; the source is retried with backoff.
+    Failing,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SourceHealth {
+    /// The sensors the source reports as.
+    pub sensors: Vec<i64>,
+    pub status: Status,
+    pub consecutive_failures: u32,
+    pub readings: u64,
+    /// Readings written to a listener and refused while the queue was full.
+    pub shed: u64,
+    pub last_success: Option<String>,
+    pub last_error: Option<String>,
+}
+
+/// Health of every source, by name.
+pub type Health = Arc<Mutex<BTreeMap<String, SourceHealth>>>;
+
+/// The health of every source and OPC UA server of `settings`, and of the
+/// line protocol, StatsD and remote_write listeners as `influx`, `statsd` and
+/// `remote_write`, none polled yet.
+pub fn health(settings: &Settings) -> Health {
+    let pending = |sensors| SourceHealth {
+        sensors,
+        status: Status::Pending,
+        consecutive_failures: 0,
+        readings: 0,
+        shed: 0,
+        last_success: None,
+        last_error: None,
+    };
+    let sources = settings
+        .sources
+        .iter()
+        .map(|source| (source.name.clone(), pending(vec![source.sensor_id])));
+    let servers = settings.opcua.iter().map(|server| {
+        let sensors = server.items.iter().map(|item| item.sensor_id).collect();
+        (server.name.clone(), pending(sensors))
+    });
+    let influx = settings.influx.iter().map(|influx| {
+        let sensors = influx
+            .rules
+            .iter()
+            .filter_map(|rule| rule.sensor_id)
+            .collect();
+        ("influx".to_string(), pending(sensors))
+    });
+    let statsd = settings.statsd.iter().map(|statsd| {
+        let sensors = statsd.rules.iter().map(|rule| rule.sensor_id).collect();
+        ("statsd".to_string(), pending(sensors))
+    });
+    let remote_write = settings.remote_write.iter().map(|remote_write| {
+        let sensors = remote_write
+            .rules
+            .iter()
+            .filter_map(|rule| rule.sensor_id)
+            .collect();
+        ("remote_write".to_string(), pending(sensors))
+    });
+    let listeners = influx.chain(statsd).chain(remote_write);
+    Arc::new(Mutex::new(
+        sources.chain(servers).chain(listeners).collect(),
+    ))
+}
+
+/// Delays between retries of a failing source.
+#[derive(Clone, Copy, Debug)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+}
+
+impl Backoff {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            initial: Duration::from_secs_f64(settings.initial_backoff_secs),
+            max: Duration::from_secs_f64(settings.max_backoff_secs),
+        }
+    }
+
+    /// The wait after the `failures`-th failure in a row: `initial`, doubled
+    /// for each failure before it, up to `max`.
+    pub fn delay(&self, failures: u32) -> Duration {
+        let factor = 2f64.powi(failures.saturating_sub(1).min(30) as i32);
+        self.initial.mul_f64(factor).min(self.max)
+    }
+}
+
+/// Starts a poller for each source of `settings`, sending what they read to
+/// `samples` and keeping their entries of `health` current.
+pub fn start(
+    settings: &Settings,
+    http: reqwest::Client,
+    samples: mpsc::Sender<Sample>,
+    health: &Health,
+) -> Vec<JoinHandle<()>> {
+    let backoff = Backoff::new(settings);
+    settings
+        .sources
+        .iter()
+        .map(|source| {
+            tokio::spawn(run(
+                source.clone(),
+                backoff,
+                http.clone(),
+                samples.clone(),
+                health.clone(),
+            ))
+        })
+        .collect()
+}
+
+async fn run(
+    source: SourceConfig,
+    backoff: Backoff,
+    http: reqwest::Client,
+    samples: mpsc::Sender<Sample>,
+    health: Health,
+) {
+    let interval = Duration::from_secs_f64(source.interval_secs);
+    let timeout = Duration::from_secs_f64(source.timeout_secs);
+    let mut next_id = 1;
+    let mut counter = snmp::Counter::default();
+    loop {
+        let poll = source::poll(&source.kind, &http, &mut counter);
+        let polled = match tokio::time::timeout(timeout, poll).await {
+            Ok(polled) => polled,
+            Err(_) => Err(format!("timed out after {:?}", timeout)),
+        };
+        let now = format_timestamp(SystemTime::now());
+        let delay = {
+            let mut health = health.lock().unwrap();
+            let entry = health
+                .get_mut(&source.name)
+                .expect("every source has health");
+            match &polled {
+                Ok(value) => {
+                    entry.status = Status::Ok;
+                    entry.consecutive_failures = 0;
+                    entry.readings += u64::from(value.is_some());
+                    entry.last_success = Some(now.clone());
+                    interval
+                }
+                Err(error) => {
+                    entry.status = Status::Failing;
+                    entry.consecutive_failures += 1;
+                    entry.last_error = Some(error.clone());
+                    backoff.delay(entry.consecutive_failures)
+                }
+            }
+        };
+        match polled {
+            Ok(None) => {}
+            Ok(Some(value)) => {
+                let sample = Sample {
+                    sensor_id: source.sensor_id,
+                    id: next_id,
+                    value,
+                    timestamp: now,
+                    lsn: None,
+                };
+                next_id += 1;
+                if samples.send(sample).await.is_err() {
+                    return;
+                }
+            }
+            Err(error) => eprintln!(
+                "Warning: {}: {}; retrying in {:?}",
+                source.name, error, delay
+            ),
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Formats `time` as `YYYY-MM-DDTHH:MM:SS` in UTC, the format the service
+/// accepts.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (days, secs) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    )
+}
+
+/// Seconds since the epoch of a `YYYY-MM-DDTHH:MM:SS` timestamp in UTC,
+/// as [`format_timestamp`] writes them; anything after the seconds is
+/// ignored.
+pub fn parse_timestamp(timestamp: &str) -> Option<i64> {
+    let field = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = timestamp.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let separators = timestamp.as_bytes();
+    if separators.len() < 19
+        || separators[4] != b'-'
+        || separators[7] != b'-'
+        || !matches!(separators[10], b'T' | b' ')
+        || separators[13] != b':'
+        || separators[16] != b':'
+    {
+        return None;
+    }
+    let (year, month, day) = (field(0..4)?, field(5..7)?, field(8..10)?);
+    let (hour, minute, second) = (field(11..13)?, field(14..16)?, field(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceKind;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let backoff = Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(10),
+        };
+        let delays: Vec<u64> = (1..=6).map(|n| backoff.delay(n).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 10, 10]);
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_formats_utc_timestamps() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_768_816_800);
+        assert_eq!(format_timestamp(time), "2026-01-19T10:00:00");
+        assert_eq!(format_timestamp(UNIX_EPOCH), "1970-01-01T00:00:00");
+    }
+
+    #[test]
+    fn test_parses_what_it_formats() {
+        for seconds in [0, 951_782_400, 1_768_816_800, 4_102_444_799] {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+            assert_eq!(
+                parse_timestamp(&format_timestamp(time)),
+                Some(seconds as i64)
+            );
+        }
+        assert_eq!(
+            parse_timestamp("2026-01-19 10:00:00.25"),
+            Some(1_768_816_800)
+        );
+        assert_eq!(parse_timestamp("2026-13-19T10:00:00"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+
+    #[tokio::test]
+    async fn test_pollers_report_readings_and_failures() {
+        let path = std::env::temp_dir().join(format!("collector-{}-poller", std::process::id()));
+        std::fs::write(&path, "21.5").unwrap();
+        let source = |name: &str, sensor_id, path| SourceConfig {
+            name: name.to_string(),
+            sensor_id,
+            interval_secs: 0.01,
+            timeout_secs: 1.0,
+            kind: SourceKind::File { path },
+        };
+        let settings = Settings {
+            initial_backoff_secs: 0.01,
+            max_backoff_secs: 0.02,
+            sources: vec![
+                source("present", 1, path.clone()),
+                source("missing", 2, path.with_extension("missing")),
+            ],
+            ..Settings::default()
+        };
+        let (sender, mut receiver) = mpsc::channel(16);
+        let health = health(&settings);
+        let tasks = start(&settings, reqwest::Client::new(), sender, &health);
+
+        for id in 1..=3 {
+            let sample = receiver.recv().await.unwrap();
+            assert_eq!((sample.sensor_id, sample.id, sample.value), (1, id, 21.5));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        {
+            let health = health.lock().unwrap();
+            assert_eq!(health["present"].status, Status::Ok);
+            assert!(health["present"].last_success.is_some());
+            let missing = &health["missing"];
+            assert_eq!((missing.status, missing.readings), (Status::Failing, 0));
+            assert!(missing.consecutive_failures >= 2);
+            assert!(missing.last_error.is_some());
+        }
+        for task in tasks {
+            task.abort();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,416 @@
+//! Batches the samples of each sensor and hands them to the detector, over
+//! HTTP or in process, writing the anomalies found as NDJSON. Sensors in a
+//! pipeline go through its flow instead, see `pipeline`. Logged readings
+//! leave the write-ahead log once their batch is sent, at the next flush.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anomaly_client::{AnalyzeRequest, Client, Reading};
+use detection_core::stats::{Reservoir, ZScorer};
+use detection_core::{Outlier, Severity, SeverityBands, zscore_outliers};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::config::{Settings, SinkKind};
+use crate::pipeline::Pipelines;
+use crate::scheduler::Sample;
+use crate::wal::Wal;
+
+/// The service's `default_threshold`, used by the local sink when none is
+/// configured.
+const DEFAULT_THRESHOLD: f64 = 2.0;
+
+/// An anomalous reading, as written to stdout.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Found {
+    pub sensor_id: i64,
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    pub z_score: f64,
+    pub severity: Severity,
+}
+
+pub enum Detector {
+    Http {
+        client: Client,
+        threshold: Option<f64>,
+    },
+    Local {
+        threshold: f64,
+        bands: SeverityBands,
+        /// Scores batches against each sensor's history instead of the
+        /// batch itself, when `reservoir_size` is set.
+        baselines: Option<Baselines>,
+    },
+}
+
+/// A reservoir sample of each sensor's readings so far.
+pub struct Baselines {
+    size: usize,
+    sensors: Mutex<HashMap<i64, Reservoir>>,
+}
+
+impl Baselines {
+    pub fn new(size: usize) -> Self {
+        Self {
+            size,
+            sensors: Mutex::default(),
+        }
+    }
+
+    /// Z-score outliers of a batch of `sensor_id` against the sensor's
+    /// sample of earlier batches, or the batch itself for its first; the
+    /// batch then joins the sample.
+    fn outliers(
+        &self,
+        sensor_id: i64,
+        readings: &[(i64, f64)],
+        threshold: f64,
+        bands: &SeverityBands,
+    ) -> Vec<Outlier> {
+        let mut sensors = self.sensors.lock().unwrap();
+        let reservoir = sensors
+            .entry(sensor_id)
+            .or_insert_with(|| Reservoir::new(self.size, sensor_id as u64));
+        if reservoir.seen() < 2 {
+            readings
+                .iter()
+                .for_each(|&(_, value)| reservoir.push(value));
+            return zscore_outliers(readings, threshold, bands);
+        }
+        let scorer = ZScorer::new(&reservoir.stats(), threshold);
+        let outliers = readings
+            .iter()
+            .filter_map(|&(reading_id, value)| {
+                let score = scorer.score(value)?;
+                Some(Outlier {
+                    reading_id,
+                    value,
+                    score,
+                    severity: bands.classify(score.abs()),
+                })
+            })
+            .collect();
+        readings
+            .iter()
+            .for_each(|&(_, value)| reservoir.push(value));
+        outliers
+    }
+}
+
+impl Detector {
+    pub fn new(settings: &Settings) -> Result<Self, String> {
+        Ok(match settings.sink {
+            SinkKind::Http => Detector::Http {
+                client: Client::new(&settings.url).map_err(|e| e.to_string())?,
+                threshold: settings.threshold,
+            },
+            SinkKind::Local => Detector::Local {
+                threshold: settings.threshold.unwrap_or(DEFAULT_THRESHOLD),
+                bands: settings.bands(),
+                baselines: settings.reservoir_size.map(Baselines::new),
+            },
+        })
+    }
+
+    async fn detect(&self, sensor_id: i64, batch: &[Sample]) -> Result<Vec<Found>, String> {
+        let timestamps: BTreeMap<i64, &str> = batch
+            .iter()
+            .map(|sample| (sample.id, sample.timestamp.as_str()))
+            .collect();
+        let found = |id: i64, value: f64, z_score: f64, severity: Severity| Found {
+            sensor_id,
+            id,
+            value,
+            timestamp: timestamps.get(&id).copied().unwrap_or_default().to_string(),
+            z_score,
+            severity,
+        };
+        match self {
+            Detector::Http { client, threshold } => {
+                let request = AnalyzeRequest {
+                    sensor_id: Some(sensor_id),
+                    readings: batch
+                        .iter()
+                        .map(|sample| Reading {
+                            id: sample.id,
+                            value: sample.value,
+                            timestamp: sample.timestamp.clone(),
+                        })
+                        .collect(),
+                    threshold: *threshold,
+                    ..AnalyzeRequest::default()
+                };
+                let response = client.analyze(&request).await.map_err(|e| e.to_string())?;
+                Ok(response
+                    .anomalies
+                    .into_iter()
+                    .map(|a| found(a.id, a.value, a.z_score, a.severity))
+                    .collect())
+            }
+            Detector::Local {
+                threshold,
+                bands,
+                baselines,
+            } => {
+                let readings: Vec<(i64, f64)> = batch.iter().map(|s| (s.id, s.value)).collect();
+                let outliers = match baselines {
+                    Some(baselines) => baselines.outliers(sensor_id, &readings, *threshold, bands),
+                    None => zscore_outliers(&readings, *threshold, bands),
+                };
+                Ok(outliers
+                    .into_iter()
+                    .map(|o| found(o.reading_id, o.value, o.score, o.severity))
+                    .collect())
+            }
+        }
+    }
+}
+
+/// Sends a sensor's samples once `batch_size` are pending, and every
+/// sensor's pending samples each `flush_interval` and when `samples`
+/// closes. A batch the detector fails on is reported and dropped.
+pub async fn run(
+    mut samples: mpsc::Receiver<Sample>,
+    detector: Detector,
+    pipelines: &Pipelines,
+    wal: Option<&Wal>,
+    batch_size: usize,
+    flush_interval: Duration,
+    out: &mut impl Write,
+) {
+    let mut pending: BTreeMap<i64, Vec<Sample>> = BTreeMap::new();
+    let mut ticks = tokio::time::interval(flush_interval);
+    ticks.tick().await;
+    loop {
+        tokio::select! {
+            sample = samples.recv() => {
+                let Some(sample) = sample else { break };
+                let sensor_id = sample.sensor_id;
+                let batch = pending.entry(sensor_id).or_default();
+                batch.push(sample);
+                if batch.len() >= batch_size {
+                    let batch = std::mem::take(batch);
+                    send(&detector, pipelines, wal, sensor_id, &batch, out).await;
+                }
+            }
+            _ = ticks.tick() => {
+                // Sensors with nothing since the last tick are dropped, so
+                // only those active within an interval are held.
+                pending.retain(|_, batch| !batch.is_empty());
+                for (sensor_id, batch) in &mut pending {
+                    if !batch.is_empty() {
+                        let batch = std::mem::take(batch);
+                        send(&detector, pipelines, wal, *sensor_id, &batch, out).await;
+                    }
+                }
+                checkpoint(wal);
+            }
+        }
+    }
+    for (sensor_id, batch) in pending {
+        if !batch.is_empty() {
+            send(&detector, pipelines, wal, sensor_id, &batch, out).await;
+        }
+    }
+    checkpoint(wal);
+}
+
+fn checkpoint(wal: Option<&Wal>) {
+    if let Some(wal) = wal
+        && let Err(e) = wal.checkpoint()
+    {
+        eprintln!("Warning: write-ahead log checkpoint: {}", e);
+    }
+}
+
+async fn send(
+    detector: &Detector,
+    pipelines: &Pipelines,
+    wal: Option<&Wal>,
+    sensor_id: i64,
+    batch: &[Sample],
+    out: &mut impl Write,
+) {
+    if !pipelines.run(sensor_id, batch, out).await {
+        detect(detector, sensor_id, batch, out).await;
+    }
+    if let Some(wal) = wal {
+        wal.sent(batch);
+    }
+}
+
+async fn detect(detector: &Detector, sensor_id: i64, batch: &[Sample], out: &mut impl Write) {
+    match detector.detect(sensor_id, batch).await {
+        Ok(found) => {
+            for anomaly in found {
+                let line = serde_json::to_string(&anomaly).expect("anomalies serialize");
+                if let Err(e) = writeln!(out, "{}", line).and_then(|()| out.flush()) {
+                    eprintln!("Error: writing anomalies: {}", e);
+                }
+            }
+        }
+        Err(error) => eprintln!(
+            "Warning: dropping {} readings of sensor {}: {}",
+            batch.len(),
+            sensor_id,
+            error
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sensor_id: i64, id: i64, value: f64) -> Sample {
+        Sample {
+            sensor_id,
+            id,
+            value,
+            timestamp: format!("2026-01-19T10:00:{:02}", id),
+            lsn: None,
+        }
+    }
+
+    fn local() -> Detector {
+        Detector::Local {
+            threshold: DEFAULT_THRESHOLD,
+            bands: SeverityBands::default(),
+            baselines: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reservoir_baselines_score_against_earlier_batches() {
+        let detector = Detector::Local {
+            threshold: DEFAULT_THRESHOLD,
+            bands: SeverityBands::default(),
+            baselines: Some(Baselines::new(64)),
+        };
+        let batch = |start: i64, values: &[f64]| -> Vec<Sample> {
+            (start..)
+                .zip(values)
+                .map(|(id, &value)| sample(5, id, value))
+                .collect()
+        };
+
+        let history: Vec<f64> = (0..40).map(|i| 10.0 + (i % 3) as f64).collect();
+        assert!(
+            detector
+                .detect(5, &batch(1, &history))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        // A batch all at the new level has no outliers of its own, but is
+        // far from the sensor's history.
+        let found = detector.detect(5, &batch(41, &[30.0; 5])).await.unwrap();
+        assert_eq!(found.len(), 5);
+        assert!(found.iter().all(|f| f.severity == Severity::Critical));
+    }
+
+    #[tokio::test]
+    async fn test_batches_per_sensor_and_reports_anomalies() {
+        let (sender, receiver) = mpsc::channel(64);
+        for id in 1..=20 {
+            let value = if id == 7 {
+                90.0
+            } else {
+                20.0 + (id % 2) as f64
+            };
+            sender.send(sample(1, id, value)).await.unwrap();
+            sender.send(sample(2, id, 5.0)).await.unwrap();
+        }
+        drop(sender);
+        let mut out = Vec::new();
+        run(
+            receiver,
+            local(),
+            &Pipelines::default(),
+            None,
+            20,
+            Duration::from_secs(3600),
+            &mut out,
+        )
+        .await;
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["sensor_id"], 1);
+        assert_eq!(lines[0]["id"], 7);
+        assert_eq!(lines[0]["timestamp"], "2026-01-19T10:00:07");
+        assert_eq!(lines[0]["severity"], "critical");
+    }
+
+    #[tokio::test]
+    async fn test_flushes_partial_batches_on_the_interval() {
+        let (sender, receiver) = mpsc::channel(64);
+        let task = tokio::spawn(async move {
+            let mut out = Vec::new();
+            run(
+                receiver,
+                local(),
+                &Pipelines::default(),
+                None,
+                1_000,
+                Duration::from_millis(20),
+                &mut out,
+            )
+            .await;
+            out
+        });
+        for id in 1..=10 {
+            let value = if id == 3 { -50.0 } else { 1.0 };
+            sender.send(sample(4, id, value)).await.unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        // After the flush, the spike is not in the batch of what follows.
+        for id in 11..=20 {
+            sender.send(sample(4, id, 1.0)).await.unwrap();
+        }
+        drop(sender);
+        let out = String::from_utf8(task.await.unwrap()).unwrap();
+        assert_eq!(out.lines().count(), 1, "{}", out);
+        assert!(out.contains("\"id\":3"), "{}", out);
+    }
+
+    #[tokio::test]
+    async fn test_sent_batches_leave_the_write_ahead_log() {
+        let path = std::env::temp_dir().join(format!("collector-{}-sink-wal", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let (wal, _) = Wal::open(&path).unwrap();
+        let mut logged: Vec<Sample> = (1..=5).map(|id| sample(3, id, 1.0)).collect();
+        wal.append(&mut logged).unwrap();
+
+        let (sender, receiver) = mpsc::channel(64);
+        for sample in logged.into_iter().take(3) {
+            sender.send(sample).await.unwrap();
+        }
+        drop(sender);
+        run(
+            receiver,
+            local(),
+            &Pipelines::default(),
+            Some(&wal),
+            2,
+            Duration::from_secs(3600),
+            &mut Vec::new(),
+        )
+        .await;
+
+        drop(wal);
+        let (_, replay) = Wal::open(&path).unwrap();
+        let ids: Vec<i64> = replay.iter().map(|sample| sample.id).collect();
+        assert_eq!(ids, [4, 5]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+//! SNMP sources, read with snmp2 over v2c or v3: one get of the source's OID
+//! and the agent's sysUpTime per poll, from a socket opened for it.
+
+use snmp2::v3::{Auth, Cipher, Security};
+use snmp2::{AsyncSession, Oid, Value};
+
+use crate::config::{AuthProtocol, PrivacyProtocol, SnmpSource, SnmpVersion};
+
+const DEFAULT_PORT: u16 = 161;
+
+/// sysUpTime.0, in hundredths of a second: the time base of counter rates,
+/// and how a restarted agent shows.
+const SYS_UP_TIME: &str = "1.3.6.1.2.1.1.3.0";
+
+/// The previous reading of a counter source, which the next one's rate is
+/// taken from.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counter {
+    last: Option<Count>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Count {
+    value: u64,
+    /// Counter64 rather than Counter32.
+    wide: bool,
+    uptime: u32,
+}
+
+impl Counter {
+    /// The per-second rate from the previous count to `count`; none for the
+    /// first count and after the agent restarted, when the counter starts
+    /// over. A counter below its previous value has wrapped.
+    fn rate(&mut self, count: Count) -> Option<f64> {
+        let last = self.last.replace(count)?;
+        // sysUpTime itself wraps after 497 days; that poll is skipped too.
+        if count.uptime <= last.uptime || count.wide != last.wide {
+            return None;
+        }
+        let delta = if count.wide {
+            count.value.wrapping_sub(last.value)
+        } else {
+            (count.value as u32).wrapping_sub(last.value as u32) as u64
+        };
+        Some(delta as f64 / ((count.uptime - last.uptime) as f64 / 100.0))
+    }
+}
+
+/// A value as the agent typed it.
+enum Reading {
+    Gauge(f64),
+    Counter(u64, bool),
+}
+
+/// Reads the value of `source`, or nothing for a counter's first poll.
+pub async fn read(source: &SnmpSource, counter: &mut Counter) -> Result<Option<f64>, String> {
+    let address = if source.address.contains(':') {
+        source.address.clone()
+    } else {
+        format!("{}:{}", source.address, DEFAULT_PORT)
+    };
+    let (reading, uptime) = get(source, &address)
+        .await
+        .map_err(|e| format!("{}: {}", address, e))?;
+    let value = match reading {
+        Reading::Gauge(value) => Some(value),
+        Reading::Counter(value, wide) => counter.rate(Count {
+            value,
+            wide,
+            uptime,
+        }),
+    };
+    Ok(value.map(|value| value * source.scale))
+}
+
+async fn get(source: &SnmpSource, address: &str) -> Result<(Reading, u32), String> {
+    let oid: Oid = source
+        .oid
+        .parse()
+        .map_err(|_| format!("invalid oid {:?}", source.oid))?;
+    let uptime_oid: Oid = SYS_UP_TIME.parse().expect("a valid OID");
+    let mut session = match source.version {
+        SnmpVersion::V2c => AsyncSession::new_v2c(address, source.community.as_bytes(), 1).await,
+        SnmpVersion::V3 => AsyncSession::new_v3(address, 1, security(source)).await,
+    }
+    .map_err(|e| e.to_string())?;
+    session.init().await.map_err(|e| e.to_string())?;
+    let mut retried = false;
+    loop {
+        let response = match session.get_many(&[&oid, &uptime_oid]).await {
+            Ok(response) => response,
+            // A v3 agent re-synchronized its engine time; ask again.
+            Err(snmp2::Error::AuthUpdated) if !retried => {
+                retried = true;
+                continue;
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+        if response.error_status != 0 {
+            return Err(format!(
+                "agent answered with error status {} for varbind {}",
+                response.error_status, response.error_index
+            ));
+        }
+        let mut varbinds = response.varbinds;
+        let reading = match varbinds.next() {
+            Some((_, value)) => reading(value).map_err(|e| format!("{} {}", source.oid, e))?,
+            None => return Err("empty response".to_string()),
+        };
+        let uptime = match varbinds.next() {
+            Some((_, Value::Timeticks(ticks))) => ticks,
+            _ => return Err("no sysUpTime in the response".to_string()),
+        };
+        return Ok((reading, uptime));
+    }
+}
+
+fn reading(value: Value) -> Result<Reading, String> {
+    match value {
+        Value::Integer(n) => Ok(Reading::Gauge(n as f64)),
+        Value::Unsigned32(n) | Value::Timeticks(n) => Ok(Reading::Gauge(n as f64)),
+        Value::Counter32(n) => Ok(Reading::Counter(n as u64, false)),
+        Value::Counter64(n) => Ok(Reading::Counter(n, true)),
+        // Some agents report temperatures as text, e.g. "41.5".
+        Value::OctetString(text) => std::str::from_utf8(text)
+            .ok()
+            .and_then(|text| text.trim().parse().ok())
+            .map(Reading::Gauge)
+            .ok_or_else(|| format!("holds {:?}, not a number", String::from_utf8_lossy(text))),
+        Value::NoSuchObject | Value::NoSuchInstance => {
+            Err("does not exist on the agent".to_string())
+        }
+        other => Err(format!("holds {:?}, not a number", other)),
+    }
+}
+
+fn security(source: &SnmpSource) -> Security {
+    let username = source.username.as_deref().unwrap_or_default();
+    let auth_password = source.auth_password.as_deref().unwrap_or_default();
+    let auth = match (&source.auth_password, &source.privacy_password) {
+        (None, _) => Auth::NoAuthNoPriv,
+        (Some(_), None) => Auth::AuthNoPriv,
+        (Some(_), Some(privacy_password)) => Auth::AuthPriv {
+            cipher: match source.privacy_protocol {
+                PrivacyProtocol::Des => Cipher::Des,
+                PrivacyProtocol::Aes128 => Cipher::Aes128,
+                PrivacyProtocol::Aes192 => Cipher::Aes192,
+                PrivacyProtocol::Aes256 => Cipher::Aes256,
+            },
+            privacy_password: privacy_password.as_bytes().to_vec(),
+        },
+    };
+    Security::new(username.as_bytes(), auth_password.as_bytes())
+        .with_auth(auth)
+        .with_auth_protocol(match source.auth_protocol {
+            AuthProtocol::Md5 => snmp2::v3::AuthProtocol::Md5,
+            AuthProtocol::Sha1 => snmp2::v3::AuthProtocol::Sha1,
+            AuthProtocol::Sha224 => snmp2::v3::AuthProtocol::Sha224,
+            AuthProtocol::Sha256 => snmp2::v3::AuthProtocol::Sha256,
+            AuthProtocol::Sha384 => snmp2::v3::AuthProtocol::Sha384,
+            AuthProtocol::Sha512 => snmp2::v3::AuthProtocol::Sha512,
+        })
+}
+
+/// The objects of a fake agent: each OID with its BER-encoded value.
+#[cfg(test)]
+pub type Objects = std::sync::Arc<std::sync::Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// An SNMP v2c agent on a local UDP port, answering gets of `objects`:
+/// sysUpTime comes from `uptime`, anything else is noSuchObject. For tests.
+#[cfg(test)]
+pub async fn fake_agent(
+    objects: Objects,
+    uptime: std::sync::Arc<std::sync::atomic::AtomicU32>,
+) -> String {
+    use std::sync::atomic::Ordering;
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        match content.len() {
+            len @ 0..0x80 => out.push(len as u8),
+            len @ 0x80..0x100 => out.extend_from_slice(&[0x81, len as u8]),
+            len => out.extend_from_slice(&[0x82, (len >> 8) as u8, len as u8]),
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap().to_string();
+    tokio::spawn(async move {
+        let mut buffer = [0; 2_048];
+        while let Ok((len, peer)) = socket.recv_from(&mut buffer).await {
+            let request = snmp2::Pdu::from_bytes(&buffer[..len]).unwrap();
+            let mut varbinds = Vec::new();
+            for (oid, _) in request.varbinds.clone() {
+                let name = oid.to_id_string();
+                let value = if name == SYS_UP_TIME {
+                    tlv(0x43, &uptime.load(Ordering::SeqCst).to_be_bytes())
+                } else {
+                    let objects = objects.lock().unwrap();
+                    match objects.iter().find(|(object, _)| *object == name) {
+                        Some((_, value)) => value.clone(),
+                        None => vec![0x80, 0],
+                    }
+                };
+                let mut varbind = tlv(0x06, oid.as_bytes());
+                varbind.extend_from_slice(&value);
+                varbinds.extend_from_slice(&tlv(0x30, &varbind));
+            }
+            let mut pdu = tlv(0x02, &request.req_id.to_be_bytes());
+            pdu.extend_from_slice(&[0x02, 1, 0, 0x02, 1, 0]);
+            pdu.extend_from_slice(&tlv(0x30, &varbinds));
+            let mut message = vec![0x02, 1, 1];
+            message.extend_from_slice(&tlv(0x04, request.community));
+            message.extend_from_slice(&tlv(0xA2, &pdu));
+            let _ = socket.send_to(&tlv(0x30, &message), peer).await;
+        }
+    });
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    fn source(address: &str, oid: &str) -> SnmpSource {
+        SnmpSource {
+            address: address.to_string(),
+            oid: oid.to_string(),
+            version: SnmpVersion::V2c,
+            community: "public".to_string(),
+            username: None,
+            auth_protocol: AuthProtocol::Sha1,
+            auth_password: None,
+            privacy_protocol: PrivacyProtocol::Aes128,
+            privacy_password: None,
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_counter_rates_survive_wraps_and_skip_restarts() {
+        let count = |value, wide, uptime| Count {
+            value,
+            wide,
+            uptime,
+        };
+        let mut counter = Counter::default();
+        assert_eq!(counter.rate(count(1_000, false, 100)), None);
+        assert_eq!(counter.rate(count(3_000, false, 300)), Some(1_000.0));
+        // Counter32 wraps at 2^32.
+        assert_eq!(
+            counter.rate(count(500, false, 400)),
+            Some((u32::MAX as u64 - 3_000 + 501) as f64)
+        );
+        // The agent restarted, so the counter did too.
+        assert_eq!(counter.rate(count(200, false, 50)), None);
+        assert_eq!(counter.rate(count(700, false, 550)), Some(100.0));
+
+        let mut wide = Counter::default();
+        assert_eq!(wide.rate(count(u64::MAX - 99, true, 0)), None);
+        assert_eq!(wide.rate(count(100, true, 100)), Some(200.0));
+    }
+
+    #[tokio::test]
+    async fn test_reads_gauges_and_counter_rates() {
+        let objects = Arc::new(Mutex::new(vec![
+            // Gauge32 of 41, an INTEGER of -3, and a text temperature.
+            (
+                "1.3.6.1.4.1.9.9.13.1.3.1.3.1".to_string(),
+                vec![0x42, 1, 41],
+            ),
+            (
+                "1.3.6.1.4.1.2021.13.16.2.1.3.1".to_string(),
+                vec![0x02, 1, 0xFD],
+            ),
+            ("1.3.6.1.4.1.8072.1.1".to_string(), b"\x04\x0441.5".to_vec()),
+            // Counter32 ifInOctets, 100 short of wrapping.
+            (
+                "1.3.6.1.2.1.2.2.1.10.3".to_string(),
+                vec![0x41, 5, 0, 0xFF, 0xFF, 0xFF, 0x9C],
+            ),
+        ]));
+        let uptime = Arc::new(AtomicU32::new(1_000));
+        let address = fake_agent(objects.clone(), uptime.clone()).await;
+        let mut counter = Counter::default();
+        let mut poll = async |oid: &str| read(&source(&address, oid), &mut counter).await;
+
+        assert_eq!(poll("1.3.6.1.4.1.9.9.13.1.3.1.3.1").await, Ok(Some(41.0)));
+        assert_eq!(poll("1.3.6.1.4.1.2021.13.16.2.1.3.1").await, Ok(Some(-3.0)));
+        assert_eq!(poll("1.3.6.1.4.1.8072.1.1").await, Ok(Some(41.5)));
+        let missing = poll("1.3.6.1.4.1.8072.9").await.unwrap_err();
+        assert!(
+            missing.ends_with("does not exist on the agent"),
+            "{}",
+            missing
+        );
+
+        assert_eq!(poll("1.3.6.1.2.1.2.2.1.10.3").await, Ok(None));
+        objects.lock().unwrap()[3].1 = vec![0x41, 2, 0x01, 0x2C];
+        uptime.store(1_200, Ordering::SeqCst);
+        // 400 octets in 2 s, across the wrap.
+        assert_eq!(poll("1.3.6.1.2.1.2.2.1.10.3").await, Ok(Some(200.0)));
+
+        let mut bits = source(&address, "1.3.6.1.2.1.2.2.1.10.3");
+        bits.scale = 8.0;
+        objects.lock().unwrap()[3].1 = vec![0x41, 2, 0x01, 0x90];
+        uptime.store(1_300, Ordering::SeqCst);
+        assert_eq!(read(&bits, &mut counter).await, Ok(Some(800.0)));
+    }
+}
@@ -0,0 +1,111 @@
+//! Reading the current value of a configured source.
+
+use serde_json::Value;
+
+use crate::config::SourceKind;
+use crate::{modbus, prometheus, snmp};
+
+/// Polls `source` once for its current value; nothing when a counter has
+/// no rate yet. `counter` is the source's own, kept between polls.
+pub async fn poll(
+    source: &SourceKind,
+    http: &reqwest::Client,
+    counter: &mut snmp::Counter,
+) -> Result<Option<f64>, String> {
+    let value = match source {
+        SourceKind::Http { url, pointer } => {
+            let response = http.get(url).send().await.map_err(|e| e.to_string())?;
+            let status = response.status();
+            if !status.is_success() {
+                return Err(format!("{} answered {}", url, status));
+            }
+            let document: Value = response.json().await.map_err(|e| e.to_string())?;
+            let value = document
+                .pointer(pointer)
+                .ok_or_else(|| format!("{} has nothing at {:?}", url, pointer))?;
+            number(value).ok_or_else(|| format!("{} holds {} at {:?}", url, value, pointer))?
+        }
+        SourceKind::Modbus(source) => modbus::read(source).await?,
+        SourceKind::File { path } => {
+            let text = tokio::fs::read_to_string(path)
+                .await
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+            first_number(&text)
+                .ok_or_else(|| format!("{}: no number in the file", path.display()))?
+        }
+        SourceKind::Snmp(source) => match snmp::read(source, counter).await? {
+            Some(value) => value,
+            None => return Ok(None),
+        },
+        SourceKind::Prometheus { url, query } => prometheus::query(http, url, query).await?,
+    };
+    if value.is_finite() {
+        Ok(Some(value))
+    } else {
+        Err(format!("read {}, not a finite number", value))
+    }
+}
+
+/// A JSON number, or a string holding one as some gateways send.
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn first_number(text: &str) -> Option<f64> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+        .find_map(|word| word.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ModbusSource, Parity, RegisterTable, RegisterType};
+
+    #[tokio::test]
+    async fn test_polls_files_and_modbus_devices() {
+        let http = reqwest::Client::new();
+        let mut counter = snmp::Counter::default();
+        let path = std::env::temp_dir().join(format!("collector-{}-source", std::process::id()));
+        std::fs::write(&path, "temp: 41.5 C\n").unwrap();
+        let file = SourceKind::File { path: path.clone() };
+        assert_eq!(poll(&file, &http, &mut counter).await, Ok(Some(41.5)));
+        std::fs::write(&path, "offline\n").unwrap();
+        assert!(
+            poll(&file, &http, &mut counter)
+                .await
+                .unwrap_err()
+                .ends_with("no number in the file")
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(poll(&file, &http, &mut counter).await.is_err());
+
+        let modbus = SourceKind::Modbus(ModbusSource {
+            address: Some(modbus::fake_device(vec![415], vec![]).await),
+            serial: None,
+            baud_rate: 9_600,
+            parity: Parity::None,
+            unit: 1,
+            table: RegisterTable::Holding,
+            register: 0,
+            register_type: RegisterType::I16,
+            scale: 0.1,
+            offset: 0.0,
+        });
+        let value = poll(&modbus, &http, &mut counter).await.unwrap().unwrap();
+        assert!((value - 41.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_json_values_become_numbers() {
+        let document: Value =
+            serde_json::json!({"plc": {"flow": 12.5, "level": "3.25", "ok": true}});
+        assert_eq!(number(document.pointer("/plc/flow").unwrap()), Some(12.5));
+        assert_eq!(number(document.pointer("/plc/level").unwrap()), Some(3.25));
+        assert_eq!(number(document.pointer("/plc/ok").unwrap()), None);
+        assert_eq!(number(&serde_json::json!(7)), Some(7.0));
+    }
+}
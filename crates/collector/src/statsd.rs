@@ -0,0 +1,519 @@
+//! StatsD and Graphite plaintext, in UDP datagrams and over TCP connections:
+//! what the lines of a metric say is summed up by its sensor's rule and sent
+//! as one reading per flush interval.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use detection_core::TDigest;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{TcpListener, UdpSocket};
+use tokio::sync::mpsc;
+
+use crate::config::{Statistic, StatsdRule, StatsdSettings};
+use crate::scheduler::{Health, Sample, Status, format_timestamp};
+
+/// The health entry of the listeners.
+const NAME: &str = "statsd";
+
+/// What a line says about a metric.
+#[derive(Clone, Debug, PartialEq)]
+enum Update<'a> {
+    /// A counter increment, already divided by its sample rate.
+    Count(f64),
+    Gauge(f64),
+    /// A gauge change, from `+n` or `-n`.
+    Adjust(f64),
+    /// A timer, histogram or distribution value.
+    Time(f64),
+    Member(&'a str),
+}
+
+/// A sensor's aggregate since the last flush.
+#[derive(Clone, Debug, PartialEq)]
+enum Aggregate {
+    Counter(f64),
+    /// The gauge outlives the flush, for later `+n` and `-n`, but is only
+    /// reported again once updated.
+    Gauge {
+        value: f64,
+        updated: bool,
+    },
+    /// Timer values in a digest, so a busy timer's memory stays bounded.
+    Timer(TDigest, Statistic),
+    Set(HashSet<String>),
+}
+
+#[derive(Default)]
+struct State {
+    aggregates: HashMap<i64, Aggregate>,
+    /// The last reading id of each sensor.
+    ids: HashMap<i64, i64>,
+}
+
+pub struct Aggregator {
+    rules: Vec<StatsdRule>,
+    health: Health,
+    state: Mutex<State>,
+}
+
+impl Aggregator {
+    pub fn new(settings: &StatsdSettings, health: Health) -> Self {
+        Self {
+            rules: settings.rules.clone(),
+            health,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Adds the lines of `text` to the aggregates of their sensors; metrics
+    /// no rule matches are dropped. A bad line fails after the others are
+    /// added.
+    fn record(&self, text: &str) -> Result<(), String> {
+        let mut error = None;
+        let mut state = self.state.lock().unwrap();
+        for line in text.lines() {
+            let (name, update) = match parse_line(line) {
+                Ok(Some(parsed)) => parsed,
+                Ok(None) => continue,
+                Err(e) => {
+                    error.get_or_insert_with(|| format!("{:?}: {}", line, e));
+                    continue;
+                }
+            };
+            let Some(rule) = self.rules.iter().find(|rule| matches(&rule.metric, name)) else {
+                continue;
+            };
+            let aggregate = state.aggregates.remove(&rule.sensor_id);
+            let aggregate = match (aggregate, update) {
+                (Some(Aggregate::Counter(sum)), Update::Count(count)) => {
+                    Aggregate::Counter(sum + count)
+                }
+                (_, Update::Count(count)) => Aggregate::Counter(count),
+                (Some(Aggregate::Gauge { value, .. }), Update::Adjust(change)) => {
+                    Aggregate::Gauge {
+                        value: value + change,
+                        updated: true,
+                    }
+                }
+                (_, Update::Gauge(value) | Update::Adjust(value)) => Aggregate::Gauge {
+                    value,
+                    updated: true,
+                },
+                (Some(Aggregate::Timer(mut values, statistic)), Update::Time(value)) => {
+                    values.push(value);
+                    Aggregate::Timer(values, statistic)
+                }
+                (_, Update::Time(value)) => {
+                    let mut values = TDigest::default();
+                    values.push(value);
+                    Aggregate::Timer(values, rule.statistic)
+                }
+                (Some(Aggregate::Set(mut members)), Update::Member(member)) => {
+                    members.insert(member.to_string());
+                    Aggregate::Set(members)
+                }
+                (_, Update::Member(member)) => Aggregate::Set(HashSet::from([member.to_string()])),
+            };
+            state.aggregates.insert(rule.sensor_id, aggregate);
+        }
+        drop(state);
+        if let Some(error) = &error {
+            let mut health = self.health.lock().unwrap();
+            let entry = health.get_mut(NAME).expect("the listeners have health");
+            entry.last_error = Some(error.clone());
+        }
+        error.map_or(Ok(()), Err)
+    }
+
+    /// A reading of each sensor updated since the last flush, `interval_secs`
+    /// ago: counters as their rate per second, gauges as their value,
+    /// timers as the rule's statistic and sets as their number of members.
+    fn flush(&self, now: SystemTime, interval_secs: f64) -> Vec<Sample> {
+        let timestamp = format_timestamp(now);
+        let mut state = self.state.lock().unwrap();
+        let mut readings = Vec::new();
+        state.aggregates.retain(|&sensor_id, aggregate| {
+            let value = match aggregate {
+                Aggregate::Counter(sum) => *sum / interval_secs,
+                Aggregate::Gauge { updated: false, .. } => return true,
+                Aggregate::Gauge { value, updated } => {
+                    *updated = false;
+                    readings.push((sensor_id, *value));
+                    return true;
+                }
+                Aggregate::Timer(values, statistic) => summarize(values, *statistic),
+                Aggregate::Set(members) => members.len() as f64,
+            };
+            readings.push((sensor_id, value));
+            false
+        });
+        readings.sort_by_key(|&(sensor_id, _)| sensor_id);
+        let samples: Vec<Sample> = readings
+            .into_iter()
+            .map(|(sensor_id, value)| {
+                let id = state.ids.entry(sensor_id).or_insert(0);
+                *id += 1;
+                Sample {
+                    sensor_id,
+                    id: *id,
+                    value,
+                    timestamp: timestamp.clone(),
+                    lsn: None,
+                }
+            })
+            .collect();
+        drop(state);
+
+        if !samples.is_empty() {
+            let mut health = self.health.lock().unwrap();
+            let entry = health.get_mut(NAME).expect("the listeners have health");
+            entry.status = Status::Ok;
+            entry.readings += samples.len() as u64;
+            entry.last_success = Some(timestamp);
+        }
+        samples
+    }
+}
+
+/// Sends the aggregates of `aggregator` to `samples` `every` so often, until
+/// the task is aborted.
+pub async fn flush(aggregator: Arc<Aggregator>, every: Duration, samples: mpsc::Sender<Sample>) {
+    let mut ticks = tokio::time::interval(every);
+    // The first tick is right away.
+    ticks.tick().await;
+    loop {
+        ticks.tick().await;
+        for sample in aggregator.flush(SystemTime::now(), every.as_secs_f64()) {
+            if samples.send(sample).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// Takes datagrams of lines on `socket` until the task is aborted.
+pub async fn listen_udp(socket: UdpSocket, aggregator: Arc<Aggregator>) {
+    let mut buffer = vec![0; 65_536];
+    loop {
+        let (length, peer) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                eprintln!("Warning: {}: {}", NAME, e);
+                continue;
+            }
+        };
+        let text = String::from_utf8_lossy(&buffer[..length]);
+        if let Err(error) = aggregator.record(&text) {
+            eprintln!("Warning: {}: {} from {}", NAME, error, peer);
+        }
+    }
+}
+
+/// Takes lines over each connection to `listener` until the task is
+/// aborted.
+pub async fn listen_tcp(listener: TcpListener, aggregator: Arc<Aggregator>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("Warning: {}: {}", NAME, e);
+                continue;
+            }
+        };
+        let aggregator = aggregator.clone();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stream).lines();
+            loop {
+                match lines.next_line().await {
+                    Ok(Some(line)) => {
+                        if let Err(error) = aggregator.record(&line) {
+                            eprintln!("Warning: {}: {} from {}", NAME, error, peer);
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Warning: {}: {}: {}", NAME, peer, e);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Parses a StatsD line, `name:value|type[|@rate][|#tags]`, or else a
+/// Graphite one, `path value [timestamp]`, which is a gauge. Blank lines
+/// are `None`.
+fn parse_line(line: &str) -> Result<Option<(&str, Update<'_>)>, String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if let Some((name, rest)) = line.split_once(':')
+        && rest.contains('|')
+    {
+        let mut parts = rest.split('|');
+        let value = parts.next().unwrap_or_default();
+        let kind = parts.next().unwrap_or_default();
+        let mut rate = 1.0;
+        // Tags and other extensions are left out.
+        for part in parts {
+            if let Some(sample_rate) = part.strip_prefix('@') {
+                rate = sample_rate
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|rate| *rate > 0.0 && *rate <= 1.0)
+                    .ok_or_else(|| format!("invalid sample rate {:?}", sample_rate))?;
+            }
+        }
+        if name.is_empty() {
+            return Err("missing metric name".to_string());
+        }
+        let update = match kind {
+            "c" => Update::Count(number(value)? / rate),
+            "g" if value.starts_with(['+', '-']) => Update::Adjust(number(value)?),
+            "g" => Update::Gauge(number(value)?),
+            "ms" | "h" | "d" => Update::Time(number(value)?),
+            "s" => Update::Member(value),
+            other => return Err(format!("unknown metric type {:?}", other)),
+        };
+        return Ok(Some((name, update)));
+    }
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let [path, value, timestamp @ ..] = &words[..] else {
+        return Err("expected name:value|type or path value [timestamp]".to_string());
+    };
+    if timestamp.len() > 1 || timestamp.iter().any(|time| time.parse::<f64>().is_err()) {
+        return Err("expected path value [timestamp]".to_string());
+    }
+    // Graphite tags, `path;tag=value`, are left out.
+    let path = path.split(';').next().unwrap_or_default();
+    Ok(Some((path, Update::Gauge(number(value)?))))
+}
+
+fn number(text: &str) -> Result<f64, String> {
+    text.parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("invalid value {:?}", text))
+}
+
+/// Whether `name` is `pattern`, a `*` matching any one segment.
+fn matches(pattern: &str, name: &str) -> bool {
+    let (mut pattern, mut name) = (pattern.split('.'), name.split('.'));
+    loop {
+        match (pattern.next(), name.next()) {
+            (None, None) => return true,
+            (Some(expected), Some(segment)) if expected == "*" || expected == segment => {}
+            _ => return false,
+        }
+    }
+}
+
+/// The statistic of a timer's values; the median is estimated from the digest,
+/// exactly for small timers.
+fn summarize(values: &TDigest, statistic: Statistic) -> f64 {
+    match statistic {
+        Statistic::Mean => values.mean(),
+        Statistic::Median => values.quantile(0.5).unwrap_or_default(),
+        Statistic::Min => values.min().unwrap_or_default(),
+        Statistic::Max => values.max().unwrap_or_default(),
+        Statistic::Sum => values.sum(),
+        Statistic::Count => values.count() as f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use tokio::io::AsyncWriteExt;
+
+    use super::*;
+    use crate::config::Settings;
+
+    #[test]
+    fn test_parses_statsd_and_graphite_lines() {
+        assert_eq!(
+            parse_line("press6.strokes:3|c|@0.5|#line:a"),
+            Ok(Some(("press6.strokes", Update::Count(6.0))))
+        );
+        assert_eq!(
+            parse_line("oil:-2.5|g"),
+            Ok(Some(("oil", Update::Adjust(-2.5))))
+        );
+        assert_eq!(
+            parse_line("oil:41|g"),
+            Ok(Some(("oil", Update::Gauge(41.0))))
+        );
+        assert_eq!(
+            parse_line("cycle:320|ms"),
+            Ok(Some(("cycle", Update::Time(320.0))))
+        );
+        assert_eq!(
+            parse_line("operators:ann|s"),
+            Ok(Some(("operators", Update::Member("ann"))))
+        );
+        assert_eq!(
+            parse_line("press6.oil;unit=c 41.5 1768816800"),
+            Ok(Some(("press6.oil", Update::Gauge(41.5))))
+        );
+        assert_eq!(
+            parse_line("press6.oil 41.5"),
+            Ok(Some(("press6.oil", Update::Gauge(41.5))))
+        );
+        assert_eq!(parse_line("  "), Ok(None));
+
+        for (line, expected) in [
+            ("oil:1|x", "unknown metric type"),
+            ("oil:abc|c", "invalid value"),
+            ("oil:1|c|@2", "invalid sample rate"),
+            (":1|c", "missing metric name"),
+            ("oil", "expected name:value|type"),
+            ("oil 41.5 soon", "expected path value"),
+            ("oil nan", "invalid value"),
+        ] {
+            let error = parse_line(line).unwrap_err();
+            assert!(error.contains(expected), "{}: {}", line, error);
+        }
+    }
+
+    #[test]
+    fn test_matches_metric_patterns() {
+        assert!(matches("press6.*.cycle", "press6.a.cycle"));
+        assert!(matches("press6.oil", "press6.oil"));
+        assert!(!matches("press6.*.cycle", "press6.cycle"));
+        assert!(!matches("press6.*", "press6.a.cycle"));
+        assert!(!matches("press6.oil", "press6.oil2"));
+    }
+
+    fn aggregator(rules: &[(&str, i64, Statistic)]) -> (Aggregator, Health) {
+        let settings = Settings {
+            statsd: Some(StatsdSettings {
+                udp: None,
+                tcp: None,
+                flush_interval_secs: 10.0,
+                rules: rules
+                    .iter()
+                    .map(|&(metric, sensor_id, statistic)| StatsdRule {
+                        metric: metric.to_string(),
+                        sensor_id,
+                        statistic,
+                    })
+                    .collect(),
+            }),
+            ..Settings::default()
+        };
+        let health = crate::scheduler::health(&settings);
+        let aggregator = Aggregator::new(settings.statsd.as_ref().unwrap(), health.clone());
+        (aggregator, health)
+    }
+
+    #[test]
+    fn test_aggregates_each_sensor_per_flush() {
+        let (aggregator, health) = aggregator(&[
+            ("strokes", 1, Statistic::Mean),
+            ("oil", 2, Statistic::Mean),
+            ("press.*.cycle", 3, Statistic::Median),
+            ("operators", 4, Statistic::Mean),
+        ]);
+        let now = UNIX_EPOCH + Duration::from_secs(1_768_816_800);
+        let values = |samples: Vec<Sample>| -> Vec<(i64, i64, f64)> {
+            samples
+                .into_iter()
+                .map(|sample| (sample.sensor_id, sample.id, sample.value))
+                .collect()
+        };
+
+        aggregator
+            .record(
+                "strokes:10|c\nstrokes:5|c|@0.5\noil:40|g\noil:+2|g\n\
+                 press.a.cycle:300|ms\npress.b.cycle:100|ms\npress.a.cycle:200|ms\n\
+                 operators:ann|s\noperators:bob|s\noperators:ann|s\nunmapped:1|c\n",
+            )
+            .unwrap();
+        let samples = aggregator.flush(now, 10.0);
+        assert_eq!(samples[0].timestamp, "2026-01-19T10:00:00");
+        assert_eq!(
+            values(samples),
+            [(1, 1, 2.0), (2, 1, 42.0), (3, 1, 200.0), (4, 1, 2.0)]
+        );
+
+        // Only an updated gauge is reported again, but it keeps its value.
+        assert_eq!(aggregator.flush(now, 10.0), []);
+        let error = aggregator.record("oil:-1|g\nstrokes:x|c\n").unwrap_err();
+        assert!(
+            error.starts_with("\"strokes:x|c\": invalid value"),
+            "{}",
+            error
+        );
+        assert_eq!(values(aggregator.flush(now, 10.0)), [(2, 2, 41.0)]);
+
+        let health = health.lock().unwrap();
+        assert_eq!(
+            (health[NAME].status, health[NAME].readings),
+            (Status::Ok, 5)
+        );
+        assert_eq!(health[NAME].sensors, [1, 2, 3, 4]);
+        assert_eq!(health[NAME].last_error.as_deref(), Some(error.as_str()));
+    }
+
+    #[test]
+    fn test_summarizes_timers() {
+        let digest = |values: &[f64]| {
+            let mut digest = TDigest::default();
+            values.iter().for_each(|&value| digest.push(value));
+            digest
+        };
+        let values = digest(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!(summarize(&values, Statistic::Mean), 2.5);
+        assert_eq!(summarize(&values, Statistic::Median), 2.5);
+        assert_eq!(summarize(&values, Statistic::Min), 1.0);
+        assert_eq!(summarize(&values, Statistic::Max), 4.0);
+        assert_eq!(summarize(&values, Statistic::Sum), 10.0);
+        assert_eq!(summarize(&values, Statistic::Count), 4.0);
+        assert_eq!(summarize(&digest(&[5.0, 1.0, 3.0]), Statistic::Median), 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_listens_on_udp_and_tcp() {
+        let (aggregator, _) =
+            aggregator(&[("strokes", 1, Statistic::Mean), ("oil", 2, Statistic::Mean)]);
+        let aggregator = Arc::new(aggregator);
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let udp_address = socket.local_addr().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let tcp_address = listener.local_addr().unwrap();
+        let (sender, mut receiver) = mpsc::channel(16);
+        let tasks = [
+            tokio::spawn(listen_udp(socket, aggregator.clone())),
+            tokio::spawn(listen_tcp(listener, aggregator.clone())),
+        ];
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client
+            .send_to(b"strokes:4|c\nstrokes:6|c", udp_address)
+            .await
+            .unwrap();
+        let mut stream = tokio::net::TcpStream::connect(tcp_address).await.unwrap();
+        stream.write_all(b"oil 41.5 1768816800\n").await.unwrap();
+        stream.flush().await.unwrap();
+        // Give both a moment to arrive before the first flush.
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let flusher = tokio::spawn(flush(aggregator, Duration::from_millis(50), sender));
+
+        let mut readings = [
+            receiver.recv().await.unwrap(),
+            receiver.recv().await.unwrap(),
+        ];
+        readings.sort_by_key(|sample| sample.sensor_id);
+        assert_eq!((readings[0].sensor_id, readings[0].value), (1, 200.0));
+        assert_eq!((readings[1].sensor_id, readings[1].value), (2, 41.5));
+        for task in tasks.into_iter().chain([flusher]) {
+            task.abort();
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! OPC UA servers: a session per server with one subscription to its
+//! monitored items, whose data changes become samples.
+//!
+//! The session reconnects and recreates the subscription on its own a few
+//! times; once it gives up, the server's task starts over with a new session
+//! after the same backoff as a failing poller.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures_util::TryStreamExt;
+use opcua::client::{
+    ClientBuilder, DataChangeCallback, IdentityToken, MonitoredItem, Session, SessionPollResult,
+};
+use opcua::types::{
+    DataValue, EndpointDescription, MessageSecurityMode, MonitoredItemCreateRequest,
+    MonitoringMode, MonitoringParameters, NodeId, ReadValueId, TimestampsToReturn, UserTokenPolicy,
+    Variant,
+};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::config::{OpcUaServer, SecurityMode, Settings};
+use crate::scheduler::{Backoff, Health, Sample, Status, format_timestamp};
+
+/// Reconnects the session itself before the task starts over.
+const SESSION_RETRY_LIMIT: i32 = 3;
+
+/// Starts a session with each OPC UA server of `settings`, sending data
+/// changes to `samples` and keeping their entries of `health` current.
+pub fn start(
+    settings: &Settings,
+    samples: mpsc::Sender<Sample>,
+    health: &Health,
+) -> Vec<JoinHandle<()>> {
+    let backoff = Backoff::new(settings);
+    settings
+        .opcua
+        .iter()
+        .map(|server| {
+            tokio::spawn(run(
+                server.clone(),
+                backoff,
+                samples.clone(),
+                health.clone(),
+            ))
+        })
+        .collect()
+}
+
+async fn run(server: OpcUaServer, backoff: Backoff, samples: mpsc::Sender<Sample>, health: Health) {
+    // Reading ids keep counting across sessions.
+    let counters: Arc<Mutex<HashMap<u32, i64>>> = Arc::default();
+    loop {
+        let error = match session(&server, &samples, &health, &counters).await {
+            Ok(()) => "session closed".to_string(),
+            Err(error) => error,
+        };
+        let failures = update(&health, &server.name, |entry| {
+            entry.status = Status::Failing;
+            entry.consecutive_failures += 1;
+            entry.last_error = Some(error.clone());
+            entry.consecutive_failures
+        });
+        let delay = backoff.delay(failures);
+        eprintln!(
+            "Warning: {}: {}; reconnecting in {:?}",
+            server.name, error, delay
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Connects, subscribes and runs the session until it is lost for good.
+async fn session(
+    server: &OpcUaServer,
+    samples: &mpsc::Sender<Sample>,
+    health: &Health,
+    counters: &Arc<Mutex<HashMap<u32, i64>>>,
+) -> Result<(), String> {
+    let mut builder = ClientBuilder::new()
+        .application_name("anomaly collector")
+        .application_uri("urn:codetex:anomaly-collector")
+        .product_uri("urn:codetex:anomaly-collector")
+        .pki_dir(&server.pki_dir)
+        .create_sample_keypair(server.certificate.is_none())
+        .trust_server_certs(server.trust_server_certs)
+        .session_retry_limit(SESSION_RETRY_LIMIT);
+    if let (Some(certificate), Some(private_key)) = (&server.certificate, &server.private_key) {
+        builder = builder
+            .certificate_path(certificate)
+            .private_key_path(private_key);
+    }
+    let mut client = builder
+        .client()
+        .map_err(|errors| format!("invalid client configuration: {}", errors.join("; ")))?;
+
+    let mode = match server.security_mode {
+        SecurityMode::None => MessageSecurityMode::None,
+        SecurityMode::Sign => MessageSecurityMode::Sign,
+        SecurityMode::SignAndEncrypt => MessageSecurityMode::SignAndEncrypt,
+    };
+    let endpoint: EndpointDescription = (
+        server.endpoint.as_str(),
+        server.security_policy.as_str(),
+        mode,
+        UserTokenPolicy::anonymous(),
+    )
+        .into();
+    let identity = match (&server.username, &server.password) {
+        (Some(username), Some(password)) => {
+            IdentityToken::new_user_name(username.clone(), password.clone())
+        }
+        _ => IdentityToken::Anonymous,
+    };
+    let (session, event_loop) = client
+        .connect_to_matching_endpoint(endpoint, identity)
+        .await
+        .map_err(|e| format!("connecting to {}: {}", server.endpoint, e))?;
+
+    // The event loop has to run for the subscription to be created at all.
+    let events = event_loop.enter();
+    let subscribing = subscribe(server, &session, samples, health, counters);
+    tokio::pin!(events, subscribing);
+    let mut subscribed = false;
+    loop {
+        tokio::select! {
+            result = &mut subscribing, if !subscribed => {
+                result?;
+                subscribed = true;
+                update(health, &server.name, |entry| {
+                    entry.status = Status::Ok;
+                    entry.consecutive_failures = 0;
+                });
+                eprintln!(
+                    "{}: subscribed to {} items at {}",
+                    server.name,
+                    server.items.len(),
+                    server.endpoint
+                );
+            }
+            event = events.try_next() => match event {
+                Ok(Some(SessionPollResult::ConnectionLost(status))) => {
+                    update(health, &server.name, |entry| {
+                        entry.status = Status::Failing;
+                        entry.last_error = Some(format!("disconnected ({}), reconnecting", status));
+                    });
+                }
+                Ok(Some(SessionPollResult::Reconnected(_))) if subscribed => {
+                    update(health, &server.name, |entry| {
+                        entry.status = Status::Ok;
+                        entry.consecutive_failures = 0;
+                    });
+                }
+                Ok(Some(_)) => {}
+                Ok(None) => return Ok(()),
+                Err(status) => return Err(format!("connection lost: {}", status)),
+            }
+        }
+    }
+}
+
+/// Creates the subscription and its monitored items once `session` is
+/// connected.
+async fn subscribe(
+    server: &OpcUaServer,
+    session: &Session,
+    samples: &mpsc::Sender<Sample>,
+    health: &Health,
+    counters: &Arc<Mutex<HashMap<u32, i64>>>,
+) -> Result<(), String> {
+    if !session.wait_for_connection().await {
+        return Err(format!("could not connect to {}", server.endpoint));
+    }
+
+    // Client handles, from 1, identify the items in data changes.
+    let sensors: HashMap<u32, i64> = (1..)
+        .zip(&server.items)
+        .map(|(handle, item)| (handle, item.sensor_id))
+        .collect();
+    let (name, health) = (server.name.clone(), health.clone());
+    let (samples, counters) = (samples.clone(), counters.clone());
+    let callback = DataChangeCallback::new(move |value: DataValue, item: &MonitoredItem| {
+        let Some(&sensor_id) = sensors.get(&item.client_handle()) else {
+            return;
+        };
+        let Some(reading) = reading_value(&value) else {
+            return;
+        };
+        let id = {
+            let mut counters = counters.lock().unwrap();
+            let counter = counters.entry(item.client_handle()).or_insert(0);
+            *counter += 1;
+            *counter
+        };
+        let sample = Sample {
+            sensor_id,
+            id,
+            value: reading,
+            timestamp: reading_timestamp(&value),
+            lsn: None,
+        };
+        update(&health, &name, |entry| {
+            entry.readings += 1;
+            entry.last_success = Some(format_timestamp(SystemTime::now()));
+        });
+        // The callback runs inside the session's event loop, which must not
+        // block; a full queue means the sink is far behind.
+        if samples.try_send(sample).is_err() {
+            eprintln!("Warning: {}: sink full, dropping a reading", name);
+        }
+    });
+    let publishing_interval = Duration::from_secs_f64(server.publishing_interval_ms / 1_000.0);
+    let subscription = session
+        .create_subscription(publishing_interval, 10, 30, 0, 0, true, callback)
+        .await
+        .map_err(|e| format!("creating the subscription: {}", e))?;
+
+    let requests: Vec<MonitoredItemCreateRequest> = (1..)
+        .zip(&server.items)
+        .map(|(handle, item)| {
+            let node_id: NodeId = item.node_id.parse().expect("validated node id");
+            MonitoredItemCreateRequest::new(
+                ReadValueId::from(node_id),
+                MonitoringMode::Reporting,
+                MonitoringParameters {
+                    client_handle: handle,
+                    sampling_interval: item
+                        .sampling_interval_ms
+                        .unwrap_or(server.publishing_interval_ms),
+                    ..MonitoringParameters::default()
+                },
+            )
+        })
+        .collect();
+    let results = session
+        .create_monitored_items(subscription, TimestampsToReturn::Both, requests)
+        .await
+        .map_err(|e| format!("creating monitored items: {}", e))?;
+    for (item, result) in server.items.iter().zip(&results) {
+        if result.result.status_code.is_bad() {
+            eprintln!(
+                "Warning: {}: cannot monitor {}: {}",
+                server.name, item.node_id, result.result.status_code
+            );
+        }
+    }
+    Ok(())
+}
+
+fn update<T>(
+    health: &Health,
+    name: &str,
+    change: impl FnOnce(&mut crate::scheduler::SourceHealth) -> T,
+) -> T {
+    let mut health = health.lock().unwrap();
+    change(health.get_mut(name).expect("every server has health"))
+}
+
+/// The number a data change carries: numeric values as they are, booleans
+/// as 0 or 1. Bad values and anything else are skipped.
+fn reading_value(value: &DataValue) -> Option<f64> {
+    if value.status.is_some_and(|status| status.is_bad()) {
+        return None;
+    }
+    match value.value.as_ref()? {
+        Variant::Boolean(on) => Some(if *on { 1.0 } else { 0.0 }),
+        variant => variant.as_f64().filter(|value| value.is_finite()),
+    }
+}
+
+/// When the server saw the value, else now.
+fn reading_timestamp(value: &DataValue) -> String {
+    let time = value
+        .source_timestamp
+        .or(value.server_timestamp)
+        .map(|timestamp| timestamp.as_chrono().timestamp())
+        .filter(|&seconds| seconds > 0)
+        .map_or_else(SystemTime::now, |seconds| {
+            UNIX_EPOCH + Duration::from_secs(seconds as u64)
+        });
+    format_timestamp(time)
+}
+
+#[cfg(test)]
+mod tests {
+    use opcua::types::{DateTime, StatusCode};
+
+    use super::*;
+
+    #[test]
+    fn test_data_changes_become_readings() {
+        let data = |value: Variant| DataValue::new_now(value);
+        assert_eq!(reading_value(&data(Variant::Double(41.5))), Some(41.5));
+        assert_eq!(reading_value(&data(Variant::Int16(-3))), Some(-3.0));
+        assert_eq!(reading_value(&data(Variant::Boolean(true))), Some(1.0));
+        assert_eq!(reading_value(&data(Variant::from("41.5"))), None);
+        assert_eq!(reading_value(&data(Variant::Double(f64::NAN))), None);
+        assert_eq!(reading_value(&DataValue::null()), None);
+
+        let mut bad = data(Variant::Double(41.5));
+        bad.status = Some(StatusCode::BadSensorFailure);
+        assert_eq!(reading_value(&bad), None);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_subscribes_to_a_server() {
+        use opcua::server::ServerBuilder;
+        use opcua::server::address_space::VariableBuilder;
+        use opcua::server::diagnostics::NamespaceMetadata;
+        use opcua::server::node_manager::memory::{SimpleNodeManager, simple_node_manager};
+        use opcua::types::{DataTypeId, ObjectId};
+
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let endpoint = format!("opc.tcp://127.0.0.1:{}/", port);
+        let pki = std::env::temp_dir().join(format!("collector-{}-pki", std::process::id()));
+        let namespace_uri = "urn:collector-test";
+        let (server, handle) = ServerBuilder::new_anonymous("collector test")
+            .host("127.0.0.1")
+            .port(port)
+            .pki_dir(pki.join("server"))
+            .create_sample_keypair(true)
+            .trust_client_certs(true)
+            .with_node_manager(simple_node_manager(
+                NamespaceMetadata {
+                    namespace_uri: namespace_uri.to_string(),
+                    ..Default::default()
+                },
+                "collector test",
+            ))
+            .build()
+            .unwrap();
+        let manager = handle
+            .node_managers()
+            .get_of_type::<SimpleNodeManager>()
+            .unwrap();
+        let namespace = handle.get_namespace_index(namespace_uri).unwrap();
+        let oil = NodeId::new(namespace, "Oil");
+        {
+            let mut address_space = manager.address_space().write();
+            VariableBuilder::new(&oil, "Oil", "Oil")
+                .data_type(DataTypeId::Double)
+                .value(41.5)
+                .organized_by(ObjectId::ObjectsFolder)
+                .insert(&mut *address_space);
+        }
+        tokio::spawn(server.run());
+
+        let settings = Settings {
+            opcua: vec![OpcUaServer {
+                name: "plc".to_string(),
+                endpoint,
+                security_policy: "None".to_string(),
+                security_mode: SecurityMode::None,
+                username: None,
+                password: None,
+                pki_dir: pki.join("client"),
+                certificate: None,
+                private_key: None,
+                trust_server_certs: true,
+                publishing_interval_ms: 50.0,
+                items: vec![crate::config::MonitoredItem {
+                    node_id: oil.to_string(),
+                    sensor_id: 21,
+                    sampling_interval_ms: Some(20.0),
+                }],
+            }],
+            ..Settings::default()
+        };
+        let health = crate::scheduler::health(&settings);
+        let (sender, mut receiver) = mpsc::channel(16);
+        let subscriptions = start(&settings, sender, &health);
+
+        let wait = Duration::from_secs(30);
+        let first = tokio::time::timeout(wait, receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((first.sensor_id, first.id, first.value), (21, 1, 41.5));
+        manager
+            .set_value(
+                handle.subscriptions(),
+                &oil,
+                None,
+                DataValue::new_now(47.25),
+            )
+            .unwrap();
+        let second = tokio::time::timeout(wait, receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((second.id, second.value), (2, 47.25));
+        {
+            let health = health.lock().unwrap();
+            assert_eq!(health["plc"].status, Status::Ok);
+            assert!(health["plc"].readings >= 2);
+        }
+
+        for subscription in subscriptions {
+            subscription.abort();
+        }
+        handle.cancel();
+        std::fs::remove_dir_all(pki).unwrap();
+    }
+
+    #[test]
+    fn test_readings_carry_the_source_timestamp() {
+        let mut value = DataValue::new_now(Variant::Double(1.0));
+        value.source_timestamp = Some(DateTime::ymd_hms(2026, 1, 19, 10, 0, 0));
+        assert_eq!(reading_timestamp(&value), "2026-01-19T10:00:00");
+        value.source_timestamp = None;
+        value.server_timestamp = None;
+        assert_eq!(reading_timestamp(&value).len(), "2026-01-19T10:00:00".len());
+    }
+}
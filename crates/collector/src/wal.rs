@@ -0,0 +1,239 @@
+//! The write-ahead log of readings written over HTTP, at `wal_path`. A
+//! write's readings are appended and synced to disk before it is answered
+//! 204, and each leaves the log once the sink has sent its batch; those
+//! still in the log at startup, which a crash kept from the sink, are
+//! queued again. Readings are sent at least once: a crash between a send
+//! and the next checkpoint sends them twice.
+//!
+//! The log is a file of JSON lines, rewritten at each checkpoint with only
+//! the readings still to send.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::scheduler::Sample;
+
+/// A reading as logged.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+struct Record {
+    /// The reading's position in the log.
+    lsn: u64,
+    sensor_id: i64,
+    id: i64,
+    value: f64,
+    timestamp: String,
+}
+
+impl Record {
+    fn sample(&self) -> Sample {
+        Sample {
+            sensor_id: self.sensor_id,
+            id: self.id,
+            value: self.value,
+            timestamp: self.timestamp.clone(),
+            lsn: Some(self.lsn),
+        }
+    }
+}
+
+struct Log {
+    file: File,
+    next: u64,
+    /// The readings not yet sent, by position.
+    pending: BTreeMap<u64, Record>,
+    /// Lines in the file of readings sent since the last checkpoint.
+    sent: usize,
+}
+
+pub struct Wal {
+    path: PathBuf,
+    log: Mutex<Log>,
+    /// The last reading id of each sensor in the log at startup.
+    last_ids: HashMap<i64, i64>,
+}
+
+impl Wal {
+    /// Opens the log at `path`, creating it if need be, with the readings
+    /// it holds to queue again. A line cut short by a crash is skipped.
+    pub fn open(path: &Path) -> Result<(Self, Vec<Sample>), String> {
+        let mut pending = BTreeMap::new();
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+                    match serde_json::from_str::<Record>(&line) {
+                        Ok(record) => {
+                            pending.insert(record.lsn, record);
+                        }
+                        Err(e) => eprintln!(
+                            "Warning: {}: skipping a damaged line: {}",
+                            path.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        }
+
+        let mut last_ids = HashMap::new();
+        for record in pending.values() {
+            let id = last_ids.entry(record.sensor_id).or_insert(record.id);
+            *id = (*id).max(record.id);
+        }
+        let replay = pending.values().map(Record::sample).collect();
+        let log = Log {
+            file: rewrite(path, pending.values())?,
+            next: pending.last_key_value().map_or(0, |(lsn, _)| lsn + 1),
+            pending,
+            sent: 0,
+        };
+        Ok((
+            Self {
+                path: path.to_path_buf(),
+                log: Mutex::new(log),
+                last_ids,
+            },
+            replay,
+        ))
+    }
+
+    pub fn last_ids(&self) -> &HashMap<i64, i64> {
+        &self.last_ids
+    }
+
+    /// Logs `samples`, giving each its position, and syncs the log.
+    pub fn append(&self, samples: &mut [Sample]) -> Result<(), String> {
+        let mut log = self.log.lock().unwrap();
+        let mut lines = Vec::new();
+        let mut records = Vec::with_capacity(samples.len());
+        for (lsn, sample) in (log.next..).zip(samples.iter_mut()) {
+            let record = Record {
+                lsn,
+                sensor_id: sample.sensor_id,
+                id: sample.id,
+                value: sample.value,
+                timestamp: sample.timestamp.clone(),
+            };
+            serde_json::to_writer(&mut lines, &record).expect("records serialize");
+            lines.push(b'\n');
+            sample.lsn = Some(lsn);
+            records.push(record);
+        }
+        log.next += records.len() as u64;
+        let written = log
+            .file
+            .write_all(&lines)
+            .and_then(|()| log.file.sync_data());
+        if let Err(e) = written {
+            // The next checkpoint rewrites whatever part did land.
+            log.sent += records.len();
+            return Err(format!("{}: {}", self.path.display(), e));
+        }
+        log.pending
+            .extend(records.into_iter().map(|record| (record.lsn, record)));
+        Ok(())
+    }
+
+    /// Notes the logged readings of `samples` sent.
+    pub fn sent(&self, samples: &[Sample]) {
+        let mut log = self.log.lock().unwrap();
+        for lsn in samples.iter().filter_map(|sample| sample.lsn) {
+            if log.pending.remove(&lsn).is_some() {
+                log.sent += 1;
+            }
+        }
+    }
+
+    /// Rewrites the log without the readings sent since the last
+    /// checkpoint.
+    pub fn checkpoint(&self) -> Result<(), String> {
+        let mut log = self.log.lock().unwrap();
+        if log.sent == 0 {
+            return Ok(());
+        }
+        log.file = rewrite(&self.path, log.pending.values())?;
+        log.sent = 0;
+        Ok(())
+    }
+}
+
+/// Replaces the file at `path` with `records`, atomically, and opens it to
+/// append.
+fn rewrite<'a>(path: &Path, records: impl Iterator<Item = &'a Record>) -> Result<File, String> {
+    let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+    let mut staged = path.as_os_str().to_owned();
+    staged.push(".tmp");
+    let staged = PathBuf::from(staged);
+    let mut file = File::create(&staged).map_err(error)?;
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, record).expect("records serialize");
+        lines.push(b'\n');
+    }
+    file.write_all(&lines)
+        .and_then(|()| file.sync_all())
+        .map_err(error)?;
+    fs::rename(&staged, path).map_err(error)?;
+    OpenOptions::new().append(true).open(path).map_err(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(sensor_id: i64, ids: std::ops::RangeInclusive<i64>) -> Vec<Sample> {
+        ids.map(|id| Sample {
+            sensor_id,
+            id,
+            value: id as f64,
+            timestamp: "2026-01-19T10:00:00".to_string(),
+            lsn: None,
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_replays_what_was_not_sent() {
+        let path = std::env::temp_dir().join(format!("collector-{}-wal", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let (wal, replay) = Wal::open(&path).unwrap();
+        assert!(replay.is_empty());
+        let mut first = samples(1, 1..=3);
+        let mut second = samples(2, 1..=2);
+        wal.append(&mut first).unwrap();
+        wal.append(&mut second).unwrap();
+        assert_eq!(second[1].lsn, Some(4));
+        wal.sent(&first[..2]);
+        wal.checkpoint().unwrap();
+        wal.sent(&second);
+        // A crash before the next checkpoint, with the end of a line lost.
+        drop(wal);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"lsn":5,"sensor_id":2,"#).unwrap();
+
+        let (wal, replay) = Wal::open(&path).unwrap();
+        let ids: Vec<(i64, i64)> = replay.iter().map(|s| (s.sensor_id, s.id)).collect();
+        assert_eq!(ids, [(1, 3), (2, 1), (2, 2)]);
+        assert_eq!(wal.last_ids()[&2], 2);
+        // New readings are logged after the replayed ones.
+        let mut third = samples(1, 4..=4);
+        wal.append(&mut third).unwrap();
+        assert_eq!(third[0].lsn, Some(5));
+
+        wal.sent(&replay);
+        wal.sent(&third);
+        wal.checkpoint().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        let (_, replay) = Wal::open(&path).unwrap();
+        assert!(replay.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
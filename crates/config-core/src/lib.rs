@@ -0,0 +1,391 @@
+//! Layered settings shared by `anomaly-detector`, `detector-cli` and
+//! `sensor-sim`, so all of them read one settings file format and resolve
+//! it the same way.
+//!
+//! A binary's settings are one section of a TOML file (`[service]`, `[cli]`,
+//! `[simulator]`), resolved from, lowest precedence first:
+//!
+//! 1. the defaults of the settings type
+//! 2. the file, from `--config` or [`CONFIG_ENV`]
+//! 3. environment variables `<PREFIX><KEY>`, e.g. `ANOMALY_DATABASE_URL` for
+//!    `database_url`; `__` separates nested keys
+//! 4. command-line flags
+//!
+//! The result is checked with [`Validate`]. A key in the file that the
+//! section does not have is an error, so a typo does not pass silently;
+//! environment variables that share the prefix but name no setting are
+//! ignored.
+//!
+//! ```toml
+//! [service]
+//! database_url = "sqlite://data/factory.db"
+//! retention_readings_days = 30
+//!
+//! [cli]
+//! zscore = 3.0
+//!
+//! [simulator]
+//! sensors = 10
+//! sink = "http"
+//! ```
+
+use std::path::{Path, PathBuf};
+
+use figment::providers::{Format, Serialized, Toml};
+use figment::value::{Dict, Map, Value};
+use figment::{Figment, Metadata, Profile, Provider, Source};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Environment variable naming the settings file when `--config` is not
+/// given.
+pub const CONFIG_ENV: &str = "ANOMALY_CONFIG";
+
+/// The settings file to read: the `--config` flag, else [`CONFIG_ENV`].
+pub fn config_file(flag: Option<&Path>) -> Option<PathBuf> {
+    flag.map(Path::to_path_buf).or_else(|| {
+        std::env::var_os(CONFIG_ENV)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+    })
+}
+
+/// Checks of the merged settings that types alone cannot express.
+pub trait Validate {
+    fn validate(&self) -> Result<(), String>;
+}
+
+/// Collects the layers of one section and resolves them with [`load`].
+///
+/// [`load`]: Loader::load
+pub struct Loader {
+    section: &'static str,
+    file: Option<PathBuf>,
+    prefix: String,
+    tables: Vec<&'static str>,
+    vars: Vec<(String, String)>,
+    overrides: Option<Value>,
+}
+
+impl Loader {
+    pub fn new(section: &'static str) -> Self {
+        Self {
+            section,
+            file: None,
+            prefix: String::new(),
+            tables: Vec::new(),
+            vars: Vec::new(),
+            overrides: None,
+        }
+    }
+
+    /// Reads the section from the TOML file at `path`, which must exist.
+    pub fn file(mut self, path: Option<PathBuf>) -> Self {
+        self.file = path;
+        self
+    }
+
+    /// Reads the process environment variables starting with `prefix`.
+    pub fn env(self, prefix: &str) -> Self {
+        self.vars(prefix, std::env::vars())
+    }
+
+    /// Reads `vars` as environment variables starting with `prefix`.
+    pub fn vars(mut self, prefix: &str, vars: impl IntoIterator<Item = (String, String)>) -> Self {
+        self.prefix = prefix.to_string();
+        self.vars = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(prefix) && name.len() > prefix.len())
+            .collect();
+        self
+    }
+
+    /// Nests `<PREFIX><TABLE>_<KEY>` variables under `table`, for tables whose
+    /// keys are not known in advance, e.g. `ANOMALY_EXPORT_AWS_REGION` as
+    /// `export.aws_region`.
+    pub fn tables(mut self, tables: &[&'static str]) -> Self {
+        self.tables = tables.to_vec();
+        self
+    }
+
+    /// Command-line flags; leave unset flags out, e.g. with
+    /// `#[serde(skip_serializing_if = "Option::is_none")]`.
+    pub fn overrides(mut self, flags: &impl Serialize) -> Self {
+        self.overrides = Value::serialize(flags).ok();
+        self
+    }
+
+    /// Merges the layers into `T` and validates it.
+    pub fn load<T>(&self) -> Result<T, String>
+    where
+        T: Default + Serialize + DeserializeOwned + Validate,
+    {
+        let section = self.section;
+        let mut figment = Figment::from(Named {
+            name: "defaults",
+            source: None,
+            data: nested(
+                section,
+                Value::serialize(T::default()).map_err(|e| describe(e, section))?,
+            ),
+        });
+        if let Some(path) = &self.file {
+            if !path.is_file() {
+                return Err(format!("config file {} not found", path.display()));
+            }
+            figment = figment.merge(Toml::file_exact(path));
+        }
+        for (name, raw) in &self.vars {
+            let key = self.env_key(&name[self.prefix.len()..]);
+            figment = figment.merge(Named {
+                name: "environment variable",
+                source: Some(name.clone()),
+                data: nested(&format!("{}.{}", section, key), Value::from(raw.clone())),
+            });
+        }
+        if let Some(flags) = &self.overrides {
+            figment = figment.merge(Serialized::defaults(flags.clone()).key(section));
+        }
+
+        // Lossy, as environment variables are strings whatever they hold.
+        let settings: T = figment
+            .extract_inner_lossy(section)
+            .map_err(|e| describe(e, section))?;
+        if let Some(path) = &self.file {
+            self.check_unknown_keys::<T>(path)?;
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn env_key(&self, name: &str) -> String {
+        let key = name.to_ascii_lowercase().replace("__", ".");
+        for table in &self.tables {
+            if let Some(rest) = key.strip_prefix(table).and_then(|k| k.strip_prefix('_')) {
+                return format!("{}.{}", table, rest);
+            }
+        }
+        key
+    }
+
+    /// Fails on keys of the file's section that `T` does not have.
+    fn check_unknown_keys<T: DeserializeOwned>(&self, path: &Path) -> Result<(), String> {
+        let file = Figment::from(Toml::file_exact(path));
+        let Ok(value) = file.find_value(self.section) else {
+            return Ok(());
+        };
+        let mut unknown = None;
+        // Only looks for ignored keys; `load` already reported any error.
+        let _: Result<T, _> = serde_ignored::deserialize(&value, |key| {
+            unknown.get_or_insert_with(|| key.to_string());
+        });
+        match unknown {
+            Some(key) => Err(format!(
+                "unknown setting {}.{} in {}",
+                self.section,
+                key,
+                path.display()
+            )),
+            None => Ok(()),
+        }
+    }
+}
+
+/// One layer of values, named for error messages.
+struct Named {
+    name: &'static str,
+    source: Option<String>,
+    data: Dict,
+}
+
+impl Provider for Named {
+    fn metadata(&self) -> Metadata {
+        let metadata = Metadata::named(self.name);
+        match &self.source {
+            Some(source) => metadata.source(Source::Custom(source.clone())),
+            None => metadata,
+        }
+    }
+
+    fn data(&self) -> Result<Map<Profile, Dict>, figment::Error> {
+        Ok(Profile::Default.collect(self.data.clone()))
+    }
+}
+
+fn nested(key: &str, value: Value) -> Dict {
+    figment::util::nest(key, value)
+        .into_dict()
+        .expect("keys are not empty")
+}
+
+/// `key in source: problem`, for each problem.
+fn describe(error: figment::Error, section: &str) -> String {
+    error
+        .into_iter()
+        .map(|mut e| {
+            // `extract_inner` appends the section it extracted to the path.
+            if e.path.last().is_some_and(|last| last == section) {
+                e.path.pop();
+                e.path.insert(0, section.to_string());
+            }
+            let source = e.metadata.as_ref().map(|m| match &m.source {
+                Some(source) => format!("{} {}", m.name, source),
+                None => m.name.to_string(),
+            });
+            match (e.path.join("."), source) {
+                (key, Some(source)) if !key.is_empty() => {
+                    format!("{} in {}: {}", key, source, e.kind)
+                }
+                (key, None) if !key.is_empty() => format!("{}: {}", key, e.kind),
+                (_, Some(source)) => format!("{}: {}", source, e.kind),
+                (_, None) => e.kind.to_string(),
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    #[serde(default)]
+    struct Settings {
+        url: String,
+        retries: u32,
+        timeout_secs: Option<u64>,
+        labels: Map<String, String>,
+    }
+
+    impl Default for Settings {
+        fn default() -> Self {
+            Self {
+                url: "http://localhost:3001".to_string(),
+                retries: 3,
+                timeout_secs: None,
+                labels: Map::new(),
+            }
+        }
+    }
+
+    impl Validate for Settings {
+        fn validate(&self) -> Result<(), String> {
+            if self.retries > 10 {
+                return Err("retries must be at most 10".to_string());
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(Serialize)]
+    struct Flags {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        retries: Option<u32>,
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> Vec<(String, String)> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn write_file(name: &str, text: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("config-core-{}-{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let file = write_file(
+            "layers.toml",
+            "[app]\nurl = \"http://file:3001\"\nretries = 5\ntimeout_secs = 10\n\n[other]\nkey = 1\n",
+        );
+        let loader = || Loader::new("app").file(Some(file.clone()));
+
+        let settings: Settings = Loader::new("app").load().unwrap();
+        assert_eq!(settings, Settings::default());
+
+        let settings: Settings = loader().load().unwrap();
+        assert_eq!(
+            (settings.url.as_str(), settings.retries),
+            ("http://file:3001", 5)
+        );
+        assert_eq!(settings.timeout_secs, Some(10));
+
+        let env = vars(&[
+            ("APP_RETRIES", "7"),
+            ("APP_LABELS_SITE", "north"),
+            ("APP_UNRELATED", "x"),
+            ("OTHER_RETRIES", "9"),
+        ]);
+        let settings: Settings = loader()
+            .vars("APP_", env.clone())
+            .tables(&["labels"])
+            .load()
+            .unwrap();
+        assert_eq!(
+            (settings.url.as_str(), settings.retries),
+            ("http://file:3001", 7)
+        );
+        assert_eq!(
+            settings.labels.get("site").map(String::as_str),
+            Some("north")
+        );
+
+        let settings: Settings = loader()
+            .vars("APP_", env.clone())
+            .overrides(&Flags { retries: Some(2) })
+            .load()
+            .unwrap();
+        assert_eq!(settings.retries, 2);
+        let settings: Settings = loader()
+            .vars("APP_", env)
+            .overrides(&Flags { retries: None })
+            .load()
+            .unwrap();
+        assert_eq!(settings.retries, 7);
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn test_errors_name_the_setting_and_its_source() {
+        let error = Loader::new("app")
+            .vars("APP_", vars(&[("APP_RETRIES", "many")]))
+            .load::<Settings>()
+            .unwrap_err();
+        assert!(
+            error.starts_with("app.retries in environment variable APP_RETRIES:"),
+            "{}",
+            error
+        );
+
+        let error = Loader::new("app")
+            .vars("APP_", vars(&[("APP_RETRIES", "11")]))
+            .load::<Settings>()
+            .unwrap_err();
+        assert_eq!(error, "retries must be at most 10");
+
+        let file = write_file("typo.toml", "[app]\nretry = 5\n");
+        let error = Loader::new("app")
+            .file(Some(file.clone()))
+            .load::<Settings>()
+            .unwrap_err();
+        assert!(
+            error.starts_with("unknown setting app.retry in "),
+            "{}",
+            error
+        );
+        std::fs::remove_file(file).unwrap();
+
+        let missing = std::env::temp_dir().join("config-core-missing.toml");
+        let error = Loader::new("app")
+            .file(Some(missing))
+            .load::<Settings>()
+            .unwrap_err();
+        assert!(error.ends_with("not found"), "{}", error);
+    }
+}
@@ -0,0 +1,112 @@
+//! Z-scoring many series at once, each against its own mean and standard
+//! deviation, on the CPU or, with the `gpu` feature, on a GPU.
+//!
+//! The GPU backend runs a wgpu compute shader over Vulkan, Metal or DX12, so
+//! NVIDIA cards are reached through their Vulkan driver rather than CUDA.
+//! [`Backend::detect`] picks it when a device is present and the CPU
+//! otherwise, so a build with the feature still runs on machines without
+//! one. The GPU works in single precision on values shifted by each series'
+//! first: its z-scores agree with the CPU's to about 1e-4, and a value that
+//! close to the threshold may be flagged by one and not the other.
+
+use crate::stats::{ZScorer, summarize};
+
+#[cfg(feature = "gpu")]
+mod gpu;
+
+#[cfg(feature = "gpu")]
+pub use gpu::Gpu;
+
+pub enum Backend {
+    Cpu,
+    #[cfg(feature = "gpu")]
+    Gpu(Gpu),
+}
+
+impl Backend {
+    /// The GPU when built with the `gpu` feature and a device is present,
+    /// otherwise the CPU.
+    pub fn detect() -> Self {
+        #[cfg(feature = "gpu")]
+        if let Some(gpu) = Gpu::new() {
+            return Backend::Gpu(gpu);
+        }
+        Backend::Cpu
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Cpu => "cpu",
+            #[cfg(feature = "gpu")]
+            Backend::Gpu(_) => "gpu",
+        }
+    }
+
+    /// The positions and z-scores of the values of each series whose
+    /// z-score exceeds `threshold`, as [`ZScorer::score`] flags them.
+    pub fn zscores(&self, series: &[&[f64]], threshold: f64) -> Vec<Vec<(usize, f64)>> {
+        match self {
+            Backend::Cpu => series.iter().map(|values| cpu(values, threshold)).collect(),
+            #[cfg(feature = "gpu")]
+            Backend::Gpu(gpu) => gpu.zscores(series, threshold),
+        }
+    }
+}
+
+fn cpu(values: &[f64], threshold: f64) -> Vec<(usize, f64)> {
+    let scorer = ZScorer::new(&summarize(values.iter().copied()), threshold);
+    values
+        .iter()
+        .enumerate()
+        .filter_map(|(position, &value)| Some((position, scorer.score(value)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(length: usize, spike: f64, offset: f64) -> Vec<f64> {
+        let mut values: Vec<f64> = (0..length)
+            .map(|i| offset + ((i * 7) % 13) as f64 / 13.0)
+            .collect();
+        values[length / 2] = offset + spike;
+        values
+    }
+
+    #[test]
+    fn test_cpu_flags_each_series_against_itself() {
+        let (a, b, flat) = (series(500, 10.0, 0.0), series(300, 0.5, 1e6), [5.0; 10]);
+        let flagged = Backend::Cpu.zscores(&[&a, &b, &flat, &[]], 4.0);
+
+        assert_eq!(flagged.len(), 4);
+        assert_eq!(flagged[0].len(), 1);
+        assert_eq!(flagged[0][0].0, 250);
+        assert!(flagged[0][0].1 > 4.0);
+        assert!(flagged[1].is_empty());
+        assert!(flagged[2].is_empty() && flagged[3].is_empty());
+    }
+
+    #[test]
+    fn test_detected_backend_matches_cpu() {
+        // The CPU without the feature or a device; otherwise the GPU, whose
+        // scores must agree with the CPU's.
+        let backend = Backend::detect();
+        let all: Vec<Vec<f64>> = (0..50)
+            .map(|i| series(1_000 + i * 37, 5.0 + i as f64, i as f64 * 1e5))
+            .collect();
+        let slices: Vec<&[f64]> = all.iter().map(Vec::as_slice).collect();
+
+        let expected = Backend::Cpu.zscores(&slices, 3.0);
+        let actual = backend.zscores(&slices, 3.0);
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(&expected) {
+            let positions =
+                |flagged: &[(usize, f64)]| flagged.iter().map(|f| f.0).collect::<Vec<_>>();
+            assert_eq!(positions(actual), positions(expected), "{}", backend.name());
+            for (a, e) in actual.iter().zip(expected) {
+                assert!((a.1 - e.1).abs() < 1e-3 * e.1.abs(), "{} vs {}", a.1, e.1);
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! The wgpu backend. Series are packed into one buffer of `f32`, each
+//! shifted by its first value so large offsets do not eat the precision, and
+//! scored by `zscore.wgsl`, one workgroup per series. Batches larger than the
+//! device's storage buffers are split into chunks of whole series; a series
+//! too long for one buffer, or a chunk the device fails on, is scored on the
+//! CPU.
+
+use std::sync::mpsc;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use super::cpu;
+
+const SHADER: &str = include_str!("zscore.wgsl");
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Span {
+    offset: u32,
+    len: u32,
+}
+
+/// Uniform buffers are laid out in 16-byte units.
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct Params {
+    threshold: f32,
+    per_row: u32,
+    padding: [u32; 2],
+}
+
+pub struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// Values that fit in one storage buffer.
+    max_points: usize,
+    per_row: u32,
+}
+
+impl Gpu {
+    /// The first hardware adapter found; `None` without one, software
+    /// adapters being slower than the CPU path.
+    pub fn new() -> Option<Self> {
+        pollster::block_on(Self::request())
+    }
+
+    async fn request() -> Option<Self> {
+        let instance =
+            wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+        if adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            return None;
+        }
+        let limits = adapter.limits();
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("zscore"),
+                required_limits: limits.clone(),
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("zscore"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("zscore"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let max_bytes = limits
+            .max_storage_buffer_binding_size
+            .min(limits.max_buffer_size);
+        Some(Self {
+            device,
+            queue,
+            pipeline,
+            max_points: (max_bytes / 4).min(u32::MAX as u64) as usize,
+            per_row: limits.max_compute_workgroups_per_dimension,
+        })
+    }
+
+    pub fn zscores(&self, series: &[&[f64]], threshold: f64) -> Vec<Vec<(usize, f64)>> {
+        let mut flagged = vec![Vec::new(); series.len()];
+        let mut chunk = Vec::new();
+        let mut points = 0;
+        for (index, values) in series.iter().enumerate() {
+            if values.len() > self.max_points {
+                flagged[index] = cpu(values, threshold);
+                continue;
+            }
+            if points + values.len() > self.max_points {
+                self.score(series, &chunk, threshold, &mut flagged);
+                chunk.clear();
+                points = 0;
+            }
+            chunk.push(index);
+            points += values.len();
+        }
+        self.score(series, &chunk, threshold, &mut flagged);
+        flagged
+    }
+
+    /// Scores the series at `chunk` into `flagged`.
+    fn score(
+        &self,
+        series: &[&[f64]],
+        chunk: &[usize],
+        threshold: f64,
+        flagged: &mut [Vec<(usize, f64)>],
+    ) {
+        let Some(scores) = pollster::block_on(self.dispatch(series, chunk, threshold)) else {
+            for &index in chunk {
+                flagged[index] = cpu(series[index], threshold);
+            }
+            return;
+        };
+        let mut offset = 0;
+        for &index in chunk {
+            let len = series[index].len();
+            flagged[index] = scores[offset..offset + len]
+                .iter()
+                .enumerate()
+                .filter(|(_, z)| **z != 0.0)
+                .map(|(position, &z)| (position, z as f64))
+                .collect();
+            offset += len;
+        }
+    }
+
+    /// Each value's score, 0 unless flagged; `None` if the device failed.
+    async fn dispatch(
+        &self,
+        series: &[&[f64]],
+        chunk: &[usize],
+        threshold: f64,
+    ) -> Option<Vec<f32>> {
+        let mut values = Vec::new();
+        let mut spans = Vec::with_capacity(chunk.len());
+        for &index in chunk {
+            let first = series[index].first().copied().unwrap_or(0.0);
+            spans.push(Span {
+                offset: values.len() as u32,
+                len: series[index].len() as u32,
+            });
+            values.extend(series[index].iter().map(|value| (value - first) as f32));
+        }
+        if values.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let out_of_memory = self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        let validation = self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let storage = |label, contents: &[u8]| {
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(label),
+                    contents,
+                    usage: wgpu::BufferUsages::STORAGE,
+                })
+        };
+        let input = storage("values", bytemuck::cast_slice(&values));
+        let spans = storage("spans", bytemuck::cast_slice(&spans));
+        let params = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("params"),
+                contents: bytemuck::bytes_of(&Params {
+                    threshold: threshold as f32,
+                    per_row: self.per_row,
+                    padding: [0; 2],
+                }),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let size = (values.len() * size_of::<f32>()) as u64;
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("scores"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("zscore"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: input.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: spans.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: output.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: params.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("zscore"),
+            });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("zscore"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            let groups = chunk.len() as u32;
+            pass.dispatch_workgroups(groups.min(self.per_row), groups.div_ceil(self.per_row), 1);
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+        self.queue.submit([encoder.finish()]);
+
+        let (sender, receiver) = mpsc::channel();
+        readback.map_async(wgpu::MapMode::Read, .., move |result| {
+            let _ = sender.send(result);
+        });
+        let polled = self.device.poll(wgpu::PollType::wait_indefinitely());
+        let failed = validation.pop().await.is_some() | out_of_memory.pop().await.is_some();
+        if failed || polled.is_err() {
+            return None;
+        }
+        receiver.recv().ok()?.ok()?;
+        let mapped = readback.get_mapped_range(..).ok()?;
+        Some(bytemuck::cast_slice(&mapped).to_vec())
+    }
+}
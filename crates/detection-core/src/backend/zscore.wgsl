@@ -0,0 +1,80 @@
+// One workgroup per series: every invocation runs Welford over a stride of
+// the series, the partial results are merged pairwise (Chan) in workgroup
+// memory, and then every invocation scores its stride. A score is written
+// only past the threshold and is 0 otherwise, which a flagged value, being
+// away from the mean, can never score.
+
+struct Span {
+    offset: u32,
+    len: u32,
+}
+
+struct Params {
+    threshold: f32,
+    // Workgroups per row of the dispatch, past which series continue on the
+    // next row.
+    per_row: u32,
+}
+
+@group(0) @binding(0) var<storage, read> values: array<f32>;
+@group(0) @binding(1) var<storage, read> spans: array<Span>;
+@group(0) @binding(2) var<storage, read_write> scores: array<f32>;
+@group(0) @binding(3) var<uniform> params: Params;
+
+const SIZE: u32 = 256u;
+
+var<workgroup> counts: array<f32, SIZE>;
+var<workgroup> means: array<f32, SIZE>;
+var<workgroup> m2s: array<f32, SIZE>;
+
+@compute @workgroup_size(256)
+fn main(
+    @builtin(workgroup_id) group: vec3<u32>,
+    @builtin(local_invocation_index) lane: u32,
+) {
+    let index = group.y * params.per_row + group.x;
+    if index >= arrayLength(&spans) {
+        return;
+    }
+    let span = spans[index];
+
+    var count = 0.0;
+    var mean = 0.0;
+    var m2 = 0.0;
+    for (var i = lane; i < span.len; i += SIZE) {
+        let value = values[span.offset + i];
+        count += 1.0;
+        let delta = value - mean;
+        mean += delta / count;
+        m2 += delta * (value - mean);
+    }
+    counts[lane] = count;
+    means[lane] = mean;
+    m2s[lane] = m2;
+    workgroupBarrier();
+
+    for (var stride = SIZE / 2u; stride > 0u; stride /= 2u) {
+        if lane < stride {
+            let a = counts[lane];
+            let b = counts[lane + stride];
+            let total = a + b;
+            if total > 0.0 {
+                let delta = means[lane + stride] - means[lane];
+                means[lane] += delta * b / total;
+                m2s[lane] += m2s[lane + stride] + delta * delta * a * b / total;
+                counts[lane] = total;
+            }
+        }
+        workgroupBarrier();
+    }
+
+    let total = counts[0];
+    let center = means[0];
+    let std_dev = select(0.0, sqrt(m2s[0] / (total - 1.0)), total > 1.0);
+    let limit = params.threshold * std_dev;
+    for (var i = lane; i < span.len; i += SIZE) {
+        let delta = values[span.offset + i] - center;
+        let flagged = std_dev > 0.0 && abs(delta) > limit;
+        scores[span.offset + i] = select(0.0, delta / std_dev, flagged);
+    }
+}
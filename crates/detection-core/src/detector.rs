@@ -0,0 +1,754 @@
+//! Detection algorithms behind one trait, looked up by name in a
+//! [`Registry`], so crates outside this one can add their own.
+//!
+//! ```
+//! use detection_core::detector::{Detector, Registry};
+//!
+//! /// Scores each value by how far it is from zero.
+//! struct Magnitude;
+//!
+//! impl Detector for Magnitude {
+//!     fn name(&self) -> &'static str {
+//!         "magnitude"
+//!     }
+//!
+//!     fn scores(&self, values: &[f64]) -> Vec<f64> {
+//!         values.to_vec()
+//!     }
+//! }
+//!
+//! let registry = Registry::builtin().with(Magnitude);
+//! assert!(registry.get("magnitude").is_some());
+//! ```
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::SeverityBands;
+use crate::outlier::Outlier;
+use crate::stats::{self, HoltWinters, IqrScorer, MadScorer, Reservoir, ZScorer, summarize};
+use crate::tdigest::TDigest;
+
+/// A batch detection algorithm: scores every reading of a series, and a
+/// reading whose score exceeds the threshold in magnitude is an outlier.
+///
+/// Scores are read like z-scores, so [`SeverityBands`] grade them.
+pub trait Detector: Send + Sync {
+    /// The name the detector is selected and stored by, e.g. `"mad"`.
+    fn name(&self) -> &'static str;
+
+    /// The score of each of `values`, in order; 0 for a value that cannot be
+    /// scored, e.g. while a detector is warming up.
+    fn scores(&self, values: &[f64]) -> Vec<f64>;
+
+    /// The threshold used when none is given.
+    fn default_threshold(&self) -> f64 {
+        3.0
+    }
+
+    /// This detector drawing its randomness from `seed`, for detectors that
+    /// use any; `None` for deterministic ones. A stochastic detector uses a
+    /// fixed seed of its own otherwise, so its scores are reproducible
+    /// either way.
+    fn reseeded(&self, _seed: u64) -> Option<Arc<dyn Detector>> {
+        None
+    }
+
+    /// The `(reading_id, value)` readings scoring past `threshold`, in
+    /// reading order.
+    fn outliers(
+        &self,
+        readings: &[(i64, f64)],
+        threshold: f64,
+        bands: &SeverityBands,
+    ) -> Vec<Outlier> {
+        let values: Vec<f64> = readings.iter().map(|r| r.1).collect();
+        readings
+            .iter()
+            .zip(self.scores(&values))
+            .filter(|(_, score)| score.abs() > threshold)
+            .map(|(&(reading_id, value), score)| Outlier {
+                reading_id,
+                value,
+                score,
+                severity: bands.classify(score.abs()),
+            })
+            .collect()
+    }
+}
+
+/// Z-scores against the mean and standard deviation of the series.
+pub struct ZScoreDetector;
+
+impl Detector for ZScoreDetector {
+    fn name(&self) -> &'static str {
+        "zscore"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let scorer = ZScorer::new(&summarize(values.iter().copied()), 0.0);
+        values.iter().map(|&value| scorer.z(value)).collect()
+    }
+
+    fn default_threshold(&self) -> f64 {
+        2.0
+    }
+}
+
+/// Modified z-scores against the median and MAD of the series.
+pub struct MadDetector;
+
+impl Detector for MadDetector {
+    fn name(&self) -> &'static str {
+        "mad"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let scorer = MadScorer::new(values, 0.0);
+        values.iter().map(|&value| scorer.z(value)).collect()
+    }
+
+    fn default_threshold(&self) -> f64 {
+        3.5
+    }
+}
+
+/// Interquartile ranges past the quartiles of the series; the threshold is
+/// Tukey's `k`.
+pub struct IqrDetector;
+
+impl Detector for IqrDetector {
+    fn name(&self) -> &'static str {
+        "iqr"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let scorer = IqrScorer::new(values, 0.0);
+        values.iter().map(|&value| scorer.z(value)).collect()
+    }
+
+    fn default_threshold(&self) -> f64 {
+        1.5
+    }
+}
+
+/// Z-scores against the exponentially weighted mean and deviation of the
+/// values before each one, with each new value weighing `alpha`; the first
+/// `1 / alpha` values are not scored.
+pub struct EwmaDetector {
+    pub alpha: f64,
+}
+
+impl Default for EwmaDetector {
+    fn default() -> Self {
+        Self { alpha: 0.3 }
+    }
+}
+
+impl Detector for EwmaDetector {
+    fn name(&self) -> &'static str {
+        "ewma"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let mut ewma = stats::Ewma::new(self.alpha);
+        values
+            .iter()
+            .map(|&value| {
+                let warm = ewma.count() as f64 * self.alpha >= 1.0;
+                let z = ewma.z(value);
+                ewma.push(value);
+                if warm { z } else { 0.0 }
+            })
+            .collect()
+    }
+}
+
+/// Z-scores against the `window` values before each one; the first
+/// `window` values are not scored.
+pub struct RollingDetector {
+    pub window: usize,
+}
+
+impl Default for RollingDetector {
+    fn default() -> Self {
+        Self { window: 20 }
+    }
+}
+
+impl Detector for RollingDetector {
+    fn name(&self) -> &'static str {
+        "rolling"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        (0..values.len())
+            .map(|position| {
+                if position < self.window {
+                    return 0.0;
+                }
+                let before = &values[position - self.window..position];
+                ZScorer::new(&summarize(before.iter().copied()), 0.0).z(values[position])
+            })
+            .collect()
+    }
+}
+
+/// Z-scores against a [`Reservoir`] sample of `capacity` of the values
+/// before each one, so the baseline spans the series' whole history rather
+/// than a recent window; the first 20 values are not scored. The sample is
+/// drawn with `seed`.
+pub struct ReservoirDetector {
+    pub capacity: usize,
+    pub seed: u64,
+}
+
+impl Default for ReservoirDetector {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            seed: 0,
+        }
+    }
+}
+
+impl Detector for ReservoirDetector {
+    fn name(&self) -> &'static str {
+        "reservoir"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let mut reservoir = Reservoir::new(self.capacity, self.seed);
+        values
+            .iter()
+            .map(|&value| {
+                let score = if reservoir.seen() < 20 {
+                    0.0
+                } else {
+                    ZScorer::new(&reservoir.stats(), 0.0).z(value)
+                };
+                reservoir.push(value);
+                score
+            })
+            .collect()
+    }
+
+    fn reseeded(&self, seed: u64) -> Option<Arc<dyn Detector>> {
+        Some(Arc::new(ReservoirDetector {
+            capacity: self.capacity,
+            seed,
+        }))
+    }
+}
+
+/// Scores values against running percentiles of the values before them,
+/// kept in a [`TDigest`]: a value as far above the median as the `quantile`
+/// percentile scores 1, and one as far below as its mirror image -1. The
+/// first `warmup` values are not scored.
+pub struct PercentileDetector {
+    pub quantile: f64,
+    pub warmup: usize,
+}
+
+impl Default for PercentileDetector {
+    /// Past the running p99.5, after 50 values.
+    fn default() -> Self {
+        Self {
+            quantile: 0.995,
+            warmup: 50,
+        }
+    }
+}
+
+impl Detector for PercentileDetector {
+    fn name(&self) -> &'static str {
+        "percentile"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let mut digest = TDigest::default();
+        values
+            .iter()
+            .map(|&value| {
+                let score = if digest.count() < self.warmup as u64 {
+                    0.0
+                } else {
+                    digest.compress();
+                    let median = digest.quantile(0.5).unwrap_or(value);
+                    let bound = if value >= median {
+                        digest.quantile(self.quantile)
+                    } else {
+                        digest.quantile(1.0 - self.quantile)
+                    };
+                    let spread = bound.map_or(0.0, |bound| (bound - median).abs());
+                    if spread > 0.0 {
+                        (value - median) / spread
+                    } else {
+                        0.0
+                    }
+                };
+                digest.push(value);
+                score
+            })
+            .collect()
+    }
+
+    fn default_threshold(&self) -> f64 {
+        1.0
+    }
+}
+
+/// Scores values by their residual from the forecast of a [`HoltWinters`]
+/// model of the series' level, trend and season of `period` values, in
+/// standard deviations of the residuals before them, so a daily cycle is
+/// forecast rather than flagged at every peak. The first two seasons train
+/// the model and are not scored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HoltWintersDetector {
+    pub period: usize,
+    pub alpha: f64,
+    pub beta: f64,
+    pub gamma: f64,
+}
+
+impl Default for HoltWintersDetector {
+    /// A season of 24 values, e.g. a day of hourly readings.
+    fn default() -> Self {
+        Self {
+            period: 24,
+            alpha: 0.2,
+            beta: 0.05,
+            gamma: 0.3,
+        }
+    }
+}
+
+impl HoltWintersDetector {
+    /// A model with these parameters, before its first value.
+    pub fn model(&self) -> HoltWinters {
+        HoltWinters::new(self.period, self.alpha, self.beta, self.gamma)
+    }
+}
+
+impl Detector for HoltWintersDetector {
+    fn name(&self) -> &'static str {
+        "holt_winters"
+    }
+
+    fn scores(&self, values: &[f64]) -> Vec<f64> {
+        let mut model = self.model();
+        values
+            .iter()
+            .map(|&value| {
+                let score = model.z(value);
+                model.push(value);
+                score
+            })
+            .collect()
+    }
+}
+
+/// Detectors by name.
+#[derive(Clone)]
+pub struct Registry {
+    detectors: BTreeMap<&'static str, Arc<dyn Detector>>,
+}
+
+impl Default for Registry {
+    /// The built-in detectors.
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+impl Registry {
+    /// No detectors at all.
+    pub fn empty() -> Self {
+        Self {
+            detectors: BTreeMap::new(),
+        }
+    }
+
+    /// `zscore`, `mad`, `iqr`, `ewma` (alpha 0.3), `rolling` (a window of
+    /// 20), `reservoir` (a sample of 256), `percentile` (past the running
+    /// p99.5), and `holt_winters` (a season of 24 values).
+    pub fn builtin() -> Self {
+        Self::empty()
+            .with(ZScoreDetector)
+            .with(MadDetector)
+            .with(IqrDetector)
+            .with(EwmaDetector::default())
+            .with(RollingDetector::default())
+            .with(ReservoirDetector::default())
+            .with(PercentileDetector::default())
+            .with(HoltWintersDetector::default())
+    }
+
+    /// Adds `detector`, replacing one registered under the same name.
+    pub fn with(mut self, detector: impl Detector + 'static) -> Self {
+        self.detectors.insert(detector.name(), Arc::new(detector));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Detector>> {
+        self.detectors.get(name).cloned()
+    }
+
+    /// Registered names, in order.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.detectors.keys().copied()
+    }
+}
+
+/// How an [`Ensemble`] combines the verdicts of its members.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Combine {
+    /// An outlier when more than half of the members flag it.
+    #[default]
+    Majority,
+    /// An outlier when the weighted mean of each member's score over its
+    /// threshold is past 1.
+    Weighted,
+}
+
+/// A detector of an [`Ensemble`], flagging scores past its own threshold.
+#[derive(Clone)]
+pub struct Member {
+    pub detector: Arc<dyn Detector>,
+    pub threshold: f64,
+    pub weight: f64,
+}
+
+/// An [`Ensemble`]'s verdict on one value.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Verdict {
+    /// The weighted mean of the members' scores, graded like theirs.
+    pub score: f64,
+    pub outlier: bool,
+    /// The score of each member, in order.
+    pub members: Vec<f64>,
+}
+
+/// Several detectors run on the same series, their verdicts combined.
+#[derive(Clone, Default)]
+pub struct Ensemble {
+    members: Vec<Member>,
+    combine: Combine,
+}
+
+impl Ensemble {
+    pub fn new(combine: Combine) -> Self {
+        Self {
+            members: Vec::new(),
+            combine,
+        }
+    }
+
+    /// Adds a member flagging scores past `threshold`; `weight` only counts
+    /// for [`Combine::Weighted`].
+    pub fn with(mut self, detector: Arc<dyn Detector>, threshold: f64, weight: f64) -> Self {
+        self.members.push(Member {
+            detector,
+            threshold,
+            weight,
+        });
+        self
+    }
+
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    pub fn combine(&self) -> Combine {
+        self.combine
+    }
+
+    /// This ensemble with its stochastic members drawing from `seed`.
+    pub fn reseeded(&self, seed: u64) -> Self {
+        let members = self
+            .members
+            .iter()
+            .map(|member| Member {
+                detector: member
+                    .detector
+                    .reseeded(seed)
+                    .unwrap_or_else(|| member.detector.clone()),
+                ..member.clone()
+            })
+            .collect();
+        Self {
+            members,
+            combine: self.combine,
+        }
+    }
+
+    /// The verdict on each of `values`, in order.
+    pub fn verdicts(&self, values: &[f64]) -> Vec<Verdict> {
+        let scores: Vec<Vec<f64>> = self
+            .members
+            .iter()
+            .map(|member| member.detector.scores(values))
+            .collect();
+        let total_weight: f64 = self.members.iter().map(|m| m.weight).sum();
+        (0..values.len())
+            .map(|position| {
+                let members: Vec<f64> = scores.iter().map(|s| s[position]).collect();
+                let weighted = |score: fn(f64, &Member) -> f64| {
+                    if total_weight > 0.0 {
+                        self.members
+                            .iter()
+                            .zip(&members)
+                            .map(|(member, &s)| member.weight * score(s, member))
+                            .sum::<f64>()
+                            / total_weight
+                    } else {
+                        0.0
+                    }
+                };
+                let outlier = match self.combine {
+                    Combine::Majority => {
+                        let votes = self
+                            .members
+                            .iter()
+                            .zip(&members)
+                            .filter(|(member, s)| s.abs() > member.threshold)
+                            .count();
+                        votes * 2 > self.members.len()
+                    }
+                    Combine::Weighted => weighted(|s, m| s.abs() / m.threshold) > 1.0,
+                };
+                Verdict {
+                    score: weighted(|s, _| s),
+                    outlier,
+                    members,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::outlier::{ewma_outliers, iqr_outliers, mad_outliers, rolling_outliers};
+    use crate::zscore_outliers;
+
+    #[test]
+    fn test_builtins_match_the_outlier_functions() {
+        let mut readings: Vec<(i64, f64)> = (0..40)
+            .map(|i| (i + 1, i as f64 + [0.0, 0.8, -0.5, 0.3][i as usize % 4]))
+            .collect();
+        readings[30].1 += 15.0;
+        readings.push((41, 200.0));
+        let bands = SeverityBands::default();
+        let ids = |outliers: Vec<Outlier>| -> Vec<i64> {
+            outliers.iter().map(|o| o.reading_id).collect()
+        };
+        let registry = Registry::builtin();
+        let detect = |name: &str, threshold| {
+            ids(registry
+                .get(name)
+                .unwrap()
+                .outliers(&readings, threshold, &bands))
+        };
+
+        assert_eq!(
+            detect("zscore", 2.0),
+            ids(zscore_outliers(&readings, 2.0, &bands))
+        );
+        assert_eq!(
+            detect("mad", 3.5),
+            ids(mad_outliers(&readings, 3.5, &bands))
+        );
+        assert_eq!(
+            detect("iqr", 1.5),
+            ids(iqr_outliers(&readings, 1.5, &bands))
+        );
+        assert_eq!(
+            detect("ewma", 3.0),
+            ids(ewma_outliers(&readings, 0.3, 3.0, &bands))
+        );
+        assert_eq!(
+            detect("rolling", 3.0),
+            ids(rolling_outliers(&readings, 20, 3.0, &bands))
+        );
+        assert_eq!(detect("rolling", 3.0), vec![31, 41]);
+    }
+
+    struct Constant;
+
+    impl Detector for Constant {
+        fn name(&self) -> &'static str {
+            "mad"
+        }
+
+        fn scores(&self, values: &[f64]) -> Vec<f64> {
+            vec![10.0; values.len()]
+        }
+    }
+
+    #[test]
+    fn test_registered_detectors_replace_those_of_the_same_name() {
+        let registry = Registry::builtin().with(Constant);
+
+        let names: Vec<&str> = registry.names().collect();
+        assert_eq!(
+            names,
+            [
+                "ewma",
+                "holt_winters",
+                "iqr",
+                "mad",
+                "percentile",
+                "reservoir",
+                "rolling",
+                "zscore"
+            ]
+        );
+        let outliers = registry.get("mad").unwrap().outliers(
+            &[(1, 1.0), (2, 1.0)],
+            3.5,
+            &SeverityBands::default(),
+        );
+        assert_eq!(outliers.len(), 2);
+        assert_eq!(outliers[0].severity, crate::Severity::Critical);
+        assert!(Registry::empty().get("zscore").is_none());
+    }
+
+    #[test]
+    fn test_percentile_flags_values_past_the_running_percentile() {
+        let mut values: Vec<f64> = (0..400).map(|i| ((i * 37) % 100) as f64).collect();
+        values[300] = 250.0;
+        values[350] = -150.0;
+        let detector = PercentileDetector::default();
+
+        let scores = detector.scores(&values);
+        assert!(scores[..50].iter().all(|&s| s == 0.0));
+        // About one value in 200 of the series itself is past its p99.5.
+        let past = scores.iter().filter(|s| s.abs() > 1.0).count();
+        assert!(past <= 6, "{}", past);
+        let flagged: Vec<usize> = (0..scores.len())
+            .filter(|&i| scores[i].abs() > 2.0)
+            .collect();
+        assert_eq!(flagged, vec![300, 350]);
+        assert!(scores[300] > 0.0 && scores[350] < 0.0);
+    }
+
+    #[test]
+    fn test_holt_winters_flags_departures_from_the_daily_cycle() {
+        // Hourly temperatures over five days, swinging 8 degrees either way
+        // around a slowly warming mean, and a night on day four 6 degrees
+        // too warm.
+        let mut values: Vec<f64> = (0..120)
+            .map(|hour| {
+                let phase = (hour % 24) as f64 / 24.0 * std::f64::consts::TAU;
+                let noise = ((hour * 37) % 11) as f64 / 10.0 - 0.5;
+                20.0 + 0.01 * hour as f64 + 8.0 * phase.sin() + noise
+            })
+            .collect();
+        values[90] += 6.0;
+        let flagged = |scores: Vec<f64>, threshold: f64| -> Vec<usize> {
+            (0..scores.len())
+                .filter(|&i| scores[i].abs() > threshold)
+                .collect()
+        };
+
+        let scores = HoltWintersDetector::default().scores(&values);
+        assert!(scores[..48].iter().all(|&s| s == 0.0));
+        assert_eq!(flagged(scores, 3.0), vec![90]);
+        // Against the mean of the whole series, the warm night is as
+        // ordinary as an afternoon, and the afternoons score higher.
+        let z_scores = ZScoreDetector.scores(&values);
+        assert!(z_scores[90].abs() < 1.0, "{}", z_scores[90]);
+        assert!(flagged(z_scores, 1.3).len() > 10);
+    }
+
+    #[test]
+    fn test_reservoir_keeps_the_early_history() {
+        // A level that creeps up: a 20-value window follows it, the
+        // reservoir still weighs the start.
+        let values: Vec<f64> = (0..300)
+            .map(|i| i as f64 * 0.1 + [0.0, 0.5, -0.5][i % 3])
+            .collect();
+        let reservoir = ReservoirDetector::default().scores(&values);
+        let rolling = RollingDetector::default().scores(&values);
+
+        assert!(reservoir[..20].iter().all(|&s| s == 0.0));
+        assert!(reservoir[299] > 1.5, "{}", reservoir[299]);
+        assert!(rolling[299] < reservoir[299]);
+    }
+
+    #[test]
+    fn test_seeds_reproduce_stochastic_scores() {
+        let values: Vec<f64> = (0..2_000).map(|i| ((i * 37) % 101) as f64).collect();
+        let registry = Registry::builtin();
+        let reservoir = registry.get("reservoir").unwrap();
+        let seeded = |seed| reservoir.reseeded(seed).unwrap().scores(&values);
+
+        assert_eq!(seeded(7), seeded(7));
+        assert_ne!(seeded(7), seeded(8));
+        assert_eq!(seeded(0), reservoir.scores(&values));
+        assert!(registry.get("mad").unwrap().reseeded(7).is_none());
+
+        let ensemble = Ensemble::default().with(reservoir.clone(), 3.0, 1.0).with(
+            registry.get("mad").unwrap(),
+            3.5,
+            1.0,
+        );
+        let members = |ensemble: &Ensemble| -> Vec<Vec<f64>> {
+            ensemble
+                .verdicts(&values)
+                .into_iter()
+                .map(|verdict| verdict.members)
+                .collect()
+        };
+        assert_eq!(
+            members(&ensemble.reseeded(7)),
+            members(&ensemble.reseeded(7))
+        );
+        assert_ne!(members(&ensemble.reseeded(7)), members(&ensemble));
+    }
+
+    #[test]
+    fn test_ensemble_combines_member_verdicts() {
+        let mut values: Vec<f64> = (0..40).map(|i| 50.0 + (i % 3) as f64).collect();
+        // Past the MAD and IQR thresholds only.
+        values[10] = 60.0;
+        values[39] = 200.0;
+        let registry = Registry::builtin();
+        let ensemble = |combine| {
+            Ensemble::new(combine)
+                .with(registry.get("zscore").unwrap(), 3.0, 1.0)
+                .with(registry.get("mad").unwrap(), 3.5, 1.0)
+                .with(registry.get("iqr").unwrap(), 1.5, 1.0)
+        };
+        let flagged = |verdicts: Vec<Verdict>| -> Vec<usize> {
+            (0..verdicts.len())
+                .filter(|&i| verdicts[i].outlier)
+                .collect()
+        };
+
+        let verdicts = ensemble(Combine::Majority).verdicts(&values);
+        assert_eq!(verdicts[39].members.len(), 3);
+        let mean = verdicts[39].members.iter().sum::<f64>() / 3.0;
+        assert!((verdicts[39].score - mean).abs() < 1e-9);
+        assert_eq!(flagged(verdicts), vec![10, 39]);
+
+        // A heavy z-score member outweighs the two that flag the first.
+        let weighted = Ensemble::new(Combine::Weighted)
+            .with(registry.get("zscore").unwrap(), 3.0, 10.0)
+            .with(registry.get("mad").unwrap(), 3.5, 1.0)
+            .with(registry.get("iqr").unwrap(), 1.5, 1.0);
+        assert_eq!(flagged(weighted.verdicts(&values)), vec![39]);
+        assert_eq!(
+            flagged(ensemble(Combine::Weighted).verdicts(&values)),
+            vec![10, 39]
+        );
+        assert!(flagged(Ensemble::default().verdicts(&values)).is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+//! Detection logic shared by the `anomaly-detector` service, `detector-cli`
+//! and the Python, browser and Node.js bindings, so all of them score
+//! readings the same way.
+//!
+//! - [`stats`]: streaming mean and standard deviation, z-scoring, and the
+//!   median/MAD, quartile and EWMA scorers
+//! - [`severity`]: severity labels and the z-score bands that grade them
+//! - [`threshold`]: checks against fixed minimum and maximum values, graded
+//!   by how far past the limit a value lies, of the change between
+//!   consecutive readings, and a tracker alerting once per excursion with
+//!   hysteresis
+//! - [`rules`]: `condition => severity` rules on a reading's value and
+//!   score, graded the same in the service and the Python bindings
+//! - [`outlier`]: z-score, MAD, IQR, EWMA and rolling-window outliers of a
+//!   batch of readings
+//! - [`tdigest`]: streaming percentiles in bounded memory
+//! - [`sketch`]: count-min and HyperLogLog sketches, per-key and distinct
+//!   counts of key streams in bounded memory
+//! - [`detector`]: the [`Detector`] trait those algorithms implement, and a
+//!   [`Registry`] other crates add their own detectors to, and an
+//!   [`Ensemble`](detector::Ensemble) voting across several of them
+//! - [`backend`]: z-scoring of many series at once, on the CPU or a GPU
+//! - [`schema`]: the JSON Schema of the alerts the service publishes
+//! - [`calendar`]: civil dates to and from days since the Unix epoch
+//! - `snapshot`: versioned snapshots of detector state and baselines, with
+//!   the upgrades from earlier versions (`serde` feature)
+//!
+//! The `serde` feature derives `Serialize`/`Deserialize` for the public types,
+//! the `pyo3` feature converts [`Severity`] and [`Breach`] to and from Python
+//! strings, and the `gpu` feature adds the wgpu backend.
+
+pub mod backend;
+pub mod calendar;
+pub mod detector;
+pub mod outlier;
+#[cfg(feature = "pyo3")]
+mod python;
+pub mod rules;
+pub mod schema;
+pub mod severity;
+pub mod sketch;
+#[cfg(feature = "serde")]
+pub mod snapshot;
+pub mod stats;
+pub mod tdigest;
+pub mod threshold;
+
+pub use backend::Backend;
+pub use detector::{Detector, Registry};
+pub use outlier::{
+    Outlier, ewma_outliers, iqr_outliers, mad_outliers, rolling_outliers, zscore_outliers,
+};
+pub use rules::{RuleError, Rules, rule_outliers};
+pub use severity::{Severity, SeverityBands, SeverityLabels, UnknownLabel};
+pub use tdigest::TDigest;
+pub use threshold::{
+    Alert, Breach, BreachBands, Hysteresis, RateLimit, ThresholdTracker, check_rate_of_change,
+    check_thresholds, check_thresholds_graded,
+};
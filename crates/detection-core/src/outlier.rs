@@ -0,0 +1,169 @@
+//! Batch outlier detection over readings in order, graded with
+//! [`SeverityBands`] on the magnitude of the score.
+//!
+//! Z-score, MAD and IQR score every reading against the whole batch; EWMA
+//! and rolling windows score each reading against the ones before it, so a
+//! slowly moving level is not flagged.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::stats::{Ewma, IqrScorer, MadScorer, ZScorer, summarize};
+use crate::{Severity, SeverityBands};
+
+/// A reading scored past the threshold.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Outlier {
+    pub reading_id: i64,
+    pub value: f64,
+    /// The z-score, or the detector's equivalent: the modified z-score for
+    /// MAD, the distance past the quartiles in IQRs for IQR.
+    pub score: f64,
+    pub severity: Severity,
+}
+
+fn outliers(
+    readings: &[(i64, f64)],
+    bands: &SeverityBands,
+    mut score: impl FnMut(f64) -> Option<f64>,
+) -> Vec<Outlier> {
+    readings
+        .iter()
+        .filter_map(|&(reading_id, value)| {
+            let score = score(value)?;
+            Some(Outlier {
+                reading_id,
+                value,
+                score,
+                severity: bands.classify(score.abs()),
+            })
+        })
+        .collect()
+}
+
+/// The `(reading_id, value)` readings whose z-score exceeds `threshold`, in
+/// reading order.
+pub fn zscore_outliers(
+    readings: &[(i64, f64)],
+    threshold: f64,
+    bands: &SeverityBands,
+) -> Vec<Outlier> {
+    let scorer = ZScorer::new(&summarize(readings.iter().map(|r| r.1)), threshold);
+    outliers(readings, bands, |value| scorer.score(value))
+}
+
+/// The `(reading_id, value)` readings whose modified z-score exceeds
+/// `threshold` (3.5 is customary), in reading order. Less thrown off than
+/// [`zscore_outliers`] by several outliers in one batch.
+pub fn mad_outliers(
+    readings: &[(i64, f64)],
+    threshold: f64,
+    bands: &SeverityBands,
+) -> Vec<Outlier> {
+    let values: Vec<f64> = readings.iter().map(|r| r.1).collect();
+    let scorer = MadScorer::new(&values, threshold);
+    outliers(readings, bands, |value| scorer.score(value))
+}
+
+/// The `(reading_id, value)` readings beyond Tukey's fences, more than `k`
+/// interquartile ranges (1.5 is customary) outside the quartiles, in reading
+/// order.
+pub fn iqr_outliers(readings: &[(i64, f64)], k: f64, bands: &SeverityBands) -> Vec<Outlier> {
+    let values: Vec<f64> = readings.iter().map(|r| r.1).collect();
+    let scorer = IqrScorer::new(&values, k);
+    outliers(readings, bands, |value| scorer.score(value))
+}
+
+/// The `(reading_id, value)` readings whose z-score against the
+/// exponentially weighted mean and deviation of the readings before them
+/// exceeds `threshold`. `alpha` in (0, 1] is the weight of each new reading;
+/// scoring starts once `1 / alpha` readings, the average's span, were seen.
+pub fn ewma_outliers(
+    readings: &[(i64, f64)],
+    alpha: f64,
+    threshold: f64,
+    bands: &SeverityBands,
+) -> Vec<Outlier> {
+    let mut ewma = Ewma::new(alpha);
+    outliers(readings, bands, |value| {
+        let warm = ewma.count() as f64 * alpha >= 1.0;
+        let z = ewma.z(value);
+        ewma.push(value);
+        (warm && z.abs() > threshold).then_some(z)
+    })
+}
+
+/// The `(reading_id, value)` readings whose z-score against the `window`
+/// readings before them exceeds `threshold`; the first `window` readings are
+/// not scored.
+pub fn rolling_outliers(
+    readings: &[(i64, f64)],
+    window: usize,
+    threshold: f64,
+    bands: &SeverityBands,
+) -> Vec<Outlier> {
+    let values: Vec<f64> = readings.iter().map(|r| r.1).collect();
+    let mut position = 0usize;
+    outliers(readings, bands, |value| {
+        let before = values[position.saturating_sub(window)..position]
+            .iter()
+            .copied();
+        position += 1;
+        if position <= window {
+            return None;
+        }
+        ZScorer::new(&summarize(before), threshold).score(value)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_outliers_are_graded_with_the_bands() {
+        let mut readings: Vec<(i64, f64)> = (0..20)
+            .map(|i| (i + 1, 10.0 + (i % 5) as f64 * 0.5))
+            .collect();
+        readings.push((21, 100.0));
+        let bands = SeverityBands::default();
+
+        let zscore = zscore_outliers(&readings, 2.0, &bands);
+        assert_eq!(zscore.len(), 1);
+        assert_eq!(zscore[0].reading_id, 21);
+        assert_eq!(zscore[0].severity, Severity::Critical);
+
+        let mad = mad_outliers(&readings, 3.5, &bands);
+        assert_eq!(
+            mad.iter().map(|o| o.reading_id).collect::<Vec<_>>(),
+            vec![21]
+        );
+        assert!(zscore_outliers(&[(1, 5.0), (2, 5.0)], 2.0, &bands).is_empty());
+    }
+
+    #[test]
+    fn test_sequential_detectors_follow_a_drifting_level() {
+        // A steady climb with some jitter, and one jump out of line.
+        let mut readings: Vec<(i64, f64)> = (0..40)
+            .map(|i| (i + 1, i as f64 + [0.0, 0.8, -0.5, 0.3][i as usize % 4]))
+            .collect();
+        readings[30].1 += 15.0;
+        let bands = SeverityBands::default();
+        let flagged = |outliers: Vec<Outlier>| -> Vec<i64> {
+            outliers.iter().map(|o| o.reading_id).collect()
+        };
+
+        assert_eq!(
+            flagged(rolling_outliers(&readings, 8, 3.0, &bands)),
+            vec![31]
+        );
+        assert_eq!(
+            flagged(ewma_outliers(&readings, 0.3, 3.0, &bands)),
+            vec![31]
+        );
+        // Scored against the whole batch, the jump is within the spread of the climb.
+        assert!(zscore_outliers(&readings, 3.0, &bands).is_empty());
+        assert!(iqr_outliers(&readings, 1.5, &bands).is_empty());
+    }
+}
@@ -0,0 +1,49 @@
+//! Python conversions of the labels, for the PyO3 bindings: each is a `str`
+//! in Python, and a `str` that is none of the enum's raises `ValueError`.
+//! Severities are returned with the installed
+//! [`SeverityLabels`](crate::SeverityLabels) and accept them back.
+
+use std::convert::Infallible;
+use std::str::FromStr;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyString;
+
+use crate::{Breach, Severity};
+
+macro_rules! label_conversions {
+    ($label:ty, $show:ident, $parse:path) => {
+        impl<'py> IntoPyObject<'py> for $label {
+            type Target = PyString;
+            type Output = Bound<'py, PyString>;
+            type Error = Infallible;
+
+            fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Infallible> {
+                Ok(PyString::new(py, &self.$show()))
+            }
+        }
+
+        impl<'py> IntoPyObject<'py> for &$label {
+            type Target = PyString;
+            type Output = Bound<'py, PyString>;
+            type Error = Infallible;
+
+            fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Infallible> {
+                (*self).into_pyobject(py)
+            }
+        }
+
+        impl<'a, 'py> FromPyObject<'a, 'py> for $label {
+            type Error = PyErr;
+
+            fn extract(obj: Borrowed<'a, 'py, PyAny>) -> PyResult<Self> {
+                let label: &str = obj.extract()?;
+                $parse(label).map_err(|e| PyValueError::new_err(e.to_string()))
+            }
+        }
+    };
+}
+
+label_conversions!(Severity, label, Severity::from_label);
+label_conversions!(Breach, as_str, Breach::from_str);
@@ -0,0 +1,735 @@
+//! Threshold and severity rules, written once and evaluated the same way by
+//! the service's sensor registry and the Python bindings.
+//!
+//! A rule is a condition on a reading and the verdict it gives:
+//!
+//! ```text
+//! value > 95 => critical
+//! abs(score) > 4 and value > 80 => high
+//! value < 10 => normal
+//! ```
+//!
+//! Conditions read `value`, the reading, and `score`, its z-score or the
+//! detector's equivalent. They combine `+ - * /`, the comparisons
+//! `< <= > >= == !=`, `and`, `or`, `not`, parentheses and the functions
+//! `abs(x)`, `min(a, b)` and `max(a, b)`. The verdict is a severity, or
+//! `normal` for a reading that is not an anomaly whatever the detector says.
+//!
+//! [`Rules`] are tried in order and the first whose condition holds decides;
+//! a reading no rule matches keeps the detector's grade, see
+//! [`Rules::grade`].
+
+use std::fmt;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::stats::{ZScorer, summarize};
+use crate::{Outlier, Severity, SeverityBands};
+
+/// What a condition is evaluated on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Reading {
+    pub value: f64,
+    /// The z-score, or the detector's equivalent.
+    pub score: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Normal,
+    Anomaly(Severity),
+}
+
+impl Verdict {
+    fn parse(label: &str) -> Option<Self> {
+        match label {
+            "normal" => Some(Verdict::Normal),
+            _ => Severity::parse(label).map(Verdict::Anomaly),
+        }
+    }
+}
+
+/// A rule that does not parse, with the 1-based rule and column it failed
+/// at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleError {
+    pub rule: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rule {}, column {}: {}",
+            self.rule, self.column, self.message
+        )
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Variable {
+    Value,
+    Score,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Function {
+    Abs,
+    Min,
+    Max,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            _ => None,
+        }
+    }
+
+    fn arity(self) -> usize {
+        match self {
+            Function::Abs => 1,
+            Function::Min | Function::Max => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arithmetic {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+/// A numeric expression.
+#[derive(Clone, Debug, PartialEq)]
+enum Number {
+    Constant(f64),
+    Variable(Variable),
+    Negate(Box<Number>),
+    Arithmetic(Arithmetic, Box<Number>, Box<Number>),
+    Call(Function, Vec<Number>),
+}
+
+impl Number {
+    fn evaluate(&self, reading: &Reading) -> f64 {
+        match self {
+            Number::Constant(value) => *value,
+            Number::Variable(Variable::Value) => reading.value,
+            Number::Variable(Variable::Score) => reading.score,
+            Number::Negate(operand) => -operand.evaluate(reading),
+            Number::Arithmetic(op, left, right) => {
+                let (left, right) = (left.evaluate(reading), right.evaluate(reading));
+                match op {
+                    Arithmetic::Add => left + right,
+                    Arithmetic::Subtract => left - right,
+                    Arithmetic::Multiply => left * right,
+                    Arithmetic::Divide => left / right,
+                }
+            }
+            Number::Call(function, args) => {
+                let arg = |i: usize| args[i].evaluate(reading);
+                match function {
+                    Function::Abs => arg(0).abs(),
+                    Function::Min => arg(0).min(arg(1)),
+                    Function::Max => arg(0).max(arg(1)),
+                }
+            }
+        }
+    }
+}
+
+/// A condition.
+#[derive(Clone, Debug, PartialEq)]
+enum Condition {
+    Compare(Comparison, Number, Number),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    /// Comparisons with NaN, e.g. of a division by zero, are false.
+    fn holds(&self, reading: &Reading) -> bool {
+        match self {
+            Condition::Compare(op, left, right) => {
+                let (left, right) = (left.evaluate(reading), right.evaluate(reading));
+                match op {
+                    Comparison::Less => left < right,
+                    Comparison::LessOrEqual => left <= right,
+                    Comparison::Greater => left > right,
+                    Comparison::GreaterOrEqual => left >= right,
+                    Comparison::Equal => left == right,
+                    Comparison::NotEqual => left != right,
+                }
+            }
+            Condition::And(left, right) => left.holds(reading) && right.holds(reading),
+            Condition::Or(left, right) => left.holds(reading) || right.holds(reading),
+            Condition::Not(operand) => !operand.holds(reading),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(f64),
+    Word(String),
+    Symbol(&'static str),
+}
+
+/// Longest first, so `<=` is not read as `<`.
+const SYMBOLS: [&str; 14] = [
+    "=>", "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "(", ")", ",",
+];
+
+/// Tokens of `source` with the 1-based column each starts at.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, (usize, String)> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_digit()
+                    || chars[i] == '.'
+                    || matches!(chars[i], 'e' | 'E')
+                    || (matches!(chars[i], '+' | '-') && matches!(chars[i - 1], 'e' | 'E')))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| (column, format!("invalid number {:?}", text)))?;
+            tokens.push((Token::Number(number), column));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Word(chars[start..i].iter().collect()), column));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(*symbol))
+                .ok_or((column, format!("unexpected {:?}", c)))?;
+            tokens.push((Token::Symbol(symbol), column));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Either kind of expression, until the parser knows which it needs.
+enum Parsed {
+    Number(Number),
+    Condition(Condition),
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// The column just past the end, for errors at the end of a rule.
+    end: usize,
+}
+
+type Parse<T> = Result<T, (usize, String)>;
+
+impl Parser {
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(_, column)| *column)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Parse<T> {
+        Err((self.column(), message.into()))
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        self.position += usize::from(found);
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w == word);
+        self.position += usize::from(found);
+        found
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Parse<()> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            self.error(format!("expected {:?}", symbol))
+        }
+    }
+
+    /// Parses an expression that must be a condition.
+    fn condition(&mut self, parse: fn(&mut Self) -> Parse<Parsed>) -> Parse<Condition> {
+        let column = self.column();
+        match parse(self)? {
+            Parsed::Condition(condition) => Ok(condition),
+            Parsed::Number(_) => Err((column, "expected a condition, found a number".into())),
+        }
+    }
+
+    /// Parses an expression that must be a number.
+    fn number(&mut self, parse: fn(&mut Self) -> Parse<Parsed>) -> Parse<Number> {
+        let column = self.column();
+        match parse(self)? {
+            Parsed::Number(number) => Ok(number),
+            Parsed::Condition(_) => Err((column, "expected a number, found a condition".into())),
+        }
+    }
+
+    fn or(&mut self) -> Parse<Parsed> {
+        let mut left = self.and()?;
+        while self.eat_word("or") {
+            let right = self.condition(Self::and)?;
+            left = Parsed::Condition(Condition::Or(Box::new(self.joined(left)?), Box::new(right)));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Parse<Parsed> {
+        let mut left = self.not()?;
+        while self.eat_word("and") {
+            let right = self.condition(Self::not)?;
+            left = Parsed::Condition(Condition::And(
+                Box::new(self.joined(left)?),
+                Box::new(right),
+            ));
+        }
+        Ok(left)
+    }
+
+    /// The left side of `and` or `or`.
+    fn joined(&self, left: Parsed) -> Parse<Condition> {
+        match left {
+            Parsed::Condition(condition) => Ok(condition),
+            Parsed::Number(_) => self.error("and/or join conditions, not numbers"),
+        }
+    }
+
+    fn not(&mut self) -> Parse<Parsed> {
+        if self.eat_word("not") {
+            let operand = self.condition(Self::not)?;
+            return Ok(Parsed::Condition(Condition::Not(Box::new(operand))));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Parse<Parsed> {
+        let column = self.column();
+        let left = self.sum()?;
+        let op = match self.peek() {
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            Some(Token::Symbol("==")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            _ => return Ok(left),
+        };
+        let Parsed::Number(left) = left else {
+            return Err((column, "conditions cannot be compared".into()));
+        };
+        self.position += 1;
+        let right = self.number(Self::sum)?;
+        Ok(Parsed::Condition(Condition::Compare(op, left, right)))
+    }
+
+    fn sum(&mut self) -> Parse<Parsed> {
+        self.binary(
+            &[("+", Arithmetic::Add), ("-", Arithmetic::Subtract)],
+            Self::product,
+        )
+    }
+
+    fn product(&mut self) -> Parse<Parsed> {
+        self.binary(
+            &[("*", Arithmetic::Multiply), ("/", Arithmetic::Divide)],
+            Self::unary,
+        )
+    }
+
+    /// Left-associative arithmetic over `operand`s.
+    fn binary(
+        &mut self,
+        ops: &[(&str, Arithmetic)],
+        operand: fn(&mut Self) -> Parse<Parsed>,
+    ) -> Parse<Parsed> {
+        let mut left = operand(self)?;
+        loop {
+            let Some(&(_, op)) = ops.iter().find(|(symbol, _)| self.eat_symbol(symbol)) else {
+                return Ok(left);
+            };
+            let column = self.column();
+            let (Parsed::Number(l), Parsed::Number(r)) = (left, operand(self)?) else {
+                return Err((column, "arithmetic needs numbers, not conditions".into()));
+            };
+            left = Parsed::Number(Number::Arithmetic(op, Box::new(l), Box::new(r)));
+        }
+    }
+
+    fn unary(&mut self) -> Parse<Parsed> {
+        if self.eat_symbol("-") {
+            let operand = self.number(Self::unary)?;
+            return Ok(Parsed::Number(Number::Negate(Box::new(operand))));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Parse<Parsed> {
+        let column = self.column();
+        let Some((token, _)) = self.tokens.get(self.position).cloned() else {
+            // The condition ends at the arrow.
+            return self.error("unexpected \"=>\"");
+        };
+        self.position += 1;
+        match token {
+            Token::Number(value) => Ok(Parsed::Number(Number::Constant(value))),
+            Token::Symbol("(") => {
+                let inner = self.or()?;
+                self.expect_symbol(")")?;
+                Ok(inner)
+            }
+            Token::Word(word) => match word.as_str() {
+                "value" => Ok(Parsed::Number(Number::Variable(Variable::Value))),
+                "score" => Ok(Parsed::Number(Number::Variable(Variable::Score))),
+                name => {
+                    let function = Function::parse(name).ok_or_else(|| {
+                        (
+                            column,
+                            format!(
+                                "unknown name {:?}; rules read value and score and call abs, min and max",
+                                name
+                            ),
+                        )
+                    })?;
+                    self.expect_symbol("(")?;
+                    let mut args = vec![self.number(Self::or)?];
+                    while self.eat_symbol(",") {
+                        args.push(self.number(Self::or)?);
+                    }
+                    self.expect_symbol(")")?;
+                    if args.len() != function.arity() {
+                        return Err((
+                            column,
+                            format!("{} takes {} argument(s)", name, function.arity()),
+                        ));
+                    }
+                    Ok(Parsed::Number(Number::Call(function, args)))
+                }
+            },
+            Token::Symbol(symbol) => Err((column, format!("unexpected {:?}", symbol))),
+        }
+    }
+}
+
+/// One `condition => verdict` rule.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    source: String,
+    condition: Condition,
+    verdict: Verdict,
+}
+
+impl Rule {
+    fn parse(source: &str) -> Parse<Self> {
+        let tokens = tokenize(source)?;
+        let arrow = tokens
+            .iter()
+            .position(|(token, _)| *token == Token::Symbol("=>"))
+            .ok_or((
+                source.chars().count() + 1,
+                "expected \"=> <severity>\" after the condition".to_string(),
+            ))?;
+        let verdict_column = tokens
+            .get(arrow + 1)
+            .map_or(source.chars().count() + 1, |(_, column)| *column);
+        let verdict = match &tokens[arrow + 1..] {
+            [(Token::Word(label), _)] => Verdict::parse(label),
+            _ => None,
+        }
+        .ok_or((
+            verdict_column,
+            "expected one of medium, high, critical and normal after \"=>\"".to_string(),
+        ))?;
+
+        let end = tokens[arrow].1;
+        let mut parser = Parser {
+            tokens: tokens[..arrow].to_vec(),
+            position: 0,
+            end,
+        };
+        let condition = parser.condition(Parser::or)?;
+        if parser.position < parser.tokens.len() {
+            return parser.error("expected \"=>\"");
+        }
+        Ok(Rule {
+            source: source.trim().to_string(),
+            condition,
+            verdict,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    pub fn verdict(&self) -> Verdict {
+        self.verdict
+    }
+
+    pub fn matches(&self, reading: &Reading) -> bool {
+        self.condition.holds(reading)
+    }
+}
+
+/// Rules tried in order, the first matching deciding.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(try_from = "Vec<String>", into = "Vec<String>")
+)]
+pub struct Rules(Vec<Rule>);
+
+impl Rules {
+    pub fn parse<S: AsRef<str>>(rules: impl IntoIterator<Item = S>) -> Result<Self, RuleError> {
+        rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, source)| {
+                Rule::parse(source.as_ref()).map_err(|(column, message)| RuleError {
+                    rule: index + 1,
+                    column,
+                    message,
+                })
+            })
+            .collect::<Result<_, _>>()
+            .map(Rules)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Rule> {
+        self.0.iter()
+    }
+
+    /// The verdict of the first rule matching `reading`, if any does.
+    pub fn evaluate(&self, reading: &Reading) -> Option<Verdict> {
+        self.0
+            .iter()
+            .find(|rule| rule.matches(reading))
+            .map(Rule::verdict)
+    }
+
+    /// The severity of `reading` given the one the detector graded it,
+    /// `None` for a normal reading: a matching rule's verdict, else the
+    /// detector's.
+    pub fn grade(&self, reading: &Reading, detected: Option<Severity>) -> Option<Severity> {
+        match self.evaluate(reading) {
+            Some(Verdict::Anomaly(severity)) => Some(severity),
+            Some(Verdict::Normal) => None,
+            None => detected,
+        }
+    }
+}
+
+impl TryFrom<Vec<String>> for Rules {
+    type Error = RuleError;
+
+    fn try_from(rules: Vec<String>) -> Result<Self, RuleError> {
+        Rules::parse(rules)
+    }
+}
+
+impl From<Rules> for Vec<String> {
+    fn from(rules: Rules) -> Self {
+        rules.0.into_iter().map(|rule| rule.source).collect()
+    }
+}
+
+/// Like [`zscore_outliers`](crate::zscore_outliers), with `rules` deciding
+/// the readings they match: each is scored against the whole batch, and
+/// graded by the first rule matching its value and z-score, else by `bands`
+/// if its z-score exceeds `threshold`.
+pub fn rule_outliers(
+    readings: &[(i64, f64)],
+    rules: &Rules,
+    threshold: f64,
+    bands: &SeverityBands,
+) -> Vec<Outlier> {
+    let scorer = ZScorer::new(&summarize(readings.iter().map(|r| r.1)), threshold);
+    readings
+        .iter()
+        .filter_map(|&(reading_id, value)| {
+            let detected = scorer.score(value).map(|z| bands.classify(z.abs()));
+            let score = scorer.z(value);
+            let severity = rules.grade(&Reading { value, score }, detected)?;
+            Some(Outlier {
+                reading_id,
+                value,
+                score,
+                severity,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(sources: &[&str]) -> Rules {
+        Rules::parse(sources).unwrap()
+    }
+
+    fn error(source: &str) -> RuleError {
+        Rules::parse([source]).unwrap_err()
+    }
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let rules = rules(&[
+            "value > 95 => critical",
+            "abs(score) > 4 and value > 80 => high",
+            "value < 10 or not (score < 3) => normal",
+        ]);
+        let grade = |value, score, detected| rules.grade(&Reading { value, score }, detected);
+        assert_eq!(grade(96.0, 0.0, None), Some(Severity::Critical));
+        assert_eq!(
+            grade(85.0, -4.5, Some(Severity::Medium)),
+            Some(Severity::High)
+        );
+        assert_eq!(grade(5.0, 9.0, Some(Severity::Critical)), None);
+        assert_eq!(grade(50.0, 3.5, Some(Severity::High)), None);
+        // No rule matches: the detector's grade stands.
+        assert_eq!(
+            grade(50.0, 2.5, Some(Severity::Medium)),
+            Some(Severity::Medium)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_and_functions() {
+        let rules = rules(&[
+            "max(value, 0) * 2 - 1e1 >= -(-30) / 3 + min(score, 20) => high",
+            "value / 0 > 1 => critical",
+        ]);
+        assert_eq!(
+            rules.evaluate(&Reading {
+                value: 10.0,
+                score: 0.0
+            }),
+            Some(Verdict::Anomaly(Severity::High))
+        );
+        // Division by zero gives infinity, and 0/0 NaN, which compares false.
+        assert_eq!(
+            rules.evaluate(&Reading {
+                value: 1.0,
+                score: 0.0
+            }),
+            Some(Verdict::Anomaly(Severity::Critical))
+        );
+        assert_eq!(rules.evaluate(&Reading::default()), None);
+    }
+
+    #[test]
+    fn test_errors_name_rule_and_column() {
+        assert_eq!(
+            Rules::parse(["value > 1 => high", "value > => high"]).unwrap_err(),
+            RuleError {
+                rule: 2,
+                column: 9,
+                message: "unexpected \"=>\"".to_string(),
+            }
+        );
+        assert!(error("value > 1").message.contains("=>"));
+        assert!(error("value > 1 => severe").message.contains("medium"));
+        assert_eq!(error("pressure > 1 => high").column, 1);
+        assert!(error("value + 1 => high").message.contains("condition"));
+        assert!(
+            error("value > 1 + (score < 2) => high")
+                .message
+                .contains("number")
+        );
+        assert!(
+            error("abs(value, 2) > 1 => high")
+                .message
+                .contains("argument")
+        );
+        assert!(
+            error("value > 1 and 2 => high")
+                .message
+                .contains("condition")
+        );
+        assert_eq!(error("value # 1 => high").column, 7);
+    }
+
+    #[test]
+    fn test_rule_outliers_grade_with_rules_then_bands() {
+        let mut readings: Vec<(i64, f64)> = (0..20)
+            .map(|i| (i + 1, 10.0 + (i % 5) as f64 * 0.5))
+            .collect();
+        readings.push((21, 100.0));
+        readings.push((22, 0.5));
+
+        let bands = SeverityBands::default();
+        let plain = rule_outliers(&readings, &Rules::default(), 2.0, &bands);
+        assert_eq!(plain, crate::zscore_outliers(&readings, 2.0, &bands));
+
+        let rules = rules(&["value < 1 => high", "value > 90 => normal"]);
+        let found: Vec<(i64, Severity)> = rule_outliers(&readings, &rules, 2.0, &bands)
+            .iter()
+            .map(|o| (o.reading_id, o.severity))
+            .collect();
+        assert_eq!(found, [(22, Severity::High)]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_rules_serialize_as_their_sources() {
+        let rules: Rules = serde_json::from_str(r#"["value > 1 => high"]"#).unwrap();
+        assert_eq!(
+            serde_json::to_string(&rules).unwrap(),
+            r#"["value > 1 => high"]"#
+        );
+        assert!(serde_json::from_str::<Rules>(r#"["value >"]"#).is_err());
+    }
+}
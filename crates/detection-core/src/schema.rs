@@ -0,0 +1,34 @@
+//! The JSON Schema of the alert format: the anomalies the service publishes
+//! to webhooks and WebSocket subscribers, for consumers to code against.
+//!
+//! [`ALERT_VERSION`] is raised with any change to the schema, additions
+//! included, since it admits no fields but its own.
+
+/// The schema, a JSON Schema (draft 2020-12) document.
+pub const ALERT: &str = include_str!("../schemas/alert.json");
+
+/// The schema's `version`.
+pub const ALERT_VERSION: u32 = 4;
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::Severity;
+
+    #[test]
+    fn test_alert_schema_matches_the_types() {
+        let schema: Value = serde_json::from_str(ALERT).unwrap();
+        assert_eq!(schema["version"], ALERT_VERSION);
+
+        let labels: Vec<&str> = Severity::ALL.iter().map(|s| s.as_str()).collect();
+        assert_eq!(
+            schema["properties"]["severity"]["examples"],
+            serde_json::json!(labels)
+        );
+        for field in schema["required"].as_array().unwrap() {
+            assert!(schema["properties"].get(field.as_str().unwrap()).is_some());
+        }
+    }
+}
@@ -0,0 +1,333 @@
+//! Severity labels, ordered from least to most severe.
+//!
+//! [`Severity`] and [`Breach`](crate::Breach) are the only forms the labels
+//! take: they are parsed once, at the edge, with [`UnknownLabel`] naming the
+//! accepted ones, and written with `as_str`, so a misspelt label is neither
+//! produced nor accepted.
+//!
+//! A deployment may show severities under its own [`SeverityLabels`], e.g.
+//! `P3`/`P2`/`P1` or translations. Once installed they are what `label`
+//! returns, and so what responses written with [`labelled`] and the Python
+//! bindings show, and are accepted back alongside the canonical labels.
+//! `as_str`, `Display`, `FromStr` and serialization keep to the canonical
+//! ones, which are what is stored and counted, so whatever was serialized
+//! reads back after the labels change.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::RwLock;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The installed labels; the canonical ones while `None`.
+static LABELS: RwLock<Option<SeverityLabels>> = RwLock::new(None);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "medium" => Some(Severity::Medium),
+            "high" => Some(Severity::High),
+            "critical" => Some(Severity::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+
+    pub const ALL: [Severity; 3] = [Severity::Medium, Severity::High, Severity::Critical];
+
+    /// The next severity up, staying at `Critical`.
+    pub fn raised(self) -> Self {
+        match self {
+            Severity::Medium => Severity::High,
+            Severity::High | Severity::Critical => Severity::Critical,
+        }
+    }
+
+    /// The label shown for this severity under the installed labels.
+    pub fn label(self) -> Cow<'static, str> {
+        match &*LABELS.read().unwrap() {
+            Some(labels) => Cow::Owned(labels.get(self).to_string()),
+            None => Cow::Borrowed(self.as_str()),
+        }
+    }
+
+    /// The severity with canonical or installed label `label`.
+    pub fn from_label(label: &str) -> Result<Self, UnknownLabel> {
+        let labels = LABELS.read().unwrap();
+        let installed = labels.as_ref().and_then(|labels| labels.find(label));
+        installed.or_else(|| Severity::parse(label)).ok_or_else(|| {
+            let mut expected: Vec<String> = Severity::ALL
+                .iter()
+                .map(|s| s.as_str().to_string())
+                .collect();
+            if let Some(labels) = &*labels {
+                expected.extend(Severity::ALL.iter().map(|&s| labels.get(s).to_string()));
+            }
+            UnknownLabel {
+                kind: "severity",
+                label: label.to_string(),
+                expected,
+            }
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for Severity {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Serializes a severity under the installed labels, for the fields of
+/// responses: `#[serde(serialize_with = "detection_core::severity::labelled")]`.
+#[cfg(feature = "serde")]
+pub fn labelled<S: Serializer>(severity: &Severity, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&severity.label())
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for Severity {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let label = String::deserialize(deserializer)?;
+        Severity::from_label(&label).map_err(serde::de::Error::custom)
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Severity {
+    type Err = UnknownLabel;
+
+    fn from_str(label: &str) -> Result<Self, UnknownLabel> {
+        Severity::parse(label).ok_or_else(|| UnknownLabel {
+            kind: "severity",
+            label: label.to_string(),
+            expected: Severity::ALL
+                .iter()
+                .map(|s| s.as_str().to_string())
+                .collect(),
+        })
+    }
+}
+
+impl TryFrom<String> for Severity {
+    type Error = UnknownLabel;
+
+    fn try_from(label: String) -> Result<Self, UnknownLabel> {
+        label.parse()
+    }
+}
+
+/// A label that is none of its enum's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownLabel {
+    pub(crate) kind: &'static str,
+    pub(crate) label: String,
+    pub(crate) expected: Vec<String>,
+}
+
+impl fmt::Display for UnknownLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "unknown {} {:?}, expected one of {}",
+            self.kind,
+            self.label,
+            self.expected.join(", ")
+        )
+    }
+}
+
+impl std::error::Error for UnknownLabel {}
+
+/// Labels shown in place of `medium`, `high` and `critical`; each left out
+/// keeps its canonical label.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct SeverityLabels {
+    pub medium: String,
+    pub high: String,
+    pub critical: String,
+}
+
+impl Default for SeverityLabels {
+    fn default() -> Self {
+        Self {
+            medium: Severity::Medium.as_str().to_string(),
+            high: Severity::High.as_str().to_string(),
+            critical: Severity::Critical.as_str().to_string(),
+        }
+    }
+}
+
+impl SeverityLabels {
+    pub fn get(&self, severity: Severity) -> &str {
+        match severity {
+            Severity::Medium => &self.medium,
+            Severity::High => &self.high,
+            Severity::Critical => &self.critical,
+        }
+    }
+
+    pub fn find(&self, label: &str) -> Option<Severity> {
+        Severity::ALL.into_iter().find(|&s| self.get(s) == label)
+    }
+
+    /// Labels must be non-empty and tell the severities apart, from each
+    /// other and from the canonical labels of the others.
+    pub fn validate(&self) -> Result<(), String> {
+        for severity in Severity::ALL {
+            let label = self.get(severity);
+            if label.trim().is_empty() {
+                return Err(format!("the {} label is empty", severity));
+            }
+            let clash = Severity::ALL.into_iter().find(|&other| {
+                other != severity && (self.get(other) == label || other.as_str() == label)
+            });
+            if let Some(other) = clash {
+                return Err(format!(
+                    "the {} label {:?} is also {}'s",
+                    severity, label, other
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes these the labels severities are shown with, process-wide.
+    pub fn install(self) {
+        let canonical = self == SeverityLabels::default();
+        *LABELS.write().unwrap() = (!canonical).then_some(self);
+    }
+}
+
+/// Minimum `|z|` for each severity above `medium`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct SeverityBands {
+    pub high: f64,
+    pub critical: f64,
+}
+
+impl Default for SeverityBands {
+    fn default() -> Self {
+        Self {
+            high: 2.5,
+            critical: 3.0,
+        }
+    }
+}
+
+impl SeverityBands {
+    pub fn classify(&self, abs_z: f64) -> Severity {
+        if abs_z > self.critical {
+            Severity::Critical
+        } else if abs_z > self.high {
+            Severity::High
+        } else {
+            Severity::Medium
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.high.is_finite() && self.high > 0.0 && self.critical > self.high {
+            Ok(())
+        } else {
+            Err("critical must be above high, both positive".to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_severity_bands() {
+        let bands = SeverityBands::default();
+        assert_eq!(bands.classify(2.1), Severity::Medium);
+        assert_eq!(bands.classify(2.7), Severity::High);
+        assert_eq!(bands.classify(3.5), Severity::Critical);
+    }
+
+    #[test]
+    fn test_labels_round_trip() {
+        for severity in [Severity::Medium, Severity::High, Severity::Critical] {
+            assert_eq!(Severity::parse(severity.as_str()), Some(severity));
+        }
+        assert_eq!(Severity::parse("low"), None);
+        assert!(Severity::Critical > Severity::High);
+
+        assert_eq!("high".parse(), Ok(Severity::High));
+        let error = "critcal".parse::<Severity>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown severity \"critcal\", expected one of medium, high, critical"
+        );
+        assert_eq!(Severity::Critical.to_string(), "critical");
+    }
+
+    #[test]
+    fn test_installed_labels() {
+        let labels = SeverityLabels {
+            medium: "P3".to_string(),
+            high: "P2".to_string(),
+            critical: "P1".to_string(),
+        };
+        assert_eq!(labels.validate(), Ok(()));
+        assert_eq!(labels.find("P1"), Some(Severity::Critical));
+        let clashing = SeverityLabels {
+            high: "medium".to_string(),
+            ..SeverityLabels::default()
+        };
+        assert!(clashing.validate().is_err());
+        assert!(SeverityLabels::default().validate().is_ok());
+
+        // The only test installing labels, so the others see the canonical ones.
+        labels.install();
+        assert_eq!(Severity::Critical.label(), "P1");
+        assert_eq!(Severity::Critical.as_str(), "critical");
+        assert_eq!(Severity::from_label("P2"), Ok(Severity::High));
+        assert_eq!(Severity::from_label("high"), Ok(Severity::High));
+        // Serialized severities stay canonical, so they read back whatever
+        // the labels become; responses show the labels.
+        #[cfg(feature = "serde")]
+        {
+            let serialized = serde_json::to_string(&Severity::Critical).unwrap();
+            assert_eq!(serialized, "\"critical\"");
+            let shown = labelled(&Severity::Critical, serde_json::value::Serializer).unwrap();
+            assert_eq!(shown, "P1");
+        }
+        assert_eq!(
+            Severity::from_label("P0").unwrap_err().to_string(),
+            "unknown severity \"P0\", expected one of medium, high, critical, P3, P2, P1"
+        );
+        SeverityLabels::default().install();
+        assert_eq!(Severity::Critical.label(), "critical");
+        assert!(Severity::from_label("P1").is_err());
+    }
+}
@@ -0,0 +1,212 @@
+//! Fixed-size summaries of key streams too large to count exactly:
+//! [`CountMinSketch`] estimates how often each key was seen, never below the
+//! true count, and [`HyperLogLog`] estimates how many distinct keys were seen.
+//!
+//! Both hash keys with std's SipHash under fixed keys, so sketches built in
+//! different processes can be merged.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+fn hash(key: &impl Hash) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Per-key counts in `depth` rows of `width` counters; an estimate is off by
+/// at most `e / width` of the total with probability `1 - e^-depth`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Vec<u64>,
+    total: u64,
+}
+
+impl Default for CountMinSketch {
+    /// 2048 by 4, 64 KiB of counters.
+    fn default() -> Self {
+        Self::new(2048, 4)
+    }
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize, depth: usize) -> Self {
+        let (width, depth) = (width.max(1), depth.max(1));
+        Self {
+            width,
+            depth,
+            counters: vec![0; width * depth],
+            total: 0,
+        }
+    }
+
+    /// The counter of a key hashed to `hashed` in each row, from the two
+    /// halves of the hash.
+    fn cells(&self, hashed: u64) -> impl Iterator<Item = usize> + use<> {
+        let (h1, h2) = (hashed & 0xffff_ffff, (hashed >> 32) | 1);
+        let width = self.width as u64;
+        (0..self.depth as u64)
+            .map(move |row| (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize)
+    }
+
+    pub fn add(&mut self, key: &impl Hash, count: u64) {
+        for cell in self.cells(hash(key)) {
+            self.counters[cell] = self.counters[cell].saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+
+    /// How often `key` was added, overestimated by collisions.
+    pub fn estimate(&self, key: &impl Hash) -> u64 {
+        self.cells(hash(key))
+            .map(|cell| self.counters[cell])
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// The sum of all counts added.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Adds the counts of a sketch of the same dimensions; false, leaving
+    /// this one as it was, for one of other dimensions.
+    pub fn merge(&mut self, other: &CountMinSketch) -> bool {
+        if (self.width, self.depth) != (other.width, other.depth) {
+            return false;
+        }
+        for (counter, other) in self.counters.iter_mut().zip(&other.counters) {
+            *counter = counter.saturating_add(*other);
+        }
+        self.total = self.total.saturating_add(other.total);
+        true
+    }
+}
+
+/// Distinct keys in `2^precision` one-byte registers, with a standard error
+/// of about `1.04 / sqrt(2^precision)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    /// Precision 14: 16 KiB, within about 1% of the true count.
+    fn default() -> Self {
+        Self::new(14)
+    }
+}
+
+impl HyperLogLog {
+    /// `precision` is clamped to 4..=18.
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(4, 18);
+        Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    pub fn insert(&mut self, key: &impl Hash) {
+        let hashed = hash(key);
+        let index = (hashed >> (64 - self.precision)) as usize;
+        // The rank of the first set bit after the index bits, with a sentinel
+        // bit so an all-zero remainder still ends.
+        let rest = (hashed << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    /// The estimated number of distinct keys inserted.
+    pub fn estimate(&self) -> f64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate while registers are empty.
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+
+    /// Takes in the keys of a sketch of the same precision; false, leaving
+    /// this one as it was, for one of another.
+    pub fn merge(&mut self, other: &HyperLogLog) -> bool {
+        if self.precision != other.precision {
+            return false;
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_min_never_underestimates() {
+        let mut sketch = CountMinSketch::new(256, 4);
+        for id in 0..10_000i64 {
+            sketch.add(&id, 1);
+        }
+        sketch.add(&42i64, 500);
+
+        assert_eq!(sketch.total(), 10_500);
+        assert!(sketch.estimate(&42i64) >= 501);
+        // e / width of the total, with a little room for the odd row.
+        let bound = (std::f64::consts::E / 256.0 * 10_500.0) as u64;
+        assert!(sketch.estimate(&42i64) <= 501 + bound);
+        assert!((0..100i64).all(|id| sketch.estimate(&id) >= 1));
+
+        let mut other = CountMinSketch::new(256, 4);
+        other.add(&42i64, 9);
+        let before = sketch.estimate(&42i64);
+        assert!(sketch.merge(&other));
+        assert_eq!(sketch.estimate(&42i64), before + 9);
+        assert!(!sketch.merge(&CountMinSketch::default()));
+    }
+
+    #[test]
+    fn test_hyperloglog_estimates_distinct_keys() {
+        let mut sketch = HyperLogLog::default();
+        assert_eq!(sketch.estimate(), 0.0);
+        for _ in 0..3 {
+            for id in 0..200_000i64 {
+                sketch.insert(&(id * 7));
+            }
+        }
+        let estimate = sketch.estimate();
+        assert!((estimate / 200_000.0 - 1.0).abs() < 0.03, "{}", estimate);
+
+        let mut small = HyperLogLog::default();
+        (0..100i64).for_each(|id| small.insert(&id));
+        assert!(
+            (small.estimate() - 100.0).abs() < 3.0,
+            "{}",
+            small.estimate()
+        );
+
+        let mut other = HyperLogLog::default();
+        (100..200i64).for_each(|id| other.insert(&id));
+        assert!(small.merge(&other));
+        assert!(
+            (small.estimate() - 200.0).abs() < 6.0,
+            "{}",
+            small.estimate()
+        );
+        assert!(!small.merge(&HyperLogLog::new(10)));
+    }
+}
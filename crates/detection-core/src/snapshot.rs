@@ -0,0 +1,150 @@
+//! Versioned snapshots of detector state and baselines, so state saved by
+//! one release still loads in the next after its struct changes.
+//!
+//! A snapshot is `{"version": N, "state": ...}`. A type whose fields change
+//! raises its [`Snapshot::VERSION`] and gives [`Snapshot::upgrade`] a step
+//! from the version before; [`load`] runs the steps from whatever version
+//! was saved up to the current one. State serialized bare, before snapshots
+//! were versioned, is version 0.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+
+use crate::stats::{Reservoir, RunningStats};
+use crate::tdigest::TDigest;
+
+pub trait Snapshot: Serialize + DeserializeOwned {
+    /// The version of the format the type serializes to now.
+    const VERSION: u32;
+
+    /// Turns `state` of version `from` into version `from + 1`.
+    fn upgrade(from: u32, state: Value) -> Result<Value, String>;
+}
+
+/// Version 1 only wrapped the state serialized until then.
+fn unversioned(from: u32, state: Value) -> Result<Value, String> {
+    match from {
+        0 => Ok(state),
+        _ => Err(format!("no upgrade from version {}", from)),
+    }
+}
+
+impl Snapshot for RunningStats {
+    const VERSION: u32 = 1;
+
+    fn upgrade(from: u32, state: Value) -> Result<Value, String> {
+        unversioned(from, state)
+    }
+}
+
+impl Snapshot for Reservoir {
+    const VERSION: u32 = 1;
+
+    fn upgrade(from: u32, state: Value) -> Result<Value, String> {
+        unversioned(from, state)
+    }
+}
+
+impl Snapshot for TDigest {
+    const VERSION: u32 = 1;
+
+    fn upgrade(from: u32, state: Value) -> Result<Value, String> {
+        unversioned(from, state)
+    }
+}
+
+pub fn save<T: Snapshot>(state: &T) -> Value {
+    json!({
+        "version": T::VERSION,
+        "state": serde_json::to_value(state).expect("state serializes"),
+    })
+}
+
+/// Reads a snapshot of any version up to the current one.
+pub fn load<T: Snapshot>(snapshot: Value) -> Result<T, String> {
+    let (mut version, mut state) = match snapshot {
+        Value::Object(mut fields)
+            if fields.len() == 2
+                && fields.contains_key("version")
+                && fields.contains_key("state") =>
+        {
+            let version = fields["version"]
+                .as_u64()
+                .and_then(|version| u32::try_from(version).ok())
+                .ok_or_else(|| format!("invalid snapshot version {}", fields["version"]))?;
+            (version, fields.remove("state").unwrap_or_default())
+        }
+        bare => (0, bare),
+    };
+    if version > T::VERSION {
+        return Err(format!(
+            "snapshot version {} is newer than this release reads ({})",
+            version,
+            T::VERSION
+        ));
+    }
+    while version < T::VERSION {
+        state = T::upgrade(version, state)
+            .map_err(|e| format!("upgrading snapshot version {}: {}", version, e))?;
+        version += 1;
+    }
+    serde_json::from_value(state).map_err(|e| format!("snapshot version {}: {}", version, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    #[test]
+    fn test_loads_current_and_unversioned_state() {
+        let mut reservoir = Reservoir::new(8, 3);
+        (0..20).for_each(|i| reservoir.push(i as f64));
+
+        let snapshot = save(&reservoir);
+        assert_eq!(snapshot["version"], 1);
+        assert_eq!(load::<Reservoir>(snapshot).unwrap(), reservoir);
+        let bare = serde_json::to_value(&reservoir).unwrap();
+        assert_eq!(load::<Reservoir>(bare).unwrap(), reservoir);
+
+        let mut stats = RunningStats::default();
+        [1.0, 2.0, 4.0].into_iter().for_each(|v| stats.push(v));
+        assert_eq!(load::<RunningStats>(save(&stats)).unwrap(), stats);
+
+        let newer = json!({ "version": 2, "state": {} });
+        let error = load::<TDigest>(newer).unwrap_err();
+        assert!(error.contains("newer"), "{}", error);
+    }
+
+    /// A baseline that was `{"total", "n"}` in version 1 and `{"sum",
+    /// "count"}` in version 2.
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Baseline {
+        sum: f64,
+        count: u64,
+    }
+
+    impl Snapshot for Baseline {
+        const VERSION: u32 = 2;
+
+        fn upgrade(from: u32, state: Value) -> Result<Value, String> {
+            match from {
+                1 => Ok(json!({ "sum": state["total"], "count": state["n"] })),
+                _ => unversioned(from, state),
+            }
+        }
+    }
+
+    #[test]
+    fn test_upgrades_through_each_version() {
+        let baseline = Baseline { sum: 6.5, count: 3 };
+        let old = json!({ "total": 6.5, "n": 3 });
+        assert_eq!(load::<Baseline>(old.clone()).unwrap(), baseline);
+        let v1 = json!({ "version": 1, "state": old });
+        assert_eq!(load::<Baseline>(v1).unwrap(), baseline);
+        assert_eq!(load::<Baseline>(save(&baseline)).unwrap(), baseline);
+        assert!(load::<Baseline>(json!({ "version": 2, "state": { "sum": 1.0 } })).is_err());
+    }
+}
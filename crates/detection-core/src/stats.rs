@@ -0,0 +1,773 @@
+//! Statistics kernels used by the detectors.
+//!
+//! Mean and variance are computed in a single streaming pass with Welford's
+//! algorithm. Values are fed to `LANES` independent accumulators that all
+//! share the same count, so each step needs one reciprocal instead of a
+//! division per value and LLVM can keep the lanes in vector registers. The
+//! lanes are merged with Chan's parallel formula at the end.
+//!
+//! [`MadScorer`] and [`IqrScorer`] are the robust alternatives: medians and
+//! quartiles are not dragged along by the outliers being looked for. [`Ewma`]
+//! tracks a series whose level moves, weighting recent values most, a
+//! [`Reservoir`] keeps a uniform sample of a stream's whole history, and
+//! [`HoltWinters`] forecasts a series with a trend and a season.
+
+const LANES: usize = 8;
+
+/// Running count, mean and sum of squared deviations (Welford).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct RunningStats {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Combines two accumulators as if all their values had been pushed into one.
+    pub fn merge(&mut self, other: &RunningStats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 +=
+            other.m2 + delta * delta * (self.count as f64 * other.count as f64) / count as f64;
+        self.count = count;
+    }
+
+    /// Takes back a value pushed before, as a sliding window drops its
+    /// oldest value.
+    pub fn remove(&mut self, value: f64) {
+        if self.count <= 1 {
+            *self = RunningStats::default();
+            return;
+        }
+        let delta = value - self.mean;
+        self.count -= 1;
+        self.mean -= delta / self.count as f64;
+        self.m2 = (self.m2 - delta * (value - self.mean)).max(0.0);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Sample standard deviation; zero for fewer than two values.
+    pub fn std_dev(&self) -> f64 {
+        if self.count <= 1 {
+            return 0.0;
+        }
+        (self.m2 / (self.count - 1) as f64).sqrt()
+    }
+}
+
+/// A standard normal draw, by the Box-Muller transform, from `u` uniform
+/// in `(0, 1]` and `v` uniform in `[0, 1)`.
+pub fn box_muller(u: f64, v: f64) -> f64 {
+    (-2.0 * u.ln()).sqrt() * (std::f64::consts::TAU * v).cos()
+}
+
+/// Computes count, mean and standard deviation of `values` in one pass.
+pub fn summarize(values: impl IntoIterator<Item = f64>) -> RunningStats {
+    let mut values = values.into_iter();
+    let mut means = [0.0; LANES];
+    let mut m2s = [0.0; LANES];
+    let mut rows = 0u64;
+    let mut tail = RunningStats::default();
+
+    loop {
+        let mut chunk = [0.0; LANES];
+        let mut filled = 0;
+        while filled < LANES {
+            match values.next() {
+                Some(v) => {
+                    chunk[filled] = v;
+                    filled += 1;
+                }
+                None => break,
+            }
+        }
+
+        if filled < LANES {
+            for &v in &chunk[..filled] {
+                tail.push(v);
+            }
+            break;
+        }
+
+        rows += 1;
+        let inv_rows = 1.0 / rows as f64;
+        for lane in 0..LANES {
+            let delta = chunk[lane] - means[lane];
+            means[lane] += delta * inv_rows;
+            m2s[lane] += delta * (chunk[lane] - means[lane]);
+        }
+    }
+
+    let mut stats = RunningStats::default();
+    for lane in 0..LANES {
+        stats.merge(&RunningStats {
+            count: rows,
+            mean: means[lane],
+            m2: m2s[lane],
+        });
+    }
+    stats.merge(&tail);
+    stats
+}
+
+/// Scores values against a fixed mean and standard deviation.
+pub struct ZScorer {
+    mean: f64,
+    std_dev: f64,
+    limit: f64,
+}
+
+impl ZScorer {
+    pub fn new(stats: &RunningStats, threshold: f64) -> Self {
+        let std_dev = stats.std_dev();
+        Self {
+            mean: stats.mean(),
+            std_dev,
+            limit: threshold * std_dev,
+        }
+    }
+
+    /// Returns the z-score of `value` if its magnitude exceeds the threshold.
+    ///
+    /// Compares `|v - mean|` against `threshold * std_dev` so the common
+    /// non-anomalous case needs no division.
+    pub fn score(&self, value: f64) -> Option<f64> {
+        if self.std_dev > 0.0 && (value - self.mean).abs() > self.limit {
+            Some(self.z(value))
+        } else {
+            None
+        }
+    }
+
+    /// The z-score of `value` whatever the threshold; 0 when the readings do
+    /// not vary.
+    pub fn z(&self, value: f64) -> f64 {
+        if self.std_dev > 0.0 {
+            (value - self.mean) / self.std_dev
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Scales the median absolute deviation to the standard deviation of normally
+/// distributed values, so modified z-scores read like z-scores.
+const MAD_SCALE: f64 = 0.6745;
+
+fn median_of(values: &mut [f64]) -> f64 {
+    let odd = values.len() % 2 == 1;
+    let (lower, upper, _) = values.select_nth_unstable_by(values.len() / 2, f64::total_cmp);
+    if odd {
+        *upper
+    } else {
+        // The lower half holds the values below the middle one, its largest
+        // being the other middle value.
+        let below = lower.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (below + *upper) / 2.0
+    }
+}
+
+/// Scores values by their modified z-score, `0.6745 * (v - median) / MAD`
+/// (Iglewicz and Hoaglin), against the values it was built from.
+pub struct MadScorer {
+    median: f64,
+    mad: f64,
+    threshold: f64,
+}
+
+impl MadScorer {
+    pub fn new(values: &[f64], threshold: f64) -> Self {
+        if values.is_empty() {
+            return Self {
+                median: 0.0,
+                mad: 0.0,
+                threshold,
+            };
+        }
+        let mut scratch = values.to_vec();
+        let median = median_of(&mut scratch);
+        let mut deviations: Vec<f64> = values.iter().map(|v| (v - median).abs()).collect();
+        Self {
+            median,
+            mad: median_of(&mut deviations),
+            threshold,
+        }
+    }
+
+    pub fn median(&self) -> f64 {
+        self.median
+    }
+
+    pub fn mad(&self) -> f64 {
+        self.mad
+    }
+
+    /// Returns the modified z-score of `value` if its magnitude exceeds the
+    /// threshold.
+    pub fn score(&self, value: f64) -> Option<f64> {
+        let z = self.z(value);
+        (z.abs() > self.threshold).then_some(z)
+    }
+
+    /// The modified z-score of `value` whatever the threshold; 0 when more
+    /// than half of the values are equal.
+    pub fn z(&self, value: f64) -> f64 {
+        if self.mad > 0.0 {
+            MAD_SCALE * (value - self.median) / self.mad
+        } else {
+            0.0
+        }
+    }
+}
+
+/// The `q` quantile of sorted `values`, interpolating linearly between the
+/// two nearest ranks.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    let position = q * (sorted.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (position - lower as f64)
+}
+
+/// Scores values by how far past the quartiles they lie, in interquartile
+/// ranges (Tukey's fences): a value is flagged beyond `Q1 - k * IQR` or
+/// `Q3 + k * IQR`.
+pub struct IqrScorer {
+    q1: f64,
+    q3: f64,
+    k: f64,
+}
+
+impl IqrScorer {
+    pub fn new(values: &[f64], k: f64) -> Self {
+        if values.is_empty() {
+            return Self {
+                q1: 0.0,
+                q3: 0.0,
+                k,
+            };
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_unstable_by(f64::total_cmp);
+        Self {
+            q1: quantile(&sorted, 0.25),
+            q3: quantile(&sorted, 0.75),
+            k,
+        }
+    }
+
+    pub fn quartiles(&self) -> (f64, f64) {
+        (self.q1, self.q3)
+    }
+
+    /// Returns the score of `value` if it lies beyond a fence.
+    pub fn score(&self, value: f64) -> Option<f64> {
+        let z = self.z(value);
+        (z.abs() > self.k).then_some(z)
+    }
+
+    /// Distance of `value` past the nearer quartile in interquartile ranges,
+    /// negative below `Q1`; 0 between the quartiles or when they are equal.
+    pub fn z(&self, value: f64) -> f64 {
+        let iqr = self.q3 - self.q1;
+        if iqr <= 0.0 {
+            0.0
+        } else if value > self.q3 {
+            (value - self.q3) / iqr
+        } else if value < self.q1 {
+            (value - self.q1) / iqr
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Exponentially weighted mean and variance: each value pushed weighs
+/// `alpha`, and what came before `1 - alpha`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Ewma {
+    alpha: f64,
+    count: u64,
+    mean: f64,
+    variance: f64,
+}
+
+impl Ewma {
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            count: 0,
+            mean: 0.0,
+            variance: 0.0,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.mean = value;
+        } else {
+            let delta = value - self.mean;
+            self.mean += self.alpha * delta;
+            self.variance = (1.0 - self.alpha) * (self.variance + self.alpha * delta * delta);
+        }
+        self.count += 1;
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// The z-score of `value` against the weighted mean and standard
+    /// deviation; 0 while the values pushed have not varied.
+    pub fn z(&self, value: f64) -> f64 {
+        let std_dev = self.std_dev();
+        if std_dev > 0.0 {
+            (value - self.mean) / std_dev
+        } else {
+            0.0
+        }
+    }
+}
+
+/// A uniform sample of at most `capacity` of the values pushed so far
+/// (Vitter's algorithm R): every value is kept with the same probability,
+/// so a baseline drawn from it covers the whole stream in fixed memory.
+///
+/// The sample is drawn with a seeded generator, so the same values and seed
+/// keep the same sample.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct Reservoir {
+    capacity: usize,
+    values: Vec<f64>,
+    seen: u64,
+    rng: u64,
+}
+
+impl Reservoir {
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            values: Vec::new(),
+            seen: 0,
+            rng: seed,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.seen += 1;
+        if self.values.len() < self.capacity {
+            self.values.push(value);
+            return;
+        }
+        let slot = self.next() % self.seen;
+        if let Some(kept) = self.values.get_mut(slot as usize) {
+            *kept = value;
+        }
+    }
+
+    /// splitmix64.
+    fn next(&mut self) -> u64 {
+        self.rng = self.rng.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// The sampled values, in no particular order.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// How many values were pushed.
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+
+    /// Mean and standard deviation of the sample.
+    pub fn stats(&self) -> RunningStats {
+        summarize(self.values.iter().copied())
+    }
+}
+
+/// How many residual standard deviations from the forecast a value may lie
+/// before [`HoltWinters`] updates with it as if it lay at that distance.
+const HOLT_WINTERS_CLAMP: f64 = 3.0;
+
+/// Weight of each new residual in the deviation [`HoltWinters`] scores by.
+const HOLT_WINTERS_RESIDUAL_WEIGHT: f64 = 0.1;
+
+/// Additive Holt-Winters, or triple exponential smoothing: a level, a trend
+/// and an offset for each of the `period` phases of a season, fitted to the
+/// first two seasons of values and then updated with each value, weighing
+/// it `alpha`, `beta` and `gamma` respectively.
+///
+/// Values are scored by their residual from the one-step forecast, in
+/// exponentially weighted standard deviations of the residuals before them.
+/// A value further than three of those from its forecast updates the model
+/// as if it lay three away, so an anomaly does not carry into the seasons
+/// after it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct HoltWinters {
+    period: usize,
+    alpha: f64,
+    beta: f64,
+    gamma: f64,
+    /// The first two seasons, until the model is fitted to them.
+    training: Vec<f64>,
+    level: f64,
+    trend: f64,
+    /// Empty until fitted.
+    seasonal: Vec<f64>,
+    step: usize,
+    residuals: Ewma,
+}
+
+impl HoltWinters {
+    pub fn new(period: usize, alpha: f64, beta: f64, gamma: f64) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            alpha,
+            beta,
+            gamma,
+            training: Vec::with_capacity(2 * period),
+            level: 0.0,
+            trend: 0.0,
+            seasonal: Vec::new(),
+            step: 0,
+            residuals: Ewma::new(HOLT_WINTERS_RESIDUAL_WEIGHT),
+        }
+    }
+
+    pub fn period(&self) -> usize {
+        self.period
+    }
+
+    /// Whether the first two seasons were pushed.
+    pub fn is_fitted(&self) -> bool {
+        !self.seasonal.is_empty()
+    }
+
+    /// The forecast of the next value, once fitted.
+    pub fn forecast(&self) -> Option<f64> {
+        self.is_fitted()
+            .then(|| self.level + self.trend + self.seasonal[self.step % self.period])
+    }
+
+    pub fn level(&self) -> f64 {
+        self.level
+    }
+
+    /// The weighted standard deviation of the residuals.
+    pub fn residual_std_dev(&self) -> f64 {
+        self.residuals.std_dev()
+    }
+
+    /// The residual z-score of `value` as the next value; 0 until fitted or
+    /// while the residuals have not varied.
+    pub fn z(&self, value: f64) -> f64 {
+        self.forecast()
+            .map_or(0.0, |forecast| self.residuals.z(value - forecast))
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.is_fitted() {
+            self.update(value, true);
+            return;
+        }
+        self.training.push(value);
+        if self.training.len() == 2 * self.period {
+            self.fit();
+        }
+    }
+
+    /// Starts the level, trend and phases from the means of the two
+    /// training seasons, then runs the training values through them.
+    fn fit(&mut self) {
+        let period = self.period;
+        let training = std::mem::take(&mut self.training);
+        let mean = |season: &[f64]| season.iter().sum::<f64>() / period as f64;
+        let (first, second) = (mean(&training[..period]), mean(&training[period..]));
+        self.trend = (second - first) / period as f64;
+        // The first season's mean lies at its middle phase.
+        self.level = first - self.trend * (period + 1) as f64 / 2.0;
+        self.seasonal = (0..period)
+            .map(|phase| {
+                let trend = self.trend * (phase as f64 - (period - 1) as f64 / 2.0);
+                (training[phase] - first + training[phase + period] - second) / 2.0 - trend
+            })
+            .collect();
+        for value in training {
+            self.update(value, false);
+        }
+    }
+
+    fn update(&mut self, value: f64, clamped: bool) {
+        let phase = self.step % self.period;
+        let forecast = self.level + self.trend + self.seasonal[phase];
+        let mut residual = value - forecast;
+        let bound = HOLT_WINTERS_CLAMP * self.residuals.std_dev();
+        if clamped && bound > 0.0 {
+            residual = residual.clamp(-bound, bound);
+        }
+        let value = forecast + residual;
+        self.residuals.push(residual);
+        let level = self.alpha * (value - self.seasonal[phase])
+            + (1.0 - self.alpha) * (self.level + self.trend);
+        self.trend = self.beta * (level - self.level) + (1.0 - self.beta) * self.trend;
+        self.seasonal[phase] =
+            self.gamma * (value - level) + (1.0 - self.gamma) * self.seasonal[phase];
+        self.level = level;
+        self.step += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_box_muller() {
+        assert_eq!(box_muller(1.0, 0.25), 0.0);
+        assert!((box_muller((-0.5f64).exp(), 0.0) - 1.0).abs() < 1e-12);
+        assert!((box_muller((-0.5f64).exp(), 0.5) + 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calculate_mean() {
+        let values = vec![10.0, 20.0, 30.0, 40.0, 50.0];
+        let stats = summarize(values);
+        assert_eq!(stats.mean(), 30.0);
+    }
+
+    #[test]
+    fn test_calculate_mean_empty() {
+        let stats = summarize(Vec::new());
+        assert_eq!(stats.count(), 0);
+        assert_eq!(stats.mean(), 0.0);
+    }
+
+    #[test]
+    fn test_calculate_mean_spans_chunks_and_remainder() {
+        let stats = summarize((1..=19).map(f64::from));
+        assert_eq!(stats.count(), 19);
+        assert!((stats.mean() - 10.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calculate_std_dev() {
+        let values = vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let stats = summarize(values);
+        assert!((stats.std_dev() - 2.138).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_calculate_std_dev_single_value() {
+        let stats = summarize(vec![42.0]);
+        assert_eq!(stats.std_dev(), 0.0);
+    }
+
+    #[test]
+    fn test_summarize_matches_two_pass() {
+        let values: Vec<f64> = (0..1003).map(|i| ((i * 37) % 101) as f64 * 0.5).collect();
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance =
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64;
+
+        let stats = summarize(values.iter().copied());
+        assert!((stats.mean() - mean).abs() < 1e-9);
+        assert!((stats.std_dev() - variance.sqrt()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_matches_sequential_push() {
+        let mut left = RunningStats::default();
+        let mut right = RunningStats::default();
+        let mut all = RunningStats::default();
+        for i in 0..50 {
+            let v = (i as f64).sin() * 10.0;
+            if i < 20 {
+                left.push(v);
+            } else {
+                right.push(v);
+            }
+            all.push(v);
+        }
+
+        left.merge(&right);
+        assert_eq!(left.count(), all.count());
+        assert!((left.mean() - all.mean()).abs() < 1e-12);
+        assert!((left.std_dev() - all.std_dev()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_remove_matches_the_values_left() {
+        let values: Vec<f64> = (0..50).map(|i| (i as f64).sin() * 10.0 + 100.0).collect();
+        let mut sliding = RunningStats::default();
+        for (i, &v) in values.iter().enumerate() {
+            sliding.push(v);
+            if i >= 10 {
+                sliding.remove(values[i - 10]);
+            }
+        }
+        let last = summarize(values[40..].iter().copied());
+        assert_eq!(sliding.count(), 10);
+        assert!((sliding.mean() - last.mean()).abs() < 1e-9);
+        assert!((sliding.std_dev() - last.std_dev()).abs() < 1e-9);
+
+        sliding = RunningStats::default();
+        sliding.push(5.0);
+        sliding.remove(5.0);
+        assert_eq!(sliding, RunningStats::default());
+    }
+
+    #[test]
+    fn test_zscorer_flags_only_outliers() {
+        let mut values = vec![10.0; 20];
+        values.push(100.0);
+        let scorer = ZScorer::new(&summarize(values), 2.0);
+
+        assert!(scorer.score(10.0).is_none());
+        assert!(scorer.score(100.0).unwrap() > 2.0);
+    }
+
+    #[test]
+    fn test_zscorer_zero_std_dev() {
+        let scorer = ZScorer::new(&summarize(vec![5.0; 10]), 2.0);
+        assert!(scorer.score(5.0).is_none());
+        assert!(scorer.score(50.0).is_none());
+    }
+
+    #[test]
+    fn test_mad_scorer_ignores_the_outliers_it_finds() {
+        let values = [10.0, 11.0, 9.0, 10.5, 9.5, 10.0, 500.0, 450.0];
+        let scorer = MadScorer::new(&values, 3.5);
+        assert_eq!(scorer.median(), 10.25);
+        assert_eq!(scorer.mad(), 0.75);
+
+        assert!(scorer.score(11.0).is_none());
+        assert!(scorer.score(450.0).unwrap() > 3.5);
+        // A z-score over the same values misses the smaller outlier.
+        assert!(ZScorer::new(&summarize(values), 3.5).score(450.0).is_none());
+    }
+
+    #[test]
+    fn test_mad_scorer_constant_values() {
+        let scorer = MadScorer::new(&[5.0, 5.0, 5.0, 9.0], 3.5);
+        assert_eq!(scorer.mad(), 0.0);
+        assert!(scorer.score(50.0).is_none());
+        assert!(MadScorer::new(&[], 3.5).score(1.0).is_none());
+    }
+
+    #[test]
+    fn test_iqr_scorer_uses_tukey_fences() {
+        let scorer = IqrScorer::new(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 100.0], 1.5);
+        assert_eq!(scorer.quartiles(), (3.0, 7.0));
+
+        // The fences lie at 3 - 6 and 7 + 6.
+        assert!(scorer.score(13.0).is_none());
+        assert_eq!(scorer.score(15.0), Some(2.0));
+        assert_eq!(scorer.score(-5.0), Some(-2.0));
+        assert_eq!(IqrScorer::new(&[4.0; 5], 1.5).z(90.0), 0.0);
+    }
+
+    #[test]
+    fn test_ewma_follows_a_moving_level() {
+        let mut ewma = Ewma::new(0.5);
+        for value in [10.0, 10.0, 12.0, 14.0] {
+            ewma.push(value);
+        }
+        assert_eq!(ewma.count(), 4);
+        assert_eq!(ewma.mean(), 12.5);
+        assert!((ewma.std_dev() - 2.75f64.sqrt()).abs() < 1e-12);
+        assert!(ewma.z(12.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_reservoir_samples_the_whole_stream() {
+        let mut reservoir = Reservoir::new(500, 7);
+        for i in 0..100_000 {
+            reservoir.push(i as f64);
+        }
+
+        assert_eq!((reservoir.values().len(), reservoir.seen()), (500, 100_000));
+        // A window of the last 500 values would have a mean near 99 750.
+        let mean = reservoir.stats().mean();
+        assert!((mean - 50_000.0).abs() < 4_000.0, "{}", mean);
+        let early = reservoir.values().iter().filter(|&&v| v < 50_000.0).count();
+        assert!((200..300).contains(&early), "{}", early);
+
+        let mut again = Reservoir::new(500, 7);
+        (0..100_000).for_each(|i| again.push(i as f64));
+        assert_eq!(again, reservoir);
+    }
+
+    #[test]
+    fn test_holt_winters_forecasts_trend_and_season() {
+        let signal = |i: usize| {
+            let phase = (i % 12) as f64 / 12.0 * std::f64::consts::TAU;
+            100.0 + 0.5 * i as f64 + 10.0 * phase.sin()
+        };
+        let mut model = HoltWinters::new(12, 0.3, 0.1, 0.3);
+        for i in 0..23 {
+            model.push(signal(i));
+            assert_eq!(model.forecast(), None);
+        }
+        model.push(signal(23));
+        assert!(model.is_fitted());
+        for i in 24..120 {
+            let forecast = model.forecast().unwrap();
+            assert!((forecast - signal(i)).abs() < 0.5, "{}: {}", i, forecast);
+            model.push(signal(i));
+        }
+
+        // A spike scores high, and the model carries on as before it.
+        let spiked = signal(120) + 30.0;
+        assert!(model.z(spiked) > 10.0, "{}", model.z(spiked));
+        model.push(spiked);
+        for i in 121..145 {
+            model.push(signal(i));
+        }
+        let forecast = model.forecast().unwrap();
+        assert!((forecast - signal(145)).abs() < 0.5, "{}", forecast);
+    }
+}
@@ -0,0 +1,283 @@
+//! Streaming percentiles in bounded memory (Dunning's merging t-digest).
+//!
+//! Values are buffered and periodically merged into centroids, weighted
+//! means of neighbouring values. The `k1` scale function keeps centroids
+//! small near the tails, so extreme percentiles such as p99.5 stay accurate
+//! while the middle is summarized coarsely. A digest holds at most a few
+//! times `compression` centroids whatever the number of values, and the
+//! count, sum, minimum and maximum are kept exactly.
+
+use std::f64::consts::PI;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Default compression: roughly the number of centroids kept.
+pub const DEFAULT_COMPRESSION: f64 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct TDigest {
+    compression: f64,
+    /// Merged centroids, ordered by mean.
+    centroids: Vec<Centroid>,
+    /// Values pushed since the last merge.
+    buffer: Vec<f64>,
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::new(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    /// A digest keeping about `compression` centroids; higher is more
+    /// accurate and larger.
+    pub fn new(compression: f64) -> Self {
+        Self {
+            compression: compression.max(10.0),
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.buffer.push(value);
+        if self.buffer.len() as f64 >= 5.0 * self.compression {
+            self.compress();
+        }
+    }
+
+    /// Combines two digests as if all their values had been pushed into one.
+    pub fn merge(&mut self, other: &TDigest) {
+        if other.count == 0 {
+            return;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend(&other.buffer);
+        self.centroids.extend(&other.centroids);
+        self.compress();
+    }
+
+    /// Merges the buffered values into the centroids.
+    pub fn compress(&mut self) {
+        if self.buffer.is_empty() && self.centroids.len() as f64 <= self.compression {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(
+            self.buffer
+                .drain(..)
+                .map(|mean| Centroid { mean, weight: 1.0 }),
+        );
+        all.sort_unstable_by(|a, b| a.mean.total_cmp(&b.mean));
+
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let k = |q: f64| self.compression / (2.0 * PI) * (2.0 * q - 1.0).asin();
+        let limit = |so_far: f64| {
+            let next = k(so_far / total) + 1.0;
+            if next >= self.compression / 4.0 {
+                total
+            } else {
+                total * ((2.0 * PI * next / self.compression).sin() + 1.0) / 2.0
+            }
+        };
+
+        let mut merged = Vec::with_capacity(all.len().min(self.compression as usize * 2));
+        let mut all = all.into_iter();
+        let Some(mut current) = all.next() else {
+            return;
+        };
+        let mut so_far = 0.0;
+        let mut bound = limit(so_far);
+        for next in all {
+            let weight = current.weight + next.weight;
+            if so_far + weight <= bound {
+                current.mean += (next.mean - current.mean) * next.weight / weight;
+                current.weight = weight;
+            } else {
+                so_far += current.weight;
+                merged.push(current);
+                bound = limit(so_far);
+                current = next;
+            }
+        }
+        merged.push(current);
+        self.centroids = merged;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// Zero for an empty digest.
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// The estimated `q` quantile, `q` in `[0, 1]`; `None` for an empty
+    /// digest. Each centroid stands at the middle of its weight, and values
+    /// between are interpolated, so few values give their exact median.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let merged;
+        let centroids = if self.buffer.is_empty() {
+            &self.centroids
+        } else {
+            let mut copy = self.clone();
+            copy.compress();
+            merged = copy.centroids;
+            &merged
+        };
+
+        let target = q.clamp(0.0, 1.0) * self.count as f64;
+        let first = centroids[0];
+        if target <= first.weight / 2.0 {
+            return Some(interpolate(
+                (0.0, self.min),
+                (first.weight / 2.0, first.mean),
+                target,
+            ));
+        }
+        let mut position = first.weight / 2.0;
+        for pair in centroids.windows(2) {
+            let next = position + (pair[0].weight + pair[1].weight) / 2.0;
+            if target <= next {
+                return Some(interpolate(
+                    (position, pair[0].mean),
+                    (next, pair[1].mean),
+                    target,
+                ));
+            }
+            position = next;
+        }
+        let last = centroids[centroids.len() - 1];
+        Some(interpolate(
+            (position, last.mean),
+            (self.count as f64, self.max),
+            target,
+        ))
+    }
+
+    /// Centroids and buffered values held.
+    pub fn len(&self) -> usize {
+        self.centroids.len() + self.buffer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+}
+
+fn interpolate((x0, y0): (f64, f64), (x1, y1): (f64, f64), x: f64) -> f64 {
+    if x1 <= x0 {
+        return y1;
+    }
+    y0 + (y1 - y0) * ((x - x0) / (x1 - x0)).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_few_values_give_exact_medians() {
+        let mut digest = TDigest::default();
+        assert_eq!(digest.quantile(0.5), None);
+        for value in [4.0, 1.0, 3.0, 2.0] {
+            digest.push(value);
+        }
+        assert_eq!(digest.quantile(0.5), Some(2.5));
+        assert_eq!(digest.quantile(0.0), Some(1.0));
+        assert_eq!(digest.quantile(1.0), Some(4.0));
+        digest.push(5.0);
+        assert_eq!(digest.quantile(0.5), Some(3.0));
+        assert_eq!(
+            (digest.count(), digest.sum(), digest.mean()),
+            (5, 15.0, 3.0)
+        );
+    }
+
+    #[test]
+    fn test_tail_quantiles_in_bounded_memory() {
+        let mut digest = TDigest::default();
+        // A shuffled 0..100_000, so the buffer never sees sorted runs.
+        let n = 100_000u64;
+        for i in 0..n {
+            digest.push(((i * 7_919) % n) as f64);
+        }
+        digest.compress();
+
+        assert!(
+            digest.len() <= 2 * DEFAULT_COMPRESSION as usize,
+            "{}",
+            digest.len()
+        );
+        for (q, tolerance) in [(0.5, 0.01), (0.99, 0.001), (0.995, 0.0005), (0.001, 0.0005)] {
+            let estimate = digest.quantile(q).unwrap() / n as f64;
+            assert!((estimate - q).abs() < tolerance, "p{}: {}", q, estimate);
+        }
+        assert_eq!(digest.min(), Some(0.0));
+        assert_eq!(digest.max(), Some((n - 1) as f64));
+    }
+
+    #[test]
+    fn test_merged_digests_match_one() {
+        let mut whole = TDigest::default();
+        let mut halves = [TDigest::default(), TDigest::default()];
+        for i in 0..10_000 {
+            let value = ((i * 31) % 10_000) as f64;
+            whole.push(value);
+            halves[i % 2].push(value);
+        }
+        let [mut left, right] = halves;
+        left.merge(&right);
+
+        assert_eq!(left.count(), whole.count());
+        for q in [0.01, 0.5, 0.99] {
+            let (a, b) = (left.quantile(q).unwrap(), whole.quantile(q).unwrap());
+            assert!((a - b).abs() < 50.0, "p{}: {} vs {}", q, a, b);
+        }
+    }
+}
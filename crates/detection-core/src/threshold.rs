@@ -0,0 +1,609 @@
+//! Checks of readings against fixed minimum and maximum values, and of the
+//! change between consecutive readings against a [`RateLimit`].
+//!
+//! A breach is graded by how far past the limit the value lies, relative to
+//! the size of the limit: by default more than 20% is critical, more than
+//! 10% high, anything else medium, and [`BreachBands`] moves those bounds.
+//! Below -50, -56 is 12% past the limit just as 56 is above 50. A rapid
+//! change is graded the same by how far its size, or its size per second,
+//! is past the rate limit.
+
+use std::fmt;
+use std::str::FromStr;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::Severity;
+use crate::severity::UnknownLabel;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Breach {
+    BelowMinimum,
+    AboveMaximum,
+    RapidChange,
+}
+
+impl Breach {
+    pub fn parse(label: &str) -> Option<Self> {
+        match label {
+            "below_minimum" => Some(Breach::BelowMinimum),
+            "above_maximum" => Some(Breach::AboveMaximum),
+            "rapid_change" => Some(Breach::RapidChange),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Breach::BelowMinimum => "below_minimum",
+            Breach::AboveMaximum => "above_maximum",
+            Breach::RapidChange => "rapid_change",
+        }
+    }
+}
+
+impl fmt::Display for Breach {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Breach {
+    type Err = UnknownLabel;
+
+    fn from_str(label: &str) -> Result<Self, UnknownLabel> {
+        Breach::parse(label).ok_or_else(|| UnknownLabel {
+            kind: "breach type",
+            label: label.to_string(),
+            expected: vec![
+                "below_minimum".to_string(),
+                "above_maximum".to_string(),
+                "rapid_change".to_string(),
+            ],
+        })
+    }
+}
+
+impl TryFrom<String> for Breach {
+    type Error = UnknownLabel;
+
+    fn try_from(label: String) -> Result<Self, UnknownLabel> {
+        label.parse()
+    }
+}
+
+/// A reading outside its limits, or too far from the reading before it.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+pub struct Alert {
+    pub reading_id: i64,
+    pub value: f64,
+    pub breach_type: Breach,
+    pub threshold_value: f64,
+    pub severity: Severity,
+}
+
+/// Minimum distance past a limit, as a share of the limit's size, for each
+/// severity above `medium`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Deserialize, Serialize))]
+#[cfg_attr(feature = "serde", serde(deny_unknown_fields))]
+pub struct BreachBands {
+    pub high: f64,
+    pub critical: f64,
+}
+
+impl Default for BreachBands {
+    fn default() -> Self {
+        Self {
+            high: 0.1,
+            critical: 0.2,
+        }
+    }
+}
+
+impl BreachBands {
+    /// Grades a breach of `limit` by `diff`, the distance past it.
+    pub fn classify(&self, diff: f64, limit: f64) -> Severity {
+        let limit = limit.abs();
+        if diff > limit * self.critical {
+            Severity::Critical
+        } else if diff > limit * self.high {
+            Severity::High
+        } else {
+            Severity::Medium
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.high.is_finite() && self.high > 0.0 && self.critical > self.high {
+            Ok(())
+        } else {
+            Err("critical must be above high, both positive".to_string())
+        }
+    }
+}
+
+/// Grades a breach of `limit` by `diff` with the default bands.
+pub fn breach_severity(diff: f64, limit: f64) -> Severity {
+    BreachBands::default().classify(diff, limit)
+}
+
+/// Returns an alert for every `(reading_id, value)` below `min_threshold` or
+/// above `max_threshold`, in reading order.
+pub fn check_thresholds(
+    readings: impl IntoIterator<Item = (i64, f64)>,
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+) -> Vec<Alert> {
+    check_thresholds_graded(
+        readings,
+        min_threshold,
+        max_threshold,
+        &BreachBands::default(),
+    )
+}
+
+/// [`check_thresholds`], grading breaches with `bands`.
+pub fn check_thresholds_graded(
+    readings: impl IntoIterator<Item = (i64, f64)>,
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+    bands: &BreachBands,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+
+    for (reading_id, value) in readings {
+        if let Some(min) = min_threshold
+            && value < min
+        {
+            alerts.push(Alert {
+                reading_id,
+                value,
+                breach_type: Breach::BelowMinimum,
+                threshold_value: min,
+                severity: bands.classify(min - value, min),
+            });
+        }
+
+        if let Some(max) = max_threshold
+            && value > max
+        {
+            alerts.push(Alert {
+                reading_id,
+                value,
+                breach_type: Breach::AboveMaximum,
+                threshold_value: max,
+                severity: bands.classify(value - max, max),
+            });
+        }
+    }
+
+    alerts
+}
+
+/// Largest change between consecutive readings of a series: `per_step`
+/// from one reading to the next, and `per_second` over the seconds between
+/// their timestamps. A change past either is a [`Breach::RapidChange`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    pub per_step: Option<f64>,
+    pub per_second: Option<f64>,
+}
+
+impl RateLimit {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, limit) in [("per_step", self.per_step), ("per_second", self.per_second)] {
+            if let Some(limit) = limit
+                && !(limit.is_finite() && limit > 0.0)
+            {
+                return Err(format!("{} must be a positive number, got {}", name, limit));
+            }
+        }
+        Ok(())
+    }
+
+    /// The change from `previous` to `current`, each a value and an
+    /// optional timestamp in seconds, and the limit it passed. `per_second`
+    /// applies only when both readings have timestamps; a change between
+    /// readings no later than the one before came in no time, so passes it
+    /// however small, and is graded critical.
+    fn breach(
+        &self,
+        previous: (f64, Option<f64>),
+        current: (f64, Option<f64>),
+    ) -> Option<(f64, f64)> {
+        let change = (current.0 - previous.0).abs();
+        if let Some(limit) = self.per_step
+            && change > limit
+        {
+            return Some((change, limit));
+        }
+        let (Some(limit), Some(from), Some(to)) = (self.per_second, previous.1, current.1) else {
+            return None;
+        };
+        let rate = if to > from {
+            change / (to - from)
+        } else if change > 0.0 {
+            f64::INFINITY
+        } else {
+            0.0
+        };
+        (rate > limit).then_some((rate, limit))
+    }
+
+    fn alert(
+        &self,
+        previous: (f64, Option<f64>),
+        reading_id: i64,
+        current: (f64, Option<f64>),
+        bands: &BreachBands,
+    ) -> Option<Alert> {
+        let (change, limit) = self.breach(previous, current)?;
+        Some(Alert {
+            reading_id,
+            value: current.0,
+            breach_type: Breach::RapidChange,
+            threshold_value: limit,
+            severity: bands.classify(change - limit, limit),
+        })
+    }
+}
+
+/// Returns a rapid-change alert for every `(reading_id, value, timestamp)`
+/// changing from the reading before it by more than `limit` allows, in
+/// reading order. Timestamps are in seconds, and only needed for
+/// `per_second`.
+pub fn check_rate_of_change(
+    readings: impl IntoIterator<Item = (i64, f64, Option<f64>)>,
+    limit: &RateLimit,
+    bands: &BreachBands,
+) -> Vec<Alert> {
+    let mut previous = None;
+    readings
+        .into_iter()
+        .filter_map(|(reading_id, value, timestamp)| {
+            let current = (value, timestamp);
+            let before = previous.replace(current)?;
+            limit.alert(before, reading_id, current, bands)
+        })
+        .collect()
+}
+
+/// Limits of a [`ThresholdTracker`]. A reading below `min` or above `max`
+/// breaches; an alarm trips after `min_consecutive_breaches` breaching
+/// readings in a row and clears once a reading is back at or inside
+/// `clear_min` or `clear_max`, which default to `min` and `max`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hysteresis {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub clear_min: Option<f64>,
+    pub clear_max: Option<f64>,
+    pub min_consecutive_breaches: u32,
+}
+
+impl Default for Hysteresis {
+    fn default() -> Self {
+        Self {
+            min: None,
+            max: None,
+            clear_min: None,
+            clear_max: None,
+            min_consecutive_breaches: 1,
+        }
+    }
+}
+
+impl Hysteresis {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.min_consecutive_breaches == 0 {
+            return Err("min_consecutive_breaches must be at least 1".to_string());
+        }
+        match (self.min, self.clear_min) {
+            (None, Some(_)) => return Err("clear_min needs a min".to_string()),
+            (Some(min), Some(clear)) if clear < min => {
+                return Err(format!("clear_min must be at least min ({})", min));
+            }
+            _ => {}
+        }
+        match (self.max, self.clear_max) {
+            (None, Some(_)) => Err("clear_max needs a max".to_string()),
+            (Some(max), Some(clear)) if clear > max => {
+                Err(format!("clear_max must be at most max ({})", max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn breach(&self, value: f64) -> Option<(Breach, f64)> {
+        match (self.min, self.max) {
+            (Some(min), _) if value < min => Some((Breach::BelowMinimum, min)),
+            (_, Some(max)) if value > max => Some((Breach::AboveMaximum, max)),
+            _ => None,
+        }
+    }
+
+    fn clears(&self, tripped: Breach, value: f64) -> bool {
+        match tripped {
+            Breach::BelowMinimum => self
+                .clear_min
+                .or(self.min)
+                .is_none_or(|clear| value >= clear),
+            Breach::AboveMaximum => self
+                .clear_max
+                .or(self.max)
+                .is_none_or(|clear| value <= clear),
+            Breach::RapidChange => true,
+        }
+    }
+}
+
+/// Checks the readings of one series as they arrive and alerts once per
+/// excursion: when an alarm trips, not on every reading past the limit.
+/// With a [`RateLimit`], a reading that does not trip the alarm but changes
+/// too fast from the one before alerts as a rapid change, every time.
+#[derive(Clone, Debug)]
+pub struct ThresholdTracker {
+    limits: Hysteresis,
+    rate: RateLimit,
+    bands: BreachBands,
+    streak: u32,
+    tripped: Option<Breach>,
+    previous: Option<(f64, Option<f64>)>,
+}
+
+impl ThresholdTracker {
+    pub fn new(limits: Hysteresis, bands: BreachBands) -> Self {
+        Self {
+            limits,
+            rate: RateLimit::default(),
+            bands,
+            streak: 0,
+            tripped: None,
+            previous: None,
+        }
+    }
+
+    pub fn with_rate_limit(mut self, rate: RateLimit) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    /// The limit the alarm tripped on, while it has not cleared.
+    pub fn tripped(&self) -> Option<Breach> {
+        self.tripped
+    }
+
+    /// Clears the alarm, the run of breaching readings and the reading
+    /// the next one's change is measured from.
+    pub fn reset(&mut self) {
+        self.streak = 0;
+        self.tripped = None;
+        self.previous = None;
+    }
+
+    /// Adds a reading, returning an alert if it trips the alarm or changes
+    /// too fast.
+    pub fn check(&mut self, reading_id: i64, value: f64) -> Option<Alert> {
+        self.check_at(reading_id, value, None)
+    }
+
+    /// [`check`](Self::check) with the reading's timestamp in seconds, for
+    /// the rate limit's `per_second`.
+    pub fn check_at(
+        &mut self,
+        reading_id: i64,
+        value: f64,
+        timestamp: Option<f64>,
+    ) -> Option<Alert> {
+        let current = (value, timestamp);
+        let rapid = self
+            .previous
+            .replace(current)
+            .and_then(|previous| self.rate.alert(previous, reading_id, current, &self.bands));
+        self.trip(reading_id, value).or(rapid)
+    }
+
+    fn trip(&mut self, reading_id: i64, value: f64) -> Option<Alert> {
+        if let Some(tripped) = self.tripped {
+            if !self.limits.clears(tripped, value) {
+                return None;
+            }
+            self.streak = 0;
+            self.tripped = None;
+        }
+        let Some((breach_type, limit)) = self.limits.breach(value) else {
+            self.streak = 0;
+            return None;
+        };
+        self.streak += 1;
+        if self.streak < self.limits.min_consecutive_breaches {
+            return None;
+        }
+        self.tripped = Some(breach_type);
+        Some(Alert {
+            reading_id,
+            value,
+            breach_type,
+            threshold_value: limit,
+            severity: self.bands.classify((value - limit).abs(), limit),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaches_are_graded_relative_to_the_limit() {
+        let alerts = check_thresholds(
+            [(1, 46.0), (2, 42.0), (3, 30.0), (4, 60.0), (5, 130.0)],
+            Some(50.0),
+            Some(100.0),
+        );
+        let graded: Vec<(i64, Breach, Severity)> = alerts
+            .iter()
+            .map(|a| (a.reading_id, a.breach_type, a.severity))
+            .collect();
+        assert_eq!(
+            graded,
+            vec![
+                (1, Breach::BelowMinimum, Severity::Medium),
+                (2, Breach::BelowMinimum, Severity::High),
+                (3, Breach::BelowMinimum, Severity::Critical),
+                (5, Breach::AboveMaximum, Severity::Critical),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_breaches_of_negative_limits_are_graded_by_their_size() {
+        let alerts = check_thresholds(
+            [(1, -52.0), (2, -37.0), (3, -65.0)],
+            Some(-50.0),
+            Some(-40.0),
+        );
+        let graded: Vec<(i64, Severity)> =
+            alerts.iter().map(|a| (a.reading_id, a.severity)).collect();
+        assert_eq!(
+            graded,
+            vec![
+                (1, Severity::Medium),
+                (2, Severity::Medium),
+                (3, Severity::Critical)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_breach_bands_move_the_grades() {
+        let bands = BreachBands {
+            high: 0.05,
+            critical: 0.5,
+        };
+        let alerts =
+            check_thresholds_graded([(1, 52.0), (2, 57.0), (3, 80.0)], None, Some(50.0), &bands);
+        let graded: Vec<Severity> = alerts.iter().map(|a| a.severity).collect();
+        assert_eq!(
+            graded,
+            vec![Severity::Medium, Severity::High, Severity::Critical]
+        );
+        assert!(bands.validate().is_ok());
+        let inverted = BreachBands {
+            high: 0.3,
+            critical: 0.2,
+        };
+        assert!(inverted.validate().is_err());
+    }
+
+    #[test]
+    fn test_tracker_debounces_and_clears_past_the_clear_limit() {
+        let limits = Hysteresis {
+            max: Some(100.0),
+            clear_max: Some(90.0),
+            min_consecutive_breaches: 2,
+            ..Hysteresis::default()
+        };
+        let mut tracker = ThresholdTracker::new(limits, BreachBands::default());
+        let values = [
+            101.0, 95.0, 101.0, 102.0, 103.0, 95.0, 101.0, 89.0, 101.0, 101.0,
+        ];
+        let alerts: Vec<i64> = values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &value)| tracker.check(i as i64 + 1, value))
+            .map(|alert| alert.reading_id)
+            .collect();
+        // A lone breach is ignored, 4 trips, and 95 keeps it tripped
+        // until 89 clears it.
+        assert_eq!(alerts, vec![4, 10]);
+        assert_eq!(tracker.tripped(), Some(Breach::AboveMaximum));
+
+        let invalid = Hysteresis {
+            clear_max: Some(110.0),
+            ..limits
+        };
+        assert!(invalid.validate().is_err());
+        assert!(limits.validate().is_ok());
+    }
+
+    #[test]
+    fn test_rate_of_change_per_step_and_per_second() {
+        let readings = [
+            (1, 20.0, Some(0.0)),
+            (2, 24.0, Some(60.0)),
+            (3, 40.0, Some(120.0)),
+            (4, 41.0, Some(121.0)),
+            (5, 39.0, None),
+        ];
+        let alerts = |limit: RateLimit| -> Vec<(i64, f64, Severity)> {
+            check_rate_of_change(readings, &limit, &BreachBands::default())
+                .into_iter()
+                .map(|alert| (alert.reading_id, alert.threshold_value, alert.severity))
+                .collect()
+        };
+
+        let per_step = RateLimit {
+            per_step: Some(10.0),
+            ..RateLimit::default()
+        };
+        // A jump of 16 is 60% past 10.
+        assert_eq!(alerts(per_step), [(3, 10.0, Severity::Critical)]);
+        let per_second = RateLimit {
+            per_second: Some(0.25),
+            ..RateLimit::default()
+        };
+        // 16 in 60 seconds and 1 in one pass 0.25/s; the untimed 5 is not
+        // checked.
+        assert_eq!(
+            alerts(per_second),
+            [(3, 0.25, Severity::Medium), (4, 0.25, Severity::Critical)]
+        );
+
+        let mut tracker = ThresholdTracker::new(
+            Hysteresis {
+                max: Some(40.5),
+                ..Hysteresis::default()
+            },
+            BreachBands::default(),
+        )
+        .with_rate_limit(per_step);
+        let found: Vec<(i64, Breach)> = readings
+            .iter()
+            .filter_map(|&(id, value, timestamp)| tracker.check_at(id, value, timestamp))
+            .map(|alert| (alert.reading_id, alert.breach_type))
+            .collect();
+        // 41 trips the alarm, which takes precedence over a rapid change.
+        assert_eq!(found, [(3, Breach::RapidChange), (4, Breach::AboveMaximum)]);
+
+        // A change at the same second, or going back in time, passes any
+        // rate; no change does not.
+        let out_of_order = [
+            (1, 20.0, Some(60.0)),
+            (2, 20.5, Some(60.0)),
+            (3, 21.0, Some(30.0)),
+            (4, 21.0, Some(30.0)),
+        ];
+        let found: Vec<(i64, Severity)> =
+            check_rate_of_change(out_of_order, &per_second, &BreachBands::default())
+                .into_iter()
+                .map(|alert| (alert.reading_id, alert.severity))
+                .collect();
+        assert_eq!(found, [(2, Severity::Critical), (3, Severity::Critical)]);
+
+        assert!(
+            RateLimit {
+                per_step: Some(0.0),
+                ..per_step
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(per_second.validate().is_ok());
+    }
+}
@@ -0,0 +1,216 @@
+//! Properties every detector must keep over generated readings, including
+//! the edge cases hand-picked ones keep missing: constant batches, negative
+//! and zero limits, and readings in any order.
+
+use std::collections::BTreeSet;
+
+use detection_core::stats::{RunningStats, summarize};
+use detection_core::threshold::breach_severity;
+use detection_core::{
+    Outlier, Severity, SeverityBands, check_thresholds, ewma_outliers, iqr_outliers, mad_outliers,
+    rolling_outliers, zscore_outliers,
+};
+use proptest::prelude::*;
+
+type Readings = Vec<(i64, f64)>;
+
+/// Values of the size sensors report, negative ones included.
+fn value() -> impl Strategy<Value = f64> {
+    -1.0e6..1.0e6
+}
+
+/// Readings with ids 1 to N.
+fn readings(max_len: usize) -> impl Strategy<Value = Readings> {
+    prop::collection::vec(value(), 0..max_len).prop_map(|values| {
+        values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| (i as i64 + 1, v))
+            .collect()
+    })
+}
+
+/// Readings and the same readings shuffled.
+fn shuffled_readings(max_len: usize) -> impl Strategy<Value = (Readings, Readings)> {
+    readings(max_len).prop_flat_map(|readings| {
+        let shuffled = Just(readings.clone()).prop_shuffle();
+        (Just(readings), shuffled)
+    })
+}
+
+fn severity_bands() -> impl Strategy<Value = SeverityBands> {
+    (0.0..5.0, 0.0..5.0).prop_map(|(high, extra)| SeverityBands {
+        high,
+        critical: high + extra,
+    })
+}
+
+fn ids(outliers: &[Outlier]) -> BTreeSet<i64> {
+    outliers.iter().map(|o| o.reading_id).collect()
+}
+
+fn assert_close(a: f64, b: f64, scale: f64) {
+    let tolerance = 1e-9 * scale.max(1.0);
+    assert!((a - b).abs() <= tolerance, "{} != {}", a, b);
+}
+
+proptest! {
+    #[test]
+    fn no_alert_within_the_limits(
+        readings in readings(200),
+        (min, max) in (value(), value()).prop_map(|(a, b)| (a.min(b), a.max(b))),
+    ) {
+        let inside: Vec<(i64, f64)> = readings
+            .iter()
+            .map(|&(id, v)| (id, v.clamp(min, max)))
+            .collect();
+        prop_assert!(check_thresholds(inside, Some(min), Some(max)).is_empty());
+        prop_assert!(check_thresholds(readings, None, None).is_empty());
+    }
+
+    #[test]
+    fn every_reading_past_a_limit_alerts(
+        readings in readings(200),
+        min in prop::option::of(value()),
+        max in prop::option::of(value()),
+    ) {
+        let alerts = check_thresholds(readings.iter().copied(), min, max);
+        let expected = readings
+            .iter()
+            .map(|&(_, v)| {
+                usize::from(min.is_some_and(|min| v < min))
+                    + usize::from(max.is_some_and(|max| v > max))
+            })
+            .sum::<usize>();
+        prop_assert_eq!(alerts.len(), expected);
+        for alert in &alerts {
+            prop_assert_eq!(
+                alert.severity,
+                breach_severity((alert.value - alert.threshold_value).abs(), alert.threshold_value)
+            );
+        }
+    }
+
+    #[test]
+    fn breach_severity_is_monotone_and_symmetric(
+        a in 0.0..1.0e6f64,
+        b in 0.0..1.0e6f64,
+        limit in value(),
+    ) {
+        let (near, far) = (a.min(b), a.max(b));
+        prop_assert!(breach_severity(near, limit) <= breach_severity(far, limit));
+        prop_assert_eq!(breach_severity(a, limit), breach_severity(a, -limit));
+        if limit != 0.0 {
+            prop_assert_eq!(breach_severity(0.05 * limit.abs(), limit), Severity::Medium);
+        }
+    }
+
+    #[test]
+    fn severity_is_monotone_in_deviation(
+        bands in severity_bands(),
+        a in 0.0..10.0f64,
+        b in 0.0..10.0f64,
+    ) {
+        let (near, far) = (a.min(b), a.max(b));
+        prop_assert!(bands.classify(near) <= bands.classify(far));
+    }
+
+    #[test]
+    fn outliers_pass_the_threshold_and_are_graded_by_score(
+        readings in readings(300),
+        threshold in 0.5..4.0f64,
+        bands in severity_bands(),
+    ) {
+        let detected = [
+            zscore_outliers(&readings, threshold, &bands),
+            mad_outliers(&readings, threshold, &bands),
+            iqr_outliers(&readings, threshold, &bands),
+            ewma_outliers(&readings, 0.2, threshold, &bands),
+            rolling_outliers(&readings, 10, threshold, &bands),
+        ];
+        for outliers in &detected {
+            let mut last_id = 0;
+            for outlier in outliers {
+                prop_assert!(outlier.score.abs() > threshold);
+                prop_assert_eq!(outlier.severity, bands.classify(outlier.score.abs()));
+                prop_assert!(outlier.reading_id > last_id, "outliers are in reading order");
+                last_id = outlier.reading_id;
+            }
+        }
+    }
+
+    #[test]
+    fn constant_readings_have_no_outliers(
+        level in value(),
+        len in 0usize..300,
+        threshold in 0.0..4.0f64,
+    ) {
+        let readings: Vec<(i64, f64)> = (1..=len as i64).map(|id| (id, level)).collect();
+        let bands = SeverityBands::default();
+        prop_assert!(zscore_outliers(&readings, threshold, &bands).is_empty());
+        prop_assert!(mad_outliers(&readings, threshold, &bands).is_empty());
+        prop_assert!(iqr_outliers(&readings, threshold, &bands).is_empty());
+        prop_assert!(ewma_outliers(&readings, 0.2, threshold, &bands).is_empty());
+        prop_assert!(rolling_outliers(&readings, 10, threshold, &bands).is_empty());
+        prop_assert_eq!(summarize(readings.iter().map(|r| r.1)).std_dev(), 0.0);
+    }
+
+    #[test]
+    fn robust_detectors_ignore_reading_order(
+        (readings, shuffled) in shuffled_readings(300),
+        threshold in 0.5..4.0f64,
+    ) {
+        // Medians and quartiles come out the same in any order, to the bit.
+        let bands = SeverityBands::default();
+        let by_id = |mut outliers: Vec<Outlier>| {
+            outliers.sort_by_key(|o| o.reading_id);
+            outliers
+        };
+        prop_assert_eq!(
+            by_id(mad_outliers(&readings, threshold, &bands)),
+            by_id(mad_outliers(&shuffled, threshold, &bands))
+        );
+        prop_assert_eq!(
+            by_id(iqr_outliers(&readings, threshold, &bands)),
+            by_id(iqr_outliers(&shuffled, threshold, &bands))
+        );
+    }
+
+    #[test]
+    fn zscore_outliers_ignore_reading_order(
+        (readings, shuffled) in shuffled_readings(300),
+        threshold in 0.5..4.0f64,
+    ) {
+        // The mean and deviation may differ in the last bits by order, so
+        // only a reading right at the threshold may be flagged one way only.
+        let bands = SeverityBands::default();
+        let stats = summarize(readings.iter().map(|r| r.1));
+        let in_order = zscore_outliers(&readings, threshold, &bands);
+        let out_of_order = zscore_outliers(&shuffled, threshold, &bands);
+        for id in ids(&in_order).symmetric_difference(&ids(&out_of_order)) {
+            let value = readings[*id as usize - 1].1;
+            let z = (value - stats.mean()) / stats.std_dev();
+            prop_assert!((z.abs() - threshold).abs() < 1e-9, "reading {} with z {}", id, z);
+        }
+    }
+
+    #[test]
+    fn merged_stats_match_one_pass(
+        left in prop::collection::vec(value(), 0..100),
+        right in prop::collection::vec(value(), 0..100),
+    ) {
+        let mut merged = summarize(left.iter().copied());
+        merged.merge(&summarize(right.iter().copied()));
+        let mut pushed = RunningStats::default();
+        for &v in left.iter().chain(&right) {
+            pushed.push(v);
+        }
+        let whole = summarize(left.iter().chain(&right).copied());
+        for stats in [merged, pushed] {
+            prop_assert_eq!(stats.count(), whole.count());
+            assert_close(stats.mean(), whole.mean(), 1.0e6);
+            assert_close(stats.std_dev(), whole.std_dev(), 1.0e6);
+        }
+        prop_assert!(whole.std_dev() >= 0.0);
+    }
+}
@@ -0,0 +1,115 @@
+/* Generated from crates/detection-ffi by cbindgen; run `just ffi-header` after changing the API. */
+
+#ifndef DETECTION_H
+#define DETECTION_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum DetStatus {
+  DET_STATUS_OK,
+  // A required pointer was null.
+  DET_STATUS_NULL_POINTER,
+  // A threshold was not a positive number, or bands were out of order.
+  DET_STATUS_INVALID_ARGUMENT,
+} DetStatus;
+
+typedef enum DetBreach {
+  DET_BREACH_BELOW_MINIMUM,
+  DET_BREACH_ABOVE_MAXIMUM,
+  DET_BREACH_RAPID_CHANGE,
+} DetBreach;
+
+typedef enum DetSeverity {
+  DET_SEVERITY_MEDIUM,
+  DET_SEVERITY_HIGH,
+  DET_SEVERITY_CRITICAL,
+} DetSeverity;
+
+typedef struct DetReading {
+  int64_t id;
+  double value;
+} DetReading;
+
+typedef struct DetAlert {
+  int64_t reading_id;
+  double value;
+  enum DetBreach breach_type;
+  double threshold_value;
+  enum DetSeverity severity;
+} DetAlert;
+
+// Minimum `|score|` graded high and critical.
+typedef struct DetBands {
+  double high;
+  double critical;
+} DetBands;
+
+typedef struct DetOutlier {
+  int64_t reading_id;
+  double value;
+  // The z-score, or the modified z-score for MAD.
+  double score;
+  enum DetSeverity severity;
+} DetOutlier;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Checks readings against `*min` and `*max`, either of which may be null
+// to leave that side unchecked.
+//
+// # Safety
+//
+// `readings` must point to `len` readings, `min` and `max` be null or valid,
+// `out` have room for `capacity` alerts and `count` be writable.
+enum DetStatus det_check_thresholds(const struct DetReading *readings,
+                                    size_t len,
+                                    const double *min,
+                                    const double *max,
+                                    struct DetAlert *out,
+                                    size_t capacity,
+                                    size_t *count);
+
+// Flags the readings whose z-score against all of them exceeds
+// `threshold`, graded by `*bands`, or the service's default bands when it
+// is null.
+//
+// # Safety
+//
+// As for [`det_check_thresholds`]; `bands` must be null or valid.
+enum DetStatus det_zscore_outliers(const struct DetReading *readings,
+                                   size_t len,
+                                   double threshold,
+                                   const struct DetBands *bands,
+                                   struct DetOutlier *out,
+                                   size_t capacity,
+                                   size_t *count);
+
+// Flags the readings whose modified z-score around the median exceeds
+// `threshold` (3.5 is customary), graded like [`det_zscore_outliers`].
+//
+// # Safety
+//
+// As for [`det_zscore_outliers`].
+enum DetStatus det_mad_outliers(const struct DetReading *readings,
+                                size_t len,
+                                double threshold,
+                                const struct DetBands *bands,
+                                struct DetOutlier *out,
+                                size_t capacity,
+                                size_t *count);
+
+// The label the service uses for `severity`, e.g. `"critical"`, as a static
+// string.
+const char *det_severity_label(enum DetSeverity severity);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DETECTION_H */
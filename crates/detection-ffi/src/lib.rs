@@ -0,0 +1,417 @@
+//! C bindings of `detection-core`, so firmware linking `libdetection_ffi`
+//! flags readings exactly as the service does. `include/detection.h` is the
+//! header, generated with cbindgen.
+//!
+//! Nothing is allocated for the caller: results are written to buffers it
+//! owns. Each detection function stores the number of results in `*count`
+//! and writes at most `capacity` of them to `out`, so a call with too small a
+//! buffer can be repeated with one of `*count` entries.
+//!
+//! ```c
+//! DetReading readings[] = {{1, 10.0}, {2, 97.5}};
+//! double max = 95.0;
+//! DetAlert alerts[8];
+//! size_t count;
+//! if (det_check_thresholds(readings, 2, NULL, &max, alerts, 8, &count) == DET_STATUS_OK) { ... }
+//! ```
+
+use std::ffi::{CStr, c_char};
+
+use detection_core::{Breach, Severity, SeverityBands};
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetStatus {
+    Ok,
+    /// A required pointer was null.
+    NullPointer,
+    /// A threshold was not a positive number, or bands were out of order.
+    InvalidArgument,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetSeverity {
+    Medium,
+    High,
+    Critical,
+}
+
+impl From<Severity> for DetSeverity {
+    fn from(severity: Severity) -> Self {
+        match severity {
+            Severity::Medium => DetSeverity::Medium,
+            Severity::High => DetSeverity::High,
+            Severity::Critical => DetSeverity::Critical,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetBreach {
+    BelowMinimum,
+    AboveMaximum,
+    RapidChange,
+}
+
+impl From<Breach> for DetBreach {
+    fn from(breach: Breach) -> Self {
+        match breach {
+            Breach::BelowMinimum => DetBreach::BelowMinimum,
+            Breach::AboveMaximum => DetBreach::AboveMaximum,
+            Breach::RapidChange => DetBreach::RapidChange,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetReading {
+    pub id: i64,
+    pub value: f64,
+}
+
+/// Minimum `|score|` graded high and critical.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetBands {
+    pub high: f64,
+    pub critical: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetAlert {
+    pub reading_id: i64,
+    pub value: f64,
+    pub breach_type: DetBreach,
+    pub threshold_value: f64,
+    pub severity: DetSeverity,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DetOutlier {
+    pub reading_id: i64,
+    pub value: f64,
+    /// The z-score, or the modified z-score for MAD.
+    pub score: f64,
+    pub severity: DetSeverity,
+}
+
+/// Reads `len` readings from `readings`, which may be null when `len` is 0.
+unsafe fn pairs(readings: *const DetReading, len: usize) -> Option<Vec<(i64, f64)>> {
+    if len == 0 {
+        return Some(Vec::new());
+    }
+    if readings.is_null() {
+        return None;
+    }
+    let readings = unsafe { std::slice::from_raw_parts(readings, len) };
+    Some(readings.iter().map(|r| (r.id, r.value)).collect())
+}
+
+/// The bands at `bands`, or the service's defaults when it is null.
+unsafe fn bands(bands: *const DetBands) -> Result<SeverityBands, DetStatus> {
+    let Some(bands) = (unsafe { bands.as_ref() }) else {
+        return Ok(SeverityBands::default());
+    };
+    if !(bands.high > 0.0 && bands.critical > bands.high) {
+        return Err(DetStatus::InvalidArgument);
+    }
+    Ok(SeverityBands {
+        high: bands.high,
+        critical: bands.critical,
+    })
+}
+
+/// Stores `results.len()` in `*count` and copies what fits into `out`.
+unsafe fn write<T: Copy>(
+    results: &[T],
+    out: *mut T,
+    capacity: usize,
+    count: *mut usize,
+) -> DetStatus {
+    let written = results.len().min(capacity);
+    if count.is_null() || (written > 0 && out.is_null()) {
+        return DetStatus::NullPointer;
+    }
+    unsafe {
+        std::ptr::copy_nonoverlapping(results.as_ptr(), out, written);
+        *count = results.len();
+    }
+    DetStatus::Ok
+}
+
+/// Checks readings against `*min` and `*max`, either of which may be null
+/// to leave that side unchecked.
+///
+/// # Safety
+///
+/// `readings` must point to `len` readings, `min` and `max` be null or valid,
+/// `out` have room for `capacity` alerts and `count` be writable.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn det_check_thresholds(
+    readings: *const DetReading,
+    len: usize,
+    min: *const f64,
+    max: *const f64,
+    out: *mut DetAlert,
+    capacity: usize,
+    count: *mut usize,
+) -> DetStatus {
+    let Some(readings) = (unsafe { pairs(readings, len) }) else {
+        return DetStatus::NullPointer;
+    };
+    let (min, max) = unsafe { (min.as_ref().copied(), max.as_ref().copied()) };
+    let alerts: Vec<DetAlert> = detection_core::check_thresholds(readings, min, max)
+        .into_iter()
+        .map(|alert| DetAlert {
+            reading_id: alert.reading_id,
+            value: alert.value,
+            breach_type: alert.breach_type.into(),
+            threshold_value: alert.threshold_value,
+            severity: alert.severity.into(),
+        })
+        .collect();
+    unsafe { write(&alerts, out, capacity, count) }
+}
+
+type Detect = fn(&[(i64, f64)], f64, &SeverityBands) -> Vec<detection_core::Outlier>;
+
+unsafe fn outliers(
+    detect: Detect,
+    readings: *const DetReading,
+    len: usize,
+    threshold: f64,
+    bands: *const DetBands,
+) -> Result<Vec<DetOutlier>, DetStatus> {
+    if !(threshold.is_finite() && threshold > 0.0) {
+        return Err(DetStatus::InvalidArgument);
+    }
+    let bands = unsafe { self::bands(bands) }?;
+    let readings = unsafe { pairs(readings, len) }.ok_or(DetStatus::NullPointer)?;
+    Ok(detect(&readings, threshold, &bands)
+        .into_iter()
+        .map(|outlier| DetOutlier {
+            reading_id: outlier.reading_id,
+            value: outlier.value,
+            score: outlier.score,
+            severity: outlier.severity.into(),
+        })
+        .collect())
+}
+
+/// Flags the readings whose z-score against all of them exceeds
+/// `threshold`, graded by `*bands`, or the service's default bands when it
+/// is null.
+///
+/// # Safety
+///
+/// As for [`det_check_thresholds`]; `bands` must be null or valid.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn det_zscore_outliers(
+    readings: *const DetReading,
+    len: usize,
+    threshold: f64,
+    bands: *const DetBands,
+    out: *mut DetOutlier,
+    capacity: usize,
+    count: *mut usize,
+) -> DetStatus {
+    match unsafe {
+        outliers(
+            detection_core::zscore_outliers,
+            readings,
+            len,
+            threshold,
+            bands,
+        )
+    } {
+        Ok(outliers) => unsafe { write(&outliers, out, capacity, count) },
+        Err(status) => status,
+    }
+}
+
+/// Flags the readings whose modified z-score around the median exceeds
+/// `threshold` (3.5 is customary), graded like [`det_zscore_outliers`].
+///
+/// # Safety
+///
+/// As for [`det_zscore_outliers`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn det_mad_outliers(
+    readings: *const DetReading,
+    len: usize,
+    threshold: f64,
+    bands: *const DetBands,
+    out: *mut DetOutlier,
+    capacity: usize,
+    count: *mut usize,
+) -> DetStatus {
+    match unsafe {
+        outliers(
+            detection_core::mad_outliers,
+            readings,
+            len,
+            threshold,
+            bands,
+        )
+    } {
+        Ok(outliers) => unsafe { write(&outliers, out, capacity, count) },
+        Err(status) => status,
+    }
+}
+
+/// The label the service uses for `severity`, e.g. `"critical"`, as a static
+/// string.
+#[unsafe(no_mangle)]
+pub extern "C" fn det_severity_label(severity: DetSeverity) -> *const c_char {
+    let label: &'static CStr = match severity {
+        DetSeverity::Medium => c"medium",
+        DetSeverity::High => c"high",
+        DetSeverity::Critical => c"critical",
+    };
+    label.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    fn readings(values: &[f64]) -> Vec<DetReading> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| DetReading {
+                id: i as i64 + 1,
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_thresholds_reports_the_count_beyond_capacity() {
+        let readings = readings(&[50.0, 10.0, 95.0]);
+        let (min, max) = (40.0, 80.0);
+        let mut out = [DetAlert {
+            reading_id: 0,
+            value: 0.0,
+            breach_type: DetBreach::BelowMinimum,
+            threshold_value: 0.0,
+            severity: DetSeverity::Medium,
+        }; 1];
+        let mut count = 0;
+        let status = unsafe {
+            det_check_thresholds(
+                readings.as_ptr(),
+                3,
+                &min,
+                &max,
+                out.as_mut_ptr(),
+                1,
+                &mut count,
+            )
+        };
+        assert_eq!(status, DetStatus::Ok);
+        assert_eq!(count, 2);
+        assert_eq!(out[0].reading_id, 2);
+        assert_eq!(out[0].severity, DetSeverity::Critical);
+
+        let status = unsafe {
+            det_check_thresholds(
+                readings.as_ptr(),
+                3,
+                ptr::null(),
+                &max,
+                ptr::null_mut(),
+                0,
+                &mut count,
+            )
+        };
+        assert_eq!((status, count), (DetStatus::Ok, 1));
+    }
+
+    #[test]
+    fn test_outliers_match_detection_core() {
+        let mut values: Vec<f64> = (0..20).map(|i| 10.0 + (i % 5) as f64 * 0.5).collect();
+        values.push(100.0);
+        let readings = readings(&values);
+        let pairs: Vec<(i64, f64)> = readings.iter().map(|r| (r.id, r.value)).collect();
+        let expected = detection_core::mad_outliers(&pairs, 3.5, &SeverityBands::default());
+
+        let mut out = Vec::with_capacity(4);
+        let mut count = 0;
+        let status = unsafe {
+            det_mad_outliers(
+                readings.as_ptr(),
+                readings.len(),
+                3.5,
+                ptr::null(),
+                out.as_mut_ptr(),
+                4,
+                &mut count,
+            )
+        };
+        unsafe { out.set_len(count) };
+        assert_eq!(status, DetStatus::Ok);
+        assert_eq!(out.len(), expected.len());
+        assert_eq!((out[0].reading_id, out[0].score), (21, expected[0].score));
+
+        let inverted = DetBands {
+            high: 3.0,
+            critical: 2.0,
+        };
+        let status = unsafe {
+            det_zscore_outliers(
+                readings.as_ptr(),
+                readings.len(),
+                2.0,
+                &inverted,
+                out.as_mut_ptr(),
+                4,
+                &mut count,
+            )
+        };
+        assert_eq!(status, DetStatus::InvalidArgument);
+        let status = unsafe {
+            det_zscore_outliers(
+                ptr::null(),
+                3,
+                2.0,
+                ptr::null(),
+                out.as_mut_ptr(),
+                4,
+                &mut count,
+            )
+        };
+        assert_eq!(status, DetStatus::NullPointer);
+    }
+
+    #[test]
+    fn test_severity_labels() {
+        let label = unsafe { CStr::from_ptr(det_severity_label(DetSeverity::High)) };
+        assert_eq!(label.to_str(), Ok(Severity::High.as_str()));
+    }
+
+    #[test]
+    fn test_header_is_up_to_date() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", root)).unwrap();
+        let mut generated = Vec::new();
+        cbindgen::generate_with_config(root, config)
+            .unwrap()
+            .write(&mut generated);
+        let path = format!("{}/include/detection.h", root);
+        if std::env::var_os("UPDATE_HEADER").is_some() {
+            std::fs::write(&path, &generated).unwrap();
+        }
+        let committed = std::fs::read(&path).unwrap_or_default();
+        assert!(
+            committed == generated,
+            "include/detection.h is stale; run `just ffi-header`"
+        );
+    }
+}
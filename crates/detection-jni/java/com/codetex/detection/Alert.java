@@ -0,0 +1,27 @@
+package com.codetex.detection;
+
+/** A reading outside its limits, or changing faster than they allow. */
+public final class Alert {
+    public final long readingId;
+    public final double value;
+    /** {@code below_minimum}, {@code above_maximum} or {@code rapid_change}. */
+    public final String breachType;
+    public final double thresholdValue;
+    /** {@code medium}, {@code high} or {@code critical}. */
+    public final String severity;
+
+    public Alert(
+            long readingId, double value, String breachType, double thresholdValue, String severity) {
+        this.readingId = readingId;
+        this.value = value;
+        this.breachType = breachType;
+        this.thresholdValue = thresholdValue;
+        this.severity = severity;
+    }
+
+    @Override
+    public String toString() {
+        return "Alert(" + readingId + ", " + value + ", " + breachType + ", " + thresholdValue
+                + ", " + severity + ")";
+    }
+}
@@ -0,0 +1,56 @@
+package com.codetex.detection;
+
+/**
+ * Threshold checks and batch outlier scoring from detection-core, graded
+ * exactly as the anomaly-detector service grades them.
+ *
+ * <p>Readings are parallel arrays of ids and values. Requires
+ * {@code libdetection_jni} on {@code java.library.path}.
+ */
+public final class Detection {
+    static {
+        System.loadLibrary("detection_jni");
+    }
+
+    private Detection() {}
+
+    /** Alerts for the readings below {@code min} or above {@code max}; either may be null. */
+    public static Alert[] checkThresholds(long[] ids, double[] values, Double min, Double max) {
+        return checkThresholds0(ids, values, orNaN(min), orNaN(max));
+    }
+
+    /**
+     * Readings whose z-score against the whole batch exceeds {@code threshold}, graded by
+     * {@code bands}, or the service's default bands when null.
+     */
+    public static Outlier[] zscoreOutliers(
+            long[] ids, double[] values, double threshold, SeverityBands bands) {
+        return bands == null
+                ? zscoreOutliers0(ids, values, threshold, Double.NaN, Double.NaN)
+                : zscoreOutliers0(ids, values, threshold, bands.high, bands.critical);
+    }
+
+    /**
+     * Readings whose modified z-score around the median exceeds {@code threshold} (3.5 is
+     * customary), graded like {@link #zscoreOutliers}.
+     */
+    public static Outlier[] madOutliers(
+            long[] ids, double[] values, double threshold, SeverityBands bands) {
+        return bands == null
+                ? madOutliers0(ids, values, threshold, Double.NaN, Double.NaN)
+                : madOutliers0(ids, values, threshold, bands.high, bands.critical);
+    }
+
+    private static double orNaN(Double value) {
+        return value == null ? Double.NaN : value;
+    }
+
+    private static native Alert[] checkThresholds0(
+            long[] ids, double[] values, double min, double max);
+
+    private static native Outlier[] zscoreOutliers0(
+            long[] ids, double[] values, double threshold, double high, double critical);
+
+    private static native Outlier[] madOutliers0(
+            long[] ids, double[] values, double threshold, double high, double critical);
+}
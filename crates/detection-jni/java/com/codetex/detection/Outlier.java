@@ -0,0 +1,23 @@
+package com.codetex.detection;
+
+/** A reading scored past the threshold. */
+public final class Outlier {
+    public final long readingId;
+    public final double value;
+    /** The z-score, or the modified z-score for MAD. */
+    public final double score;
+    /** {@code medium}, {@code high} or {@code critical}. */
+    public final String severity;
+
+    public Outlier(long readingId, double value, double score, String severity) {
+        this.readingId = readingId;
+        this.value = value;
+        this.score = score;
+        this.severity = severity;
+    }
+
+    @Override
+    public String toString() {
+        return "Outlier(" + readingId + ", " + value + ", " + score + ", " + severity + ")";
+    }
+}
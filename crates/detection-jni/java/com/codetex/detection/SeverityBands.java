@@ -0,0 +1,12 @@
+package com.codetex.detection;
+
+/** Minimum |score| graded high and critical. */
+public final class SeverityBands {
+    public final double high;
+    public final double critical;
+
+    public SeverityBands(double high, double critical) {
+        this.high = high;
+        this.critical = critical;
+    }
+}
@@ -0,0 +1,244 @@
+//! JNI bindings of `detection-core` for JVM pipelines (Flink, Spark), so
+//! they grade readings with the service's rules instead of a reimplementation.
+//!
+//! The Java side is `com.codetex.detection.Detection` under `java/`; it loads
+//! `libdetection_jni` and turns missing limits and bands into the sentinels
+//! these functions take. Readings are passed as parallel `long[]` ids and
+//! `double[]` values; alerts and outliers come back as
+//! `com.codetex.detection.Alert[]` and `Outlier[]`. Invalid arguments throw
+//! `IllegalArgumentException`.
+
+use detection_core::{Severity, SeverityBands};
+use jni::JNIEnv;
+use jni::objects::{JClass, JDoubleArray, JLongArray, JObject, JObjectArray, JValue};
+use jni::sys::{jdouble, jobjectArray};
+
+const ALERT_CLASS: &str = "com/codetex/detection/Alert";
+const ALERT_CONSTRUCTOR: &str = "(JDLjava/lang/String;DLjava/lang/String;)V";
+const OUTLIER_CLASS: &str = "com/codetex/detection/Outlier";
+const OUTLIER_CONSTRUCTOR: &str = "(JDDLjava/lang/String;)V";
+
+enum Error {
+    /// Thrown to Java as `IllegalArgumentException`.
+    Invalid(String),
+    /// A JNI call failed; when it raised a Java exception, that one is left
+    /// pending.
+    Jni(jni::errors::Error),
+}
+
+impl From<jni::errors::Error> for Error {
+    fn from(error: jni::errors::Error) -> Self {
+        Error::Jni(error)
+    }
+}
+
+/// `NaN` stands for no limit.
+fn limit(value: f64) -> Option<f64> {
+    (!value.is_nan()).then_some(value)
+}
+
+/// Both `NaN` stand for the service's default bands.
+fn bands(high: f64, critical: f64) -> Result<SeverityBands, Error> {
+    if high.is_nan() && critical.is_nan() {
+        return Ok(SeverityBands::default());
+    }
+    if !(high > 0.0 && critical > high) {
+        return Err(Error::Invalid(
+            "bands: critical must be above high, both positive".to_string(),
+        ));
+    }
+    Ok(SeverityBands { high, critical })
+}
+
+fn positive(threshold: f64) -> Result<f64, Error> {
+    if threshold.is_finite() && threshold > 0.0 {
+        Ok(threshold)
+    } else {
+        Err(Error::Invalid(format!(
+            "threshold must be a positive number, got {}",
+            threshold
+        )))
+    }
+}
+
+fn pairs(ids: &[i64], values: &[f64]) -> Result<Vec<(i64, f64)>, Error> {
+    if ids.len() != values.len() {
+        return Err(Error::Invalid(format!(
+            "{} ids but {} values",
+            ids.len(),
+            values.len()
+        )));
+    }
+    Ok(ids.iter().copied().zip(values.iter().copied()).collect())
+}
+
+fn readings(
+    env: &mut JNIEnv,
+    ids: &JLongArray,
+    values: &JDoubleArray,
+) -> Result<Vec<(i64, f64)>, Error> {
+    let mut id_buffer = vec![0; env.get_array_length(ids)? as usize];
+    env.get_long_array_region(ids, 0, &mut id_buffer)?;
+    let mut value_buffer = vec![0.0; env.get_array_length(values)? as usize];
+    env.get_double_array_region(values, 0, &mut value_buffer)?;
+    pairs(&id_buffer, &value_buffer)
+}
+
+fn severity<'local>(
+    env: &mut JNIEnv<'local>,
+    severity: Severity,
+) -> Result<JObject<'local>, Error> {
+    Ok(env.new_string(severity.as_str())?.into())
+}
+
+/// Builds a Java array of `class`, one element per item made by `build`.
+fn object_array<'local, T>(
+    env: &mut JNIEnv<'local>,
+    class: &str,
+    items: &[T],
+    build: impl Fn(&mut JNIEnv<'local>, &T) -> Result<JObject<'local>, Error>,
+) -> Result<JObjectArray<'local>, Error> {
+    let array = env.new_object_array(items.len() as i32, class, JObject::null())?;
+    for (index, item) in items.iter().enumerate() {
+        let element = build(env, item)?;
+        env.set_object_array_element(&array, index as i32, &element)?;
+        // Keeps the local reference table small on large batches.
+        env.delete_local_ref(element)?;
+    }
+    Ok(array)
+}
+
+/// Returns `result` to Java, throwing when it failed.
+fn finish(env: &mut JNIEnv, result: Result<JObjectArray, Error>) -> jobjectArray {
+    match result {
+        Ok(array) => array.into_raw(),
+        Err(Error::Invalid(message)) => {
+            let _ = env.throw_new("java/lang/IllegalArgumentException", message);
+            std::ptr::null_mut()
+        }
+        Err(Error::Jni(error)) => {
+            if !env.exception_check().unwrap_or(true) {
+                let _ = env.throw_new("java/lang/RuntimeException", error.to_string());
+            }
+            std::ptr::null_mut()
+        }
+    }
+}
+
+fn check_thresholds<'local>(
+    env: &mut JNIEnv<'local>,
+    ids: &JLongArray,
+    values: &JDoubleArray,
+    min: f64,
+    max: f64,
+) -> Result<JObjectArray<'local>, Error> {
+    let readings = readings(env, ids, values)?;
+    let alerts = detection_core::check_thresholds(readings, limit(min), limit(max));
+    object_array(env, ALERT_CLASS, &alerts, |env, alert| {
+        let breach_type: JObject = env.new_string(alert.breach_type.as_str())?.into();
+        let severity = severity(env, alert.severity)?;
+        Ok(env.new_object(
+            ALERT_CLASS,
+            ALERT_CONSTRUCTOR,
+            &[
+                JValue::Long(alert.reading_id),
+                JValue::Double(alert.value),
+                JValue::Object(&breach_type),
+                JValue::Double(alert.threshold_value),
+                JValue::Object(&severity),
+            ],
+        )?)
+    })
+}
+
+type Detect = fn(&[(i64, f64)], f64, &SeverityBands) -> Vec<detection_core::Outlier>;
+
+fn outliers<'local>(
+    env: &mut JNIEnv<'local>,
+    detect: Detect,
+    ids: &JLongArray,
+    values: &JDoubleArray,
+    threshold: f64,
+    bands: SeverityBands,
+) -> Result<JObjectArray<'local>, Error> {
+    let threshold = positive(threshold)?;
+    let readings = readings(env, ids, values)?;
+    let outliers = detect(&readings, threshold, &bands);
+    object_array(env, OUTLIER_CLASS, &outliers, |env, outlier| {
+        let severity = severity(env, outlier.severity)?;
+        Ok(env.new_object(
+            OUTLIER_CLASS,
+            OUTLIER_CONSTRUCTOR,
+            &[
+                JValue::Long(outlier.reading_id),
+                JValue::Double(outlier.value),
+                JValue::Double(outlier.score),
+                JValue::Object(&severity),
+            ],
+        )?)
+    })
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codetex_detection_Detection_checkThresholds0<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ids: JLongArray<'local>,
+    values: JDoubleArray<'local>,
+    min: jdouble,
+    max: jdouble,
+) -> jobjectArray {
+    let result = check_thresholds(&mut env, &ids, &values, min, max);
+    finish(&mut env, result)
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codetex_detection_Detection_zscoreOutliers0<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ids: JLongArray<'local>,
+    values: JDoubleArray<'local>,
+    threshold: jdouble,
+    high: jdouble,
+    critical: jdouble,
+) -> jobjectArray {
+    let result = bands(high, critical).and_then(|bands| {
+        let detect: Detect = detection_core::zscore_outliers;
+        outliers(&mut env, detect, &ids, &values, threshold, bands)
+    });
+    finish(&mut env, result)
+}
+
+#[unsafe(no_mangle)]
+pub extern "system" fn Java_com_codetex_detection_Detection_madOutliers0<'local>(
+    mut env: JNIEnv<'local>,
+    _class: JClass<'local>,
+    ids: JLongArray<'local>,
+    values: JDoubleArray<'local>,
+    threshold: jdouble,
+    high: jdouble,
+    critical: jdouble,
+) -> jobjectArray {
+    let result = bands(high, critical).and_then(|bands| {
+        let detect: Detect = detection_core::mad_outliers;
+        outliers(&mut env, detect, &ids, &values, threshold, bands)
+    });
+    finish(&mut env, result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sentinels_and_validation() {
+        assert_eq!(limit(f64::NAN), None);
+        assert_eq!(limit(-5.0), Some(-5.0));
+        assert!(matches!(bands(f64::NAN, f64::NAN), Ok(b) if b == SeverityBands::default()));
+        assert!(matches!(bands(2.0, f64::NAN), Err(Error::Invalid(_))));
+        assert!(matches!(bands(3.0, 2.0), Err(Error::Invalid(_))));
+        assert!(matches!(positive(f64::INFINITY), Err(Error::Invalid(_))));
+        assert!(matches!(pairs(&[1, 2], &[1.0]), Err(Error::Invalid(_))));
+        assert_eq!(pairs(&[1], &[4.5]).ok(), Some(vec![(1, 4.5)]));
+    }
+}
@@ -0,0 +1,105 @@
+//! Browser bindings of `detection-core`, so the dashboard can screen readings
+//! before sending them to the service and get the same verdicts.
+//!
+//! Built with `wasm-pack build crates/detection-wasm --target web`:
+//!
+//! ```js
+//! import init, { checkThresholds, detectZScore, detectMad } from "./pkg/detection_wasm.js";
+//!
+//! await init();
+//! const readings = [{ id: 1, value: 10.0 }, { id: 2, value: 97.5 }];
+//! checkThresholds(readings, 0, 95);      // [{ reading_id: 2, breach_type: "above_maximum", ... }]
+//! detectZScore(readings, 2.0);           // [{ reading_id, value, score, severity }]
+//! detectMad(readings, 3.5, { high: 4, critical: 5 });
+//! ```
+//!
+//! Readings are `{ id, value }` objects; other fields are ignored. Invalid
+//! input throws an `Error`.
+
+use detection_core::{Alert, Outlier, SeverityBands, mad_outliers, zscore_outliers};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+#[derive(Deserialize)]
+struct Reading {
+    id: i64,
+    value: f64,
+}
+
+fn readings(value: JsValue) -> Result<Vec<Reading>, JsError> {
+    serde_wasm_bindgen::from_value(value).map_err(|e| JsError::new(&format!("readings: {}", e)))
+}
+
+/// The severity bands given, or the service's defaults when left out.
+fn bands(value: JsValue) -> Result<SeverityBands, JsError> {
+    if value.is_undefined() || value.is_null() {
+        return Ok(SeverityBands::default());
+    }
+    let bands: SeverityBands = serde_wasm_bindgen::from_value(value)
+        .map_err(|e| JsError::new(&format!("bands: {}", e)))?;
+    if !(bands.high > 0.0 && bands.critical > bands.high) {
+        return Err(JsError::new(
+            "bands.critical must be above bands.high, both positive",
+        ));
+    }
+    Ok(bands)
+}
+
+fn positive(name: &str, threshold: f64) -> Result<f64, JsError> {
+    if threshold.is_finite() && threshold > 0.0 {
+        Ok(threshold)
+    } else {
+        Err(JsError::new(&format!(
+            "{} must be a positive number, got {}",
+            name, threshold
+        )))
+    }
+}
+
+fn to_js<T: Serialize>(value: &T) -> Result<JsValue, JsError> {
+    serde_wasm_bindgen::to_value(value).map_err(|e| JsError::new(&e.to_string()))
+}
+
+fn pairs(readings: JsValue) -> Result<Vec<(i64, f64)>, JsError> {
+    Ok(self::readings(readings)?
+        .into_iter()
+        .map(|r| (r.id, r.value))
+        .collect())
+}
+
+/// Returns an alert for every reading below `min` or above `max`, graded as
+/// the threshold checker grades them.
+#[wasm_bindgen(js_name = checkThresholds)]
+pub fn check_thresholds(
+    readings: JsValue,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<JsValue, JsError> {
+    let alerts: Vec<Alert> = detection_core::check_thresholds(pairs(readings)?, min, max);
+    to_js(&alerts)
+}
+
+/// Flags the readings whose z-score against all of them exceeds
+/// `threshold`, graded by `bands` (`{ high, critical }`) as the service
+/// grades them.
+#[wasm_bindgen(js_name = detectZScore)]
+pub fn detect_zscore(
+    readings: JsValue,
+    threshold: f64,
+    bands: JsValue,
+) -> Result<JsValue, JsError> {
+    let threshold = positive("threshold", threshold)?;
+    let outliers: Vec<Outlier> =
+        zscore_outliers(&pairs(readings)?, threshold, &self::bands(bands)?);
+    to_js(&outliers)
+}
+
+/// Flags the readings whose modified z-score around the median exceeds
+/// `threshold` (3.5 is customary), graded by `bands` like z-scores. Less
+/// thrown off than `detectZScore` by several outliers in one batch.
+#[wasm_bindgen(js_name = detectMad)]
+pub fn detect_mad(readings: JsValue, threshold: f64, bands: JsValue) -> Result<JsValue, JsError> {
+    let threshold = positive("threshold", threshold)?;
+    let outliers: Vec<Outlier> = mad_outliers(&pairs(readings)?, threshold, &self::bands(bands)?);
+    to_js(&outliers)
+}
@@ -0,0 +1,224 @@
+//! The `[cli]` section of the settings file, overridden by `DETECTOR_*`
+//! environment variables and then by the flags; see `config-core`.
+//!
+//! ```toml
+//! [cli]
+//! max = 95.0
+//! zscore = 3.0
+//! method = "holt_winters"
+//! warmup = 60
+//! ```
+
+use std::path::Path;
+
+use config_core::{Loader, Validate, config_file};
+use detection_core::SeverityBands;
+use detection_core::detector::Registry;
+use serde::{Deserialize, Serialize};
+
+use crate::detect::Detectors;
+
+const ENV_PREFIX: &str = "DETECTOR_";
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct CliSettings {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub zscore: Option<f64>,
+    pub reservoir: Option<f64>,
+    /// Seed of the reservoir samples.
+    pub seed: u64,
+    /// A registry detector, e.g. `mad` or `holt_winters`, in `analyze`.
+    pub method: Option<String>,
+    /// Score threshold of `method`; the detector's own by default.
+    pub threshold: Option<f64>,
+    pub high: f64,
+    pub critical: f64,
+    /// Readings of a sensor seen before its z-scores are checked, in `watch`
+    /// and `monitor`.
+    pub warmup: u64,
+}
+
+impl Default for CliSettings {
+    fn default() -> Self {
+        let bands = SeverityBands::default();
+        Self {
+            min: None,
+            max: None,
+            zscore: None,
+            reservoir: None,
+            seed: 0,
+            method: None,
+            threshold: None,
+            high: bands.high,
+            critical: bands.critical,
+            warmup: 30,
+        }
+    }
+}
+
+impl Validate for CliSettings {
+    fn validate(&self) -> Result<(), String> {
+        for (name, threshold) in [
+            ("zscore", self.zscore),
+            ("reservoir", self.reservoir),
+            ("threshold", self.threshold),
+        ] {
+            if let Some(threshold) = threshold
+                && !(threshold.is_finite() && threshold > 0.0)
+            {
+                return Err(format!(
+                    "{} must be a positive number, got {}",
+                    name, threshold
+                ));
+            }
+        }
+        if let Some(method) = &self.method {
+            let registry = Registry::builtin();
+            if registry.get(method).is_none() {
+                let names: Vec<&str> = registry.names().collect();
+                return Err(format!(
+                    "unknown method {:?}; choose one of {}",
+                    method,
+                    names.join(", ")
+                ));
+            }
+        } else if self.threshold.is_some() {
+            return Err("threshold applies to a method; give --method".to_string());
+        }
+        if !(self.high > 0.0 && self.critical > self.high) {
+            return Err("critical must be above high, both positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl CliSettings {
+    pub fn detectors(&self) -> Result<Detectors, String> {
+        let detectors = Detectors {
+            min: self.min,
+            max: self.max,
+            zscore: self.zscore,
+            reservoir: self.reservoir,
+            seed: self.seed,
+            method: self
+                .method
+                .as_deref()
+                .and_then(|method| Registry::builtin().names().find(|name| *name == method)),
+            threshold: self.threshold,
+            bands: SeverityBands {
+                high: self.high,
+                critical: self.critical,
+            },
+        };
+        if detectors.is_empty() {
+            return Err(
+                "nothing to check: give --min, --max, --zscore, --reservoir or --method"
+                    .to_string(),
+            );
+        }
+        Ok(detectors)
+    }
+}
+
+/// The flags given on the command line; unset ones leave the layers below
+/// in place.
+#[derive(Default, Serialize)]
+pub struct Flags {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub zscore: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reservoir: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub method: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub threshold: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub high: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critical: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warmup: Option<u64>,
+}
+
+pub fn load(config: Option<&Path>, flags: &Flags) -> Result<CliSettings, String> {
+    Loader::new("cli")
+        .file(config_file(config))
+        .env(ENV_PREFIX)
+        .overrides(flags)
+        .load()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_override_the_file() {
+        let path = std::env::temp_dir().join(format!("detector-cli-{}.toml", std::process::id()));
+        std::fs::write(&path, "[cli]\nmax = 95.0\nzscore = 3.0\nwarmup = 60\n").unwrap();
+        let flags = Flags {
+            zscore: Some(2.5),
+            ..Flags::default()
+        };
+        let settings = load(Some(&path), &flags);
+        let invalid = load(
+            Some(&path),
+            &Flags {
+                high: Some(4.0),
+                ..Flags::default()
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let settings = settings.unwrap();
+        assert_eq!(
+            (settings.max, settings.zscore, settings.warmup),
+            (Some(95.0), Some(2.5), 60)
+        );
+        assert_eq!(
+            settings.detectors().unwrap().bands,
+            SeverityBands::default()
+        );
+        assert_eq!(
+            invalid.unwrap_err(),
+            "critical must be above high, both positive"
+        );
+        assert!(CliSettings::default().detectors().is_err());
+    }
+
+    #[test]
+    fn test_method_names_a_registry_detector() {
+        let flags = |method: &str, threshold| Flags {
+            method: Some(method.to_string()),
+            threshold,
+            ..Flags::default()
+        };
+        let detectors = load(None, &flags("holt_winters", Some(4.0)))
+            .unwrap()
+            .detectors()
+            .unwrap();
+        assert_eq!(
+            (detectors.method, detectors.threshold),
+            (Some("holt_winters"), Some(4.0))
+        );
+        let unknown = load(None, &flags("prophet", None)).unwrap_err();
+        assert!(unknown.starts_with("unknown method \"prophet\"; choose one of ewma,"));
+        assert!(load(None, &flags("mad", Some(0.0))).is_err());
+        let alone = Flags {
+            threshold: Some(3.0),
+            ..Flags::default()
+        };
+        assert_eq!(
+            load(None, &alone).unwrap_err(),
+            "threshold applies to a method; give --method"
+        );
+    }
+}
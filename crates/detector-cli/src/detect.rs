@@ -0,0 +1,421 @@
+//! Runs the detectors from `detection-core` over readings: over a whole file
+//! at once with [`run`], or reading by reading with a [`Watcher`]. A file
+//! may also be scored by any detector of the `detection-core` registry, by
+//! name as `/analyze` takes its `method`.
+
+use std::collections::HashMap;
+
+use detection_core::detector::{Registry, ReservoirDetector};
+use detection_core::stats::{Reservoir, RunningStats, ZScorer};
+use detection_core::{Backend, Breach, Detector as _, Severity, SeverityBands, check_thresholds};
+use serde::{Serialize, Serializer};
+
+use crate::input::Reading;
+
+/// Which checks to run; none of them is on by default.
+#[derive(Clone, Debug, Default)]
+pub struct Detectors {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    /// Z-score threshold, scoring each sensor's series against its own mean.
+    pub zscore: Option<f64>,
+    /// Z-score threshold against a seeded sample of each sensor's earlier
+    /// readings.
+    pub reservoir: Option<f64>,
+    /// Seed of the reservoir samples, so runs over the same readings agree.
+    pub seed: u64,
+    /// A detector of [`Registry::builtin`] scoring each sensor's series,
+    /// by name, with `threshold` or else the detector's own.
+    pub method: Option<&'static str>,
+    pub threshold: Option<f64>,
+    pub bands: SeverityBands,
+}
+
+impl Detectors {
+    pub fn is_empty(&self) -> bool {
+        self.min.is_none()
+            && self.max.is_none()
+            && self.zscore.is_none()
+            && self.reservoir.is_none()
+            && self.method.is_none()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detector {
+    Threshold,
+    ZScore,
+    Reservoir,
+    /// A registry detector, by name.
+    Method(&'static str),
+}
+
+impl Detector {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Detector::Threshold => "threshold",
+            Detector::ZScore => "zscore",
+            Detector::Reservoir => "reservoir",
+            Detector::Method(name) => name,
+        }
+    }
+}
+
+impl Serialize for Detector {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// One finding. Fields that do not apply to the detector are empty.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Alert {
+    pub reading_id: i64,
+    pub sensor_id: Option<i64>,
+    pub timestamp: Option<String>,
+    pub value: f64,
+    pub detector: Detector,
+    pub severity: Severity,
+    pub breach_type: Option<Breach>,
+    pub threshold_value: Option<f64>,
+    pub z_score: Option<f64>,
+}
+
+/// Readings from which [`backend`] looks for a GPU; below, setting one up
+/// takes longer than scoring on the CPU.
+pub const GPU_MIN_READINGS: usize = 1_000_000;
+
+/// The backend to z-score `readings` readings on.
+pub fn backend(readings: usize) -> Backend {
+    if readings >= GPU_MIN_READINGS {
+        Backend::detect()
+    } else {
+        Backend::Cpu
+    }
+}
+
+/// Returns the alerts for `readings` in reading order, threshold breaches
+/// before the z-score, the reservoir and then the method alert of the same
+/// reading. Z-scores are computed on `backend`, every sensor's series at
+/// once.
+pub fn run(readings: &[Reading], detectors: &Detectors, backend: &Backend) -> Vec<Alert> {
+    let mut series: HashMap<Option<i64>, (Vec<usize>, Vec<f64>)> = HashMap::new();
+    if detectors.zscore.is_some() || detectors.reservoir.is_some() || detectors.method.is_some() {
+        for (position, reading) in readings.iter().enumerate() {
+            let (positions, values) = series.entry(reading.sensor_id).or_default();
+            positions.push(position);
+            values.push(reading.value);
+        }
+    }
+    let series: Vec<(Vec<usize>, Vec<f64>)> = series.into_values().collect();
+
+    let z_scores: HashMap<usize, f64> = match detectors.zscore {
+        Some(threshold) => {
+            let values: Vec<&[f64]> = series.iter().map(|(_, values)| values.as_slice()).collect();
+            backend
+                .zscores(&values, threshold)
+                .into_iter()
+                .zip(&series)
+                .flat_map(|(flagged, (positions, _))| {
+                    flagged
+                        .into_iter()
+                        .map(|(index, z_score)| (positions[index], z_score))
+                })
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+    let reservoir_scores: HashMap<usize, f64> = match detectors.reservoir {
+        Some(threshold) => {
+            let detector = ReservoirDetector {
+                seed: detectors.seed,
+                ..ReservoirDetector::default()
+            };
+            series
+                .iter()
+                .flat_map(|(positions, values)| {
+                    detector
+                        .scores(values)
+                        .into_iter()
+                        .zip(positions)
+                        .filter(|(score, _)| score.abs() > threshold)
+                        .map(|(score, &position)| (position, score))
+                })
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+    let method_scores: HashMap<usize, f64> = match detectors
+        .method
+        .and_then(|name| Registry::builtin().get(name))
+    {
+        Some(detector) => {
+            let threshold = detectors
+                .threshold
+                .unwrap_or_else(|| detector.default_threshold());
+            series
+                .iter()
+                .flat_map(|(positions, values)| {
+                    detector
+                        .scores(values)
+                        .into_iter()
+                        .zip(positions)
+                        .filter(|(score, _)| score.abs() > threshold)
+                        .map(|(score, &position)| (position, score))
+                })
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+
+    readings
+        .iter()
+        .enumerate()
+        .flat_map(|(position, reading)| {
+            let mut alerts = check(
+                reading,
+                detectors,
+                z_scores.get(&position).copied(),
+                reservoir_scores.get(&position).copied(),
+            );
+            if let (Some(name), Some(&score)) = (detectors.method, method_scores.get(&position)) {
+                alerts.push(Alert {
+                    z_score: Some(score),
+                    ..alert(
+                        reading,
+                        Detector::Method(name),
+                        detectors.bands.classify(score.abs()),
+                    )
+                });
+            }
+            alerts
+        })
+        .collect()
+}
+
+/// An alert of `detector` on `reading`, without the fields of a finding.
+fn alert(reading: &Reading, detector: Detector, severity: Severity) -> Alert {
+    Alert {
+        reading_id: reading.id,
+        sensor_id: reading.sensor_id,
+        timestamp: reading.timestamp.clone(),
+        value: reading.value,
+        detector,
+        severity,
+        breach_type: None,
+        threshold_value: None,
+        z_score: None,
+    }
+}
+
+/// Checks one reading, with its z-score and reservoir score when past their
+/// thresholds.
+fn check(
+    reading: &Reading,
+    detectors: &Detectors,
+    z_score: Option<f64>,
+    reservoir_score: Option<f64>,
+) -> Vec<Alert> {
+    let alert = |detector, severity| alert(reading, detector, severity);
+    let mut alerts: Vec<Alert> =
+        check_thresholds([(reading.id, reading.value)], detectors.min, detectors.max)
+            .into_iter()
+            .map(|breach| Alert {
+                breach_type: Some(breach.breach_type),
+                threshold_value: Some(breach.threshold_value),
+                ..alert(Detector::Threshold, breach.severity)
+            })
+            .collect();
+    for (detector, score) in [
+        (Detector::ZScore, z_score),
+        (Detector::Reservoir, reservoir_score),
+    ] {
+        if let Some(score) = score {
+            alerts.push(Alert {
+                z_score: Some(score),
+                ..alert(detector, detectors.bands.classify(score.abs()))
+            });
+        }
+    }
+    alerts
+}
+
+/// Online detection: each reading is z-scored against the readings of its
+/// sensor seen before it, and against a seeded sample of them, once there
+/// are `warmup` of them, and then added to them.
+pub struct Watcher {
+    detectors: Detectors,
+    warmup: u64,
+    series: HashMap<Option<i64>, RunningStats>,
+    samples: HashMap<Option<i64>, Reservoir>,
+}
+
+impl Watcher {
+    pub fn new(detectors: Detectors, warmup: u64) -> Self {
+        Self {
+            detectors,
+            warmup,
+            series: HashMap::new(),
+            samples: HashMap::new(),
+        }
+    }
+
+    pub fn observe(&mut self, reading: &Reading) -> Vec<Alert> {
+        let stats = self.series.entry(reading.sensor_id).or_default();
+        let scorer = match self.detectors.zscore {
+            Some(threshold) if stats.count() >= self.warmup => Some(ZScorer::new(stats, threshold)),
+            _ => None,
+        };
+        stats.push(reading.value);
+        let z_score = scorer.and_then(|scorer| scorer.score(reading.value));
+        let reservoir_score = self.detectors.reservoir.and_then(|threshold| {
+            let capacity = ReservoirDetector::default().capacity;
+            let sample = self
+                .samples
+                .entry(reading.sensor_id)
+                .or_insert_with(|| Reservoir::new(capacity, self.detectors.seed));
+            let score = (sample.seen() >= self.warmup)
+                .then(|| ZScorer::new(&sample.stats(), threshold).score(reading.value))
+                .flatten();
+            sample.push(reading.value);
+            score
+        });
+        check(reading, &self.detectors, z_score, reservoir_score)
+    }
+
+    /// The readings of `sensor_id` seen so far.
+    pub fn baseline(&self, sensor_id: Option<i64>) -> Option<&RunningStats> {
+        self.series.get(&sensor_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(id: i64, sensor_id: i64, value: f64) -> Reading {
+        Reading {
+            id,
+            sensor_id: Some(sensor_id),
+            value,
+            timestamp: None,
+        }
+    }
+
+    #[test]
+    fn test_scores_each_sensor_against_its_own_series() {
+        let mut readings: Vec<Reading> = (1..=20).map(|id| reading(id, 1, 10.0)).collect();
+        readings.push(reading(21, 1, 100.0));
+        // Sensor 2 runs around 100, so the same value is normal there.
+        readings.extend((22..=30).map(|id| reading(id, 2, 99.0 + (id % 3) as f64)));
+        let detectors = Detectors {
+            max: Some(95.0),
+            zscore: Some(3.0),
+            ..Detectors::default()
+        };
+
+        let alerts = run(&readings, &detectors, &Backend::Cpu);
+
+        let found: Vec<(i64, Detector)> =
+            alerts.iter().map(|a| (a.reading_id, a.detector)).collect();
+        assert_eq!(
+            found[..2],
+            [(21, Detector::Threshold), (21, Detector::ZScore)]
+        );
+        assert_eq!(alerts[0].breach_type, Some(Breach::AboveMaximum));
+        assert_eq!(alerts[1].severity, Severity::Critical);
+        assert!(
+            alerts[2..]
+                .iter()
+                .all(|a| a.detector == Detector::Threshold)
+        );
+        assert_eq!(alerts.len(), 2 + 9);
+    }
+
+    #[test]
+    fn test_method_scores_each_sensor_with_a_registry_detector() {
+        // A daily cycle on sensor 1, with one hour too warm for its time of
+        // day, and a flat sensor 2.
+        let mut readings: Vec<Reading> = (0..96)
+            .map(|hour| {
+                let phase = (hour % 24) as f64 / 24.0 * std::f64::consts::TAU;
+                let noise = ((hour * 37) % 11) as f64 / 10.0 - 0.5;
+                reading(hour, 1, 20.0 + 8.0 * phase.sin() + noise)
+            })
+            .collect();
+        readings[80].value += 6.0;
+        readings.extend((96..120).map(|id| reading(id, 2, 20.0)));
+        let detectors = |method| Detectors {
+            method: Some(method),
+            ..Detectors::default()
+        };
+
+        let alerts = run(&readings, &detectors("holt_winters"), &Backend::Cpu);
+        let found: Vec<(i64, Detector)> =
+            alerts.iter().map(|a| (a.reading_id, a.detector)).collect();
+        assert_eq!(found, [(80, Detector::Method("holt_winters"))]);
+        assert!(alerts[0].z_score.unwrap() > 3.0);
+        assert_eq!(
+            serde_json::to_value(&alerts[0]).unwrap()["detector"],
+            "holt_winters"
+        );
+        // The whole cycle looks alike to a detector ignoring the time of day.
+        assert!(run(&readings, &detectors("mad"), &Backend::Cpu).is_empty());
+        let lowered = Detectors {
+            threshold: Some(1.0),
+            ..detectors("mad")
+        };
+        assert!(!run(&readings, &lowered, &Backend::Cpu).is_empty());
+    }
+
+    #[test]
+    fn test_watcher_scores_after_warmup_per_sensor() {
+        let mut watcher = Watcher::new(
+            Detectors {
+                zscore: Some(3.0),
+                ..Detectors::default()
+            },
+            5,
+        );
+        // Too little history yet on either sensor.
+        assert!(watcher.observe(&reading(1, 1, 10.0)).is_empty());
+        assert!(watcher.observe(&reading(2, 2, 500.0)).is_empty());
+        for id in 3..8 {
+            let value = 10.0 + (id % 2) as f64;
+            assert!(watcher.observe(&reading(id, 1, value)).is_empty());
+        }
+
+        let alerts = watcher.observe(&reading(8, 1, 50.0));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].detector, Detector::ZScore);
+        assert!(alerts[0].z_score.unwrap() > 3.0);
+        assert!(watcher.observe(&reading(9, 2, 500.0)).is_empty());
+    }
+
+    #[test]
+    fn test_seeded_reservoir_alerts_are_reproducible() {
+        // Both sensors drift upward past the sample's capacity, so which of
+        // their readings it holds decides the score of each.
+        let readings: Vec<Reading> = (1..=1200)
+            .map(|id| reading(id, 1 + id % 2, (id / 2) as f64 + (id % 7) as f64 * 3.0))
+            .collect();
+        let detectors = |seed| Detectors {
+            reservoir: Some(1.5),
+            seed,
+            ..Detectors::default()
+        };
+        let alerts = |seed| run(&readings, &detectors(seed), &Backend::Cpu);
+
+        let first = alerts(7);
+        assert!(!first.is_empty());
+        assert!(first.iter().all(|a| a.detector == Detector::Reservoir));
+        assert_eq!(first, alerts(7));
+        assert_ne!(first, alerts(8));
+
+        let mut watcher = Watcher::new(detectors(7), 20);
+        let mut again = Watcher::new(detectors(7), 20);
+        for reading in &readings {
+            assert_eq!(watcher.observe(reading), again.observe(reading));
+        }
+    }
+}
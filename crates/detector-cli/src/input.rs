@@ -0,0 +1,248 @@
+//! Reading files: CSV with a header row, NDJSON (one object per line) or
+//! Parquet.
+//!
+//! Every row needs a `value`. `id` defaults to the row's position (from 1),
+//! and `sensor_id` and `timestamp` are optional; series are scored per
+//! `sensor_id`. Parquet timestamps must be strings.
+//!
+//! [`LineParser`] reads CSV and NDJSON one line at a time, for streams.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use clap::ValueEnum;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::file::reader::ChunkReader;
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    Csv,
+    Ndjson,
+    Parquet,
+}
+
+impl InputFormat {
+    /// Guesses the format from the file extension.
+    pub fn detect(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "csv" => Some(InputFormat::Csv),
+            "ndjson" | "jsonl" => Some(InputFormat::Ndjson),
+            "parquet" => Some(InputFormat::Parquet),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    pub id: i64,
+    pub sensor_id: Option<i64>,
+    pub value: f64,
+    pub timestamp: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Row {
+    #[serde(default)]
+    id: Option<i64>,
+    #[serde(default)]
+    sensor_id: Option<i64>,
+    value: f64,
+    #[serde(default)]
+    timestamp: Option<String>,
+}
+
+impl Row {
+    fn into_reading(self, position: usize) -> Reading {
+        Reading {
+            id: self.id.unwrap_or(position as i64 + 1),
+            sensor_id: self.sensor_id,
+            value: self.value,
+            timestamp: self.timestamp,
+        }
+    }
+}
+
+pub fn read_file(path: &Path, format: InputFormat) -> Result<Vec<Reading>, String> {
+    let context = |e: &dyn std::fmt::Display| format!("{}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| context(&e))?;
+    match format {
+        InputFormat::Csv => read_csv(file),
+        InputFormat::Ndjson => read_ndjson(BufReader::new(file)),
+        InputFormat::Parquet => read_parquet(file),
+    }
+    .map_err(|e| context(&e))
+}
+
+pub fn read_csv(reader: impl Read) -> Result<Vec<Reading>, String> {
+    csv::Reader::from_reader(reader)
+        .deserialize::<Row>()
+        .enumerate()
+        .map(|(position, row)| {
+            row.map(|row| row.into_reading(position))
+                .map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+pub fn read_ndjson(reader: impl BufRead) -> Result<Vec<Reading>, String> {
+    let mut readings = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: Row =
+            serde_json::from_str(&line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        readings.push(row.into_reading(readings.len()));
+    }
+    Ok(readings)
+}
+
+/// Parses a stream line by line; a CSV stream starts with its header.
+pub enum LineParser {
+    Ndjson,
+    Csv(Option<csv::StringRecord>),
+}
+
+impl LineParser {
+    pub fn new(format: InputFormat) -> Result<Self, String> {
+        match format {
+            InputFormat::Ndjson => Ok(LineParser::Ndjson),
+            InputFormat::Csv => Ok(LineParser::Csv(None)),
+            InputFormat::Parquet => Err("parquet files cannot be streamed".to_string()),
+        }
+    }
+
+    /// Parses one line into the reading at `position`; the CSV header and
+    /// blank lines give `None`.
+    pub fn parse(&mut self, line: &str, position: usize) -> Result<Option<Reading>, String> {
+        if line.trim().is_empty() {
+            return Ok(None);
+        }
+        let row: Row = match self {
+            LineParser::Ndjson => serde_json::from_str(line).map_err(|e| e.to_string())?,
+            LineParser::Csv(headers) => {
+                let record = csv::ReaderBuilder::new()
+                    .has_headers(false)
+                    .from_reader(line.as_bytes())
+                    .records()
+                    .next()
+                    .transpose()
+                    .map_err(|e| e.to_string())?
+                    .unwrap_or_default();
+                let Some(headers) = headers else {
+                    *headers = Some(record);
+                    return Ok(None);
+                };
+                record
+                    .deserialize(Some(headers))
+                    .map_err(|e| e.to_string())?
+            }
+        };
+        Ok(Some(row.into_reading(position)))
+    }
+}
+
+/// Reads a Parquet file, from a `File` or the whole file in memory as
+/// `bytes::Bytes`.
+pub fn read_parquet(input: impl ChunkReader + 'static) -> Result<Vec<Reading>, String> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(input)
+        .and_then(|builder| builder.build())
+        .map_err(|e| e.to_string())?;
+    let mut readings = Vec::new();
+    for batch in reader {
+        let batch = batch.map_err(|e| e.to_string())?;
+        let rows: Vec<Row> = serde_arrow::from_record_batch(&batch).map_err(|e| e.to_string())?;
+        for row in rows {
+            readings.push(row.into_reading(readings.len()));
+        }
+    }
+    Ok(readings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_schema::FieldRef;
+    use parquet::arrow::ArrowWriter;
+    use serde::Serialize;
+    use serde_arrow::schema::{SchemaLike, TracingOptions};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_csv_and_ndjson_default_missing_columns() {
+        let csv = "value,sensor_id\n10.5,7\n11.0,\n";
+        let readings = read_csv(csv.as_bytes()).unwrap();
+        assert_eq!(readings[0].id, 1);
+        assert_eq!(readings[0].sensor_id, Some(7));
+        assert_eq!(readings[1].id, 2);
+        assert_eq!(readings[1].sensor_id, None);
+
+        let ndjson = "{\"id\": 9, \"value\": 3.0, \"timestamp\": \"2026-01-19T10:00:00\"}\n\n{\"value\": 4.0}\n";
+        let readings = read_ndjson(ndjson.as_bytes()).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!(
+            readings[0].timestamp.as_deref(),
+            Some("2026-01-19T10:00:00")
+        );
+        assert_eq!(readings[1].id, 2);
+
+        assert!(read_ndjson("{\"id\": 1}\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_line_parser_takes_the_csv_header_first() {
+        let mut parser = LineParser::new(InputFormat::Csv).unwrap();
+        assert_eq!(parser.parse("sensor_id,value", 0), Ok(None));
+        let reading = parser.parse("7,12.5", 0).unwrap().unwrap();
+        assert_eq!(
+            (reading.id, reading.sensor_id, reading.value),
+            (1, Some(7), 12.5)
+        );
+        assert!(parser.parse("7,warm", 1).is_err());
+
+        let mut parser = LineParser::new(InputFormat::Ndjson).unwrap();
+        assert_eq!(parser.parse("  ", 0), Ok(None));
+        assert_eq!(
+            parser
+                .parse(r#"{"id": 3, "value": 1.0}"#, 0)
+                .unwrap()
+                .unwrap()
+                .id,
+            3
+        );
+        assert!(LineParser::new(InputFormat::Parquet).is_err());
+    }
+
+    #[test]
+    fn test_reads_parquet() {
+        #[derive(Deserialize, Serialize)]
+        struct Written {
+            id: i64,
+            value: f64,
+        }
+        let rows = vec![Written { id: 4, value: 1.5 }, Written { id: 5, value: 2.5 }];
+        let fields = Vec::<FieldRef>::from_type::<Written>(TracingOptions::default()).unwrap();
+        let batch = serde_arrow::to_record_batch(&fields, &rows).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("detector-cli-{}.parquet", std::process::id()));
+        let mut writer = ArrowWriter::try_new(
+            File::create(&path).unwrap(),
+            Arc::new(arrow_schema::Schema::new(fields)),
+            None,
+        )
+        .unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+
+        let format = InputFormat::detect(&path).unwrap();
+        let readings = read_file(&path, format).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(readings.len(), 2);
+        assert_eq!((readings[1].id, readings[1].value), (5, 2.5));
+        assert_eq!(readings[1].sensor_id, None);
+    }
+}
@@ -0,0 +1,7 @@
+//! The reading parsers of `detector-cli`, as a library so the fuzz targets
+//! in `fuzz/` can feed them arbitrary input.
+//!
+//! - [`input`]: CSV, NDJSON and Parquet reading files, and streams line by
+//!   line
+
+pub mod input;
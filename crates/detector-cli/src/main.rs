@@ -0,0 +1,318 @@
+//! `detector-cli`: offline anomaly detection over reading files, with the
+//! same statistics and severity grading as the HTTP service, for machines
+//! where the service cannot run.
+//!
+//! ```text
+//! detector-cli analyze readings.csv --max 95 --zscore 3 --output-format csv -o alerts.csv
+//! detector-cli analyze readings.parquet --method holt_winters --threshold 3.5
+//! tail -f sensor.log | detector-cli watch --zscore 3
+//! detector-cli monitor --url ws://localhost:3001/ws/anomalies
+//! ```
+//!
+//! Checks and their severity bands can also come from the `[cli]` section of
+//! a settings file (`--config` or `ANOMALY_CONFIG`) and `DETECTOR_*`
+//! environment variables, which the flags override.
+
+mod config;
+mod detect;
+mod monitor;
+mod output;
+mod tail;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use clap::{Args, Parser, Subcommand};
+
+use config::{CliSettings, Flags};
+use detect::{Detectors, Watcher};
+use detection_core::Backend;
+use detector_cli::input::{self, InputFormat, LineParser};
+use output::{Color, LineFormat, OutputFormat};
+
+#[derive(Parser)]
+#[command(name = "detector-cli", version, about)]
+struct Cli {
+    /// Settings file with a `[cli]` section; defaults to $ANOMALY_CONFIG.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Checks every reading in a file and writes the alerts.
+    Analyze(AnalyzeArgs),
+    /// Follows a file, named pipe or stdin and prints alerts as they occur.
+    Watch(WatchArgs),
+    /// Shows sensors and alerts live in the terminal, from a local stream or
+    /// the service.
+    Monitor(MonitorArgs),
+}
+
+#[derive(Args)]
+struct DetectorArgs {
+    /// Alert on values below this minimum.
+    #[arg(long)]
+    min: Option<f64>,
+    /// Alert on values above this maximum.
+    #[arg(long)]
+    max: Option<f64>,
+    /// Alert on readings whose z-score magnitude exceeds this threshold.
+    #[arg(long)]
+    zscore: Option<f64>,
+    /// Alert on readings whose z-score against a random sample of the
+    /// sensor's earlier readings exceeds this threshold.
+    #[arg(long)]
+    reservoir: Option<f64>,
+    /// Seed of the samples, so reruns raise the same alerts [default: 0].
+    #[arg(long)]
+    seed: Option<u64>,
+    /// Alert on readings a detector of the service's `/analyze` scores past
+    /// --threshold: zscore, mad, iqr, ewma, rolling, reservoir, percentile
+    /// or holt_winters (`analyze` only).
+    #[arg(long)]
+    method: Option<String>,
+    /// Score threshold of --method [default: the detector's own].
+    #[arg(long)]
+    threshold: Option<f64>,
+    /// Minimum |z| graded high [default: 2.5].
+    #[arg(long)]
+    high: Option<f64>,
+    /// Minimum |z| graded critical [default: 3].
+    #[arg(long)]
+    critical: Option<f64>,
+}
+
+impl DetectorArgs {
+    /// The settings of the file and environment with these flags on top.
+    fn settings(&self, config: Option<&Path>, warmup: Option<u64>) -> Result<CliSettings, String> {
+        let flags = Flags {
+            min: self.min,
+            max: self.max,
+            zscore: self.zscore,
+            reservoir: self.reservoir,
+            seed: self.seed,
+            method: self.method.clone(),
+            threshold: self.threshold,
+            high: self.high,
+            critical: self.critical,
+            warmup,
+        };
+        config::load(config, &flags)
+    }
+}
+
+/// The detectors of `watch` and `monitor`, which check a reading as it
+/// arrives, while a method scores a sensor's whole series.
+fn online(settings: &CliSettings) -> Result<Detectors, String> {
+    match &settings.method {
+        Some(method) => Err(format!(
+            "method {} scores whole files; use it with analyze",
+            method
+        )),
+        None => settings.detectors(),
+    }
+}
+
+#[derive(Args)]
+struct AnalyzeArgs {
+    /// Readings file (.csv, .ndjson/.jsonl or .parquet).
+    input: PathBuf,
+    /// Input format, when the extension does not tell.
+    #[arg(long, value_enum)]
+    format: Option<InputFormat>,
+    #[command(flatten)]
+    detectors: DetectorArgs,
+    /// Where to write the alerts; stdout by default.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
+}
+
+fn analyze(args: AnalyzeArgs, config: Option<&Path>) -> Result<(), String> {
+    let detectors = args.detectors.settings(config, None)?.detectors()?;
+    let format = args
+        .format
+        .or_else(|| InputFormat::detect(&args.input))
+        .ok_or_else(|| {
+            format!(
+                "cannot tell the format of {}; pass --format",
+                args.input.display()
+            )
+        })?;
+    let readings = input::read_file(&args.input, format)?;
+    let backend = match detectors.zscore {
+        Some(_) => detect::backend(readings.len()),
+        None => Backend::Cpu,
+    };
+    if readings.len() >= detect::GPU_MIN_READINGS && detectors.zscore.is_some() {
+        eprintln!("Z-scoring on the {}", backend.name());
+    }
+    let alerts = detect::run(&readings, &detectors, &backend);
+
+    let writer: Box<dyn Write> = match &args.output {
+        Some(path) => {
+            Box::new(File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?)
+        }
+        None => Box::new(std::io::stdout().lock()),
+    };
+    output::write_alerts(BufWriter::new(writer), &alerts, args.output_format)?;
+    eprintln!(
+        "Checked {} readings: {} alerts",
+        readings.len(),
+        alerts.len()
+    );
+    Ok(())
+}
+
+#[derive(Args)]
+struct WatchArgs {
+    /// File or named pipe to follow (CSV or NDJSON); stdin when left out or `-`.
+    input: Option<PathBuf>,
+    /// Input format, when the extension does not tell; NDJSON by default.
+    #[arg(long, value_enum)]
+    format: Option<InputFormat>,
+    #[command(flatten)]
+    detectors: DetectorArgs,
+    /// Readings of a sensor seen before its z-scores are checked [default: 30].
+    #[arg(long)]
+    warmup: Option<u64>,
+    /// Read a file from its start instead of only the lines added to it.
+    #[arg(long)]
+    from_start: bool,
+    #[arg(long, value_enum, default_value_t)]
+    output_format: LineFormat,
+    /// Colors severities in text output.
+    #[arg(long, value_enum, default_value_t)]
+    color: Color,
+}
+
+fn watch(args: WatchArgs, config: Option<&Path>) -> Result<(), String> {
+    let settings = args.detectors.settings(config, args.warmup)?;
+    let detectors = online(&settings)?;
+    let path = args.input.filter(|path| path != Path::new("-"));
+    let format = args
+        .format
+        .or_else(|| path.as_deref().and_then(InputFormat::detect))
+        .unwrap_or(InputFormat::Ndjson);
+    let mut parser = LineParser::new(format)?;
+    let (reader, follow): (Box<dyn BufRead>, bool) = match &path {
+        Some(path) => (
+            tail::open(path, args.from_start, format == InputFormat::Csv)
+                .map_err(|e| format!("{}: {}", path.display(), e))?,
+            true,
+        ),
+        None => (Box::new(std::io::stdin().lock()), false),
+    };
+    let color = args.output_format == LineFormat::Text
+        && match args.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => std::io::stdout().is_terminal(),
+        };
+
+    let mut watcher = Watcher::new(detectors, settings.warmup);
+    let mut stdout = std::io::stdout().lock();
+    let (mut readings, mut line_number) = (0, 0);
+    tail::lines(reader, follow, |line| {
+        line_number += 1;
+        match parser.parse(line, readings) {
+            Ok(Some(reading)) => {
+                readings += 1;
+                for alert in watcher.observe(&reading) {
+                    let line = output::alert_line(&alert, args.output_format, color);
+                    // Stops quietly once the reader of stdout goes away.
+                    if writeln!(stdout, "{}", line)
+                        .and_then(|()| stdout.flush())
+                        .is_err()
+                    {
+                        return ControlFlow::Break(());
+                    }
+                }
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: Skipping line {}: {}", line_number, e),
+        }
+        ControlFlow::Continue(())
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[derive(Args)]
+struct MonitorArgs {
+    /// File or named pipe to follow (CSV or NDJSON); stdin when left out or `-`.
+    #[arg(conflicts_with = "url")]
+    input: Option<PathBuf>,
+    /// The service's live anomalies, e.g. ws://localhost:3001/ws/anomalies?sensors=1,2,
+    /// instead of checking readings locally.
+    #[arg(long)]
+    url: Option<String>,
+    /// Input format, when the extension does not tell; NDJSON by default.
+    #[arg(long, value_enum)]
+    format: Option<InputFormat>,
+    #[command(flatten)]
+    detectors: DetectorArgs,
+    /// Readings of a sensor seen before its z-scores are checked [default: 30].
+    #[arg(long)]
+    warmup: Option<u64>,
+    /// Read a file from its start instead of only the lines added to it.
+    #[arg(long)]
+    from_start: bool,
+}
+
+fn monitor(args: MonitorArgs, config: Option<&Path>) -> Result<(), String> {
+    let (updates, received) = mpsc::channel();
+    let view = match args.url {
+        Some(url) => {
+            let view = monitor::Monitor::new(url.clone(), None);
+            std::thread::spawn(move || monitor::read_service(url, updates));
+            view
+        }
+        None => {
+            let settings = args.detectors.settings(config, args.warmup)?;
+            let detectors = online(&settings)?;
+            let path = args.input.filter(|path| path != Path::new("-"));
+            let format = args
+                .format
+                .or_else(|| path.as_deref().and_then(InputFormat::detect))
+                .unwrap_or(InputFormat::Ndjson);
+            let parser = LineParser::new(format)?;
+            let (reader, follow, source): (Box<dyn BufRead + Send>, bool, String) = match &path {
+                Some(path) => (
+                    tail::open(path, args.from_start, format == InputFormat::Csv)
+                        .map_err(|e| format!("{}: {}", path.display(), e))?,
+                    true,
+                    path.display().to_string(),
+                ),
+                None => (
+                    Box::new(BufReader::new(std::io::stdin())),
+                    false,
+                    "stdin".to_string(),
+                ),
+            };
+            std::thread::spawn(move || monitor::read_stream(reader, follow, parser, updates));
+            monitor::Monitor::new(source, Some(Watcher::new(detectors, settings.warmup)))
+        }
+    };
+    monitor::run(view, received).map_err(|e| e.to_string())
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let config = cli.config.as_deref();
+    let result = match cli.command {
+        Command::Analyze(args) => analyze(args, config),
+        Command::Watch(args) => watch(args, config),
+        Command::Monitor(args) => monitor(args, config),
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
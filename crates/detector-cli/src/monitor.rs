@@ -0,0 +1,464 @@
+//! `monitor`: a terminal UI showing a sparkline and the running baseline of
+//! every sensor above a scrolling feed of alerts.
+//!
+//! Readings come from a local stream, checked as `watch` does, or the
+//! service's live anomalies are shown from its `/ws/anomalies` WebSocket.
+//! The service only sends anomalies, so in that mode the sparklines plot
+//! anomalous values and there are no baselines.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io::BufRead;
+use std::ops::ControlFlow;
+use std::sync::mpsc::{Receiver, Sender};
+use std::time::Duration;
+
+use detection_core::Severity;
+use detection_core::detector::Registry;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, List, ListItem, Paragraph, Sparkline};
+use ratatui::{DefaultTerminal, Frame};
+use serde::Deserialize;
+
+use crate::detect::{Alert, Detector, Watcher};
+use crate::input::{LineParser, Reading};
+use crate::output::{LineFormat, alert_line};
+use crate::tail;
+
+/// Values kept per sensor for its sparkline.
+const HISTORY: usize = 240;
+
+/// Alerts kept in the feed.
+const FEED_SIZE: usize = 500;
+
+/// Width of the sensor column left of the sparklines.
+const LABEL_WIDTH: u16 = 56;
+
+/// How long the UI waits for a key before drawing new updates.
+const FRAME_INTERVAL: Duration = Duration::from_millis(200);
+
+pub enum Update {
+    Reading(Reading),
+    Alert(Alert),
+    Status(String),
+}
+
+#[derive(Default)]
+struct SensorView {
+    values: VecDeque<f64>,
+    alerts: u64,
+}
+
+impl SensorView {
+    fn record(&mut self, value: f64) {
+        if self.values.len() == HISTORY {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+}
+
+pub struct Monitor {
+    source: String,
+    /// Checks local readings; `None` when showing the service's anomalies.
+    watcher: Option<Watcher>,
+    sensors: BTreeMap<Option<i64>, SensorView>,
+    feed: VecDeque<Alert>,
+    readings: u64,
+    alerts: u64,
+    status: String,
+}
+
+impl Monitor {
+    pub fn new(source: String, watcher: Option<Watcher>) -> Self {
+        Self {
+            source,
+            watcher,
+            sensors: BTreeMap::new(),
+            feed: VecDeque::new(),
+            readings: 0,
+            alerts: 0,
+            status: String::new(),
+        }
+    }
+
+    pub fn apply(&mut self, update: Update) {
+        match update {
+            Update::Reading(reading) => {
+                self.readings += 1;
+                self.sensors
+                    .entry(reading.sensor_id)
+                    .or_default()
+                    .record(reading.value);
+                let alerts = match &mut self.watcher {
+                    Some(watcher) => watcher.observe(&reading),
+                    None => Vec::new(),
+                };
+                for alert in alerts {
+                    self.push_alert(alert);
+                }
+            }
+            Update::Alert(alert) => {
+                let view = self.sensors.entry(alert.sensor_id).or_default();
+                view.record(alert.value);
+                self.push_alert(alert);
+            }
+            Update::Status(status) => self.status = status,
+        }
+    }
+
+    fn push_alert(&mut self, alert: Alert) {
+        self.alerts += 1;
+        self.sensors.entry(alert.sensor_id).or_default().alerts += 1;
+        if self.feed.len() == FEED_SIZE {
+            self.feed.pop_back();
+        }
+        self.feed.push_front(alert);
+    }
+
+    fn sensor_label(&self, sensor_id: Option<i64>, view: &SensorView) -> String {
+        let name = sensor_id.map_or("(none)".to_string(), |id| id.to_string());
+        let last = view.values.back().copied().unwrap_or_default();
+        let baseline = self
+            .watcher
+            .as_ref()
+            .and_then(|watcher| watcher.baseline(sensor_id))
+            .map_or("-".to_string(), |stats| {
+                format!("{:.2} ± {:.2}", stats.mean(), stats.std_dev())
+            });
+        format!(
+            "{:>8} {:>10.2} {:>20} {:>6}",
+            name, last, baseline, view.alerts
+        )
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
+        let [header, sensors, feed] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Percentage(50),
+            Constraint::Fill(1),
+        ])
+        .areas(frame.area());
+
+        let mut summary = format!(
+            "{} | {} readings, {} alerts | ",
+            self.source, self.readings, self.alerts
+        );
+        if !self.status.is_empty() {
+            summary += &format!("{} | ", self.status);
+        }
+        summary += "q quits";
+        frame.render_widget(
+            Paragraph::new(summary).style(Style::new().add_modifier(Modifier::REVERSED)),
+            header,
+        );
+
+        let block = Block::bordered().title(format!(
+            " {:>8} {:>10} {:>20} {:>6} ",
+            "sensor", "last", "mean ± std dev", "alerts"
+        ));
+        let inner = block.inner(sensors);
+        frame.render_widget(block, sensors);
+        for (row, (sensor_id, view)) in self.sensors.iter().take(inner.height as usize).enumerate()
+        {
+            let area = Rect {
+                y: inner.y + row as u16,
+                height: 1,
+                ..inner
+            };
+            let [label, spark] =
+                Layout::horizontal([Constraint::Length(LABEL_WIDTH), Constraint::Fill(1)])
+                    .areas(area);
+            frame.render_widget(Paragraph::new(self.sensor_label(*sensor_id, view)), label);
+            let history = spark.width as usize;
+            let values: Vec<f64> = view
+                .values
+                .iter()
+                .rev()
+                .take(history)
+                .rev()
+                .copied()
+                .collect();
+            frame.render_widget(
+                Sparkline::default()
+                    .data(scaled(&values))
+                    .max(8)
+                    .style(Style::new().fg(Color::Green)),
+                spark,
+            );
+        }
+
+        let items: Vec<ListItem> = self
+            .feed
+            .iter()
+            .take(feed.height as usize)
+            .map(|alert| {
+                ListItem::new(alert_line(alert, LineFormat::Text, false))
+                    .style(severity_style(alert.severity))
+            })
+            .collect();
+        frame.render_widget(
+            List::new(items).block(Block::bordered().title(" Alerts ")),
+            feed,
+        );
+    }
+}
+
+fn severity_style(severity: Severity) -> Style {
+    match severity {
+        Severity::Critical => Style::new().fg(Color::Red).add_modifier(Modifier::BOLD),
+        Severity::High => Style::new().fg(Color::Yellow),
+        Severity::Medium => Style::new().fg(Color::Cyan),
+    }
+}
+
+/// Maps values between their minimum and maximum onto the eight bar heights
+/// of a one-line sparkline, the lowest value still drawing a bar.
+fn scaled(values: &[f64]) -> Vec<u64> {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            if max > min {
+                1 + ((value - min) / (max - min) * 7.0).round() as u64
+            } else {
+                4
+            }
+        })
+        .collect()
+}
+
+/// Sends the readings of a local stream.
+pub fn read_stream(
+    reader: Box<dyn BufRead + Send>,
+    follow: bool,
+    mut parser: LineParser,
+    updates: Sender<Update>,
+) {
+    let (mut readings, mut line_number) = (0, 0);
+    let result = tail::lines(reader, follow, |line| {
+        line_number += 1;
+        let update = match parser.parse(line, readings) {
+            Ok(Some(reading)) => {
+                readings += 1;
+                Update::Reading(reading)
+            }
+            Ok(None) => return ControlFlow::Continue(()),
+            Err(e) => Update::Status(format!("skipped line {}: {}", line_number, e)),
+        };
+        match updates.send(update) {
+            Ok(()) => ControlFlow::Continue(()),
+            Err(_) => ControlFlow::Break(()),
+        }
+    });
+    let status = match result {
+        Ok(()) => "end of input".to_string(),
+        Err(e) => format!("read failed: {}", e),
+    };
+    let _ = updates.send(Update::Status(status));
+}
+
+/// A message from the service's `/ws/anomalies`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServiceMessage {
+    Subscribed {},
+    Anomaly {
+        sensor_id: i64,
+        reading_id: i64,
+        value: f64,
+        timestamp: String,
+        method: String,
+        score: f64,
+        severity: Severity,
+    },
+    Lagged {
+        missed: u64,
+    },
+    Error {
+        error: String,
+    },
+}
+
+fn service_update(text: &str) -> Update {
+    let message = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(e) => return Update::Status(format!("unreadable message: {}", e)),
+    };
+    match message {
+        ServiceMessage::Subscribed {} => Update::Status("subscribed".to_string()),
+        ServiceMessage::Anomaly {
+            sensor_id,
+            reading_id,
+            value,
+            timestamp,
+            method,
+            score,
+            severity,
+        } => {
+            let detector = match method.as_str() {
+                "zscore" => Detector::ZScore,
+                "reservoir" => Detector::Reservoir,
+                method => match Registry::builtin().names().find(|name| *name == method) {
+                    Some(name) => Detector::Method(name),
+                    None => return Update::Status(format!("skipped a {} anomaly", method)),
+                },
+            };
+            Update::Alert(Alert {
+                reading_id,
+                sensor_id: Some(sensor_id),
+                timestamp: Some(timestamp),
+                value,
+                detector,
+                severity,
+                breach_type: None,
+                threshold_value: None,
+                z_score: Some(score),
+            })
+        }
+        ServiceMessage::Lagged { missed } => {
+            Update::Status(format!("missed {} anomalies while behind", missed))
+        }
+        ServiceMessage::Error { error } => Update::Status(format!("service: {}", error)),
+    }
+}
+
+/// Sends the anomalies pushed by the service at `url` until it disconnects.
+pub fn read_service(url: String, updates: Sender<Update>) {
+    let mut socket = match tungstenite::connect(url.as_str()) {
+        Ok((socket, _)) => socket,
+        Err(e) => {
+            let _ = updates.send(Update::Status(format!("cannot connect: {}", e)));
+            return;
+        }
+    };
+    loop {
+        let update = match socket.read() {
+            Ok(tungstenite::Message::Text(text)) => service_update(text.as_str()),
+            Ok(tungstenite::Message::Close(_)) => break,
+            Ok(_) => continue,
+            Err(e) => {
+                let _ = updates.send(Update::Status(format!("disconnected: {}", e)));
+                return;
+            }
+        };
+        if updates.send(update).is_err() {
+            return;
+        }
+    }
+    let _ = updates.send(Update::Status("closed by the service".to_string()));
+}
+
+/// Runs the UI until `q` or Esc is pressed.
+pub fn run(mut monitor: Monitor, updates: Receiver<Update>) -> std::io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = ui_loop(&mut terminal, &mut monitor, &updates);
+    ratatui::restore();
+    result
+}
+
+fn ui_loop(
+    terminal: &mut DefaultTerminal,
+    monitor: &mut Monitor,
+    updates: &Receiver<Update>,
+) -> std::io::Result<()> {
+    loop {
+        for update in updates.try_iter() {
+            monitor.apply(update);
+        }
+        terminal.draw(|frame| monitor.draw(frame))?;
+        if event::poll(FRAME_INTERVAL)?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+            && matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)
+        {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::detect::Detectors;
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    fn reading(id: i64, value: f64) -> Reading {
+        Reading {
+            id,
+            sensor_id: Some(7),
+            value,
+            timestamp: None,
+        }
+    }
+
+    fn screen(monitor: &Monitor) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(100, 16)).unwrap();
+        terminal.draw(|frame| monitor.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_local_readings_show_baselines_and_alerts() {
+        let detectors = Detectors {
+            max: Some(50.0),
+            ..Detectors::default()
+        };
+        let mut monitor = Monitor::new("stdin".to_string(), Some(Watcher::new(detectors, 1)));
+        monitor.apply(Update::Reading(reading(1, 10.0)));
+        monitor.apply(Update::Reading(reading(2, 12.0)));
+        monitor.apply(Update::Reading(reading(3, 80.0)));
+
+        let screen = screen(&monitor);
+        assert!(
+            screen.contains("stdin | 3 readings, 1 alerts"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("34.00 ± 39.85"), "{}", screen);
+        assert!(
+            screen.contains("reading 3: 80 above_maximum 50"),
+            "{}",
+            screen
+        );
+        assert!(screen.contains("▁▁█"), "{}", screen);
+    }
+
+    #[test]
+    fn test_service_messages() {
+        let mut monitor = Monitor::new("ws://service".to_string(), None);
+        monitor.apply(service_update(
+            r#"{"type": "anomaly", "sensor_id": 4, "reading_id": 9, "value": 99.0,
+                "timestamp": "2026-01-19 10:00:00", "method": "zscore", "score": 3.4,
+                "severity": "critical"}"#,
+        ));
+        monitor.apply(service_update(r#"{"type": "lagged", "missed": 3}"#));
+
+        assert_eq!(monitor.feed[0].severity, Severity::Critical);
+        assert_eq!(monitor.status, "missed 3 anomalies while behind");
+        let screen = screen(&monitor);
+        assert!(
+            screen.contains("sensor 4 reading 9: 99 z-score 3.40"),
+            "{}",
+            screen
+        );
+        assert!(matches!(service_update("not json"), Update::Status(_)));
+    }
+
+    #[test]
+    fn test_scaled_values_span_the_sparkline() {
+        assert_eq!(scaled(&[2.0, 4.0, 3.0]), vec![1, 8, 5]);
+        assert_eq!(scaled(&[5.0, 5.0]), vec![4, 4]);
+    }
+}
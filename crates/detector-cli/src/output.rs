@@ -0,0 +1,166 @@
+//! Writing alerts as a JSON array, NDJSON or CSV, or one line at a time as
+//! they occur.
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use detection_core::Severity;
+
+use crate::detect::{Alert, Detector};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Json,
+    Ndjson,
+    Csv,
+}
+
+pub fn write_alerts(
+    mut writer: impl Write,
+    alerts: &[Alert],
+    format: OutputFormat,
+) -> Result<(), String> {
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, alerts).map_err(|e| e.to_string())?;
+            writeln!(writer).map_err(|e| e.to_string())
+        }
+        OutputFormat::Ndjson => {
+            for alert in alerts {
+                serde_json::to_writer(&mut writer, alert).map_err(|e| e.to_string())?;
+                writeln!(writer).map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        }
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            for alert in alerts {
+                writer.serialize(alert).map_err(|e| e.to_string())?;
+            }
+            writer.flush().map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Format of alerts printed as they occur.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LineFormat {
+    #[default]
+    Text,
+    Ndjson,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Color {
+    /// When writing to a terminal.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Formats one alert as a line, without the newline.
+pub fn alert_line(alert: &Alert, format: LineFormat, color: bool) -> String {
+    if format == LineFormat::Ndjson {
+        return serde_json::to_string(alert).unwrap_or_default();
+    }
+    let finding = match alert.detector {
+        Detector::Threshold => format!(
+            "{} {}",
+            alert.breach_type.map(|b| b.as_str()).unwrap_or_default(),
+            alert.threshold_value.unwrap_or_default()
+        ),
+        Detector::ZScore => format!("z-score {:.2}", alert.z_score.unwrap_or_default()),
+        Detector::Reservoir => {
+            format!("reservoir z-score {:.2}", alert.z_score.unwrap_or_default())
+        }
+        Detector::Method(name) => {
+            format!("{} score {:.2}", name, alert.z_score.unwrap_or_default())
+        }
+    };
+    let severity = if color {
+        let code = match alert.severity {
+            Severity::Critical => "1;31",
+            Severity::High => "33",
+            Severity::Medium => "36",
+        };
+        format!("\x1b[{}m{}\x1b[0m", code, alert.severity.as_str())
+    } else {
+        alert.severity.as_str().to_string()
+    };
+    let sensor = alert
+        .sensor_id
+        .map(|id| format!("sensor {} ", id))
+        .unwrap_or_default();
+    format!(
+        "{} [{}] {}reading {}: {} {}",
+        alert.timestamp.as_deref().unwrap_or("-"),
+        severity,
+        sensor,
+        alert.reading_id,
+        alert.value,
+        finding
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use detection_core::Breach;
+
+    fn alert() -> Alert {
+        Alert {
+            reading_id: 3,
+            sensor_id: Some(7),
+            timestamp: None,
+            value: 96.5,
+            detector: Detector::Threshold,
+            severity: Severity::Medium,
+            breach_type: Some(Breach::AboveMaximum),
+            threshold_value: Some(95.0),
+            z_score: None,
+        }
+    }
+
+    #[test]
+    fn test_csv_leaves_fields_that_do_not_apply_empty() {
+        let mut out = Vec::new();
+        write_alerts(&mut out, &[alert()], OutputFormat::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "reading_id,sensor_id,timestamp,value,detector,severity,breach_type,threshold_value,z_score\n\
+             3,7,,96.5,threshold,medium,above_maximum,95.0,\n"
+        );
+    }
+
+    #[test]
+    fn test_json_formats() {
+        let mut out = Vec::new();
+        write_alerts(&mut out, &[alert(), alert()], OutputFormat::Ndjson).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["breach_type"], "above_maximum");
+
+        let mut out = Vec::new();
+        write_alerts(&mut out, &[alert()], OutputFormat::Json).unwrap();
+        let array: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(array[0]["z_score"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn test_alert_lines() {
+        assert_eq!(
+            alert_line(&alert(), LineFormat::Text, false),
+            "- [medium] sensor 7 reading 3: 96.5 above_maximum 95"
+        );
+        assert!(alert_line(&alert(), LineFormat::Text, true).contains("\x1b[36mmedium\x1b[0m"));
+        let line = alert_line(&alert(), LineFormat::Ndjson, true);
+        let value: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["severity"], "medium");
+    }
+}
@@ -0,0 +1,133 @@
+//! Following a growing file, a named pipe or stdin line by line.
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::ops::ControlFlow;
+use std::path::Path;
+use std::time::Duration;
+
+/// How often the end of a followed file is checked for new lines.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Opens `path` to follow. A regular file is read from its current end
+/// unless `from_start`, after its first line when `keep_header`, so a CSV
+/// stream still starts with its header.
+pub fn open(
+    path: &Path,
+    from_start: bool,
+    keep_header: bool,
+) -> io::Result<Box<dyn BufRead + Send>> {
+    let file = File::open(path)?;
+    if from_start || !file.metadata()?.is_file() {
+        return Ok(Box::new(BufReader::new(file)));
+    }
+    let mut reader = BufReader::new(file);
+    let mut header = String::new();
+    if keep_header {
+        reader.read_line(&mut header)?;
+    }
+    reader.seek(SeekFrom::End(0))?;
+    Ok(Box::new(Cursor::new(header.into_bytes()).chain(reader)))
+}
+
+/// Calls `on_line` with every line of `reader`, without its line ending,
+/// until it breaks. At the end of the input it waits for more when `follow`
+/// is set, as `tail -f` does, and returns otherwise.
+pub fn lines(
+    mut reader: impl BufRead,
+    follow: bool,
+    mut on_line: impl FnMut(&str) -> ControlFlow<()>,
+) -> io::Result<()> {
+    let mut line = String::new();
+    loop {
+        reader.read_line(&mut line)?;
+        if line.ends_with('\n') {
+            if on_line(line.trim_end_matches(['\n', '\r'])).is_break() {
+                return Ok(());
+            }
+            line.clear();
+        } else if follow {
+            // A partial line stays buffered until the writer finishes it.
+            std::thread::sleep(POLL_INTERVAL);
+        } else {
+            if !line.is_empty() {
+                let _ = on_line(&line);
+            }
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn temp_file(name: &str, contents: &str) -> std::path::PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("detector-cli-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn append(path: &Path, contents: &str) {
+        let mut file = std::fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_joins_lines_finished_by_later_writes() {
+        let path = temp_file("partial", "a\nb");
+        let mut seen = Vec::new();
+        lines(open(&path, true, false).unwrap(), true, |line| {
+            seen.push(line.to_string());
+            if line == "a" {
+                append(&path, "c\r\nd\n");
+            }
+            if seen.len() == 3 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(seen, ["a", "bc", "d"]);
+    }
+
+    #[test]
+    fn test_follows_new_lines_after_the_header() {
+        let path = temp_file("follow", "value\n1\n2\n");
+        let writer = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                append(&path, "3\n");
+            })
+        };
+        let mut seen = Vec::new();
+        lines(open(&path, false, true).unwrap(), true, |line| {
+            seen.push(line.to_string());
+            if seen.len() == 2 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        })
+        .unwrap();
+        writer.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(seen, ["value", "3"]);
+    }
+
+    #[test]
+    fn test_stops_at_the_end_of_unfollowed_input() {
+        let mut seen = Vec::new();
+        lines(Cursor::new("x\ny"), false, |line| {
+            seen.push(line.to_string());
+            ControlFlow::Continue(())
+        })
+        .unwrap();
+        assert_eq!(seen, ["x", "y"]);
+    }
+}
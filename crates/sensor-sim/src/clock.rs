@@ -0,0 +1,65 @@
+//! `YYYY-MM-DDTHH:MM:SS` timestamps of the simulated clock, in UTC, the
+//! format the service accepts.
+
+use detection_core::calendar::{civil_from_days, days_from_civil};
+
+/// Seconds since the Unix epoch of a `YYYY-MM-DDTHH:MM:SS` timestamp.
+pub fn parse_timestamp(raw: &str) -> Option<i64> {
+    let (date, time) = raw.split_once(['T', ' '])?;
+    let mut date = date.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+    let (year, month, day) = (date.next()??, date.next()??, date.next()??);
+    let mut time = time.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+    let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+    if !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+        || !(0..24).contains(&hour)
+        || !(0..60).contains(&minute)
+        || !(0..60).contains(&second)
+    {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86_400 + hour * 3_600 + minute * 60 + second)
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SS`, with
+/// milliseconds when there is a fraction.
+pub fn format_timestamp(time: f64) -> String {
+    let millis = (time * 1_000.0).round() as i64;
+    let (seconds, millis) = (millis.div_euclid(1_000), millis.rem_euclid(1_000));
+    let (days, secs) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    let (year, month, day) = civil_from_days(days);
+    let formatted = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3_600,
+        secs % 3_600 / 60,
+        secs % 60
+    );
+    if millis == 0 {
+        formatted
+    } else {
+        format!("{}.{:03}", formatted, millis)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timestamps_round_trip() {
+        let seconds = parse_timestamp("2026-01-19T10:30:00").unwrap();
+        assert_eq!(seconds, 1_768_818_600);
+        assert_eq!(format_timestamp(seconds as f64), "2026-01-19T10:30:00");
+        assert_eq!(
+            format_timestamp(seconds as f64 + 0.25),
+            "2026-01-19T10:30:00.250"
+        );
+        assert_eq!(format_timestamp(951_782_400.0), "2000-02-29T00:00:00");
+        assert_eq!(parse_timestamp("2026-13-01T00:00:00"), None);
+        assert_eq!(parse_timestamp("yesterday"), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! The `[simulator]` section of the settings file, overridden by
+//! `SENSOR_SIM_*` environment variables and then by the flags; see
+//! `config-core`.
+//!
+//! ```toml
+//! [simulator]
+//! sensors = 20
+//! spike_rate = 0.01
+//! sink = "mqtt"
+//! broker = "mqtt.plant.local:1883"
+//! ```
+
+use std::path::Path;
+
+use clap::ValueEnum;
+use config_core::{Loader, Validate, config_file};
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+use crate::signal::Profile;
+
+const ENV_PREFIX: &str = "SENSOR_SIM_";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum SinkKind {
+    Stdout,
+    Http,
+    Mqtt,
+    Kafka,
+}
+
+/// One setting per flag; `sensor-sim --help` documents them.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub sensors: i64,
+    pub rate: f64,
+    pub speed: f64,
+    pub count: Option<u64>,
+    pub duration: Option<f64>,
+    pub start: Option<String>,
+    pub seed: u64,
+    pub base: f64,
+    pub amplitude: f64,
+    pub noise: f64,
+    pub drift: f64,
+    pub spike_rate: f64,
+    pub spike_magnitude: f64,
+    pub flatline_rate: f64,
+    pub flatline_length: usize,
+    pub gap_rate: f64,
+    pub gap_length: usize,
+    pub sink: SinkKind,
+    pub url: String,
+    pub broker: String,
+    pub topic: String,
+    pub batch_size: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            sensors: 5,
+            rate: 1.0,
+            speed: 1.0,
+            count: None,
+            duration: None,
+            start: None,
+            seed: 1,
+            base: 50.0,
+            amplitude: 5.0,
+            noise: 1.0,
+            drift: 0.0,
+            spike_rate: 0.0,
+            spike_magnitude: 8.0,
+            flatline_rate: 0.0,
+            flatline_length: 30,
+            gap_rate: 0.0,
+            gap_length: 60,
+            sink: SinkKind::Stdout,
+            url: "http://localhost:3001".to_string(),
+            broker: "localhost:1883".to_string(),
+            topic: "sensors".to_string(),
+            batch_size: 60,
+        }
+    }
+}
+
+impl Validate for Settings {
+    fn validate(&self) -> Result<(), String> {
+        self.profile().validate()?;
+        if self.sensors < 1 {
+            return Err("sensors must be at least 1".to_string());
+        }
+        if !(self.rate.is_finite() && self.rate > 0.0) {
+            return Err("rate must be a positive number".to_string());
+        }
+        if !(self.speed.is_finite() && self.speed >= 0.0) {
+            return Err("speed must not be negative".to_string());
+        }
+        if let Some(start) = &self.start
+            && clock::parse_timestamp(start).is_none()
+        {
+            return Err(format!(
+                "invalid start {:?}, expected YYYY-MM-DDTHH:MM:SS",
+                start
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Settings {
+    pub fn profile(&self) -> Profile {
+        Profile {
+            base: self.base,
+            amplitude: self.amplitude,
+            noise: self.noise,
+            drift: self.drift,
+            spike_rate: self.spike_rate,
+            spike_magnitude: self.spike_magnitude,
+            flatline_rate: self.flatline_rate,
+            flatline_length: self.flatline_length,
+            gap_rate: self.gap_rate,
+            gap_length: self.gap_length,
+        }
+    }
+}
+
+/// `flags` are the command line: a `Serialize` struct that leaves out the
+/// flags not given.
+pub fn load(config: Option<&Path>, flags: &impl Serialize) -> Result<Settings, String> {
+    Loader::new("simulator")
+        .file(config_file(config))
+        .env(ENV_PREFIX)
+        .overrides(flags)
+        .load()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct Flags {
+        sensors: i64,
+    }
+
+    #[test]
+    fn test_file_settings_under_the_flags() {
+        let path = std::env::temp_dir().join(format!("sensor-sim-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[simulator]\nsensors = 20\nsink = \"mqtt\"\nspike_rate = 0.5\n",
+        )
+        .unwrap();
+        let settings = load(Some(&path), &Flags { sensors: 3 });
+        let invalid = std::fs::write(&path, "[simulator]\nsink = \"carrier-pigeon\"\n")
+            .map(|()| load(Some(&path), &Flags { sensors: 3 }));
+        std::fs::remove_file(&path).unwrap();
+
+        let settings = settings.unwrap();
+        assert_eq!(settings.sensors, 3);
+        assert_eq!(settings.sink, SinkKind::Mqtt);
+        assert_eq!(settings.profile().spike_rate, 0.5);
+        assert_eq!(settings.batch_size, Settings::default().batch_size);
+        assert!(invalid.unwrap().is_err());
+    }
+}
@@ -0,0 +1,256 @@
+//! `sensor-sim`: streams synthetic sensor readings, with injected faults, to
+//! the anomaly detector or a message broker, for end-to-end tests and demos.
+//!
+//! ```text
+//! sensor-sim --sensors 10 --rate 1 --spike-rate 0.01 --sink http --url http://localhost:3001
+//! sensor-sim --sink mqtt --broker localhost:1883 --topic plant/sensors
+//! sensor-sim --sink kafka --broker localhost:9092 --topic readings
+//! sensor-sim --speed 0 --count 1440 --start 2026-01-19T00:00:00 > day.ndjson
+//! ```
+//!
+//! Settings also come from the `[simulator]` section of a settings file
+//! (`--config` or `ANOMALY_CONFIG`) and `SENSOR_SIM_*` environment
+//! variables, e.g. `SENSOR_SIM_SPIKE_RATE=0.01`, which the flags override.
+//!
+//! `--speed` scales the simulated clock: at 60 a minute of readings goes out
+//! every second, and at 0 readings go out as fast as the sink takes them.
+
+mod clock;
+mod config;
+mod signal;
+mod sink;
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+use serde::Serialize;
+
+use crate::config::{Settings, SinkKind};
+use crate::signal::{Fault, Sensor};
+use crate::sink::{Sample, Sink};
+
+// Every flag left out falls back to the settings file, the environment
+// and then the default in brackets.
+#[derive(Parser, Serialize)]
+#[command(name = "sensor-sim", version, about)]
+struct Cli {
+    /// Settings file with a `[simulator]` section; defaults to
+    /// $ANOMALY_CONFIG.
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Number of sensors, with ids 1 to N [default: 5].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sensors: Option<i64>,
+    /// Readings per second of simulated time, per sensor [default: 1].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rate: Option<f64>,
+    /// Simulated seconds per wall-clock second; 0 sends without waiting
+    /// [default: 1].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f64>,
+    /// Stops after this many readings per sensor.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<u64>,
+    /// Stops after this many seconds of simulated time.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    duration: Option<f64>,
+    /// Simulated start time, as YYYY-MM-DDTHH:MM:SS in UTC; defaults to now.
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    /// Seed of the signals and faults; the same seed repeats a run
+    /// [default: 1].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+
+    /// Mean value of a sensor, varied by up to 10% between sensors
+    /// [default: 50].
+    #[arg(long, allow_negative_numbers = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    base: Option<f64>,
+    /// Half the peak-to-trough swing of the daily cycle [default: 5].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    amplitude: Option<f64>,
+    /// Standard deviation of the noise [default: 1].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    noise: Option<f64>,
+    /// Change of the baseline per hour [default: 0].
+    #[arg(long, allow_negative_numbers = true)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    drift: Option<f64>,
+
+    /// Chance per reading of a spike [default: 0].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spike_rate: Option<f64>,
+    /// Size of a spike, in noise standard deviations [default: 8].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    spike_magnitude: Option<f64>,
+    /// Chance per reading that the sensor gets stuck at its last value
+    /// [default: 0].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flatline_rate: Option<f64>,
+    /// Readings a stuck sensor repeats its value for [default: 30].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flatline_length: Option<usize>,
+    /// Chance per reading that the sensor goes silent [default: 0].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gap_rate: Option<f64>,
+    /// Readings a silent sensor skips [default: 60].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gap_length: Option<usize>,
+
+    /// Where readings go [default: stdout].
+    #[arg(long, value_enum)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sink: Option<SinkKind>,
+    /// Base URL of the service, for the http sink [default:
+    /// http://localhost:3001].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Broker address for the mqtt and kafka sinks, as host:port; a
+    /// comma-separated list for kafka [default: localhost:1883].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    broker: Option<String>,
+    /// MQTT topic prefix, or Kafka topic [default: sensors].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    topic: Option<String>,
+    /// Readings per sensor in one request of the http sink [default: 60].
+    #[arg(long)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    batch_size: Option<usize>,
+}
+
+fn open_sink(settings: &Settings) -> Result<Sink, String> {
+    match settings.sink {
+        SinkKind::Stdout => Ok(Sink::stdout()),
+        SinkKind::Http => Sink::http(&settings.url, settings.batch_size),
+        SinkKind::Mqtt => Sink::mqtt(&settings.broker, &settings.topic),
+        SinkKind::Kafka => Sink::kafka(&settings.broker, &settings.topic),
+    }
+}
+
+#[derive(Default)]
+struct Summary {
+    readings: u64,
+    spikes: u64,
+    flatlined: u64,
+    missing: u64,
+    failed_pushes: u64,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let settings = match config::load(cli.config.as_deref(), &cli) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let profile = settings.profile();
+    let start = match settings.start.as_deref().and_then(clock::parse_timestamp) {
+        Some(seconds) => seconds as f64,
+        None => SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()) as f64,
+    };
+    let mut sink = match open_sink(&settings) {
+        Ok(sink) => sink,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut sensors: Vec<Sensor> = (1..=settings.sensors)
+        .map(|id| Sensor::new(id, &profile, settings.seed, start))
+        .collect();
+    let step = 1.0 / settings.rate;
+    let started = Instant::now();
+    let mut summary = Summary::default();
+    let mut next_id = 1;
+    let mut samples = Vec::with_capacity(sensors.len());
+    for tick in 0u64.. {
+        let elapsed = tick as f64 * step;
+        if settings.count.is_some_and(|count| tick >= count)
+            || settings
+                .duration
+                .is_some_and(|duration| elapsed >= duration)
+        {
+            break;
+        }
+        if settings.speed > 0.0 {
+            let due = Duration::from_secs_f64(elapsed / settings.speed);
+            if let Some(wait) = due.checked_sub(started.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        let time = start + elapsed;
+        let timestamp = clock::format_timestamp(time);
+        samples.clear();
+        for sensor in &mut sensors {
+            let Some((value, fault)) = sensor.sample(time) else {
+                summary.missing += 1;
+                continue;
+            };
+            match fault {
+                Some(Fault::Spike) => summary.spikes += 1,
+                Some(Fault::Flatline) => summary.flatlined += 1,
+                None => {}
+            }
+            samples.push(Sample {
+                sensor_id: sensor.id,
+                id: next_id,
+                value,
+                timestamp: timestamp.clone(),
+                fault,
+            });
+            next_id += 1;
+        }
+        summary.readings += samples.len() as u64;
+        if let Err(e) = sink.push(&samples) {
+            summary.failed_pushes += 1;
+            eprintln!("Warning: {}", e);
+        }
+    }
+    if let Err(e) = sink.flush() {
+        summary.failed_pushes += 1;
+        eprintln!("Warning: {}", e);
+    }
+
+    eprintln!(
+        "Generated {} readings from {} sensors: {} spikes, {} flatlined, {} missing",
+        summary.readings,
+        sensors.len(),
+        summary.spikes,
+        summary.flatlined,
+        summary.missing
+    );
+    if summary.failed_pushes > 0 {
+        eprintln!("Warning: {} sends failed", summary.failed_pushes);
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,256 @@
+//! Simulated sensor signals: a baseline with linear drift, a daily cycle and
+//! Gaussian noise, with faults injected at random readings:
+//!
+//! - spikes: one reading `spike_magnitude` noise deviations up or down
+//! - flatlines: the sensor repeats its last value for `flatline_length`
+//!   readings, as a stuck sensor does
+//! - gaps: the sensor sends nothing for `gap_length` readings
+//!
+//! Every sensor draws from its own stream of the seed, with its own baseline
+//! offset and cycle phase, so a run is reproducible and sensors differ.
+
+use std::f64::consts::TAU;
+
+use detection_core::stats::box_muller;
+use serde::Serialize;
+
+const DAY_SECONDS: f64 = 86_400.0;
+
+/// SplitMix64: small, fast and the same on every platform, so a seed keeps
+/// reproducing the same streams.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal.
+    fn normal(&mut self) -> f64 {
+        let u = 1.0 - self.next_f64();
+        box_muller(u, self.next_f64())
+    }
+}
+
+/// Signal shape and fault rates shared by all sensors. Rates are the chance
+/// per reading that a fault starts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Profile {
+    pub base: f64,
+    /// Half the peak-to-trough swing of the daily cycle.
+    pub amplitude: f64,
+    /// Standard deviation of the noise.
+    pub noise: f64,
+    /// Change of the baseline per hour.
+    pub drift: f64,
+    pub spike_rate: f64,
+    /// In noise standard deviations.
+    pub spike_magnitude: f64,
+    pub flatline_rate: f64,
+    pub flatline_length: usize,
+    pub gap_rate: f64,
+    pub gap_length: usize,
+}
+
+impl Profile {
+    pub fn validate(&self) -> Result<(), String> {
+        let values = [
+            ("base", self.base),
+            ("amplitude", self.amplitude),
+            ("noise", self.noise),
+            ("drift", self.drift),
+            ("spike magnitude", self.spike_magnitude),
+        ];
+        if let Some((name, _)) = values.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(format!("{} must be a finite number", name));
+        }
+        if self.noise < 0.0 {
+            return Err("noise must not be negative".to_string());
+        }
+        let rates = [self.spike_rate, self.flatline_rate, self.gap_rate];
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) || rates.iter().sum::<f64>() > 1.0 {
+            return Err("fault rates must be between 0 and 1, and at most 1 together".to_string());
+        }
+        if self.flatline_length == 0 || self.gap_length == 0 {
+            return Err("flatline and gap lengths must be at least 1".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fault {
+    Spike,
+    Flatline,
+}
+
+pub struct Sensor {
+    pub id: i64,
+    profile: Profile,
+    phase: f64,
+    start: f64,
+    rng: Rng,
+    last: f64,
+    flatline_left: usize,
+    gap_left: usize,
+}
+
+impl Sensor {
+    /// Sensor `id` of a run with `seed` whose clock starts at `start`
+    /// seconds since the Unix epoch.
+    pub fn new(id: i64, profile: &Profile, seed: u64, start: f64) -> Self {
+        let mut rng = Rng(seed ^ (id as u64).wrapping_mul(0xA24B_AED4_963E_E407));
+        let mut profile = profile.clone();
+        profile.base += (rng.next_f64() - 0.5) * 0.2 * profile.base.abs();
+        let phase = rng.next_f64() * TAU;
+        Sensor {
+            id,
+            last: profile.base,
+            profile,
+            phase,
+            start,
+            rng,
+            flatline_left: 0,
+            gap_left: 0,
+        }
+    }
+
+    /// The reading at `time` seconds since the Unix epoch, or `None` during
+    /// a gap.
+    pub fn sample(&mut self, time: f64) -> Option<(f64, Option<Fault>)> {
+        if self.gap_left > 0 {
+            self.gap_left -= 1;
+            return None;
+        }
+        if self.flatline_left > 0 {
+            self.flatline_left -= 1;
+            return Some((self.last, Some(Fault::Flatline)));
+        }
+
+        let profile = &self.profile;
+        let roll = self.rng.next_f64();
+        if roll < profile.gap_rate {
+            self.gap_left = profile.gap_length - 1;
+            return None;
+        }
+        if roll < profile.gap_rate + profile.flatline_rate {
+            self.flatline_left = profile.flatline_length - 1;
+            return Some((self.last, Some(Fault::Flatline)));
+        }
+
+        let hours = (time - self.start) / 3_600.0;
+        let day = time.rem_euclid(DAY_SECONDS) / DAY_SECONDS;
+        let mut value = profile.base
+            + profile.drift * hours
+            + profile.amplitude * (TAU * day + self.phase).sin()
+            + profile.noise * self.rng.normal();
+        let mut fault = None;
+        if roll < profile.gap_rate + profile.flatline_rate + profile.spike_rate {
+            let sign = if self.rng.next_f64() < 0.5 { -1.0 } else { 1.0 };
+            value += sign * profile.spike_magnitude * profile.noise;
+            fault = Some(Fault::Spike);
+        }
+        self.last = value;
+        Some((value, fault))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile() -> Profile {
+        Profile {
+            base: 50.0,
+            amplitude: 5.0,
+            noise: 1.0,
+            drift: 0.0,
+            spike_rate: 0.0,
+            spike_magnitude: 10.0,
+            flatline_rate: 0.0,
+            flatline_length: 5,
+            gap_rate: 0.0,
+            gap_length: 3,
+        }
+    }
+
+    fn run(profile: &Profile, id: i64, readings: usize) -> Vec<Option<(f64, Option<Fault>)>> {
+        let mut sensor = Sensor::new(id, profile, 42, 0.0);
+        (0..readings)
+            .map(|i| sensor.sample(i as f64 * 60.0))
+            .collect()
+    }
+
+    #[test]
+    fn test_sensors_are_reproducible_and_distinct() {
+        let profile = profile();
+        assert_eq!(run(&profile, 1, 100), run(&profile, 1, 100));
+        assert_ne!(run(&profile, 1, 100), run(&profile, 2, 100));
+
+        let drifting = Profile {
+            drift: 10.0,
+            noise: 0.0,
+            amplitude: 0.0,
+            ..profile
+        };
+        let values = run(&drifting, 1, 121);
+        let (first, last) = (values[0].unwrap().0, values[120].unwrap().0);
+        assert!((last - first - 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_injects_faults() {
+        let spiky = Profile {
+            base: 0.0,
+            amplitude: 0.0,
+            spike_rate: 1.0,
+            ..profile()
+        };
+        assert!(run(&spiky, 1, 20).iter().all(|sample| {
+            let (value, fault) = sample.unwrap();
+            fault == Some(Fault::Spike) && value.abs() > 5.0
+        }));
+
+        let stuck = Profile {
+            flatline_rate: 0.2,
+            ..profile()
+        };
+        let samples = run(&stuck, 1, 200);
+        let start = samples
+            .iter()
+            .position(|s| s.unwrap().1 == Some(Fault::Flatline))
+            .unwrap();
+        let stuck_at = samples[start].unwrap().0;
+        assert!(
+            samples[start..start + 5]
+                .iter()
+                .all(|s| s.unwrap().0 == stuck_at)
+        );
+
+        let gappy = Profile {
+            gap_rate: 0.05,
+            ..profile()
+        };
+        let samples = run(&gappy, 1, 500);
+        let start = samples.iter().position(Option::is_none).unwrap();
+        assert!(samples[start..start + 3].iter().all(Option::is_none));
+
+        let invalid = Profile {
+            spike_rate: 0.6,
+            gap_rate: 0.6,
+            ..profile()
+        };
+        assert!(invalid.validate().is_err());
+        assert!(profile().validate().is_ok());
+    }
+}
@@ -0,0 +1,295 @@
+//! Where simulated readings go:
+//!
+//! - `stdout`: one JSON sample per line
+//! - `http`: `POST /analyze` on the service, one request per sensor every
+//!   `batch_size` readings
+//! - `mqtt`: one JSON sample per message on `<topic>/<sensor_id>`
+//! - `kafka`: one JSON sample per record on `<topic>`, keyed by sensor id
+
+use std::collections::BTreeMap;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::thread;
+use std::time::Duration;
+
+use kafka::producer::{Producer, Record, RequiredAcks};
+use rumqttc::{Client, MqttOptions, QoS};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::signal::Fault;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Sample {
+    pub sensor_id: i64,
+    /// Reading id, unique over the run.
+    pub id: i64,
+    pub value: f64,
+    pub timestamp: String,
+    /// The fault injected into this reading, so a run can be checked
+    /// against what the service flags.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fault: Option<Fault>,
+}
+
+pub enum Sink {
+    Stdout(BufWriter<Stdout>),
+    Http {
+        client: reqwest::blocking::Client,
+        url: String,
+        batch_size: usize,
+        pending: BTreeMap<i64, Vec<Sample>>,
+    },
+    Mqtt {
+        client: Client,
+        topic: String,
+    },
+    Kafka {
+        producer: Box<Producer>,
+        topic: String,
+    },
+}
+
+impl Sink {
+    pub fn stdout() -> Self {
+        Sink::Stdout(BufWriter::new(io::stdout()))
+    }
+
+    /// Sends to the service at `url`, e.g. `http://localhost:3001`.
+    pub fn http(url: &str, batch_size: usize) -> Result<Self, String> {
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .map_err(|e| format!("Failed to create the HTTP client: {}", e))?;
+        Ok(Sink::Http {
+            client,
+            url: format!("{}/analyze", url.trim_end_matches('/')),
+            batch_size: batch_size.max(1),
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Publishes to the broker at `broker`, as `host:port`.
+    pub fn mqtt(broker: &str, topic: &str) -> Result<Self, String> {
+        let (host, port) = split_host_port(broker, 1883)?;
+        let mut options = MqttOptions::new("sensor-sim", host, port);
+        options.set_keep_alive(Duration::from_secs(30));
+        let (client, mut connection) = Client::new(options, 1_000);
+        // The connection only makes progress while it is iterated.
+        thread::spawn(move || {
+            for notification in connection.iter() {
+                if let Err(e) = notification {
+                    eprintln!("Warning: MQTT connection error: {}", e);
+                    thread::sleep(Duration::from_secs(1));
+                }
+            }
+        });
+        Ok(Sink::Mqtt {
+            client,
+            topic: topic.trim_end_matches('/').to_string(),
+        })
+    }
+
+    /// Produces to the brokers in `brokers`, a comma-separated list of
+    /// `host:port`.
+    pub fn kafka(brokers: &str, topic: &str) -> Result<Self, String> {
+        let hosts = brokers
+            .split(',')
+            .map(|host| host.trim().to_string())
+            .collect();
+        let producer = Producer::from_hosts(hosts)
+            .with_ack_timeout(Duration::from_secs(5))
+            .with_required_acks(RequiredAcks::One)
+            .create()
+            .map_err(|e| format!("Failed to connect to Kafka at {}: {}", brokers, e))?;
+        Ok(Sink::Kafka {
+            producer: Box::new(producer),
+            topic: topic.to_string(),
+        })
+    }
+
+    pub fn push(&mut self, samples: &[Sample]) -> Result<(), String> {
+        match self {
+            Sink::Stdout(out) => {
+                for sample in samples {
+                    writeln!(out, "{}", to_json(sample)).map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            Sink::Http {
+                client,
+                url,
+                batch_size,
+                pending,
+            } => {
+                let mut full = Vec::new();
+                for sample in samples {
+                    let batch = pending.entry(sample.sensor_id).or_default();
+                    batch.push(sample.clone());
+                    if batch.len() >= *batch_size {
+                        full.push(sample.sensor_id);
+                    }
+                }
+                for sensor_id in full {
+                    if let Some(batch) = pending.remove(&sensor_id) {
+                        post(client, url, sensor_id, &batch)?;
+                    }
+                }
+                Ok(())
+            }
+            Sink::Mqtt { client, topic } => {
+                for sample in samples {
+                    client
+                        .publish(
+                            format!("{}/{}", topic, sample.sensor_id),
+                            QoS::AtLeastOnce,
+                            false,
+                            to_json(sample),
+                        )
+                        .map_err(|e| format!("Failed to publish to MQTT: {}", e))?;
+                }
+                Ok(())
+            }
+            Sink::Kafka { producer, topic } => {
+                let keys: Vec<String> = samples.iter().map(|s| s.sensor_id.to_string()).collect();
+                let values: Vec<String> = samples.iter().map(to_json).collect();
+                let records: Vec<Record<'_, &[u8], &[u8]>> = keys
+                    .iter()
+                    .zip(&values)
+                    .map(|(key, value)| {
+                        Record::from_key_value(topic.as_str(), key.as_bytes(), value.as_bytes())
+                    })
+                    .collect();
+                producer
+                    .send_all(&records)
+                    .map(|_| ())
+                    .map_err(|e| format!("Failed to produce to Kafka: {}", e))
+            }
+        }
+    }
+
+    /// Sends what is still buffered.
+    pub fn flush(&mut self) -> Result<(), String> {
+        match self {
+            Sink::Stdout(out) => out.flush().map_err(|e| e.to_string()),
+            Sink::Http {
+                client,
+                url,
+                pending,
+                ..
+            } => {
+                for (sensor_id, batch) in std::mem::take(pending) {
+                    post(client, url, sensor_id, &batch)?;
+                }
+                Ok(())
+            }
+            Sink::Mqtt { client, .. } => {
+                // Give the connection thread a moment to send what is queued.
+                thread::sleep(Duration::from_millis(500));
+                client
+                    .disconnect()
+                    .map_err(|e| format!("Failed to disconnect from MQTT: {}", e))
+            }
+            Sink::Kafka { .. } => Ok(()),
+        }
+    }
+}
+
+fn to_json(sample: &Sample) -> String {
+    serde_json::to_string(sample).expect("samples serialize")
+}
+
+/// The `POST /analyze` body for `batch` of sensor `sensor_id`.
+fn analyze_body(sensor_id: i64, batch: &[Sample]) -> Value {
+    let readings: Vec<Value> = batch
+        .iter()
+        .map(|sample| {
+            json!({
+                "id": sample.id,
+                "value": sample.value,
+                "timestamp": sample.timestamp,
+            })
+        })
+        .collect();
+    json!({ "sensor_id": sensor_id, "readings": readings })
+}
+
+fn post(
+    client: &reqwest::blocking::Client,
+    url: &str,
+    sensor_id: i64,
+    batch: &[Sample],
+) -> Result<(), String> {
+    let response = client
+        .post(url)
+        .json(&analyze_body(sensor_id, batch))
+        .send()
+        .map_err(|e| format!("Failed to send readings of sensor {}: {}", sensor_id, e))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let body = response.text().unwrap_or_default();
+        return Err(format!(
+            "Service rejected readings of sensor {}: {} {}",
+            sensor_id,
+            status,
+            body.trim()
+        ));
+    }
+    Ok(())
+}
+
+fn split_host_port(address: &str, default_port: u16) -> Result<(&str, u16), String> {
+    match address.rsplit_once(':') {
+        Some((host, port)) => port
+            .parse()
+            .map(|port| (host, port))
+            .map_err(|_| format!("Invalid port in {:?}", address)),
+        None => Ok((address, default_port)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(sensor_id: i64, id: i64, fault: Option<Fault>) -> Sample {
+        Sample {
+            sensor_id,
+            id,
+            value: 50.5,
+            timestamp: "2026-01-19T10:30:00".to_string(),
+            fault,
+        }
+    }
+
+    #[test]
+    fn test_samples_serialize_for_the_service() {
+        assert_eq!(
+            to_json(&sample(3, 7, Some(Fault::Spike))),
+            "{\"sensor_id\":3,\"id\":7,\"value\":50.5,\"timestamp\":\"2026-01-19T10:30:00\",\"fault\":\"spike\"}"
+        );
+        assert!(!to_json(&sample(3, 7, None)).contains("fault"));
+
+        let body = analyze_body(3, &[sample(3, 7, Some(Fault::Spike)), sample(3, 8, None)]);
+        assert_eq!(body["sensor_id"], 3);
+        assert_eq!(body["readings"][1]["id"], 8);
+        assert!(body["readings"][0].get("fault").is_none());
+    }
+
+    #[test]
+    fn test_http_sink_batches_per_sensor() {
+        // Nothing listens here, so a batch that is sent fails.
+        let mut sink = Sink::http("http://127.0.0.1:9/", 2).unwrap();
+        sink.push(&[sample(1, 1, None), sample(2, 2, None)])
+            .unwrap();
+        let error = sink.push(&[sample(1, 3, None)]).unwrap_err();
+        assert!(error.contains("sensor 1"), "{}", error);
+        if let Sink::Http { pending, url, .. } = &sink {
+            assert_eq!(url, "http://127.0.0.1:9/analyze");
+            assert_eq!(pending.keys().collect::<Vec<_>>(), [&2]);
+        }
+
+        assert_eq!(split_host_port("broker:1884", 1883), Ok(("broker", 1884)));
+        assert_eq!(split_host_port("broker", 1883), Ok(("broker", 1883)));
+        assert!(split_host_port("broker:x", 1883).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+//! AES-256-GCM encryption of segment blocks and log frames.
+//!
+//! Each sealed piece is a fresh random 96-bit nonce, the ciphertext and the
+//! 16-byte tag, so a damaged or tampered piece, or one read with the wrong
+//! key, fails to open rather than yielding readings.
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Bytes a sealed piece has beyond its plaintext.
+pub const OVERHEAD: usize = NONCE_LEN + 16;
+
+/// A 256-bit key for [`crate::Options::encryption`].
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Parses 64 hexadecimal digits, as kept in an environment variable.
+    pub fn from_hex(hex: &str) -> io::Result<Self> {
+        let hex = hex.trim();
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an encryption key must be 64 hexadecimal digits",
+            )
+        };
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+
+    /// Reads a key file, as written by a KMS agent: the 32 key bytes, or 64
+    /// hexadecimal digits.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let contents = std::fs::read(path)?;
+        match <[u8; 32]>::try_from(&contents[..]) {
+            Ok(bytes) => Ok(Self(bytes)),
+            Err(_) => Self::from_hex(&String::from_utf8_lossy(&contents)),
+        }
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Key(..)")
+    }
+}
+
+#[derive(Clone)]
+pub struct Cipher {
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+impl Cipher {
+    pub fn new(key: &Key) -> Self {
+        let key = UnboundKey::new(&AES_256_GCM, &key.0).expect("a 256-bit key");
+        Self {
+            key: LessSafeKey::new(key),
+            random: SystemRandom::new(),
+        }
+    }
+
+    /// Encrypts `plaintext`, binding it to `aad`.
+    pub fn seal(&self, aad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let mut nonce = [0; NONCE_LEN];
+        self.random
+            .fill(&mut nonce)
+            .map_err(|_| io::Error::other("no randomness for a nonce"))?;
+        let mut body = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad),
+                &mut body,
+            )
+            .map_err(|_| io::Error::other("encryption failed"))?;
+        let mut sealed = Vec::with_capacity(NONCE_LEN + body.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&body);
+        Ok(sealed)
+    }
+
+    /// Decrypts what [`Cipher::seal`] made with the same `aad`.
+    pub fn open(&self, aad: &[u8], sealed: &[u8]) -> io::Result<Vec<u8>> {
+        let failed = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "decryption failed: wrong key or damaged data",
+            )
+        };
+        if sealed.len() < OVERHEAD {
+            return Err(failed());
+        }
+        let (nonce, body) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).map_err(|_| failed())?;
+        let mut body = body.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(aad), &mut body)
+            .map_err(|_| failed())?;
+        let length = plaintext.len();
+        body.truncate(length);
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sealed_data_opens_only_with_its_key_and_aad() {
+        let cipher = Cipher::new(&Key::new([7; 32]));
+        let sealed = cipher.seal(b"segment 1", b"readings").unwrap();
+        assert_eq!(sealed.len(), 8 + OVERHEAD);
+        assert_eq!(cipher.open(b"segment 1", &sealed).unwrap(), b"readings");
+        assert!(cipher.open(b"segment 2", &sealed).is_err());
+        let other = Cipher::new(&Key::new([8; 32]));
+        assert!(other.open(b"segment 1", &sealed).is_err());
+
+        let hex = "07".repeat(32);
+        assert_eq!(Key::from_hex(&hex).unwrap(), Key::new([7; 32]));
+        assert!(Key::from_hex("07").is_err());
+        assert_eq!(format!("{:?}", Key::new([7; 32])), "Key(..)");
+    }
+}
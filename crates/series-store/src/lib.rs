@@ -0,0 +1,459 @@
+//! Append-only storage of sensor readings for edge boxes, where generic SQL
+//! storage cannot keep up with the ingestion rate.
+//!
+//! A store is a directory of segments. Readings are appended to the active
+//! log (`<sequence>.log`) and kept in memory; once it holds
+//! [`Options::segment_readings`] readings it is sealed into an immutable
+//! segment (`<sequence>.seg`) with the readings grouped by sensor, sorted
+//! by time and optionally LZ4-compressed, behind an index of the sensors it
+//! holds. Scans map sealed segments and binary-search that index and then
+//! the sensor's readings, so a time-range scan of one sensor reads only its
+//! readings. A crash loses at most what was appended since the last
+//! [`Store::flush`] ([`Store::sync`] for power loss); a torn final record
+//! is dropped on open.
+//!
+//! Timestamps are integers in a unit of the caller's choosing, usually
+//! microseconds since the Unix epoch; readings may arrive out of order.
+//!
+//! ```no_run
+//! use series_store::{Options, Reading, Store};
+//!
+//! let mut store = Store::open("data/readings", Options::default())?;
+//! store.append(&[Reading { sensor_id: 7, timestamp: 1_768_816_800_000_000, value: 21.5 }])?;
+//! store.flush()?;
+//! let last_hour = store.scan(7, 1_768_813_200_000_000, 1_768_816_800_000_001)?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+//!
+//! The store has a single writer; share it between threads behind a
+//! `RwLock`, as scans only need `&self`.
+//!
+//! With [`Options::encryption`], segments and the active log are encrypted
+//! with AES-256-GCM, so readings are unreadable on disk without the
+//! [`Key`]; only segment headers (counts and time range) stay in the clear.
+//! Segments and a log written without a key stay readable, and a log
+//! started without one is sealed on open, so encryption can be turned on
+//! for an existing store.
+
+mod cipher;
+mod log;
+mod segment;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub use crate::cipher::Key;
+
+use crate::cipher::Cipher;
+use crate::log::{Log, log_path};
+use crate::segment::Segment;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Reading {
+    pub sensor_id: i64,
+    pub timestamp: i64,
+    pub value: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Compression {
+    #[default]
+    None = 0,
+    /// Smaller segments for slower scans; readings of slowly changing
+    /// sensors compress best.
+    Lz4 = 1,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    /// Readings in the active log before it is sealed into a segment; they
+    /// are held in memory, 24 bytes each, until then.
+    pub segment_readings: usize,
+    pub compression: Compression,
+    /// Encrypts what is written from now on, see [`Key::from_hex`] and
+    /// [`Key::from_file`] for keys kept in the environment or by a KMS.
+    pub encryption: Option<Key>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            segment_readings: 1 << 20,
+            compression: Compression::None,
+            encryption: None,
+        }
+    }
+}
+
+pub struct Store {
+    dir: PathBuf,
+    options: Options,
+    cipher: Option<Cipher>,
+    /// In sequence order.
+    segments: Vec<Segment>,
+    log: Log,
+    sequence: u64,
+    active: Vec<Reading>,
+}
+
+impl Store {
+    /// Opens the store in `dir`, creating it if needed, and recovers the
+    /// active log.
+    pub fn open(dir: impl AsRef<Path>, options: Options) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        if options.segment_readings == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "segment_readings must be positive",
+            ));
+        }
+        fs::create_dir_all(&dir)?;
+        let cipher = options.encryption.as_ref().map(Cipher::new);
+
+        let mut sealed = Vec::new();
+        let mut logs = Vec::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let sequence = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<u64>().ok());
+            let extension = path.extension().and_then(|e| e.to_str());
+            match (sequence, extension) {
+                (Some(sequence), Some("seg")) => sealed.push(sequence),
+                (Some(sequence), Some("log")) => logs.push(sequence),
+                // A seal that did not finish; its log is still there.
+                (Some(_), Some("tmp")) => fs::remove_file(&path)?,
+                _ => {}
+            }
+        }
+        sealed.sort_unstable();
+        logs.sort_unstable();
+        let mut segments = sealed
+            .iter()
+            .map(|&sequence| Segment::open(segment_path(&dir, sequence), cipher.as_ref()))
+            .collect::<io::Result<Vec<_>>>()?;
+        // A log whose segment exists was sealed before the crash.
+        for sequence in logs.iter().filter(|s| sealed.binary_search(s).is_ok()) {
+            fs::remove_file(log_path(&dir, *sequence))?;
+        }
+        logs.retain(|s| sealed.binary_search(s).is_err());
+
+        let next = sealed.iter().chain(&logs).max().map_or(1, |s| s + 1);
+        let sequence = logs.pop().unwrap_or(next);
+        // Only a crash between sealing and opening the next log leaves more
+        // than one; seal the older ones as they are.
+        for older in logs {
+            let (_, mut readings) = Log::open(log_path(&dir, older), cipher.as_ref())?;
+            segments.push(seal(
+                &dir,
+                older,
+                &mut readings,
+                options.compression,
+                cipher.as_ref(),
+            )?);
+            fs::remove_file(log_path(&dir, older))?;
+        }
+        segments.sort_by_key(|s| s.path.clone());
+
+        let (log, active) = Log::open(log_path(&dir, sequence), cipher.as_ref())?;
+        let mut store = Self {
+            dir,
+            options,
+            cipher,
+            segments,
+            log,
+            sequence,
+            active,
+        };
+        // A log started before encryption was turned on is sealed now, so
+        // no more readings are written to it in the clear.
+        let unencrypted = store.cipher.is_some() && !store.log.is_encrypted();
+        if store.active.len() >= store.options.segment_readings
+            || (unencrypted && !store.active.is_empty())
+        {
+            store.seal()?;
+        }
+        Ok(store)
+    }
+
+    /// Appends `readings` to the active log, sealing it when full.
+    pub fn append(&mut self, readings: &[Reading]) -> io::Result<()> {
+        let mut readings = readings;
+        while !readings.is_empty() {
+            let room = self.options.segment_readings - self.active.len();
+            let (now, later) = readings.split_at(room.min(readings.len()));
+            self.log.append(now)?;
+            self.active.extend_from_slice(now);
+            if self.active.len() >= self.options.segment_readings {
+                self.seal()?;
+            }
+            readings = later;
+        }
+        Ok(())
+    }
+
+    /// Writes buffered readings to the operating system, so they survive a
+    /// crash of the process.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.log.flush()
+    }
+
+    /// Flushes and waits for the readings to reach the disk, so they
+    /// survive a power loss.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.log.sync()
+    }
+
+    /// Seals the active log into a segment now, even if it is not full.
+    pub fn seal(&mut self) -> io::Result<()> {
+        if self.active.is_empty() {
+            return Ok(());
+        }
+        self.log.flush()?;
+        let segment = seal(
+            &self.dir,
+            self.sequence,
+            &mut self.active,
+            self.options.compression,
+            self.cipher.as_ref(),
+        )?;
+        self.segments.push(segment);
+        let sealed = self.log.path.clone();
+        self.sequence += 1;
+        let (log, _) = Log::open(log_path(&self.dir, self.sequence), self.cipher.as_ref())?;
+        self.log = log;
+        self.active.clear();
+        fs::remove_file(sealed)
+    }
+
+    /// Readings of `sensor_id` with a timestamp in `[start, end)`, in time
+    /// order; readings with the same timestamp keep their append order.
+    pub fn scan(&self, sensor_id: i64, start: i64, end: i64) -> io::Result<Vec<Reading>> {
+        let mut readings = Vec::new();
+        for segment in &self.segments {
+            if segment.max_timestamp >= start && segment.min_timestamp < end {
+                segment.scan(sensor_id, start, end, &mut readings)?;
+            }
+        }
+        readings.extend(
+            self.active
+                .iter()
+                .filter(|r| r.sensor_id == sensor_id && r.timestamp >= start && r.timestamp < end),
+        );
+        readings.sort_by_key(|r| r.timestamp);
+        Ok(readings)
+    }
+
+    /// Deletes the segments holding only readings before `cutoff`, returning
+    /// how many readings went with them. The active log is never deleted.
+    pub fn drop_before(&mut self, cutoff: i64) -> io::Result<u64> {
+        let mut dropped = 0;
+        let mut kept = Vec::with_capacity(self.segments.len());
+        for segment in std::mem::take(&mut self.segments) {
+            if segment.max_timestamp < cutoff {
+                dropped += segment.readings;
+                let path = segment.path.clone();
+                drop(segment);
+                fs::remove_file(path)?;
+            } else {
+                kept.push(segment);
+            }
+        }
+        self.segments = kept;
+        Ok(dropped)
+    }
+
+    /// Readings in the store, sealed or not.
+    pub fn len(&self) -> u64 {
+        self.segments.iter().map(|s| s.readings).sum::<u64>() + self.active.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn segment_path(dir: &Path, sequence: u64) -> PathBuf {
+    dir.join(format!("{:010}.seg", sequence))
+}
+
+fn seal(
+    dir: &Path,
+    sequence: u64,
+    readings: &mut [Reading],
+    compression: Compression,
+    cipher: Option<&Cipher>,
+) -> io::Result<Segment> {
+    let path = segment_path(dir, sequence);
+    segment::write(&path, readings, compression, cipher)?;
+    Segment::open(path, cipher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("series-store-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn reading(sensor_id: i64, timestamp: i64) -> Reading {
+        Reading {
+            sensor_id,
+            timestamp,
+            value: timestamp as f64 * 0.5,
+        }
+    }
+
+    fn timestamps(readings: &[Reading]) -> Vec<i64> {
+        readings.iter().map(|r| r.timestamp).collect()
+    }
+
+    #[test]
+    fn test_scans_span_segments_and_the_active_log() {
+        for compression in [Compression::None, Compression::Lz4] {
+            let dir = dir(&format!("scan-{:?}", compression));
+            let options = Options {
+                segment_readings: 10,
+                compression,
+                ..Options::default()
+            };
+            let mut store = Store::open(&dir, options).unwrap();
+            // Sensor 1 every 10, sensor 2 in between, and a late reading.
+            let readings: Vec<Reading> = (0..25)
+                .flat_map(|i| [reading(1, i * 10), reading(2, i * 10 + 5)])
+                .chain([reading(1, 3)])
+                .collect();
+            store.append(&readings).unwrap();
+            assert_eq!(store.segments.len(), 5);
+            assert_eq!(store.active.len(), 1);
+            assert_eq!(store.len(), 51);
+
+            let scanned = store.scan(1, 0, 50).unwrap();
+            assert_eq!(timestamps(&scanned), [0, 3, 10, 20, 30, 40]);
+            assert_eq!(scanned[2].value, 5.0);
+            let scanned = store.scan(2, 230, i64::MAX).unwrap();
+            assert_eq!(timestamps(&scanned), [235, 245]);
+            assert!(store.scan(3, i64::MIN, i64::MAX).unwrap().is_empty());
+            fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_reopening_recovers_segments_and_the_active_log() {
+        let dir = dir("reopen");
+        let options = Options {
+            segment_readings: 4,
+            ..Options::default()
+        };
+        let mut store = Store::open(&dir, options.clone()).unwrap();
+        store
+            .append(&(0..6).map(|t| reading(1, t)).collect::<Vec<_>>())
+            .unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        let mut store = Store::open(&dir, options.clone()).unwrap();
+        assert_eq!(
+            timestamps(&store.scan(1, 0, 100).unwrap()),
+            [0, 1, 2, 3, 4, 5]
+        );
+        store.append(&[reading(1, 6), reading(1, 7)]).unwrap();
+        assert_eq!(store.segments.len(), 2);
+
+        // A crash after a seal wrote its segment but before the log went.
+        store.append(&[reading(1, 8)]).unwrap();
+        store.flush().unwrap();
+        let log = store.log.path.clone();
+        let mut active = store.active.clone();
+        segment::write(
+            &segment_path(&dir, store.sequence),
+            &mut active,
+            options.compression,
+            None,
+        )
+        .unwrap();
+        drop(store);
+        let store = Store::open(&dir, options).unwrap();
+        assert!(!log.exists());
+        assert_eq!(store.len(), 9);
+        assert_eq!(store.scan(1, 0, 100).unwrap().len(), 9);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encryption_can_be_turned_on_for_an_existing_store() {
+        let dir = dir("encrypt");
+        let plain = Options {
+            segment_readings: 4,
+            ..Options::default()
+        };
+        let mut store = Store::open(&dir, plain.clone()).unwrap();
+        store
+            .append(&(0..6).map(|t| reading(1, t)).collect::<Vec<_>>())
+            .unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        let encrypted = Options {
+            encryption: Some(Key::new([9; 32])),
+            ..plain.clone()
+        };
+        let mut store = Store::open(&dir, encrypted.clone()).unwrap();
+        assert!(store.log.is_encrypted());
+        assert_eq!(store.segments.len(), 2);
+        store.append(&[reading(1, 6), reading(2, 7)]).unwrap();
+        store.flush().unwrap();
+        drop(store);
+
+        let store = Store::open(&dir, encrypted).unwrap();
+        assert_eq!(
+            timestamps(&store.scan(1, 0, 100).unwrap()),
+            [0, 1, 2, 3, 4, 5, 6]
+        );
+        assert_eq!(store.len(), 8);
+        drop(store);
+        assert!(Store::open(&dir, plain).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_drop_before_deletes_whole_old_segments() {
+        let dir = dir("drop");
+        let options = Options {
+            segment_readings: 5,
+            ..Options::default()
+        };
+        let mut store = Store::open(&dir, options).unwrap();
+        store
+            .append(&(0..12).map(|t| reading(1, t)).collect::<Vec<_>>())
+            .unwrap();
+        assert_eq!(store.drop_before(7).unwrap(), 5);
+        assert_eq!(
+            timestamps(&store.scan(1, 0, 100).unwrap()),
+            (5..12).collect::<Vec<_>>()
+        );
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        store.seal().unwrap();
+        assert_eq!(store.drop_before(100).unwrap(), 7);
+        assert!(store.is_empty());
+        assert!(
+            Store::open(
+                &dir,
+                Options {
+                    segment_readings: 0,
+                    ..Options::default()
+                }
+            )
+            .is_err()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,226 @@
+//! The active log: readings appended since the last seal, in arrival
+//! order, as fixed-size records of sensor id, timestamp and value.
+//!
+//! An encrypted log starts with the magic `SLOGAES1` and holds one frame
+//! per append: its length (u32) and the records sealed by [`Cipher`] with
+//! the frame's offset in the file as associated data, so frames cannot be
+//! reordered. A frame cut short by a crash is dropped like a torn record.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::Reading;
+use crate::cipher::Cipher;
+
+const RECORD_SIZE: usize = 24;
+
+const MAGIC: &[u8; 8] = b"SLOGAES1";
+
+/// Bytes buffered before the log is written out.
+const BUFFER_SIZE: usize = 1 << 20;
+
+pub struct Log {
+    pub path: PathBuf,
+    out: BufWriter<File>,
+    /// Set for an encrypted log.
+    cipher: Option<Cipher>,
+    /// Bytes written to the log, buffered or not.
+    len: u64,
+}
+
+impl Log {
+    /// Opens the log at `path`, returning what it already holds. A record
+    /// cut short by a crash is dropped. A new log is encrypted when `cipher`
+    /// is given; an existing one keeps the format it was started with, and
+    /// an encrypted one cannot be opened without it.
+    pub fn open(path: PathBuf, cipher: Option<&Cipher>) -> io::Result<(Self, Vec<Reading>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let encrypted = bytes.starts_with(MAGIC);
+        let (whole, records) = match (encrypted, cipher) {
+            (false, _) => {
+                let whole = bytes.len() - bytes.len() % RECORD_SIZE;
+                (whole, bytes[..whole].to_vec())
+            }
+            (true, None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{}: encrypted, and no key was given", path.display()),
+                ));
+            }
+            (true, Some(cipher)) => open_frames(cipher, &bytes)?,
+        };
+        if whole < bytes.len() {
+            file.set_len(whole as u64)?;
+        }
+        let readings = records
+            .chunks_exact(RECORD_SIZE)
+            .map(|record| {
+                let field = |at: usize| -> [u8; 8] { record[at..at + 8].try_into().unwrap() };
+                Reading {
+                    sensor_id: i64::from_le_bytes(field(0)),
+                    timestamp: i64::from_le_bytes(field(8)),
+                    value: f64::from_le_bytes(field(16)),
+                }
+            })
+            .collect();
+        let mut log = Log {
+            path,
+            out: BufWriter::with_capacity(BUFFER_SIZE, file),
+            cipher: None,
+            len: whole as u64,
+        };
+        if encrypted || (whole == 0 && cipher.is_some()) {
+            log.cipher = cipher.cloned();
+        }
+        if whole == 0 && log.cipher.is_some() {
+            log.out.write_all(MAGIC)?;
+            log.len = MAGIC.len() as u64;
+        }
+        Ok((log, readings))
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    pub fn append(&mut self, readings: &[Reading]) -> io::Result<()> {
+        let mut records = Vec::with_capacity(readings.len() * RECORD_SIZE);
+        for reading in readings {
+            records.extend_from_slice(&reading.sensor_id.to_le_bytes());
+            records.extend_from_slice(&reading.timestamp.to_le_bytes());
+            records.extend_from_slice(&reading.value.to_le_bytes());
+        }
+        if let Some(cipher) = &self.cipher {
+            if readings.is_empty() {
+                return Ok(());
+            }
+            let frame = cipher.seal(&self.len.to_le_bytes(), &records)?;
+            self.out.write_all(&(frame.len() as u32).to_le_bytes())?;
+            records = frame;
+            self.len += 4;
+        }
+        self.out.write_all(&records)?;
+        self.len += records.len() as u64;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.out.flush()?;
+        self.out.get_ref().sync_data()
+    }
+}
+
+/// The length of the whole frames of an encrypted log and their records.
+fn open_frames(cipher: &Cipher, bytes: &[u8]) -> io::Result<(usize, Vec<u8>)> {
+    let mut records = Vec::new();
+    let mut at = MAGIC.len();
+    while let Some(length) = bytes.get(at..at + 4) {
+        let length = u32::from_le_bytes(length.try_into().unwrap()) as usize;
+        let Some(frame) = bytes.get(at + 4..at + 4 + length) else {
+            break;
+        };
+        records.extend(cipher.open(&(at as u64).to_le_bytes(), frame)?);
+        at += 4 + length;
+    }
+    Ok((at, records))
+}
+
+/// The log of segment `sequence` in `dir`; it becomes `<sequence>.seg` when
+/// sealed.
+pub fn log_path(dir: &Path, sequence: u64) -> PathBuf {
+    dir.join(format!("{:010}.log", sequence))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::Key;
+
+    #[test]
+    fn test_log_survives_reopening_and_torn_writes() {
+        let dir = std::env::temp_dir().join(format!("series-store-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = log_path(&dir, 1);
+        let readings = [
+            Reading {
+                sensor_id: 1,
+                timestamp: 10,
+                value: 1.5,
+            },
+            Reading {
+                sensor_id: 2,
+                timestamp: 11,
+                value: -2.0,
+            },
+        ];
+        let (mut log, existing) = Log::open(path.clone(), None).unwrap();
+        assert!(existing.is_empty());
+        log.append(&readings).unwrap();
+        log.sync().unwrap();
+        drop(log);
+
+        // Half a record, as a crash mid-write leaves.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[7; 10]).unwrap();
+        drop(file);
+
+        let (mut log, existing) = Log::open(path.clone(), None).unwrap();
+        assert_eq!(existing, readings);
+        log.append(&readings[..1]).unwrap();
+        log.flush().unwrap();
+        let (_, existing) = Log::open(path, None).unwrap();
+        assert_eq!(existing.len(), 3);
+        assert_eq!(existing[2], readings[0]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_encrypted_log_drops_torn_frames() {
+        let dir = std::env::temp_dir().join(format!("series-store-elog-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = log_path(&dir, 1);
+        let cipher = Cipher::new(&Key::new([5; 32]));
+        let reading = Reading {
+            sensor_id: 3,
+            timestamp: 12,
+            value: 0.75,
+        };
+        let (mut log, _) = Log::open(path.clone(), Some(&cipher)).unwrap();
+        assert!(log.is_encrypted());
+        log.append(&[reading, reading]).unwrap();
+        log.append(&[reading]).unwrap();
+        log.flush().unwrap();
+        drop(log);
+        let bytes = std::fs::read(&path).unwrap();
+        assert!(!bytes.windows(8).any(|w| w == 0.75f64.to_le_bytes()));
+
+        // A frame whose length made it to disk, but not all of its records.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&100u32.to_le_bytes()).unwrap();
+        file.write_all(&[7; 10]).unwrap();
+        drop(file);
+
+        let (mut log, existing) = Log::open(path.clone(), Some(&cipher)).unwrap();
+        assert_eq!(existing, [reading; 3]);
+        log.append(&[reading]).unwrap();
+        log.flush().unwrap();
+        let (_, existing) = Log::open(path.clone(), Some(&cipher)).unwrap();
+        assert_eq!(existing.len(), 4);
+
+        assert!(Log::open(path.clone(), None).is_err());
+        let wrong = Cipher::new(&Key::new([6; 32]));
+        assert!(Log::open(path, Some(&wrong)).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,391 @@
+//! Sealed segments: immutable files holding the readings of one full
+//! active log, grouped by sensor and sorted by time.
+//!
+//! Layout, all little-endian:
+//!
+//! ```text
+//! header   magic "SSEG0001", compression u8, encrypted u8, 6 padding
+//!          bytes, sensor count u64, reading count u64, min and max
+//!          timestamp i64
+//! index    per sensor, by id: sensor id, min and max timestamp (i64),
+//!          block offset, block length and reading count (u64)
+//! blocks   per sensor: (timestamp i64, value f64) pairs in time order,
+//!          LZ4-compressed as one block when compression is on
+//! ```
+//!
+//! Reads map the file, find the sensor by binary search of the index and,
+//! for uncompressed blocks, the time range by binary search of the block.
+//!
+//! In an encrypted segment the index and each block, after compression,
+//! are sealed by [`Cipher`] with the header as associated data, the
+//! sensor id added for blocks; the header stays readable, so segments
+//! outside a scan's time range are skipped without decrypting anything.
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use memmap2::Mmap;
+
+use crate::cipher::{Cipher, OVERHEAD};
+use crate::{Compression, Reading};
+
+const MAGIC: &[u8; 8] = b"SSEG0001";
+const HEADER_SIZE: usize = 48;
+const INDEX_ENTRY_SIZE: usize = 48;
+/// A reading within a sensor's block: timestamp and value.
+const POINT_SIZE: usize = 16;
+
+struct IndexEntry {
+    sensor_id: i64,
+    min_timestamp: i64,
+    max_timestamp: i64,
+    offset: u64,
+    length: u64,
+    count: u64,
+}
+
+/// Associated data of a sensor's block: the header, then the sensor id.
+fn block_aad(header: &[u8], sensor_id: i64) -> Vec<u8> {
+    [header, &sensor_id.to_le_bytes()[..]].concat()
+}
+
+/// Writes `readings` as a segment at `path`, through a temporary file so a
+/// crash never leaves a partial segment behind; encrypted with `cipher`
+/// when given.
+pub fn write(
+    path: &Path,
+    readings: &mut [Reading],
+    compression: Compression,
+    cipher: Option<&Cipher>,
+) -> io::Result<()> {
+    readings.sort_by_key(|r| (r.sensor_id, r.timestamp));
+    let runs: Vec<&[Reading]> = readings
+        .chunk_by(|a, b| a.sensor_id == b.sensor_id)
+        .collect();
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&[compression as u8, cipher.is_some() as u8, 0, 0, 0, 0, 0, 0]);
+    header.extend_from_slice(&(runs.len() as u64).to_le_bytes());
+    header.extend_from_slice(&(readings.len() as u64).to_le_bytes());
+    let min = runs.iter().map(|run| run[0].timestamp).min().unwrap_or(0);
+    let max = runs.iter().map(|run| run[run.len() - 1].timestamp).max();
+    header.extend_from_slice(&min.to_le_bytes());
+    header.extend_from_slice(&max.unwrap_or(0).to_le_bytes());
+
+    let mut index = Vec::new();
+    let mut blocks = Vec::new();
+    for run in runs {
+        let mut block = Vec::with_capacity(run.len() * POINT_SIZE);
+        for reading in run {
+            block.extend_from_slice(&reading.timestamp.to_le_bytes());
+            block.extend_from_slice(&reading.value.to_le_bytes());
+        }
+        if compression == Compression::Lz4 {
+            block = lz4_flex::block::compress(&block);
+        }
+        if let Some(cipher) = cipher {
+            block = cipher.seal(&block_aad(&header, run[0].sensor_id), &block)?;
+        }
+        index.push(IndexEntry {
+            sensor_id: run[0].sensor_id,
+            min_timestamp: run[0].timestamp,
+            max_timestamp: run[run.len() - 1].timestamp,
+            offset: 0,
+            length: block.len() as u64,
+            count: run.len() as u64,
+        });
+        blocks.push(block);
+    }
+    let sealing = if cipher.is_some() { OVERHEAD } else { 0 };
+    let mut offset = (HEADER_SIZE + index.len() * INDEX_ENTRY_SIZE + sealing) as u64;
+    for entry in &mut index {
+        entry.offset = offset;
+        offset += entry.length;
+    }
+    let mut index_bytes = Vec::with_capacity(index.len() * INDEX_ENTRY_SIZE);
+    for entry in &index {
+        index_bytes.extend_from_slice(&entry.sensor_id.to_le_bytes());
+        index_bytes.extend_from_slice(&entry.min_timestamp.to_le_bytes());
+        index_bytes.extend_from_slice(&entry.max_timestamp.to_le_bytes());
+        index_bytes.extend_from_slice(&entry.offset.to_le_bytes());
+        index_bytes.extend_from_slice(&entry.length.to_le_bytes());
+        index_bytes.extend_from_slice(&entry.count.to_le_bytes());
+    }
+    if let Some(cipher) = cipher {
+        index_bytes = cipher.seal(&header, &index_bytes)?;
+    }
+
+    let temporary = path.with_extension("tmp");
+    let mut out = BufWriter::new(File::create(&temporary)?);
+    out.write_all(&header)?;
+    out.write_all(&index_bytes)?;
+    for block in &blocks {
+        out.write_all(block)?;
+    }
+    out.into_inner()?.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// A sealed segment, mapped read-only.
+pub struct Segment {
+    pub path: PathBuf,
+    map: Mmap,
+    compression: Compression,
+    /// The decrypted index and the cipher of an encrypted segment.
+    encryption: Option<(Vec<u8>, Cipher)>,
+    sensors: usize,
+    pub readings: u64,
+    pub min_timestamp: i64,
+    pub max_timestamp: i64,
+}
+
+impl Segment {
+    /// Opens the segment at `path`; an encrypted one needs `cipher`.
+    pub fn open(path: PathBuf, cipher: Option<&Cipher>) -> io::Result<Self> {
+        let file = File::open(&path)?;
+        // Segments are never written after they are renamed into place.
+        let map = unsafe { Mmap::map(&file)? };
+        let name = path.display().to_string();
+        let invalid = |problem: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", name, problem))
+        };
+        if map.len() < HEADER_SIZE || &map[..8] != MAGIC {
+            return Err(invalid("not a segment"));
+        }
+        let compression = match map[8] {
+            0 => Compression::None,
+            1 => Compression::Lz4,
+            other => return Err(invalid(&format!("unknown compression {}", other))),
+        };
+        let encrypted = match map[9] {
+            0 => false,
+            1 => true,
+            other => return Err(invalid(&format!("unknown encryption {}", other))),
+        };
+        let sensors = u64_at(&map, 16) as usize;
+        let sealing = if encrypted { OVERHEAD } else { 0 };
+        let index_end = sensors
+            .checked_mul(INDEX_ENTRY_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE + sealing));
+        let Some(index_end) = index_end.filter(|end| *end <= map.len()) else {
+            return Err(invalid("truncated index"));
+        };
+        let encryption = match (encrypted, cipher) {
+            (false, _) => None,
+            (true, None) => return Err(invalid("encrypted, and no key was given")),
+            (true, Some(cipher)) => {
+                let index = cipher
+                    .open(&map[..HEADER_SIZE], &map[HEADER_SIZE..index_end])
+                    .map_err(|e| invalid(&e.to_string()))?;
+                Some((index, cipher.clone()))
+            }
+        };
+        let segment = Segment {
+            readings: u64_at(&map, 24),
+            min_timestamp: i64_at(&map, 32),
+            max_timestamp: i64_at(&map, 40),
+            path,
+            map,
+            compression,
+            encryption,
+            sensors,
+        };
+        for i in 0..sensors {
+            let entry = segment.entry(i);
+            let end = entry.offset.checked_add(entry.length);
+            if end.is_none_or(|end| end > segment.map.len() as u64) {
+                return Err(invalid("truncated block"));
+            }
+        }
+        Ok(segment)
+    }
+
+    fn index(&self) -> &[u8] {
+        match &self.encryption {
+            Some((index, _)) => index,
+            None => &self.map[HEADER_SIZE..HEADER_SIZE + self.sensors * INDEX_ENTRY_SIZE],
+        }
+    }
+
+    fn entry(&self, i: usize) -> IndexEntry {
+        let (index, at) = (self.index(), i * INDEX_ENTRY_SIZE);
+        IndexEntry {
+            sensor_id: i64_at(index, at),
+            min_timestamp: i64_at(index, at + 8),
+            max_timestamp: i64_at(index, at + 16),
+            offset: u64_at(index, at + 24),
+            length: u64_at(index, at + 32),
+            count: u64_at(index, at + 40),
+        }
+    }
+
+    fn find(&self, sensor_id: i64) -> Option<IndexEntry> {
+        let (mut low, mut high) = (0, self.sensors);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let id = i64_at(self.index(), mid * INDEX_ENTRY_SIZE);
+            match id.cmp(&sensor_id) {
+                std::cmp::Ordering::Less => low = mid + 1,
+                std::cmp::Ordering::Greater => high = mid,
+                std::cmp::Ordering::Equal => return Some(self.entry(mid)),
+            }
+        }
+        None
+    }
+
+    /// Appends the readings of `sensor_id` in `[start, end)` to `out`, in
+    /// time order.
+    pub fn scan(
+        &self,
+        sensor_id: i64,
+        start: i64,
+        end: i64,
+        out: &mut Vec<Reading>,
+    ) -> io::Result<()> {
+        let Some(entry) = self.find(sensor_id) else {
+            return Ok(());
+        };
+        if entry.max_timestamp < start || entry.min_timestamp >= end {
+            return Ok(());
+        }
+        let mut raw = &self.map[entry.offset as usize..(entry.offset + entry.length) as usize];
+        let decrypted;
+        if let Some((_, cipher)) = &self.encryption {
+            decrypted = cipher.open(&block_aad(&self.map[..HEADER_SIZE], sensor_id), raw)?;
+            raw = &decrypted;
+        }
+        let decompressed;
+        let block = match self.compression {
+            Compression::None => raw,
+            Compression::Lz4 => {
+                decompressed = lz4_flex::block::decompress(raw, entry.count as usize * POINT_SIZE)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+                &decompressed[..]
+            }
+        };
+        let points = block.len() / POINT_SIZE;
+        let timestamp = |i: usize| i64_at(block, i * POINT_SIZE);
+        let first = partition_point(points, |i| timestamp(i) < start);
+        let last = partition_point(points, |i| timestamp(i) < end);
+        out.extend((first..last).map(|i| Reading {
+            sensor_id,
+            timestamp: timestamp(i),
+            value: f64::from_le_bytes(bytes_at(block, i * POINT_SIZE + 8)),
+        }));
+        Ok(())
+    }
+}
+
+/// The first index in `0..len` for which `before` is false.
+fn partition_point(len: usize, before: impl Fn(usize) -> bool) -> usize {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if before(mid) {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+fn bytes_at(bytes: &[u8], at: usize) -> [u8; 8] {
+    bytes[at..at + 8].try_into().expect("8 bytes")
+}
+
+fn i64_at(bytes: &[u8], at: usize) -> i64 {
+    i64::from_le_bytes(bytes_at(bytes, at))
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes_at(bytes, at))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cipher::Key;
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("series-store-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_segments_round_trip_with_and_without_compression() {
+        for compression in [Compression::None, Compression::Lz4] {
+            let path = path(&format!("{:?}.seg", compression));
+            let mut readings: Vec<Reading> = (0..1_000)
+                .rev()
+                .map(|i| Reading {
+                    sensor_id: i % 3,
+                    timestamp: i * 10,
+                    value: i as f64 / 2.0,
+                })
+                .collect();
+            write(&path, &mut readings, compression, None).unwrap();
+
+            let segment = Segment::open(path.clone(), None).unwrap();
+            assert_eq!(segment.readings, 1_000);
+            assert_eq!((segment.min_timestamp, segment.max_timestamp), (0, 9_990));
+            let mut out = Vec::new();
+            segment.scan(1, 100, 200, &mut out).unwrap();
+            let timestamps: Vec<i64> = out.iter().map(|r| r.timestamp).collect();
+            assert_eq!(timestamps, [100, 130, 160, 190]);
+            assert_eq!(out[0].value, 5.0);
+
+            out.clear();
+            segment.scan(7, 0, i64::MAX, &mut out).unwrap();
+            segment.scan(1, 20_000, 30_000, &mut out).unwrap();
+            assert!(out.is_empty());
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_encrypted_segments_need_their_key() {
+        let path = path("encrypted.seg");
+        let cipher = Cipher::new(&Key::new([3; 32]));
+        let mut readings: Vec<Reading> = (0..100)
+            .map(|i| Reading {
+                sensor_id: i % 2,
+                timestamp: i,
+                value: 0.25,
+            })
+            .collect();
+        write(&path, &mut readings, Compression::None, Some(&cipher)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        assert!(!bytes.windows(8).any(|w| w == 0.25f64.to_le_bytes()));
+
+        let segment = Segment::open(path.clone(), Some(&cipher)).unwrap();
+        let mut out = Vec::new();
+        segment.scan(1, 0, 10, &mut out).unwrap();
+        let timestamps: Vec<i64> = out.iter().map(|r| r.timestamp).collect();
+        assert_eq!(timestamps, [1, 3, 5, 7, 9]);
+        assert!(Segment::open(path.clone(), None).is_err());
+        let wrong = Cipher::new(&Key::new([4; 32]));
+        assert!(Segment::open(path.clone(), Some(&wrong)).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_rejects_damaged_files() {
+        let path = path("damaged.seg");
+        let mut readings = vec![Reading {
+            sensor_id: 1,
+            timestamp: 1,
+            value: 1.0,
+        }];
+        write(&path, &mut readings, Compression::None, None).unwrap();
+        let bytes = fs::read(&path).unwrap();
+
+        fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        let error = Segment::open(path.clone(), None).err().unwrap();
+        assert!(error.to_string().ends_with("truncated block"), "{}", error);
+        fs::write(&path, b"not a segment at all, just some text here").unwrap();
+        assert!(Segment::open(path.clone(), None).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,222 @@
+//! Node.js bindings of `detection-core`, so the TypeScript ingestion tools
+//! check readings in process with the same results as the Python extension
+//! and the service.
+//!
+//! Built with `napi build --platform --release` from this directory:
+//!
+//! ```js
+//! const { checkThresholds, detectZScore, detectMad } = require("threshold-checker-node");
+//!
+//! const readings = [{ id: 1, value: 10.0 }, { id: 2, value: 97.5 }];
+//! checkThresholds(readings, 0, 95); // [{ readingId: 2, breachType: "above_maximum", ... }]
+//! detectZScore(readings, 2.0);      // [{ readingId, value, score, severity }]
+//! detectMad(readings, 3.5, { high: 4, critical: 5 });
+//! ```
+//!
+//! Invalid thresholds or bands throw an `Error`.
+
+use napi::{Error, Result, Status};
+use napi_derive::napi;
+
+#[napi(object)]
+pub struct Reading {
+    pub id: i64,
+    pub value: f64,
+}
+
+#[napi(object)]
+pub struct Alert {
+    pub reading_id: i64,
+    pub value: f64,
+    /// `below_minimum`, `above_maximum` or `rapid_change`.
+    pub breach_type: String,
+    pub threshold_value: f64,
+    pub severity: String,
+}
+
+impl From<detection_core::Alert> for Alert {
+    fn from(alert: detection_core::Alert) -> Self {
+        Alert {
+            reading_id: alert.reading_id,
+            value: alert.value,
+            breach_type: alert.breach_type.as_str().to_string(),
+            threshold_value: alert.threshold_value,
+            severity: alert.severity.as_str().to_string(),
+        }
+    }
+}
+
+/// A reading flagged by `detectZScore` or `detectMad`.
+#[napi(object)]
+pub struct Outlier {
+    pub reading_id: i64,
+    pub value: f64,
+    /// The z-score, or the modified z-score for MAD.
+    pub score: f64,
+    pub severity: String,
+}
+
+impl From<detection_core::Outlier> for Outlier {
+    fn from(outlier: detection_core::Outlier) -> Self {
+        Outlier {
+            reading_id: outlier.reading_id,
+            value: outlier.value,
+            score: outlier.score,
+            severity: outlier.severity.as_str().to_string(),
+        }
+    }
+}
+
+/// Minimum `|score|` graded high and critical.
+#[napi(object)]
+pub struct SeverityBands {
+    pub high: f64,
+    pub critical: f64,
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(Status::InvalidArg, message)
+}
+
+fn pairs(readings: &[Reading]) -> Vec<(i64, f64)> {
+    readings.iter().map(|r| (r.id, r.value)).collect()
+}
+
+/// The severity bands given, or the service's defaults when left out.
+fn bands(bands: Option<SeverityBands>) -> Result<detection_core::SeverityBands> {
+    let Some(bands) = bands else {
+        return Ok(detection_core::SeverityBands::default());
+    };
+    if !(bands.high > 0.0 && bands.critical > bands.high) {
+        return Err(invalid(
+            "bands.critical must be above bands.high, both positive".to_string(),
+        ));
+    }
+    Ok(detection_core::SeverityBands {
+        high: bands.high,
+        critical: bands.critical,
+    })
+}
+
+fn positive(threshold: f64) -> Result<f64> {
+    if threshold.is_finite() && threshold > 0.0 {
+        Ok(threshold)
+    } else {
+        Err(invalid(format!(
+            "threshold must be a positive number, got {}",
+            threshold
+        )))
+    }
+}
+
+/// Returns an alert for every reading below `minThreshold` or above
+/// `maxThreshold`.
+#[napi]
+pub fn check_thresholds(
+    readings: Vec<Reading>,
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+) -> Vec<Alert> {
+    detection_core::check_thresholds(pairs(&readings), min_threshold, max_threshold)
+        .into_iter()
+        .map(Alert::from)
+        .collect()
+}
+
+/// Flags the readings whose z-score against all of them exceeds `threshold`,
+/// graded by `bands` as the service grades them.
+#[napi(js_name = "detectZScore")]
+pub fn detect_zscore(
+    readings: Vec<Reading>,
+    threshold: f64,
+    bands: Option<SeverityBands>,
+) -> Result<Vec<Outlier>> {
+    let outliers = detection_core::zscore_outliers(
+        &pairs(&readings),
+        positive(threshold)?,
+        &self::bands(bands)?,
+    );
+    Ok(outliers.into_iter().map(Outlier::from).collect())
+}
+
+/// Flags the readings whose modified z-score around the median exceeds
+/// `threshold` (3.5 is customary), graded by `bands` like z-scores.
+#[napi]
+pub fn detect_mad(
+    readings: Vec<Reading>,
+    threshold: f64,
+    bands: Option<SeverityBands>,
+) -> Result<Vec<Outlier>> {
+    let outliers = detection_core::mad_outliers(
+        &pairs(&readings),
+        positive(threshold)?,
+        &self::bands(bands)?,
+    );
+    Ok(outliers.into_iter().map(Outlier::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn readings(values: &[f64]) -> Vec<Reading> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| Reading {
+                id: i as i64 + 1,
+                value,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_thresholds() {
+        let alerts = check_thresholds(readings(&[50.0, 10.0, 95.0]), Some(40.0), Some(80.0));
+        let found: Vec<(i64, &str, &str)> = alerts
+            .iter()
+            .map(|a| (a.reading_id, a.breach_type.as_str(), a.severity.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (2, "below_minimum", "critical"),
+                (3, "above_maximum", "high")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_detectors_use_given_bands() {
+        let mut values: Vec<f64> = (0..20).map(|i| 10.0 + (i % 5) as f64 * 0.5).collect();
+        values.push(100.0);
+        let bands = || {
+            Some(SeverityBands {
+                high: 1.0,
+                critical: 1000.0,
+            })
+        };
+
+        let zscore = detect_zscore(readings(&values), 2.0, bands()).unwrap();
+        assert_eq!(zscore.len(), 1);
+        assert_eq!(
+            (zscore[0].reading_id, zscore[0].severity.as_str()),
+            (21, "high")
+        );
+        let mad = detect_mad(readings(&values), 3.5, None).unwrap();
+        assert_eq!(
+            (mad[0].reading_id, mad[0].severity.as_str()),
+            (21, "critical")
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_parameters() {
+        assert!(detect_zscore(readings(&[1.0]), 0.0, None).is_err());
+        let inverted = Some(SeverityBands {
+            high: 3.0,
+            critical: 2.0,
+        });
+        assert!(detect_mad(readings(&[1.0]), 3.5, inverted).is_err());
+    }
+}
@@ -1,5 +1,22 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use detection_core::detector::{Detector, ReservoirDetector};
+use detection_core::{
+    Breach, BreachBands, Hysteresis, RateLimit, Rules, Severity, SeverityBands, SeverityLabels,
+    ThresholdTracker,
+};
+use numpy::PyReadonlyArray1;
+use pyo3::exceptions::{PyOSError, PyValueError};
 use pyo3::prelude::*;
 use pyo3::types::PyDict;
+use rayon::prelude::*;
+use serde::Deserialize;
+
+/// Readings each parallel task of `check_thresholds_numpy` scans.
+const CHUNK_READINGS: usize = 64 * 1024;
 
 #[pyclass]
 #[derive(Clone)]
@@ -9,118 +26,905 @@ struct Alert {
     #[pyo3(get)]
     value: f64,
     #[pyo3(get)]
-    breach_type: String,
+    breach_type: Breach,
     #[pyo3(get)]
     threshold_value: f64,
-    #[pyo3(get)]
-    severity: String,
+    severity: Severity,
+    labels: Labels,
+}
+
+/// The labels of a call's `SeverityConfig`, if it set any; else severities
+/// are shown with those of `set_severity_labels`.
+type Labels = Option<Arc<SeverityLabels>>;
+
+fn shown(severity: Severity, labels: &Labels) -> String {
+    match labels {
+        Some(labels) => labels.get(severity).to_string(),
+        None => severity.label().into_owned(),
+    }
+}
+
+impl Alert {
+    fn new(alert: detection_core::Alert, labels: &Labels) -> Self {
+        Alert {
+            reading_id: alert.reading_id,
+            value: alert.value,
+            breach_type: alert.breach_type,
+            threshold_value: alert.threshold_value,
+            severity: alert.severity,
+            labels: labels.clone(),
+        }
+    }
 }
 
 #[pymethods]
 impl Alert {
+    #[getter(severity)]
+    fn severity_label(&self) -> String {
+        shown(self.severity, &self.labels)
+    }
+
     fn to_dict(&self, py: Python) -> PyResult<Py<PyDict>> {
         let dict = PyDict::new(py);
         dict.set_item("reading_id", self.reading_id)?;
         dict.set_item("value", self.value)?;
-        dict.set_item("breach_type", &self.breach_type)?;
+        dict.set_item("breach_type", self.breach_type)?;
         dict.set_item("threshold_value", self.threshold_value)?;
-        dict.set_item("severity", &self.severity)?;
+        dict.set_item("severity", self.severity_label())?;
         Ok(dict.into())
     }
 }
 
+/// How far past a limit, as a share of the limit's size, a breach must lie
+/// to be `high` (default 0.1) or `critical` (default 0.2), and the labels,
+/// e.g. `critical="P1"`, the alerts of the calls given it show severities
+/// with; labels left out are those of `set_severity_labels`.
+#[pyclass]
+#[derive(Clone)]
+struct SeverityConfig {
+    bands: BreachBands,
+    labels: Labels,
+}
+
+#[pymethods]
+impl SeverityConfig {
+    #[new]
+    #[pyo3(signature = (high=None, critical=None, medium_label=None, high_label=None, critical_label=None))]
+    fn new(
+        high: Option<f64>,
+        critical: Option<f64>,
+        medium_label: Option<String>,
+        high_label: Option<String>,
+        critical_label: Option<String>,
+    ) -> Result<Self, InvalidArgument> {
+        let defaults = BreachBands::default();
+        let bands = BreachBands {
+            high: finite("high", high)?.unwrap_or(defaults.high),
+            critical: finite("critical", critical)?.unwrap_or(defaults.critical),
+        };
+        bands.validate().map_err(InvalidArgument)?;
+        let labels = match (medium_label, high_label, critical_label) {
+            (None, None, None) => None,
+            (medium, high, critical) => {
+                let shown = |severity| Severity::label(severity).into_owned();
+                let labels = SeverityLabels {
+                    medium: medium.unwrap_or_else(|| shown(Severity::Medium)),
+                    high: high.unwrap_or_else(|| shown(Severity::High)),
+                    critical: critical.unwrap_or_else(|| shown(Severity::Critical)),
+                };
+                labels.validate().map_err(InvalidArgument)?;
+                Some(Arc::new(labels))
+            }
+        };
+        Ok(SeverityConfig { bands, labels })
+    }
+
+    #[getter]
+    fn high(&self) -> f64 {
+        self.bands.high
+    }
+
+    #[getter]
+    fn critical(&self) -> f64 {
+        self.bands.critical
+    }
+}
+
+fn breach_bands(severity: &Option<SeverityConfig>) -> BreachBands {
+    severity
+        .as_ref()
+        .map_or_else(BreachBands::default, |config| config.bands)
+}
+
+fn severity_labels(severity: &Option<SeverityConfig>) -> Labels {
+    severity.as_ref().and_then(|config| config.labels.clone())
+}
+
 #[pyfunction]
+#[pyo3(signature = (readings, min_threshold=None, max_threshold=None, severity=None))]
 fn check_thresholds(
     readings: Vec<(i64, f64)>,
     min_threshold: Option<f64>,
     max_threshold: Option<f64>,
-) -> Vec<Alert> {
-    let mut alerts = Vec::new();
-
-    for (reading_id, value) in readings {
-        if let Some(min) = min_threshold {
-            if value < min {
-                let diff = min - value;
-                let severity = if diff > min * 0.2 {
-                    "critical"
-                } else if diff > min * 0.1 {
-                    "high"
-                } else {
-                    "medium"
-                };
+    severity: Option<SeverityConfig>,
+) -> Result<Vec<Alert>, InvalidArgument> {
+    finite_readings(readings.iter().copied())?;
+    let labels = severity_labels(&severity);
+    Ok(detection_core::check_thresholds_graded(
+        readings,
+        finite("min_threshold", min_threshold)?,
+        finite("max_threshold", max_threshold)?,
+        &breach_bands(&severity),
+    )
+    .into_iter()
+    .map(|alert| Alert::new(alert, &labels))
+    .collect())
+}
+
+/// A reading of `check_rate_of_change`: `(reading_id, value)`, or
+/// `(reading_id, value, timestamp)` with the timestamp in seconds.
+#[derive(FromPyObject)]
+enum Step {
+    Timed(i64, f64, f64),
+    Untimed(i64, f64),
+}
+
+fn rate_limit(
+    max_change: Option<f64>,
+    max_change_per_second: Option<f64>,
+) -> Result<RateLimit, InvalidArgument> {
+    Ok(RateLimit {
+        per_step: max_change
+            .map(|limit| positive("max_change", limit))
+            .transpose()?,
+        per_second: max_change_per_second
+            .map(|limit| positive("max_change_per_second", limit))
+            .transpose()?,
+    })
+}
+
+/// Alerts `rapid_change` for every reading changing from the one before it
+/// by more than `max_change`, or by more than `max_change_per_second` per
+/// second between their timestamps, in reading order; a change to a reading
+/// no later than the one before passes any `max_change_per_second`.
+/// Readings without a timestamp raise `ValueError` when
+/// `max_change_per_second` is set.
+#[pyfunction]
+#[pyo3(signature = (readings, max_change=None, max_change_per_second=None, severity=None))]
+fn check_rate_of_change(
+    readings: Vec<Step>,
+    max_change: Option<f64>,
+    max_change_per_second: Option<f64>,
+    severity: Option<SeverityConfig>,
+) -> Result<Vec<Alert>, InvalidArgument> {
+    let limit = rate_limit(max_change, max_change_per_second)?;
+    let readings = readings
+        .into_iter()
+        .map(|step| match step {
+            Step::Timed(reading_id, value, timestamp) => Ok((
+                reading_id,
+                value,
+                Some(finite_timestamp(reading_id, timestamp)?),
+            )),
+            Step::Untimed(reading_id, _) if limit.per_second.is_some() => {
+                Err(InvalidArgument(format!(
+                    "reading {}: max_change_per_second needs (reading_id, value, timestamp)",
+                    reading_id
+                )))
+            }
+            Step::Untimed(reading_id, value) => Ok((reading_id, value, None)),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    finite_readings(
+        readings
+            .iter()
+            .map(|&(reading_id, value, _)| (reading_id, value)),
+    )?;
+    let labels = severity_labels(&severity);
+    Ok(
+        detection_core::check_rate_of_change(readings, &limit, &breach_bands(&severity))
+            .into_iter()
+            .map(|alert| Alert::new(alert, &labels))
+            .collect(),
+    )
+}
+
+/// The limits of one sensor in a `ThresholdProfile`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Limits {
+    #[serde(default)]
+    min_threshold: Option<f64>,
+    #[serde(default)]
+    max_threshold: Option<f64>,
+}
 
-                alerts.push(Alert {
-                    reading_id,
-                    value,
-                    breach_type: "below_minimum".to_string(),
-                    threshold_value: min,
-                    severity: severity.to_string(),
-                });
+impl Limits {
+    fn from_dict(sensor: &str, dict: HashMap<String, f64>) -> Result<Self, InvalidArgument> {
+        let mut limits = Limits::default();
+        for (key, value) in dict {
+            match key.as_str() {
+                "min_threshold" => limits.min_threshold = Some(value),
+                "max_threshold" => limits.max_threshold = Some(value),
+                _ => {
+                    return Err(InvalidArgument(format!(
+                        "sensor {}: unknown limit {}, expected min_threshold or max_threshold",
+                        sensor, key
+                    )));
+                }
             }
         }
+        Ok(limits)
+    }
+}
 
-        if let Some(max) = max_threshold {
-            if value > max {
-                let diff = value - max;
-                let severity = if diff > max * 0.2 {
-                    "critical"
-                } else if diff > max * 0.1 {
-                    "high"
-                } else {
-                    "medium"
-                };
+/// The `min_threshold` and `max_threshold` of each sensor by name, e.g.
+/// `{"boiler-1": {"max_threshold": 90.0}}`, built from a dict or read from
+/// a YAML or JSON file of the same shape with `from_file`.
+#[pyclass]
+#[derive(Clone, Debug, Default)]
+struct ThresholdProfile {
+    sensors: HashMap<String, Limits>,
+}
 
-                alerts.push(Alert {
-                    reading_id,
-                    value,
-                    breach_type: "above_maximum".to_string(),
-                    threshold_value: max,
-                    severity: severity.to_string(),
-                });
+impl ThresholdProfile {
+    fn checked(sensors: HashMap<String, Limits>) -> Result<Self, InvalidArgument> {
+        for (sensor, limits) in &sensors {
+            let finite = [limits.min_threshold, limits.max_threshold]
+                .into_iter()
+                .flatten()
+                .all(f64::is_finite);
+            if !finite {
+                return Err(InvalidArgument(format!(
+                    "sensor {}: limits must be finite numbers",
+                    sensor
+                )));
+            }
+            if let (Some(min), Some(max)) = (limits.min_threshold, limits.max_threshold)
+                && min > max
+            {
+                return Err(InvalidArgument(format!(
+                    "sensor {}: min_threshold {} is above max_threshold {}",
+                    sensor, min, max
+                )));
             }
         }
+        Ok(ThresholdProfile { sensors })
+    }
+
+    /// Parses a profile from YAML, or else JSON.
+    fn parse(text: &str, yaml: bool) -> Result<Self, String> {
+        let sensors = if yaml {
+            serde_yaml::from_str(text).map_err(|e| e.to_string())?
+        } else {
+            serde_json::from_str(text).map_err(|e| e.to_string())?
+        };
+        ThresholdProfile::checked(sensors).map_err(|e| e.0)
+    }
+
+    fn limits(&self, sensor: &str) -> Result<Limits, InvalidArgument> {
+        self.sensors
+            .get(sensor)
+            .copied()
+            .ok_or_else(|| InvalidArgument(format!("no thresholds for sensor {}", sensor)))
+    }
+}
+
+#[pymethods]
+impl ThresholdProfile {
+    #[new]
+    fn new(sensors: HashMap<String, HashMap<String, f64>>) -> Result<Self, InvalidArgument> {
+        let sensors = sensors
+            .into_iter()
+            .map(|(sensor, dict)| Ok((sensor.clone(), Limits::from_dict(&sensor, dict)?)))
+            .collect::<Result<_, InvalidArgument>>()?;
+        ThresholdProfile::checked(sensors)
+    }
+
+    /// Reads a profile from a `.yaml`/`.yml` file, or else a JSON one.
+    #[staticmethod]
+    fn from_file(path: PathBuf) -> PyResult<Self> {
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| PyOSError::new_err(format!("{}: {}", path.display(), e)))?;
+        let yaml = matches!(
+            path.extension().and_then(|e| e.to_str()),
+            Some("yaml" | "yml")
+        );
+        ThresholdProfile::parse(&text, yaml)
+            .map_err(|e| InvalidArgument(format!("{}: {}", path.display(), e)).into())
+    }
+
+    fn sensors(&self) -> Vec<String> {
+        let mut sensors: Vec<String> = self.sensors.keys().cloned().collect();
+        sensors.sort();
+        sensors
+    }
+
+    fn __len__(&self) -> usize {
+        self.sensors.len()
+    }
+}
+
+/// A `ThresholdProfile`, or the dict to build one from.
+#[derive(FromPyObject)]
+enum Profile {
+    Loaded(ThresholdProfile),
+    Dict(HashMap<String, HashMap<String, f64>>),
+}
+
+/// Checks `(sensor, reading_id, value)` readings against the limits of
+/// their sensor in `profile`, in one pass; the alerts by sensor, in reading
+/// order, for the sensors that have any. A sensor missing from the profile
+/// raises `ValueError`.
+#[pyfunction]
+#[pyo3(signature = (readings, profile, severity=None))]
+fn check_thresholds_batch(
+    readings: Vec<(String, i64, f64)>,
+    profile: Profile,
+    severity: Option<SeverityConfig>,
+) -> Result<HashMap<String, Vec<Alert>>, InvalidArgument> {
+    let profile = match profile {
+        Profile::Loaded(profile) => profile,
+        Profile::Dict(sensors) => ThresholdProfile::new(sensors)?,
+    };
+    let (bands, labels) = (breach_bands(&severity), severity_labels(&severity));
+    let mut alerts: HashMap<String, Vec<Alert>> = HashMap::new();
+    finite_readings(
+        readings
+            .iter()
+            .map(|(_, reading_id, value)| (*reading_id, *value)),
+    )?;
+    for (sensor, reading_id, value) in readings {
+        let limits = profile.limits(&sensor)?;
+        let found = detection_core::check_thresholds_graded(
+            [(reading_id, value)],
+            limits.min_threshold,
+            limits.max_threshold,
+            &bands,
+        );
+        if !found.is_empty() {
+            alerts
+                .entry(sensor)
+                .or_default()
+                .extend(found.into_iter().map(|alert| Alert::new(alert, &labels)));
+        }
     }
+    Ok(alerts)
+}
 
-    alerts
+/// Checks `ids` and `values` read side by side in chunks on the rayon pool,
+/// the alerts in reading order.
+fn check_slices(
+    ids: &[i64],
+    values: &[f64],
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+    bands: &BreachBands,
+) -> Vec<detection_core::Alert> {
+    ids.par_chunks(CHUNK_READINGS)
+        .zip(values.par_chunks(CHUNK_READINGS))
+        .flat_map_iter(|(ids, values)| {
+            detection_core::check_thresholds_graded(
+                ids.iter().copied().zip(values.iter().copied()),
+                min_threshold,
+                max_threshold,
+                bands,
+            )
+        })
+        .collect()
+}
+
+/// `check_thresholds` over NumPy arrays of reading ids and values, scanned
+/// on all cores with the GIL released.
+#[pyfunction]
+#[pyo3(signature = (ids, values, min_threshold=None, max_threshold=None, severity=None))]
+fn check_thresholds_numpy(
+    py: Python<'_>,
+    ids: PyReadonlyArray1<'_, i64>,
+    values: PyReadonlyArray1<'_, f64>,
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+    severity: Option<SeverityConfig>,
+) -> PyResult<Vec<Alert>> {
+    let (bands, labels) = (breach_bands(&severity), severity_labels(&severity));
+    let (ids, values) = (ids.as_array(), values.as_array());
+    if ids.len() != values.len() {
+        return Err(InvalidArgument(format!(
+            "ids and values must be the same length, got {} and {}",
+            ids.len(),
+            values.len()
+        ))
+        .into());
+    }
+    // Strided views, e.g. a column of a 2-D array, are copied first.
+    let ids = ids
+        .as_slice()
+        .map_or_else(|| Cow::Owned(ids.to_vec()), Cow::Borrowed);
+    let values = values
+        .as_slice()
+        .map_or_else(|| Cow::Owned(values.to_vec()), Cow::Borrowed);
+    let (min_threshold, max_threshold) = (
+        finite("min_threshold", min_threshold)?,
+        finite("max_threshold", max_threshold)?,
+    );
+    let alerts = py.detach(|| {
+        finite_readings(ids.iter().copied().zip(values.iter().copied()))?;
+        Ok::<_, InvalidArgument>(check_slices(
+            &ids,
+            &values,
+            min_threshold,
+            max_threshold,
+            &bands,
+        ))
+    })?;
+    Ok(alerts
+        .into_iter()
+        .map(|alert| Alert::new(alert, &labels))
+        .collect())
+}
+
+/// Checks the readings of one sensor across calls and alerts once per
+/// excursion: after `min_consecutive_breaches` readings in a row past
+/// `min_threshold` or `max_threshold`, then not again until a reading is
+/// back at or inside `clear_min` or `clear_max` (by default the limits
+/// themselves). With `max_change` or `max_change_per_second`, readings not
+/// tripping the alarm alert `rapid_change` as in `check_rate_of_change`,
+/// the per-second limit applying to those added with `check_timed`.
+#[pyclass]
+#[derive(Clone)]
+struct ThresholdChecker {
+    tracker: ThresholdTracker,
+    labels: Labels,
+}
+
+#[pymethods]
+impl ThresholdChecker {
+    #[new]
+    #[pyo3(signature = (
+        min_threshold=None,
+        max_threshold=None,
+        clear_min=None,
+        clear_max=None,
+        min_consecutive_breaches=1,
+        severity=None,
+        max_change=None,
+        max_change_per_second=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        min_threshold: Option<f64>,
+        max_threshold: Option<f64>,
+        clear_min: Option<f64>,
+        clear_max: Option<f64>,
+        min_consecutive_breaches: u32,
+        severity: Option<SeverityConfig>,
+        max_change: Option<f64>,
+        max_change_per_second: Option<f64>,
+    ) -> Result<Self, InvalidArgument> {
+        let limits = Hysteresis {
+            min: finite("min_threshold", min_threshold)?,
+            max: finite("max_threshold", max_threshold)?,
+            clear_min: finite("clear_min", clear_min)?,
+            clear_max: finite("clear_max", clear_max)?,
+            min_consecutive_breaches,
+        };
+        limits.validate().map_err(InvalidArgument)?;
+        let rate = rate_limit(max_change, max_change_per_second)?;
+        Ok(ThresholdChecker {
+            tracker: ThresholdTracker::new(limits, breach_bands(&severity)).with_rate_limit(rate),
+            labels: severity_labels(&severity),
+        })
+    }
+
+    /// Adds `readings` in order, returning the alerts of those that trip
+    /// the alarm. Readings with a value that is not a finite number raise
+    /// `ValueError` before any is added.
+    fn check(&mut self, readings: Vec<(i64, f64)>) -> Result<Vec<Alert>, InvalidArgument> {
+        finite_readings(readings.iter().copied())?;
+        Ok(readings
+            .into_iter()
+            .filter_map(|(reading_id, value)| self.tracker.check(reading_id, value))
+            .map(|alert| Alert::new(alert, &self.labels))
+            .collect())
+    }
+
+    /// `check` with `(reading_id, value, timestamp)` readings, the
+    /// timestamp in seconds.
+    fn check_timed(
+        &mut self,
+        readings: Vec<(i64, f64, f64)>,
+    ) -> Result<Vec<Alert>, InvalidArgument> {
+        finite_readings(
+            readings
+                .iter()
+                .map(|&(reading_id, value, _)| (reading_id, value)),
+        )?;
+        for &(reading_id, _, timestamp) in &readings {
+            finite_timestamp(reading_id, timestamp)?;
+        }
+        Ok(readings
+            .into_iter()
+            .filter_map(|(reading_id, value, timestamp)| {
+                self.tracker.check_at(reading_id, value, Some(timestamp))
+            })
+            .map(|alert| Alert::new(alert, &self.labels))
+            .collect())
+    }
+
+    /// The breach type of the alarm while it is tripped, else `None`.
+    #[getter]
+    fn tripped(&self) -> Option<Breach> {
+        self.tracker.tripped()
+    }
+
+    fn reset(&mut self) {
+        self.tracker.reset();
+    }
+}
+
+/// A reading flagged by one of the statistical detectors.
+#[pyclass]
+#[derive(Clone)]
+struct Outlier {
+    #[pyo3(get)]
+    reading_id: i64,
+    #[pyo3(get)]
+    value: f64,
+    #[pyo3(get)]
+    score: f64,
+    #[pyo3(get)]
+    severity: Severity,
+}
+
+impl From<detection_core::Outlier> for Outlier {
+    fn from(outlier: detection_core::Outlier) -> Self {
+        Outlier {
+            reading_id: outlier.reading_id,
+            value: outlier.value,
+            score: outlier.score,
+            severity: outlier.severity,
+        }
+    }
+}
+
+#[pymethods]
+impl Outlier {
+    fn to_dict(&self, py: Python) -> PyResult<Py<PyDict>> {
+        let dict = PyDict::new(py);
+        dict.set_item("reading_id", self.reading_id)?;
+        dict.set_item("value", self.value)?;
+        dict.set_item("score", self.score)?;
+        dict.set_item("severity", self.severity)?;
+        Ok(dict.into())
+    }
+}
+
+/// Raised in Python as `ValueError`.
+#[derive(Debug)]
+struct InvalidArgument(String);
+
+impl From<InvalidArgument> for PyErr {
+    fn from(error: InvalidArgument) -> Self {
+        PyValueError::new_err(error.0)
+    }
+}
+
+type Detected = Result<Vec<Outlier>, InvalidArgument>;
+
+/// The severity bands, each left out defaulting to the service's.
+fn bands(high: Option<f64>, critical: Option<f64>) -> Result<SeverityBands, InvalidArgument> {
+    let defaults = SeverityBands::default();
+    let bands = SeverityBands {
+        high: finite("high", high)?.unwrap_or(defaults.high),
+        critical: finite("critical", critical)?.unwrap_or(defaults.critical),
+    };
+    if !(bands.high > 0.0 && bands.critical > bands.high) {
+        return Err(InvalidArgument(
+            "critical must be above high, both positive".to_string(),
+        ));
+    }
+    Ok(bands)
+}
+
+fn positive(name: &str, value: f64) -> Result<f64, InvalidArgument> {
+    if value.is_finite() && value > 0.0 {
+        Ok(value)
+    } else {
+        Err(InvalidArgument(format!(
+            "{} must be a positive number, got {}",
+            name, value
+        )))
+    }
+}
+
+/// `value`, if set, when it is a finite number.
+fn finite(name: &str, value: Option<f64>) -> Result<Option<f64>, InvalidArgument> {
+    match value {
+        Some(value) if !value.is_finite() => Err(InvalidArgument(format!(
+            "{} must be a finite number, got {}",
+            name, value
+        ))),
+        _ => Ok(value),
+    }
+}
+
+/// Refuses `(reading_id, value)` readings unless every value is a finite
+/// number.
+fn finite_readings(readings: impl IntoIterator<Item = (i64, f64)>) -> Result<(), InvalidArgument> {
+    match readings.into_iter().find(|(_, value)| !value.is_finite()) {
+        Some((reading_id, value)) => Err(InvalidArgument(format!(
+            "reading {}: value must be a finite number, got {}",
+            reading_id, value
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn finite_timestamp(reading_id: i64, timestamp: f64) -> Result<f64, InvalidArgument> {
+    if timestamp.is_finite() {
+        Ok(timestamp)
+    } else {
+        Err(InvalidArgument(format!(
+            "reading {}: timestamp must be a finite number, got {}",
+            reading_id, timestamp
+        )))
+    }
+}
+
+fn outliers(found: Vec<detection_core::Outlier>) -> Vec<Outlier> {
+    found.into_iter().map(Outlier::from).collect()
+}
+
+/// Readings whose z-score against the whole batch exceeds `threshold`.
+#[pyfunction]
+#[pyo3(signature = (readings, threshold, high=None, critical=None))]
+fn zscore_outliers(
+    readings: Vec<(i64, f64)>,
+    threshold: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    let threshold = positive("threshold", threshold)?;
+    Ok(outliers(detection_core::zscore_outliers(
+        &readings,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings whose modified z-score around the median exceeds `threshold`.
+#[pyfunction]
+#[pyo3(signature = (readings, threshold=3.5, high=None, critical=None))]
+fn mad_outliers(
+    readings: Vec<(i64, f64)>,
+    threshold: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    let threshold = positive("threshold", threshold)?;
+    Ok(outliers(detection_core::mad_outliers(
+        &readings,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings more than `k` interquartile ranges outside the quartiles.
+#[pyfunction]
+#[pyo3(signature = (readings, k=1.5, high=None, critical=None))]
+fn iqr_outliers(
+    readings: Vec<(i64, f64)>,
+    k: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    let k = positive("k", k)?;
+    Ok(outliers(detection_core::iqr_outliers(
+        &readings,
+        k,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings, in order, whose z-score against the exponentially weighted
+/// mean of the readings before them exceeds `threshold`.
+#[pyfunction]
+#[pyo3(signature = (readings, alpha, threshold, high=None, critical=None))]
+fn ewma_outliers(
+    readings: Vec<(i64, f64)>,
+    alpha: f64,
+    threshold: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    if !(alpha > 0.0 && alpha <= 1.0) {
+        return Err(InvalidArgument(format!(
+            "alpha must be in (0, 1], got {}",
+            alpha
+        )));
+    }
+    let threshold = positive("threshold", threshold)?;
+    Ok(outliers(detection_core::ewma_outliers(
+        &readings,
+        alpha,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings, in order, whose z-score against the `window` readings before
+/// them exceeds `threshold`.
+#[pyfunction]
+#[pyo3(signature = (readings, window, threshold, high=None, critical=None))]
+fn rolling_outliers(
+    readings: Vec<(i64, f64)>,
+    window: usize,
+    threshold: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    if window < 2 {
+        return Err(InvalidArgument("window must be at least 2".to_string()));
+    }
+    let threshold = positive("threshold", threshold)?;
+    Ok(outliers(detection_core::rolling_outliers(
+        &readings,
+        window,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings whose z-score against a random sample of `capacity` of the
+/// readings before them exceeds `threshold`, once 20 have been seen. The
+/// sample is drawn with `seed`, so the same seed gives the same outliers as
+/// the service's `reservoir` method.
+#[pyfunction]
+#[pyo3(signature = (readings, threshold=3.0, capacity=256, seed=0, high=None, critical=None))]
+fn reservoir_outliers(
+    readings: Vec<(i64, f64)>,
+    threshold: f64,
+    capacity: usize,
+    seed: u64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    if capacity == 0 {
+        return Err(InvalidArgument("capacity must be at least 1".to_string()));
+    }
+    let threshold = positive("threshold", threshold)?;
+    let detector = ReservoirDetector { capacity, seed };
+    Ok(outliers(detector.outliers(
+        &readings,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Readings graded by `rules`, e.g. `["value > 95 => critical"]`, the
+/// first matching a reading's value and z-score against the batch deciding,
+/// and otherwise whose z-score exceeds `threshold`; the same rules as a
+/// sensor's `rules` in the service, graded the same way.
+#[pyfunction]
+#[pyo3(signature = (readings, rules, threshold=3.0, high=None, critical=None))]
+fn rule_outliers(
+    readings: Vec<(i64, f64)>,
+    rules: Vec<String>,
+    threshold: f64,
+    high: Option<f64>,
+    critical: Option<f64>,
+) -> Detected {
+    finite_readings(readings.iter().copied())?;
+    let rules = Rules::parse(&rules).map_err(|e| InvalidArgument(e.to_string()))?;
+    let threshold = positive("threshold", threshold)?;
+    Ok(outliers(detection_core::rule_outliers(
+        &readings,
+        &rules,
+        threshold,
+        &bands(high, critical)?,
+    )))
+}
+
+/// Shows severities under the given labels, e.g. `critical="P1"`, each left
+/// out keeping its own, in `severity` and `to_dict` from then on.
+#[pyfunction]
+#[pyo3(signature = (medium=None, high=None, critical=None))]
+fn set_severity_labels(
+    medium: Option<String>,
+    high: Option<String>,
+    critical: Option<String>,
+) -> Result<(), InvalidArgument> {
+    let defaults = SeverityLabels::default();
+    let labels = SeverityLabels {
+        medium: medium.unwrap_or(defaults.medium),
+        high: high.unwrap_or(defaults.high),
+        critical: critical.unwrap_or(defaults.critical),
+    };
+    labels.validate().map_err(InvalidArgument)?;
+    labels.install();
+    Ok(())
 }
 
 #[pymodule]
 fn threshold_checker(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(check_thresholds, m)?)?;
+    m.add_function(wrap_pyfunction!(check_thresholds_numpy, m)?)?;
+    m.add_function(wrap_pyfunction!(check_thresholds_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(check_rate_of_change, m)?)?;
+    m.add_function(wrap_pyfunction!(zscore_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(mad_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(iqr_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(ewma_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(rolling_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(reservoir_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(rule_outliers, m)?)?;
+    m.add_function(wrap_pyfunction!(set_severity_labels, m)?)?;
     m.add_class::<Alert>()?;
+    m.add_class::<SeverityConfig>()?;
+    m.add_class::<ThresholdChecker>()?;
+    m.add_class::<ThresholdProfile>()?;
+    m.add_class::<Outlier>()?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Mutex, MutexGuard, PoisonError};
+
+    /// Held by the tests installing or reading the process-wide labels, and
+    /// putting the canonical ones back when dropped, even after a failure.
+    struct InstalledLabels {
+        _held: MutexGuard<'static, ()>,
+    }
+
+    impl InstalledLabels {
+        fn lock() -> Self {
+            static LABELS: Mutex<()> = Mutex::new(());
+            InstalledLabels {
+                _held: LABELS.lock().unwrap_or_else(PoisonError::into_inner),
+            }
+        }
+    }
+
+    impl Drop for InstalledLabels {
+        fn drop(&mut self) {
+            SeverityLabels::default().install();
+        }
+    }
 
     #[test]
     fn test_no_breaches() {
         let readings = vec![(1, 50.0), (2, 60.0), (3, 70.0)];
-        let alerts = check_thresholds(readings, Some(40.0), Some(80.0));
+        let alerts = check_thresholds(readings, Some(40.0), Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 0);
     }
 
     #[test]
     fn test_below_minimum() {
         let readings = vec![(1, 50.0), (2, 10.0), (3, 70.0)];
-        let alerts = check_thresholds(readings, Some(40.0), Some(80.0));
+        let alerts = check_thresholds(readings, Some(40.0), Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 1);
         assert_eq!(alerts[0].reading_id, 2);
         assert_eq!(alerts[0].value, 10.0);
-        assert_eq!(alerts[0].breach_type, "below_minimum");
+        assert_eq!(alerts[0].breach_type, Breach::BelowMinimum);
         assert_eq!(alerts[0].threshold_value, 40.0);
     }
 
     #[test]
     fn test_above_maximum() {
         let readings = vec![(1, 50.0), (2, 90.0), (3, 70.0)];
-        let alerts = check_thresholds(readings, Some(40.0), Some(80.0));
+        let alerts = check_thresholds(readings, Some(40.0), Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 1);
         assert_eq!(alerts[0].reading_id, 2);
         assert_eq!(alerts[0].value, 90.0);
-        assert_eq!(alerts[0].breach_type, "above_maximum");
+        assert_eq!(alerts[0].breach_type, Breach::AboveMaximum);
         assert_eq!(alerts[0].threshold_value, 80.0);
     }
 
@@ -132,61 +936,434 @@ mod tests {
             (3, 95.0), // Above max
             (4, 5.0),  // Below min
         ];
-        let alerts = check_thresholds(readings, Some(40.0), Some(80.0));
+        let alerts = check_thresholds(readings, Some(40.0), Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 3);
     }
 
+    #[test]
+    fn test_check_slices_matches_check_thresholds() {
+        let count = 3 * CHUNK_READINGS + 17;
+        let ids: Vec<i64> = (0..count as i64).collect();
+        let values: Vec<f64> = ids.iter().map(|&id| ((id * 37) % 101) as f64).collect();
+        let alerts = check_slices(
+            &ids,
+            &values,
+            Some(5.0),
+            Some(95.0),
+            &BreachBands::default(),
+        );
+        let expected = detection_core::check_thresholds(
+            ids.iter().copied().zip(values.iter().copied()),
+            Some(5.0),
+            Some(95.0),
+        );
+        assert!(!alerts.is_empty());
+        assert_eq!(alerts, expected);
+    }
+
+    #[test]
+    fn test_severity_config() {
+        let Ok(config) = SeverityConfig::new(Some(0.05), None, None, None, None) else {
+            panic!("valid bands were rejected");
+        };
+        assert_eq!((config.high(), config.critical()), (0.05, 0.2));
+        let alerts = check_slices(&[1, 2], &[51.0, 53.0], None, Some(50.0), &config.bands);
+        let severities: Vec<Severity> = alerts.iter().map(|a| a.severity).collect();
+        assert_eq!(severities, vec![Severity::Medium, Severity::High]);
+        assert!(SeverityConfig::new(Some(0.3), Some(0.2), None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_check_thresholds_batch() {
+        let limits = |pairs: &[(&str, f64)]| {
+            pairs
+                .iter()
+                .map(|&(key, value)| (key.to_string(), value))
+                .collect::<HashMap<_, _>>()
+        };
+        let profile = HashMap::from([
+            ("boiler".to_string(), limits(&[("max_threshold", 90.0)])),
+            (
+                "intake".to_string(),
+                limits(&[("min_threshold", 5.0), ("max_threshold", 30.0)]),
+            ),
+        ]);
+        let readings = vec![
+            ("boiler".to_string(), 1, 95.0),
+            ("intake".to_string(), 2, 95.0),
+            ("intake".to_string(), 3, 10.0),
+            ("boiler".to_string(), 4, 85.0),
+            ("intake".to_string(), 5, 1.0),
+        ];
+        let alerts =
+            check_thresholds_batch(readings.clone(), Profile::Dict(profile.clone()), None).unwrap();
+        let breaches = |sensor: &str| {
+            alerts[sensor]
+                .iter()
+                .map(|a| (a.reading_id, a.breach_type))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(breaches("boiler"), [(1, Breach::AboveMaximum)]);
+        assert_eq!(
+            breaches("intake"),
+            [(2, Breach::AboveMaximum), (5, Breach::BelowMinimum)]
+        );
+
+        let mut unknown = readings;
+        unknown.push(("chiller".to_string(), 6, 1.0));
+        let missing = check_thresholds_batch(unknown, Profile::Dict(profile), None);
+        assert!(missing.is_err_and(|e| e.0 == "no thresholds for sensor chiller"));
+        let typo = ThresholdProfile::new(HashMap::from([(
+            "boiler".to_string(),
+            limits(&[("max", 90.0)]),
+        )]));
+        assert!(typo.is_err());
+        let inverted = ThresholdProfile::new(HashMap::from([(
+            "boiler".to_string(),
+            limits(&[("min_threshold", 90.0), ("max_threshold", 10.0)]),
+        )]));
+        assert!(inverted.is_err());
+    }
+
+    #[test]
+    fn test_threshold_profile_from_yaml_or_json() {
+        let yaml = "boiler:\n  max_threshold: 90\nintake: {min_threshold: 5}\n";
+        let json = r#"{"boiler": {"max_threshold": 90}, "intake": {"min_threshold": 5}}"#;
+        let from_yaml = ThresholdProfile::parse(yaml, true).unwrap();
+        let from_json = ThresholdProfile::parse(json, false).unwrap();
+        assert_eq!(from_yaml.sensors(), ["boiler", "intake"]);
+        assert_eq!(from_yaml.sensors, from_json.sensors);
+        assert_eq!(
+            from_yaml.limits("intake").unwrap(),
+            Limits {
+                min_threshold: Some(5.0),
+                max_threshold: None,
+            }
+        );
+        assert!(ThresholdProfile::parse(r#"{"boiler": {"max": 90}}"#, false).is_err());
+    }
+
+    #[test]
+    fn test_threshold_checker_alerts_once_per_excursion() {
+        let Ok(mut checker) =
+            ThresholdChecker::new(None, Some(80.0), None, Some(75.0), 2, None, None, None)
+        else {
+            panic!("valid limits were rejected");
+        };
+        let ids = |alerts: Vec<Alert>| alerts.iter().map(|a| a.reading_id).collect::<Vec<_>>();
+        assert!(
+            ids(checker
+                .check(vec![(1, 81.0), (2, 79.0), (3, 81.0)])
+                .unwrap())
+            .is_empty()
+        );
+        assert_eq!(
+            ids(checker
+                .check(vec![(4, 82.0), (5, 83.0), (6, 78.0)])
+                .unwrap()),
+            [4]
+        );
+        assert_eq!(checker.tripped(), Some(Breach::AboveMaximum));
+        assert!(ids(checker.check(vec![(7, 74.0), (8, 81.0)]).unwrap()).is_empty());
+        assert_eq!(checker.tripped(), None);
+        checker.reset();
+        assert!(ids(checker.check(vec![(9, 81.0)]).unwrap()).is_empty());
+
+        assert!(
+            ThresholdChecker::new(None, Some(80.0), None, Some(85.0), 1, None, None, None).is_err()
+        );
+        assert!(ThresholdChecker::new(Some(10.0), None, None, None, 0, None, None, None).is_err());
+    }
+
+    #[test]
+    fn test_rapid_changes_alert_within_the_limits() {
+        let readings = || {
+            vec![
+                Step::Timed(1, 20.0, 0.0),
+                Step::Timed(2, 22.0, 10.0),
+                Step::Timed(3, 35.0, 20.0),
+                Step::Timed(4, 36.0, 21.0),
+            ]
+        };
+        let found = |alerts: Vec<Alert>| {
+            alerts
+                .iter()
+                .map(|a| (a.reading_id, a.breach_type, a.threshold_value))
+                .collect::<Vec<_>>()
+        };
+
+        let per_step = check_rate_of_change(readings(), Some(10.0), None, None).unwrap();
+        assert_eq!(found(per_step), [(3, Breach::RapidChange, 10.0)]);
+        let per_second = check_rate_of_change(readings(), None, Some(0.5), None).unwrap();
+        assert_eq!(
+            found(per_second),
+            [(3, Breach::RapidChange, 0.5), (4, Breach::RapidChange, 0.5)]
+        );
+        let untimed = check_rate_of_change(vec![Step::Untimed(1, 20.0)], None, Some(0.5), None);
+        assert!(untimed.is_err_and(|e| e.0.starts_with("reading 1:")));
+        let invalid = check_rate_of_change(readings(), Some(-1.0), None, None);
+        assert!(invalid.is_err_and(|e| e.0.starts_with("max_change must")));
+
+        let Ok(mut checker) =
+            ThresholdChecker::new(None, Some(40.0), None, None, 1, None, Some(10.0), None)
+        else {
+            panic!("valid limits were rejected");
+        };
+        assert_eq!(
+            found(
+                checker
+                    .check(vec![(1, 20.0), (2, 35.0), (3, 50.0)])
+                    .unwrap()
+            ),
+            [
+                (2, Breach::RapidChange, 10.0),
+                (3, Breach::AboveMaximum, 40.0)
+            ]
+        );
+        checker.reset();
+        assert!(
+            checker
+                .check_timed(vec![(4, 10.0, 0.0)])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
     #[test]
     fn test_severity_critical() {
         let readings = vec![(1, 0.0)]; // 50 below threshold of 50 = 100% difference
-        let alerts = check_thresholds(readings, Some(50.0), None);
+        let alerts = check_thresholds(readings, Some(50.0), None, None).unwrap();
         assert_eq!(alerts.len(), 1);
-        assert_eq!(alerts[0].severity, "critical");
+        assert_eq!(alerts[0].severity, Severity::Critical);
     }
 
     #[test]
     fn test_severity_high() {
         let readings = vec![(1, 35.0)]; // 15 below threshold of 50 = 30% difference
-        let alerts = check_thresholds(readings, Some(50.0), None);
+        let alerts = check_thresholds(readings, Some(50.0), None, None).unwrap();
         assert_eq!(alerts.len(), 1);
-        assert_eq!(alerts[0].severity, "critical"); // 15/50 = 0.3 > 0.2
+        assert_eq!(alerts[0].severity, Severity::Critical); // 15/50 = 0.3 > 0.2
     }
 
     #[test]
     fn test_severity_medium() {
         let readings = vec![(1, 46.0)]; // 4 below threshold of 50 = 8% difference (< 10%)
-        let alerts = check_thresholds(readings, Some(50.0), None);
+        let alerts = check_thresholds(readings, Some(50.0), None, None).unwrap();
         assert_eq!(alerts.len(), 1);
-        assert_eq!(alerts[0].severity, "medium"); // 4/50 = 0.08 < 0.1
+        assert_eq!(alerts[0].severity, Severity::Medium); // 4/50 = 0.08 < 0.1
     }
 
     #[test]
     fn test_no_thresholds() {
         let readings = vec![(1, 50.0), (2, 100.0)];
-        let alerts = check_thresholds(readings, None, None);
+        let alerts = check_thresholds(readings, None, None, None).unwrap();
         assert_eq!(alerts.len(), 0);
     }
 
     #[test]
     fn test_only_min_threshold() {
         let readings = vec![(1, 10.0), (2, 100.0)];
-        let alerts = check_thresholds(readings, Some(40.0), None);
+        let alerts = check_thresholds(readings, Some(40.0), None, None).unwrap();
         assert_eq!(alerts.len(), 1);
-        assert_eq!(alerts[0].breach_type, "below_minimum");
+        assert_eq!(alerts[0].breach_type, Breach::BelowMinimum);
     }
 
     #[test]
     fn test_only_max_threshold() {
         let readings = vec![(1, 10.0), (2, 100.0)];
-        let alerts = check_thresholds(readings, None, Some(80.0));
+        let alerts = check_thresholds(readings, None, Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 1);
-        assert_eq!(alerts[0].breach_type, "above_maximum");
+        assert_eq!(alerts[0].breach_type, Breach::AboveMaximum);
+    }
+
+    #[test]
+    fn test_detectors_grade_with_the_bands() {
+        let mut readings: Vec<(i64, f64)> = (0..20)
+            .map(|i| (i + 1, 10.0 + (i % 5) as f64 * 0.5))
+            .collect();
+        readings.push((21, 100.0));
+
+        let zscore = zscore_outliers(readings.clone(), 2.0, Some(1.0), Some(1000.0)).unwrap();
+        assert_eq!(zscore.len(), 1);
+        assert_eq!(zscore[0].severity, Severity::High);
+        for found in [
+            mad_outliers(readings.clone(), 3.5, None, None).unwrap(),
+            iqr_outliers(readings.clone(), 1.5, None, None).unwrap(),
+        ] {
+            assert_eq!(found.len(), 1);
+            assert_eq!(
+                (found[0].reading_id, found[0].severity),
+                (21, Severity::Critical)
+            );
+        }
+        assert_eq!(
+            rolling_outliers(readings.clone(), 10, 3.0, None, None)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            ewma_outliers(readings, 0.2, 3.0, None, None).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_detectors_reject_invalid_parameters() {
+        let readings = vec![(1, 50.0), (2, 60.0)];
+        assert!(zscore_outliers(readings.clone(), -1.0, None, None).is_err());
+        assert!(mad_outliers(readings.clone(), 3.5, Some(3.0), None).is_err());
+        assert!(ewma_outliers(readings.clone(), 1.5, 3.0, None, None).is_err());
+        assert!(rolling_outliers(readings, 1, 3.0, None, None).is_err());
+    }
+
+    #[test]
+    fn test_non_finite_numbers_are_rejected() {
+        let message = |result: Result<Vec<Alert>, InvalidArgument>| match result {
+            Ok(_) => panic!("non-finite number accepted"),
+            Err(error) => error.0,
+        };
+        let readings = vec![(1, 50.0), (2, f64::NAN)];
+        assert_eq!(
+            message(check_thresholds(readings.clone(), Some(40.0), None, None)),
+            "reading 2: value must be a finite number, got NaN"
+        );
+        assert_eq!(
+            message(check_thresholds(
+                vec![(1, 50.0)],
+                None,
+                Some(f64::INFINITY),
+                None
+            )),
+            "max_threshold must be a finite number, got inf"
+        );
+        assert!(zscore_outliers(readings.clone(), 3.0, None, None).is_err());
+        assert!(mad_outliers(vec![(1, f64::NEG_INFINITY)], 3.5, None, None).is_err());
+        assert!(SeverityConfig::new(None, Some(f64::INFINITY), None, None, None).is_err());
+        let batch = vec![("boiler-1".to_string(), 3, f64::INFINITY)];
+        let profile = HashMap::from([(
+            "boiler-1".to_string(),
+            HashMap::from([("max_threshold".to_string(), 90.0)]),
+        )]);
+        assert!(check_thresholds_batch(batch, Profile::Dict(profile), None).is_err());
+        assert_eq!(
+            message(check_rate_of_change(
+                vec![Step::Timed(1, 5.0, f64::NAN)],
+                Some(1.0),
+                None,
+                None
+            )),
+            "reading 1: timestamp must be a finite number, got NaN"
+        );
+
+        let mut checker =
+            ThresholdChecker::new(None, Some(80.0), None, None, 1, None, None, None).unwrap();
+        assert!(checker.check(vec![(1, 81.0), (2, f64::NAN)]).is_err());
+        // Nothing was added, so the first reading still trips the alarm.
+        assert_eq!(checker.check(vec![(1, 81.0)]).unwrap().len(), 1);
+        assert!(
+            ThresholdChecker::new(Some(f64::NAN), None, None, None, 1, None, None, None).is_err()
+        );
     }
 
     #[test]
     fn test_empty_readings() {
         let readings = vec![];
-        let alerts = check_thresholds(readings, Some(40.0), Some(80.0));
+        let alerts = check_thresholds(readings, Some(40.0), Some(80.0), None).unwrap();
         assert_eq!(alerts.len(), 0);
     }
+
+    #[test]
+    fn test_reservoir_outliers_are_seeded() {
+        let mut readings: Vec<(i64, f64)> =
+            (0..400).map(|i| (i, ((i * 37) % 101) as f64)).collect();
+        readings.push((400, 500.0));
+        let found = |seed| {
+            reservoir_outliers(readings.clone(), 2.0, 16, seed, None, None)
+                .unwrap()
+                .iter()
+                .map(|o| (o.reading_id, o.score))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(found(7), found(7));
+        assert_ne!(found(7), found(8));
+        assert!(found(7).iter().any(|&(id, _)| id == 400));
+        assert!(reservoir_outliers(readings, 3.0, 0, 0, None, None).is_err());
+    }
+
+    #[test]
+    fn test_rule_outliers() {
+        let mut readings: Vec<(i64, f64)> = (0..20)
+            .map(|i| (i + 1, 10.0 + (i % 5) as f64 * 0.5))
+            .collect();
+        readings.push((21, 100.0));
+        let rules = |rules: &[&str]| rules.iter().map(|r| r.to_string()).collect();
+
+        let found = rule_outliers(
+            readings.clone(),
+            rules(&["value < 10.2 => medium", "value > 90 => high"]),
+            3.0,
+            None,
+            None,
+        )
+        .unwrap();
+        let graded: Vec<(i64, Severity)> =
+            found.iter().map(|o| (o.reading_id, o.severity)).collect();
+        assert_eq!(
+            graded,
+            [
+                (1, Severity::Medium),
+                (6, Severity::Medium),
+                (11, Severity::Medium),
+                (16, Severity::Medium),
+                (21, Severity::High)
+            ]
+        );
+        let invalid = rule_outliers(readings, rules(&["value >> 1 => high"]), 3.0, None, None);
+        assert!(invalid.is_err_and(|e| e.0.starts_with("rule 1, column 8")));
+    }
+
+    #[test]
+    fn test_set_severity_labels() {
+        let _labels = InstalledLabels::lock();
+        let clash = set_severity_labels(Some("P1".to_string()), None, Some("P1".to_string()));
+        assert!(clash.is_err());
+        assert_eq!(Severity::Critical.label(), "critical");
+
+        set_severity_labels(None, None, Some("P1".to_string())).unwrap();
+        assert_eq!(Severity::Critical.label(), "P1");
+        assert_eq!(Severity::High.label(), "high");
+        set_severity_labels(None, None, None).unwrap();
+        assert_eq!(Severity::Critical.label(), "critical");
+    }
+
+    #[test]
+    fn test_severity_config_labels_apply_to_its_calls() {
+        // Labels left out are the installed ones.
+        let _labels = InstalledLabels::lock();
+        let clash = SeverityConfig::new(None, None, None, Some("P1".into()), Some("P1".into()));
+        assert!(clash.is_err());
+
+        let config = SeverityConfig::new(None, None, None, None, Some("P1".into())).unwrap();
+        let mut checker = ThresholdChecker::new(
+            Some(50.0),
+            None,
+            None,
+            None,
+            1,
+            Some(config.clone()),
+            None,
+            None,
+        )
+        .unwrap();
+        let labelled = [
+            check_thresholds(vec![(1, 0.0)], Some(50.0), None, Some(config)).unwrap(),
+            checker.check(vec![(1, 0.0)]).unwrap(),
+        ];
+        for alerts in labelled {
+            assert_eq!(alerts[0].severity, Severity::Critical);
+            assert_eq!(alerts[0].severity_label(), "P1");
+        }
+    }
 }
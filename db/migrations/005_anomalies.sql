@@ -0,0 +1,15 @@
+-- Add anomalies table (written by the anomaly-detector service)
+-- No foreign key to readings: anomalies are retained longer than raw readings.
+CREATE TABLE IF NOT EXISTS anomalies (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	reading_id INTEGER NOT NULL,
+	sensor_id INTEGER NOT NULL,
+	value REAL NOT NULL,
+	timestamp TIMESTAMP NOT NULL,
+	method TEXT NOT NULL,
+	score REAL NOT NULL,
+	severity TEXT NOT NULL,
+	detected_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_anomalies_sensor_time ON anomalies(sensor_id, timestamp);
@@ -0,0 +1,17 @@
+-- Add downsampled reading rollups (maintained by the anomaly-detector service)
+CREATE TABLE IF NOT EXISTS rollups (
+	sensor_id INTEGER NOT NULL,
+	resolution INTEGER NOT NULL,
+	bucket TIMESTAMP NOT NULL,
+	count INTEGER NOT NULL,
+	sum REAL NOT NULL,
+	min REAL NOT NULL,
+	max REAL NOT NULL,
+	PRIMARY KEY (sensor_id, resolution, bucket)
+);
+
+-- Highest readings.id already folded into rollups
+CREATE TABLE IF NOT EXISTS rollup_progress (
+	id INTEGER PRIMARY KEY CHECK (id = 1),
+	last_reading_id INTEGER NOT NULL
+);
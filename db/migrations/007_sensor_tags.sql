@@ -0,0 +1,7 @@
+-- Free-form sensor tags (e.g. "line:a"), used to route anomaly notifications
+CREATE TABLE IF NOT EXISTS sensor_tags (
+	sensor_id INTEGER NOT NULL,
+	tag TEXT NOT NULL,
+	PRIMARY KEY (sensor_id, tag),
+	FOREIGN KEY (sensor_id) REFERENCES sensors(id) ON DELETE CASCADE
+);
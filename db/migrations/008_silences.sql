@@ -0,0 +1,12 @@
+-- Notification silences: anomalies matching an active silence are stored but not notified
+CREATE TABLE IF NOT EXISTS silences (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	matchers TEXT NOT NULL,
+	comment TEXT NOT NULL DEFAULT '',
+	created_by TEXT NOT NULL DEFAULT '',
+	starts_at TIMESTAMP NOT NULL,
+	ends_at TIMESTAMP NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_silences_ends_at ON silences(ends_at);
@@ -0,0 +1,14 @@
+-- Open anomaly episodes per sensor and method, tracked for escalation of unacknowledged alerts
+CREATE TABLE IF NOT EXISTS incidents (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	sensor_id INTEGER NOT NULL,
+	method TEXT NOT NULL,
+	last_anomaly_id INTEGER NOT NULL,
+	opened_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	acknowledged_at TIMESTAMP,
+	resolved_at TIMESTAMP,
+	escalation_level INTEGER NOT NULL DEFAULT 0,
+	escalated_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_incidents_open ON incidents(sensor_id, method) WHERE resolved_at IS NULL;
@@ -0,0 +1,9 @@
+-- Audit trail of runtime settings changed through the anomaly-detector admin API;
+-- the latest row holds the settings in effect
+CREATE TABLE IF NOT EXISTS config_changes (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	actor TEXT NOT NULL,
+	previous TEXT NOT NULL,
+	settings TEXT NOT NULL
+);
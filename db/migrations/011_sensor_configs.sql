@@ -0,0 +1,13 @@
+-- Sensor registry of the anomaly detector: display metadata plus the detection
+-- method, z-score threshold and hard limits applied to the sensor's readings
+CREATE TABLE IF NOT EXISTS sensor_configs (
+	sensor_id INTEGER PRIMARY KEY,
+	name TEXT NOT NULL,
+	unit TEXT NOT NULL DEFAULT '',
+	method TEXT NOT NULL DEFAULT 'zscore',
+	threshold REAL,
+	min_value REAL,
+	max_value REAL,
+	updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (sensor_id) REFERENCES sensors(id) ON DELETE CASCADE
+);
@@ -0,0 +1,14 @@
+-- Who changed which anomaly-detector configuration and when, with the value
+-- before and after as JSON (NULL when the resource was created or deleted)
+CREATE TABLE IF NOT EXISTS audit_log (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	changed_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	actor TEXT NOT NULL,
+	resource TEXT NOT NULL,
+	resource_id TEXT,
+	action TEXT NOT NULL,
+	before TEXT,
+	after TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_resource ON audit_log(resource, resource_id);
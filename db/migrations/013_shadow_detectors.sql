@@ -0,0 +1,37 @@
+-- Shadow detectors: an alternative detector per sensor that the anomaly
+-- detector runs next to the primary one on live traffic without notifying
+CREATE TABLE IF NOT EXISTS sensor_shadows (
+	sensor_id INTEGER PRIMARY KEY,
+	config TEXT NOT NULL,
+	updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (sensor_id) REFERENCES sensors(id) ON DELETE CASCADE
+);
+
+-- Agreement of the shadow with the primary detector, one row per analyzed series
+CREATE TABLE IF NOT EXISTS shadow_runs (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	sensor_id INTEGER NOT NULL,
+	ran_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	readings INTEGER NOT NULL,
+	both_flagged INTEGER NOT NULL,
+	primary_only INTEGER NOT NULL,
+	shadow_only INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_shadow_runs_sensor_time ON shadow_runs(sensor_id, ran_at);
+
+-- Anomalies found by shadow detectors, kept apart from the notified ones
+CREATE TABLE IF NOT EXISTS shadow_anomalies (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	run_id INTEGER NOT NULL,
+	reading_id INTEGER NOT NULL,
+	sensor_id INTEGER NOT NULL,
+	value REAL NOT NULL,
+	timestamp TIMESTAMP NOT NULL,
+	method TEXT NOT NULL,
+	score REAL NOT NULL,
+	severity TEXT NOT NULL,
+	FOREIGN KEY (run_id) REFERENCES shadow_runs(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_shadow_anomalies_sensor_time ON shadow_anomalies(sensor_id, timestamp);
@@ -0,0 +1,27 @@
+-- Anomalies waiting to be notified, and incidents to resolve after them,
+-- delivered in id order by the anomaly detector's notification worker
+CREATE TABLE IF NOT EXISTS notification_queue (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	kind TEXT NOT NULL,
+	anomaly_id INTEGER,
+	sensor_id INTEGER NOT NULL,
+	method TEXT NOT NULL,
+	dedup_key TEXT,
+	queued_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	claimed_at TIMESTAMP,
+	attempts INTEGER NOT NULL DEFAULT 0,
+	delivered_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_notification_queue_dedup ON notification_queue(dedup_key);
+CREATE INDEX IF NOT EXISTS idx_notification_queue_pending ON notification_queue(delivered_at, id);
+
+-- Anomalies collected for email digest groups until their next digest
+CREATE TABLE IF NOT EXISTS email_digest_items (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	group_name TEXT NOT NULL,
+	anomaly TEXT NOT NULL,
+	queued_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_digest_items_group ON email_digest_items(group_name, id);
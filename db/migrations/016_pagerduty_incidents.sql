@@ -0,0 +1,6 @@
+-- PagerDuty incidents triggered by the anomaly detector and not yet resolved,
+-- shared by every replica so any of them can resolve what another triggered
+CREATE TABLE IF NOT EXISTS pagerduty_incidents (
+	dedup_key TEXT PRIMARY KEY,
+	triggered_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
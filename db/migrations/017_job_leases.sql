@@ -0,0 +1,8 @@
+-- Leases electing the one anomaly detector replica that runs each background
+-- job; a lease not renewed before expires_at can be taken by another replica
+CREATE TABLE IF NOT EXISTS job_leases (
+	job TEXT PRIMARY KEY,
+	holder TEXT NOT NULL,
+	acquired_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	expires_at TIMESTAMP NOT NULL
+);
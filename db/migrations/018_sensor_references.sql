@@ -0,0 +1,9 @@
+-- Reference (canary) sensor of a registered sensor: the one of its cohort,
+-- e.g. the other half of a redundant pair, whose concurrent readings method
+-- "reference" scores the sensor's readings against
+CREATE TABLE IF NOT EXISTS sensor_references (
+	sensor_id INTEGER PRIMARY KEY,
+	reference_id INTEGER NOT NULL,
+	FOREIGN KEY (sensor_id) REFERENCES sensors(id) ON DELETE CASCADE,
+	FOREIGN KEY (reference_id) REFERENCES sensors(id) ON DELETE CASCADE
+);
@@ -0,0 +1,26 @@
+-- Assignees, notes and anomalies of incidents, for the /incidents API. The
+-- anomalies may live in another alert store, so they are not foreign keys.
+CREATE TABLE IF NOT EXISTS incident_assignees (
+	incident_id INTEGER PRIMARY KEY,
+	assignee TEXT NOT NULL,
+	assigned_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (incident_id) REFERENCES incidents(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS incident_notes (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	incident_id INTEGER NOT NULL,
+	author TEXT NOT NULL DEFAULT '',
+	body TEXT NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+	FOREIGN KEY (incident_id) REFERENCES incidents(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_incident_notes_incident ON incident_notes(incident_id);
+
+CREATE TABLE IF NOT EXISTS incident_anomalies (
+	incident_id INTEGER NOT NULL,
+	anomaly_id INTEGER NOT NULL,
+	PRIMARY KEY (incident_id, anomaly_id),
+	FOREIGN KEY (incident_id) REFERENCES incidents(id) ON DELETE CASCADE
+);
@@ -0,0 +1,8 @@
+-- How far the scheduled archive job has exported each kind of row, so each
+-- run picks up where the last stopped and retention keeps what is not yet
+-- archived.
+CREATE TABLE IF NOT EXISTS archive_watermarks (
+	kind TEXT PRIMARY KEY,
+	exported_until TIMESTAMP NOT NULL,
+	updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,27 @@
+-- Every mutating request to the anomaly detector: who made it, what it was,
+-- a digest of its body and the status it got. Each entry's hash covers its
+-- fields and the previous entry's hash, so an entry changed or removed in
+-- the middle breaks the chain; the triggers refuse updates and deletes.
+CREATE TABLE IF NOT EXISTS request_log (
+	id INTEGER PRIMARY KEY AUTOINCREMENT,
+	received_at TIMESTAMP NOT NULL,
+	actor TEXT NOT NULL,
+	method TEXT NOT NULL,
+	path TEXT NOT NULL,
+	body_sha256 TEXT NOT NULL,
+	status INTEGER NOT NULL,
+	prev_hash TEXT NOT NULL,
+	hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_request_log_actor ON request_log(actor);
+
+CREATE TRIGGER IF NOT EXISTS request_log_no_update BEFORE UPDATE ON request_log
+BEGIN
+	SELECT RAISE(ABORT, 'request_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS request_log_no_delete BEFORE DELETE ON request_log
+BEGIN
+	SELECT RAISE(ABORT, 'request_log is append-only');
+END;
@@ -0,0 +1,11 @@
+-- Readings ingested and analyses run by each tenant of the anomaly detector
+-- per UTC day, for quotas and billing, and the requests refused for
+-- exceeding a quota
+CREATE TABLE IF NOT EXISTS tenant_usage (
+	tenant TEXT NOT NULL,
+	day DATE NOT NULL,
+	readings INTEGER NOT NULL DEFAULT 0,
+	analyses INTEGER NOT NULL DEFAULT 0,
+	rejected INTEGER NOT NULL DEFAULT 0,
+	PRIMARY KEY (tenant, day)
+);
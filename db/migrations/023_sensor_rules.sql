@@ -0,0 +1,7 @@
+-- Threshold and severity rules of a registered sensor, a JSON array of
+-- "<condition> => <severity>" strings, tried in order before its detector
+CREATE TABLE IF NOT EXISTS sensor_rules (
+	sensor_id INTEGER PRIMARY KEY,
+	rules TEXT NOT NULL,
+	FOREIGN KEY (sensor_id) REFERENCES sensors(id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+-- Webhooks added at runtime with POST /webhooks, as the JSON of their
+-- config, registered again on start so their queued deliveries find them
+CREATE TABLE IF NOT EXISTS webhooks (
+	name TEXT PRIMARY KEY,
+	config TEXT NOT NULL,
+	created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,8 @@
+-- Each streamed sensor's window as the JSON of its state, shared by every
+-- replica; a replica writes it back only if its version is the one it read
+CREATE TABLE IF NOT EXISTS stream_windows (
+	sensor_id INTEGER PRIMARY KEY,
+	state TEXT NOT NULL,
+	version INTEGER NOT NULL,
+	updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
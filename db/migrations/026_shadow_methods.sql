@@ -0,0 +1,4 @@
+-- Shadows no longer choose a method: drop the "zscore" that configs saved
+-- before then may carry, so they keep loading
+UPDATE sensor_shadows SET config = json_remove(config, '$.method')
+WHERE json_extract(config, '$.method') IS NOT NULL;
@@ -0,0 +1,23 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use detection_core::SeverityBands;
+use libfuzzer_sys::fuzz_target;
+
+/// Readings and settings as a request could send them, non-finite values
+/// included.
+#[derive(Arbitrary, Debug)]
+struct Input {
+    readings: Vec<(i64, f64)>,
+    threshold: f64,
+    high: f64,
+    critical: f64,
+}
+
+fuzz_target!(|input: Input| {
+    let bands = SeverityBands {
+        high: input.high,
+        critical: input.critical,
+    };
+    fuzz::score(&input.readings, input.threshold, &bands);
+});
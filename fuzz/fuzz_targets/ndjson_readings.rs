@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(readings) = detector_cli::input::read_ndjson(data) {
+        fuzz::score_readings(&readings);
+    }
+});
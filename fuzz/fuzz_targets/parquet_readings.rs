@@ -0,0 +1,10 @@
+#![no_main]
+
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(readings) = detector_cli::input::read_parquet(Bytes::copy_from_slice(data)) {
+        fuzz::score_readings(&readings);
+    }
+});
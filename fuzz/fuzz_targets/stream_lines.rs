@@ -0,0 +1,24 @@
+#![no_main]
+
+use detector_cli::input::{InputFormat, LineParser};
+use libfuzzer_sys::fuzz_target;
+
+// The first byte picks the format, as `watch --input-format` does.
+fuzz_target!(|data: &[u8]| {
+    let Some((&format, text)) = data.split_first() else {
+        return;
+    };
+    let format = if format % 2 == 0 {
+        InputFormat::Csv
+    } else {
+        InputFormat::Ndjson
+    };
+    let mut parser = LineParser::new(format).unwrap();
+    let mut readings = Vec::new();
+    for (position, line) in String::from_utf8_lossy(text).lines().enumerate() {
+        if let Ok(Some(reading)) = parser.parse(line, position) {
+            readings.push(reading);
+        }
+    }
+    fuzz::score_readings(&readings);
+});
@@ -0,0 +1,26 @@
+//! Shared by the fuzz targets: runs every detector over readings that a
+//! parser accepted, since odd values (NaN, infinities, huge magnitudes) only
+//! do harm once they are scored.
+
+use detection_core::stats::summarize;
+use detection_core::{
+    SeverityBands, check_thresholds, ewma_outliers, iqr_outliers, mad_outliers, rolling_outliers,
+    zscore_outliers,
+};
+use detector_cli::input::Reading;
+
+pub fn score(readings: &[(i64, f64)], threshold: f64, bands: &SeverityBands) {
+    summarize(readings.iter().map(|r| r.1));
+    check_thresholds(readings.iter().copied(), Some(-threshold), Some(threshold));
+    zscore_outliers(readings, threshold, bands);
+    mad_outliers(readings, threshold, bands);
+    iqr_outliers(readings, threshold, bands);
+    ewma_outliers(readings, 0.1, threshold, bands);
+    rolling_outliers(readings, 16, threshold, bands);
+}
+
+/// Scores parsed readings with the service's default thresholds.
+pub fn score_readings(readings: &[Reading]) {
+    let readings: Vec<(i64, f64)> = readings.iter().map(|r| (r.id, r.value)).collect();
+    score(&readings, 2.0, &SeverityBands::default());
+}